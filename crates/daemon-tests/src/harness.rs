@@ -0,0 +1,95 @@
+//! A black-box harness for protocol tests between a maker and a taker.
+//!
+//! [`Harness`] runs the actor systems of a maker and a taker in-process
+//! and connected to each other, with the oracle, the monitor, the wallet
+//! and the price feed mocked. Its helpers drive a CFD through a protocol
+//! and only return once both parties agree on the resulting state, so a
+//! test only has to spell out the steps it is interested in:
+//!
+//! ```ignore
+//! let mut harness = Harness::start().await;
+//!
+//! let order_id = harness.open_cfd(OpenCfdArgs::default()).await;
+//! harness.roll_over(order_id, btc_example_0()).await;
+//! harness.settle_collaboratively(order_id).await;
+//! ```
+//!
+//! The helpers expect the CFD they are given to be the only CFD of the
+//! maker and the taker.
+
+use crate::maia::OliviaData;
+use crate::open_cfd;
+use crate::rollover::rollover;
+use crate::settle_collaboratively;
+use crate::settle_non_collaboratively;
+use crate::Maker;
+use crate::MakerConfig;
+use crate::OfferParamsBuilder;
+use crate::OpenCfdArgs;
+use crate::Taker;
+use crate::TakerConfig;
+use anyhow::Context;
+use daemon::oracle::Attestation;
+use model::ContractSymbol;
+use model::OrderId;
+
+pub struct Harness {
+    pub maker: Maker,
+    pub taker: Taker,
+}
+
+impl Harness {
+    /// Start a maker and a taker with the default configuration.
+    pub async fn start() -> Self {
+        Self::start_with(&MakerConfig::default(), &TakerConfig::default()).await
+    }
+
+    pub async fn start_with(maker_config: &MakerConfig, taker_config: &TakerConfig) -> Self {
+        let maker = Maker::start(maker_config).await;
+        let taker = Taker::start(taker_config, maker.identity, maker.connect_addr.clone()).await;
+
+        Self { maker, taker }
+    }
+
+    /// Open a CFD, returning once its lock transaction is confirmed.
+    pub async fn open_cfd(&mut self, args: OpenCfdArgs) -> OrderId {
+        open_cfd(&mut self.taker, &mut self.maker, args).await
+    }
+
+    /// Roll the CFD over to the settlement event of `oracle_data`.
+    ///
+    /// The maker only accepts a rollover while it has an offer for the
+    /// contract symbol of the CFD, so one is published first.
+    pub async fn roll_over(&mut self, order_id: OrderId, oracle_data: OliviaData) {
+        let contract_symbol = self.contract_symbol(order_id);
+        self.maker
+            .set_offer_params(OfferParamsBuilder::new(contract_symbol).build())
+            .await;
+
+        rollover(&mut self.maker, &mut self.taker, order_id, oracle_data).await;
+    }
+
+    /// Settle the CFD collaboratively, at the price of the mocked quotes.
+    pub async fn settle_collaboratively(&mut self, order_id: OrderId) {
+        settle_collaboratively(&mut self.taker, &mut self.maker, order_id).await;
+    }
+
+    /// Settle the CFD through the CET matching `attestation`.
+    pub async fn settle_non_collaboratively(
+        &mut self,
+        order_id: OrderId,
+        attestation: &Attestation,
+    ) {
+        settle_non_collaboratively(&mut self.taker, &mut self.maker, order_id, attestation).await;
+    }
+
+    fn contract_symbol(&mut self, order_id: OrderId) -> ContractSymbol {
+        self.taker
+            .cfds()
+            .into_iter()
+            .find(|cfd| cfd.order_id == order_id)
+            .context("CFD not found")
+            .unwrap()
+            .contract_symbol
+    }
+}
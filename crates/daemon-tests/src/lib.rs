@@ -13,10 +13,12 @@ use daemon::bdk::bitcoin::Amount;
 use daemon::bdk::bitcoin::Network;
 use daemon::bdk::bitcoin::SignedAmount;
 use daemon::bdk::bitcoin::Txid;
+use daemon::block_explorer::BlockExplorer;
 use daemon::libp2p_utils::create_connect_multiaddr;
 use daemon::maia_core::secp256k1_zkp::XOnlyPublicKey;
 use daemon::online_status::ConnectionStatus;
 use daemon::oracle::Attestation;
+use daemon::process_manager;
 use daemon::projection;
 use daemon::projection::Cfd;
 use daemon::projection::CfdState;
@@ -24,14 +26,17 @@ use daemon::projection::FeedReceivers;
 use daemon::projection::MakerOffers;
 use daemon::seed::RandomSeed;
 use daemon::seed::Seed;
+use daemon::ConnectionSettings;
 use daemon::Environment;
 use daemon::N_PAYOUTS;
 use maia::olivia::btc_example_0;
 use maia::OliviaData;
+use maker::cfd::OfferHysteresis;
 use maker::cfd::OfferParams;
 use model::libp2p::PeerId;
 use model::olivia::Announcement;
 use model::olivia::BitMexPriceEventId;
+use model::olivia::OracleKeys;
 use model::CfdEvent;
 use model::CompleteFee;
 use model::ContractSymbol;
@@ -39,11 +44,13 @@ use model::Contracts;
 use model::Dlc;
 use model::EventKind;
 use model::FeeAccount;
+use model::FeeSubsidy;
 use model::FundingFee;
 use model::FundingRate;
 use model::Identity;
 use model::Leverage;
 use model::LotSize;
+use model::OfferTier;
 use model::OpeningFee;
 use model::OrderId;
 use model::Position;
@@ -53,6 +60,7 @@ use model::TxFeeRate;
 use model::SETTLEMENT_INTERVAL;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
@@ -71,6 +79,7 @@ use xtra_libp2p::libp2p::Multiaddr;
 use xtra_libp2p::multiaddress_ext::MultiaddrExt;
 
 pub mod flow;
+pub mod harness;
 pub mod maia;
 pub mod mocks;
 pub mod rollover;
@@ -274,10 +283,12 @@ impl OpenCfdArgs {
     }
 
     pub fn fee_calculator(&self) -> FeeCalculator {
-        debug_assert!(self
-            .offer_params()
-            .leverage_choices
-            .contains(&self.taker_leverage));
+        let offer_params = self.offer_params();
+        let leverage_choices = match self.position_maker {
+            Position::Long => offer_params.leverage_choices_long,
+            Position::Short => offer_params.leverage_choices_short,
+        };
+        debug_assert!(leverage_choices.contains(&self.taker_leverage));
 
         FeeCalculator::new(
             self.contract_symbol,
@@ -344,7 +355,7 @@ pub async fn open_cfd(taker: &mut Taker, maker: &mut Maker, args: OpenCfdArgs) -
 
     let order_id = taker
         .system
-        .place_order(offer_id, quantity, taker_leverage)
+        .place_order(offer_id, quantity, taker_leverage, None)
         .await
         .unwrap();
     wait_next_state!(order_id, maker, taker, CfdState::PendingSetup);
@@ -366,6 +377,36 @@ pub async fn open_cfd(taker: &mut Taker, maker: &mut Maker, args: OpenCfdArgs) -
     order_id
 }
 
+/// Settle a CFD collaboratively.
+///
+/// The taker proposes to settle at the price of the mocked quotes, the maker accepts and the
+/// settlement transaction is confirmed.
+pub async fn settle_collaboratively(taker: &mut Taker, maker: &mut Maker, order_id: OrderId) {
+    let contract_symbol = taker
+        .cfds()
+        .into_iter()
+        .find(|cfd| cfd.order_id == order_id)
+        .context("CFD to settle not found")
+        .unwrap()
+        .contract_symbol;
+    mock_quotes(maker, taker, contract_symbol).await;
+
+    taker.system.propose_settlement(order_id).await.unwrap();
+    wait_next_state!(
+        order_id,
+        maker,
+        taker,
+        CfdState::IncomingSettlementProposal,
+        CfdState::OutgoingSettlementProposal
+    );
+
+    maker.system.accept_settlement(order_id).await.unwrap();
+    wait_next_state!(order_id, maker, taker, CfdState::PendingClose);
+
+    confirm!(close transaction, order_id, maker, taker);
+    wait_next_state!(order_id, maker, taker, CfdState::Closed);
+}
+
 /// Settle a CFD non collaboratively.
 ///
 /// It publishes the commit transaction; expires of the CET timelock on it; and simulates the
@@ -690,7 +731,7 @@ impl Maker {
         let maker = maker::ActorSystem::new(
             db.clone(),
             wallet_addr,
-            config.oracle_pk,
+            OracleKeys::new(config.oracle_pk),
             |executor| {
                 let (oracle, mock) = OracleActor::new(executor);
                 oracle_mock = Some(mock);
@@ -705,28 +746,46 @@ impl Maker {
             },
             settlement_interval,
             config.n_payouts,
+            OfferHysteresis::DISABLED,
+            (
+                price_feed_addr.clone().into(),
+                price_feed_addr.clone().into(),
+                Duration::from_secs(10),
+            ),
             projection_actor,
             identities.clone(),
-            endpoint_listen.clone(),
+            vec![endpoint_listen.clone()],
+            ConnectionSettings::default(),
             config.blocked_peers.clone(),
+            HashMap::new(),
+            HashMap::new(),
+            None,
+            None,
+            None,
+            process_manager::DEFAULT_DELIVERY_TIMEOUT,
+            daemon::electrum::ActiveServer::fixed(String::new()),
+            daemon::watchtower::tower::PunishPolicy::default(),
         )
         .unwrap();
 
-        let mocks = mocks::Mocks::new(
+        let mut mocks = mocks::Mocks::new(
             wallet_mock,
             price_feed_mock,
             monitor_mock.unwrap(),
             oracle_mock.unwrap(),
         );
+        // Lock times of settlements proposed by the taker are checked against the block height
+        mocks.mock_block_height().await;
 
         let (feed_senders, feed_receivers) = projection::feeds();
         let feed_senders = Arc::new(feed_senders);
         let proj_actor = projection::Actor::new(
             db,
-            Network::Testnet,
+            BlockExplorer::mempool_space(Network::Testnet),
             price_feed_addr.into(),
             Role::Maker,
             feed_senders,
+            Decimal::TEN,
         );
         tasks.add(projection_context.run(proj_actor));
 
@@ -752,9 +811,14 @@ impl Maker {
             funding_rate_long,
             funding_rate_short,
             opening_fee,
-            leverage_choices,
+            leverage_choices_long,
+            leverage_choices_short,
             contract_symbol,
             lot_size,
+            fee_subsidy,
+            min_taker_balance,
+            refund_timelock,
+            tier,
         } = offer_params;
         self.system
             .set_offer_params(
@@ -766,9 +830,14 @@ impl Maker {
                 funding_rate_long,
                 funding_rate_short,
                 opening_fee,
-                leverage_choices,
+                leverage_choices_long,
+                leverage_choices_short,
                 contract_symbol,
                 lot_size,
+                fee_subsidy,
+                min_taker_balance,
+                refund_timelock,
+                tier,
             )
             .await
             .unwrap();
@@ -886,7 +955,7 @@ impl Taker {
         let taker = daemon::TakerActorSystem::new(
             db.clone(),
             wallet_addr,
-            config.oracle_pk,
+            OracleKeys::new(config.oracle_pk),
             identities.clone(),
             |executor| {
                 let (oracle, mock) = OracleActor::new(executor);
@@ -902,29 +971,39 @@ impl Taker {
             },
             price_feed_addr,
             config.n_payouts,
-            Duration::from_secs(10),
+            ConnectionSettings::default(),
             projection_actor,
             maker_identity,
             maker_multiaddr.clone(),
             Environment::new("test"),
+            None,
+            None,
+            process_manager::DEFAULT_DELIVERY_TIMEOUT,
+            None,
+            None,
         )
         .unwrap();
 
-        let mocks = mocks::Mocks::new(
+        let mut mocks = mocks::Mocks::new(
             wallet_mock,
             price_feed_mock,
             monitor_mock.unwrap(),
             oracle_mock.unwrap(),
         );
+        // The balance is checked before placing an order
+        mocks.mock_wallet_utxos().await;
+        // Settlements are locked to the block height
+        mocks.mock_block_height().await;
 
         let (feed_senders, feed_receivers) = projection::feeds();
         let feed_senders = Arc::new(feed_senders);
         let proj_actor = projection::Actor::new(
             db.clone(),
-            Network::Testnet,
+            BlockExplorer::mempool_space(Network::Testnet),
             taker.price_feed_actor.clone().into(),
             Role::Taker,
             feed_senders,
+            Decimal::TEN,
         );
         tasks.add(projection_context.run(proj_actor));
 
@@ -1043,9 +1122,14 @@ impl OfferParamsBuilder {
             funding_rate_long: FundingRate::new(dec!(0.00024)).unwrap(),
             funding_rate_short: FundingRate::new(dec!(0.00024)).unwrap(),
             opening_fee: OpeningFee::new(Amount::from_sat(2)),
-            leverage_choices: vec![Leverage::TWO],
+            leverage_choices_long: vec![Leverage::TWO],
+            leverage_choices_short: vec![Leverage::TWO],
             contract_symbol: symbol,
             lot_size: lot_size_for(symbol),
+            fee_subsidy: None,
+            min_taker_balance: None,
+            refund_timelock: None,
+            tier: OfferTier::Public,
         })
     }
 
@@ -1057,7 +1141,32 @@ impl OfferParamsBuilder {
     }
 
     pub fn leverage_choices(mut self, choices: Vec<Leverage>) -> Self {
-        self.0.leverage_choices = choices;
+        self.0.leverage_choices_long = choices.clone();
+        self.0.leverage_choices_short = choices;
+
+        self
+    }
+
+    pub fn fee_subsidy(mut self, fee_subsidy: FeeSubsidy) -> Self {
+        self.0.fee_subsidy = Some(fee_subsidy);
+
+        self
+    }
+
+    pub fn min_taker_balance(mut self, min_taker_balance: Amount) -> Self {
+        self.0.min_taker_balance = Some(min_taker_balance);
+
+        self
+    }
+
+    pub fn refund_timelock(mut self, refund_timelock: u32) -> Self {
+        self.0.refund_timelock = Some(refund_timelock);
+
+        self
+    }
+
+    pub fn tier(mut self, tier: OfferTier) -> Self {
+        self.0.tier = tier;
 
         self
     }
@@ -85,6 +85,20 @@ impl Mocks {
             .returning(wallet::build_party_params);
     }
 
+    pub async fn mock_wallet_utxos(&mut self) {
+        self.wallet()
+            .await
+            .expect_list_utxos()
+            .returning(wallet::list_utxos);
+    }
+
+    pub async fn mock_block_height(&mut self) {
+        self.wallet()
+            .await
+            .expect_get_block_height()
+            .returning(wallet::get_block_height);
+    }
+
     pub async fn mock_latest_quotes(&mut self) {
         self.price_feed()
             .await
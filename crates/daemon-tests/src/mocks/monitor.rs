@@ -48,6 +48,8 @@ impl MonitorActor {
     async fn handle(&mut self, _: monitor::MonitorCetFinality) -> Result<()> {
         Ok(())
     }
+
+    async fn handle(&mut self, _: xtras::Probe) {}
 }
 
 pub struct MockMonitor {
@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use daemon::price_feed;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use xtra_bitmex_price_feed::LatestQuotes;
@@ -32,6 +33,10 @@ impl PriceFeedActor {
     async fn handle(&mut self, _: xtra_bitmex_price_feed::GetLatestQuotes) -> LatestQuotes {
         self.mock.lock().await.latest_quotes()
     }
+
+    async fn handle(&mut self, _: price_feed::GetTwap) -> Option<price_feed::Twap> {
+        None
+    }
 }
 
 #[derive(Default, Clone)]
@@ -3,6 +3,7 @@ use async_trait::async_trait;
 use bdk_ext::new_test_wallet;
 use daemon::bdk::bitcoin::util::psbt::PartiallySignedTransaction;
 use daemon::bdk::bitcoin::Amount;
+use daemon::bdk::bitcoin::OutPoint;
 use daemon::bdk::bitcoin::Txid;
 use daemon::bdk::wallet::tx_builder::TxOrdering;
 use daemon::bdk::wallet::AddressIndex;
@@ -40,6 +41,7 @@ impl xtra::Actor for WalletActor {
 
 #[xtra_productivity]
 impl WalletActor {
+    async fn handle(&mut self, _: xtras::Probe) {}
     async fn handle(&mut self, msg: wallet::BuildPartyParams) -> Result<PartyParams> {
         self.mock.lock().await.build_party_params(msg)
     }
@@ -52,6 +54,18 @@ impl WalletActor {
     async fn handle(&mut self, msg: wallet::Sync) {
         self.mock.lock().await.sync(msg)
     }
+    async fn handle(&mut self, msg: wallet::ListUtxos) -> Result<Vec<wallet::Utxo>> {
+        self.mock.lock().await.list_utxos(msg)
+    }
+    async fn handle(&mut self, msg: wallet::FreezeUtxos) -> Result<()> {
+        self.mock.lock().await.freeze_utxos(msg)
+    }
+    async fn handle(&mut self, msg: wallet::UnfreezeUtxos) {
+        self.mock.lock().await.unfreeze_utxos(msg)
+    }
+    async fn handle(&mut self, msg: wallet::GetBlockHeight) -> Result<u32> {
+        self.mock.lock().await.get_block_height(msg)
+    }
 }
 
 #[automock]
@@ -71,6 +85,41 @@ pub trait Wallet {
     fn sync(&mut self, _msg: wallet::Sync) {
         unreachable!("mockall will reimplement this method")
     }
+
+    fn list_utxos(&mut self, _msg: wallet::ListUtxos) -> Result<Vec<wallet::Utxo>> {
+        unreachable!("mockall will reimplement this method")
+    }
+
+    fn freeze_utxos(&mut self, _msg: wallet::FreezeUtxos) -> Result<()> {
+        unreachable!("mockall will reimplement this method")
+    }
+
+    fn unfreeze_utxos(&mut self, _msg: wallet::UnfreezeUtxos) {
+        unreachable!("mockall will reimplement this method")
+    }
+
+    fn get_block_height(&mut self, _msg: wallet::GetBlockHeight) -> Result<u32> {
+        unreachable!("mockall will reimplement this method")
+    }
+}
+
+/// The same block height for the maker and the taker.
+pub fn get_block_height(_msg: wallet::GetBlockHeight) -> Result<u32> {
+    Ok(750_000)
+}
+
+/// A wallet with enough spendable funds for any order placed in the tests.
+pub fn list_utxos(_msg: wallet::ListUtxos) -> Result<Vec<wallet::Utxo>> {
+    let utxos = (0..5)
+        .map(|vout| wallet::Utxo {
+            outpoint: OutPoint::new(Txid::default(), vout),
+            amount: Amount::from_btc(1.4).unwrap(),
+            frozen: false,
+            reserved: false,
+        })
+        .collect();
+
+    Ok(utxos)
 }
 
 pub fn build_party_params(msg: wallet::BuildPartyParams) -> Result<PartyParams> {
@@ -9,6 +9,7 @@ use daemon_tests::maia::olivia::btc_example_0;
 use daemon_tests::maia::olivia::eth_example_0;
 use daemon_tests::mock_quotes;
 use daemon_tests::open_cfd;
+use daemon_tests::settle_collaboratively;
 use daemon_tests::start_both;
 use daemon_tests::wait_next_state;
 use daemon_tests::Maker;
@@ -109,23 +110,8 @@ async fn collaboratively_close_an_open_cfd(
         },
     )
     .await;
-    mock_quotes(&mut maker, &mut taker, contract_symbol).await;
 
-    taker.system.propose_settlement(order_id).await.unwrap();
-
-    wait_next_state!(
-        order_id,
-        maker,
-        taker,
-        CfdState::IncomingSettlementProposal,
-        CfdState::OutgoingSettlementProposal
-    );
-
-    maker.system.accept_settlement(order_id).await.unwrap();
-    wait_next_state!(order_id, maker, taker, CfdState::PendingClose);
-
-    confirm!(close transaction, order_id, maker, taker);
-    wait_next_state!(order_id, maker, taker, CfdState::Closed);
+    settle_collaboratively(&mut taker, &mut maker, order_id).await;
     verify_closed_cfds(&mut maker, &mut taker);
 }
 
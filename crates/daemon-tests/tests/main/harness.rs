@@ -0,0 +1,18 @@
+use daemon_tests::harness::Harness;
+use daemon_tests::maia::olivia::btc_example_0;
+use daemon_tests::OpenCfdArgs;
+use otel_tests::otel_test;
+
+#[otel_test]
+async fn open_roll_over_and_settle_cfd_through_harness() {
+    let mut harness = Harness::start().await;
+
+    let order_id = harness.open_cfd(OpenCfdArgs::default()).await;
+    harness.roll_over(order_id, btc_example_0()).await;
+    harness.settle_collaboratively(order_id).await;
+
+    assert_eq!(
+        harness.maker.first_cfd().closing_price,
+        harness.taker.first_cfd().closing_price
+    );
+}
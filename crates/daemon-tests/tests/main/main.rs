@@ -1,5 +1,6 @@
 mod collaborative_settlement;
 mod connectivity;
+mod harness;
 mod liquidation;
 mod non_collaborative_settlement;
 mod offer;
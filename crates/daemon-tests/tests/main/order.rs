@@ -1,3 +1,6 @@
+use daemon::bdk::bitcoin::Amount;
+use daemon::bdk::bitcoin::SignedAmount;
+use daemon::pending_requests::PendingRequestKind;
 use daemon::projection::CfdState;
 use daemon_tests::confirm;
 use daemon_tests::flow::cfd_with_state;
@@ -13,6 +16,7 @@ use daemon_tests::OfferParamsBuilder;
 use daemon_tests::Taker;
 use model::ContractSymbol;
 use model::Contracts;
+use model::FeeSubsidy;
 use model::Leverage;
 use model::OrderId;
 use otel_tests::otel_test;
@@ -38,17 +42,59 @@ async fn taker_places_order_and_maker_rejects() {
     maker.mocks.mock_oracle_announcement(symbol).await;
     let order_id = taker
         .system
-        .place_order(offer_id, Contracts::new(100), Leverage::TWO)
+        .place_order(offer_id, Contracts::new(100), Leverage::TWO, None)
         .await
         .unwrap();
 
     wait_next_state!(order_id, maker, taker, CfdState::PendingSetup);
 
-    maker.system.reject_order(order_id).await.unwrap();
+    maker.system.reject_order(order_id, None).await.unwrap();
 
     wait_next_state!(order_id, maker, taker, CfdState::Rejected);
 }
 
+#[otel_test]
+async fn taker_cancels_order_awaiting_maker_decision() {
+    let (mut maker, mut taker) = start_both().await;
+
+    ensure_null_next_offers(taker.offers_feed()).await.unwrap();
+
+    let symbol = ContractSymbol::BtcUsd;
+    maker
+        .set_offer_params(OfferParamsBuilder::new(symbol).build())
+        .await;
+
+    let (_, received) = next_maker_offers(maker.offers_feed(), taker.offers_feed(), &symbol)
+        .await
+        .unwrap();
+
+    let offer_id = received.btcusd_short.unwrap().id;
+
+    taker.mocks.mock_oracle_announcement(symbol).await;
+    maker.mocks.mock_oracle_announcement(symbol).await;
+    let order_id = taker
+        .system
+        .place_order(offer_id, Contracts::new(100), Leverage::TWO, None)
+        .await
+        .unwrap();
+
+    wait_next_state!(order_id, maker, taker, CfdState::PendingSetup);
+
+    let pending = taker.system.pending_requests().await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].order_id, order_id);
+    assert_eq!(pending[0].kind, PendingRequestKind::Order);
+
+    taker.system.cancel_pending_request(order_id).await.unwrap();
+
+    next_with(taker.cfd_feed(), |maybe_cfds| {
+        maybe_cfds.and_then(one_cfd_with_state(CfdState::SetupFailed))
+    })
+    .await
+    .unwrap();
+    assert!(taker.system.pending_requests().await.unwrap().is_empty());
+}
+
 #[otel_test]
 async fn taker_places_btc_usd_order_and_maker_accepts_and_contract_setup() {
     taker_places_order_and_maker_accepts_and_contract_setup(ContractSymbol::BtcUsd).await;
@@ -83,13 +129,201 @@ async fn taker_places_order_and_maker_accepts_and_contract_setup(contract_symbol
 
     let order_id = taker
         .system
-        .place_order(offer_id, Contracts::new(100), Leverage::TWO)
+        .place_order(offer_id, Contracts::new(100), Leverage::TWO, None)
         .await
         .unwrap();
 
     contract_setup(&mut maker, &mut taker, order_id).await;
 }
 
+#[otel_test]
+async fn taker_queries_open_cfd_after_contract_setup() {
+    let (mut maker, mut taker) = start_both().await;
+
+    ensure_null_next_offers(taker.offers_feed()).await.unwrap();
+
+    let symbol = ContractSymbol::BtcUsd;
+    maker
+        .set_offer_params(OfferParamsBuilder::new(symbol).build())
+        .await;
+
+    let (_, received) = next_maker_offers(maker.offers_feed(), taker.offers_feed(), &symbol)
+        .await
+        .unwrap();
+
+    let offer_id = received.btcusd_short.unwrap().id;
+
+    taker.mocks.mock_oracle_announcement(symbol).await;
+    maker.mocks.mock_oracle_announcement(symbol).await;
+    let order_id = taker
+        .system
+        .place_order(offer_id, Contracts::new(100), Leverage::TWO, None)
+        .await
+        .unwrap();
+
+    contract_setup(&mut maker, &mut taker, order_id).await;
+
+    let open_cfds = taker.system.open_cfds().await.unwrap();
+    assert_eq!(open_cfds.len(), 1);
+    assert_eq!(open_cfds[0].order_id, order_id);
+
+    let cfd = taker.system.cfd(order_id).await.unwrap().unwrap();
+    assert_eq!(cfd.state, CfdState::Open);
+    assert!(taker
+        .system
+        .cfd(OrderId::default())
+        .await
+        .unwrap()
+        .is_none());
+
+    assert!(taker.system.pending_rollovers().await.unwrap().is_empty());
+}
+
+#[otel_test]
+async fn taker_places_order_for_subsidized_offer_and_pays_no_fees() {
+    let (mut maker, mut taker) = start_both().await;
+
+    ensure_null_next_offers(taker.offers_feed()).await.unwrap();
+
+    let symbol = ContractSymbol::BtcUsd;
+    maker
+        .set_offer_params(
+            OfferParamsBuilder::new(symbol)
+                .fee_subsidy(FeeSubsidy {
+                    opening_fee: true,
+                    initial_funding_fee: true,
+                })
+                .build(),
+        )
+        .await;
+
+    let (_, received) = next_maker_offers(maker.offers_feed(), taker.offers_feed(), &symbol)
+        .await
+        .unwrap();
+
+    let offer = received.btcusd_short.unwrap();
+    assert!(offer.fee_subsidy.is_some());
+    assert_eq!(offer.opening_fee, Some(Amount::ZERO));
+
+    taker.mocks.mock_oracle_announcement(symbol).await;
+    maker.mocks.mock_oracle_announcement(symbol).await;
+    let order_id = taker
+        .system
+        .place_order(offer.id, Contracts::new(100), Leverage::TWO, None)
+        .await
+        .unwrap();
+
+    contract_setup(&mut maker, &mut taker, order_id).await;
+
+    assert_eq!(taker.latest_accumulated_fees(), SignedAmount::ZERO);
+    assert_eq!(maker.latest_accumulated_fees(), SignedAmount::ZERO);
+}
+
+#[otel_test]
+async fn taker_places_order_for_offer_with_custom_refund_timelock_and_contract_setup() {
+    let (mut maker, mut taker) = start_both().await;
+
+    ensure_null_next_offers(taker.offers_feed()).await.unwrap();
+
+    let symbol = ContractSymbol::BtcUsd;
+    let refund_timelock = 300;
+    maker
+        .set_offer_params(
+            OfferParamsBuilder::new(symbol)
+                .refund_timelock(refund_timelock)
+                .build(),
+        )
+        .await;
+
+    let (_, received) = next_maker_offers(maker.offers_feed(), taker.offers_feed(), &symbol)
+        .await
+        .unwrap();
+
+    let offer_id = received.btcusd_short.unwrap().id;
+
+    taker.mocks.mock_oracle_announcement(symbol).await;
+    maker.mocks.mock_oracle_announcement(symbol).await;
+    let order_id = taker
+        .system
+        .place_order(offer_id, Contracts::new(100), Leverage::TWO, None)
+        .await
+        .unwrap();
+
+    contract_setup(&mut maker, &mut taker, order_id).await;
+}
+
+#[otel_test]
+async fn taker_refuses_to_place_order_without_minimum_balance_required_by_maker() {
+    let (mut maker, mut taker) = start_both().await;
+
+    ensure_null_next_offers(taker.offers_feed()).await.unwrap();
+
+    let symbol = ContractSymbol::BtcUsd;
+    let min_taker_balance = Amount::from_btc(100.0).unwrap();
+    maker
+        .set_offer_params(
+            OfferParamsBuilder::new(symbol)
+                .min_taker_balance(min_taker_balance)
+                .build(),
+        )
+        .await;
+
+    let (_, received) = next_maker_offers(maker.offers_feed(), taker.offers_feed(), &symbol)
+        .await
+        .unwrap();
+
+    let offer = received.btcusd_short.unwrap();
+    assert_eq!(offer.min_taker_balance, Some(min_taker_balance));
+
+    taker
+        .system
+        .place_order(offer.id, Contracts::new(100), Leverage::TWO, None)
+        .await
+        .expect_err("wallet to hold less than the minimum balance");
+
+    assert!(taker.cfds().is_empty());
+}
+
+#[otel_test]
+async fn taker_places_order_twice_with_same_order_id_results_in_one_cfd() {
+    let (mut maker, mut taker) = start_both().await;
+
+    ensure_null_next_offers(taker.offers_feed()).await.unwrap();
+
+    let symbol = ContractSymbol::BtcUsd;
+    maker
+        .set_offer_params(OfferParamsBuilder::new(symbol).build())
+        .await;
+
+    let (_, received) = next_maker_offers(maker.offers_feed(), taker.offers_feed(), &symbol)
+        .await
+        .unwrap();
+
+    let offer_id = received.btcusd_short.unwrap().id;
+
+    taker.mocks.mock_oracle_announcement(symbol).await;
+    maker.mocks.mock_oracle_announcement(symbol).await;
+    let order_id = OrderId::default();
+    let first_order_id = taker
+        .system
+        .place_order(offer_id, Contracts::new(100), Leverage::TWO, Some(order_id))
+        .await
+        .unwrap();
+    let second_order_id = taker
+        .system
+        .place_order(offer_id, Contracts::new(100), Leverage::TWO, Some(order_id))
+        .await
+        .unwrap();
+
+    assert_eq!(first_order_id, order_id);
+    assert_eq!(second_order_id, order_id);
+
+    contract_setup(&mut maker, &mut taker, order_id).await;
+
+    assert_eq!(taker.cfds().len(), 1);
+    assert_eq!(maker.cfds().len(), 1);
+}
+
 #[otel_test]
 async fn taker_places_order_for_same_offer_twice_results_in_two_cfds() {
     let (mut maker, mut taker) = start_both().await;
@@ -111,7 +345,7 @@ async fn taker_places_order_for_same_offer_twice_results_in_two_cfds() {
     maker.mocks.mock_oracle_announcement(symbol).await;
     let first_order_id = taker
         .system
-        .place_order(offer_id, Contracts::new(10), Leverage::TWO)
+        .place_order(offer_id, Contracts::new(100), Leverage::TWO, None)
         .await
         .unwrap();
 
@@ -119,7 +353,7 @@ async fn taker_places_order_for_same_offer_twice_results_in_two_cfds() {
 
     let second_order_id = taker
         .system
-        .place_order(offer_id, Contracts::new(10), Leverage::TWO)
+        .place_order(offer_id, Contracts::new(100), Leverage::TWO, None)
         .await
         .unwrap();
 
@@ -0,0 +1,257 @@
+//! Statistics of the market of each contract symbol.
+//!
+//! The realized volatility is derived from quotes of the price feed which
+//! the [`QuoteRecorder`] samples into the database, the average funding
+//! rate from the funding rates charged during rollovers. Both the maker's
+//! pricing and the frontends use [`statistics`], so that they agree on
+//! the numbers.
+
+use crate::projection;
+use anyhow::Result;
+use async_trait::async_trait;
+use model::ContractSymbol;
+use model::Price;
+use model::Timestamp;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlite_db::quotes::SampledQuote;
+use std::collections::HashMap;
+use std::time::Duration;
+use strum::IntoEnumIterator;
+use xtra::prelude::MessageChannel;
+use xtra_bitmex_price_feed::GetLatestQuotes;
+use xtra_bitmex_price_feed::LatestQuotes;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often to sample the latest quotes of the price feed.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long sampled quotes are kept.
+const QUOTE_RETENTION: time::Duration = time::Duration::days(30);
+
+/// The window over which statistics are computed unless another one is
+/// requested.
+pub const DEFAULT_WINDOW: time::Duration = time::Duration::days(7);
+
+const SECONDS_PER_YEAR: f64 = 365.25 * 24.0 * 60.0 * 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Statistics {
+    pub contract_symbol: ContractSymbol,
+    pub window_hours: i64,
+    /// The annualized realized volatility of the mid price, e.g. `0.6`
+    /// for 60 %
+    ///
+    /// `None` if fewer than two quotes were sampled within the window.
+    pub realized_volatility: Option<f64>,
+    pub quote_samples: usize,
+    /// The average funding rate charged during rollovers within the
+    /// window
+    pub average_funding_rate: Option<Decimal>,
+    pub funding_samples: usize,
+}
+
+/// Compute the statistics of `contract_symbol` over the `window` up to
+/// now.
+///
+/// Windows longer than the retention of sampled quotes only extend the
+/// average funding rate further into the past.
+pub async fn statistics(
+    db: &sqlite_db::Connection,
+    contract_symbol: ContractSymbol,
+    window: time::Duration,
+) -> Result<Statistics> {
+    let since = Timestamp::new(Timestamp::now().seconds() - window.whole_seconds());
+
+    let quotes = db.load_quotes(contract_symbol, since).await?;
+    let funding_rates = db
+        .load_funding_rates(contract_symbol, since)
+        .await?
+        .into_iter()
+        .map(|(_, funding_rate)| funding_rate.to_decimal())
+        .collect::<Vec<_>>();
+
+    let average_funding_rate = (!funding_rates.is_empty())
+        .then(|| funding_rates.iter().sum::<Decimal>() / Decimal::from(funding_rates.len()));
+
+    Ok(Statistics {
+        contract_symbol,
+        window_hours: window.whole_hours(),
+        realized_volatility: realized_volatility(&quotes),
+        quote_samples: quotes.len(),
+        average_funding_rate,
+        funding_samples: funding_rates.len(),
+    })
+}
+
+/// Parse a contract symbol as displayed, e.g. `BTCUSD`, ignoring case.
+pub fn parse_contract_symbol(symbol: &str) -> Option<ContractSymbol> {
+    ContractSymbol::iter()
+        .find(|contract_symbol| contract_symbol.to_string().eq_ignore_ascii_case(symbol))
+}
+
+/// The annualized volatility of the mid price of `quotes`, which must be
+/// ordered by time.
+///
+/// Quotes are not necessarily sampled at a regular interval, hence the
+/// squared log returns are scaled by the time which passed between the
+/// first and the last quote rather than by the number of samples.
+fn realized_volatility(quotes: &[SampledQuote]) -> Option<f64> {
+    let mid_prices = quotes
+        .iter()
+        .filter_map(|quote| Some((quote.timestamp.seconds(), mid_price(quote).to_f64()?)))
+        .collect::<Vec<_>>();
+
+    let (sum_of_squared_returns, elapsed_secs) =
+        mid_prices
+            .windows(2)
+            .fold((0.0, 0), |(sum, elapsed), pair| {
+                let [(previous_time, previous), (time, price)] = [pair[0], pair[1]];
+                let log_return = (price / previous).ln();
+
+                (
+                    sum + log_return * log_return,
+                    elapsed + (time - previous_time),
+                )
+            });
+
+    if elapsed_secs <= 0 {
+        return None;
+    }
+
+    Some((sum_of_squared_returns / elapsed_secs as f64 * SECONDS_PER_YEAR).sqrt())
+}
+
+fn mid_price(quote: &SampledQuote) -> Decimal {
+    (quote.bid.into_decimal() + quote.ask.into_decimal()) / Decimal::TWO
+}
+
+/// Samples the latest quotes of the price feed into the database.
+pub struct QuoteRecorder {
+    db: sqlite_db::Connection,
+    price_feed: MessageChannel<GetLatestQuotes, LatestQuotes>,
+    /// When the last sampled quote of each symbol was published, so that
+    /// a quote is only sampled once
+    last_sampled: HashMap<ContractSymbol, Timestamp>,
+}
+
+impl QuoteRecorder {
+    pub fn new(
+        db: sqlite_db::Connection,
+        price_feed: MessageChannel<GetLatestQuotes, LatestQuotes>,
+    ) -> Self {
+        Self {
+            db,
+            price_feed,
+            last_sampled: HashMap::new(),
+        }
+    }
+
+    async fn sample(&mut self) -> Result<()> {
+        let quotes = self.price_feed.send(GetLatestQuotes).await?;
+
+        for (symbol, quote) in quotes {
+            let contract_symbol = projection::as_contract_symbol(&symbol);
+            let quote = SampledQuote {
+                timestamp: Timestamp::new(quote.timestamp.unix_timestamp()),
+                bid: Price::new(quote.bid)?,
+                ask: Price::new(quote.ask)?,
+            };
+
+            if self.last_sampled.get(&contract_symbol) == Some(&quote.timestamp) {
+                continue;
+            }
+
+            self.db.insert_quote(contract_symbol, quote).await?;
+            self.last_sampled.insert(contract_symbol, quote.timestamp);
+        }
+
+        let retained_since =
+            Timestamp::new(Timestamp::now().seconds() - QUOTE_RETENTION.whole_seconds());
+        self.db.delete_quotes_before(retained_since).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SampleQuotes;
+
+#[xtra_productivity]
+impl QuoteRecorder {
+    async fn handle(&mut self, _: SampleQuotes) {
+        if let Err(e) = self.sample().await {
+            tracing::warn!("Failed to sample quotes: {e:#}");
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for QuoteRecorder {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(SAMPLE_INTERVAL, || SampleQuotes, xtras::IncludeSpan::Always),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn quote(timestamp: i64, mid_price: Decimal) -> SampledQuote {
+        SampledQuote {
+            timestamp: Timestamp::new(timestamp),
+            bid: Price::new(mid_price - dec!(0.5)).unwrap(),
+            ask: Price::new(mid_price + dec!(0.5)).unwrap(),
+        }
+    }
+
+    #[test]
+    fn volatility_is_annualized_by_elapsed_time() {
+        let hour = 60 * 60;
+        let hourly = [
+            quote(0, dec!(20_000)),
+            quote(hour, dec!(20_200)),
+            quote(2 * hour, dec!(20_000)),
+        ];
+        let gap = [
+            quote(0, dec!(20_000)),
+            quote(2 * hour, dec!(20_200)),
+            quote(4 * hour, dec!(20_000)),
+        ];
+
+        let hourly = realized_volatility(&hourly).unwrap();
+        let gap = realized_volatility(&gap).unwrap();
+
+        assert!((hourly / gap - 2_f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn no_volatility_without_two_quotes() {
+        assert_eq!(realized_volatility(&[]), None);
+        assert_eq!(realized_volatility(&[quote(0, dec!(20_000))]), None);
+    }
+
+    #[test]
+    fn contract_symbol_is_parsed_ignoring_case() {
+        assert_eq!(
+            parse_contract_symbol("btcusd"),
+            Some(ContractSymbol::BtcUsd)
+        );
+        assert_eq!(
+            parse_contract_symbol("ETHUSD"),
+            Some(ContractSymbol::EthUsd)
+        );
+        assert_eq!(parse_contract_symbol("XBTUSD"), None);
+    }
+}
@@ -1,5 +1,9 @@
 use crate::command;
+use crate::identify::PeerInfo;
+use crate::listen_protocols::Feature;
 use crate::oracle;
+use crate::preferences;
+use crate::taker_cfd;
 use crate::Txid;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -7,11 +11,15 @@ use futures::StreamExt;
 use model::libp2p::PeerId;
 use model::olivia::BitMexPriceEventId;
 use model::CannotRollover;
+use model::FundingRate;
 use model::OrderId;
+use model::RolloverPolicy;
 use rollover::taker::ProposeRollover;
 use sqlite_db;
 use std::time::Duration;
 use time::OffsetDateTime;
+use tokio::sync::watch;
+use xtra::prelude::MessageChannel;
 use xtra::Address;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncNext;
@@ -21,6 +29,8 @@ pub struct Actor {
     db: sqlite_db::Connection,
     libp2p_rollover:
         Address<rollover::taker::Actor<command::Executor, oracle::AnnouncementsChannel>>,
+    funding_rate: MessageChannel<taker_cfd::GetFundingRate, Option<FundingRate>>,
+    maker_info: watch::Receiver<Option<PeerInfo>>,
 }
 
 impl Actor {
@@ -29,10 +39,14 @@ impl Actor {
         libp2p_rollover: Address<
             rollover::taker::Actor<command::Executor, oracle::AnnouncementsChannel>,
         >,
+        funding_rate: MessageChannel<taker_cfd::GetFundingRate, Option<FundingRate>>,
+        maker_info: watch::Receiver<Option<PeerInfo>>,
     ) -> Self {
         Self {
             db,
             libp2p_rollover,
+            funding_rate,
+            maker_info,
         }
     }
 }
@@ -49,6 +63,14 @@ impl Actor {
         }
     }
 
+    async fn handle(&mut self, msg: SetRolloverPolicy) -> Result<()> {
+        let SetRolloverPolicy { order_id, policy } = msg;
+
+        tracing::info!(%order_id, ?policy, "Updating rollover policy");
+
+        self.db.set_rollover_policy(order_id, policy).await
+    }
+
     async fn handle(
         &mut self,
         Rollover {
@@ -86,6 +108,11 @@ impl Actor {
             .address()
             .expect("actor to be able to give address to itself");
 
+        if !self.maker_supports_rollover() {
+            tracing::debug!("Maker does not support rollover, skipping auto-rollover");
+            return Ok(());
+        }
+
         let mut stream = self.db.load_all_open_cfds::<model::Cfd>(());
 
         while let Some(cfd) = stream.next().await {
@@ -99,8 +126,22 @@ impl Actor {
             let order_id = cfd.id();
             let maker_peer_id = cfd.counterparty_peer_id();
 
-            match cfd.can_auto_rollover_taker(OffsetDateTime::now_utc()) {
+            let now = OffsetDateTime::now_utc();
+            match cfd.can_auto_rollover_taker(now) {
                 Ok((from_commit_txid, from_settlement_event_id)) => {
+                    // A CFD whose settlement event expired can only be
+                    // settled by rolling it over, regardless of the policy
+                    if let Some(event_id) = cfd.expired_settlement_event(now) {
+                        tracing::warn!(
+                            %order_id,
+                            %event_id,
+                            "Settlement event expired, rolling over onto superseding event"
+                        );
+                    } else if !self.is_allowed_by_policy(&cfd).await {
+                        tracing::debug!(%order_id, "Rollover policy prevents auto-rollover");
+                        continue;
+                    }
+
                     this.send_async_next(Rollover {
                         order_id,
                         maker_peer_id,
@@ -120,6 +161,59 @@ impl Actor {
 
         Ok(())
     }
+
+    /// Whether the maker supports rolling over.
+    ///
+    /// We assume it does until we have identified the maker.
+    fn maker_supports_rollover(&self) -> bool {
+        self.maker_info
+            .borrow()
+            .as_ref()
+            .map(|maker| maker.maker_compatibility().supports(Feature::Rollover))
+            .unwrap_or(true)
+    }
+
+    /// The rollover policy the user prefers for CFDs without a policy of
+    /// their own.
+    async fn default_policy(&self) -> RolloverPolicy {
+        match preferences::load(&self.db).await {
+            Ok(preferences) => preferences.default_rollover_policy,
+            Err(e) => {
+                tracing::warn!("Failed to load preferences: {e:#}");
+                RolloverPolicy::default()
+            }
+        }
+    }
+
+    async fn is_allowed_by_policy(&self, cfd: &model::Cfd) -> bool {
+        let order_id = cfd.id();
+
+        let policy = match self.db.load_rollover_policy(order_id).await {
+            Ok(Some(policy)) => policy,
+            Ok(None) => self.default_policy().await,
+            Err(e) => {
+                tracing::warn!(%order_id, "Failed to load rollover policy, using default: {e:#}");
+                self.default_policy().await
+            }
+        };
+
+        let funding_rate = match policy {
+            RolloverPolicy::FundingRateBelow { .. } => self
+                .funding_rate
+                .send(taker_cfd::GetFundingRate {
+                    contract_symbol: cfd.contract_symbol(),
+                    position_maker: cfd.position().counter_position(),
+                })
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!(%order_id, "Failed to get current funding rate: {e:#}");
+                    None
+                }),
+            RolloverPolicy::Always | RolloverPolicy::Never => None,
+        };
+
+        policy.allows_rollover(cfd.position(), funding_rate)
+    }
 }
 
 #[async_trait]
@@ -146,6 +240,13 @@ impl xtra::Actor for Actor {
 #[derive(Clone, Copy)]
 pub struct AutoRollover;
 
+/// Set the rollover policy of a CFD
+#[derive(Clone, Copy)]
+pub struct SetRolloverPolicy {
+    pub order_id: OrderId,
+    pub policy: RolloverPolicy,
+}
+
 /// Message used to trigger rollover internally within the `auto_rollover::Actor`
 ///
 /// This helps us trigger rollover in the tests unconditionally of time.
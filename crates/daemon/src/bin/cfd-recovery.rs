@@ -0,0 +1,45 @@
+//! Report what can still be claimed of the open CFDs after restoring
+//! from a backup.
+//!
+//! Usage: `cfd-recovery <seed-file> <database> <electrum-url> <network>`
+//!
+//! The database is migrated to the current version, so point this at a
+//! copy of the backup. Prints the report as JSON.
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use daemon::bdk::bitcoin::Network;
+use daemon::bdk::electrum_client;
+use daemon::recovery;
+use daemon::seed::RandomSeed;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: cfd-recovery <seed-file> <database> <electrum-url> <network>";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let [seed_file, database, electrum_url, network]: [String; 4] = std::env::args()
+        .skip(1)
+        .collect::<Vec<_>>()
+        .try_into()
+        .map_err(|_| anyhow!(USAGE))?;
+
+    let seed = RandomSeed::read_from(&PathBuf::from(seed_file))
+        .await
+        .context("Failed to read seed")?;
+    let network = network.parse::<Network>()?;
+
+    let database = PathBuf::from(database);
+    ensure!(database.exists(), "No database at {}", database.display());
+    let db = sqlite_db::connect(database, false).await?;
+
+    let electrum = electrum_client::Client::new(&electrum_url)
+        .context("Failed to initialize Electrum RPC client")?;
+
+    let report = recovery::report(&seed, network, &db, &electrum).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! Replay substreams recorded with `--record-protocols`.
+//!
+//! Usage: `protocol-replay <recording>...`
+//!
+//! Prints a timeline of each recording and runs the protocol handlers
+//! of this version on it. Fails at the first recording which the
+//! handlers reject.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use daemon::replay::replay;
+use daemon::replay::Outcome;
+use std::path::PathBuf;
+use xtra_libp2p::recorder::Direction;
+use xtra_libp2p::recorder::Payload;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let paths = std::env::args_os()
+        .skip(1)
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+
+    if paths.is_empty() {
+        bail!("Usage: protocol-replay <recording>...");
+    }
+
+    for path in paths {
+        let (header, frames) = xtra_libp2p::recorder::load(&path)?;
+
+        println!(
+            "{}: {} as {} with {} ({} frames)",
+            path.display(),
+            header.protocol,
+            header.role,
+            header.peer_id,
+            frames.len()
+        );
+
+        for frame in frames.iter() {
+            let arrow = match frame.direction {
+                Direction::Inbound => "<-",
+                Direction::Outbound => "->",
+            };
+
+            println!(
+                "{:>8}ms {arrow} {}",
+                frame.elapsed_ms,
+                message_name(&frame.payload)
+            );
+        }
+
+        let outcome = replay(&header, &frames)
+            .await
+            .with_context(|| format!("Replay of {} failed", path.display()))?;
+
+        match outcome {
+            Outcome::Accepted => println!("Accepted"),
+            Outcome::Redacted { frame } => {
+                println!("Accepted up to frame {frame}, which contains redacted fields")
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Name of the message contained in the payload.
+///
+/// Our enums are serialized with serde's default external tagging, so
+/// the name is the single key of the JSON object.
+fn message_name(payload: &Payload) -> String {
+    match payload {
+        Payload::Json(serde_json::Value::Object(fields)) if fields.len() == 1 => {
+            fields.keys().next().cloned().unwrap_or_default()
+        }
+        Payload::Json(_) => "<message>".to_owned(),
+        Payload::Raw(hex) => format!("<{} raw bytes>", hex.len() / 2),
+    }
+}
@@ -0,0 +1,153 @@
+//! Links to transactions on a block explorer.
+
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::Network;
+use bdk::bitcoin::Txid;
+use reqwest::Url;
+use std::str::FromStr;
+
+/// The block explorer which the transactions of the CFDs link to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockExplorer {
+    /// The URL the path of a transaction is appended to, ending with a
+    /// slash
+    ///
+    /// `None` if the block explorer does not support the network.
+    base_url: Option<String>,
+}
+
+impl BlockExplorer {
+    /// mempool.space, which supports mainnet, testnet and signet.
+    pub fn mempool_space(network: Network) -> Self {
+        let base_url = match network {
+            Network::Bitcoin => Some("https://mempool.space/"),
+            Network::Testnet => Some("https://mempool.space/testnet/"),
+            Network::Signet => Some("https://mempool.space/signet/"),
+            Network::Regtest => None,
+        };
+
+        Self {
+            base_url: base_url.map(str::to_owned),
+        }
+    }
+
+    /// blockstream.info, which supports mainnet and testnet.
+    pub fn blockstream(network: Network) -> Self {
+        let base_url = match network {
+            Network::Bitcoin => Some("https://blockstream.info/"),
+            Network::Testnet => Some("https://blockstream.info/testnet/"),
+            Network::Signet | Network::Regtest => None,
+        };
+
+        Self {
+            base_url: base_url.map(str::to_owned),
+        }
+    }
+
+    /// A self-hosted instance of mempool or esplora, which serves the
+    /// network the daemon runs on at `base_url`.
+    pub fn self_hosted(base_url: Url) -> Self {
+        Self {
+            base_url: Some(format!("{}/", base_url.as_str().trim_end_matches('/'))),
+        }
+    }
+
+    /// Parse `mempool.space`, `blockstream.info` or the URL of a
+    /// self-hosted block explorer for `network`.
+    pub fn parse(block_explorer: &str, network: Network) -> Result<Self> {
+        let block_explorer = match block_explorer {
+            "mempool.space" => Self::mempool_space(network),
+            "blockstream.info" => Self::blockstream(network),
+            url => {
+                let url = Url::from_str(url)
+                    .with_context(|| format!("Unknown block explorer `{block_explorer}`"))?;
+
+                Self::self_hosted(url)
+            }
+        };
+
+        Ok(block_explorer)
+    }
+
+    /// The URL of the transaction with `txid`.
+    ///
+    /// Falls back to the txid if the block explorer does not support
+    /// the network.
+    pub fn tx_url(&self, txid: Txid) -> String {
+        self.tx_link(txid).unwrap_or_else(|| txid.to_string())
+    }
+
+    /// The URL of the transaction with `txid`, if the block explorer
+    /// supports the network.
+    pub fn tx_link(&self, txid: Txid) -> Option<String> {
+        let base_url = self.base_url.as_ref()?;
+
+        Some(format!("{base_url}tx/{txid}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txid() -> Txid {
+        "c2bc1ad9e6ac7e1d2a0bd9e7b2fe4cd74c9d1e84a7d1dd4a2c6a8e6a3f1b2c3d"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn links_to_network_of_public_block_explorer() {
+        let txid = txid();
+
+        assert_eq!(
+            BlockExplorer::parse("mempool.space", Network::Signet)
+                .unwrap()
+                .tx_url(txid),
+            format!("https://mempool.space/signet/tx/{txid}")
+        );
+        assert_eq!(
+            BlockExplorer::parse("blockstream.info", Network::Testnet)
+                .unwrap()
+                .tx_url(txid),
+            format!("https://blockstream.info/testnet/tx/{txid}")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_txid_if_network_is_not_supported() {
+        let txid = txid();
+
+        assert_eq!(
+            BlockExplorer::blockstream(Network::Signet).tx_url(txid),
+            txid.to_string()
+        );
+        assert_eq!(
+            BlockExplorer::mempool_space(Network::Regtest).tx_url(txid),
+            txid.to_string()
+        );
+    }
+
+    #[test]
+    fn self_hosted_block_explorer_links_to_any_network() {
+        let txid = txid();
+
+        for url in [
+            "http://localhost:8080/explorer",
+            "http://localhost:8080/explorer/",
+        ] {
+            assert_eq!(
+                BlockExplorer::parse(url, Network::Regtest)
+                    .unwrap()
+                    .tx_url(txid),
+                format!("http://localhost:8080/explorer/tx/{txid}")
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_block_explorer_is_rejected() {
+        assert!(BlockExplorer::parse("my-explorer", Network::Bitcoin).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! The keys of CFDs, derived deterministically from the seed.
+//!
+//! The identity, revocation and publish keys a CFD is set up with are
+//! derived along a BIP32 path which is determined by a key index. Key
+//! indices are allocated from a local counter, never from anything the
+//! counterparty chooses such as the order id, and every index is used
+//! for a single CFD only. The allocated indices and the derivation paths
+//! are recorded in the database, see [`sqlite_db::cfd_keys`]. Without
+//! the database, the keys can still be recovered from the seed by
+//! scanning the key indices in order.
+//!
+//! The identity key controls our share of the lock output and is kept
+//! across rollovers. The revocation and publish keys of a rollover are
+//! still generated randomly.
+
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::secp256k1::SecretKey;
+use bdk::bitcoin::secp256k1::SECP256K1;
+use bdk::bitcoin::util::bip32::ChildNumber;
+use bdk::bitcoin::util::bip32::DerivationPath;
+use bdk::bitcoin::util::bip32::ExtendedPrivKey;
+use bdk::bitcoin::PublicKey;
+use bdk_ext::SecretKeyExt;
+use model::OrderId;
+use sqlite_db::cfd_keys::KeyDerivation;
+use std::fmt;
+
+/// What a key of a CFD is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    Identity,
+    Revocation,
+    Publish,
+}
+
+impl KeyKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyKind::Identity => "identity",
+            KeyKind::Revocation => "revocation",
+            KeyKind::Publish => "publish",
+        }
+    }
+
+    fn index(&self) -> u32 {
+        match self {
+            KeyKind::Identity => 0,
+            KeyKind::Revocation => 1,
+            KeyKind::Publish => 2,
+        }
+    }
+}
+
+/// The key pairs to set up a CFD with.
+#[derive(Clone, Copy)]
+pub struct SetupKeys {
+    pub identity: (SecretKey, PublicKey),
+    pub revocation: (SecretKey, PublicKey),
+    pub publish: (SecretKey, PublicKey),
+}
+
+/// Derives the keys of CFDs from the seed.
+#[derive(Clone, Copy)]
+pub struct CfdKeys {
+    root: ExtendedPrivKey,
+}
+
+impl CfdKeys {
+    pub fn new(root: ExtendedPrivKey) -> Self {
+        Self { root }
+    }
+
+    /// The path along which the key of `kind` of the CFD with
+    /// `key_index` is derived.
+    pub fn derivation_path(key_index: u32, kind: KeyKind) -> Result<DerivationPath> {
+        let path = [key_index, kind.index()]
+            .into_iter()
+            .map(ChildNumber::from_hardened_idx)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Key index {key_index} out of range"))?;
+
+        Ok(path.into())
+    }
+
+    /// Derive the key of `kind` of the CFD with `key_index`.
+    pub fn derive(&self, key_index: u32, kind: KeyKind) -> Result<(SecretKey, PublicKey)> {
+        let path = Self::derivation_path(key_index, kind)?;
+        let sk = self
+            .root
+            .derive_priv(SECP256K1, &path)
+            .with_context(|| format!("Failed to derive {} key along {path}", kind.name()))?
+            .private_key;
+
+        Ok((sk, PublicKey::new(sk.to_public_key())))
+    }
+
+    /// Derive the keys to set up the CFD with `order_id`, recording how
+    /// they were derived.
+    ///
+    /// Fails if keys were derived for the CFD before, so that keys are
+    /// never reused.
+    pub async fn derive_for_setup(
+        &self,
+        db: &sqlite_db::Connection,
+        order_id: OrderId,
+    ) -> Result<SetupKeys> {
+        let key_index = db.allocate_key_index(order_id).await?;
+
+        let kinds = [KeyKind::Identity, KeyKind::Revocation, KeyKind::Publish];
+
+        let derivations = kinds
+            .iter()
+            .map(|kind| {
+                Ok(KeyDerivation {
+                    kind: kind.name().to_owned(),
+                    path: Self::derivation_path(key_index, *kind)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        db.save_key_derivations(order_id, &derivations)
+            .await
+            .context("Failed to record derivation of CFD keys")?;
+
+        Ok(SetupKeys {
+            identity: self.derive(key_index, KeyKind::Identity)?,
+            revocation: self.derive(key_index, KeyKind::Revocation)?,
+            publish: self.derive(key_index, KeyKind::Publish)?,
+        })
+    }
+}
+
+impl fmt::Debug for CfdKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CfdKeys").field(&"...").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::Network;
+
+    fn cfd_keys() -> CfdKeys {
+        CfdKeys::new(ExtendedPrivKey::new_master(Network::Bitcoin, &[1u8; 64]).unwrap())
+    }
+
+    #[test]
+    fn keys_are_recovered_from_seed_and_key_index() {
+        let identity = cfd_keys().derive(1, KeyKind::Identity).unwrap();
+        let revocation = cfd_keys().derive(1, KeyKind::Revocation).unwrap();
+
+        assert_eq!(identity, cfd_keys().derive(1, KeyKind::Identity).unwrap());
+        assert_ne!(identity, revocation);
+        assert_ne!(identity, cfd_keys().derive(2, KeyKind::Identity).unwrap());
+    }
+
+    #[test]
+    fn derivation_path_is_made_of_key_index_and_kind() {
+        let path = CfdKeys::derivation_path(7, KeyKind::Publish).unwrap();
+
+        assert_eq!(path.to_string(), "m/7'/2'");
+        assert!(CfdKeys::derivation_path(u32::MAX, KeyKind::Publish).is_err());
+    }
+
+    #[tokio::test]
+    async fn keys_of_different_cfds_are_different() {
+        let db = sqlite_db::memory().await.unwrap();
+
+        let first = cfd_keys()
+            .derive_for_setup(&db, OrderId::default())
+            .await
+            .unwrap();
+        let second = cfd_keys()
+            .derive_for_setup(&db, OrderId::default())
+            .await
+            .unwrap();
+
+        assert_ne!(first.identity, second.identity);
+    }
+
+    #[tokio::test]
+    async fn keys_are_not_derived_twice_for_same_cfd() {
+        let db = sqlite_db::memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        cfd_keys().derive_for_setup(&db, order_id).await.unwrap();
+
+        assert!(cfd_keys().derive_for_setup(&db, order_id).await.is_err());
+    }
+}
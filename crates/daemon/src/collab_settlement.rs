@@ -1,4 +1,65 @@
+use crate::command;
+use crate::wallet;
+use anyhow::Result;
+use xtra::prelude::MessageChannel;
+
 mod current;
 pub mod deprecated;
 
 pub use current::*;
+
+/// Conclude the collaborative settlements of the open CFDs which were
+/// interrupted by a restart.
+///
+/// Should be called on startup, after rehydrating the open CFDs.
+/// Settlements which were signed by both parties are completed, which
+/// also broadcasts their transaction. All others fail, so that the CFDs
+/// can be settled again.
+pub async fn resume_interrupted(
+    db: &sqlite_db::Connection,
+    executor: &command::Executor,
+) -> Result<()> {
+    for order_id in db.load_open_cfd_ids().await? {
+        let settlement = db.load_pending_collab_settlement(order_id).await?;
+        let is_pending = settlement.is_some();
+
+        if let Err(e) = executor
+            .execute(order_id, |cfd| {
+                Ok(cfd.resume_collaborative_settlement(settlement))
+            })
+            .await
+        {
+            tracing::warn!(%order_id, "Failed to resume collaborative settlement: {e:#}");
+            continue;
+        }
+
+        if is_pending {
+            tracing::info!(%order_id, "Resumed signed collaborative settlement");
+            db.delete_pending_collab_settlement(order_id).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Our block height, which bounds the lock time of settlements proposed by takers.
+///
+/// Falls back to `0` if we fail to get the block height, so that only
+/// settlements without a lock time are accepted.
+pub(crate) async fn maker_block_height(
+    block_height: &MessageChannel<wallet::GetBlockHeight, Result<u32>>,
+) -> u32 {
+    match block_height.send(wallet::GetBlockHeight).await {
+        Ok(Ok(height)) => height,
+        Ok(Err(e)) => {
+            tracing::warn!("Only accepting settlements without lock time, no block height: {e:#}");
+            0
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Only accepting settlements without lock time, wallet actor disconnected: {e:#}"
+            );
+            0
+        }
+    }
+}
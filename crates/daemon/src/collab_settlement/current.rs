@@ -1,5 +1,61 @@
+use anyhow::Result;
+use asynchronous_codec::Framed;
+use asynchronous_codec::JsonCodec;
+use xtra_libp2p::recorder::ensure_replay_finished;
+use xtra_libp2p::recorder::next_replayed;
+use xtra_libp2p::recorder::ReplayedSubstream;
+
 pub mod maker;
 pub mod protocol;
 pub mod taker;
 
 pub const PROTOCOL: &str = "/itchysats/collab-settlement/2.0.0";
+
+/// Advertised by makers which accept settlement transactions with a lock
+/// time, next to [`PROTOCOL`].
+///
+/// Nobody listens for it, it only tells the taker that it may lock the
+/// settlement transaction to the current block height.
+pub const LOCK_TIME_FEATURE: &str = "/itchysats/collab-settlement/lock-time/1.0.0";
+
+/// Run the taker's handling of a recorded substream on the frames the
+/// maker sent.
+pub async fn replay_dialer(stream: ReplayedSubstream) -> Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        JsonCodec::<protocol::DialerMessage, protocol::ListenerMessage>::new(),
+    );
+
+    let decision = match next_replayed(&mut framed).await? {
+        Some(msg) => msg.into_decision()?,
+        None => return Ok(()),
+    };
+
+    if let protocol::Decision::Accept = decision {
+        if let Some(msg) = next_replayed(&mut framed).await? {
+            msg.into_listener_signature()?;
+        }
+    }
+
+    ensure_replay_finished(&mut framed).await
+}
+
+/// Run the maker's handling of a recorded substream on the frames the
+/// taker sent.
+pub async fn replay_listener(stream: ReplayedSubstream) -> Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        JsonCodec::<protocol::ListenerMessage, protocol::DialerMessage>::new(),
+    );
+
+    match next_replayed(&mut framed).await? {
+        Some(msg) => msg.into_propose()?,
+        None => return Ok(()),
+    };
+
+    if let Some(msg) = next_replayed(&mut framed).await? {
+        msg.into_dialer_signature()?;
+    }
+
+    ensure_replay_finished(&mut framed).await
+}
@@ -1,6 +1,9 @@
+use crate::collab_settlement::maker_block_height;
 use crate::collab_settlement::protocol::*;
 use crate::command;
+use crate::wallet;
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
@@ -15,16 +18,29 @@ use model::OrderId;
 use model::SettlementProposal;
 use model::SettlementTransaction;
 use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
 use tokio_extras::FutureExt;
+use tracing::field;
+use tracing::Instrument;
+use tracing::Span;
+use xtra::prelude::MessageChannel;
+use xtra::Address;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_libp2p::Substream;
 use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often we check for settlement proposals which expired before being decided upon.
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 
 type ListenerConnection = (
     Framed<Substream, JsonCodec<ListenerMessage, DialerMessage>>,
     SettlementTransaction,
     SettlementProposal,
     PeerId,
+    // The time at which the proposal expires unless decided upon
+    Instant,
 );
 
 /// Permanent actor to handle incoming substreams for the `/itchysats/collab-settlement/1.0.0`
@@ -35,15 +51,24 @@ type ListenerConnection = (
 pub struct Actor {
     pending_protocols: HashMap<OrderId, ListenerConnection>,
     executor: command::Executor,
+    db: sqlite_db::Connection,
     n_payouts: usize,
+    block_height: MessageChannel<wallet::GetBlockHeight, Result<u32>>,
 }
 
 impl Actor {
-    pub fn new(executor: command::Executor, n_payouts: usize) -> Self {
+    pub fn new(
+        executor: command::Executor,
+        db: sqlite_db::Connection,
+        n_payouts: usize,
+        block_height: MessageChannel<wallet::GetBlockHeight, Result<u32>>,
+    ) -> Self {
         Self {
             pending_protocols: HashMap::default(),
             executor,
+            db,
             n_payouts,
+            block_height,
         }
     }
 }
@@ -52,6 +77,18 @@ impl Actor {
 impl xtra::Actor for Actor {
     type Stop = ();
 
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(
+                EXPIRY_CHECK_INTERVAL,
+                || ExpireProposals,
+                xtras::IncludeSpan::Never,
+            ),
+        );
+    }
+
     async fn stopped(self) -> Self::Stop {}
 }
 
@@ -60,6 +97,11 @@ impl Actor {
     async fn handle(&mut self, msg: NewInboundSubstream, ctx: &mut xtra::Context<Self>) {
         let NewInboundSubstream { peer_id, stream } = msg;
         let address = ctx.address().expect("we are alive");
+        let span = tracing::info_span!(
+            "Collaborative settlement",
+            order_id = field::Empty,
+            %peer_id,
+        );
 
         tokio_extras::spawn_fallible(
             &address.clone(),
@@ -74,6 +116,8 @@ impl Actor {
                     .context("Failed to decode Propose")?
                     .into_propose()?;
 
+                Span::current().record("order_id", &field::display(propose.id));
+
                 address
                     .send(ProposeReceived {
                         propose,
@@ -83,9 +127,13 @@ impl Actor {
                     .await?;
 
                 anyhow::Ok(())
-            },
-            move |e| async move {
-                tracing::warn!(%peer_id, "Failed to handle incoming collab settlement: {e:#}")
+            }
+            .instrument(span.clone()),
+            move |e| {
+                async move {
+                    tracing::warn!(%peer_id, "Failed to handle incoming collab settlement: {e:#}")
+                }
+                .instrument(span)
             },
         );
     }
@@ -100,15 +148,18 @@ impl Actor {
             peer_id,
         } = msg;
         let order_id = propose.id;
+        let expires_at = Instant::now() + propose.ttl();
+        let block_height = maker_block_height(&self.block_height).await;
 
         let result = self
             .executor
-            .execute(order_id, |cfd| {
+            .execute_with_retry(order_id, |cfd| {
                 cfd.verify_counterparty_peer_id(&peer_id.into())?;
                 cfd.start_collab_settlement_maker_olivia_max(
                     propose.price,
                     self.n_payouts,
                     &propose.unsigned_tx,
+                    block_height,
                 )
             })
             .await
@@ -122,23 +173,37 @@ impl Actor {
             }
         };
 
-        self.pending_protocols
-            .insert(order_id, (framed, transaction, proposal, peer_id));
+        self.pending_protocols.insert(
+            order_id,
+            (framed, transaction, proposal, peer_id, expires_at),
+        );
     }
 
     async fn handle(&mut self, msg: Accept, ctx: &mut xtra::Context<Self>) -> Result<()> {
         let Accept { order_id } = msg;
 
-        let (mut framed, transaction, proposal, _peer) =
-            self.pending_protocols
-                .remove(&order_id)
-                .with_context(|| format!("No active protocol for order {order_id}"))?;
+        let connection = self
+            .pending_protocols
+            .remove(&order_id)
+            .with_context(|| format!("No active protocol for order {order_id}"))?;
 
         let this = ctx.address().expect("we are alive");
+
+        if connection.4 <= Instant::now() {
+            self.expire(order_id, connection, &this).await;
+            bail!("Settlement proposal for order {order_id} expired");
+        }
+
+        let (mut framed, transaction, proposal, peer_id, _) = connection;
+
+        let span = tracing::info_span!("Collaborative settlement", %order_id, %peer_id);
+
         tokio_extras::spawn_fallible(
             &this,
             {
                 let executor = self.executor.clone();
+                let db = self.db.clone();
+                let span = span.clone();
                 async move {
                     executor
                         .execute(order_id, |cfd| {
@@ -180,6 +245,13 @@ impl Actor {
                         "Received collab settlement transaction from taker"
                     );
 
+                    // Once we reveal our signature, the taker can publish the settlement
+                    // transaction. Persist it first, so that we can complete the settlement
+                    // even if we are interrupted.
+                    db.insert_pending_collab_settlement(order_id, &settlement)
+                        .await
+                        .context("Failed to persist signed settlement")?;
+
                     framed
                         .send(ListenerMessage::ListenerSignature(ListenerSignature {
                             listener_signature,
@@ -191,22 +263,31 @@ impl Actor {
                         })?;
 
                     emit_completed(order_id, settlement, &executor).await;
+                    delete_pending_settlement(order_id, &db).await;
+
                     Ok(())
                 }
+                .instrument(span)
             },
             {
                 let executor = self.executor.clone();
-                move |failed| async move {
-                    match failed {
-                        e @ Failed::BeforeReceiving { .. } => {
-                            emit_failed(order_id, anyhow!(e), &executor).await;
-                        }
-                        e @ Failed::AfterReceiving { .. } => {
-                            // TODO: proceed with the transaction when taker will be able to handle
-                            // that case.
-                            emit_failed(order_id, anyhow!(e), &executor).await;
+                let db = self.db.clone();
+                move |failed| {
+                    async move {
+                        match failed {
+                            e @ Failed::BeforeReceiving { .. } => {
+                                emit_failed(order_id, anyhow!(e), &executor).await;
+                            }
+                            e @ Failed::AfterReceiving { .. } => {
+                                // TODO: proceed with the transaction when taker will be able to
+                                // handle that case.
+                                emit_failed(order_id, anyhow!(e), &executor).await;
+                            }
                         }
+
+                        delete_pending_settlement(order_id, &db).await;
                     }
+                    .instrument(span)
                 }
             },
         );
@@ -217,22 +298,36 @@ impl Actor {
     async fn handle(&mut self, msg: Reject, ctx: &mut xtra::Context<Self>) -> Result<()> {
         let Reject { order_id } = msg;
 
-        let (mut framed, ..) = self
+        let connection = self
             .pending_protocols
             .remove(&order_id)
             .with_context(|| format!("No active protocol for order {order_id}"))?;
-        emit_rejected(order_id, &self.executor).await;
 
         let this = ctx.address().expect("we are alive");
+
+        if connection.4 <= Instant::now() {
+            self.expire(order_id, connection, &this).await;
+            bail!("Settlement proposal for order {order_id} expired");
+        }
+
+        let (mut framed, .., peer_id, _) = connection;
+        emit_rejected(order_id, &self.executor).await;
+
+        let span = tracing::info_span!("Collaborative settlement", %order_id, %peer_id);
+
         tokio_extras::spawn_fallible(
             &this,
             async move {
                 framed
                     .send(ListenerMessage::Decision(Decision::Reject))
                     .await
-            },
-            move |e| async move {
-                tracing::warn!(%order_id, "Failed to reject collaborative settlement: {e:#}")
+            }
+            .instrument(span.clone()),
+            move |e| {
+                async move {
+                    tracing::warn!(%order_id, "Failed to reject collaborative settlement: {e:#}")
+                }
+                .instrument(span)
             },
         );
 
@@ -240,12 +335,78 @@ impl Actor {
     }
 }
 
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _: ExpireProposals, ctx: &mut xtra::Context<Self>) {
+        let now = Instant::now();
+        let expired = self
+            .pending_protocols
+            .iter()
+            .filter(|(_, (.., expires_at))| *expires_at <= now)
+            .map(|(order_id, _)| *order_id)
+            .collect::<Vec<_>>();
+
+        let this = ctx.address().expect("we are alive");
+        for order_id in expired {
+            let connection = self
+                .pending_protocols
+                .remove(&order_id)
+                .expect("expired proposal to be pending");
+
+            self.expire(order_id, connection, &this).await;
+        }
+    }
+}
+
+impl Actor {
+    /// Expire a settlement proposal which was not decided upon within its time-to-live and let
+    /// the taker know.
+    async fn expire(
+        &self,
+        order_id: OrderId,
+        connection: ListenerConnection,
+        this: &Address<Self>,
+    ) {
+        let (mut framed, .., peer_id, _) = connection;
+        emit_expired(order_id, &self.executor).await;
+
+        let span = tracing::info_span!("Collaborative settlement", %order_id, %peer_id);
+
+        tokio_extras::spawn_fallible(
+            this,
+            async move {
+                framed
+                    .send(ListenerMessage::Decision(Decision::Expired))
+                    .await
+            }
+            .instrument(span.clone()),
+            move |e| {
+                async move {
+                    tracing::debug!(%order_id, "Failed to notify taker about expired settlement proposal: {e:#}")
+                }
+                .instrument(span)
+            },
+        );
+    }
+}
+
+async fn delete_pending_settlement(order_id: OrderId, db: &sqlite_db::Connection) {
+    if let Err(e) = db.delete_pending_collab_settlement(order_id).await {
+        tracing::warn!(%order_id, "Failed to delete pending settlement: {e:#}");
+    }
+}
+
 struct ProposeReceived {
     propose: Propose,
     framed: Framed<Substream, JsonCodec<ListenerMessage, DialerMessage>>,
     peer_id: PeerId,
 }
 
+/// Message sent to ourselves at an interval to expire settlement proposals which were not decided
+/// upon in time.
+#[derive(Clone, Copy)]
+struct ExpireProposals;
+
 #[derive(Clone, Copy)]
 pub struct Accept {
     pub order_id: OrderId,
@@ -17,25 +17,35 @@ use model::Price;
 use model::SettlementTransaction;
 use serde::Deserialize;
 use serde::Serialize;
+use tokio_extras::CommitToken;
 use tokio_extras::FutureExt;
 use xtra::Address;
 use xtra_libp2p::Endpoint;
 use xtra_libp2p::OpenSubstream;
 
-/// The duration that the taker waits until a decision (accept/reject) is expected from the maker
+/// How long a settlement proposal can await the maker's decision (accept/reject)
 ///
-/// If the maker does not respond within `DECISION_TIMEOUT` seconds then the taker will fail the
-/// collab settlement.
-const DECISION_TIMEOUT: Duration = Duration::from_secs(30);
+/// The taker sends the time-to-live along with the proposal. If the maker has not decided within
+/// it, both sides expire the proposal.
+pub const SETTLEMENT_PROPOSAL_TTL: Duration = Duration::from_secs(30);
+
+/// How much longer than the proposal's time-to-live the taker waits for the maker's decision
+///
+/// The maker only decides within the time-to-live, measured from when it received the proposal.
+/// The grace period accounts for the proposal and the decision being in transit, so that the taker
+/// does not expire a proposal which the maker accepted in time.
+const DECISION_GRACE_PERIOD: Duration = Duration::from_secs(10);
 
 pub const SETTLEMENT_MSG_TIMEOUT: Duration = Duration::from_secs(120);
 
-#[tracing::instrument(skip(endpoint, collab_settlement_tx))]
+#[tracing::instrument(skip(endpoint, collab_settlement_tx, token))]
 pub async fn dialer(
     endpoint: Address<Endpoint>,
     order_id: OrderId,
     counterparty: PeerId,
     collab_settlement_tx: SettlementTransaction,
+    token: CommitToken,
+    completion_deadline: Duration,
 ) -> Result<CollaborativeSettlement, DialerFailed> {
     let substream = endpoint
         .send(OpenSubstream::single_protocol(counterparty, PROTOCOL))
@@ -56,27 +66,34 @@ pub async fn dialer(
             id: order_id,
             price: collab_settlement_tx.price(),
             unsigned_tx: unsigned_tx.clone(),
+            ttl_secs: Some(SETTLEMENT_PROPOSAL_TTL.as_secs()),
         }))
         .await
         .context("Failed to send Propose")?;
 
-    if let Decision::Reject = framed
+    let decision = match framed
         .next()
-        .timeout(DECISION_TIMEOUT, || {
+        .timeout(SETTLEMENT_PROPOSAL_TTL + DECISION_GRACE_PERIOD, || {
             tracing::debug_span!("receive decision")
         })
         .await
-        .with_context(|| {
-            format!(
-                "Maker did not accept/reject within {} seconds.",
-                DECISION_TIMEOUT.as_secs()
-            )
-        })?
-        .context("End of stream while receiving Decision")?
-        .context("Failed to decode Decision")?
-        .into_decision()?
     {
-        return Err(DialerFailed::Rejected);
+        Ok(decision) => decision
+            .context("End of stream while receiving Decision")?
+            .context("Failed to decode Decision")?
+            .into_decision()?,
+        Err(_) => return Err(DialerFailed::Expired),
+    };
+
+    // Once the maker has decided, the proposal can no longer be cancelled
+    token
+        .commit()
+        .context("Settlement proposal was cancelled")?;
+
+    match decision {
+        Decision::Accept => {}
+        Decision::Reject => return Err(DialerFailed::Rejected),
+        Decision::Expired => return Err(DialerFailed::Expired),
     }
 
     framed
@@ -86,14 +103,29 @@ pub async fn dialer(
         .await
         .context("Failed to send DialerSignature")?;
 
-    let listener_signature = match framed.next().await {
-        Some(Ok(msg)) => msg.into_listener_signature()?,
-        Some(Err(_)) | None => {
+    let listener_signature = match framed
+        .next()
+        .timeout(completion_deadline, || {
+            tracing::debug_span!("receive listener signature")
+        })
+        .await
+    {
+        Ok(Some(Ok(msg))) => msg.into_listener_signature()?,
+        Ok(Some(Err(_)) | None) => {
             return Err(DialerFailed::AfterSendingSignature {
                 unsigned_tx: unsigned_tx.clone(),
                 error: anyhow!("failed to receive ListenerSignature"),
             });
         }
+        Err(_) => {
+            return Err(DialerFailed::AfterSendingSignature {
+                unsigned_tx: unsigned_tx.clone(),
+                error: anyhow!(
+                    "Maker did not send ListenerSignature within {} seconds",
+                    completion_deadline.as_secs()
+                ),
+            });
+        }
     };
 
     let collab_settlement_tx = match collab_settlement_tx
@@ -123,6 +155,8 @@ pub async fn dialer(
 pub enum DialerFailed {
     #[error("Rejected")]
     Rejected,
+    #[error("Proposal expired before the maker decided")]
+    Expired,
     #[error("Failed after sending signature")]
     AfterSendingSignature {
         unsigned_tx: Transaction,
@@ -198,12 +232,31 @@ pub struct Propose {
     /// side wants to perform collaborative settlement.
     #[serde(with = "hex_transaction")]
     pub unsigned_tx: Transaction,
+    /// How long the proposal can await the maker's decision, in seconds.
+    ///
+    /// Not sent by takers which predate proposal expiry, in which case
+    /// [`SETTLEMENT_PROPOSAL_TTL`] applies.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+impl Propose {
+    pub fn ttl(&self) -> Duration {
+        self.ttl_secs
+            .map(Duration::from_secs)
+            .unwrap_or(SETTLEMENT_PROPOSAL_TTL)
+    }
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum Decision {
     Accept,
     Reject,
+    /// The maker did not decide within the proposal's time-to-live.
+    ///
+    /// Takers which predate proposal expiry fail to decode this and fail
+    /// the settlement instead.
+    Expired,
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -242,6 +295,42 @@ pub(crate) async fn emit_rejected(order_id: OrderId, executor: &command::Executo
     }
 }
 
+pub(crate) async fn emit_expired(order_id: OrderId, executor: &command::Executor) {
+    if let Err(e) = executor
+        .execute(order_id, |cfd| {
+            cfd.expire_collaborative_settlement_proposal()
+        })
+        .await
+    {
+        tracing::error!(%order_id, "Failed to execute `expire_collaborative_settlement_proposal` command: {e:#}")
+    }
+}
+
+/// Abandon a settlement which the maker did not complete after receiving our signature, and commit
+/// the CFD to the blockchain instead.
+pub(crate) async fn emit_abandoned(
+    order_id: OrderId,
+    e: anyhow::Error,
+    executor: &command::Executor,
+) {
+    let reason = format!("{e:#}");
+
+    if let Err(e) = executor
+        .execute(order_id, |cfd| cfd.abandon_collaborative_settlement(reason))
+        .await
+    {
+        tracing::error!(%order_id, "Failed to execute `abandon_collaborative_settlement` command: {e:#}");
+        return;
+    }
+
+    if let Err(e) = executor
+        .execute(order_id, |cfd| cfd.manual_commit_to_blockchain())
+        .await
+    {
+        tracing::error!(%order_id, "Failed to execute `manual_commit_to_blockchain` command: {e:#}");
+    }
+}
+
 pub(crate) async fn emit_failed(order_id: OrderId, e: anyhow::Error, executor: &command::Executor) {
     if let Err(e) = executor
         .execute(order_id, |cfd| Ok(cfd.fail_collaborative_settlement(e)))
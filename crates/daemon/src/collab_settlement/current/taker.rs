@@ -1,5 +1,7 @@
 use crate::collab_settlement::protocol::*;
 use crate::command;
+use crate::identify::PeerInfo;
+use crate::wallet;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
@@ -7,22 +9,77 @@ use async_trait::async_trait;
 use model::libp2p::PeerId;
 use model::OrderId;
 use model::Price;
+use model::Timestamp;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio_extras::CancellableTasks;
+use tokio_extras::CommitToken;
+use tracing::Instrument;
+use xtra::prelude::MessageChannel;
 use xtra::Address;
 use xtra_libp2p::Endpoint;
 use xtra_productivity::xtra_productivity;
 
+/// How long the maker has to complete a settlement it accepted, once it received our signature.
+///
+/// Past this deadline we assume the maker went away and commit the CFD, because the maker could
+/// still publish the settlement transaction we signed.
+const COMPLETION_DEADLINE: Duration = Duration::from_secs(120);
+
 pub struct Actor {
     endpoint: Address<Endpoint>,
     executor: command::Executor,
     n_payouts: usize,
+    maker_info: watch::Receiver<Option<PeerInfo>>,
+    block_height: MessageChannel<wallet::GetBlockHeight, Result<u32>>,
+    pending_proposals: CancellableTasks<OrderId, Timestamp>,
 }
 
 impl Actor {
-    pub fn new(endpoint: Address<Endpoint>, executor: command::Executor, n_payouts: usize) -> Self {
+    pub fn new(
+        endpoint: Address<Endpoint>,
+        executor: command::Executor,
+        n_payouts: usize,
+        maker_info: watch::Receiver<Option<PeerInfo>>,
+        block_height: MessageChannel<wallet::GetBlockHeight, Result<u32>>,
+    ) -> Self {
         Self {
             endpoint,
             executor,
             n_payouts,
+            maker_info,
+            block_height,
+            pending_proposals: CancellableTasks::default(),
+        }
+    }
+
+    /// The block height to lock the settlement transaction to, so that
+    /// miners gain nothing from reorganising the chain to take its fee.
+    ///
+    /// This is best-effort: without a lock time the settlement is just
+    /// as valid, hence we fall back to `0` if the maker would not accept
+    /// a lock time or we fail to get the current block height.
+    async fn lock_time(&self) -> u32 {
+        let maker_accepts_lock_time = self
+            .maker_info
+            .borrow()
+            .as_ref()
+            .map_or(false, PeerInfo::maker_accepts_settlement_lock_time);
+
+        if !maker_accepts_lock_time {
+            return 0;
+        }
+
+        match self.block_height.send(wallet::GetBlockHeight).await {
+            Ok(Ok(height)) => height,
+            Ok(Err(e)) => {
+                tracing::warn!("Settling without lock time, no block height: {e:#}");
+                0
+            }
+            Err(e) => {
+                tracing::warn!("Settling without lock time, wallet actor disconnected: {e:#}");
+                0
+            }
         }
     }
 }
@@ -41,60 +98,119 @@ pub struct Settle {
     pub maker_peer_id: PeerId,
 }
 
+/// List the settlement proposals which are still awaiting the maker's
+/// decision, together with the time they were sent.
+#[derive(Clone, Copy)]
+pub struct GetPendingProposals;
+
+/// Cancel a settlement proposal which is still awaiting the maker's
+/// decision.
+#[derive(Clone, Copy)]
+pub struct CancelProposal {
+    pub order_id: OrderId,
+}
+
 #[xtra_productivity]
 impl Actor {
-    pub async fn handle(&mut self, msg: Settle, ctx: &mut xtra::Context<Self>) -> Result<()> {
+    pub async fn handle(&mut self, msg: Settle) -> Result<()> {
         let Settle {
             order_id,
             price,
             maker_peer_id,
         } = msg;
 
+        let lock_time = self.lock_time().await;
+
         let (collab_settlement_tx, _) = self
             .executor
-            .execute(order_id, |cfd| {
-                cfd.start_collab_settlement_taker(price, self.n_payouts)
+            .execute_with_retry(order_id, |cfd| {
+                cfd.start_collab_settlement_taker(price, self.n_payouts, lock_time)
             })
             .await
             .context("could not start closing position")?;
 
-        tokio_extras::spawn_fallible(
-            &ctx.address().expect("self to be alive"),
-            {
-                let endpoint = self.endpoint.clone();
-                let executor = self.executor.clone();
-                async move {
-                    let settlement = dialer(
-                        endpoint,
-                        order_id,
-                        maker_peer_id.inner(),
-                        collab_settlement_tx.clone(),
-                    )
-                    .await?;
-
-                    emit_completed(order_id, settlement, &executor).await;
-                    Ok(())
-                }
-            },
-            {
-                let executor = self.executor.clone();
-                move |e| async move {
-                    match e {
-                        e @ DialerFailed::AfterSendingSignature { .. } => {
-                            // TODO: We should start monitoring whether other party published the
-                            // transaction
-                            emit_failed(order_id, anyhow!(e), &executor).await;
-                        }
-                        e @ DialerFailed::BeforeSendingSignature { .. } => {
-                            emit_failed(order_id, anyhow!(e), &executor).await;
+        let span = tracing::info_span!(
+            "Collaborative settlement",
+            %order_id,
+            peer_id = %maker_peer_id,
+        );
+
+        self.pending_proposals
+            .add_fallible(
+                order_id,
+                Timestamp::now(),
+                {
+                    let endpoint = self.endpoint.clone();
+                    let executor = self.executor.clone();
+                    let span = span.clone();
+                    move |token: CommitToken| {
+                        async move {
+                            let settlement = dialer(
+                                endpoint,
+                                order_id,
+                                maker_peer_id.inner(),
+                                collab_settlement_tx.clone(),
+                                token,
+                                COMPLETION_DEADLINE,
+                            )
+                            .await?;
+
+                            emit_completed(order_id, settlement, &executor).await;
+                            Ok(())
                         }
-                        DialerFailed::Rejected => {
-                            emit_rejected(order_id, &executor).await;
+                        .instrument(span)
+                    }
+                },
+                {
+                    let executor = self.executor.clone();
+                    move |e| {
+                        async move {
+                            match e {
+                                DialerFailed::AfterSendingSignature { error, .. } => {
+                                    emit_abandoned(order_id, error, &executor).await;
+                                }
+                                e @ DialerFailed::BeforeSendingSignature { .. } => {
+                                    emit_failed(order_id, anyhow!(e), &executor).await;
+                                }
+                                DialerFailed::Rejected => {
+                                    emit_rejected(order_id, &executor).await;
+                                }
+                                DialerFailed::Expired => {
+                                    emit_expired(order_id, &executor).await;
+                                }
+                            }
                         }
+                        .instrument(span)
                     }
-                }
-            },
-        );
+                },
+            )
+            .context("Collaborative settlement is already in progress")?;
+
+        Ok(())
+    }
+
+    pub async fn handle(&mut self, _: GetPendingProposals) -> Vec<(OrderId, Timestamp)> {
+        self.pending_proposals
+            .cancellable()
+            .map(|(order_id, since)| (*order_id, *since))
+            .collect()
+    }
+
+    pub async fn handle(&mut self, msg: CancelProposal) -> Result<()> {
+        let CancelProposal { order_id } = msg;
+
+        self.pending_proposals.cancel(&order_id).with_context(|| {
+            format!("No settlement proposal for {order_id} awaiting the maker's decision")
+        })?;
+
+        tracing::info!(%order_id, "Cancelled settlement proposal");
+
+        emit_failed(
+            order_id,
+            anyhow!("Settlement proposal cancelled by user"),
+            &self.executor,
+        )
+        .await;
 
         Ok(())
     }
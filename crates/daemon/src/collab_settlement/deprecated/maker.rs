@@ -1,5 +1,7 @@
+use crate::collab_settlement::maker_block_height;
 use crate::collab_settlement::protocol::*;
 use crate::command;
+use crate::wallet;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
@@ -17,6 +19,10 @@ use model::SettlementTransaction;
 use std::collections::HashMap;
 use tokio_extras::FutureExt;
 use tokio_extras::Tasks;
+use tracing::field;
+use tracing::Instrument;
+use tracing::Span;
+use xtra::prelude::MessageChannel;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_libp2p::Substream;
 use xtra_productivity::xtra_productivity;
@@ -38,15 +44,21 @@ pub struct Actor {
     pending_protocols: HashMap<OrderId, ListenerConnection>,
     executor: command::Executor,
     n_payouts: usize,
+    block_height: MessageChannel<wallet::GetBlockHeight, Result<u32>>,
 }
 
 impl Actor {
-    pub fn new(executor: command::Executor, n_payouts: usize) -> Self {
+    pub fn new(
+        executor: command::Executor,
+        n_payouts: usize,
+        block_height: MessageChannel<wallet::GetBlockHeight, Result<u32>>,
+    ) -> Self {
         Self {
             protocol_tasks: HashMap::default(),
             pending_protocols: HashMap::default(),
             executor,
             n_payouts,
+            block_height,
         }
     }
 }
@@ -63,6 +75,11 @@ impl Actor {
     async fn handle(&mut self, msg: NewInboundSubstream, ctx: &mut xtra::Context<Self>) {
         let NewInboundSubstream { peer_id, stream } = msg;
         let address = ctx.address().expect("we are alive");
+        let span = tracing::info_span!(
+            "Collaborative settlement",
+            order_id = field::Empty,
+            %peer_id,
+        );
 
         tokio_extras::spawn_fallible(
             &address.clone(),
@@ -77,6 +94,8 @@ impl Actor {
                     .context("Failed to decode Propose")?
                     .into_propose()?;
 
+                Span::current().record("order_id", &field::display(propose.id));
+
                 address
                     .send(ProposeReceived {
                         propose,
@@ -86,9 +105,13 @@ impl Actor {
                     .await?;
 
                 anyhow::Ok(())
-            },
-            move |e| async move {
-                tracing::warn!(%peer_id, "Failed to handle incoming collab settlement: {e:#}")
+            }
+            .instrument(span.clone()),
+            move |e| {
+                async move {
+                    tracing::warn!(%peer_id, "Failed to handle incoming collab settlement: {e:#}")
+                }
+                .instrument(span)
             },
         );
     }
@@ -103,15 +126,17 @@ impl Actor {
             peer_id,
         } = msg;
         let order_id = propose.id;
+        let block_height = maker_block_height(&self.block_height).await;
 
         let result = self
             .executor
-            .execute(order_id, |cfd| {
+            .execute_with_retry(order_id, |cfd| {
                 cfd.verify_counterparty_peer_id(&peer_id.into())?;
                 cfd.start_collab_settlement_maker_double_initial(
                     propose.price,
                     self.n_payouts,
                     &propose.unsigned_tx,
+                    block_height,
                 )
             })
             .await
@@ -132,15 +157,18 @@ impl Actor {
     async fn handle(&mut self, msg: Accept) -> Result<()> {
         let Accept { order_id } = msg;
 
-        let (mut framed, transaction, proposal, _peer) =
-            self.pending_protocols
-                .remove(&order_id)
-                .with_context(|| format!("No active protocol for order {order_id}"))?;
+        let (mut framed, transaction, proposal, peer_id) = self
+            .pending_protocols
+            .remove(&order_id)
+            .with_context(|| format!("No active protocol for order {order_id}"))?;
+
+        let span = tracing::info_span!("Collaborative settlement", %order_id, %peer_id);
 
         let mut tasks = Tasks::default();
         tasks.add_fallible(
             {
                 let executor = self.executor.clone();
+                let span = span.clone();
                 async move {
                     executor
                         .execute(order_id, |cfd| {
@@ -195,20 +223,24 @@ impl Actor {
                     emit_completed(order_id, settlement, &executor).await;
                     Ok(())
                 }
+                .instrument(span)
             },
             {
                 let executor = self.executor.clone();
-                move |failed| async move {
-                    match failed {
-                        e @ Failed::BeforeReceiving { .. } => {
-                            emit_failed(order_id, anyhow!(e), &executor).await;
-                        }
-                        e @ Failed::AfterReceiving { .. } => {
-                            // TODO: proceed with the transaction when taker will be able to handle
-                            // that case.
-                            emit_failed(order_id, anyhow!(e), &executor).await;
+                move |failed| {
+                    async move {
+                        match failed {
+                            e @ Failed::BeforeReceiving { .. } => {
+                                emit_failed(order_id, anyhow!(e), &executor).await;
+                            }
+                            e @ Failed::AfterReceiving { .. } => {
+                                // TODO: proceed with the transaction when taker will be able to
+                                // handle that case.
+                                emit_failed(order_id, anyhow!(e), &executor).await;
+                            }
                         }
                     }
+                    .instrument(span)
                 }
             },
         );
@@ -220,21 +252,27 @@ impl Actor {
     async fn handle(&mut self, msg: Reject) -> Result<()> {
         let Reject { order_id } = msg;
 
-        let (mut framed, ..) = self
+        let (mut framed, .., peer_id) = self
             .pending_protocols
             .remove(&order_id)
             .with_context(|| format!("No active protocol for order {order_id}"))?;
         emit_rejected(order_id, &self.executor).await;
 
+        let span = tracing::info_span!("Collaborative settlement", %order_id, %peer_id);
+
         let mut tasks = Tasks::default();
         tasks.add_fallible(
             async move {
                 framed
                     .send(ListenerMessage::Decision(Decision::Reject))
                     .await
-            },
-            move |e| async move {
-                tracing::warn!(%order_id, "Failed to reject collaborative settlement: {e:#}")
+            }
+            .instrument(span.clone()),
+            move |e| {
+                async move {
+                    tracing::warn!(%order_id, "Failed to reject collaborative settlement: {e:#}")
+                }
+                .instrument(span)
             },
         );
         self.protocol_tasks.insert(order_id, tasks);
@@ -1,3 +1,14 @@
+//! Execution of commands on CFD aggregates.
+//!
+//! A command is a function of the latest state of a CFD which decides on the next event of the
+//! CFD, e.g. `|cfd| cfd.manual_commit_to_blockchain()`. The [`Executor`] loads the CFD, executes
+//! the command and hands the resulting event to the process manager, which stores it and acts
+//! upon it.
+//!
+//! Commands which race with other commands on the same CFD can be executed with
+//! [`Executor::execute_with_retry`]. The event is then only stored if no other event was stored
+//! for the CFD in the meantime, otherwise the command is executed again on the new state.
+
 use crate::process_manager;
 use crate::OrderId;
 use anyhow::Context;
@@ -6,28 +17,55 @@ use async_trait::async_trait;
 use model::Cfd;
 use model::ExtractEventFromTuple;
 use sqlite_db;
+use sqlite_db::rehydration;
 use std::fmt;
 use std::fmt::Debug;
-use xtra::Address;
+use std::time::Instant;
+use tokio::sync::watch;
+use xtra::prelude::MessageChannel;
+
+/// How often [`Executor::execute_with_retry`] executes a command on a CFD which keeps changing
+/// before giving up.
+const MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("CFD {0} not found in the open CFDs")]
+    NotFound(OrderId),
+    #[error("CFD {order_id} changed from version {expected} to {actual} while executing command")]
+    VersionConflict {
+        order_id: OrderId,
+        expected: u32,
+        actual: u32,
+    },
+    /// The command rejected the current state of the CFD, e.g. because the CFD cannot be
+    /// settled collaboratively while it is being rolled over.
+    #[error("Failed to execute command on CFD: {0:#}")]
+    InvalidTransition(anyhow::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
 
+/// Executes commands on CFDs and stores the resulting events.
+///
+/// Cheap to clone, every component which needs to change the state of CFDs holds its own
+/// executor.
 #[derive(Clone)]
 pub struct Executor {
     db: sqlite_db::Connection,
-    process_manager: Address<process_manager::Actor>,
+    process_manager: MessageChannel<process_manager::Event, Result<()>>,
 }
 
 impl Debug for Executor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Executor")
-            .field("process_manager", &self.process_manager)
-            .finish()
+        f.debug_struct("Executor").finish_non_exhaustive()
     }
 }
 
 impl Executor {
     pub fn new(
         db: sqlite_db::Connection,
-        process_manager: Address<process_manager::Actor>,
+        process_manager: MessageChannel<process_manager::Event, Result<()>>,
     ) -> Self {
         Self {
             db,
@@ -35,6 +73,32 @@ impl Executor {
         }
     }
 
+    /// Rehydrate all open CFDs concurrently, reporting the progress on
+    /// `progress`.
+    ///
+    /// Should be called on startup, so that commands and queries on the
+    /// open CFDs don't have to apply all of their events first.
+    pub async fn rehydrate(&self, progress: watch::Sender<rehydration::Progress>) -> Result<()> {
+        let started_at = Instant::now();
+
+        let rehydration::Progress {
+            rehydrated, failed, ..
+        } = self
+            .db
+            .rehydrate_open_cfds::<Cfd>((), &progress)
+            .await
+            .context("Failed to rehydrate open CFDs")?;
+
+        tracing::info!(
+            rehydrated,
+            failed,
+            elapsed_ms = started_at.elapsed().as_millis(),
+            "Rehydrated open CFDs"
+        );
+
+        Ok(())
+    }
+
     /// A way of querying values of a CFD, without issuing any commands or
     /// mutating its state.
     ///
@@ -49,31 +113,103 @@ impl Executor {
         query(&cfd).context("Failed to execute command on CFD")
     }
 
+    /// Execute `command` on the latest state of the CFD with `id` and store the resulting
+    /// event, if any.
+    ///
+    /// The event is stored even if other events were stored for the CFD while executing the
+    /// command. Use [`Executor::execute_with_retry`] if the command must see the latest state.
     pub async fn execute<T: ExtractEventFromTuple>(
         &self,
         id: OrderId,
         command: impl FnOnce(Cfd) -> Result<T>,
-    ) -> Result<T::Rest> {
-        let cfd = self
-            .db
-            .load_open_cfd(id, ())
-            .await
-            .context("Failed to load CFD")?;
-
-        let return_val = command(cfd).context("Failed to execute command on CFD")?;
+    ) -> Result<T::Rest, Error> {
+        let cfd = self.load(id).await?;
 
-        let (event, rest) = return_val.extract_event();
+        let (event, rest) = command(cfd)
+            .map_err(Error::InvalidTransition)?
+            .extract_event();
 
         if let Some(event) = event {
-            self.process_manager
-                .send(process_manager::Event::new(event))
-                .await
-                .context("ProcessManager is disconnected")?
-                .context("Failed to process new domain event")?;
+            self.process(id, process_manager::Event::new(event)).await?;
         }
 
         Ok(rest)
     }
+
+    /// Execute `command` on the latest state of the CFD with `id` and store the resulting
+    /// event, if any, unless other events were stored for the CFD in the meantime.
+    ///
+    /// On such a conflict the command is executed again on the new state of the CFD, up to
+    /// [`MAX_ATTEMPTS`] times, before failing with [`Error::VersionConflict`].
+    pub async fn execute_with_retry<T: ExtractEventFromTuple>(
+        &self,
+        id: OrderId,
+        command: impl Fn(Cfd) -> Result<T>,
+    ) -> Result<T::Rest, Error> {
+        let mut attempt = 1;
+
+        loop {
+            let cfd = self.load(id).await?;
+            let version = cfd.version();
+
+            let (event, rest) = command(cfd)
+                .map_err(Error::InvalidTransition)?
+                .extract_event();
+
+            let event = match event {
+                Some(event) => process_manager::Event::at_version(event, version),
+                None => return Ok(rest),
+            };
+
+            match self.process(id, event).await {
+                Err(Error::VersionConflict { actual, .. }) if attempt < MAX_ATTEMPTS => {
+                    tracing::debug!(
+                        order_id = %id,
+                        expected = version,
+                        actual,
+                        attempt,
+                        "CFD changed while executing command, retrying"
+                    );
+                    attempt += 1;
+                }
+                result => return result.map(|()| rest),
+            }
+        }
+    }
+
+    async fn load(&self, id: OrderId) -> Result<Cfd, Error> {
+        match self.db.load_open_cfd(id, ()).await {
+            Ok(cfd) => Ok(cfd),
+            Err(sqlite_db::Error::OpenCfdNotFound) => Err(Error::NotFound(id)),
+            Err(e) => Err(Error::Other(
+                anyhow::Error::new(e).context("Failed to load CFD"),
+            )),
+        }
+    }
+
+    async fn process(&self, id: OrderId, event: process_manager::Event) -> Result<(), Error> {
+        let result = self
+            .process_manager
+            .send(event)
+            .await
+            .context("ProcessManager is disconnected")?;
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => match e.downcast_ref::<sqlite_db::Error>() {
+                Some(sqlite_db::Error::VersionConflict { expected, actual }) => {
+                    Err(Error::VersionConflict {
+                        order_id: id,
+                        expected: *expected,
+                        actual: *actual,
+                    })
+                }
+                _ => Err(Error::Other(
+                    e.context("Failed to process new domain event"),
+                )),
+            },
+        }
+    }
 }
 
 #[async_trait]
@@ -87,6 +223,181 @@ impl model::ExecuteOnCfd for Executor {
         T: ExtractEventFromTuple + Send,
         T::Rest: Send,
     {
-        self.execute(id, command).await
+        Ok(self.execute(id, command).await?)
+    }
+
+    async fn execute_with_retry<T>(
+        &self,
+        id: OrderId,
+        command: impl Fn(Cfd) -> Result<T> + Send + Sync,
+    ) -> Result<T::Rest>
+    where
+        T: ExtractEventFromTuple + Send,
+        T::Rest: Send,
+    {
+        Ok(self.execute_with_retry(id, command).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::Amount;
+    use model::CfdEvent;
+    use model::ContractSymbol;
+    use model::Contracts;
+    use model::EventKind;
+    use model::FundingRate;
+    use model::Leverage;
+    use model::OfferId;
+    use model::OpeningFee;
+    use model::Position;
+    use model::Price;
+    use model::Role;
+    use model::TxFeeRate;
+    use rust_decimal_macros::dec;
+    use std::sync::atomic::AtomicU32;
+    use std::sync::atomic::Ordering;
+    use tokio_extras::Tasks;
+    use xtra::Actor as _;
+    use xtra_productivity::xtra_productivity;
+
+    #[tokio::test]
+    async fn given_cfd_changed_while_executing_command_then_command_is_retried() {
+        let db = sqlite_db::memory().await.unwrap();
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+        let mut tasks = Tasks::default();
+        let executor = executor(&db, cfd.id(), 1, &mut tasks);
+
+        let attempts = AtomicU32::new(0);
+        executor
+            .execute_with_retry(cfd.id(), |cfd| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Ok(Some(lock_confirmed(cfd.id())))
+            })
+            .await
+            .unwrap();
+
+        let cfd = db.load_open_cfd::<Cfd>(cfd.id(), ()).await.unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(cfd.version(), 2);
+    }
+
+    #[tokio::test]
+    async fn given_cfd_keeps_changing_while_executing_command_then_version_conflict() {
+        let db = sqlite_db::memory().await.unwrap();
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+        let mut tasks = Tasks::default();
+        let executor = executor(&db, cfd.id(), MAX_ATTEMPTS, &mut tasks);
+
+        let attempts = AtomicU32::new(0);
+        let result = executor
+            .execute_with_retry(cfd.id(), |cfd| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Ok(Some(lock_confirmed(cfd.id())))
+            })
+            .await;
+
+        let cfd = db.load_open_cfd::<Cfd>(cfd.id(), ()).await.unwrap();
+        assert!(matches!(
+            result,
+            Err(Error::VersionConflict {
+                expected: 2,
+                actual: 3,
+                ..
+            })
+        ));
+        assert_eq!(attempts.load(Ordering::SeqCst), MAX_ATTEMPTS);
+        assert_eq!(cfd.version(), MAX_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn given_cfd_changed_while_executing_command_without_retry_then_event_is_stored() {
+        let db = sqlite_db::memory().await.unwrap();
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+        let mut tasks = Tasks::default();
+        let executor = executor(&db, cfd.id(), 1, &mut tasks);
+
+        executor
+            .execute(cfd.id(), |cfd| Ok(Some(lock_confirmed(cfd.id()))))
+            .await
+            .unwrap();
+
+        let cfd = db.load_open_cfd::<Cfd>(cfd.id(), ()).await.unwrap();
+        assert_eq!(cfd.version(), 2);
+    }
+
+    /// Stores events like the process manager, but stores an event of a concurrent command
+    /// before each of the first `concurrent_commands` events it receives.
+    struct ConcurrentProcessManager {
+        db: sqlite_db::Connection,
+        order_id: OrderId,
+        concurrent_commands: u32,
+    }
+
+    #[async_trait]
+    impl xtra::Actor for ConcurrentProcessManager {
+        type Stop = ();
+
+        async fn stopped(self) -> Self::Stop {}
+    }
+
+    #[xtra_productivity]
+    impl ConcurrentProcessManager {
+        async fn handle(&mut self, msg: process_manager::Event) -> Result<()> {
+            if self.concurrent_commands > 0 {
+                self.concurrent_commands -= 1;
+                self.db.append_event(lock_confirmed(self.order_id)).await?;
+            }
+
+            msg.append_to(&self.db).await?;
+
+            Ok(())
+        }
+    }
+
+    fn executor(
+        db: &sqlite_db::Connection,
+        order_id: OrderId,
+        concurrent_commands: u32,
+        tasks: &mut Tasks,
+    ) -> Executor {
+        let process_manager = ConcurrentProcessManager {
+            db: db.clone(),
+            order_id,
+            concurrent_commands,
+        }
+        .create(None)
+        .spawn(tasks);
+
+        Executor::new(db.clone(), process_manager.into())
+    }
+
+    fn lock_confirmed(order_id: OrderId) -> CfdEvent {
+        CfdEvent::new(order_id, EventKind::LockConfirmed)
+    }
+
+    fn dummy_cfd() -> Cfd {
+        Cfd::new(
+            OrderId::default(),
+            OfferId::default(),
+            Position::Long,
+            Price::new(dec!(60_000)).unwrap(),
+            Leverage::TWO,
+            time::Duration::hours(24),
+            Role::Taker,
+            Contracts::new(1_000),
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"
+                .parse()
+                .unwrap(),
+            None,
+            OpeningFee::new(Amount::from_sat(2000)),
+            FundingRate::default(),
+            TxFeeRate::default(),
+            ContractSymbol::BtcUsd,
+        )
     }
 }
@@ -0,0 +1,143 @@
+//! Periodic backups of the database.
+//!
+//! At every interval a snapshot of the database is written to the backup
+//! directory and checked with `PRAGMA integrity_check`. Snapshots which
+//! fail the check are deleted again. Only the most recent snapshots are
+//! retained, older ones are deleted once a new snapshot has been taken.
+
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+const EXTENSION: &str = "sqlite";
+
+#[derive(Debug, Clone)]
+pub struct BackupSettings {
+    /// The directory the snapshots are written to
+    pub dir: PathBuf,
+    pub interval: Duration,
+    /// How many snapshots to keep
+    pub retain: usize,
+}
+
+impl BackupSettings {
+    pub fn new(dir: PathBuf, interval: Duration, retain: usize) -> Result<Self> {
+        ensure!(!interval.is_zero(), "Backup interval must not be zero");
+        ensure!(retain > 0, "At least one backup must be retained");
+
+        Ok(Self {
+            dir,
+            interval,
+            retain,
+        })
+    }
+}
+
+/// Back up the database every interval, forever.
+///
+/// The snapshots are named after `name`, e.g. `taker-1666000000.sqlite`.
+pub async fn run(db: sqlite_db::Connection, name: &'static str, settings: BackupSettings) {
+    loop {
+        match backup(&db, name, &settings).await {
+            Ok(path) => tracing::info!(path = %path.display(), "Backed up database"),
+            Err(e) => tracing::error!("Failed to back up database: {e:#}"),
+        }
+
+        tokio_extras::time::sleep(settings.interval).await;
+    }
+}
+
+/// Take a snapshot of the database, check it, and delete the snapshots
+/// which are no longer retained.
+pub async fn backup(
+    db: &sqlite_db::Connection,
+    name: &str,
+    settings: &BackupSettings,
+) -> Result<PathBuf> {
+    tokio::fs::create_dir_all(&settings.dir)
+        .await
+        .with_context(|| format!("Failed to create {}", settings.dir.display()))?;
+
+    let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+    let path = settings.dir.join(format!("{name}-{timestamp}.{EXTENSION}"));
+
+    db.backup_to(&path).await?;
+
+    if let Err(e) = sqlite_db::backup::check_integrity(&path).await {
+        tokio::fs::remove_file(&path).await?;
+        return Err(e);
+    }
+
+    for expired in expired_backups(&settings.dir, name, settings.retain)? {
+        tokio::fs::remove_file(&expired)
+            .await
+            .with_context(|| format!("Failed to delete {}", expired.display()))?;
+    }
+
+    Ok(path)
+}
+
+/// The backups named after `name` in `dir`, except for the `retain` most
+/// recent ones.
+fn expired_backups(dir: &Path, name: &str, retain: usize) -> Result<Vec<PathBuf>> {
+    let mut backups = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter_map(|path| Some((backup_timestamp(&path, name)?, path)))
+        .collect::<Vec<_>>();
+
+    backups.sort();
+
+    let n_expired = backups.len().saturating_sub(retain);
+
+    Ok(backups
+        .into_iter()
+        .take(n_expired)
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// The time at which the backup at `path` was taken, if it is a backup
+/// named after `name`.
+fn backup_timestamp(path: &Path, name: &str) -> Option<i64> {
+    if path.extension()? != EXTENSION {
+        return None;
+    }
+
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix(name)?
+        .strip_prefix('-')?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_backups_with_matching_name_are_recognized() {
+        assert_eq!(
+            backup_timestamp(Path::new("/backups/taker-1666000000.sqlite"), "taker"),
+            Some(1666000000)
+        );
+        assert_eq!(
+            backup_timestamp(Path::new("/backups/maker-1666000000.sqlite"), "taker"),
+            None
+        );
+        assert_eq!(
+            backup_timestamp(Path::new("/backups/taker-1666000000.sqlite-wal"), "taker"),
+            None
+        );
+        assert_eq!(
+            backup_timestamp(Path::new("/backups/taker.sqlite"), "taker"),
+            None
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! Health-aware failover between Electrum servers.
+//!
+//! The [`Actor`] periodically checks every configured Electrum server for
+//! reachability, latency and tip height. A server is healthy if it
+//! responds within [`MAX_LATENCY`] and its tip is at most [`MAX_TIP_LAG`]
+//! blocks behind the highest tip reported by any server. The current
+//! server is kept for as long as it is healthy; otherwise the first
+//! healthy server in the configured order takes over. The wallet and the
+//! monitor follow the [`ActiveServer`] and reconnect whenever it changes.
+
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use bdk::electrum_client;
+use bdk::electrum_client::ElectrumApi;
+use serde::Serialize;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::watch;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often the health of the Electrum servers is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Timeout in seconds for connecting to and querying a server.
+const TIMEOUT_SECS: u8 = 10;
+
+/// Servers which take longer than this to report their tip are
+/// considered unhealthy.
+pub const MAX_LATENCY: Duration = Duration::from_secs(5);
+
+/// Servers whose tip is more than this many blocks behind the highest
+/// known tip are considered stale.
+pub const MAX_TIP_LAG: u64 = 2;
+
+/// The health of all configured Electrum servers and the one in use.
+#[derive(Debug, Clone, Serialize)]
+pub struct ElectrumStatus {
+    pub active: String,
+    pub servers: Vec<ServerHealth>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServerHealth {
+    pub url: String,
+    /// Time it took the server to report its tip, `None` if it was not
+    /// reachable.
+    pub latency_ms: Option<u64>,
+    pub tip_height: Option<u64>,
+    pub healthy: bool,
+}
+
+/// The Electrum server the wallet and the monitor are supposed to use.
+#[derive(Clone)]
+pub struct ActiveServer(watch::Receiver<ElectrumStatus>);
+
+impl ActiveServer {
+    /// An active server which never fails over.
+    pub fn fixed(url: String) -> Self {
+        let (_, receiver) = watch::channel(ElectrumStatus {
+            active: url,
+            servers: Vec::new(),
+        });
+
+        Self(receiver)
+    }
+
+    pub fn url(&self) -> String {
+        self.0.borrow().active.clone()
+    }
+
+    pub fn status(&self) -> ElectrumStatus {
+        self.0.borrow().clone()
+    }
+}
+
+/// Message sent to ourselves at an interval to check the health of all
+/// servers.
+#[derive(Clone, Copy)]
+struct CheckServers;
+
+pub struct Actor {
+    servers: Vec<String>,
+    sender: watch::Sender<ElectrumStatus>,
+}
+
+impl Actor {
+    /// Fail over between `servers`, starting with the first one.
+    pub fn new(servers: Vec<String>) -> Result<(Self, ActiveServer)> {
+        let active = servers
+            .first()
+            .context("At least one Electrum server is required")?
+            .clone();
+
+        let (sender, receiver) = watch::channel(ElectrumStatus {
+            active,
+            servers: Vec::new(),
+        });
+
+        Ok((Self { servers, sender }, ActiveServer(receiver)))
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _: CheckServers) {
+        let servers = self.servers.clone();
+        let checks = match tokio::task::spawn_blocking(move || {
+            servers
+                .into_iter()
+                .map(|url| {
+                    let check = check_server(&url);
+                    (url, check)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        {
+            Ok(checks) => checks,
+            Err(e) => {
+                tracing::warn!("Failed to check Electrum servers: {e:#}");
+                return;
+            }
+        };
+
+        for (url, check) in checks.iter() {
+            if let Err(e) = check {
+                tracing::debug!(%url, "Electrum server health check failed: {e:#}");
+            }
+        }
+
+        let servers = health(checks);
+
+        let current = self.sender.borrow().active.clone();
+        let active = match select_active(&current, &servers) {
+            Some(active) => active.to_owned(),
+            None => {
+                tracing::warn!(%current, "No healthy Electrum server available");
+                current.clone()
+            }
+        };
+
+        if active != current {
+            tracing::warn!(from = %current, to = %active, "Failing over to Electrum server");
+        }
+
+        let _ = self.sender.send(ElectrumStatus { active, servers });
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(CHECK_INTERVAL, || CheckServers, xtras::IncludeSpan::Never),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// Fetch the tip height of the server at `url`, together with the time it
+/// took.
+fn check_server(url: &str) -> Result<(Duration, u64)> {
+    let config = electrum_client::ConfigBuilder::new()
+        .timeout(Some(TIMEOUT_SECS))?
+        .build();
+    let client = electrum_client::Client::from_config(url, config)
+        .context("Failed to connect to Electrum server")?;
+
+    let started = Instant::now();
+    let tip = client
+        .block_headers_subscribe()
+        .context("Failed to get tip")?
+        .height;
+
+    Ok((started.elapsed(), tip as u64))
+}
+
+fn health(checks: Vec<(String, Result<(Duration, u64)>)>) -> Vec<ServerHealth> {
+    let max_tip = checks
+        .iter()
+        .filter_map(|(_, check)| check.as_ref().ok().map(|(_, tip)| *tip))
+        .max();
+
+    checks
+        .into_iter()
+        .map(|(url, check)| match (check, max_tip) {
+            (Ok((latency, tip)), Some(max_tip)) => ServerHealth {
+                url,
+                latency_ms: Some(latency.as_millis() as u64),
+                tip_height: Some(tip),
+                healthy: latency <= MAX_LATENCY && tip + MAX_TIP_LAG >= max_tip,
+            },
+            _ => ServerHealth {
+                url,
+                latency_ms: None,
+                tip_height: None,
+                healthy: false,
+            },
+        })
+        .collect()
+}
+
+/// The server to use: `current` if it is healthy, otherwise the first
+/// healthy server.
+///
+/// Returns `None` if no server is healthy.
+fn select_active<'a>(current: &'a str, servers: &'a [ServerHealth]) -> Option<&'a str> {
+    let healthy = |server: &&ServerHealth| server.healthy;
+
+    if servers.iter().filter(healthy).any(|s| s.url == current) {
+        return Some(current);
+    }
+
+    servers.iter().find(healthy).map(|s| s.url.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: &str = "ssl://primary:50002";
+    const BACKUP: &str = "ssl://backup:50002";
+
+    fn reachable(url: &str, latency_ms: u64, tip: u64) -> (String, Result<(Duration, u64)>) {
+        (url.to_owned(), Ok((Duration::from_millis(latency_ms), tip)))
+    }
+
+    fn unreachable(url: &str) -> (String, Result<(Duration, u64)>) {
+        (url.to_owned(), Err(anyhow::anyhow!("Connection refused")))
+    }
+
+    #[test]
+    fn keeps_current_server_while_healthy() {
+        let servers = health(vec![
+            reachable(PRIMARY, 100, 700_000),
+            reachable(BACKUP, 50, 700_001),
+        ]);
+
+        assert_eq!(select_active(BACKUP, &servers), Some(BACKUP));
+        assert_eq!(select_active(PRIMARY, &servers), Some(PRIMARY));
+    }
+
+    #[test]
+    fn fails_over_from_stale_or_unreachable_server() {
+        let stale = health(vec![
+            reachable(PRIMARY, 100, 700_000),
+            reachable(BACKUP, 100, 700_003),
+        ]);
+        let slow = health(vec![
+            reachable(PRIMARY, 6_000, 700_000),
+            reachable(BACKUP, 100, 700_000),
+        ]);
+        let down = health(vec![unreachable(PRIMARY), reachable(BACKUP, 100, 700_000)]);
+
+        assert_eq!(select_active(PRIMARY, &stale), Some(BACKUP));
+        assert_eq!(select_active(PRIMARY, &slow), Some(BACKUP));
+        assert_eq!(select_active(PRIMARY, &down), Some(BACKUP));
+    }
+
+    #[test]
+    fn no_server_selected_if_none_is_healthy() {
+        let servers = health(vec![unreachable(PRIMARY), unreachable(BACKUP)]);
+
+        assert_eq!(select_active(PRIMARY, &servers), None);
+    }
+}
@@ -0,0 +1,158 @@
+//! Estimation of the fee rate a transaction needs to be confirmed in
+//! time under the current mempool conditions.
+//!
+//! The transactions of a CFD are signed during contract setup, so their
+//! fee rate is fixed by the time they are broadcast. The estimate tells
+//! us whether a transaction pays enough, and how much a child has to
+//! pay to get it confirmed via CPFP.
+//!
+//! Fee rates are estimated by the Electrum server by default. The
+//! mempool.space API can be used instead; if it cannot be reached we
+//! fall back to the Electrum server.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::Amount;
+use bdk::electrum_client;
+use bdk::electrum_client::ElectrumApi;
+use bdk::FeeRate;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Timeout for requests to the mempool.space API.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub enum FeeEstimator {
+    /// Ask the Electrum server for `estimatefee`
+    Electrum,
+    /// Ask the mempool.space API at `url` for its recommended fees
+    MempoolSpace {
+        url: String,
+        client: reqwest::Client,
+    },
+}
+
+impl FeeEstimator {
+    pub fn mempool_space(url: String) -> Self {
+        Self::MempoolSpace {
+            url,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Fee rate needed for a transaction to be confirmed within
+    /// `target_blocks`.
+    pub async fn estimate(
+        &self,
+        electrum: &electrum_client::Client,
+        target_blocks: usize,
+    ) -> Result<FeeRate> {
+        match self {
+            FeeEstimator::Electrum => estimate_with_electrum(electrum, target_blocks),
+            FeeEstimator::MempoolSpace { url, client } => {
+                match estimate_with_mempool_space(client, url, target_blocks).await {
+                    Ok(fee_rate) => Ok(fee_rate),
+                    Err(e) => {
+                        tracing::warn!("Falling back to Electrum for fee estimation: {e:#}");
+
+                        estimate_with_electrum(electrum, target_blocks)
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn estimate_with_electrum(
+    client: &electrum_client::Client,
+    target_blocks: usize,
+) -> Result<FeeRate> {
+    let btc_per_kvb = client
+        .estimate_fee(target_blocks)
+        .context("Failed to estimate fee")?;
+
+    // Electrum returns -1 if it does not have enough data
+    let btc_per_kvb = if btc_per_kvb > 0.0 {
+        btc_per_kvb
+    } else {
+        client.relay_fee().context("Failed to get relay fee")?
+    };
+
+    let sat_per_vb = Amount::from_btc(btc_per_kvb)?.as_sat() as f32 / 1000.0;
+
+    Ok(FeeRate::from_sat_per_vb(sat_per_vb))
+}
+
+async fn estimate_with_mempool_space(
+    client: &reqwest::Client,
+    url: &str,
+    target_blocks: usize,
+) -> Result<FeeRate> {
+    let url = format!("{}/api/v1/fees/recommended", url.trim_end_matches('/'));
+
+    let response = client
+        .get(&url)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .with_context(|| format!("Failed to GET {url}"))?;
+
+    let code = response.status();
+    if !code.is_success() {
+        bail!("GET {url} responded with {code}");
+    }
+
+    let fees = response
+        .json::<RecommendedFees>()
+        .await
+        .context("Failed to deserialize recommended fees")?;
+
+    Ok(FeeRate::from_sat_per_vb(fees.for_target(target_blocks)))
+}
+
+/// Fee rates recommended by mempool.space, in sat/vB.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecommendedFees {
+    /// Confirmation within the next block
+    fastest_fee: f32,
+    /// Confirmation within half an hour, i.e. three blocks
+    half_hour_fee: f32,
+    /// Confirmation within an hour, i.e. six blocks
+    hour_fee: f32,
+    economy_fee: f32,
+    minimum_fee: f32,
+}
+
+impl RecommendedFees {
+    fn for_target(&self, target_blocks: usize) -> f32 {
+        let fee = match target_blocks {
+            0 | 1 => self.fastest_fee,
+            2 | 3 => self.half_hour_fee,
+            4..=6 => self.hour_fee,
+            _ => self.economy_fee,
+        };
+
+        fee.max(self.minimum_fee)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recommended_fee_depends_on_confirmation_target() {
+        let fees = serde_json::from_str::<RecommendedFees>(
+            r#"{"fastestFee":30,"halfHourFee":20,"hourFee":10,"economyFee":2,"minimumFee":1}"#,
+        )
+        .unwrap();
+
+        assert_eq!(fees.for_target(1), 30.0);
+        assert_eq!(fees.for_target(3), 20.0);
+        assert_eq!(fees.for_target(6), 10.0);
+        assert_eq!(fees.for_target(144), 2.0);
+    }
+}
@@ -0,0 +1,242 @@
+//! Detection of consumers lagging behind the projection feeds.
+//!
+//! The projection publishes its state through watch channels. A watch
+//! channel only holds the latest value, so a slow consumer (e.g. an SSE
+//! client on a bad connection) never fails: it silently keeps showing
+//! stale data. Every feed therefore has a [`Freshness`] which tracks
+//! since when each consumer has not observed a produced value. The
+//! [`Actor`] periodically exports the staleness of every feed as a
+//! metric and warns about consumers which lag behind for too long.
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often the staleness of all feeds is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Tracks which values of a feed have been observed by its consumers.
+#[derive(Clone, Default)]
+pub struct Freshness(Arc<Mutex<Consumers>>);
+
+#[derive(Default)]
+struct Consumers {
+    next_id: u64,
+    /// For each consumer, since when a produced value is waiting to
+    /// be observed.
+    stale_since: HashMap<u64, Option<Instant>>,
+}
+
+impl Freshness {
+    /// Record that a new value was sent into the feed.
+    pub fn produced(&self) {
+        let now = Instant::now();
+
+        for stale_since in self
+            .0
+            .lock()
+            .expect("lock not to be poisoned")
+            .stale_since
+            .values_mut()
+        {
+            stale_since.get_or_insert(now);
+        }
+    }
+
+    /// Register a new consumer of the feed.
+    ///
+    /// The consumer is deregistered when the returned [`Consumer`] is
+    /// dropped.
+    pub fn consumer(&self) -> Consumer {
+        let mut consumers = self.0.lock().expect("lock not to be poisoned");
+
+        let id = consumers.next_id;
+        consumers.next_id += 1;
+        consumers.stale_since.insert(id, None);
+
+        Consumer {
+            id,
+            freshness: self.clone(),
+        }
+    }
+
+    /// For how long the slowest consumer has not observed a produced
+    /// value.
+    ///
+    /// Returns `None` if all consumers are up-to-date.
+    pub fn staleness(&self, now: Instant) -> Option<Duration> {
+        self.0
+            .lock()
+            .expect("lock not to be poisoned")
+            .stale_since
+            .values()
+            .flatten()
+            .map(|stale_since| now.saturating_duration_since(*stale_since))
+            .max()
+    }
+}
+
+/// A registered consumer of a feed.
+pub struct Consumer {
+    id: u64,
+    freshness: Freshness,
+}
+
+impl Consumer {
+    /// Record that the consumer observed the latest value of the feed.
+    pub fn observed(&self) {
+        if let Some(stale_since) = self
+            .freshness
+            .0
+            .lock()
+            .expect("lock not to be poisoned")
+            .stale_since
+            .get_mut(&self.id)
+        {
+            *stale_since = None;
+        }
+    }
+}
+
+impl Drop for Consumer {
+    fn drop(&mut self) {
+        self.freshness
+            .0
+            .lock()
+            .expect("lock not to be poisoned")
+            .stale_since
+            .remove(&self.id);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CheckFeeds;
+
+pub struct Actor {
+    feeds: Vec<(&'static str, Freshness)>,
+    warn_after: Duration,
+    stale_feeds: HashSet<&'static str>,
+}
+
+impl Actor {
+    /// Watch the given feeds and warn if a consumer did not observe an
+    /// update for longer than `warn_after`.
+    pub fn new(feeds: Vec<(&'static str, Freshness)>, warn_after: Duration) -> Self {
+        Self {
+            feeds,
+            warn_after,
+            stale_feeds: HashSet::default(),
+        }
+    }
+
+    fn check(&mut self, now: Instant) {
+        for (name, freshness) in self.feeds.iter() {
+            let name = *name;
+            let staleness = freshness.staleness(now).unwrap_or_default();
+
+            FEED_STALENESS_GAUGE
+                .with(&HashMap::from([(FEED_LABEL, name)]))
+                .set(staleness.as_secs_f64());
+
+            if staleness > self.warn_after {
+                if self.stale_feeds.insert(name) {
+                    tracing::warn!(
+                        feed = name,
+                        "Consumers have not observed updates of the feed for {staleness:?}"
+                    );
+                }
+            } else if self.stale_feeds.remove(name) {
+                tracing::info!(feed = name, "Consumers caught up with the feed again");
+            }
+        }
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    fn handle(&mut self, _: CheckFeeds) {
+        self.check(Instant::now());
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(CHECK_INTERVAL, || CheckFeeds, xtras::IncludeSpan::Never),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+const FEED_LABEL: &str = "feed";
+
+static FEED_STALENESS_GAUGE: conquer_once::Lazy<prometheus::GaugeVec> =
+    conquer_once::Lazy::new(|| {
+        prometheus::register_gauge_vec!(
+            "projection_feed_staleness_seconds",
+            "For how long the slowest consumer of a feed has not observed its latest value.",
+            &[FEED_LABEL]
+        )
+        .unwrap()
+    });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consumer_is_stale_since_first_unobserved_value() {
+        let freshness = Freshness::default();
+        let consumer = freshness.consumer();
+        let start = Instant::now();
+
+        freshness.produced();
+        freshness.produced();
+
+        let staleness = freshness
+            .staleness(start + Duration::from_secs(60))
+            .unwrap();
+        assert!(staleness >= Duration::from_secs(59));
+
+        consumer.observed();
+        assert_eq!(freshness.staleness(start), None);
+    }
+
+    #[test]
+    fn dropped_consumer_does_not_count_as_stale() {
+        let freshness = Freshness::default();
+        let consumer = freshness.consumer();
+
+        freshness.produced();
+        drop(consumer);
+
+        assert_eq!(freshness.staleness(Instant::now()), None);
+    }
+
+    #[test]
+    fn stale_feed_is_reported_once_until_it_recovers() {
+        let freshness = Freshness::default();
+        let consumer = freshness.consumer();
+        let mut actor = Actor::new(vec![("cfds", freshness.clone())], Duration::from_secs(30));
+
+        freshness.produced();
+        actor.check(Instant::now() + Duration::from_secs(60));
+        assert!(actor.stale_feeds.contains("cfds"));
+
+        consumer.observed();
+        actor.check(Instant::now());
+        assert!(actor.stale_feeds.is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+//! Exchange rates for displaying amounts in the fiat currency selected
+//! by the user.
+//!
+//! Bitcoin is valued in US dollars at the BTCUSD price of the price
+//! feed, regardless of the symbol of the CFD. The US dollar amount is
+//! then converted into the selected currency at the exchange rate of a
+//! configurable provider. The provider is expected to serve the API of
+//! frankfurter.app, which publishes the reference rates of the European
+//! Central Bank.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use bdk::bitcoin::SignedAmount;
+use model::Price;
+use model::Timestamp;
+use parse_display::Display;
+use parse_display::FromStr;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use time::Date;
+use tokio::sync::watch;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+pub const DEFAULT_PROVIDER_URL: &str = "https://api.frankfurter.app";
+
+/// Timeout for requests to the exchange rate provider.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to fetch the latest exchange rate.
+///
+/// The reference rates are only published once per working day.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, FromStr, Serialize, Deserialize)]
+#[display(style = "UPPERCASE")]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    Usd,
+    Eur,
+    Gbp,
+    Chf,
+}
+
+/// The exchange rate from US dollars to `currency`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FiatRate {
+    pub currency: Currency,
+    /// How many units of `currency` one US dollar is worth
+    pub usd_rate: Decimal,
+    pub updated_at: Timestamp,
+}
+
+impl FiatRate {
+    fn usd() -> Self {
+        Self {
+            currency: Currency::Usd,
+            usd_rate: Decimal::ONE,
+            updated_at: Timestamp::now(),
+        }
+    }
+
+    /// The value of `amount` in fiat, given the price of bitcoin in US
+    /// dollars.
+    pub fn value(&self, amount: SignedAmount, btc_usd_price: Price) -> FiatAmount {
+        let usd = Decimal::new(amount.as_sat(), 8) * btc_usd_price.into_decimal();
+
+        FiatAmount {
+            currency: self.currency,
+            amount: (usd * self.usd_rate).round_dp(2),
+        }
+    }
+}
+
+/// An amount of fiat money, rounded to two decimal places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FiatAmount {
+    pub currency: Currency,
+    pub amount: Decimal,
+}
+
+/// Fetches exchange rates from US dollars to one currency.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    client: reqwest::Client,
+    url: String,
+    currency: Currency,
+}
+
+impl Provider {
+    pub fn new(url: String, currency: Currency) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            currency,
+        }
+    }
+
+    /// The latest exchange rate.
+    pub async fn latest(&self) -> Result<FiatRate> {
+        self.fetch("latest").await
+    }
+
+    /// The exchange rate on `date`, e.g. to value a trade that was
+    /// closed that day.
+    ///
+    /// For days without a published rate the rate of the previous
+    /// working day is returned.
+    pub async fn on(&self, date: Date) -> Result<FiatRate> {
+        self.fetch(&date.to_string()).await
+    }
+
+    async fn fetch(&self, path: &str) -> Result<FiatRate> {
+        if self.currency == Currency::Usd {
+            return Ok(FiatRate::usd());
+        }
+
+        let url = format!(
+            "{}/{path}?from=USD&to={}",
+            self.url.trim_end_matches('/'),
+            self.currency
+        );
+
+        let response = self
+            .client
+            .get(&url)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .with_context(|| format!("Failed to GET {url}"))?;
+
+        let code = response.status();
+        if !code.is_success() {
+            bail!("GET {url} responded with {code}");
+        }
+
+        let rates = response
+            .json::<Rates>()
+            .await
+            .context("Failed to deserialize exchange rates")?;
+
+        rates.to_fiat_rate(self.currency)
+    }
+}
+
+/// Exchange rates as served by the provider.
+#[derive(Debug, Clone, Deserialize)]
+struct Rates {
+    rates: HashMap<String, f64>,
+}
+
+impl Rates {
+    fn to_fiat_rate(&self, currency: Currency) -> Result<FiatRate> {
+        let rate = *self
+            .rates
+            .get(&currency.to_string())
+            .with_context(|| format!("No exchange rate for {currency}"))?;
+
+        Ok(FiatRate {
+            currency,
+            usd_rate: Decimal::try_from(rate)?,
+            updated_at: Timestamp::now(),
+        })
+    }
+}
+
+/// Keeps the latest exchange rate of the selected currency up to date.
+pub struct Actor {
+    provider: Provider,
+    tx: watch::Sender<Option<FiatRate>>,
+}
+
+impl Actor {
+    pub fn new(provider: Provider) -> (Self, watch::Receiver<Option<FiatRate>>) {
+        let (tx, rx) = watch::channel(None);
+
+        (Self { provider, tx }, rx)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct UpdateRate;
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _: UpdateRate) {
+        match self.provider.latest().await {
+            Ok(rate) => {
+                let _ = self.tx.send(Some(rate));
+            }
+            Err(e) => {
+                tracing::warn!(
+                    currency = %self.provider.currency,
+                    "Failed to update exchange rate: {e:#}"
+                );
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(UPDATE_INTERVAL, || UpdateRate, xtras::IncludeSpan::Always),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn bitcoin_is_valued_in_usd_then_converted() {
+        let rate = FiatRate {
+            currency: Currency::Eur,
+            usd_rate: dec!(0.5),
+            updated_at: Timestamp::now(),
+        };
+        let btc_usd_price = Price::new(dec!(20_000)).unwrap();
+
+        let value = rate.value(SignedAmount::from_sat(-50_000), btc_usd_price);
+
+        assert_eq!(value.currency, Currency::Eur);
+        assert_eq!(value.amount, dec!(-5));
+    }
+
+    #[test]
+    fn rate_of_selected_currency_is_picked_from_response() {
+        let rates = serde_json::from_str::<Rates>(
+            r#"{"amount":1.0,"base":"USD","date":"2022-10-14","rates":{"CHF":0.99,"EUR":1.02}}"#,
+        )
+        .unwrap();
+
+        let rate = rates.to_fiat_rate(Currency::Eur).unwrap();
+
+        assert_eq!(rate.usd_rate, dec!(1.02));
+        assert!(rates.to_fiat_rate(Currency::Gbp).is_err());
+    }
+}
@@ -1,5 +1,11 @@
+use crate::listen_protocols::Compatibility;
+use crate::listen_protocols::Feature;
 use crate::Environment;
+use asynchronous_codec::FramedRead;
+use asynchronous_codec::JsonCodec;
 use std::collections::HashSet;
+use xtra_libp2p::recorder::ensure_replay_finished;
+use xtra_libp2p::recorder::ReplayedSubstream;
 
 pub mod dialer;
 pub mod listener;
@@ -7,6 +13,25 @@ pub mod protocol;
 
 pub const PROTOCOL: &str = "/itchysats/id/1.0.0";
 
+/// Run the dialer's handling of a recorded substream on the frames the
+/// listener sent.
+pub async fn replay_dialer(stream: ReplayedSubstream) -> anyhow::Result<()> {
+    let identify_msg = protocol::recv(stream).await?;
+    PeerInfo::try_from(identify_msg)?;
+
+    Ok(())
+}
+
+/// Run the listener's handling of a recorded substream on the frames the
+/// dialer sent.
+///
+/// Only the listener sends a message, in response to the dialer opening
+/// the substream.
+pub async fn replay_listener(stream: ReplayedSubstream) -> anyhow::Result<()> {
+    let mut framed = FramedRead::new(stream, JsonCodec::<(), serde_json::Value>::new());
+    ensure_replay_finished(&mut framed).await
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PeerInfo {
     pub wire_version: String,
@@ -15,6 +40,24 @@ pub struct PeerInfo {
     pub protocols: HashSet<String>,
 }
 
+impl PeerInfo {
+    /// How well we as a taker can work with the maker this is about.
+    pub fn maker_compatibility(&self) -> Compatibility {
+        Compatibility::new(&self.protocols)
+    }
+
+    /// Whether the maker this is about accepts collaborative settlement
+    /// transactions with a lock time.
+    ///
+    /// Older makers reject them because they do not match the transaction
+    /// they build themselves. Makers which accept them advertise
+    /// [`Feature::SettlementLockTime`].
+    pub fn maker_accepts_settlement_lock_time(&self) -> bool {
+        self.maker_compatibility()
+            .supports(Feature::SettlementLockTime)
+    }
+}
+
 impl TryFrom<protocol::IdentifyMsg> for PeerInfo {
     type Error = ConversionError;
 
@@ -40,7 +83,9 @@ pub struct ConversionError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::collab_settlement;
     use crate::identify::PeerInfo;
+    use crate::listen_protocols::MAKER_LISTEN_PROTOCOLS;
     use crate::Environment;
     use libp2p_core::PublicKey;
     use std::collections::HashSet;
@@ -116,6 +161,22 @@ mod tests {
         assert_eq!(taker_peer_info, expected_taker_peer_info);
     }
 
+    #[test]
+    fn only_makers_advertising_lock_time_feature_accept_settlement_lock_time() {
+        let peer_info = |protocols: HashSet<String>| PeerInfo {
+            wire_version: "0.3.0".to_string(),
+            daemon_version: "0.7.0".to_string(),
+            environment: Environment::unknown(),
+            protocols,
+        };
+
+        let mut old_maker: HashSet<String> = MAKER_LISTEN_PROTOCOLS.into();
+        old_maker.remove(collab_settlement::LOCK_TIME_FEATURE);
+
+        assert!(peer_info(MAKER_LISTEN_PROTOCOLS.into()).maker_accepts_settlement_lock_time());
+        assert!(!peer_info(old_maker).maker_accepts_settlement_lock_time());
+    }
+
     #[allow(clippy::type_complexity)]
     fn create_endpoint_with_identify(
         daemon_version: String,
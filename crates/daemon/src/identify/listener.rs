@@ -2,10 +2,15 @@ use crate::identify::protocol;
 use crate::Environment;
 use async_trait::async_trait;
 use libp2p_core::Multiaddr;
+use libp2p_core::PeerId;
 use libp2p_core::PublicKey;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use tokio_extras::spawn_fallible;
+use xtra::Address;
 use xtra::Context;
+use xtra_libp2p::Endpoint;
+use xtra_libp2p::GetRestrictedProtocols;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_productivity::xtra_productivity;
 
@@ -15,6 +20,8 @@ pub struct Actor {
     identity: PublicKey,
     listen_addrs: HashSet<Multiaddr>,
     protocols: HashSet<String>,
+    /// The endpoint to ask for restricted protocols, if any.
+    endpoint: Option<Address<Endpoint>>,
 }
 
 impl Actor {
@@ -31,8 +38,17 @@ impl Actor {
             identity,
             listen_addrs,
             protocols,
+            endpoint: None,
         }
     }
+
+    /// Only advertise the protocols restricted on `endpoint` to the
+    /// peers allowed to negotiate them.
+    #[must_use]
+    pub fn with_restricted_protocols(mut self, endpoint: Address<Endpoint>) -> Self {
+        self.endpoint = Some(endpoint);
+        self
+    }
 }
 
 #[xtra_productivity]
@@ -40,18 +56,35 @@ impl Actor {
     async fn handle(&mut self, message: NewInboundSubstream, ctx: &mut Context<Self>) {
         let NewInboundSubstream { stream, peer_id } = message;
 
-        // TODO: Set observed address according to the address we observed when establishing the
-        //  connection
-        let identify_msg = protocol::IdentifyMsg::new(
-            self.daemon_version.clone(),
-            self.environment.clone().into(),
-            self.identity.clone(),
-            self.listen_addrs.clone(),
-            Multiaddr::empty(),
-            self.protocols.clone(),
-        );
+        let daemon_version = self.daemon_version.clone();
+        let environment = self.environment.clone();
+        let identity = self.identity.clone();
+        let listen_addrs = self.listen_addrs.clone();
+        let protocols = self.protocols.clone();
+        let endpoint = self.endpoint.clone();
 
-        let send_identify_msg_fut = protocol::send(stream, identify_msg);
+        let send_identify_msg_fut = async move {
+            let protocols = match endpoint {
+                Some(endpoint) => {
+                    let restricted_protocols = endpoint.send(GetRestrictedProtocols).await?;
+                    advertised_protocols(protocols, &restricted_protocols, &peer_id)
+                }
+                None => protocols,
+            };
+
+            // TODO: Set observed address according to the address we observed when establishing
+            //  the connection
+            let identify_msg = protocol::IdentifyMsg::new(
+                daemon_version,
+                environment.into(),
+                identity,
+                listen_addrs,
+                Multiaddr::empty(),
+                protocols,
+            );
+
+            protocol::send(stream, identify_msg).await
+        };
 
         let err_handler = move |e| async move {
             tracing::debug!(%peer_id, "Identify protocol failed upon response: {e:#}")
@@ -62,9 +95,55 @@ impl Actor {
     }
 }
 
+/// The subset of our `protocols` to advertise to `peer_id`.
+///
+/// Restricted protocols are only advertised to the peers allowed to
+/// negotiate them. Other peers must not even learn about them, let
+/// alone attempt to use them.
+fn advertised_protocols(
+    protocols: HashSet<String>,
+    restricted_protocols: &HashMap<String, HashSet<PeerId>>,
+    peer_id: &PeerId,
+) -> HashSet<String> {
+    protocols
+        .into_iter()
+        .filter(|protocol| {
+            restricted_protocols
+                .get(protocol)
+                .map_or(true, |allowed_peers| allowed_peers.contains(peer_id))
+        })
+        .collect()
+}
+
 #[async_trait]
 impl xtra::Actor for Actor {
     type Stop = ();
 
     async fn stopped(self) -> Self::Stop {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STABLE: &str = "/stable/1.0.0";
+    const EXPERIMENTAL: &str = "/experimental/1.0.0";
+
+    #[test]
+    fn restricted_protocol_is_only_advertised_to_allowed_peers() {
+        let friend = PeerId::random();
+        let stranger = PeerId::random();
+        let protocols = HashSet::from([STABLE.to_owned(), EXPERIMENTAL.to_owned()]);
+        let restricted_protocols =
+            HashMap::from([(EXPERIMENTAL.to_owned(), HashSet::from([friend]))]);
+
+        assert_eq!(
+            advertised_protocols(protocols.clone(), &restricted_protocols, &friend),
+            protocols
+        );
+        assert_eq!(
+            advertised_protocols(protocols, &restricted_protocols, &stranger),
+            HashSet::from([STABLE.to_owned()])
+        );
+    }
+}
@@ -2,68 +2,104 @@
 
 use crate::bitcoin::util::psbt::PartiallySignedTransaction;
 use crate::bitcoin::Txid;
+use crate::block_explorer::BlockExplorer;
+use crate::listen_protocols::Feature;
 use crate::listen_protocols::TAKER_LISTEN_PROTOCOLS;
 use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context as _;
 use anyhow::Result;
 pub use bdk;
 use bdk::bitcoin;
 use bdk::bitcoin::Amount;
+use bdk::bitcoin::OutPoint;
 use bdk::FeeRate;
 use identify::PeerInfo;
 use libp2p_core::Multiaddr;
-use libp2p_tcp::TokioTcpConfig;
 pub use maia;
 pub use maia_core;
-use maia_core::secp256k1_zkp::XOnlyPublicKey;
 use model::libp2p::PeerId;
 use model::olivia;
+use model::olivia::OracleKeys;
+use model::ContractSymbol;
 use model::Contracts;
+use model::FundingRate;
 use model::Identity;
 use model::Leverage;
 use model::OfferId;
 use model::OrderId;
+use model::Position;
 use model::Price;
+use model::PriceTrigger;
 use model::Role;
+use model::RolloverPolicy;
+use model::Timestamp;
 use online_status::ConnectionStatus;
 use parse_display::Display;
+use pending_requests::PendingRequest;
+use pending_requests::PendingRequestKind;
 use ping_pong::ping;
 use ping_pong::pong;
 use seed::Identities;
+use sqlite_db::rehydration;
 use std::collections::HashSet;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use time::ext::NumericalDuration;
+use time::OffsetDateTime;
 use tokio::sync::watch;
 use tokio_extras::Tasks;
 use tracing::instrument;
 use xtra::prelude::*;
 use xtra_bitmex_price_feed::QUOTE_INTERVAL_MINUTES;
-use xtra_libp2p::dialer;
 use xtra_libp2p::endpoint;
 use xtra_libp2p::multiaddress_ext::MultiaddrExt;
+use xtra_libp2p::recorder::Recorder;
 use xtra_libp2p::Endpoint;
-use xtras::supervisor::always_restart_after;
 use xtras::supervisor::Supervisor;
 
+pub mod analytics;
 pub mod archive_closed_cfds;
 pub mod archive_failed_cfds;
 pub mod auto_rollover;
+pub mod block_explorer;
+pub mod cfd_keys;
 pub mod collab_settlement;
 pub mod command;
+pub mod db_backup;
+pub mod electrum;
+pub mod fee_estimation;
+pub mod feed_lag;
+pub mod fiat_rates;
 pub mod identify;
 pub mod libp2p_utils;
 pub mod listen_protocols;
+pub mod maker_connection;
+pub mod maker_identity;
 pub mod monitor;
 pub mod online_status;
 pub mod oracle;
 pub mod order;
+pub mod pending_requests;
 pub mod position_metrics;
+pub mod preferences;
+pub mod price_feed;
+pub mod price_trigger;
 pub mod process_manager;
 pub mod projection;
+pub mod readiness;
+pub mod recovery;
+pub mod replay;
+pub mod risk_limits;
+pub mod scheduled_settlement;
 pub mod seed;
 pub mod taker_cfd;
+pub mod trace;
+pub mod trade_history;
 pub mod wallet;
+pub mod watchdog;
+pub mod watchtower;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -74,6 +110,56 @@ pub const RESTART_INTERVAL: Duration = Duration::from_secs(5);
 pub const ENDPOINT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(20);
 pub const PING_INTERVAL: Duration = Duration::from_secs(30);
 
+/// Timings of the libp2p connections between taker and maker.
+///
+/// The defaults suit direct connections. Links with a high latency, e.g.
+/// over Tor or satellite, need a longer connection timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionSettings {
+    /// How often to ping the counterparty to measure latency and keep
+    /// the connection alive
+    pub ping_interval: Duration,
+    /// How long to wait for a connection to be established
+    pub connection_timeout: Duration,
+}
+
+impl ConnectionSettings {
+    pub const MIN_PING_INTERVAL: Duration = Duration::from_secs(5);
+    pub const MAX_PING_INTERVAL: Duration = Duration::from_secs(10 * 60);
+    pub const MIN_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+    pub const MAX_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+    pub fn new(ping_interval: Duration, connection_timeout: Duration) -> Result<Self> {
+        ensure!(
+            (Self::MIN_PING_INTERVAL..=Self::MAX_PING_INTERVAL).contains(&ping_interval),
+            "Ping interval must be between {}s and {}s",
+            Self::MIN_PING_INTERVAL.as_secs(),
+            Self::MAX_PING_INTERVAL.as_secs()
+        );
+        ensure!(
+            (Self::MIN_CONNECTION_TIMEOUT..=Self::MAX_CONNECTION_TIMEOUT)
+                .contains(&connection_timeout),
+            "Connection timeout must be between {}s and {}s",
+            Self::MIN_CONNECTION_TIMEOUT.as_secs(),
+            Self::MAX_CONNECTION_TIMEOUT.as_secs()
+        );
+
+        Ok(Self {
+            ping_interval,
+            connection_timeout,
+        })
+    }
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            ping_interval: PING_INTERVAL,
+            connection_timeout: ENDPOINT_CONNECTION_TIMEOUT,
+        }
+    }
+}
+
 pub const N_PAYOUTS: usize = 200;
 
 pub struct TakerActorSystem<O, W, P> {
@@ -82,15 +168,38 @@ pub struct TakerActorSystem<O, W, P> {
     _oracle_actor: Address<O>,
     pub auto_rollover_actor: Address<auto_rollover::Actor>,
     pub price_feed_actor: Address<P>,
+    /// Settle at the time-weighted average price of this window instead
+    /// of the latest quote
+    settlement_twap: Option<time::Duration>,
+    order_actor: Address<order::taker::Actor>,
+    collab_settlement_actor: Address<collab_settlement::taker::Actor>,
+    rollover_actor:
+        Address<rollover::taker::Actor<command::Executor, oracle::AnnouncementsChannel>>,
+    risk_limits_actor: Address<risk_limits::Actor>,
+    price_trigger_actor: Address<price_trigger::Actor>,
+    scheduled_settlement_actor: Address<scheduled_settlement::Actor>,
+    projection_actor: Address<projection::Actor>,
     executor: command::Executor,
+    db: sqlite_db::Connection,
+    offer_filter: watch::Sender<offer::taker::OfferFilter>,
     _close_cfds_actor: Address<archive_closed_cfds::Actor>,
     _archive_failed_cfds_actor: Address<archive_failed_cfds::Actor>,
+    _watchdog_actor: Address<watchdog::Actor>,
     _pong_actor: Address<pong::Actor>,
-    _online_status_actor: Address<online_status::Actor>,
+    online_status_actor: Address<online_status::Actor>,
     _identify_dialer_actor: Address<identify::dialer::Actor>,
+    pub maker_connection_actor: Address<maker_connection::Actor>,
+    maker_identity_actor: Address<maker_identity::Actor>,
+    _watchtower_client_actor: Option<Address<watchtower::client::Actor>>,
 
     pub maker_online_status_feed_receiver: watch::Receiver<ConnectionStatus>,
+    /// Round trip times to the maker, `None` while we have not measured any
+    pub maker_latency_feed_receiver: watch::Receiver<Option<online_status::Latency>>,
     pub identify_info_feed_receiver: watch::Receiver<Option<PeerInfo>>,
+    /// Whether the identity of the maker matches the one pinned on first use
+    pub maker_identity_pin_receiver: watch::Receiver<maker_identity::PinStatus>,
+    /// How far the rehydration of the open CFDs on startup has progressed
+    pub rehydration_progress: watch::Receiver<rehydration::Progress>,
 
     _tasks: Tasks,
 }
@@ -106,18 +215,25 @@ where
         + Handler<wallet::Sign, Return = Result<PartiallySignedTransaction>>
         + Handler<wallet::Withdraw, Return = Result<Txid>>
         + Handler<wallet::Sync, Return = ()>
+        + Handler<wallet::ListUtxos, Return = Result<Vec<wallet::Utxo>>>
+        + Handler<wallet::FreezeUtxos, Return = Result<()>>
+        + Handler<wallet::UnfreezeUtxos, Return = ()>
+        + Handler<wallet::GetBlockHeight, Return = Result<u32>>
+        + Handler<xtras::Probe, Return = ()>
         + Actor<Stop = ()>,
     P: Handler<
             xtra_bitmex_price_feed::GetLatestQuotes,
             Return = xtra_bitmex_price_feed::LatestQuotes,
-        > + Actor<Stop = xtra_bitmex_price_feed::Error>,
+        > + Handler<price_feed::GetTwap, Return = Option<price_feed::Twap>>
+        + Actor,
 {
     #[instrument(
         name = "Create TakerActorSystem",
         skip_all,
         fields(
             %n_payouts,
-            connect_timeout_secs = %connect_timeout.as_secs(),
+            ping_interval_secs = %connection.ping_interval.as_secs(),
+            connection_timeout_secs = %connection.connection_timeout.as_secs(),
             %environment,
         )
         err,
@@ -126,17 +242,22 @@ where
     pub fn new<M>(
         db: sqlite_db::Connection,
         wallet_actor_addr: Address<W>,
-        oracle_pk: XOnlyPublicKey,
+        oracle_keys: OracleKeys,
         identity: Identities,
         oracle_constructor: impl FnOnce(command::Executor) -> O,
         monitor_constructor: impl FnOnce(command::Executor) -> Result<M>,
         price_feed_actor: Address<P>,
         n_payouts: usize,
-        connect_timeout: Duration,
+        connection: ConnectionSettings,
         projection_actor: Address<projection::Actor>,
         maker_identity: Identity,
         maker_multiaddr: Multiaddr,
         environment: Environment,
+        tor_socks5_proxy: Option<SocketAddr>,
+        protocol_recorder: Option<Recorder>,
+        event_delivery_timeout: Duration,
+        watchtower_multiaddr: Option<Multiaddr>,
+        settlement_twap: Option<time::Duration>,
     ) -> Result<Self>
     where
         M: Handler<monitor::MonitorAfterContractSetup, Return = ()>
@@ -145,19 +266,35 @@ where
             + Handler<monitor::MonitorCollaborativeSettlement, Return = ()>
             + Handler<monitor::MonitorCetFinality, Return = Result<()>>
             + Handler<monitor::TryBroadcastTransaction, Return = Result<()>>
+            + Handler<xtras::Probe, Return = ()>
             + Actor<Stop = ()>,
     {
         let (maker_online_status_feed_sender, maker_online_status_feed_receiver) =
             watch::channel(ConnectionStatus::Offline);
+        let (maker_latency_feed_sender, maker_latency_feed_receiver) = watch::channel(None);
 
         let (monitor_addr, monitor_ctx) = Context::new(None);
         let (oracle_addr, oracle_ctx) = Context::new(None);
         let (process_manager_addr, process_manager_ctx) = Context::new(None);
 
-        let executor = command::Executor::new(db.clone(), process_manager_addr.clone());
+        let executor = command::Executor::new(db.clone(), process_manager_addr.clone().into());
 
         let mut tasks = Tasks::default();
 
+        let (rehydration_progress_sender, rehydration_progress) =
+            watch::channel(rehydration::Progress::default());
+        tasks.add_fallible(
+            {
+                let executor = executor.clone();
+                let db = db.clone();
+                async move {
+                    executor.rehydrate(rehydration_progress_sender).await?;
+                    collab_settlement::resume_interrupted(&db, &executor).await
+                }
+            },
+            |e| async move { tracing::error!("{e:#}") },
+        );
+
         let position_metrics_actor = position_metrics::Actor::new(db.clone())
             .create(None)
             .spawn(&mut tasks);
@@ -165,18 +302,23 @@ where
         tasks.add(process_manager_ctx.run(process_manager::Actor::new(
             db.clone(),
             Role::Taker,
+            event_delivery_timeout,
             projection_actor.clone().into(),
             position_metrics_actor.into(),
             monitor_addr.clone().into(),
             monitor_addr.clone().into(),
             monitor_addr.clone().into(),
             monitor_addr.clone().into(),
-            monitor_addr.into(),
+            monitor_addr.clone().into(),
             oracle_addr.clone().into(),
         )));
 
         let (endpoint_addr, endpoint_context) = Context::new(None);
 
+        let (identify_dialer_actor, identify_info_feed_receiver) =
+            identify::dialer::Actor::new_with_subscriber(endpoint_addr.clone());
+
+        let cfd_keys = identity.cfd_keys;
         let (order_supervisor, order) = Supervisor::new({
             let oracle = oracle_addr.clone();
             let db = db.clone();
@@ -187,7 +329,8 @@ where
             move || {
                 order::taker::Actor::new(
                     n_payouts,
-                    oracle_pk,
+                    oracle_keys,
+                    cfd_keys,
                     oracle.clone().into(),
                     (db.clone(), process_manager.clone()),
                     (wallet.clone().into(), wallet.clone().into()),
@@ -200,21 +343,31 @@ where
         let (collab_settlement_supervisor, collab_settlement_addr) = Supervisor::new({
             let endpoint_addr = endpoint_addr.clone();
             let executor = executor.clone();
+            let maker_info = identify_info_feed_receiver.clone();
+            let wallet = wallet_actor_addr.clone();
             move || {
                 collab_settlement::taker::Actor::new(
                     endpoint_addr.clone(),
                     executor.clone(),
                     n_payouts,
+                    maker_info.clone(),
+                    wallet.clone().into(),
                 )
             }
         });
         tasks.add(collab_settlement_supervisor.run_log_summary());
 
+        let risk_limits_actor = risk_limits::Actor::new(db.clone())
+            .create(None)
+            .spawn(&mut tasks);
+
         let cfd_actor_addr = taker_cfd::Actor::new(
             db.clone(),
-            projection_actor,
-            collab_settlement_addr,
-            order,
+            projection_actor.clone(),
+            collab_settlement_addr.clone(),
+            order.clone(),
+            risk_limits_actor.clone(),
+            wallet_actor_addr.clone().into(),
             maker_identity,
             PeerId::from(
                 maker_multiaddr
@@ -234,7 +387,7 @@ where
                 rollover::taker::Actor::new(
                     endpoint_addr.clone(),
                     executor.clone(),
-                    oracle_pk,
+                    oracle_keys,
                     oracle::AnnouncementsChannel::new(oracle_addr.clone().into()),
                     n_payouts,
                 )
@@ -242,36 +395,105 @@ where
         });
         tasks.add(rollover_supervisor.run_log_summary());
 
-        let auto_rollover_addr = auto_rollover::Actor::new(db.clone(), rollover_addr)
-            .create(None)
-            .spawn(&mut tasks);
+        let auto_rollover_addr = auto_rollover::Actor::new(
+            db.clone(),
+            rollover_addr.clone(),
+            cfd_actor_addr.clone().into(),
+            identify_info_feed_receiver.clone(),
+        )
+        .create(None)
+        .spawn(&mut tasks);
+
+        let price_trigger_actor = price_trigger::Actor::new(
+            db.clone(),
+            executor.clone(),
+            cfd_actor_addr.clone(),
+            price_feed_actor.clone().into(),
+            maker_online_status_feed_receiver.clone(),
+        )
+        .create(None)
+        .spawn(&mut tasks);
+
+        let scheduled_settlement_actor = scheduled_settlement::Actor::new(
+            db.clone(),
+            executor.clone(),
+            cfd_actor_addr.clone(),
+            price_feed_actor.clone().into(),
+            maker_online_status_feed_receiver.clone(),
+        )
+        .create(None)
+        .spawn(&mut tasks);
+
+        let maker_peer_id = maker_multiaddr
+            .clone()
+            .extract_peer_id()
+            .expect("to be able to extract peer id");
 
         let online_status_actor = online_status::Actor::new(
             endpoint_addr.clone(),
-            maker_multiaddr
-                .clone()
-                .extract_peer_id()
-                .expect("to be able to extract peer id"),
+            maker_peer_id,
+            connection.ping_interval,
             maker_online_status_feed_sender,
+            maker_latency_feed_sender,
         )
         .create(None)
         .spawn(&mut tasks);
 
+        let (maker_identity_actor, maker_identity_pin_receiver) = maker_identity::Actor::new(
+            db.clone(),
+            maker_identity::MakerIdentity {
+                peer_id: maker_peer_id.into(),
+                identity: maker_identity,
+            },
+        );
+        let maker_identity_actor = maker_identity_actor.create(None).spawn(&mut tasks);
+
         tasks.add(monitor_ctx.run(monitor_constructor(executor.clone())?));
         tasks.add(oracle_ctx.run(oracle_constructor(executor.clone())));
 
-        let dialer_constructor = {
+        let maker_connection_settings = maker_connection::Settings::new(maker_multiaddr)?;
+        let (maker_connection_supervisor, maker_connection_actor) = Supervisor::new({
             let endpoint_addr = endpoint_addr.clone();
-            move || dialer::Actor::new(endpoint_addr.clone(), maker_multiaddr.clone())
+            let db = db.clone();
+            move || {
+                maker_connection::Actor::new(
+                    endpoint_addr.clone(),
+                    maker_connection_settings.clone(),
+                    db.clone(),
+                    connection.connection_timeout,
+                )
+            }
+        });
+        tasks.add(maker_connection_supervisor.run_log_summary());
+
+        let watchtower_client_actor = match watchtower_multiaddr {
+            Some(multiaddr) => {
+                // The maker is the party the watchtower watches, so it
+                // must not be trusted to punish itself
+                ensure!(
+                    multiaddr.clone().extract_peer_id() != Some(maker_peer_id),
+                    "The maker cannot be our watchtower"
+                );
+
+                Some(
+                    watchtower::client::Actor::new(endpoint_addr.clone(), db.clone(), multiaddr)?
+                        .create(None)
+                        .spawn(&mut tasks),
+                )
+            }
+            None => None,
         };
-        let (dialer_supervisor, dialer_actor) = Supervisor::<_, dialer::Error>::with_policy(
-            dialer_constructor,
-            always_restart_after(RESTART_INTERVAL),
-        );
 
+        let (offer_filter_sender, offer_filter_receiver) =
+            watch::channel(offer::taker::OfferFilter::default());
         let (offer_supervisor, offer_addr) = Supervisor::new({
             let cfd_actor_addr = cfd_actor_addr.clone();
-            move || offer::taker::Actor::new(cfd_actor_addr.clone().into())
+            move || {
+                offer::taker::Actor::new(
+                    cfd_actor_addr.clone().into(),
+                    offer_filter_receiver.clone(),
+                )
+            }
         });
 
         let (identify_listener_supervisor, identify_listener_actor) = Supervisor::new({
@@ -287,20 +509,24 @@ where
             }
         });
 
-        let (identify_dialer_actor, identify_info_feed_receiver) =
-            identify::dialer::Actor::new_with_subscriber(endpoint_addr.clone());
         let identify_dialer_actor = identify_dialer_actor.create(None).spawn(&mut tasks);
 
         let pong_address = pong::Actor.create(None).spawn(&mut tasks);
 
-        let (supervisor, ping_actor) =
-            Supervisor::new(move || ping::Actor::new(endpoint_addr.clone(), PING_INTERVAL));
+        let (supervisor, ping_actor) = Supervisor::new({
+            let endpoint_addr = endpoint_addr.clone();
+            let online_status_actor = online_status_actor.clone();
+            move || {
+                ping::Actor::new(endpoint_addr.clone(), connection.ping_interval)
+                    .with_latency_subscriber(online_status_actor.clone().into())
+            }
+        });
         tasks.add(supervisor.run_log_summary());
 
         let endpoint = Endpoint::new(
-            Box::new(TokioTcpConfig::new),
+            Box::new(move || libp2p_utils::taker_transport(tor_socks5_proxy)),
             identity.libp2p,
-            ENDPOINT_CONNECTION_TIMEOUT,
+            connection.connection_timeout,
             TAKER_LISTEN_PROTOCOLS.inbound_substream_handlers(
                 pong_address.clone(),
                 identify_listener_actor,
@@ -311,9 +537,9 @@ where
                     online_status_actor.clone().into(),
                     ping_actor.clone().into(),
                     identify_dialer_actor.clone().into(),
+                    maker_connection_actor.clone().into(),
                 ],
                 vec![
-                    dialer_actor.into(),
                     ping_actor.into(),
                     online_status_actor.clone().into(),
                     identify_dialer_actor.clone().into(),
@@ -323,20 +549,32 @@ where
             ),
             Arc::new(HashSet::default()), // Taker does not block peers
         );
+        let endpoint = match protocol_recorder {
+            Some(recorder) => endpoint.with_recorder(recorder),
+            None => endpoint,
+        };
 
         tasks.add(endpoint_context.run(endpoint));
 
-        tasks.add(dialer_supervisor.run_log_summary());
         tasks.add(offer_supervisor.run_log_summary());
         tasks.add(identify_listener_supervisor.run_log_summary());
 
         let close_cfds_actor = archive_closed_cfds::Actor::new(db.clone())
             .create(None)
             .spawn(&mut tasks);
-        let archive_failed_cfds_actor = archive_failed_cfds::Actor::new(db)
+        let archive_failed_cfds_actor = archive_failed_cfds::Actor::new(db.clone())
             .create(None)
             .spawn(&mut tasks);
 
+        let watchdog_actor = watchdog::Actor::new(vec![
+            watchdog::Watched::new("wallet", wallet_actor_addr.clone()),
+            watchdog::Watched::new("cfd", cfd_actor_addr.clone()),
+            watchdog::Watched::new("monitor", monitor_addr),
+            watchdog::Watched::new("endpoint", endpoint_addr),
+        ])
+        .create(None)
+        .spawn(&mut tasks);
+
         tracing::debug!("Taker actor system ready");
 
         Ok(Self {
@@ -345,28 +583,62 @@ where
             _oracle_actor: oracle_addr,
             auto_rollover_actor: auto_rollover_addr,
             price_feed_actor,
+            settlement_twap,
+            order_actor: order,
+            collab_settlement_actor: collab_settlement_addr,
+            rollover_actor: rollover_addr,
+            risk_limits_actor,
+            price_trigger_actor,
+            scheduled_settlement_actor,
+            projection_actor,
             executor,
+            db,
+            offer_filter: offer_filter_sender,
             _close_cfds_actor: close_cfds_actor,
             _archive_failed_cfds_actor: archive_failed_cfds_actor,
+            _watchdog_actor: watchdog_actor,
             _tasks: tasks,
             maker_online_status_feed_receiver,
+            maker_latency_feed_receiver,
             identify_info_feed_receiver,
-            _online_status_actor: online_status_actor,
+            rehydration_progress,
+            online_status_actor,
             _pong_actor: pong_address,
             _identify_dialer_actor: identify_dialer_actor,
+            maker_connection_actor,
+            maker_identity_actor,
+            _watchtower_client_actor: watchtower_client_actor,
+            maker_identity_pin_receiver,
         })
     }
 
+    /// Place an order for the offer with `offer_id`.
+    ///
+    /// With a caller-supplied `order_id` placing the order is
+    /// idempotent: if an order with this ID was already placed, its ID
+    /// is returned without placing another order.
     #[instrument(skip(self), err)]
     pub async fn place_order(
         &self,
         offer_id: OfferId,
         quantity: Contracts,
         leverage: Leverage,
+        order_id: Option<OrderId>,
     ) -> Result<OrderId> {
+        self.maker_identity_actor
+            .send(maker_identity::EnsureTrusted)
+            .await??;
+
+        self.maker_connection_actor
+            .send(maker_connection::EnsureConnected)
+            .await??
+            .wait()
+            .await?;
+
         let order_id = self
             .cfd_actor
             .send(taker_cfd::PlaceOrder {
+                order_id,
                 offer_id,
                 quantity,
                 leverage,
@@ -376,8 +648,353 @@ where
         Ok(order_id)
     }
 
+    /// Configure when to disconnect from an idle maker. `None` keeps
+    /// the connection up at all times.
+    #[instrument(skip(self), err)]
+    pub async fn set_idle_policy(
+        &self,
+        policy: Option<maker_connection::IdlePolicy>,
+    ) -> Result<()> {
+        self.maker_connection_actor
+            .send(maker_connection::SetIdlePolicy(policy))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Connect to the maker at `maker_multiaddr` from now on.
+    ///
+    /// The address is persisted and used instead of the maker address
+    /// from the command line on subsequent starts.
+    #[instrument(skip(self), err)]
+    pub async fn update_maker_address(&self, maker_multiaddr: Multiaddr) -> Result<()> {
+        let update = maker_connection::UpdateMakerAddress::new(maker_multiaddr)?;
+
+        self.db.save_maker_address(&update.multiaddr).await?;
+
+        self.cfd_actor.send(update.clone()).await?;
+        self.online_status_actor.send(update.clone()).await??;
+        self.maker_identity_actor.send(update.clone()).await??;
+        self.maker_connection_actor.send(update).await??;
+
+        Ok(())
+    }
+
+    /// Trust the identity of the maker we are configured to connect to,
+    /// even though it differs from the one pinned on first use.
+    #[instrument(skip(self), err)]
+    pub async fn trust_maker_identity(&self) -> Result<()> {
+        self.maker_identity_actor
+            .send(maker_identity::Trust)
+            .await??;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn set_loss_limit(&self, limit: Option<risk_limits::LossLimit>) -> Result<()> {
+        self.risk_limits_actor
+            .send(risk_limits::SetLossLimit(limit))
+            .await??;
+
+        Ok(())
+    }
+
+    /// Allow placing orders for [`risk_limits::OVERRIDE_DURATION`] even if
+    /// the configured loss limit has been reached.
+    #[instrument(skip(self), err)]
+    pub async fn override_loss_limit(&self) -> Result<()> {
+        self.risk_limits_actor
+            .send(risk_limits::OverrideLossLimit)
+            .await??;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn risk_status(&self) -> Result<risk_limits::RiskStatus> {
+        self.risk_limits_actor
+            .send(risk_limits::GetRiskStatus)
+            .await?
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn set_rollover_policy(
+        &self,
+        order_id: OrderId,
+        policy: RolloverPolicy,
+    ) -> Result<()> {
+        self.auto_rollover_actor
+            .send(auto_rollover::SetRolloverPolicy { order_id, policy })
+            .await?
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn preferences(&self) -> Result<preferences::Preferences> {
+        preferences::load(&self.db).await
+    }
+
+    /// Replace the preferences of the user, so that all frontends pick
+    /// them up.
+    #[instrument(skip(self), err)]
+    pub async fn set_preferences(&self, preferences: preferences::Preferences) -> Result<()> {
+        preferences::save(&self.db, &preferences).await
+    }
+
+    /// The funding rate the maker currently offers for CFDs in which the
+    /// maker holds `position_maker`, if any.
+    #[instrument(skip(self), err)]
+    pub async fn current_funding_rate(
+        &self,
+        contract_symbol: ContractSymbol,
+        position_maker: Position,
+    ) -> Result<Option<FundingRate>> {
+        let funding_rate = self
+            .cfd_actor
+            .send(taker_cfd::GetFundingRate {
+                contract_symbol,
+                position_maker,
+            })
+            .await?;
+
+        Ok(funding_rate)
+    }
+
+    /// Close the CFD once the price moves beyond `trigger_price`,
+    /// replacing any previous trigger price of the same kind.
+    #[instrument(skip(self), err)]
+    pub async fn set_price_trigger(
+        &self,
+        order_id: OrderId,
+        trigger: PriceTrigger,
+        trigger_price: Price,
+    ) -> Result<()> {
+        self.price_trigger_actor
+            .send(price_trigger::SetPriceTrigger {
+                order_id,
+                trigger,
+                trigger_price,
+            })
+            .await?
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn remove_price_trigger(
+        &self,
+        order_id: OrderId,
+        trigger: PriceTrigger,
+    ) -> Result<()> {
+        self.price_trigger_actor
+            .send(price_trigger::RemovePriceTrigger { order_id, trigger })
+            .await?
+    }
+
+    /// Close the CFD at the time given by `schedule`, replacing any
+    /// previously scheduled settlement.
+    ///
+    /// Returns the time at which the CFD will be settled.
+    #[instrument(skip(self), err)]
+    pub async fn schedule_settlement(
+        &self,
+        order_id: OrderId,
+        schedule: scheduled_settlement::Schedule,
+    ) -> Result<Timestamp> {
+        self.scheduled_settlement_actor
+            .send(scheduled_settlement::ScheduleSettlement { order_id, schedule })
+            .await?
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn cancel_scheduled_settlement(&self, order_id: OrderId) -> Result<()> {
+        self.scheduled_settlement_actor
+            .send(scheduled_settlement::CancelScheduledSettlement { order_id })
+            .await?
+    }
+
+    /// Only forward the maker's offers which match `filter`.
+    ///
+    /// The filter takes effect when the maker publishes its offers
+    /// the next time.
+    #[instrument(skip(self))]
+    pub fn set_offer_filter(&self, filter: offer::taker::OfferFilter) {
+        self.offer_filter.send_replace(filter);
+    }
+
+    /// List all outgoing requests which are still awaiting the maker's
+    /// decision, oldest first.
+    #[instrument(skip(self), err)]
+    pub async fn pending_requests(&self) -> Result<Vec<PendingRequest>> {
+        let orders = self
+            .order_actor
+            .send(order::taker::GetPendingOrders)
+            .await?;
+        let settlements = self
+            .collab_settlement_actor
+            .send(collab_settlement::taker::GetPendingProposals)
+            .await?;
+        let rollovers = self
+            .rollover_actor
+            .send(rollover::taker::GetPendingRollovers)
+            .await?;
+
+        let mut requests = PendingRequest::from_protocol(PendingRequestKind::Order, orders)
+            .chain(PendingRequest::from_protocol(
+                PendingRequestKind::Settlement,
+                settlements,
+            ))
+            .chain(PendingRequest::from_protocol(
+                PendingRequestKind::Rollover,
+                rollovers,
+            ))
+            .collect::<Vec<_>>();
+        requests.sort_by_key(|request| request.since);
+
+        Ok(requests)
+    }
+
+    /// Cancel the outgoing request for the given CFD if the maker has
+    /// not decided on it yet.
+    #[instrument(skip(self), err)]
+    pub async fn cancel_pending_request(&self, order_id: OrderId) -> Result<()> {
+        let request = self
+            .pending_requests()
+            .await?
+            .into_iter()
+            .find(|request| request.order_id == order_id)
+            .with_context(|| format!("No pending request for {order_id}"))?;
+
+        match request.kind {
+            PendingRequestKind::Order => {
+                self.order_actor
+                    .send(order::taker::CancelOrder { order_id })
+                    .await??
+            }
+            PendingRequestKind::Settlement => {
+                self.collab_settlement_actor
+                    .send(collab_settlement::taker::CancelProposal { order_id })
+                    .await??
+            }
+            PendingRequestKind::Rollover => {
+                self.rollover_actor
+                    .send(rollover::taker::CancelRollover { order_id })
+                    .await??
+            }
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn get_funding_history(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Vec<sqlite_db::FundingPayment>> {
+        self.db.load_funding_history(order_id).await
+    }
+
+    /// Load the archived CFDs, which are not part of the CFD feed.
+    #[instrument(skip(self), err)]
+    pub async fn archived_cfds(&self) -> Result<Vec<projection::Cfd>> {
+        let cfds = self
+            .projection_actor
+            .send(projection::GetArchivedCfds)
+            .await?;
+
+        Ok(cfds)
+    }
+
+    /// Get the CFDs with an open position, newest first.
+    #[instrument(skip(self), err)]
+    pub async fn open_cfds(&self) -> Result<Vec<projection::Cfd>> {
+        let cfds = self.projection_actor.send(projection::GetCfds).await??;
+
+        Ok(cfds
+            .into_iter()
+            .filter(|cfd| cfd.state.has_open_position())
+            .collect())
+    }
+
+    /// Get the CFD with the given order ID, whether it is part of the
+    /// CFD feed or archived.
+    #[instrument(skip(self), err)]
+    pub async fn cfd(&self, order_id: OrderId) -> Result<Option<projection::Cfd>> {
+        let cfds = self.projection_actor.send(projection::GetCfds).await??;
+        if let Some(cfd) = cfds.into_iter().find(|cfd| cfd.order_id == order_id) {
+            return Ok(Some(cfd));
+        }
+
+        let archived = self.archived_cfds().await?;
+
+        Ok(archived.into_iter().find(|cfd| cfd.order_id == order_id))
+    }
+
+    /// List the rollovers which are still awaiting the maker's decision,
+    /// oldest first.
+    #[instrument(skip(self), err)]
+    pub async fn pending_rollovers(&self) -> Result<Vec<PendingRequest>> {
+        let rollovers = self
+            .rollover_actor
+            .send(rollover::taker::GetPendingRollovers)
+            .await?;
+
+        let mut requests = PendingRequest::from_protocol(PendingRequestKind::Rollover, rollovers)
+            .collect::<Vec<_>>();
+        requests.sort_by_key(|request| request.since);
+
+        Ok(requests)
+    }
+
+    /// Rebuild the projection from the event store in the background.
+    ///
+    /// The CFD feed keeps serving the current state until the rebuild is complete.
+    #[instrument(skip(self), err)]
+    pub async fn rebuild_projection(&self) -> Result<()> {
+        self.projection_actor.send(projection::Rebuild).await??;
+
+        Ok(())
+    }
+
+    /// Load all closed CFDs, including the archived ones, as trades.
+    ///
+    /// The amounts are valued in fiat with the exchange rates of `fiat_rate_provider`, if given.
+    #[instrument(skip(self, fiat_rate_provider), err)]
+    pub async fn trade_history(
+        &self,
+        fiat_rate_provider: Option<&fiat_rates::Provider>,
+    ) -> Result<Vec<trade_history::Trade>> {
+        trade_history::load(&self.db, fiat_rate_provider).await
+    }
+
+    /// The statistics of the market of `contract_symbol` over the `window` up to now.
+    #[instrument(skip(self), err)]
+    pub async fn market_statistics(
+        &self,
+        contract_symbol: ContractSymbol,
+        window: time::Duration,
+    ) -> Result<analytics::Statistics> {
+        analytics::statistics(&self.db, contract_symbol, window).await
+    }
+
+    /// Load the lineage of a CFD, from the offer to its settlement.
+    #[instrument(skip(self), err)]
+    pub async fn trace(
+        &self,
+        order_id: OrderId,
+        block_explorer: &BlockExplorer,
+    ) -> Result<Option<trace::Trace>> {
+        trace::load(&self.db, block_explorer, order_id).await
+    }
+
     #[instrument(skip(self), err)]
     pub async fn commit(&self, order_id: OrderId) -> Result<()> {
+        let commit_txid = self
+            .executor
+            .query(order_id, |cfd| {
+                cfd.commit_txid().context("Cannot commit without a DLC")
+            })
+            .await?;
+        monitor::ensure_not_broadcast(&self.db, commit_txid).await?;
+
         self.executor
             .execute(order_id, |cfd| cfd.manual_commit_to_blockchain())
             .await?;
@@ -387,38 +1004,67 @@ where
 
     #[instrument(skip(self), err)]
     pub async fn propose_settlement(&self, order_id: OrderId) -> Result<()> {
+        if let Some(maker) = self.identify_info_feed_receiver.borrow().as_ref() {
+            if !maker
+                .maker_compatibility()
+                .supports(Feature::CollaborativeSettlement)
+            {
+                bail!("Maker does not support collaborative settlement")
+            }
+        }
+
         let contract_symbol = self
             .executor
             .query(order_id, |cfd| Ok(cfd.contract_symbol()))
             .await?;
 
-        let latest_quote = *self
-            .price_feed_actor
-            .send(xtra_bitmex_price_feed::GetLatestQuotes)
-            .await
-            .context("Price feed not available")?
-            .get(&into_price_feed_symbol(contract_symbol))
-            .context("No quote available")?;
+        let symbol = into_price_feed_symbol(contract_symbol);
+        let (bid, ask, quote_timestamp) = match self.settlement_twap {
+            Some(duration) => {
+                let twap = self
+                    .price_feed_actor
+                    .send(price_feed::GetTwap { symbol, duration })
+                    .await
+                    .context("Price feed not available")?
+                    .context("No quotes available for TWAP")?;
 
-        let quote_timestamp = latest_quote
-            .timestamp
-            .format(&time::format_description::well_known::Rfc3339)
-            .context("Failed to format timestamp")?;
+                (twap.bid, twap.ask, twap.last_quote_at)
+            }
+            None => {
+                let latest_quote = *self
+                    .price_feed_actor
+                    .send(xtra_bitmex_price_feed::GetLatestQuotes)
+                    .await
+                    .context("Price feed not available")?
+                    .get(&symbol)
+                    .context("No quote available")?;
+
+                (
+                    latest_quote.bid(),
+                    latest_quote.ask(),
+                    latest_quote.timestamp,
+                )
+            }
+        };
 
         let threshold = QUOTE_INTERVAL_MINUTES.minutes() * 2;
 
-        if latest_quote.is_older_than(threshold) {
+        if quote_timestamp < OffsetDateTime::now_utc() - threshold {
             bail!(
                 "Latest quote is older than {} minutes. Refusing to settle with old price.",
                 threshold.whole_minutes()
             )
         }
 
+        let quote_timestamp = quote_timestamp
+            .format(&time::format_description::well_known::Rfc3339)
+            .context("Failed to format timestamp")?;
+
         self.cfd_actor
             .send(taker_cfd::ProposeSettlement {
                 order_id,
-                bid: Price::new(latest_quote.bid())?,
-                ask: Price::new(latest_quote.ask())?,
+                bid: Price::new(bid)?,
+                ask: Price::new(ask)?,
                 quote_timestamp,
             })
             .await?
@@ -430,12 +1076,16 @@ where
         amount: Option<Amount>,
         address: bitcoin::Address,
         fee_rate: FeeRate,
+        utxos: Vec<OutPoint>,
+        exclude: Vec<OutPoint>,
     ) -> Result<Txid> {
         self.wallet_actor
             .send(wallet::Withdraw {
                 amount,
                 address,
                 fee: Some(fee_rate),
+                utxos,
+                exclude,
             })
             .await?
     }
@@ -445,6 +1095,34 @@ where
         self.wallet_actor.send(wallet::Sync).await?;
         Ok(())
     }
+
+    #[instrument(skip(self), err)]
+    pub async fn list_utxos(&self) -> Result<Vec<wallet::Utxo>> {
+        self.wallet_actor.send(wallet::ListUtxos).await?
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn freeze_utxos(&self, outpoints: Vec<bitcoin::OutPoint>) -> Result<()> {
+        self.wallet_actor
+            .send(wallet::FreezeUtxos {
+                outpoints: outpoints.clone(),
+            })
+            .await??;
+
+        self.db.insert_frozen_utxos(&outpoints).await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn unfreeze_utxos(&self, outpoints: Vec<bitcoin::OutPoint>) -> Result<()> {
+        self.db.delete_frozen_utxos(&outpoints).await?;
+
+        self.wallet_actor
+            .send(wallet::UnfreezeUtxos { outpoints })
+            .await?;
+        Ok(())
+    }
 }
 
 /// A struct defining our environment
@@ -500,4 +1178,13 @@ mod tests {
             "Unknown".to_string()
         );
     }
+
+    #[test]
+    fn connection_settings_are_bounded() {
+        let secs = Duration::from_secs;
+
+        ConnectionSettings::new(secs(30), secs(120)).expect("settings within bounds");
+        ConnectionSettings::new(secs(1), secs(20)).expect_err("ping interval too short");
+        ConnectionSettings::new(secs(30), secs(3600)).expect_err("connection timeout too long");
+    }
 }
@@ -4,8 +4,42 @@ use anyhow::Result;
 use std::net::IpAddr;
 use std::net::SocketAddr;
 
+use libp2p_core::either::EitherTransport;
+use libp2p_core::transport::OrTransport;
 use libp2p_core::Multiaddr;
 use libp2p_core::PeerId;
+use libp2p_core::Transport;
+use libp2p_tcp::TokioTcpConfig;
+use libp2p_websocket::WsConfig;
+use xtra_libp2p::socks5::Socks5Transport;
+
+/// Transport which supports both plain TCP and WebSocket multiaddrs.
+///
+/// Addresses ending in `/ws` are handled by the WebSocket transport,
+/// all other TCP addresses by plain TCP. Connections over either
+/// transport are upgraded with noise and yamux by the `Endpoint`.
+pub type TcpOrWebSocketTransport = OrTransport<TokioTcpConfig, WsConfig<TokioTcpConfig>>;
+
+/// Construct a [`TcpOrWebSocketTransport`].
+///
+/// WebSockets allow reaching a peer from networks where middleboxes
+/// only let HTTP(S)-like traffic through.
+pub fn tcp_or_websocket_transport() -> TcpOrWebSocketTransport {
+    TokioTcpConfig::new().or_transport(WsConfig::new(TokioTcpConfig::new()))
+}
+
+/// Construct the transport the taker uses to dial the maker.
+///
+/// If a SOCKS5 proxy (e.g. Tor) is given, all connections are dialed through it. Otherwise the
+/// maker is dialed directly via TCP or WebSocket.
+pub fn taker_transport(
+    socks5_proxy: Option<SocketAddr>,
+) -> EitherTransport<Socks5Transport, TcpOrWebSocketTransport> {
+    match socks5_proxy {
+        Some(proxy) => EitherTransport::Left(Socks5Transport::new(proxy)),
+        None => EitherTransport::Right(tcp_or_websocket_transport()),
+    }
+}
 
 /// Creates MultiAddr from SocketAddr and PeerId
 pub fn create_connect_tcp_multiaddr(
@@ -21,6 +55,47 @@ pub fn create_connect_tcp_multiaddr(
         .with_context(|| "failed to construct multiaddr")
 }
 
+/// Creates a WebSocket MultiAddr from SocketAddr and PeerId
+pub fn create_connect_websocket_multiaddr(
+    socket_addr: &SocketAddr,
+    peer_id: PeerId,
+) -> Result<Multiaddr> {
+    let ip = socket_addr.ip();
+    let port = socket_addr.port();
+    ensure!(socket_addr.is_ipv4(), "only ipv4 is supported");
+
+    format!("/ip4/{ip}/tcp/{port}/ws/p2p/{peer_id}")
+        .parse::<Multiaddr>()
+        .with_context(|| "failed to construct multiaddr")
+}
+
+/// Creates MultiAddr from a `host:port` address and PeerId, to be dialed through a SOCKS5 proxy.
+///
+/// The host is not resolved locally but by the proxy. Hosts ending in `.onion` are turned into
+/// `/onion3` addresses.
+pub fn create_connect_socks5_multiaddr(address: &str, peer_id: PeerId) -> Result<Multiaddr> {
+    let (host, port) = address
+        .rsplit_once(':')
+        .with_context(|| format!("address {address} does not contain a port"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = port
+        .parse::<u16>()
+        .with_context(|| format!("invalid port in address {address}"))?;
+
+    let multiaddr = match host.strip_suffix(".onion") {
+        Some(onion) => format!("/onion3/{onion}:{port}/p2p/{peer_id}"),
+        None => match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => format!("/ip4/{ip}/tcp/{port}/p2p/{peer_id}"),
+            Ok(IpAddr::V6(ip)) => format!("/ip6/{ip}/tcp/{port}/p2p/{peer_id}"),
+            Err(_) => format!("/dns/{host}/tcp/{port}/p2p/{peer_id}"),
+        },
+    };
+
+    multiaddr
+        .parse::<Multiaddr>()
+        .with_context(|| "failed to construct multiaddr")
+}
+
 /// Construct a Multiaddr that can dial in to other party given their MultiAddr
 /// and PeerId
 pub fn create_connect_multiaddr(
@@ -41,6 +116,15 @@ pub fn create_listen_tcp_multiaddr(ip: &IpAddr, port: u16) -> Result<Multiaddr>
         .with_context(|| "failed to construct multiaddr")
 }
 
+/// Creates a WebSocket MultiAddr from SocketAddr
+pub fn create_listen_websocket_multiaddr(ip: &IpAddr, port: u16) -> Result<Multiaddr> {
+    ensure!(ip.is_ipv4(), "only ipv4 is supported");
+
+    format!("/ip4/{ip}/tcp/{port}/ws")
+        .parse::<Multiaddr>()
+        .with_context(|| "failed to construct multiaddr")
+}
+
 /// Determine whether to use libp2p or fallback to a legacy protocol
 pub fn can_use_libp2p(cfd: &model::Cfd) -> bool {
     // Our abitily to kick-off a libp2p version of protocol is constrained by
@@ -48,3 +132,40 @@ pub fn can_use_libp2p(cfd: &model::Cfd) -> bool {
     // can't dial in to them using libp2p.
     cfd.counterparty_peer_id().is_some()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER_ID: &str = "12D3KooWP3BN6bq9jPy8cP7Grj1QyUBfr7U6BeQFgMwfTTu12wuY";
+
+    #[test]
+    fn onion_host_becomes_onion3_multiaddr() {
+        let onion = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+
+        let multiaddr = create_connect_socks5_multiaddr(
+            &format!("{onion}.onion:10000"),
+            PEER_ID.parse().unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            multiaddr.to_string(),
+            format!("/onion3/{onion}:10000/p2p/{PEER_ID}")
+        );
+    }
+
+    #[test]
+    fn domain_is_not_resolved_locally() {
+        let multiaddr = create_connect_socks5_multiaddr(
+            "mainnet.itchysats.network:10001",
+            PEER_ID.parse().unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            multiaddr.to_string(),
+            format!("/dns/mainnet.itchysats.network/tcp/10001/p2p/{PEER_ID}")
+        );
+    }
+}
@@ -3,7 +3,9 @@ use crate::command;
 use crate::identify;
 use crate::oracle;
 use crate::order;
+use crate::watchtower;
 use ping_pong::pong;
+use serde::Serialize;
 use std::collections::HashSet;
 use xtra::message_channel::MessageChannel;
 use xtra::Address;
@@ -18,19 +20,21 @@ pub const MAKER_LISTEN_PROTOCOLS: MakerListenProtocols = MakerListenProtocols::n
         collab_settlement::PROTOCOL,
         collab_settlement::deprecated::PROTOCOL,
     ),
+    watchtower::PROTOCOL,
 );
 
-pub const TAKER_LISTEN_PROTOCOLS: TakerListenProtocols =
-    TakerListenProtocols::new(ping_pong::PROTOCOL, identify::PROTOCOL, offer::PROTOCOL);
+/// Protocol identifiers the maker advertises in addition to the
+/// protocols it listens for, to signal features of those protocols.
+pub const MAKER_ADVERTISED_FEATURES: [&str; 1] = [collab_settlement::LOCK_TIME_FEATURE];
+
+pub const TAKER_LISTEN_PROTOCOLS: TakerListenProtocols = TakerListenProtocols::new(
+    ping_pong::PROTOCOL,
+    identify::PROTOCOL,
+    (offer::PROTOCOL, offer::PROTOCOL_WITHOUT_FEE_SUBSIDY),
+);
 
 pub const REQUIRED_MAKER_LISTEN_PROTOCOLS: RequiredMakerListenProtocols =
-    RequiredMakerListenProtocols::new(
-        ping_pong::PROTOCOL,
-        identify::PROTOCOL,
-        order::PROTOCOL,
-        rollover::PROTOCOL,
-        collab_settlement::PROTOCOL,
-    );
+    RequiredMakerListenProtocols::new(ping_pong::PROTOCOL, identify::PROTOCOL, order::PROTOCOL);
 
 /// Verify if the listen protocols that the `maker` supports are
 /// sufficient to fulfil the `requirements` of the taker.
@@ -51,6 +55,105 @@ pub fn does_maker_satisfy_taker_needs(
     Ok(())
 }
 
+/// A feature the taker only offers if the maker supports it.
+///
+/// Unlike the [`REQUIRED_MAKER_LISTEN_PROTOCOLS`], a maker lacking one of
+/// these is still compatible; the taker just disables the feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Rollover,
+    CollaborativeSettlement,
+    /// Locking the collaborative settlement transaction to the current
+    /// block height
+    SettlementLockTime,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [
+        Feature::Rollover,
+        Feature::CollaborativeSettlement,
+        Feature::SettlementLockTime,
+    ];
+
+    /// The protocol the maker has to advertise to support the feature.
+    pub fn protocol(&self) -> &'static str {
+        match self {
+            Feature::Rollover => rollover::PROTOCOL,
+            Feature::CollaborativeSettlement => collab_settlement::PROTOCOL,
+            Feature::SettlementLockTime => collab_settlement::LOCK_TIME_FEATURE,
+        }
+    }
+}
+
+/// How well the taker can work with a maker, judging by the protocols the
+/// maker is listening for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compatibility {
+    /// Required protocols that the maker does not support.
+    pub missing_protocols: HashSet<String>,
+    /// Features which are disabled because the maker does not support them.
+    pub disabled_features: HashSet<Feature>,
+    /// Whether the maker only supports newer versions of some of the
+    /// protocols we speak, i.e. the maker requires a newer taker.
+    pub taker_outdated: bool,
+}
+
+impl Compatibility {
+    pub fn new(maker: &HashSet<String>) -> Self {
+        let missing_protocols =
+            does_maker_satisfy_taker_needs(maker, REQUIRED_MAKER_LISTEN_PROTOCOLS)
+                .err()
+                .unwrap_or_default();
+
+        let disabled_features = Feature::ALL
+            .into_iter()
+            .filter(|feature| !maker.contains(feature.protocol()))
+            .collect::<HashSet<_>>();
+
+        let taker_outdated = missing_protocols
+            .iter()
+            .map(String::as_str)
+            .chain(disabled_features.iter().map(Feature::protocol))
+            .any(|protocol| has_newer_version(maker, protocol));
+
+        Self {
+            missing_protocols,
+            disabled_features,
+            taker_outdated,
+        }
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        !self.disabled_features.contains(&feature)
+    }
+}
+
+/// Whether `maker` listens for a newer version of `protocol`.
+fn has_newer_version(maker: &HashSet<String>, protocol: &str) -> bool {
+    let (name, version) = match parse_protocol(protocol) {
+        Some(parsed) => parsed,
+        None => return false,
+    };
+
+    maker
+        .iter()
+        .filter_map(|protocol| parse_protocol(protocol))
+        .any(|(maker_name, maker_version)| maker_name == name && maker_version > version)
+}
+
+/// Split a protocol identifier such as `/itchysats/order/2.0.0` into its
+/// name and version.
+fn parse_protocol(protocol: &str) -> Option<(&str, Vec<u64>)> {
+    let (name, version) = protocol.rsplit_once('/')?;
+    let version = version
+        .split('.')
+        .map(|number| number.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+
+    Some((name, version))
+}
+
 /// The set of protocols that the maker's `Endpoint` is listening for.
 #[derive(Clone, Copy)]
 pub struct MakerListenProtocols {
@@ -62,6 +165,7 @@ pub struct MakerListenProtocols {
     rollover_deprecated: &'static str,
     collaborative_settlement: &'static str,
     collaborative_settlement_deprecated: &'static str,
+    watchtower: &'static str,
 }
 
 type RolloverAddress<R> =
@@ -72,7 +176,7 @@ type RolloverDeprecatedAddress<RD> = Address<
 >;
 
 impl MakerListenProtocols {
-    pub const NR_OF_SUPPORTED_PROTOCOLS: usize = 8;
+    pub const NR_OF_SUPPORTED_PROTOCOLS: usize = 9;
 
     pub const fn new(
         ping: &'static str,
@@ -83,6 +187,7 @@ impl MakerListenProtocols {
             &'static str,
             &'static str,
         ),
+        watchtower: &'static str,
     ) -> Self {
         Self {
             ping,
@@ -93,6 +198,7 @@ impl MakerListenProtocols {
             rollover_deprecated,
             collaborative_settlement,
             collaborative_settlement_deprecated,
+            watchtower,
         }
     }
 
@@ -116,6 +222,7 @@ impl MakerListenProtocols {
             Address<collab_settlement::maker::Actor>,
             Address<collab_settlement::deprecated::maker::Actor>,
         ),
+        watchtower_handler: Address<watchtower::tower::Actor>,
     ) -> [(&'static str, MessageChannel<NewInboundSubstream, ()>); Self::NR_OF_SUPPORTED_PROTOCOLS]
     where
         R: rollover::protocol::GetRates + Send + Sync + Clone + 'static,
@@ -131,6 +238,7 @@ impl MakerListenProtocols {
             rollover_deprecated,
             collaborative_settlement,
             collaborative_settlement_deprecated,
+            watchtower,
         } = self;
 
         [
@@ -148,6 +256,7 @@ impl MakerListenProtocols {
                 collaborative_settlement_deprecated,
                 collaborative_settlement_deprecated_handler.into(),
             ),
+            (watchtower, watchtower_handler.into()),
         ]
     }
 }
@@ -164,18 +273,24 @@ impl From<MakerListenProtocols> for HashSet<String> {
             rollover_deprecated,
             collaborative_settlement,
             collaborative_settlement_deprecated,
+            watchtower,
         } = maker;
 
-        HashSet::from([
-            ping.to_string(),
-            identify.to_string(),
-            order.to_string(),
-            order_deprecated.to_string(),
-            rollover.to_string(),
-            rollover_deprecated.to_string(),
-            collaborative_settlement.to_string(),
-            collaborative_settlement_deprecated.to_string(),
-        ])
+        [
+            ping,
+            identify,
+            order,
+            order_deprecated,
+            rollover,
+            rollover_deprecated,
+            collaborative_settlement,
+            collaborative_settlement_deprecated,
+            watchtower,
+        ]
+        .into_iter()
+        .chain(MAKER_ADVERTISED_FEATURES)
+        .map(str::to_string)
+        .collect()
     }
 }
 
@@ -186,24 +301,14 @@ pub struct RequiredMakerListenProtocols {
     ping: &'static str,
     identify: &'static str,
     order: &'static str,
-    rollover: &'static str,
-    collaborative_settlement: &'static str,
 }
 
 impl RequiredMakerListenProtocols {
-    pub const fn new(
-        ping: &'static str,
-        identify: &'static str,
-        order: &'static str,
-        rollover: &'static str,
-        collaborative_settlement: &'static str,
-    ) -> Self {
+    pub const fn new(ping: &'static str, identify: &'static str, order: &'static str) -> Self {
         Self {
             ping,
             identify,
             order,
-            rollover,
-            collaborative_settlement,
         }
     }
 }
@@ -215,17 +320,9 @@ impl From<RequiredMakerListenProtocols> for HashSet<String> {
             ping,
             identify,
             order,
-            rollover,
-            collaborative_settlement,
         } = required;
 
-        HashSet::from([
-            ping.to_string(),
-            identify.to_string(),
-            order.to_string(),
-            rollover.to_string(),
-            collaborative_settlement.to_string(),
-        ])
+        HashSet::from([ping.to_string(), identify.to_string(), order.to_string()])
     }
 }
 
@@ -235,16 +332,22 @@ pub struct TakerListenProtocols {
     ping: &'static str,
     identify: &'static str,
     offer: &'static str,
+    offer_without_fee_subsidy: &'static str,
 }
 
 impl TakerListenProtocols {
-    const NR_OF_SUPPORTED_PROTOCOLS: usize = 3;
+    const NR_OF_SUPPORTED_PROTOCOLS: usize = 4;
 
-    pub const fn new(ping: &'static str, identify: &'static str, offer: &'static str) -> Self {
+    pub const fn new(
+        ping: &'static str,
+        identify: &'static str,
+        (offer, offer_without_fee_subsidy): (&'static str, &'static str),
+    ) -> Self {
         Self {
             ping,
             identify,
             offer,
+            offer_without_fee_subsidy,
         }
     }
 
@@ -264,12 +367,14 @@ impl TakerListenProtocols {
             ping,
             identify,
             offer,
+            offer_without_fee_subsidy,
         } = self;
 
         [
             (ping, ping_handler.into()),
             (identify, identify_handler.into()),
-            (offer, offer_handler.into()),
+            (offer, offer_handler.clone().into()),
+            (offer_without_fee_subsidy, offer_handler.into()),
         ]
     }
 }
@@ -281,9 +386,15 @@ impl From<TakerListenProtocols> for HashSet<String> {
             ping,
             identify,
             offer,
+            offer_without_fee_subsidy,
         } = protocols;
 
-        HashSet::from_iter([ping.to_string(), identify.to_string(), offer.to_string()])
+        HashSet::from_iter([
+            ping.to_string(),
+            identify.to_string(),
+            offer.to_string(),
+            offer_without_fee_subsidy.to_string(),
+        ])
     }
 }
 
@@ -330,11 +441,48 @@ mod tests {
         assert!(result.is_ok(), "Missing protocols detected: {result:?}");
     }
 
+    #[test]
+    fn given_maker_supports_everything_then_all_features_enabled() {
+        let compatibility = Compatibility::new(&MAKER_LISTEN_PROTOCOLS.into());
+
+        assert!(compatibility.missing_protocols.is_empty());
+        assert!(compatibility.disabled_features.is_empty());
+        assert!(!compatibility.taker_outdated);
+    }
+
+    #[test]
+    fn given_maker_lacks_feature_protocol_then_only_feature_disabled() {
+        let mut maker_protocols_as_hashset: HashSet<String> = MAKER_LISTEN_PROTOCOLS.into();
+        maker_protocols_as_hashset.remove(collab_settlement::PROTOCOL);
+
+        let compatibility = Compatibility::new(&maker_protocols_as_hashset);
+
+        assert!(compatibility.missing_protocols.is_empty());
+        assert!(!compatibility.supports(Feature::CollaborativeSettlement));
+        assert!(compatibility.supports(Feature::Rollover));
+        assert!(!compatibility.taker_outdated);
+    }
+
+    #[test]
+    fn given_maker_only_supports_newer_protocol_version_then_taker_outdated() {
+        let mut maker_protocols_as_hashset: HashSet<String> = MAKER_LISTEN_PROTOCOLS.into();
+        maker_protocols_as_hashset.remove(order::PROTOCOL);
+        maker_protocols_as_hashset.insert("/itchysats/order/99.0.0".to_string());
+
+        let compatibility = Compatibility::new(&maker_protocols_as_hashset);
+
+        assert_eq!(
+            compatibility.missing_protocols,
+            HashSet::from([order::PROTOCOL.to_string()])
+        );
+        assert!(compatibility.taker_outdated);
+    }
+
     #[test]
     fn ensure_nr_of_maker_protocols_matches_hashset_len() {
         let maker_protocols_as_hashset: HashSet<String> = MAKER_LISTEN_PROTOCOLS.into();
         assert_eq!(
-            MakerListenProtocols::NR_OF_SUPPORTED_PROTOCOLS,
+            MakerListenProtocols::NR_OF_SUPPORTED_PROTOCOLS + MAKER_ADVERTISED_FEATURES.len(),
             maker_protocols_as_hashset.len()
         );
     }
@@ -0,0 +1,407 @@
+//! The taker's connection to the maker.
+//!
+//! By default the taker stays connected to the maker at all times and
+//! reconnects whenever the connection drops. With an [`IdlePolicy`] the
+//! taker disconnects once it has no open CFDs and the connection has not
+//! been used for [`IdlePolicy::timeout`], which spares the maker from
+//! keeping up connections to takers that are not trading. The
+//! connection is re-established on demand, e.g. when an order is placed
+//! or the UI follows the offers of the maker.
+//!
+//! If the maker migrates to a new address, the connection is moved over
+//! with [`UpdateMakerAddress`] without having to restart the taker.
+//!
+//! The actor is meant to be supervised. Its [`Settings`] are shared with
+//! the supervisor, so that a restarted actor connects to the latest
+//! maker address with the latest idle policy.
+
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use libp2p_core::Multiaddr;
+use libp2p_core::PeerId;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::oneshot;
+use xtra::Address;
+use xtra_libp2p::endpoint;
+use xtra_libp2p::multiaddress_ext::MultiaddrExt;
+use xtra_libp2p::Connect;
+use xtra_libp2p::Disconnect;
+use xtra_libp2p::Endpoint;
+use xtra_libp2p::GetConnectionStats;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often the connection to the maker is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// When to disconnect from the maker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdlePolicy {
+    /// Disconnect if the connection was not used for this long and there
+    /// are no open CFDs.
+    pub timeout: Duration,
+}
+
+/// Where the maker is and when to disconnect from it.
+///
+/// Cloning shares the settings, which lets them outlive the [`Actor`]
+/// when it is restarted by its supervisor.
+#[derive(Clone)]
+pub struct Settings(Arc<Mutex<SettingsInner>>);
+
+struct SettingsInner {
+    maker_multiaddr: Multiaddr,
+    maker_peer_id: PeerId,
+    idle_policy: Option<IdlePolicy>,
+}
+
+impl Settings {
+    pub fn new(maker_multiaddr: Multiaddr) -> Result<Self> {
+        let maker_peer_id = maker_multiaddr
+            .clone()
+            .extract_peer_id()
+            .context("Maker address does not contain a peer id")?;
+
+        Ok(Self(Arc::new(Mutex::new(SettingsInner {
+            maker_multiaddr,
+            maker_peer_id,
+            idle_policy: None,
+        }))))
+    }
+
+    fn maker(&self) -> (Multiaddr, PeerId) {
+        let inner = self.0.lock().expect("not poisoned");
+
+        (inner.maker_multiaddr.clone(), inner.maker_peer_id)
+    }
+
+    fn maker_peer_id(&self) -> PeerId {
+        self.0.lock().expect("not poisoned").maker_peer_id
+    }
+
+    fn idle_policy(&self) -> Option<IdlePolicy> {
+        self.0.lock().expect("not poisoned").idle_policy
+    }
+
+    fn set_maker(&self, maker_multiaddr: Multiaddr, maker_peer_id: PeerId) {
+        let mut inner = self.0.lock().expect("not poisoned");
+
+        inner.maker_multiaddr = maker_multiaddr;
+        inner.maker_peer_id = maker_peer_id;
+    }
+
+    fn set_idle_policy(&self, idle_policy: Option<IdlePolicy>) {
+        self.0.lock().expect("not poisoned").idle_policy = idle_policy;
+    }
+}
+
+/// Configure the idle policy. `None` keeps the connection up at all
+/// times.
+#[derive(Clone, Copy)]
+pub struct SetIdlePolicy(pub Option<IdlePolicy>);
+
+/// Record that the connection is in use, reconnecting in the background
+/// if necessary.
+#[derive(Clone, Copy)]
+pub struct KeepAlive;
+
+/// Record that the connection is in use and reconnect if necessary.
+///
+/// Returns a [`Connected`] future instead of waiting for the connection,
+/// so that the actor keeps handling messages in the meantime.
+#[derive(Clone, Copy)]
+pub struct EnsureConnected;
+
+/// Waits until the connection to the maker is established.
+#[must_use]
+pub struct Connected {
+    /// `None` if we are connected already.
+    established: Option<oneshot::Receiver<()>>,
+    timeout: Duration,
+}
+
+impl Connected {
+    pub async fn wait(self) -> Result<()> {
+        let established = match self.established {
+            Some(established) => established,
+            None => return Ok(()),
+        };
+
+        tokio_extras::time::timeout(self.timeout, established, || {
+            tracing::debug_span!("Wait for connection to maker")
+        })
+        .await
+        .context("Could not connect to maker")?
+        .context("Stopped waiting for connection to maker")?;
+
+        Ok(())
+    }
+}
+
+/// Connect to the maker at a new address.
+///
+/// Also handled by the other actors which need to know who the maker is,
+/// e.g. to watch its online status.
+#[derive(Clone, Debug)]
+pub struct UpdateMakerAddress {
+    pub multiaddr: Multiaddr,
+    pub peer_id: PeerId,
+}
+
+impl UpdateMakerAddress {
+    pub fn new(multiaddr: Multiaddr) -> Result<Self> {
+        let peer_id = multiaddr
+            .clone()
+            .extract_peer_id()
+            .context("Maker address does not contain a peer id")?;
+
+        Ok(Self { multiaddr, peer_id })
+    }
+}
+
+/// Message sent to ourselves at an interval to reconnect or disconnect
+/// from the maker.
+#[derive(Clone, Copy)]
+struct CheckConnection;
+
+pub struct Actor {
+    endpoint: Address<Endpoint>,
+    settings: Settings,
+    db: sqlite_db::Connection,
+    connection_timeout: Duration,
+    last_activity: Instant,
+    /// Waiting for the connection to the maker to be established.
+    waiting: Vec<oneshot::Sender<()>>,
+}
+
+impl Actor {
+    pub fn new(
+        endpoint: Address<Endpoint>,
+        settings: Settings,
+        db: sqlite_db::Connection,
+        connection_timeout: Duration,
+    ) -> Self {
+        Self {
+            endpoint,
+            settings,
+            db,
+            connection_timeout,
+            last_activity: Instant::now(),
+            waiting: Vec::new(),
+        }
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        let stats = self
+            .endpoint
+            .send(GetConnectionStats)
+            .await
+            .context("Endpoint actor is disconnected")?;
+
+        Ok(stats
+            .connected_peers
+            .contains(&self.settings.maker_peer_id()))
+    }
+
+    async fn connect(&self) -> Result<()> {
+        let (maker_multiaddr, _) = self.settings.maker();
+        let result = self
+            .endpoint
+            .send(Connect(maker_multiaddr))
+            .await
+            .context("Endpoint actor is disconnected")?;
+
+        // Failing to connect is expected while a previous attempt is
+        // still in flight, we try again on the next check
+        if let Err(e) = result {
+            tracing::debug!("Failed to request connection to maker: {e:#}");
+        }
+
+        Ok(())
+    }
+
+    /// Whether the connection to the maker is not needed.
+    async fn is_idle(&self) -> Result<bool> {
+        if !is_timed_out(self.settings.idle_policy(), self.last_activity.elapsed()) {
+            return Ok(false);
+        }
+
+        let open_cfds = self.db.load_open_cfd_ids().await?;
+
+        Ok(open_cfds.is_empty())
+    }
+
+    async fn check_connection(&mut self) -> Result<()> {
+        let is_connected = self.is_connected().await?;
+        let is_idle = self.is_idle().await?;
+
+        match (is_connected, is_idle) {
+            (false, false) => self.connect().await?,
+            (true, true) => {
+                let maker_peer_id = self.settings.maker_peer_id();
+                tracing::info!(peer_id = %maker_peer_id, "Disconnecting from idle maker");
+
+                self.endpoint
+                    .send(Disconnect(maker_peer_id))
+                    .await
+                    .context("Endpoint actor is disconnected")?;
+            }
+            (true, false) | (false, true) => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    fn handle(&mut self, msg: SetIdlePolicy) {
+        self.settings.set_idle_policy(msg.0);
+    }
+
+    async fn handle(&mut self, _: KeepAlive) -> Result<()> {
+        self.last_activity = Instant::now();
+
+        if !self.is_connected().await? {
+            tracing::info!(peer_id = %self.settings.maker_peer_id(), "Reconnecting to maker");
+            self.connect().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, _: EnsureConnected) -> Result<Connected> {
+        self.last_activity = Instant::now();
+
+        if self.is_connected().await? {
+            return Ok(Connected {
+                established: None,
+                timeout: self.connection_timeout,
+            });
+        }
+
+        tracing::info!(peer_id = %self.settings.maker_peer_id(), "Reconnecting to maker");
+        self.connect().await?;
+
+        let (sender, receiver) = oneshot::channel();
+        self.waiting.retain(|waiting| !waiting.is_closed());
+        self.waiting.push(sender);
+
+        Ok(Connected {
+            established: Some(receiver),
+            timeout: self.connection_timeout,
+        })
+    }
+
+    async fn handle(&mut self, msg: UpdateMakerAddress) -> Result<()> {
+        let UpdateMakerAddress { multiaddr, peer_id } = msg;
+        let previous_peer_id = self.settings.maker_peer_id();
+
+        if peer_id != previous_peer_id && self.is_connected().await? {
+            tracing::info!(
+                peer_id = %previous_peer_id,
+                "Disconnecting from maker at previous address"
+            );
+
+            self.endpoint
+                .send(Disconnect(previous_peer_id))
+                .await
+                .context("Endpoint actor is disconnected")?;
+        }
+
+        tracing::info!(%peer_id, %multiaddr, "Updated maker address");
+
+        self.settings.set_maker(multiaddr, peer_id);
+        self.last_activity = Instant::now();
+
+        self.check_connection().await
+    }
+
+    async fn handle(&mut self, _: CheckConnection) {
+        if let Err(e) = self.check_connection().await {
+            tracing::warn!("Failed to check connection to maker: {e:#}");
+        }
+    }
+
+    async fn handle(&mut self, msg: endpoint::ConnectionEstablished) {
+        if msg.peer_id != self.settings.maker_peer_id() {
+            return;
+        }
+
+        for waiting in self.waiting.drain(..) {
+            let _ = waiting.send(());
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(
+                CHECK_INTERVAL,
+                || CheckConnection,
+                xtras::IncludeSpan::Never,
+            ),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// Whether the connection has not been used for longer than the idle
+/// policy allows.
+fn is_timed_out(policy: Option<IdlePolicy>, since_last_activity: Duration) -> bool {
+    match policy {
+        Some(IdlePolicy { timeout }) => since_last_activity >= timeout,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_only_times_out_with_idle_policy() {
+        let policy = IdlePolicy {
+            timeout: Duration::from_secs(60),
+        };
+
+        assert!(!is_timed_out(None, Duration::from_secs(3600)));
+        assert!(!is_timed_out(Some(policy), Duration::from_secs(59)));
+        assert!(is_timed_out(Some(policy), Duration::from_secs(60)));
+    }
+
+    #[tokio::test]
+    async fn connected_resolves_once_connection_is_established() {
+        let (established, receiver) = oneshot::channel();
+        let connected = Connected {
+            established: Some(receiver),
+            timeout: Duration::from_secs(10),
+        };
+
+        established.send(()).unwrap();
+
+        connected.wait().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn connected_fails_if_connection_is_not_established_in_time() {
+        let (_established, receiver) = oneshot::channel();
+        let connected = Connected {
+            established: Some(receiver),
+            timeout: Duration::from_millis(10),
+        };
+
+        assert!(connected.wait().await.is_err());
+    }
+}
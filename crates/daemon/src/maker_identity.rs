@@ -0,0 +1,206 @@
+//! Pinning the identity of the maker on first use.
+//!
+//! The taker pins the libp2p peer id and the CFD identity of the maker
+//! the first time it starts. If the maker it is configured to connect to
+//! has a different identity on a subsequent start, e.g. because the
+//! command line arguments changed, the change is published as
+//! [`PinStatus::Changed`] and no orders can be placed until the new
+//! identity is trusted explicitly with [`Trust`].
+//!
+//! Updating the address of the maker at runtime with
+//! [`UpdateMakerAddress`] is an explicit decision as well, so the peer id
+//! of the new address is pinned right away.
+
+use crate::maker_connection::UpdateMakerAddress;
+use anyhow::bail;
+use anyhow::Context as _;
+use anyhow::Result;
+use async_trait::async_trait;
+use model::libp2p::PeerId;
+use model::Identity;
+use serde::Serialize;
+use tokio::sync::watch;
+use xtra_productivity::xtra_productivity;
+
+/// Who the maker is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MakerIdentity {
+    pub peer_id: PeerId,
+    pub identity: Identity,
+}
+
+/// Whether the maker we connect to is the one we pinned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PinStatus {
+    /// The identity of the maker was not compared to the pinned one yet
+    Unverified,
+    Trusted,
+    /// The identity of the maker differs from the pinned one
+    Changed {
+        pinned: MakerIdentity,
+        current: MakerIdentity,
+    },
+}
+
+impl PinStatus {
+    fn compare(pinned: Option<MakerIdentity>, current: MakerIdentity) -> Self {
+        match pinned {
+            Some(pinned) if pinned != current => PinStatus::Changed { pinned, current },
+            _ => PinStatus::Trusted,
+        }
+    }
+}
+
+/// Fail unless the identity of the maker is trusted.
+#[derive(Clone, Copy)]
+pub struct EnsureTrusted;
+
+/// Trust the current identity of the maker, replacing the pinned one.
+#[derive(Clone, Copy)]
+pub struct Trust;
+
+pub struct Actor {
+    db: sqlite_db::Connection,
+    maker: MakerIdentity,
+    status: watch::Sender<PinStatus>,
+}
+
+impl Actor {
+    pub fn new(
+        db: sqlite_db::Connection,
+        maker: MakerIdentity,
+    ) -> (Self, watch::Receiver<PinStatus>) {
+        let (status, receiver) = watch::channel(PinStatus::Unverified);
+
+        (Self { db, maker, status }, receiver)
+    }
+
+    async fn verify(&mut self) -> Result<()> {
+        let pinned = self
+            .db
+            .load_maker_identity()
+            .await
+            .context("Failed to load pinned identity of maker")?
+            .map(|(peer_id, identity)| MakerIdentity { peer_id, identity });
+
+        if pinned.is_none() {
+            tracing::info!(
+                peer_id = %self.maker.peer_id,
+                identity = %self.maker.identity,
+                "Pinning identity of maker on first use"
+            );
+            self.pin().await?;
+        }
+
+        let status = PinStatus::compare(pinned, self.maker);
+        if let PinStatus::Changed { pinned, current } = status {
+            tracing::error!(
+                pinned_peer_id = %pinned.peer_id,
+                pinned_identity = %pinned.identity,
+                peer_id = %current.peer_id,
+                identity = %current.identity,
+                "Identity of maker changed, not placing orders until it is trusted"
+            );
+        }
+
+        let _ = self.status.send(status);
+
+        Ok(())
+    }
+
+    async fn pin(&mut self) -> Result<()> {
+        self.db
+            .save_maker_identity(self.maker.peer_id, self.maker.identity)
+            .await
+            .context("Failed to pin identity of maker")?;
+
+        let _ = self.status.send(PinStatus::Trusted);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, _: &mut xtra::Context<Self>) {
+        if let Err(e) = self.verify().await {
+            tracing::error!("Failed to verify identity of maker: {e:#}");
+        }
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _: EnsureTrusted) -> Result<()> {
+        if *self.status.borrow() == PinStatus::Unverified {
+            self.verify().await?;
+        }
+
+        match *self.status.borrow() {
+            PinStatus::Trusted => Ok(()),
+            PinStatus::Unverified => bail!("Identity of maker is not verified"),
+            PinStatus::Changed { current, .. } => bail!(
+                "Identity of maker changed to {} with peer id {}, trust it to place orders",
+                current.identity,
+                current.peer_id
+            ),
+        }
+    }
+
+    async fn handle(&mut self, _: Trust) -> Result<()> {
+        tracing::warn!(
+            peer_id = %self.maker.peer_id,
+            identity = %self.maker.identity,
+            "Trusting new identity of maker"
+        );
+
+        self.pin().await
+    }
+
+    async fn handle(&mut self, msg: UpdateMakerAddress) -> Result<()> {
+        tracing::info!(peer_id = %msg.peer_id, "Pinning peer id of updated maker address");
+
+        self.maker.peer_id = msg.peer_id.into();
+
+        self.pin().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn maker(peer_id: PeerId) -> MakerIdentity {
+        MakerIdentity {
+            peer_id,
+            identity: Identity::new(x25519_dalek::PublicKey::from([42u8; 32])),
+        }
+    }
+
+    #[test]
+    fn maker_is_trusted_on_first_use_and_while_unchanged() {
+        let current = maker(PeerId::random());
+
+        assert_eq!(PinStatus::compare(None, current), PinStatus::Trusted);
+        assert_eq!(
+            PinStatus::compare(Some(current), current),
+            PinStatus::Trusted
+        );
+    }
+
+    #[test]
+    fn changed_peer_id_is_not_trusted() {
+        let pinned = maker(PeerId::random());
+        let current = maker(PeerId::random());
+
+        assert_eq!(
+            PinStatus::compare(Some(pinned), current),
+            PinStatus::Changed { pinned, current }
+        );
+    }
+}
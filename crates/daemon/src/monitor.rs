@@ -1,10 +1,16 @@
 use crate::bitcoin::consensus::encode::serialize_hex;
 use crate::bitcoin::Transaction;
 use crate::command;
+use crate::electrum;
+use crate::fee_estimation::FeeEstimator;
+use crate::wallet;
 use crate::wallet::RpcErrorCode;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
+use bdk::bitcoin::Amount;
+use bdk::bitcoin::Network;
 use bdk::bitcoin::PublicKey;
 use bdk::bitcoin::Script;
 use bdk::bitcoin::Txid;
@@ -12,6 +18,7 @@ use bdk::descriptor::Descriptor;
 use bdk::electrum_client;
 use bdk::electrum_client::ElectrumApi;
 use bdk::miniscript::DescriptorTrait;
+use bdk::FeeRate;
 use btsieve::ScriptStatus;
 use btsieve::State;
 use btsieve::TxStatus;
@@ -20,20 +27,75 @@ use model::CfdEvent;
 use model::Dlc;
 use model::EventKind;
 use model::OrderId;
+use model::Role;
+use model::Timestamp;
 use model::CET_TIMELOCK;
 use serde_json::Value;
 use sqlite_db;
 use std::collections::HashMap;
 use std::time::Duration;
+use std::time::Instant;
 use tracing::Instrument;
+use xtra::prelude::MessageChannel;
 use xtra_productivity::xtra_productivity;
 use xtras::SendInterval;
 
-const LOCK_FINALITY_CONFIRMATIONS: u32 = 1;
-const CLOSE_FINALITY_CONFIRMATIONS: u32 = 3;
-const COMMIT_FINALITY_CONFIRMATIONS: u32 = 1;
-const CET_FINALITY_CONFIRMATIONS: u32 = 3;
-const REFUND_FINALITY_CONFIRMATIONS: u32 = 3;
+/// How many confirmations the transactions of a CFD need to be
+/// considered final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FinalityConfirmations {
+    pub lock: u32,
+    pub commit: u32,
+    pub close: u32,
+    pub cet: u32,
+    pub refund: u32,
+}
+
+impl FinalityConfirmations {
+    /// The confirmations required on `network`.
+    ///
+    /// Blocks on regtest are only mined on demand, so a single
+    /// confirmation suffices there.
+    pub fn for_network(network: Network) -> Self {
+        match network {
+            Network::Bitcoin | Network::Testnet | Network::Signet => Self::default(),
+            Network::Regtest => Self {
+                lock: 1,
+                commit: 1,
+                close: 1,
+                cet: 1,
+                refund: 1,
+            },
+        }
+    }
+}
+
+impl Default for FinalityConfirmations {
+    fn default() -> Self {
+        Self {
+            lock: 1,
+            commit: 1,
+            close: 3,
+            cet: 3,
+            refund: 3,
+        }
+    }
+}
+
+/// Number of blocks within which we want an unconfirmed CET to be
+/// included.
+const CET_CONFIRMATION_TARGET_BLOCKS: usize = 6;
+
+/// Number of blocks within which we want a commit transaction to be
+/// included.
+const COMMIT_CONFIRMATION_TARGET_BLOCKS: usize = 3;
+
+/// Minimum time between two attempts at bumping the fee of the same CET.
+const CET_FEE_BUMP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// How long the claim of a broadcast suppresses concurrent broadcasts of
+/// the same transaction.
+const BROADCAST_CLAIM_DURATION: Duration = Duration::from_secs(60);
 
 pub struct MonitorAfterContractSetup {
     order_id: OrderId,
@@ -55,9 +117,53 @@ pub struct MonitorCetFinality {
     pub cet: Transaction,
 }
 
+/// Broadcast a transaction of a CFD, unless it is being broadcast
+/// concurrently.
+///
+/// A transaction which was broadcast recently by any subsystem is not
+/// broadcast again while it is in the mempool or confirmed, see
+/// [`sqlite_db::broadcast`]. A suppressed duplicate broadcast is not an
+/// error.
 pub struct TryBroadcastTransaction {
+    pub order_id: OrderId,
     pub tx: Transaction,
     pub kind: TransactionKind,
+    pub initiator: BroadcastInitiator,
+}
+
+/// The subsystem which initiated the broadcast of a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BroadcastInitiator {
+    /// The protocol, in reaction to an event of the CFD
+    Protocol,
+    /// A manual commit, e.g. through the API
+    ManualCommit,
+    /// The monitor, for transactions which are due when it starts
+    Monitor,
+}
+
+impl BroadcastInitiator {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BroadcastInitiator::Protocol => "protocol",
+            BroadcastInitiator::ManualCommit => "manual-commit",
+            BroadcastInitiator::Monitor => "monitor",
+        }
+    }
+}
+
+/// Fail if the transaction with `txid` was broadcast before, naming the
+/// subsystem which initiated the broadcast.
+pub async fn ensure_not_broadcast(db: &sqlite_db::Connection, txid: Txid) -> Result<()> {
+    if let Some(broadcast) = db.load_broadcast(txid).await? {
+        bail!(
+            "The {} transaction {txid} was already broadcast, initiated by the {}",
+            broadcast.kind,
+            broadcast.initiator
+        );
+    }
+
+    Ok(())
 }
 
 #[derive(Clone, Copy)]
@@ -108,14 +214,32 @@ pub struct Sync;
 pub struct Actor {
     executor: command::Executor,
     client: bdk::electrum_client::Client,
+    /// The Electrum server to follow, and the one `client` is connected
+    /// to.
+    active_electrum: electrum::ActiveServer,
+    electrum_url: String,
     state: State<Event>,
     db: sqlite_db::Connection,
+    cpfp_transaction: MessageChannel<wallet::CpfpTransaction, Result<Txid>>,
+    fee_estimator: FeeEstimator,
+    finality: FinalityConfirmations,
+    unconfirmed_cets: HashMap<OrderId, UnconfirmedCet>,
+}
+
+/// A CET which we have broadcast but which has not been confirmed yet.
+struct UnconfirmedCet {
+    tx: Transaction,
+    /// The child transaction of our latest fee bump, which the next
+    /// bump replaces.
+    child: Option<Txid>,
+    last_fee_bump_attempt: Option<Instant>,
 }
 
 /// Read-model of the CFD for the monitoring actor.
 #[derive(Clone)]
 struct Cfd {
     id: OrderId,
+    role: Role,
 
     lock: Option<Lock>,
     monitor_lock_finality: bool,
@@ -140,6 +264,8 @@ struct Cfd {
     broadcast_lock: Option<Transaction>,
     broadcast_cet: Option<Transaction>,
     broadcast_commit: Option<Transaction>,
+    /// Only the maker publishes the collaborative settlement transaction
+    broadcast_collaborative_settlement: Option<Transaction>,
 
     version: u32,
 }
@@ -150,6 +276,7 @@ impl sqlite_db::CfdAggregate for Cfd {
     fn new(_: Self::CtorArgs, cfd: sqlite_db::Cfd) -> Self {
         Self {
             id: cfd.id,
+            role: cfd.role,
             lock: None,
             monitor_lock_finality: false,
             collaborative_settlement: None,
@@ -166,6 +293,7 @@ impl sqlite_db::CfdAggregate for Cfd {
             broadcast_lock: None,
             broadcast_cet: None,
             broadcast_commit: None,
+            broadcast_collaborative_settlement: None,
             version: 0,
         }
     }
@@ -237,11 +365,15 @@ impl Cfd {
             CollaborativeSettlementCompleted {
                 spend_tx, script, ..
             } => {
+                let broadcast_collaborative_settlement =
+                    (self.role == Role::Maker).then(|| spend_tx.clone());
+
                 Self {
                     collaborative_settlement: Some((spend_tx.txid(), script)),
                     monitor_collaborative_settlement_finality: true,
                     monitor_lock_finality: false, // Lock is already final if we collab settle.
                     broadcast_lock: None,
+                    broadcast_collaborative_settlement,
                     ..self
                 }
             }
@@ -272,6 +404,7 @@ impl Cfd {
                 broadcast_lock: None,
                 broadcast_cet: None,
                 broadcast_commit: None,
+                broadcast_collaborative_settlement: None,
                 ..self
             },
             CetTimelockExpiredPriorOracleAttestation => Self {
@@ -299,11 +432,14 @@ impl Cfd {
             | CollaborativeSettlementStarted { .. }
             | CollaborativeSettlementRejected
             | CollaborativeSettlementFailed
+            | CollaborativeSettlementAbandoned { .. }
+            | CollaborativeSettlementProposalExpired
             | CollaborativeSettlementProposalAccepted
             | ContractSetupStarted
             | ContractSetupFailed
-            | OfferRejected
-            | RolloverRejected => self,
+            | OfferRejected(_)
+            | RolloverRejected
+            | PriceTriggered { .. } => self,
             RevokeConfirmed => {
                 // TODO: Implement revoked logic
                 self
@@ -325,10 +461,12 @@ fn cet_txid_and_script(cet: Transaction) -> Option<(Txid, Script)> {
 impl Actor {
     pub fn new(
         db: sqlite_db::Connection,
-        electrum_rpc_url: String,
+        active_electrum: electrum::ActiveServer,
         executor: command::Executor,
+        cpfp_transaction: MessageChannel<wallet::CpfpTransaction, Result<Txid>>,
     ) -> Result<Self> {
-        let client = bdk::electrum_client::Client::new(&electrum_rpc_url)
+        let electrum_url = active_electrum.url();
+        let client = bdk::electrum_client::Client::new(&electrum_url)
             .context("Failed to initialize Electrum RPC client")?;
 
         // Initially fetch the latest block for storing the height.
@@ -341,11 +479,32 @@ impl Actor {
 
         Ok(Self {
             client,
+            active_electrum,
+            electrum_url,
             executor,
             state: State::new(latest_block),
             db,
+            cpfp_transaction,
+            fee_estimator: FeeEstimator::Electrum,
+            finality: FinalityConfirmations::default(),
+            unconfirmed_cets: HashMap::default(),
         })
     }
+
+    /// Estimate fee rates with `fee_estimator` instead of the Electrum
+    /// server.
+    pub fn with_fee_estimator(self, fee_estimator: FeeEstimator) -> Self {
+        Self {
+            fee_estimator,
+            ..self
+        }
+    }
+
+    /// Consider transactions final after `finality` confirmations
+    /// instead of the defaults for mainnet.
+    pub fn with_finality_confirmations(self, finality: FinalityConfirmations) -> Self {
+        Self { finality, ..self }
+    }
 }
 
 impl Actor {
@@ -353,7 +512,7 @@ impl Actor {
         self.state.monitor(
             txid,
             descriptor.script_pubkey(),
-            ScriptStatus::with_confirmations(LOCK_FINALITY_CONFIRMATIONS),
+            ScriptStatus::with_confirmations(self.finality.lock),
             Event::LockFinality(order_id),
         )
     }
@@ -362,7 +521,7 @@ impl Actor {
         self.state.monitor(
             txid,
             descriptor.script_pubkey(),
-            ScriptStatus::with_confirmations(COMMIT_FINALITY_CONFIRMATIONS),
+            ScriptStatus::with_confirmations(self.finality.commit),
             Event::CommitFinality(order_id),
         )
     }
@@ -371,7 +530,7 @@ impl Actor {
         self.state.monitor(
             close_params.0,
             close_params.1,
-            ScriptStatus::with_confirmations(CLOSE_FINALITY_CONFIRMATIONS),
+            ScriptStatus::with_confirmations(self.finality.close),
             Event::CloseFinality(order_id),
         );
     }
@@ -380,7 +539,7 @@ impl Actor {
         self.state.monitor(
             close_params.0,
             close_params.1,
-            ScriptStatus::with_confirmations(CET_FINALITY_CONFIRMATIONS),
+            ScriptStatus::with_confirmations(self.finality.cet),
             Event::CetFinality(order_id),
         );
     }
@@ -424,7 +583,7 @@ impl Actor {
         self.state.monitor(
             txid,
             script_pubkey,
-            ScriptStatus::with_confirmations(REFUND_FINALITY_CONFIRMATIONS),
+            ScriptStatus::with_confirmations(self.finality.refund),
             Event::RefundFinality(order_id),
         );
     }
@@ -448,8 +607,28 @@ impl Actor {
         }
     }
 
+    /// Reconnect to the active Electrum server if we failed over to a
+    /// different one.
+    fn follow_active_electrum(&mut self) -> Result<()> {
+        let url = self.active_electrum.url();
+
+        if url == self.electrum_url {
+            return Ok(());
+        }
+
+        self.client = bdk::electrum_client::Client::new(&url)
+            .context("Failed to initialize Electrum RPC client")?;
+        self.electrum_url = url;
+
+        tracing::info!(url = %self.electrum_url, "Monitor switched to Electrum server");
+
+        Ok(())
+    }
+
     #[tracing::instrument("Sync monitor", skip_all, err)]
     async fn sync(&mut self) -> Result<()> {
+        self.follow_active_electrum()?;
+
         // Fetch the latest block for storing the height.
         // We do not act on this subscription after this call, as we cannot rely on
         // subscription push notifications because eventually the Electrum server will
@@ -507,6 +686,7 @@ impl Actor {
                         .await
                 }
                 Event::CetFinality(id) => {
+                    self.unconfirmed_cets.remove(&id);
                     self.invoke_cfd_command(id, |cfd| Ok(Some(cfd.handle_cet_confirmed())))
                         .await
                 }
@@ -540,6 +720,285 @@ impl Actor {
             }
         }
     }
+
+    /// Bump the fee of all CETs which are still unconfirmed and pay
+    /// less than what is currently needed to get confirmed within
+    /// [`CET_CONFIRMATION_TARGET_BLOCKS`].
+    #[tracing::instrument("Bump fees of unconfirmed CETs", skip_all)]
+    async fn bump_stuck_cets(&mut self) -> Result<()> {
+        if self.unconfirmed_cets.is_empty() {
+            return Ok(());
+        }
+
+        let target_fee_rate = self
+            .estimate_fee_rate(CET_CONFIRMATION_TARGET_BLOCKS)
+            .await?;
+
+        let order_ids = self.unconfirmed_cets.keys().copied().collect::<Vec<_>>();
+        for order_id in order_ids {
+            if let Err(e) = self.bump_cet(order_id, target_fee_rate).await {
+                tracing::warn!(%order_id, "Failed to bump fee of CET: {e:#}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn bump_cet(&mut self, order_id: OrderId, target_fee_rate: FeeRate) -> Result<()> {
+        let cet = match self.unconfirmed_cets.get(&order_id) {
+            Some(cet) => cet,
+            None => return Ok(()),
+        };
+
+        if let Some(last_attempt) = cet.last_fee_bump_attempt {
+            if last_attempt.elapsed() < CET_FEE_BUMP_INTERVAL {
+                return Ok(());
+            }
+        }
+
+        let tx = cet.tx.clone();
+        let child = cet.child;
+        let txid = tx.txid();
+
+        let script = &tx
+            .output
+            .first()
+            .context("CET without outputs")?
+            .script_pubkey;
+        let is_confirmed = self
+            .client
+            .script_get_history(script)
+            .context("Failed to get script history")?
+            .iter()
+            .any(|entry| entry.tx_hash == txid && entry.height > 0);
+        if is_confirmed {
+            self.unconfirmed_cets.remove(&order_id);
+            return Ok(());
+        }
+
+        let (fee, fee_rate) = self.transaction_fee_rate(&tx)?;
+        let package_fee_rate = match child {
+            Some(child_txid) => self.package_fee_rate(&tx, fee, child_txid)?,
+            None => fee_rate,
+        };
+
+        if package_fee_rate >= target_fee_rate {
+            return Ok(());
+        }
+
+        tracing::info!(
+            %order_id,
+            %txid,
+            fee_rate = %fee_rate.as_sat_vb(),
+            package_fee_rate = %package_fee_rate.as_sat_vb(),
+            target_fee_rate = %target_fee_rate.as_sat_vb(),
+            "CET pays too little fees, bumping via CPFP"
+        );
+
+        if let Some(cet) = self.unconfirmed_cets.get_mut(&order_id) {
+            cet.last_fee_bump_attempt = Some(Instant::now());
+        }
+
+        let child_txid = self
+            .cpfp_transaction
+            .send(wallet::CpfpTransaction {
+                parent: tx,
+                parent_fee: fee,
+                target_fee_rate,
+            })
+            .await
+            .context("Wallet actor disconnected")??;
+
+        if let Some(cet) = self.unconfirmed_cets.get_mut(&order_id) {
+            cet.child = Some(child_txid);
+        }
+
+        if child == Some(child_txid) {
+            return Ok(());
+        }
+
+        tracing::info!(%order_id, %txid, %child_txid, "Bumped fee of CET");
+
+        CET_FEE_BUMP_COUNTER.inc();
+
+        Ok(())
+    }
+
+    /// Fee rate needed for a transaction to be confirmed within
+    /// `target_blocks`.
+    async fn estimate_fee_rate(&self, target_blocks: usize) -> Result<FeeRate> {
+        self.fee_estimator
+            .estimate(&self.client, target_blocks)
+            .await
+    }
+
+    /// Fee rate paid by `tx`, which has to spend confirmed outputs or
+    /// outputs of transactions in the mempool.
+    fn transaction_fee_rate(&self, tx: &Transaction) -> Result<(Amount, FeeRate)> {
+        let fee = self.transaction_fee(tx)?;
+        let vbytes = (tx.weight() as u64 + 3) / 4;
+        let fee_rate = FeeRate::from_sat_per_vb(fee.as_sat() as f32 / vbytes as f32);
+
+        Ok((fee, fee_rate))
+    }
+
+    /// Fee rate paid by the package of `tx`, which pays `fee`, and its
+    /// child.
+    ///
+    /// Only `tx` is considered if the child is not known to the
+    /// Electrum server anymore, e.g. because it was evicted from the
+    /// mempool.
+    fn package_fee_rate(&self, tx: &Transaction, fee: Amount, child_txid: Txid) -> Result<FeeRate> {
+        let (child_fee, child_vbytes) = match self.client.transaction_get(&child_txid) {
+            Ok(child) => (
+                self.transaction_fee(&child)?,
+                (child.weight() as u64 + 3) / 4,
+            ),
+            Err(e) => {
+                tracing::debug!(%child_txid, "Ignoring unknown CPFP transaction: {e:#}");
+                (Amount::ZERO, 0)
+            }
+        };
+
+        let vbytes = (tx.weight() as u64 + 3) / 4 + child_vbytes;
+        let fee = fee + child_fee;
+
+        Ok(FeeRate::from_sat_per_vb(
+            fee.as_sat() as f32 / vbytes as f32,
+        ))
+    }
+
+    /// Broadcast a transaction, treating it as broadcast if it is
+    /// already on-chain.
+    async fn broadcast(
+        &mut self,
+        order_id: OrderId,
+        tx: Transaction,
+        kind: TransactionKind,
+    ) -> Result<()> {
+        if let TransactionKind::Commit = kind {
+            if let Err(e) = self.check_commit_fee_rate(order_id, &tx).await {
+                tracing::warn!(%order_id, "Failed to check fee rate of commit transaction: {e:#}");
+            }
+        }
+
+        let result = self.client.transaction_broadcast(&tx);
+
+        if let Err(electrum_client::Error::Protocol(ref value)) = result {
+            let rpc_error = parse_rpc_protocol_error(value)
+                .with_context(|| format!("Failed to parse electrum error response '{value:?}'"))?;
+
+            if rpc_error.code == i64::from(RpcErrorCode::RpcVerifyAlreadyInChain) {
+                let txid = tx.txid();
+                tracing::trace!(
+                    %txid, kind = %kind.name(), "Attempted to broadcast transaction that was already on-chain",
+                );
+
+                return Ok(());
+            }
+
+            // We do this check because electrum sometimes returns an RpcVerifyError when it should
+            // be returning a RpcVerifyAlreadyInChain error,
+            if rpc_error.code == i64::from(RpcErrorCode::RpcVerifyError)
+                && rpc_error.message == "bad-txns-inputs-missingorspent"
+            {
+                if let Ok(tx) = self.client.transaction_get(&tx.txid()) {
+                    let txid = tx.txid();
+                    tracing::trace!(
+                        %txid, kind = %kind.name(), "Attempted to broadcast transaction that was already on-chain",
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        let txid = tx.txid();
+
+        result.with_context(|| {
+            let tx_hex = serialize_hex(&tx);
+
+            format!("Failed to broadcast transaction. Txid: {txid}. Kind: {}. Raw transaction: {tx_hex}", kind.name())
+        })?;
+
+        tracing::info!(%txid, kind = %kind.name(), "Transaction published on chain");
+
+        TRANSACTION_BROADCAST_COUNTER
+            .with(&HashMap::from([(KIND_LABEL, kind.name())]))
+            .inc();
+
+        if let TransactionKind::Cet = kind {
+            // Keep the child of an earlier fee bump if the CET is
+            // broadcast again
+            self.unconfirmed_cets
+                .entry(order_id)
+                .or_insert(UnconfirmedCet {
+                    tx,
+                    child: None,
+                    last_fee_bump_attempt: None,
+                });
+
+            // Bump the fee right away instead of waiting for the next sync
+            // if the CET pays too little for the current mempool conditions
+            let result = async {
+                let target_fee_rate = self
+                    .estimate_fee_rate(CET_CONFIRMATION_TARGET_BLOCKS)
+                    .await?;
+                self.bump_cet(order_id, target_fee_rate).await
+            };
+            if let Err(e) = result.await {
+                tracing::warn!(%order_id, "Failed to bump fee of CET: {e:#}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Warn if the commit transaction `tx` pays less than what is
+    /// currently needed to get confirmed within
+    /// [`COMMIT_CONFIRMATION_TARGET_BLOCKS`].
+    ///
+    /// The commit transaction only has outputs locked to both parties,
+    /// so its fee cannot be bumped by us.
+    async fn check_commit_fee_rate(&self, order_id: OrderId, tx: &Transaction) -> Result<()> {
+        let target_fee_rate = self
+            .estimate_fee_rate(COMMIT_CONFIRMATION_TARGET_BLOCKS)
+            .await?;
+        let (_, fee_rate) = self.transaction_fee_rate(tx)?;
+
+        if fee_rate < target_fee_rate {
+            tracing::warn!(
+                %order_id,
+                txid = %tx.txid(),
+                fee_rate = %fee_rate.as_sat_vb(),
+                target_fee_rate = %target_fee_rate.as_sat_vb(),
+                "Commit transaction pays too little fees, confirmation may be delayed"
+            );
+        }
+
+        Ok(())
+    }
+
+    fn transaction_fee(&self, tx: &Transaction) -> Result<Amount> {
+        let mut input_value = 0;
+        for input in tx.input.iter() {
+            let prev_tx = self
+                .client
+                .transaction_get(&input.previous_output.txid)
+                .context("Failed to get previous transaction")?;
+            let prev_output = prev_tx
+                .output
+                .get(input.previous_output.vout as usize)
+                .context("Previous output does not exist")?;
+
+            input_value += prev_output.value;
+        }
+
+        let output_value = tx.output.iter().map(|output| output.value).sum::<u64>();
+        let fee = input_value
+            .checked_sub(output_value)
+            .context("Transaction outputs exceed its inputs")?;
+
+        Ok(Amount::from_sat(fee))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Copy)]
@@ -595,6 +1054,7 @@ impl xtra::Actor for Actor {
                             broadcast_lock,
                             broadcast_cet,
                             broadcast_commit,
+                            broadcast_collaborative_settlement,
                             ..
                         } = match cfd {
                             Ok(cfd) => cfd,
@@ -607,8 +1067,10 @@ impl xtra::Actor for Actor {
                             let span = tracing::debug_span!("Broadcast commit TX", order_id = %id);
                             if let Err(e) = this
                                 .send(TryBroadcastTransaction {
+                                    order_id: id,
                                     tx,
                                     kind: TransactionKind::Commit,
+                                    initiator: BroadcastInitiator::Monitor,
                                 })
                                 .instrument(span)
                                 .await?
@@ -617,12 +1079,34 @@ impl xtra::Actor for Actor {
                             }
                         }
 
+                        let unconfirmed_cet = broadcast_cet.clone();
                         if let Some(tx) = broadcast_cet {
                             let span = tracing::debug_span!("Broadcast CET", order_id = %id);
                             if let Err(e) = this
                                 .send(TryBroadcastTransaction {
+                                    order_id: id,
                                     tx,
                                     kind: TransactionKind::Cet,
+                                    initiator: BroadcastInitiator::Monitor,
+                                })
+                                .instrument(span)
+                                .await?
+                            {
+                                tracing::warn!("{e:#}")
+                            }
+                        }
+
+                        if let Some(tx) = broadcast_collaborative_settlement {
+                            let span = tracing::debug_span!(
+                                "Broadcast collaborative settlement TX",
+                                order_id = %id
+                            );
+                            if let Err(e) = this
+                                .send(TryBroadcastTransaction {
+                                    order_id: id,
+                                    tx,
+                                    kind: TransactionKind::CollaborativeClose,
+                                    initiator: BroadcastInitiator::Monitor,
                                 })
                                 .instrument(span)
                                 .await?
@@ -635,8 +1119,10 @@ impl xtra::Actor for Actor {
                             let span = tracing::debug_span!("Broadcast lock TX", order_id = %id);
                             if let Err(e) = this
                                 .send(TryBroadcastTransaction {
+                                    order_id: id,
                                     tx,
                                     kind: TransactionKind::Lock,
+                                    initiator: BroadcastInitiator::Monitor,
                                 })
                                 .instrument(span)
                                 .await?
@@ -660,6 +1146,7 @@ impl xtra::Actor for Actor {
                             refund,
                             monitor_refund_finality,
                             monitor_revoked_commit_transactions,
+                            unconfirmed_cet,
                         })
                         .await?;
                     }
@@ -724,53 +1211,67 @@ impl Actor {
         );
     }
 
-    async fn handle_try_broadcast_transaction(&self, msg: TryBroadcastTransaction) -> Result<()> {
-        let TryBroadcastTransaction { tx, kind } = msg;
-
-        let result = self.client.transaction_broadcast(&tx);
+    async fn handle_try_broadcast_transaction(
+        &mut self,
+        msg: TryBroadcastTransaction,
+    ) -> Result<()> {
+        let TryBroadcastTransaction {
+            order_id,
+            tx,
+            kind,
+            initiator,
+        } = msg;
 
-        if let Err(electrum_client::Error::Protocol(ref value)) = result {
-            let rpc_error = parse_rpc_protocol_error(value)
-                .with_context(|| format!("Failed to parse electrum error response '{value:?}'"))?;
+        self.follow_active_electrum()?;
 
-            if rpc_error.code == i64::from(RpcErrorCode::RpcVerifyAlreadyInChain) {
-                let txid = tx.txid();
-                tracing::trace!(
-                    %txid, kind = %kind.name(), "Attempted to broadcast transaction that was already on-chain",
+        let txid = tx.txid();
+        let claim = sqlite_db::broadcast::Broadcast {
+            txid,
+            order_id,
+            kind: kind.name().to_owned(),
+            initiator: initiator.name().to_owned(),
+            timestamp: Timestamp::now(),
+        };
+        let expired_before =
+            Timestamp::new(claim.timestamp.seconds() - BROADCAST_CLAIM_DURATION.as_secs() as i64);
+        if let Some(broadcast) = self.db.claim_broadcast(&claim, expired_before).await? {
+            // A transaction which is neither in the mempool nor confirmed
+            // has to be broadcast again, no matter who claimed it
+            if self.client.transaction_get(&txid).is_ok() {
+                tracing::info!(
+                    %order_id,
+                    %txid,
+                    kind = %kind.name(),
+                    initiator = %initiator.name(),
+                    broadcast_by = %broadcast.initiator,
+                    "Suppressed duplicate broadcast of transaction"
                 );
+                DUPLICATE_BROADCAST_COUNTER
+                    .with(&HashMap::from([(KIND_LABEL, kind.name())]))
+                    .inc();
 
                 return Ok(());
             }
 
-            // We do this check because electrum sometimes returns an RpcVerifyError when it should
-            // be returning a RpcVerifyAlreadyInChain error,
-            if rpc_error.code == i64::from(RpcErrorCode::RpcVerifyError)
-                && rpc_error.message == "bad-txns-inputs-missingorspent"
-            {
-                if let Ok(tx) = self.client.transaction_get(&tx.txid()) {
-                    let txid = tx.txid();
-                    tracing::trace!(
-                        %txid, kind = %kind.name(), "Attempted to broadcast transaction that was already on-chain",
-                    );
-                    return Ok(());
-                }
-            }
+            tracing::debug!(
+                %order_id,
+                %txid,
+                kind = %kind.name(),
+                broadcast_by = %broadcast.initiator,
+                "Broadcasting claimed transaction which is not known to Electrum"
+            );
         }
-        let txid = tx.txid();
-
-        result.with_context(|| {
-            let tx_hex = serialize_hex(&tx);
-
-            format!("Failed to broadcast transaction. Txid: {txid}. Kind: {}. Raw transaction: {tx_hex}", kind.name())
-        })?;
 
-        tracing::info!(%txid, kind = %kind.name(), "Transaction published on chain");
+        let result = self.broadcast(order_id, tx, kind).await;
 
-        TRANSACTION_BROADCAST_COUNTER
-            .with(&HashMap::from([(KIND_LABEL, kind.name())]))
-            .inc();
+        // Releasing the claim allows the broadcast to be retried
+        if result.is_err() {
+            if let Err(e) = self.db.release_broadcast(txid).await {
+                tracing::warn!(%txid, "Failed to release claim of broadcast: {e:#}");
+            }
+        }
 
-        Ok(())
+        result
     }
 
     async fn handle_reinit_monitoring(&mut self, msg: ReinitMonitoring) {
@@ -789,6 +1290,7 @@ impl Actor {
             refund,
             monitor_refund_finality,
             monitor_revoked_commit_transactions,
+            unconfirmed_cet,
         } = msg;
 
         if let (Some(lock), true) = (lock, monitor_lock_finality) {
@@ -825,6 +1327,15 @@ impl Actor {
         if let (Some(params), true) = (cet, monitor_cet_finality) {
             self.monitor_cet_finality(id, params);
         }
+
+        // Keep bumping the fee of the CET after a restart until it is confirmed
+        if let (Some(tx), true) = (unconfirmed_cet, monitor_cet_finality) {
+            self.unconfirmed_cets.entry(id).or_insert(UnconfirmedCet {
+                tx,
+                child: None,
+                last_fee_bump_attempt: None,
+            });
+        }
     }
 
     async fn handle_monitor_cet_finality(&mut self, msg: MonitorCetFinality) -> Result<()> {
@@ -877,7 +1388,7 @@ impl TransactionsAfterContractSetup {
         // should have put up coins to create the CFD
         let refund_script_pubkey = dlc.maker_address.script_pubkey();
         let refund_txid = dlc.refund.0.txid();
-        let refund_timelock = dlc.refund_timelock;
+        let refund_timelock = dlc.refund_timelock_in_blocks();
 
         Self {
             lock: Lock {
@@ -911,7 +1422,7 @@ impl TransactionsAfterRollover {
         // should have put up coins to create the CFD
         let refund_script_pubkey = dlc.maker_address.script_pubkey();
         let refund_txid = dlc.refund.0.txid();
-        let refund_timelock = dlc.refund_timelock;
+        let refund_timelock = dlc.refund_timelock_in_blocks();
 
         let revoked_commits = dlc
             .revoked_commit
@@ -989,6 +1500,9 @@ struct ReinitMonitoring {
     monitor_refund_finality: bool,
 
     monitor_revoked_commit_transactions: Vec<RevokedCommit>,
+
+    /// The CET we broadcast, if it is not confirmed yet
+    unconfirmed_cet: Option<Transaction>,
 }
 
 #[xtra_productivity]
@@ -997,7 +1511,13 @@ impl Actor {
         if let Err(e) = self.sync().await {
             tracing::warn!("Sync failed: {:#}", e);
         }
+
+        if let Err(e) = self.bump_stuck_cets().await {
+            tracing::warn!("Failed to bump fees of unconfirmed CETs: {e:#}");
+        }
     }
+
+    async fn handle(&mut self, _: xtras::Probe) {}
 }
 
 const KIND_LABEL: &str = "kind";
@@ -1011,3 +1531,22 @@ static TRANSACTION_BROADCAST_COUNTER: conquer_once::Lazy<prometheus::IntCounterV
         )
         .unwrap()
     });
+
+static DUPLICATE_BROADCAST_COUNTER: conquer_once::Lazy<prometheus::IntCounterVec> =
+    conquer_once::Lazy::new(|| {
+        prometheus::register_int_counter_vec!(
+            "blockchain_transactions_duplicate_broadcast_total",
+            "The number of transactions not broadcast because they were broadcast before.",
+            &[KIND_LABEL]
+        )
+        .unwrap()
+    });
+
+static CET_FEE_BUMP_COUNTER: conquer_once::Lazy<prometheus::IntCounter> =
+    conquer_once::Lazy::new(|| {
+        prometheus::register_int_counter!(
+            "blockchain_cet_fee_bumps_total",
+            "The number of CPFP transactions published to bump the fee of a CET."
+        )
+        .unwrap()
+    });
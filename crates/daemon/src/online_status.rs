@@ -1,37 +1,202 @@
+use crate::maker_connection::UpdateMakerAddress;
+use anyhow::Context as _;
+use anyhow::Result;
 use async_trait::async_trait;
 use libp2p_core::PeerId;
+use ping_pong::ping::LatencyMeasured;
+use std::collections::VecDeque;
 use std::time::Duration;
+use std::time::Instant;
 use tokio::sync::watch;
 use xtra::prelude::*;
 use xtra_libp2p::endpoint;
 use xtra_libp2p::Endpoint;
 use xtra_libp2p::GetConnectionStats;
 use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// Round trips to the watched peer slower than this mark it as degraded.
+///
+/// This is well below the timeouts of our protocols, so that UIs can warn
+/// the user before e.g. a settlement times out.
+pub const DEGRADED_LATENCY: Duration = Duration::from_secs(2);
+
+/// How many of the latest round trips the latency percentiles are
+/// computed over.
+const LATENCY_WINDOW: usize = 20;
+
+/// How often to check whether a pong of the watched peer is overdue.
+const LIVENESS_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ConnectionStatus {
     Online,
+    /// Connected, but the peer responds slowly or not at all to pings.
+    Degraded,
     Offline,
 }
 
+/// Round trip times to the watched peer, as measured by pinging it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Latency {
+    pub last: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    /// How many round trips the percentiles are computed over
+    pub samples: usize,
+}
+
+impl Latency {
+    /// `None` until a round trip was measured.
+    fn new(round_trips: &VecDeque<Duration>) -> Option<Self> {
+        let last = *round_trips.back()?;
+
+        let mut sorted = round_trips.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        Some(Self {
+            last,
+            p50: percentile(&sorted, 50),
+            p95: percentile(&sorted, 95),
+            samples: sorted.len(),
+        })
+    }
+}
+
+/// The nearest-rank percentile of `sorted`, which must not be empty.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (percent * sorted.len() + 99) / 100;
+
+    sorted[rank.max(1) - 1]
+}
+
+/// What we know about the connection to the watched peer.
+struct Liveness {
+    ping_interval: Duration,
+    connected: bool,
+    round_trips: VecDeque<Duration>,
+    /// When the connection was established or the last pong received
+    last_heard_from: Instant,
+}
+
+impl Liveness {
+    fn new(ping_interval: Duration) -> Self {
+        Self {
+            ping_interval,
+            connected: false,
+            round_trips: VecDeque::new(),
+            last_heard_from: Instant::now(),
+        }
+    }
+
+    fn connected(&mut self, now: Instant) {
+        self.connected = true;
+        self.last_heard_from = now;
+    }
+
+    fn disconnected(&mut self) {
+        self.connected = false;
+        self.round_trips.clear();
+    }
+
+    fn record(&mut self, round_trip: Duration, now: Instant) {
+        if self.round_trips.len() >= LATENCY_WINDOW {
+            self.round_trips.pop_front();
+        }
+        self.round_trips.push_back(round_trip);
+        self.last_heard_from = now;
+    }
+
+    /// The peer is degraded if the last round trip was too slow, or if a
+    /// pong is overdue by more than we would accept for a round trip.
+    fn status(&self, now: Instant) -> ConnectionStatus {
+        if !self.connected {
+            return ConnectionStatus::Offline;
+        }
+
+        let slow = self
+            .round_trips
+            .back()
+            .map_or(false, |round_trip| *round_trip > DEGRADED_LATENCY);
+        let since_heard_from = now.saturating_duration_since(self.last_heard_from);
+        let pong_overdue = since_heard_from > self.ping_interval + DEGRADED_LATENCY;
+
+        if slow || pong_overdue {
+            ConnectionStatus::Degraded
+        } else {
+            ConnectionStatus::Online
+        }
+    }
+
+    fn latency(&self) -> Option<Latency> {
+        Latency::new(&self.round_trips)
+    }
+}
+
 /// Actor that transmits updates of ConnectionStatus of a specified PeerId based on
 /// information transmitted by the Endpoint via a watch channel.
+///
+/// The latencies measured by the ping actor tell apart a peer that is merely connected from one
+/// which responds in time; they are transmitted via a second watch channel.
 pub struct Actor {
     endpoint: Address<Endpoint>,
     watched_peer: PeerId,
     sender: watch::Sender<ConnectionStatus>,
+    latency_sender: watch::Sender<Option<Latency>>,
+    liveness: Liveness,
 }
 
 impl Actor {
     pub fn new(
         endpoint: Address<Endpoint>,
         watched_peer: PeerId,
+        ping_interval: Duration,
         sender: watch::Sender<ConnectionStatus>,
+        latency_sender: watch::Sender<Option<Latency>>,
     ) -> Self {
         Self {
             endpoint,
             watched_peer,
             sender,
+            latency_sender,
+            liveness: Liveness::new(ping_interval),
+        }
+    }
+}
+
+impl Actor {
+    async fn is_connected(&self) -> Result<bool> {
+        let connection_stats = self
+            .endpoint
+            .send(GetConnectionStats)
+            .await
+            .context("Endpoint actor is disconnected")?;
+
+        Ok(connection_stats
+            .connected_peers
+            .contains(&self.watched_peer))
+    }
+
+    /// Transmit the status and latency of the watched peer if they changed.
+    fn publish(&self) {
+        let status = self.liveness.status(Instant::now());
+        let status_changed = *self.sender.borrow() != status;
+        if status_changed {
+            if status == ConnectionStatus::Degraded {
+                tracing::warn!(peer_id = %self.watched_peer, "Peer responds slowly to pings");
+            }
+
+            self.sender
+                .send(status)
+                .expect("Receiver to outlive this actor");
+        }
+
+        let latency = self.liveness.latency();
+        let latency_changed = *self.latency_sender.borrow() != latency;
+        if latency_changed {
+            self.latency_sender
+                .send(latency)
+                .expect("Receiver to outlive this actor");
         }
     }
 }
@@ -47,19 +212,12 @@ impl xtra::Actor for Actor {
             self.watched_peer
         );
 
-        match self.endpoint.send(GetConnectionStats).await {
-            Ok(connection_stats) => {
-                let status = if connection_stats
-                    .connected_peers
-                    .contains(&self.watched_peer)
-                {
-                    ConnectionStatus::Online
-                } else {
-                    ConnectionStatus::Offline
-                };
-                self.sender
-                    .send(status)
-                    .expect("Receiver to outlive this actor");
+        match self.is_connected().await {
+            Ok(connected) => {
+                if connected {
+                    self.liveness.connected(Instant::now());
+                }
+                self.publish();
             }
             Err(e) => {
                 tracing::error!(
@@ -73,13 +231,29 @@ impl xtra::Actor for Actor {
                 tokio_extras::time::sleep(Duration::from_secs(2)).await;
 
                 ctx.stop_self();
+                return;
             }
         }
+
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(
+                LIVENESS_CHECK_INTERVAL,
+                || CheckLiveness,
+                xtras::IncludeSpan::Never,
+            ),
+        );
     }
 
     async fn stopped(self) -> Self::Stop {}
 }
 
+/// Private message to mark the watched peer as degraded once a pong is
+/// overdue.
+#[derive(Clone, Copy)]
+struct CheckLiveness;
+
 #[xtra_productivity]
 impl Actor {
     async fn handle_connection_established(&mut self, msg: endpoint::ConnectionEstablished) {
@@ -88,9 +262,8 @@ impl Actor {
             msg.peer_id
         );
         if msg.peer_id == self.watched_peer {
-            self.sender
-                .send(ConnectionStatus::Online)
-                .expect("Receiver to outlive this actor");
+            self.liveness.connected(Instant::now());
+            self.publish();
         }
     }
 
@@ -101,9 +274,103 @@ impl Actor {
         );
 
         if msg.peer_id == self.watched_peer {
-            self.sender
-                .send(ConnectionStatus::Offline)
-                .expect("Receiver to outlive this actor");
+            self.liveness.disconnected();
+            self.publish();
+        }
+    }
+
+    async fn handle_latency_measured(&mut self, msg: LatencyMeasured) {
+        if msg.peer_id == self.watched_peer {
+            self.liveness.record(msg.latency, Instant::now());
+            self.publish();
+        }
+    }
+
+    async fn handle_check_liveness(&mut self, _: CheckLiveness) {
+        self.publish();
+    }
+
+    async fn handle_update_maker_address(&mut self, msg: UpdateMakerAddress) -> Result<()> {
+        tracing::debug!(
+            "Monitoring new maker address for peer id changes: {:?}",
+            msg.peer_id
+        );
+
+        self.watched_peer = msg.peer_id;
+        self.liveness.disconnected();
+
+        if self.is_connected().await? {
+            self.liveness.connected(Instant::now());
+        }
+        self.publish();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PING_INTERVAL: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn latency_percentiles_are_rolling() {
+        let mut liveness = Liveness::new(PING_INTERVAL);
+        let now = Instant::now();
+        liveness.connected(now);
+
+        liveness.record(Duration::from_secs(10), now);
+        for millis in 1..=LATENCY_WINDOW as u64 {
+            liveness.record(Duration::from_millis(millis), now);
         }
+
+        let latency = liveness.latency().unwrap();
+
+        assert_eq!(latency.last, Duration::from_millis(20));
+        assert_eq!(latency.p50, Duration::from_millis(10));
+        assert_eq!(latency.p95, Duration::from_millis(19));
+        assert_eq!(latency.samples, LATENCY_WINDOW);
+    }
+
+    #[test]
+    fn slow_round_trip_degrades_peer() {
+        let mut liveness = Liveness::new(PING_INTERVAL);
+        let now = Instant::now();
+        liveness.connected(now);
+        assert_eq!(liveness.status(now), ConnectionStatus::Online);
+
+        liveness.record(DEGRADED_LATENCY + Duration::from_millis(1), now);
+        assert_eq!(liveness.status(now), ConnectionStatus::Degraded);
+
+        liveness.record(Duration::from_millis(50), now);
+        assert_eq!(liveness.status(now), ConnectionStatus::Online);
+    }
+
+    #[test]
+    fn overdue_pong_degrades_peer() {
+        let mut liveness = Liveness::new(PING_INTERVAL);
+        let now = Instant::now();
+        liveness.connected(now);
+        liveness.record(Duration::from_millis(50), now);
+
+        let on_time = now + PING_INTERVAL + DEGRADED_LATENCY;
+        let overdue = on_time + Duration::from_millis(1);
+
+        assert_eq!(liveness.status(on_time), ConnectionStatus::Online);
+        assert_eq!(liveness.status(overdue), ConnectionStatus::Degraded);
+    }
+
+    #[test]
+    fn disconnected_peer_is_offline_without_latency() {
+        let mut liveness = Liveness::new(PING_INTERVAL);
+        let now = Instant::now();
+        liveness.connected(now);
+        liveness.record(Duration::from_millis(50), now);
+
+        liveness.disconnected();
+
+        assert_eq!(liveness.status(now), ConnectionStatus::Offline);
+        assert_eq!(liveness.latency(), None);
     }
 }
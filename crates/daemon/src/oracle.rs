@@ -11,6 +11,7 @@ use model::olivia::BitMexPriceEventId;
 use model::CfdEvent;
 use model::ContractSymbol;
 use model::EventKind;
+use model::EMERGENCY_COMMIT_AFTER;
 use sqlite_db;
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -36,12 +37,24 @@ const SYNC_ANNOUNCEMENTS_INTERVAL: core::time::Duration = std::time::Duration::f
 /// We want to sync attestations fast but don't spam our internal actor. Hence, we chose 30 seconds.
 const SYNC_ATTESTATIONS_INTERVAL: core::time::Duration = std::time::Duration::from_secs(30);
 
+/// How long an attestation is kept in the cache after the event was attested.
+///
+/// The attestation is only needed until the CET of every CFD referring to the event is
+/// decrypted, which normally happens right after the attestation was fetched. A week leaves
+/// enough room for a daemon which was offline around the time of the attestation.
+const ATTESTATION_CACHE_RETENTION: Duration = Duration::days(7);
+
 pub struct Actor {
     announcements: HashMap<BitMexPriceEventId, (OffsetDateTime, Vec<XOnlyPublicKey>)>,
     pending_attestations: HashSet<BitMexPriceEventId>,
+    /// Pending attestations whose event expired, see [`olivia::ATTESTATION_DEADLINE`]
+    expired_attestations: HashSet<BitMexPriceEventId>,
+    /// Expired events for which the CFDs depending on them were committed
+    emergency_committed: HashSet<BitMexPriceEventId>,
     executor: command::Executor,
     db: sqlite_db::Connection,
     client: reqwest::Client,
+    base_url: reqwest::Url,
 }
 
 /// We want to fetch at least this much announcements into the future
@@ -152,12 +165,23 @@ impl Actor {
         Self {
             announcements: HashMap::new(),
             pending_attestations: HashSet::new(),
+            expired_attestations: HashSet::new(),
+            emergency_committed: HashSet::new(),
             executor,
             db,
             client: reqwest::Client::new(),
+            base_url: olivia::BASE_URL.parse().expect("valid URL from constant"),
         }
     }
 
+    /// Fetch announcements and attestations from the oracle at
+    /// `base_url` instead of olivia, e.g. from a local development
+    /// oracle.
+    #[must_use]
+    pub fn with_base_url(self, base_url: reqwest::Url) -> Self {
+        Self { base_url, ..self }
+    }
+
     fn ensure_having_announcements(
         &mut self,
         contract_symbol: ContractSymbol,
@@ -174,11 +198,10 @@ impl Actor {
             }
             let this = ctx.address().expect("self to be alive");
             let client = self.client.clone();
+            let url = event_id.to_url(&self.base_url);
 
             let this_clone = this.clone();
             let task = async move {
-                let url = event_id.to_olivia_url();
-
                 tracing::debug!(event_id = %event_id, "Fetching announcement");
 
                 let response = client
@@ -217,6 +240,64 @@ impl Actor {
         }
     }
 
+    /// Handle the pending attestations whose event expired without being attested.
+    ///
+    /// We keep fetching the attestation of an expired event, in case it shows up late. In the
+    /// meantime, we fetch the superseding announcements, so that the CFDs depending on the
+    /// expired event can be rolled over. If that does not happen within
+    /// [`EMERGENCY_COMMIT_AFTER`], the CFDs are committed instead.
+    fn handle_expired_attestations(&mut self, ctx: &mut xtra::Context<Self>) {
+        let now = OffsetDateTime::now_utc();
+
+        let newly_expired = self
+            .pending_attestations
+            .iter()
+            .copied()
+            .filter(|event_id| event_id.is_expired(now))
+            .filter(|event_id| !self.expired_attestations.contains(event_id))
+            .collect::<Vec<_>>();
+
+        for event_id in newly_expired {
+            tracing::warn!(%event_id, "Oracle event expired without attestation");
+
+            self.expired_attestations.insert(event_id);
+            self.ensure_having_announcements(event_id.contract_symbol(), ctx);
+        }
+
+        let due_for_commit = self
+            .expired_attestations
+            .iter()
+            .copied()
+            .filter(|event_id| event_id.is_expired(now - EMERGENCY_COMMIT_AFTER))
+            .filter(|event_id| !self.emergency_committed.contains(event_id))
+            .collect::<Vec<_>>();
+
+        if due_for_commit.is_empty() {
+            return;
+        }
+
+        self.emergency_committed.extend(due_for_commit);
+
+        let this = ctx.address().expect("self to be alive");
+        let db = self.db.clone();
+        let executor = self.executor.clone();
+        tokio_extras::spawn_fallible(
+            &this,
+            async move {
+                for id in db.load_open_cfd_ids().await? {
+                    if let Err(e) = executor.execute(id, |cfd| cfd.emergency_commit(now)).await {
+                        tracing::error!(order_id = %id, "Failed to commit CFD: {e:#}");
+                    }
+                }
+
+                anyhow::Ok(())
+            },
+            |e| async move {
+                tracing::warn!("Failed to commit CFDs with expired settlement event: {e:#}");
+            },
+        );
+    }
+
     fn update_pending_attestations(&mut self, ctx: &mut xtra::Context<Self>) {
         for event_id in self.pending_attestations.iter().copied() {
             if !event_id.has_likely_occurred() {
@@ -227,11 +308,32 @@ impl Actor {
 
             let this = ctx.address().expect("self to be alive");
             let client = self.client.clone();
+            let db = self.db.clone();
+            let url = event_id.to_url(&self.base_url);
 
             tokio_extras::spawn_fallible(
                 &this.clone(),
                 async move {
-                    let url = event_id.to_olivia_url();
+                    match db
+                        .load_cached_attestation(event_id, OffsetDateTime::now_utc())
+                        .await
+                    {
+                        Ok(Some(attestation)) => {
+                            tracing::debug!(%event_id, "Using cached attestation");
+
+                            this.send(NewAttestationFetched {
+                                id: event_id,
+                                attestation: Attestation(attestation),
+                            })
+                            .await??;
+
+                            return Ok(());
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            tracing::warn!(%event_id, "Failed to load cached attestation: {e:#}")
+                        }
+                    }
 
                     tracing::debug!(%event_id, "Fetching attestation");
 
@@ -267,6 +369,31 @@ impl Actor {
         }
     }
 
+    /// Restore the announcements cached by a previous run, so that they do not have to be
+    /// fetched from the oracle again.
+    async fn load_cache(&mut self) {
+        let now = OffsetDateTime::now_utc();
+
+        match self.db.delete_expired_oracle_cache(now).await {
+            Ok(deleted) => tracing::debug!("Deleted {deleted} expired oracle cache entries"),
+            Err(e) => tracing::warn!("Failed to delete expired oracle cache entries: {e:#}"),
+        }
+
+        match self.db.load_cached_announcements(now).await {
+            Ok(announcements) => {
+                tracing::debug!("Loaded {} cached announcements", announcements.len());
+
+                for announcement in announcements {
+                    self.announcements.insert(
+                        announcement.id,
+                        (announcement.expected_outcome_time, announcement.nonce_pks),
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("Failed to load cached announcements: {e:#}"),
+        }
+    }
+
     fn add_pending_attestation(&mut self, event_id: BitMexPriceEventId) {
         if !self.pending_attestations.insert(event_id) {
             tracing::trace!("Attestation for {event_id} already being monitored");
@@ -303,9 +430,26 @@ impl Actor {
         Ok(announcements)
     }
 
-    fn handle_new_announcement_fetched(&mut self, msg: NewAnnouncementFetched) {
-        self.announcements
-            .insert(msg.id, (msg.expected_outcome_time, msg.nonce_pks));
+    async fn handle_new_announcement_fetched(&mut self, msg: NewAnnouncementFetched) {
+        let announcement = olivia::Announcement {
+            id: msg.id,
+            expected_outcome_time: msg.expected_outcome_time,
+            nonce_pks: msg.nonce_pks,
+        };
+
+        // Once the outcome is attested, nobody will set up a contract based on the announcement.
+        if let Err(e) = self
+            .db
+            .insert_cached_announcement(&announcement, announcement.expected_outcome_time)
+            .await
+        {
+            tracing::warn!(event_id = %msg.id, "Failed to cache announcement: {e:#}");
+        }
+
+        self.announcements.insert(
+            announcement.id,
+            (announcement.expected_outcome_time, announcement.nonce_pks),
+        );
     }
 
     fn handle_sync_announcements(&mut self, _: SyncAnnouncements, ctx: &mut xtra::Context<Self>) {
@@ -316,6 +460,7 @@ impl Actor {
 
     fn handle_sync_attestations(&mut self, _: SyncAttestations, ctx: &mut xtra::Context<Self>) {
         self.update_pending_attestations(ctx);
+        self.handle_expired_attestations(ctx);
     }
 
     async fn handle_new_attestation_fetched(&mut self, msg: NewAttestationFetched) -> Result<()> {
@@ -323,6 +468,17 @@ impl Actor {
 
         tracing::info!("Fetched new attestation for {id}");
 
+        if let Err(e) = self
+            .db
+            .insert_cached_attestation(
+                attestation.as_inner(),
+                id.timestamp() + ATTESTATION_CACHE_RETENTION,
+            )
+            .await
+        {
+            tracing::warn!(event_id = %id, "Failed to cache attestation: {e:#}");
+        }
+
         for id in self.db.load_open_cfd_ids().await? {
             if let Err(err) = self
                 .executor
@@ -334,6 +490,8 @@ impl Actor {
         }
 
         self.pending_attestations.remove(&id);
+        self.expired_attestations.remove(&id);
+        self.emergency_committed.remove(&id);
 
         Ok(())
     }
@@ -347,6 +505,8 @@ pub struct NoAnnouncement(pub BitMexPriceEventId);
 impl xtra::Actor for Actor {
     type Stop = ();
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        self.load_cache().await;
+
         let this = ctx.address().expect("we are alive");
         tokio_extras::spawn(
             &this,
@@ -1,6 +1,80 @@
+use anyhow::bail;
+use anyhow::Result;
+use asynchronous_codec::Framed;
+use asynchronous_codec::JsonCodec;
+use asynchronous_codec::JsonCodecError;
+use futures::Stream;
+use xtra_libp2p::recorder::ensure_replay_finished;
+use xtra_libp2p::recorder::next_replayed;
+use xtra_libp2p::recorder::ReplayedSubstream;
+
 mod contract_setup;
 pub mod maker;
 mod protocol;
 pub mod taker;
 
 pub const PROTOCOL: &str = "/itchysats/order/2.0.0";
+
+/// Run the taker's handling of a recorded substream on the frames the
+/// maker sent.
+pub async fn replay_dialer(stream: ReplayedSubstream) -> Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        JsonCodec::<protocol::TakerMessage, protocol::MakerMessage>::new(),
+    );
+
+    let decision = match next_replayed(&mut framed).await? {
+        Some(protocol::MakerMessage::Decision(decision)) => decision,
+        Some(protocol::MakerMessage::ContractSetupMsg(_)) => bail!("Unexpected message"),
+        None => return Ok(()),
+    };
+
+    if let protocol::Decision::Accept = decision {
+        replay_contract_setup(&mut framed).await?;
+    }
+
+    ensure_replay_finished(&mut framed).await
+}
+
+/// Run the maker's handling of a recorded substream on the frames the
+/// taker sent.
+pub async fn replay_listener(stream: ReplayedSubstream) -> Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        JsonCodec::<protocol::MakerMessage, protocol::TakerMessage>::new(),
+    );
+
+    match next_replayed(&mut framed).await? {
+        Some(protocol::TakerMessage::PlaceOrder { .. }) => {}
+        Some(protocol::TakerMessage::ContractSetupMsg(_)) => bail!("Unexpected message"),
+        None => return Ok(()),
+    }
+
+    replay_contract_setup(&mut framed).await?;
+
+    ensure_replay_finished(&mut framed).await
+}
+
+/// Receive the messages of the contract setup in the order
+/// [`contract_setup::new`] expects them.
+async fn replay_contract_setup<S, M>(stream: &mut S) -> Result<()>
+where
+    S: Stream<Item = Result<M, JsonCodecError>> + Unpin,
+    protocol::SetupMsg: TryFrom<M, Error = anyhow::Error>,
+{
+    let steps: [fn(protocol::SetupMsg) -> Result<()>; 4] = [
+        |msg| msg.try_into_msg0().map(|_| ()),
+        |msg| msg.try_into_msg1().map(|_| ()),
+        |msg| msg.try_into_msg2().map(|_| ()),
+        |msg| msg.try_into_msg3().map(|_| ()),
+    ];
+
+    for step in steps {
+        match next_replayed(stream).await? {
+            Some(msg) => step(protocol::SetupMsg::try_from(msg)?)?,
+            None => return Ok(()),
+        }
+    }
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::cfd_keys::CfdKeys;
 use crate::command;
 use crate::oracle;
 use crate::oracle::NoAnnouncement;
@@ -8,8 +9,10 @@ use crate::order::current::protocol::SetupMsg;
 use crate::order::current::protocol::TakerMessage;
 use crate::process_manager;
 use crate::projection;
+use crate::projection::CfdState;
 use crate::wallet;
 use anyhow::anyhow;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -23,17 +26,27 @@ use futures::SinkExt;
 use futures::StreamExt;
 use maia_core::PartyParams;
 use model::olivia;
+use model::olivia::OracleKeys;
+use model::validate_quantity;
 use model::Cfd;
+use model::Contracts;
 use model::Identity;
 use model::OfferId;
 use model::OrderId;
+use model::RejectReason;
 use model::Role;
+use offer::OfferUnavailable;
+use rust_decimal::Decimal;
+use sqlite_db::taker_records::TakerRecord;
 use std::collections::HashMap;
 use std::fmt;
 use std::time::Duration;
+use tokio::sync::watch;
 use tokio_extras::FutureExt;
 use tracing::instrument;
+use tracing::Instrument;
 use xtra::prelude::MessageChannel;
+use xtra_libp2p::libp2p::PeerId;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_libp2p::Substream;
 use xtra_productivity::xtra_productivity;
@@ -41,9 +54,74 @@ use xtras::SendAsyncSafe;
 
 const ORDER_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// Limits on the takers the maker enters CFDs with.
+///
+/// Orders of takers exceeding any of the limits are rejected. Limits which
+/// are not set do not apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TakerLimits {
+    /// Reject takers which aborted this many contract setups
+    pub max_aborted_setups: Option<u32>,
+    /// Reject takers which failed this many rollovers
+    pub max_failed_rollovers: Option<u32>,
+    /// Reject orders which would take the open notional of the taker,
+    /// i.e. the quantity of all its CFDs which are not closed yet, above
+    /// this cap
+    pub max_open_notional: Option<Contracts>,
+}
+
+impl TakerLimits {
+    /// Whether any limit applies.
+    pub fn is_enabled(&self) -> bool {
+        self.max_aborted_setups.is_some()
+            || self.max_failed_rollovers.is_some()
+            || self.max_open_notional.is_some()
+    }
+
+    fn ensure_within(&self, record: &TakerRecord, open_notional: Decimal) -> Result<()> {
+        if let Some(max) = self.max_aborted_setups {
+            ensure!(
+                record.aborted_setups < max,
+                "Taker aborted {} contract setups",
+                record.aborted_setups
+            );
+        }
+        if let Some(max) = self.max_failed_rollovers {
+            ensure!(
+                record.failed_rollovers < max,
+                "Taker failed {} rollovers",
+                record.failed_rollovers
+            );
+        }
+        if let Some(max) = self.max_open_notional {
+            ensure!(
+                open_notional <= max.into_decimal(),
+                "Open notional of {open_notional} would exceed {max}"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The quantity of all CFDs with `peer_id` which are not closed yet.
+fn open_notional(cfds: &[projection::Cfd], peer_id: PeerId) -> Decimal {
+    cfds.iter()
+        .filter(|cfd| cfd.counterparty.inner() == peer_id)
+        .filter(|cfd| {
+            !matches!(
+                cfd.state,
+                CfdState::Rejected | CfdState::SetupFailed | CfdState::Closed | CfdState::Refunded
+            )
+        })
+        .map(|cfd| cfd.quantity.into_decimal())
+        .sum()
+}
+
 pub struct Actor {
     executor: command::Executor,
-    oracle_pk: XOnlyPublicKey,
+    oracle_keys: OracleKeys,
+    cfd_keys: CfdKeys,
     get_announcement:
         MessageChannel<oracle::GetAnnouncements, Result<Vec<olivia::Announcement>, NoAnnouncement>>,
     build_party_params: MessageChannel<wallet::BuildPartyParams, Result<PartyParams>>,
@@ -52,13 +130,16 @@ pub struct Actor {
     n_payouts: usize,
     decision_senders: HashMap<OrderId, oneshot::Sender<protocol::Decision>>,
     db: sqlite_db::Connection,
-    latest_offers: MessageChannel<offer::maker::GetLatestOffers, Vec<model::Offer>>,
+    offers: MessageChannel<offer::maker::GetOffer, Result<model::Offer, OfferUnavailable>>,
+    taker_limits: Option<(TakerLimits, watch::Receiver<Option<Vec<projection::Cfd>>>)>,
 }
 
 impl Actor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         n_payouts: usize,
-        oracle_pk: XOnlyPublicKey,
+        oracle_keys: OracleKeys,
+        cfd_keys: CfdKeys,
         get_announcement: MessageChannel<
             oracle::GetAnnouncements,
             Result<Vec<olivia::Announcement>, NoAnnouncement>,
@@ -69,11 +150,12 @@ impl Actor {
             MessageChannel<wallet::Sign, Result<PartiallySignedTransaction>>,
         ),
         projection: xtra::Address<projection::Actor>,
-        latest_offers: MessageChannel<offer::maker::GetLatestOffers, Vec<model::Offer>>,
+        offers: MessageChannel<offer::maker::GetOffer, Result<model::Offer, OfferUnavailable>>,
     ) -> Self {
         Self {
-            executor: command::Executor::new(db.clone(), process_manager),
-            oracle_pk,
+            executor: command::Executor::new(db.clone(), process_manager.into()),
+            oracle_keys,
+            cfd_keys,
             get_announcement,
             build_party_params,
             sign,
@@ -81,10 +163,23 @@ impl Actor {
             n_payouts,
             decision_senders: HashMap::default(),
             db,
-            latest_offers,
+            offers,
+            taker_limits: None,
         }
     }
 
+    /// Reject orders of takers which exceed `limits`, judging their open
+    /// notional by the given `cfds`.
+    #[must_use]
+    pub fn with_taker_limits(
+        mut self,
+        limits: TakerLimits,
+        cfds: watch::Receiver<Option<Vec<projection::Cfd>>>,
+    ) -> Self {
+        self.taker_limits = Some((limits, cfds));
+        self
+    }
+
     #[instrument(skip(self), err)]
     async fn receive_order(
         &mut self,
@@ -102,21 +197,70 @@ impl Actor {
     }
 
     #[instrument(skip(self))]
-    async fn pick_offer(&self, offer_id: OfferId) -> Result<model::Offer> {
-        let latest_offers = self
-            .latest_offers
-            .send(offer::maker::GetLatestOffers)
+    async fn pick_offer(&self, offer_id: OfferId, peer_id: PeerId) -> Result<model::Offer> {
+        let offer = self
+            .offers
+            .send(offer::maker::GetOffer { offer_id, peer_id })
             .await
-            .context("Failed to retrieve latest offer from offers actor")?;
-
-        let offer = latest_offers
-            .iter()
-            .find(|offer| offer.id == offer_id)
-            .with_context(|| format!("Offer with id {offer_id} not found in current offers"))?
-            .clone();
+            .context("Failed to retrieve offer from offers actor")??;
 
         Ok(offer)
     }
+
+    /// Ensure that the taker uses the same oracle key for the event of the offer as we do.
+    ///
+    /// Takers which predate oracle key rotation do not tell us which key they use.
+    fn ensure_same_oracle_pk(
+        &self,
+        offer: &model::Offer,
+        taker_oracle_pk: Option<XOnlyPublicKey>,
+    ) -> Result<()> {
+        let oracle_pk = self.oracle_keys.for_event(offer.oracle_event_id);
+
+        if let Some(taker_oracle_pk) = taker_oracle_pk {
+            ensure!(
+                taker_oracle_pk == oracle_pk,
+                "Taker uses oracle key {taker_oracle_pk} for event {} instead of {oracle_pk}",
+                offer.oracle_event_id
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Ensure that the taker orders a quantity which can be entered for the offer.
+    fn ensure_quantity_within_bounds(offer: &model::Offer, quantity: Contracts) -> Result<()> {
+        validate_quantity(
+            quantity,
+            offer.min_quantity,
+            offer.max_quantity,
+            offer.lot_size,
+        )
+        .context(RejectReason::QuantityOutOfBounds)?;
+
+        Ok(())
+    }
+
+    /// Ensure that the taker does not exceed our limits if we accept its order for `quantity`.
+    async fn ensure_within_taker_limits(&self, peer_id: PeerId, quantity: Contracts) -> Result<()> {
+        let (limits, cfds) = match &self.taker_limits {
+            Some(taker_limits) => taker_limits,
+            None => return Ok(()),
+        };
+
+        let record = self.db.load_taker_record(peer_id.into()).await?;
+        let open_notional = cfds
+            .borrow()
+            .as_deref()
+            .map(|cfds| open_notional(cfds, peer_id))
+            .unwrap_or_default();
+
+        limits
+            .ensure_within(&record, open_notional + quantity.into_decimal())
+            .context(RejectReason::TakerLimitExceeded)?;
+
+        Ok(())
+    }
 }
 
 #[xtra_productivity]
@@ -134,13 +278,14 @@ impl Actor {
             }
         };
 
-        let (order_id, offer_id, quantity, leverage) = match order {
+        let (order_id, offer_id, quantity, leverage, taker_oracle_pk) = match order {
             TakerMessage::PlaceOrder {
                 id,
                 offer,
                 quantity,
                 leverage,
-            } => (id, offer.id, quantity, leverage),
+                oracle_pk,
+            } => (id, offer.id, quantity, leverage, oracle_pk),
             TakerMessage::ContractSetupMsg(_) => {
                 tracing::error!("Unexpected message");
                 return;
@@ -149,19 +294,35 @@ impl Actor {
 
         tracing::info!(%peer_id, %quantity, %order_id, %offer_id, "Taker wants to place an order");
 
-        // Reject the order if the offer cannot be found in the latest offers
-        let offer = match self.pick_offer(offer_id).await {
+        // Reject the order if the offer cannot be found in the latest offers, if we disagree on
+        // the oracle key, if the quantity does not fit the offer or if the taker exceeds our limits
+        let offer = match async {
+            let offer = self.pick_offer(offer_id, peer_id).await?;
+            self.ensure_same_oracle_pk(&offer, taker_oracle_pk)?;
+            Self::ensure_quantity_within_bounds(&offer, quantity)?;
+            self.ensure_within_taker_limits(peer_id, quantity).await?;
+
+            anyhow::Ok(offer)
+        }
+        .await
+        {
             Ok(offer) => offer,
             Err(e) => {
-                tracing::warn!(
-                    %peer_id,
-                    "Rejecting taker order because unable to pick offer: {e:#}"
-                );
+                tracing::warn!(%peer_id, "Rejecting taker order: {e:#}");
+
+                let decision = match (
+                    e.downcast_ref::<OfferUnavailable>(),
+                    e.downcast_ref::<RejectReason>(),
+                ) {
+                    (Some(unavailable), _) => {
+                        protocol::Decision::RejectOfferUnavailable(*unavailable)
+                    }
+                    (None, Some(reason)) => protocol::Decision::RejectWithReason(*reason),
+                    (None, None) => protocol::Decision::Reject,
+                };
 
                 let future = async move {
-                    framed
-                        .send(MakerMessage::Decision(protocol::Decision::Reject))
-                        .await?;
+                    framed.send(MakerMessage::Decision(decision)).await?;
 
                     anyhow::Ok(())
                 };
@@ -179,6 +340,7 @@ impl Actor {
         };
 
         let oracle_event_id = offer.oracle_event_id;
+        let oracle_pk = self.oracle_keys.for_event(oracle_event_id);
 
         let cfd = Cfd::from_order(
             order_id,
@@ -216,7 +378,8 @@ impl Actor {
             let sign = self.sign.clone();
             let get_announcement = self.get_announcement.clone();
             let executor = self.executor.clone();
-            let oracle_pk = self.oracle_pk;
+            let db = self.db.clone();
+            let cfd_keys = self.cfd_keys;
             let n_payouts = self.n_payouts;
             async move {
                 match receiver.await? {
@@ -227,17 +390,22 @@ impl Actor {
 
                         tracing::info!(%peer_id, %quantity, %order_id, "Order accepted");
                     }
-                    protocol::Decision::Reject => {
-                        framed
-                            .send(MakerMessage::Decision(protocol::Decision::Reject))
-                            .await?;
+                    // Only we reject orders because of unavailable offers, before asking for a
+                    // decision
+                    decision @ (protocol::Decision::Reject
+                    | protocol::Decision::RejectOfferUnavailable(_)
+                    | protocol::Decision::RejectWithReason(_)) => {
+                        let reason = match decision {
+                            protocol::Decision::RejectWithReason(reason) => Some(reason),
+                            _ => None,
+                        };
 
-                        tracing::info!(%peer_id, %quantity, %order_id, "Order rejected");
+                        framed.send(MakerMessage::Decision(decision)).await?;
+
+                        tracing::info!(%peer_id, %quantity, %order_id, ?reason, "Order rejected");
 
                         executor
-                            .execute(order_id, |cfd| {
-                                cfd.reject_contract_setup(anyhow::anyhow!("Unknown"))
-                            })
+                            .execute(order_id, |cfd| cfd.reject_contract_setup(reason))
                             .await?;
 
                         return anyhow::Ok(());
@@ -254,6 +422,8 @@ impl Actor {
                     .send(oracle::GetAnnouncements(vec![oracle_event_id]))
                     .await??;
 
+                let keys = cfd_keys.derive_for_setup(&db, order_id).await?;
+
                 let dlc = contract_setup::new(
                     sink.with(|msg| future::ok(MakerMessage::ContractSetupMsg(Box::new(msg)))),
                     Box::pin(stream.filter_map(|msg| async move {
@@ -276,6 +446,7 @@ impl Actor {
                     .fuse(),
                     (oracle_pk, announcement),
                     setup_params,
+                    keys,
                     build_party_params,
                     sign,
                     Role::Maker,
@@ -307,6 +478,10 @@ impl Actor {
             }
         };
 
+        let span = tracing::info_span!("Contract setup", %order_id, %offer_id, %peer_id);
+        let task = task.instrument(span.clone());
+        let err_handler = move |e| err_handler(e).instrument(span);
+
         let address = ctx.address().expect("we are alive");
         tokio_extras::spawn_fallible(&address, task, err_handler);
     }
@@ -332,13 +507,13 @@ impl Actor {
 #[derive(Clone, Copy)]
 pub enum Decision {
     Accept(OrderId),
-    Reject(OrderId),
+    Reject(OrderId, Option<RejectReason>),
 }
 
 impl Decision {
     fn id(&self) -> OrderId {
         match self {
-            Decision::Accept(id) | Decision::Reject(id) => *id,
+            Decision::Accept(id) | Decision::Reject(id, _) => *id,
         }
     }
 }
@@ -347,7 +522,8 @@ impl From<Decision> for protocol::Decision {
     fn from(decision: Decision) -> Self {
         match decision {
             Decision::Accept(_) => protocol::Decision::Accept,
-            Decision::Reject(_) => protocol::Decision::Reject,
+            Decision::Reject(_, None) => protocol::Decision::Reject,
+            Decision::Reject(_, Some(reason)) => protocol::Decision::RejectWithReason(reason),
         }
     }
 }
@@ -356,7 +532,7 @@ impl fmt::Display for Decision {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             Decision::Accept(_) => "Accept",
-            Decision::Reject(_) => "Reject",
+            Decision::Reject(..) => "Reject",
         };
 
         s.fmt(f)
@@ -369,3 +545,35 @@ impl xtra::Actor for Actor {
 
     async fn stopped(self) -> Self::Stop {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn taker_within_limits_until_one_is_reached() {
+        let limits = TakerLimits {
+            max_aborted_setups: Some(2),
+            max_failed_rollovers: None,
+            max_open_notional: Some(Contracts::new(1_000)),
+        };
+        let record = TakerRecord {
+            completed_cfds: 0,
+            aborted_setups: 1,
+            failed_rollovers: 10,
+        };
+
+        assert!(limits.ensure_within(&record, dec!(1_000)).is_ok());
+        assert!(limits.ensure_within(&record, dec!(1_001)).is_err());
+        assert!(limits
+            .ensure_within(
+                &TakerRecord {
+                    aborted_setups: 2,
+                    ..record
+                },
+                dec!(0)
+            )
+            .is_err());
+    }
+}
@@ -5,6 +5,7 @@ use bdk::bitcoin::secp256k1::ecdsa::Signature;
 use bdk::bitcoin::Address;
 use bdk::bitcoin::Amount;
 use bdk::bitcoin::PublicKey;
+use bdk::bitcoin::XOnlyPublicKey;
 use maia_core::secp256k1_zkp::EcdsaAdaptorSignature;
 use maia_core::CfdTransactions;
 use maia_core::PartyParams;
@@ -25,6 +26,11 @@ pub(crate) enum TakerMessage {
         offer: Offer,
         quantity: Contracts,
         leverage: Leverage,
+        /// The oracle key the taker uses for the event of the offer.
+        ///
+        /// Not sent by takers which predate oracle key rotation.
+        #[serde(default)]
+        oracle_pk: Option<XOnlyPublicKey>,
     },
     ContractSetupMsg(Box<SetupMsg>),
 }
@@ -48,6 +54,16 @@ pub(crate) enum MakerMessage {
 pub(crate) enum Decision {
     Accept,
     Reject,
+    /// The order references an offer which can no longer be taken.
+    ///
+    /// Takers which predate offer expiry fail to decode this and fail
+    /// the order instead.
+    RejectOfferUnavailable(offer::OfferUnavailable),
+    /// The maker rejected the order for the given reason.
+    ///
+    /// Takers which predate rejection reasons fail to decode this and
+    /// fail the order instead.
+    RejectWithReason(model::RejectReason),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
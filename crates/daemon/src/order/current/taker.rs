@@ -1,3 +1,4 @@
+use crate::cfd_keys::CfdKeys;
 use crate::command;
 use crate::oracle;
 use crate::oracle::NoAnnouncement;
@@ -11,6 +12,7 @@ use crate::order::current::PROTOCOL;
 use crate::process_manager;
 use crate::projection;
 use crate::wallet;
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
@@ -18,22 +20,27 @@ use async_trait::async_trait;
 use asynchronous_codec::Framed;
 use asynchronous_codec::JsonCodec;
 use bdk::bitcoin::psbt::PartiallySignedTransaction;
-use bdk::bitcoin::XOnlyPublicKey;
 use futures::future;
 use futures::SinkExt;
 use futures::StreamExt;
 use libp2p_core::PeerId;
 use maia_core::PartyParams;
 use model::olivia;
+use model::olivia::OracleKeys;
 use model::Cfd;
 use model::Contracts;
 use model::Identity;
 use model::Leverage;
 use model::Offer;
 use model::OrderId;
+use model::RejectReason;
 use model::Role;
+use model::Timestamp;
 use std::time::Duration;
+use tokio_extras::CancellableTasks;
+use tokio_extras::CommitToken;
 use tokio_extras::FutureExt;
+use tracing::Instrument;
 use xtra::prelude::MessageChannel;
 use xtra_libp2p::Endpoint;
 use xtra_libp2p::OpenSubstream;
@@ -45,7 +52,8 @@ const PLACE_ORDER_RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
 pub struct Actor {
     endpoint: xtra::Address<Endpoint>,
     executor: command::Executor,
-    oracle_pk: XOnlyPublicKey,
+    oracle_keys: OracleKeys,
+    cfd_keys: CfdKeys,
     get_announcement:
         MessageChannel<oracle::GetAnnouncements, Result<Vec<olivia::Announcement>, NoAnnouncement>>,
     build_party_params: MessageChannel<wallet::BuildPartyParams, Result<PartyParams>>,
@@ -53,12 +61,15 @@ pub struct Actor {
     projection: xtra::Address<projection::Actor>,
     n_payouts: usize,
     db: sqlite_db::Connection,
+    pending_orders: CancellableTasks<OrderId, Timestamp>,
 }
 
 impl Actor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         n_payouts: usize,
-        oracle_pk: XOnlyPublicKey,
+        oracle_keys: OracleKeys,
+        cfd_keys: CfdKeys,
         get_announcement: MessageChannel<
             oracle::GetAnnouncements,
             Result<Vec<olivia::Announcement>, NoAnnouncement>,
@@ -73,22 +84,30 @@ impl Actor {
     ) -> Self {
         Self {
             endpoint,
-            executor: command::Executor::new(db.clone(), process_manager),
-            oracle_pk,
+            executor: command::Executor::new(db.clone(), process_manager.into()),
+            oracle_keys,
+            cfd_keys,
             get_announcement,
             build_party_params,
             sign,
             projection,
             n_payouts,
             db,
+            pending_orders: CancellableTasks::default(),
         }
     }
 }
 
 #[xtra_productivity]
 impl Actor {
-    pub async fn handle(&mut self, msg: PlaceOrder, ctx: &mut xtra::Context<Self>) {
+    pub async fn handle(&mut self, msg: PlaceOrder) {
         let id = msg.order_id;
+        let span = tracing::info_span!(
+            "Contract setup",
+            order_id = %id,
+            offer_id = %msg.offer.id,
+            peer_id = %msg.maker_peer_id,
+        );
 
         let task = {
             let build_party_params = self.build_party_params.clone();
@@ -97,10 +116,11 @@ impl Actor {
             let endpoint = self.endpoint.clone();
             let executor = self.executor.clone();
             let db = self.db.clone();
-            let oracle_pk = self.oracle_pk;
+            let oracle_keys = self.oracle_keys;
+            let cfd_keys = self.cfd_keys;
             let n_payouts = self.n_payouts;
             let projection = self.projection.clone();
-            async move {
+            move |token: CommitToken| async move {
                 tracing::info!(order = ?msg, "Placing order");
 
                 let PlaceOrder {
@@ -113,6 +133,7 @@ impl Actor {
                 } = msg;
 
                 let oracle_event_id = offer.oracle_event_id;
+                let oracle_pk = oracle_keys.for_event(oracle_event_id);
                 let cfd = Cfd::from_order(
                     order_id,
                     &offer,
@@ -149,10 +170,11 @@ impl Actor {
                         offer: protocol::Offer { id: offer.id },
                         quantity,
                         leverage,
+                        oracle_pk: Some(oracle_pk),
                     })
                     .await?;
 
-                match framed
+                let response = framed
                     .next()
                     .timeout(PLACE_ORDER_RESPONSE_TIMEOUT, || {
                         tracing::debug_span!("receive make response")
@@ -164,22 +186,47 @@ impl Actor {
                             PLACE_ORDER_RESPONSE_TIMEOUT.as_secs()
                         )
                     })?
-                    .context("Stream terminated")??
-                {
+                    .context("Stream terminated")??;
+
+                // Once the maker has decided, the order can no longer be cancelled
+                if token.commit().is_err() {
+                    tracing::debug!(%order_id, "Order was cancelled before the maker decided");
+                    return anyhow::Ok(());
+                }
+
+                match response {
                     MakerMessage::Decision(Decision::Accept) => {
                         tracing::info!(order_id = %msg.order_id, %maker_peer_id, "Order accepted");
                     }
                     MakerMessage::Decision(Decision::Reject) => {
                         tracing::info!(order_id = %msg.order_id, %maker_peer_id, "Order rejected");
 
+                        executor
+                            .execute(order_id, |cfd| cfd.reject_contract_setup(None))
+                            .await?;
+
+                        return anyhow::Ok(());
+                    }
+                    MakerMessage::Decision(Decision::RejectOfferUnavailable(unavailable)) => {
+                        tracing::info!(order_id = %msg.order_id, %maker_peer_id, %unavailable, "Order rejected");
+
                         executor
                             .execute(order_id, |cfd| {
-                                cfd.reject_contract_setup(anyhow::anyhow!("Unknown"))
+                                cfd.reject_contract_setup(Some(RejectReason::OfferOutdated))
                             })
                             .await?;
 
                         return anyhow::Ok(());
                     }
+                    MakerMessage::Decision(Decision::RejectWithReason(reason)) => {
+                        tracing::info!(order_id = %msg.order_id, %maker_peer_id, %reason, "Order rejected");
+
+                        executor
+                            .execute(order_id, |cfd| cfd.reject_contract_setup(Some(reason)))
+                            .await?;
+
+                        return anyhow::Ok(());
+                    }
                     MakerMessage::ContractSetupMsg(_) => bail!("Unexpected message"),
                 };
 
@@ -193,6 +240,8 @@ impl Actor {
                     .send(oracle::GetAnnouncements(vec![oracle_event_id]))
                     .await??;
 
+                let keys = cfd_keys.derive_for_setup(&db, order_id).await?;
+
                 let dlc = contract_setup::new(
                     sink.with(|msg| future::ok(TakerMessage::ContractSetupMsg(Box::new(msg)))),
                     Box::pin(stream.filter_map(|msg| async move {
@@ -215,6 +264,7 @@ impl Actor {
                     .fuse(),
                     (oracle_pk, announcement),
                     setup_params,
+                    keys,
                     build_party_params,
                     sign,
                     Role::Taker,
@@ -246,11 +296,57 @@ impl Actor {
             }
         };
 
-        let address = ctx.address().expect("we are alive");
-        tokio_extras::spawn_fallible(&address, task, err_handler);
+        let task = {
+            let span = span.clone();
+            move |token| task(token).instrument(span)
+        };
+        let err_handler = move |e| err_handler(e).instrument(span);
+
+        if let Err(e) = self
+            .pending_orders
+            .add_fallible(id, Timestamp::now(), task, err_handler)
+        {
+            tracing::error!(%id, "Failed to start contract setup: {e:#}");
+        }
+    }
+
+    pub async fn handle(&mut self, _: GetPendingOrders) -> Vec<(OrderId, Timestamp)> {
+        self.pending_orders
+            .cancellable()
+            .map(|(order_id, since)| (*order_id, *since))
+            .collect()
+    }
+
+    pub async fn handle(&mut self, msg: CancelOrder) -> Result<()> {
+        let CancelOrder { order_id } = msg;
+
+        self.pending_orders
+            .cancel(&order_id)
+            .with_context(|| format!("No order {order_id} awaiting the maker's decision"))?;
+
+        tracing::info!(%order_id, "Cancelled order");
+
+        self.executor
+            .execute(order_id, |cfd| {
+                Ok(cfd.fail_contract_setup(anyhow!("Order cancelled by user")))
+            })
+            .await?;
+
+        Ok(())
     }
 }
 
+/// List the orders which are still awaiting the maker's decision,
+/// together with the time they were placed.
+#[derive(Clone, Copy)]
+pub struct GetPendingOrders;
+
+/// Cancel an order which is still awaiting the maker's decision.
+#[derive(Clone, Copy)]
+pub struct CancelOrder {
+    pub order_id: OrderId,
+}
+
 #[derive(Debug)]
 pub(crate) struct PlaceOrder {
     order_id: OrderId,
@@ -1,5 +1,6 @@
 use crate::bitcoin::secp256k1::SecretKey;
 use crate::bitcoin::PublicKey;
+use crate::cfd_keys::SetupKeys;
 use crate::order::deprecated::protocol::Msg0;
 use crate::order::deprecated::protocol::Msg1;
 use crate::order::deprecated::protocol::Msg2;
@@ -13,7 +14,6 @@ use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
 use bdk::bitcoin::Amount;
 use bdk::bitcoin::Transaction;
 use bdk::miniscript::Descriptor;
-use bdk_ext::keypair;
 use futures::Sink;
 use futures::SinkExt;
 use futures::Stream;
@@ -73,6 +73,7 @@ pub async fn new(
     mut stream: impl Stream<Item = SetupMsg> + Unpin,
     (oracle_pk, announcements): (XOnlyPublicKey, Vec<olivia::Announcement>),
     setup_params: SetupParams,
+    keys: SetupKeys,
     build_party_params_channel: MessageChannel<wallet::BuildPartyParams, Result<PartyParams>>,
     sign_channel: MessageChannel<wallet::Sign, Result<PartiallySignedTransaction>>,
     own_role: Role,
@@ -83,7 +84,7 @@ pub async fn new(
     tracing::trace!(?oracle_pk, ?announcements);
 
     let (own, own_punish, key_pairs) =
-        own_setup_params(build_party_params_channel, setup_params).await?;
+        own_setup_params(build_party_params_channel, setup_params, keys).await?;
 
     sink.send(SetupMsg::Msg0(Msg0::from((own.clone(), own_punish))))
         .instrument(tracing::debug_span!("Send Msg0"))
@@ -262,11 +263,12 @@ struct KeyPairs {
 async fn own_setup_params(
     build_party_params_channel: MessageChannel<wallet::BuildPartyParams, Result<PartyParams>>,
     setup_params: SetupParams,
+    keys: SetupKeys,
 ) -> Result<(PartyParams, PunishParams, KeyPairs)> {
     let key_pairs = KeyPairs {
-        identity: keypair::new(&mut rand::thread_rng()).into(),
-        revoke: keypair::new(&mut rand::thread_rng()).into(),
-        publish: keypair::new(&mut rand::thread_rng()).into(),
+        identity: keys.identity.into(),
+        revoke: keys.revocation.into(),
+        publish: keys.publish.into(),
     };
 
     let own = build_party_params_channel
@@ -1,3 +1,4 @@
+use crate::cfd_keys::CfdKeys;
 use crate::command;
 use crate::oracle;
 use crate::oracle::NoAnnouncement;
@@ -16,13 +17,13 @@ use async_trait::async_trait;
 use asynchronous_codec::Framed;
 use asynchronous_codec::JsonCodec;
 use bdk::bitcoin::psbt::PartiallySignedTransaction;
-use bdk::bitcoin::XOnlyPublicKey;
 use futures::channel::oneshot;
 use futures::future;
 use futures::SinkExt;
 use futures::StreamExt;
 use maia_core::PartyParams;
 use model::olivia;
+use model::olivia::OracleKeys;
 use model::Cfd;
 use model::Identity;
 use model::OfferId;
@@ -33,6 +34,7 @@ use std::fmt;
 use std::time::Duration;
 use tokio_extras::FutureExt;
 use tracing::instrument;
+use tracing::Instrument;
 use xtra::prelude::MessageChannel;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_libp2p::Substream;
@@ -43,7 +45,8 @@ const ORDER_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct Actor {
     executor: command::Executor,
-    oracle_pk: XOnlyPublicKey,
+    oracle_keys: OracleKeys,
+    cfd_keys: CfdKeys,
     get_announcement:
         MessageChannel<oracle::GetAnnouncements, Result<Vec<olivia::Announcement>, NoAnnouncement>>,
     build_party_params: MessageChannel<wallet::BuildPartyParams, Result<PartyParams>>,
@@ -56,9 +59,11 @@ pub struct Actor {
 }
 
 impl Actor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         n_payouts: usize,
-        oracle_pk: XOnlyPublicKey,
+        oracle_keys: OracleKeys,
+        cfd_keys: CfdKeys,
         get_announcement: MessageChannel<
             oracle::GetAnnouncements,
             Result<Vec<olivia::Announcement>, NoAnnouncement>,
@@ -72,8 +77,9 @@ impl Actor {
         latest_offers: MessageChannel<offer::maker::GetLatestOffers, Vec<model::Offer>>,
     ) -> Self {
         Self {
-            executor: command::Executor::new(db.clone(), process_manager),
-            oracle_pk,
+            executor: command::Executor::new(db.clone(), process_manager.into()),
+            oracle_keys,
+            cfd_keys,
             get_announcement,
             build_party_params,
             sign,
@@ -176,6 +182,7 @@ impl Actor {
         };
 
         let oracle_event_id = offer.oracle_event_id;
+        let oracle_pk = self.oracle_keys.for_event(oracle_event_id);
 
         let cfd = Cfd::from_order(
             order_id,
@@ -213,7 +220,8 @@ impl Actor {
             let sign = self.sign.clone();
             let get_announcement = self.get_announcement.clone();
             let executor = self.executor.clone();
-            let oracle_pk = self.oracle_pk;
+            let db = self.db.clone();
+            let cfd_keys = self.cfd_keys;
             let n_payouts = self.n_payouts;
             async move {
                 match receiver.await? {
@@ -232,9 +240,7 @@ impl Actor {
                         tracing::info!(%peer_id, %quantity, %order_id, "Order rejected");
 
                         executor
-                            .execute(order_id, |cfd| {
-                                cfd.reject_contract_setup(anyhow::anyhow!("Unknown"))
-                            })
+                            .execute(order_id, |cfd| cfd.reject_contract_setup(None))
                             .await?;
 
                         return anyhow::Ok(());
@@ -251,6 +257,8 @@ impl Actor {
                     .send(oracle::GetAnnouncements(vec![oracle_event_id]))
                     .await??;
 
+                let keys = cfd_keys.derive_for_setup(&db, order_id).await?;
+
                 let dlc = contract_setup::new(
                     sink.with(|msg| future::ok(MakerMessage::ContractSetupMsg(Box::new(msg)))),
                     Box::pin(stream.filter_map(|msg| async move {
@@ -273,6 +281,7 @@ impl Actor {
                     .fuse(),
                     (oracle_pk, announcement),
                     setup_params,
+                    keys,
                     build_party_params,
                     sign,
                     Role::Maker,
@@ -304,6 +313,10 @@ impl Actor {
             }
         };
 
+        let span = tracing::info_span!("Contract setup", %order_id, %offer_id, %peer_id);
+        let task = task.instrument(span.clone());
+        let err_handler = move |e| err_handler(e).instrument(span);
+
         let address = ctx.address().expect("we are alive");
         tokio_extras::spawn_fallible(&address, task, err_handler);
     }
@@ -0,0 +1,38 @@
+//! Outgoing protocol requests which are still awaiting the maker's
+//! decision.
+//!
+//! Until the maker has decided, such a request can be cancelled by
+//! the user. Afterwards the protocol runs to completion.
+
+use model::OrderId;
+use model::Timestamp;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingRequestKind {
+    Order,
+    Settlement,
+    Rollover,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PendingRequest {
+    pub order_id: OrderId,
+    pub kind: PendingRequestKind,
+    /// When the request was sent to the maker.
+    pub since: Timestamp,
+}
+
+impl PendingRequest {
+    pub(crate) fn from_protocol(
+        kind: PendingRequestKind,
+        requests: Vec<(OrderId, Timestamp)>,
+    ) -> impl Iterator<Item = Self> {
+        requests.into_iter().map(move |(order_id, since)| Self {
+            order_id,
+            kind,
+            since,
+        })
+    }
+}
@@ -16,6 +16,7 @@ use model::OrderId;
 use model::Position;
 use model::Role;
 use model::Settlement;
+use rust_decimal::Decimal;
 use sqlite_db;
 use std::collections::HashMap;
 use strum::IntoEnumIterator;
@@ -88,6 +89,26 @@ impl Actor {
             metrics::update_position_metrics(&self.state.cfds, symbol)
         }
     }
+
+    fn handle(&mut self, msg: GetExposure) -> Exposure {
+        let GetExposure(contract_symbol) = msg;
+
+        self.state
+            .cfds
+            .values()
+            .filter(|cfd| cfd.contract_symbol == contract_symbol)
+            .filter(|cfd| matches!(cfd.state, AggregatedState::New | AggregatedState::Open))
+            .fold(Exposure::default(), |exposure, cfd| match cfd.position {
+                Position::Long => Exposure {
+                    long: exposure.long + cfd.quantity,
+                    ..exposure
+                },
+                Position::Short => Exposure {
+                    short: exposure.short + cfd.quantity,
+                    ..exposure
+                },
+            })
+    }
 }
 
 impl State {
@@ -106,6 +127,34 @@ struct Initialize;
 #[derive(Clone, Copy)]
 pub struct CfdChanged(pub OrderId);
 
+/// Query our exposure in a contract symbol.
+#[derive(Clone, Copy)]
+pub struct GetExposure(pub ContractSymbol);
+
+/// Total quantity of our CFDs which are open or being set up, per
+/// position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exposure {
+    pub long: Contracts,
+    pub short: Contracts,
+}
+
+impl Exposure {
+    /// Long minus short quantity.
+    pub fn net(&self) -> Decimal {
+        self.long.into_decimal() - self.short.into_decimal()
+    }
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Self {
+            long: Contracts::ZERO,
+            short: Contracts::ZERO,
+        }
+    }
+}
+
 /// Read-model of the CFD for the position metrics actor.
 #[derive(Clone, Copy)]
 pub struct Cfd {
@@ -194,7 +243,7 @@ impl Cfd {
                 state: AggregatedState::Failed,
                 ..self
             },
-            OfferRejected => Self {
+            OfferRejected(_) => Self {
                 state: AggregatedState::Rejected,
                 ..self
             },
@@ -209,7 +258,10 @@ impl Cfd {
             CollaborativeSettlementStarted { .. }
             | CollaborativeSettlementProposalAccepted
             | CollaborativeSettlementRejected
-            | CollaborativeSettlementFailed => Self {
+            | CollaborativeSettlementFailed
+            | CollaborativeSettlementAbandoned { .. }
+            | CollaborativeSettlementProposalExpired
+            | PriceTriggered { .. } => Self {
                 // should still be open
                 ..self
             },
@@ -0,0 +1,121 @@
+//! Preferences of the user, shared by all frontends attached to the
+//! daemon.
+//!
+//! Every preference is stored as JSON under its own key. Preferences
+//! which were never saved take their default value and keys which are
+//! no longer known are ignored, so the schema can evolve without a
+//! migration.
+
+use crate::fiat_rates::Currency;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use model::Leverage;
+use model::RolloverPolicy;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// The fiat currency to display amounts in, in addition to bitcoin
+    pub display_currency: Option<Currency>,
+    /// The leverage preselected when placing an order
+    pub default_leverage: Option<Leverage>,
+    pub notifications: Notifications,
+    /// The rollover policy of CFDs without a policy of their own
+    pub default_rollover_policy: RolloverPolicy,
+}
+
+/// What frontends notify the user about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Notifications {
+    /// Changes of the state of a CFD, e.g. once it is open or closed
+    pub cfd_state_changes: bool,
+    /// The connection to the maker being lost or restored
+    pub maker_connection: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            cfd_state_changes: true,
+            maker_connection: true,
+        }
+    }
+}
+
+pub async fn load(db: &sqlite_db::Connection) -> Result<Preferences> {
+    let mut fields = serde_json::Map::new();
+    for (key, value) in db.load_preferences().await? {
+        let value = serde_json::from_str(&value)
+            .with_context(|| format!("Invalid value of preference {key}"))?;
+        fields.insert(key, value);
+    }
+
+    let preferences =
+        serde_json::from_value(Value::Object(fields)).context("Invalid preferences")?;
+
+    Ok(preferences)
+}
+
+pub async fn save(db: &sqlite_db::Connection, preferences: &Preferences) -> Result<()> {
+    let fields = match serde_json::to_value(preferences)? {
+        Value::Object(fields) => fields,
+        other => bail!("Preferences serialized to {other} instead of an object"),
+    };
+
+    db.insert_preferences(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key, value.to_string()))
+            .collect(),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn preferences_which_were_never_saved_take_their_default() {
+        let db = sqlite_db::memory().await.unwrap();
+        db.insert_preferences(vec![
+            ("default_leverage".to_string(), "2".to_string()),
+            ("removed_preference".to_string(), "true".to_string()),
+        ])
+        .await
+        .unwrap();
+
+        let preferences = load(&db).await.unwrap();
+
+        assert_eq!(
+            preferences,
+            Preferences {
+                default_leverage: Some(Leverage::TWO),
+                ..Preferences::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn saved_preferences_are_loaded() {
+        let db = sqlite_db::memory().await.unwrap();
+        let preferences = Preferences {
+            display_currency: Some(Currency::Eur),
+            default_leverage: None,
+            notifications: Notifications {
+                cfd_state_changes: false,
+                maker_connection: true,
+            },
+            default_rollover_policy: RolloverPolicy::Never,
+        };
+
+        save(&db, &preferences).await.unwrap();
+
+        assert_eq!(load(&db).await.unwrap(), preferences);
+    }
+}
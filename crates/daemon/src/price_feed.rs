@@ -0,0 +1,749 @@
+//! A price feed which does not depend on a single exchange.
+//!
+//! The [`Aggregator`] serves the same [`GetLatestQuotes`] interface as
+//! the BitMEX price feed, but asks any number of sources for their
+//! latest quotes and combines the fresh ones according to a [`Policy`].
+//! Besides BitMEX, an [`ExchangeFeed`] can poll the public ticker of
+//! Kraken, Coinbase or Deribit, so that an outage of BitMEX does not
+//! keep the daemon from pricing settlements.
+//!
+//! The aggregator also keeps a history of the combined quotes over a
+//! configurable window, from which it serves time-weighted average
+//! prices ([`GetTwap`]) and candles ([`GetOhlc`]) for pricing with
+//! smoothed rather than instantaneous prices.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use parse_display::Display;
+use parse_display::FromStr;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio_extras::Tasks;
+use xtra::prelude::MessageChannel;
+use xtra::Actor as _;
+use xtra::Address;
+use xtra_bitmex_price_feed::ContractSymbol;
+use xtra_bitmex_price_feed::GetLatestQuotes;
+use xtra_bitmex_price_feed::LatestQuotes;
+use xtra_bitmex_price_feed::Quote;
+use xtra_bitmex_price_feed::QUOTE_INTERVAL_MINUTES;
+use xtra_productivity::xtra_productivity;
+use xtras::supervisor::always_restart;
+use xtras::supervisor::Supervisor;
+use xtras::SendInterval;
+
+/// How often an [`ExchangeFeed`] polls the ticker of its exchange.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Timeout for requests to the ticker of an exchange.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the [`Aggregator`] waits for the quotes of a source.
+const SOURCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the [`Aggregator`] samples its combined quotes into the
+/// history.
+const HISTORY_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long the [`Aggregator`] keeps quotes in its history unless
+/// configured otherwise.
+pub const DEFAULT_HISTORY_WINDOW: time::Duration = time::Duration::hours(1);
+
+/// The contract symbols an [`ExchangeFeed`] polls quotes for.
+const SYMBOLS: [ContractSymbol; 2] = [ContractSymbol::BtcUsd, ContractSymbol::EthUsd];
+
+/// How the [`Aggregator`] combines the fresh quotes of its sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, FromStr)]
+#[display(style = "lowercase")]
+pub enum Policy {
+    /// Use the quote of the first source, in the order the sources were
+    /// added, which has a fresh one.
+    #[default]
+    Fallback,
+    /// Use the median bid and ask of all sources with a fresh quote.
+    Median,
+}
+
+/// An exchange whose public ticker can be polled for quotes.
+///
+/// The tickers always serve mainnet prices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, FromStr)]
+#[display(style = "lowercase")]
+pub enum Exchange {
+    Kraken,
+    Coinbase,
+    Deribit,
+}
+
+impl Exchange {
+    fn ticker_url(&self, symbol: ContractSymbol) -> &'static str {
+        match (self, symbol) {
+            (Exchange::Kraken, ContractSymbol::BtcUsd) => {
+                "https://api.kraken.com/0/public/Ticker?pair=XBTUSD"
+            }
+            (Exchange::Kraken, ContractSymbol::EthUsd) => {
+                "https://api.kraken.com/0/public/Ticker?pair=ETHUSD"
+            }
+            (Exchange::Coinbase, ContractSymbol::BtcUsd) => {
+                "https://api.exchange.coinbase.com/products/BTC-USD/ticker"
+            }
+            (Exchange::Coinbase, ContractSymbol::EthUsd) => {
+                "https://api.exchange.coinbase.com/products/ETH-USD/ticker"
+            }
+            (Exchange::Deribit, ContractSymbol::BtcUsd) => {
+                "https://www.deribit.com/api/v2/public/ticker?instrument_name=BTC-PERPETUAL"
+            }
+            (Exchange::Deribit, ContractSymbol::EthUsd) => {
+                "https://www.deribit.com/api/v2/public/ticker?instrument_name=ETH-PERPETUAL"
+            }
+        }
+    }
+
+    /// Extract the best bid and ask from a response of the ticker.
+    fn parse_ticker(&self, body: &str) -> Result<(Decimal, Decimal)> {
+        let (bid, ask) = match self {
+            Exchange::Kraken => {
+                let response = serde_json::from_str::<wire::KrakenResponse>(body)?;
+                if !response.error.is_empty() {
+                    bail!("Kraken responded with {}", response.error.join(", "));
+                }
+                let ticker = response
+                    .result
+                    .into_values()
+                    .next()
+                    .context("Kraken responded without a ticker")?;
+                let bid = ticker.b.into_iter().next().context("No bid")?;
+                let ask = ticker.a.into_iter().next().context("No ask")?;
+
+                (bid.parse::<Decimal>()?, ask.parse::<Decimal>()?)
+            }
+            Exchange::Coinbase => {
+                let ticker = serde_json::from_str::<wire::CoinbaseTicker>(body)?;
+
+                (
+                    ticker.bid.parse::<Decimal>()?,
+                    ticker.ask.parse::<Decimal>()?,
+                )
+            }
+            Exchange::Deribit => {
+                let ticker = serde_json::from_str::<wire::DeribitResponse>(body)?.result;
+
+                (
+                    Decimal::try_from(ticker.best_bid_price)?,
+                    Decimal::try_from(ticker.best_ask_price)?,
+                )
+            }
+        };
+
+        if bid <= Decimal::ZERO || ask <= Decimal::ZERO {
+            bail!("Ticker quoted bid {bid} and ask {ask}");
+        }
+
+        Ok((bid, ask))
+    }
+}
+
+/// Spawn the price feed of the daemon.
+///
+/// BitMEX is always the first source, followed by `exchanges` in the
+/// given order.
+pub fn spawn(
+    network: xtra_bitmex_price_feed::Network,
+    exchanges: &[Exchange],
+    policy: Policy,
+    history_window: time::Duration,
+    tasks: &mut Tasks,
+) -> Address<Aggregator> {
+    let (supervisor, bitmex) = Supervisor::<_, xtra_bitmex_price_feed::Error>::with_policy(
+        move || xtra_bitmex_price_feed::Actor::new(network),
+        always_restart(),
+    );
+    tasks.add(supervisor.run_log_summary());
+
+    let mut aggregator = Aggregator::new(policy)
+        .with_history_window(history_window)
+        .with_source("bitmex", bitmex.into());
+    for exchange in exchanges {
+        let feed = ExchangeFeed::new(*exchange).create(None).spawn(tasks);
+        aggregator = aggregator.with_source(exchange.to_string(), feed.into());
+    }
+
+    aggregator.create(None).spawn(tasks)
+}
+
+struct Source {
+    name: String,
+    quotes: MessageChannel<GetLatestQuotes, LatestQuotes>,
+}
+
+/// Combines the latest quotes of several price feeds.
+pub struct Aggregator {
+    sources: Vec<Source>,
+    policy: Policy,
+    max_quote_age: time::Duration,
+    /// The combined quotes of each symbol within the history window,
+    /// oldest first
+    history: HashMap<ContractSymbol, VecDeque<Quote>>,
+    history_window: time::Duration,
+}
+
+impl Aggregator {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            sources: Vec::new(),
+            policy,
+            max_quote_age: time::Duration::minutes(QUOTE_INTERVAL_MINUTES * 2),
+            history: HashMap::new(),
+            history_window: DEFAULT_HISTORY_WINDOW,
+        }
+    }
+
+    #[must_use]
+    pub fn with_source(
+        mut self,
+        name: impl Into<String>,
+        quotes: MessageChannel<GetLatestQuotes, LatestQuotes>,
+    ) -> Self {
+        self.sources.push(Source {
+            name: name.into(),
+            quotes,
+        });
+        self
+    }
+
+    /// Keep the quotes of the last `history_window` for [`GetTwap`] and
+    /// [`GetOhlc`].
+    #[must_use]
+    pub fn with_history_window(mut self, history_window: time::Duration) -> Self {
+        self.history_window = history_window;
+        self
+    }
+
+    async fn latest_quotes(&self) -> LatestQuotes {
+        let responses = futures::future::join_all(self.sources.iter().map(|source| async move {
+            let response = tokio_extras::time::timeout(
+                SOURCE_TIMEOUT,
+                source.quotes.send(GetLatestQuotes),
+                tokio_extras::time::already_instrumented,
+            )
+            .await;
+
+            (source.name.as_str(), response)
+        }))
+        .await;
+
+        let mut fresh_quotes = HashMap::<ContractSymbol, Vec<Quote>>::new();
+        for (name, response) in responses {
+            let quotes = match response {
+                Ok(Ok(quotes)) => quotes,
+                Ok(Err(e)) => {
+                    tracing::debug!(source = name, "Price feed is unavailable: {e:#}");
+                    continue;
+                }
+                Err(_) => {
+                    tracing::debug!(source = name, "Price feed did not respond in time");
+                    continue;
+                }
+            };
+
+            for symbol in SYMBOLS {
+                match quotes.get(&symbol) {
+                    Some(quote) if !quote.is_older_than(self.max_quote_age) => {
+                        fresh_quotes.entry(symbol).or_default().push(*quote);
+                    }
+                    _ => {
+                        tracing::debug!(source = name, %symbol, "No fresh quote");
+                    }
+                }
+            }
+        }
+
+        fresh_quotes
+            .into_iter()
+            .filter_map(|(symbol, quotes)| Some((symbol, combine(self.policy, &quotes)?)))
+            .collect()
+    }
+
+    fn history(&self, symbol: ContractSymbol) -> impl Iterator<Item = &Quote> {
+        self.history.get(&symbol).into_iter().flatten()
+    }
+}
+
+/// Request the time-weighted average price of `symbol` over the last
+/// `duration`.
+#[derive(Debug, Clone, Copy)]
+pub struct GetTwap {
+    pub symbol: ContractSymbol,
+    pub duration: time::Duration,
+}
+
+/// Request the candles of the mid price of `symbol` within the history
+/// window, `interval` long each.
+#[derive(Debug, Clone, Copy)]
+pub struct GetOhlc {
+    pub symbol: ContractSymbol,
+    pub interval: time::Duration,
+}
+
+/// A time-weighted average of the bid and ask.
+///
+/// Each quote is weighted by how long it was the latest one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Twap {
+    pub bid: Decimal,
+    pub ask: Decimal,
+    pub samples: usize,
+    /// When the first of the averaged quotes was published
+    pub first_quote_at: OffsetDateTime,
+    /// When the last of the averaged quotes was published
+    pub last_quote_at: OffsetDateTime,
+}
+
+impl Twap {
+    pub fn is_older_than(&self, duration: time::Duration) -> bool {
+        self.last_quote_at < OffsetDateTime::now_utc() - duration
+    }
+}
+
+/// The open, high, low and close of the mid price within an interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candle {
+    pub start: OffsetDateTime,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+}
+
+#[derive(Clone, Copy)]
+struct SampleHistory;
+
+#[xtra_productivity]
+impl Aggregator {
+    async fn handle(&mut self, _: GetLatestQuotes) -> LatestQuotes {
+        self.latest_quotes().await
+    }
+
+    async fn handle(&mut self, msg: GetTwap) -> Option<Twap> {
+        let now = OffsetDateTime::now_utc();
+
+        twap(self.history(msg.symbol), now - msg.duration, now)
+    }
+
+    async fn handle(&mut self, msg: GetOhlc) -> Vec<Candle> {
+        ohlc(self.history(msg.symbol), msg.interval)
+    }
+
+    async fn handle(&mut self, _: SampleHistory) {
+        let quotes = self.latest_quotes().await;
+
+        for (symbol, quote) in quotes {
+            let history = self.history.entry(symbol).or_default();
+            if history.back().map(|latest| latest.timestamp) != Some(quote.timestamp) {
+                history.push_back(quote);
+            }
+        }
+
+        let retained_since = OffsetDateTime::now_utc() - self.history_window;
+        for history in self.history.values_mut() {
+            while matches!(history.front(), Some(oldest) if oldest.timestamp < retained_since) {
+                history.pop_front();
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Aggregator {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(
+                HISTORY_SAMPLE_INTERVAL,
+                || SampleHistory,
+                xtras::IncludeSpan::Never,
+            ),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// The time-weighted average of the quotes in `history` published since
+/// `since`, with the last one being the latest until `now`.
+fn twap<'a>(
+    history: impl Iterator<Item = &'a Quote>,
+    since: OffsetDateTime,
+    now: OffsetDateTime,
+) -> Option<Twap> {
+    let quotes = history
+        .filter(|quote| quote.timestamp >= since)
+        .collect::<Vec<_>>();
+    let first = *quotes.first()?;
+    let last = *quotes.last()?;
+
+    let superseded_at = quotes
+        .iter()
+        .skip(1)
+        .map(|quote| quote.timestamp)
+        .chain([now]);
+
+    let (mut bid, mut ask, mut total_weight) = (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+    for (quote, superseded_at) in quotes.iter().zip(superseded_at) {
+        let weight = Decimal::from((superseded_at - quote.timestamp).whole_seconds().max(0));
+
+        bid += quote.bid * weight;
+        ask += quote.ask * weight;
+        total_weight += weight;
+    }
+
+    let (bid, ask) = if total_weight.is_zero() {
+        (last.bid, last.ask)
+    } else {
+        (bid / total_weight, ask / total_weight)
+    };
+
+    Some(Twap {
+        bid,
+        ask,
+        samples: quotes.len(),
+        first_quote_at: first.timestamp,
+        last_quote_at: last.timestamp,
+    })
+}
+
+/// Group the quotes of `history`, which must be ordered by time, into
+/// candles of `interval`, aligned to the Unix epoch.
+fn ohlc<'a>(history: impl Iterator<Item = &'a Quote>, interval: time::Duration) -> Vec<Candle> {
+    let interval_secs = interval.whole_seconds();
+    if interval_secs <= 0 {
+        return Vec::new();
+    }
+
+    let mut candles = Vec::<Candle>::new();
+    for quote in history {
+        let mid = (quote.bid + quote.ask) / Decimal::TWO;
+        let timestamp = quote.timestamp.unix_timestamp();
+        let start = timestamp - timestamp.rem_euclid(interval_secs);
+
+        match candles.last_mut() {
+            Some(candle) if candle.start.unix_timestamp() == start => {
+                candle.high = candle.high.max(mid);
+                candle.low = candle.low.min(mid);
+                candle.close = mid;
+            }
+            _ => candles.push(Candle {
+                start: OffsetDateTime::from_unix_timestamp(start)
+                    .expect("start of interval to be a valid timestamp"),
+                open: mid,
+                high: mid,
+                low: mid,
+                close: mid,
+            }),
+        }
+    }
+
+    candles
+}
+
+/// Combine the fresh `quotes` of one symbol, ordered like their sources.
+///
+/// The median quote is as old as the oldest quote it was derived from.
+fn combine(policy: Policy, quotes: &[Quote]) -> Option<Quote> {
+    let first = *quotes.first()?;
+
+    let quote = match policy {
+        Policy::Fallback => first,
+        Policy::Median => Quote {
+            timestamp: quotes.iter().map(|quote| quote.timestamp).min()?,
+            bid: median(quotes.iter().map(|quote| quote.bid).collect())?,
+            ask: median(quotes.iter().map(|quote| quote.ask).collect())?,
+            symbol: first.symbol,
+        },
+    };
+
+    Some(quote)
+}
+
+fn median(mut values: Vec<Decimal>) -> Option<Decimal> {
+    values.sort();
+
+    let middle = values.len() / 2;
+    match values.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((values[middle - 1] + values[middle]) / Decimal::TWO),
+        _ => Some(values[middle]),
+    }
+}
+
+/// Polls the public ticker of an exchange for quotes.
+///
+/// Quotes are timestamped when they were received, since not all
+/// tickers report when they were last updated.
+pub struct ExchangeFeed {
+    exchange: Exchange,
+    client: reqwest::Client,
+    latest_quotes: LatestQuotes,
+}
+
+impl ExchangeFeed {
+    pub fn new(exchange: Exchange) -> Self {
+        Self {
+            exchange,
+            client: reqwest::Client::new(),
+            latest_quotes: LatestQuotes::new(),
+        }
+    }
+
+    async fn fetch(&self, symbol: ContractSymbol) -> Result<Quote> {
+        let url = self.exchange.ticker_url(symbol);
+
+        let response = self
+            .client
+            .get(url)
+            // Coinbase rejects requests without a user agent
+            .header(reqwest::header::USER_AGENT, "itchysats")
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .with_context(|| format!("Failed to GET {url}"))?;
+
+        let code = response.status();
+        if !code.is_success() {
+            bail!("GET {url} responded with {code}");
+        }
+
+        let body = response.text().await?;
+        let (bid, ask) = self
+            .exchange
+            .parse_ticker(&body)
+            .with_context(|| format!("Failed to parse ticker of {}", self.exchange))?;
+
+        Ok(Quote {
+            timestamp: OffsetDateTime::now_utc(),
+            bid,
+            ask,
+            symbol,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PollTicker;
+
+#[xtra_productivity]
+impl ExchangeFeed {
+    async fn handle(&mut self, _: PollTicker) {
+        for symbol in SYMBOLS {
+            match self.fetch(symbol).await {
+                Ok(quote) => {
+                    self.latest_quotes.insert(symbol, quote);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        exchange = %self.exchange,
+                        %symbol,
+                        "Failed to poll quote: {e:#}"
+                    );
+                }
+            }
+        }
+    }
+
+    async fn handle(&mut self, _: GetLatestQuotes) -> LatestQuotes {
+        self.latest_quotes.clone()
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for ExchangeFeed {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(POLL_INTERVAL, || PollTicker, xtras::IncludeSpan::Always),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+mod wire {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    pub struct KrakenResponse {
+        #[serde(default)]
+        pub error: Vec<String>,
+        #[serde(default)]
+        pub result: HashMap<String, KrakenTicker>,
+    }
+
+    /// The best ask `a` and bid `b`, each as price, whole lot volume and
+    /// lot volume.
+    #[derive(Debug, Deserialize)]
+    pub struct KrakenTicker {
+        pub a: Vec<String>,
+        pub b: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct CoinbaseTicker {
+        pub bid: String,
+        pub ask: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct DeribitResponse {
+        pub result: DeribitTicker,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct DeribitTicker {
+        pub best_bid_price: f64,
+        pub best_ask_price: f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn quote(minutes_ago: i64, bid: Decimal, ask: Decimal) -> Quote {
+        Quote {
+            timestamp: OffsetDateTime::now_utc() - time::Duration::minutes(minutes_ago),
+            bid,
+            ask,
+            symbol: ContractSymbol::BtcUsd,
+        }
+    }
+
+    #[test]
+    fn fallback_uses_quote_of_first_source() {
+        let bitmex = quote(0, dec!(20_000), dec!(20_001));
+        let kraken = quote(0, dec!(20_010), dec!(20_011));
+
+        let combined = combine(Policy::Fallback, &[bitmex, kraken]).unwrap();
+
+        assert_eq!(combined.bid, dec!(20_000));
+        assert_eq!(combined.ask, dec!(20_001));
+        assert!(combine(Policy::Fallback, &[]).is_none());
+    }
+
+    #[test]
+    fn median_is_as_old_as_oldest_quote() {
+        let quotes = [
+            quote(0, dec!(20_000), dec!(20_004)),
+            quote(1, dec!(20_100), dec!(20_101)),
+            quote(0, dec!(19_000), dec!(20_002)),
+        ];
+
+        let combined = combine(Policy::Median, &quotes).unwrap();
+        let even = combine(Policy::Median, &quotes[..2]).unwrap();
+
+        assert_eq!(combined.bid, dec!(20_000));
+        assert_eq!(combined.ask, dec!(20_004));
+        assert_eq!(combined.timestamp, quotes[1].timestamp);
+        assert_eq!(even.bid, dec!(20_050));
+        assert_eq!(even.ask, dec!(20_052.5));
+    }
+
+    #[test]
+    fn twap_weights_quotes_by_how_long_they_were_the_latest() {
+        let now = OffsetDateTime::now_utc();
+        let minutes_ago = |minutes: i64, bid: Decimal, ask: Decimal| Quote {
+            timestamp: now - time::Duration::minutes(minutes),
+            bid,
+            ask,
+            symbol: ContractSymbol::BtcUsd,
+        };
+        let history = [
+            minutes_ago(20, dec!(1_000), dec!(1_000)),
+            minutes_ago(10, dec!(20_000), dec!(20_010)),
+            minutes_ago(4, dec!(20_600), dec!(20_610)),
+        ];
+
+        let twap = twap(history.iter(), now - time::Duration::minutes(10), now).unwrap();
+
+        // 6 minutes at the first and 4 minutes at the second quote within the window
+        assert_eq!(twap.samples, 2);
+        assert_eq!(twap.bid, dec!(20_240));
+        assert_eq!(twap.ask, dec!(20_250));
+        assert_eq!(twap.last_quote_at, history[2].timestamp);
+    }
+
+    #[test]
+    fn quotes_are_grouped_into_candles_of_mid_price() {
+        let at = |secs: i64, mid: Decimal| Quote {
+            timestamp: OffsetDateTime::from_unix_timestamp(secs).unwrap(),
+            bid: mid - dec!(1),
+            ask: mid + dec!(1),
+            symbol: ContractSymbol::BtcUsd,
+        };
+        let history = [
+            at(600, dec!(100)),
+            at(610, dec!(120)),
+            at(620, dec!(90)),
+            at(630, dec!(110)),
+            at(660, dec!(105)),
+        ];
+
+        let candles = ohlc(history.iter(), time::Duration::minutes(1));
+
+        assert_eq!(
+            candles,
+            vec![
+                Candle {
+                    start: OffsetDateTime::from_unix_timestamp(600).unwrap(),
+                    open: dec!(100),
+                    high: dec!(120),
+                    low: dec!(90),
+                    close: dec!(110),
+                },
+                Candle {
+                    start: OffsetDateTime::from_unix_timestamp(660).unwrap(),
+                    open: dec!(105),
+                    high: dec!(105),
+                    low: dec!(105),
+                    close: dec!(105),
+                },
+            ]
+        );
+        assert!(ohlc(history.iter(), time::Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn best_bid_and_ask_are_parsed_from_tickers() {
+        let kraken = r#"{"result":{"XXBTZUSD":{"a":["19150.1","1","1"],"b":["19150.0","2","2"]}}}"#;
+        let coinbase = r#"{"ask":"19151.22","bid":"19151.21","price":"19151.21"}"#;
+        let deribit = r#"{"result":{"best_bid_price":19148.5,"best_ask_price":19149.0}}"#;
+
+        assert_eq!(
+            Exchange::Kraken.parse_ticker(kraken).unwrap(),
+            (dec!(19150.0), dec!(19150.1))
+        );
+        assert_eq!(
+            Exchange::Coinbase.parse_ticker(coinbase).unwrap(),
+            (dec!(19151.21), dec!(19151.22))
+        );
+        assert_eq!(
+            Exchange::Deribit.parse_ticker(deribit).unwrap(),
+            (dec!(19148.5), dec!(19149.0))
+        );
+        assert!(Exchange::Kraken
+            .parse_ticker(r#"{"error":["EQuery:Unknown asset pair"]}"#)
+            .is_err());
+    }
+}
@@ -0,0 +1,428 @@
+//! Stop-loss and take-profit orders of the taker.
+//!
+//! Both close a CFD once the price moves beyond a trigger price, by
+//! settling collaboratively at the current market price:
+//!
+//! - A stop-loss triggers when the price moves against the position. If the maker is offline, the
+//!   proposal cannot be sent, or the maker does not settle within [`SETTLEMENT_TIMEOUT`], the CFD
+//!   is force-closed by committing to the blockchain instead.
+//! - A take-profit triggers when the price moves in favour of the position. It never commits to the
+//!   blockchain: if the maker is offline or does not settle, the settlement is proposed again after
+//!   [`RETRY_INTERVAL`] as long as the price stays beyond the trigger price.
+//!
+//! Reaching a trigger price is recorded in the CFD's event history
+//! once, no matter how often the settlement is proposed.
+
+use crate::command;
+use crate::into_price_feed_symbol;
+use crate::online_status::ConnectionStatus;
+use crate::taker_cfd;
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use model::market_closing_price;
+use model::OrderId;
+use model::Position;
+use model::Price;
+use model::PriceTrigger;
+use model::Role;
+use sqlite_db::price_trigger::PriceTriggerOrder;
+use std::collections::HashMap;
+use time::ext::NumericalDuration;
+use time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::watch;
+use xtra::prelude::MessageChannel;
+use xtra::Address;
+use xtra_bitmex_price_feed::GetLatestQuotes;
+use xtra_bitmex_price_feed::LatestQuotes;
+use xtra_bitmex_price_feed::Quote;
+use xtra_bitmex_price_feed::QUOTE_INTERVAL_MINUTES;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often the trigger prices are checked against the latest quotes.
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long to wait for a collaborative settlement after a stop-loss was
+/// triggered before committing to the blockchain.
+pub const SETTLEMENT_TIMEOUT: Duration = Duration::minutes(2);
+
+/// How long to wait for a collaborative settlement after a take-profit
+/// was triggered before proposing it again.
+pub const RETRY_INTERVAL: Duration = Duration::minutes(5);
+
+/// Set a trigger price of an open CFD, replacing any previous one of the
+/// same kind.
+#[derive(Clone, Copy)]
+pub struct SetPriceTrigger {
+    pub order_id: OrderId,
+    pub trigger: PriceTrigger,
+    pub trigger_price: Price,
+}
+
+/// Remove a trigger price of a CFD.
+#[derive(Clone, Copy)]
+pub struct RemovePriceTrigger {
+    pub order_id: OrderId,
+    pub trigger: PriceTrigger,
+}
+
+/// Message sent to ourselves at an interval to check whether any
+/// trigger price has been reached.
+#[derive(Clone, Copy)]
+struct CheckPriceTriggers;
+
+pub struct Actor {
+    db: sqlite_db::Connection,
+    executor: command::Executor,
+    cfd_actor: Address<taker_cfd::Actor>,
+    price_feed: MessageChannel<GetLatestQuotes, LatestQuotes>,
+    maker_online_status: watch::Receiver<ConnectionStatus>,
+    /// Triggered take-profits for which a settlement was proposed, and
+    /// when.
+    proposed: HashMap<OrderId, OffsetDateTime>,
+}
+
+impl Actor {
+    pub fn new(
+        db: sqlite_db::Connection,
+        executor: command::Executor,
+        cfd_actor: Address<taker_cfd::Actor>,
+        price_feed: MessageChannel<GetLatestQuotes, LatestQuotes>,
+        maker_online_status: watch::Receiver<ConnectionStatus>,
+    ) -> Self {
+        Self {
+            db,
+            executor,
+            cfd_actor,
+            price_feed,
+            maker_online_status,
+            proposed: HashMap::default(),
+        }
+    }
+
+    async fn check_price_triggers(&mut self) -> Result<()> {
+        let price_triggers = self.db.load_price_triggers().await?;
+
+        if price_triggers.is_empty() {
+            return Ok(());
+        }
+
+        let quotes = self
+            .price_feed
+            .send(GetLatestQuotes)
+            .await
+            .context("Price feed not available")?;
+
+        for price_trigger in price_triggers {
+            let order_id = price_trigger.order_id;
+            let trigger = price_trigger.trigger;
+
+            if let Err(e) = self.check_price_trigger(price_trigger, &quotes).await {
+                tracing::warn!(%order_id, ?trigger, "Failed to check trigger price: {e:#}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_price_trigger(
+        &mut self,
+        PriceTriggerOrder {
+            order_id,
+            trigger,
+            trigger_price,
+            triggered_at,
+        }: PriceTriggerOrder,
+        quotes: &LatestQuotes,
+    ) -> Result<()> {
+        let cfd = match self.db.load_open_cfd::<model::Cfd>(order_id, ()).await {
+            Ok(cfd) => cfd,
+            Err(sqlite_db::Error::OpenCfdNotFound) => {
+                tracing::debug!(%order_id, "Removing trigger prices of CFD which is not open");
+                return self.remove_all(order_id).await;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if !cfd.is_position_open() {
+            tracing::debug!(%order_id, "Removing trigger prices of CFD which is being closed");
+            return self.remove_all(order_id).await;
+        }
+
+        let now = OffsetDateTime::now_utc();
+        match (trigger, triggered_at) {
+            (PriceTrigger::StopLoss, Some(triggered_at)) => {
+                if now - triggered_at >= SETTLEMENT_TIMEOUT {
+                    tracing::warn!(
+                        %order_id,
+                        "Stop-loss was not settled in time, committing to the blockchain"
+                    );
+                    return self.commit(order_id).await;
+                }
+
+                return Ok(());
+            }
+            (PriceTrigger::TakeProfit, _) => {
+                if let Some(proposed_at) = self.proposed.get(&order_id) {
+                    if now - *proposed_at < RETRY_INTERVAL {
+                        return Ok(());
+                    }
+                }
+            }
+            (PriceTrigger::StopLoss, None) => {}
+        }
+
+        let quote = quotes
+            .get(&into_price_feed_symbol(cfd.contract_symbol()))
+            .context("No quote available")?;
+
+        if quote.is_older_than(QUOTE_INTERVAL_MINUTES.minutes() * 2) {
+            tracing::debug!(%order_id, ?trigger, "Latest quote is too old to check trigger price");
+            return Ok(());
+        }
+
+        let bid = Price::new(quote.bid())?;
+        let ask = Price::new(quote.ask())?;
+        let closing_price = market_closing_price(bid, ask, Role::Taker, cfd.position());
+
+        if !is_triggered(trigger, cfd.position(), trigger_price, closing_price) {
+            return Ok(());
+        }
+
+        let maker_offline = *self.maker_online_status.borrow() == ConnectionStatus::Offline;
+
+        if trigger == PriceTrigger::TakeProfit && maker_offline {
+            tracing::debug!(%order_id, "Take-profit triggered but maker is offline");
+            return Ok(());
+        }
+
+        if triggered_at.is_none() {
+            tracing::info!(%order_id, ?trigger, %trigger_price, %closing_price, "Price triggered");
+
+            self.executor
+                .execute(order_id, |cfd| {
+                    cfd.record_price_trigger(trigger, closing_price)
+                })
+                .await?;
+            self.db.mark_price_triggered(order_id, trigger, now).await?;
+        }
+
+        match trigger {
+            PriceTrigger::StopLoss => {
+                if maker_offline {
+                    tracing::info!(%order_id, "Maker is offline, committing to the blockchain");
+                    return self.commit(order_id).await;
+                }
+
+                if let Err(e) = self.propose_settlement(order_id, bid, ask, quote).await {
+                    tracing::warn!(
+                        %order_id,
+                        "Failed to propose settlement, committing to the blockchain: {e:#}"
+                    );
+                    return self.commit(order_id).await;
+                }
+            }
+            PriceTrigger::TakeProfit => {
+                self.proposed.insert(order_id, now);
+                self.propose_settlement(order_id, bid, ask, quote).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn propose_settlement(
+        &self,
+        order_id: OrderId,
+        bid: Price,
+        ask: Price,
+        quote: &Quote,
+    ) -> Result<()> {
+        let quote_timestamp = quote
+            .timestamp
+            .format(&time::format_description::well_known::Rfc3339)
+            .context("Failed to format timestamp")?;
+
+        self.cfd_actor
+            .send(taker_cfd::ProposeSettlement {
+                order_id,
+                bid,
+                ask,
+                quote_timestamp,
+            })
+            .await
+            .context("CFD actor disconnected")?
+    }
+
+    async fn commit(&mut self, order_id: OrderId) -> Result<()> {
+        self.executor
+            .execute(order_id, |cfd| cfd.manual_commit_to_blockchain())
+            .await?;
+
+        self.remove_all(order_id).await
+    }
+
+    async fn remove(&mut self, order_id: OrderId, trigger: PriceTrigger) -> Result<()> {
+        if trigger == PriceTrigger::TakeProfit {
+            self.proposed.remove(&order_id);
+        }
+        self.db.delete_price_trigger(order_id, trigger).await
+    }
+
+    async fn remove_all(&mut self, order_id: OrderId) -> Result<()> {
+        self.proposed.remove(&order_id);
+        self.db.delete_price_triggers(order_id).await
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, msg: SetPriceTrigger) -> Result<()> {
+        let SetPriceTrigger {
+            order_id,
+            trigger,
+            trigger_price,
+        } = msg;
+
+        let cfd = self.db.load_open_cfd::<model::Cfd>(order_id, ()).await?;
+        anyhow::ensure!(
+            cfd.is_position_open(),
+            "Cannot set {trigger:?} of CFD {order_id} without an open position"
+        );
+
+        tracing::info!(%order_id, ?trigger, %trigger_price, "Setting trigger price");
+
+        if trigger == PriceTrigger::TakeProfit {
+            self.proposed.remove(&order_id);
+        }
+        self.db
+            .set_price_trigger(order_id, trigger, trigger_price)
+            .await
+    }
+
+    async fn handle(&mut self, msg: RemovePriceTrigger) -> Result<()> {
+        let RemovePriceTrigger { order_id, trigger } = msg;
+
+        tracing::info!(%order_id, ?trigger, "Removing trigger price");
+
+        self.remove(order_id, trigger).await
+    }
+
+    async fn handle(&mut self, _: CheckPriceTriggers) {
+        if let Err(e) = self.check_price_triggers().await {
+            tracing::warn!("Failed to check trigger prices: {e:#}");
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(
+                CHECK_INTERVAL,
+                || CheckPriceTriggers,
+                xtras::IncludeSpan::Never,
+            ),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// Whether the price at which the taker's `position` would be closed has
+/// moved beyond `trigger_price`: against the position for a stop-loss, in
+/// favour of the position for a take-profit.
+fn is_triggered(
+    trigger: PriceTrigger,
+    position: Position,
+    trigger_price: Price,
+    closing_price: Price,
+) -> bool {
+    match (trigger, position) {
+        (PriceTrigger::StopLoss, Position::Long) | (PriceTrigger::TakeProfit, Position::Short) => {
+            closing_price <= trigger_price
+        }
+        (PriceTrigger::StopLoss, Position::Short) | (PriceTrigger::TakeProfit, Position::Long) => {
+            closing_price >= trigger_price
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn stop_loss_triggers_when_price_moves_against_position() {
+        let trigger_price = Price::new(dec!(19_000)).unwrap();
+        let below = Price::new(dec!(18_999)).unwrap();
+        let above = Price::new(dec!(19_001)).unwrap();
+        let stop_loss = PriceTrigger::StopLoss;
+
+        assert!(is_triggered(
+            stop_loss,
+            Position::Long,
+            trigger_price,
+            below
+        ));
+        assert!(!is_triggered(
+            stop_loss,
+            Position::Long,
+            trigger_price,
+            above
+        ));
+        assert!(is_triggered(
+            stop_loss,
+            Position::Short,
+            trigger_price,
+            above
+        ));
+        assert!(!is_triggered(
+            stop_loss,
+            Position::Short,
+            trigger_price,
+            below
+        ));
+    }
+
+    #[test]
+    fn take_profit_triggers_when_price_moves_in_favour_of_position() {
+        let trigger_price = Price::new(dec!(21_000)).unwrap();
+        let below = Price::new(dec!(20_999)).unwrap();
+        let above = Price::new(dec!(21_001)).unwrap();
+        let take_profit = PriceTrigger::TakeProfit;
+
+        assert!(is_triggered(
+            take_profit,
+            Position::Long,
+            trigger_price,
+            above
+        ));
+        assert!(!is_triggered(
+            take_profit,
+            Position::Long,
+            trigger_price,
+            below
+        ));
+        assert!(is_triggered(
+            take_profit,
+            Position::Short,
+            trigger_price,
+            below
+        ));
+        assert!(!is_triggered(
+            take_profit,
+            Position::Short,
+            trigger_price,
+            above
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::monitor::BroadcastInitiator;
 use crate::monitor::MonitorAfterContractSetup;
 use crate::monitor::MonitorAfterRollover;
 use crate::monitor::MonitorCetFinality;
@@ -13,14 +14,28 @@ use model::CfdEvent;
 use model::EventKind;
 use model::Role;
 use sqlite_db;
+use sqlite_db::taker_records::TakerOutcome;
+use std::future::Future;
+use std::time::Duration;
+use std::time::Instant;
 use tracing::Instrument;
 use xtra::prelude::MessageChannel;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncSafe;
 
+/// How long to wait for a non-critical subscriber to accept an event
+/// before skipping it.
+pub const DEFAULT_DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const MONITOR: &str = "monitor";
+const ORACLE: &str = "oracle";
+const PROJECTION: &str = "projection";
+const POSITION_METRICS: &str = "position_metrics";
+
 pub struct Actor {
     db: sqlite_db::Connection,
     role: Role,
+    delivery_timeout: Duration,
     cfds_changed: MessageChannel<projection::CfdChanged, ()>,
     cfd_changed_metrics: MessageChannel<position_metrics::CfdChanged, ()>,
     try_broadcast_transaction: MessageChannel<TryBroadcastTransaction, Result<()>>,
@@ -31,11 +46,42 @@ pub struct Actor {
     monitor_attestation: MessageChannel<oracle::MonitorAttestations, ()>,
 }
 
-pub struct Event(CfdEvent);
+pub struct Event {
+    event: CfdEvent,
+    /// The version of the CFD the event was derived from, if the event must only be stored if
+    /// the CFD is still in that version
+    expected_version: Option<u32>,
+}
 
 impl Event {
     pub fn new(event: CfdEvent) -> Self {
-        Self(event)
+        Self {
+            event,
+            expected_version: None,
+        }
+    }
+
+    /// An event which is rejected with [`sqlite_db::Error::VersionConflict`] if other events
+    /// were stored for the CFD since it was in `version`.
+    pub fn at_version(event: CfdEvent, version: u32) -> Self {
+        Self {
+            event,
+            expected_version: Some(version),
+        }
+    }
+
+    /// Append the event to the events of its CFD, rejecting it if the CFD is no longer in the
+    /// expected version.
+    pub(crate) async fn append_to(self, db: &sqlite_db::Connection) -> Result<CfdEvent> {
+        match self.expected_version {
+            Some(version) => {
+                db.append_event_at_version(self.event.clone(), version)
+                    .await?
+            }
+            None => db.append_event(self.event.clone()).await?,
+        }
+
+        Ok(self.event)
     }
 }
 
@@ -44,6 +90,7 @@ impl Actor {
     pub fn new(
         db: sqlite_db::Connection,
         role: Role,
+        delivery_timeout: Duration,
         cfds_changed: MessageChannel<projection::CfdChanged, ()>,
         cfd_changed_metrics: MessageChannel<position_metrics::CfdChanged, ()>,
         try_broadcast_transaction: MessageChannel<TryBroadcastTransaction, Result<()>>,
@@ -56,6 +103,7 @@ impl Actor {
         Self {
             db,
             role,
+            delivery_timeout,
             cfds_changed,
             cfd_changed_metrics,
             try_broadcast_transaction,
@@ -68,38 +116,126 @@ impl Actor {
     }
 }
 
+impl Actor {
+    /// Deliver an event to a non-critical subscriber, i.e. one which does
+    /// not rely on receiving every event in order.
+    ///
+    /// A subscriber which does not accept the event within the delivery
+    /// timeout is skipped, so that it does not hold up all other
+    /// subscribers.
+    async fn deliver_or_skip(
+        &self,
+        subscriber: &'static str,
+        delivery: impl Future<Output = Result<(), xtra::Error>>,
+    ) -> Result<(), xtra::Error> {
+        let delivery = tokio_extras::time::timeout(
+            self.delivery_timeout,
+            deliver(subscriber, delivery),
+            tokio_extras::time::already_instrumented,
+        );
+
+        match delivery.await {
+            Ok(result) => result,
+            Err(_) => {
+                tracing::warn!(
+                    subscriber,
+                    timeout_secs = self.delivery_timeout.as_secs_f64(),
+                    "Skipped delivery of event to slow subscriber"
+                );
+                DELIVERIES_SKIPPED_COUNTER
+                    .with_label_values(&[subscriber])
+                    .inc();
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// How `event` counts towards the record of the taker, if at all.
+fn taker_outcome(event: &EventKind) -> Option<TakerOutcome> {
+    match event {
+        EventKind::ContractSetupFailed => Some(TakerOutcome::SetupAborted),
+        EventKind::RolloverFailed => Some(TakerOutcome::RolloverFailed),
+        EventKind::CollaborativeSettlementConfirmed
+        | EventKind::CetConfirmed
+        | EventKind::RefundConfirmed => Some(TakerOutcome::CfdCompleted),
+        _ => None,
+    }
+}
+
+/// Deliver an event to a subscriber and record how long it took.
+///
+/// Deliveries to the same subscriber which are awaited one after another
+/// arrive in order.
+async fn deliver(
+    subscriber: &'static str,
+    delivery: impl Future<Output = Result<(), xtra::Error>>,
+) -> Result<(), xtra::Error> {
+    let started_at = Instant::now();
+    let result = delivery.await;
+
+    DELIVERY_DURATION_HISTOGRAM
+        .with_label_values(&[subscriber])
+        .observe(started_at.elapsed().as_secs_f64());
+
+    result
+}
+
 #[xtra_productivity]
 impl Actor {
     fn handle(&mut self, msg: Event) -> Result<()> {
-        let event = msg.0;
-
         // 1. Safe in DB
-        self.db.append_event(event.clone()).await?;
+        let event = msg.append_to(&self.db).await?;
+
+        if let (Role::Maker, Some(outcome)) = (self.role, taker_outcome(&event.event)) {
+            if let Err(e) = self.db.record_taker_outcome(event.id, outcome).await {
+                tracing::warn!(
+                    order_id = %event.id,
+                    ?outcome,
+                    "Failed to record taker outcome: {e:#}"
+                );
+            }
+        }
 
         // 2. Post process event
+        //
+        // The monitor and the oracle have to see the events of a CFD in
+        // order, hence we wait for every delivery to them.
         use EventKind::*;
         match event.event {
             ContractSetupCompleted { dlc: Some(dlc), .. } => {
                 let lock_tx = dlc.lock.0.clone();
 
                 let span = tracing::debug_span!("Broadcast lock TX", order_id = %event.id);
-                self.try_broadcast_transaction
-                    .send_async_safe(TryBroadcastTransaction {
-                        tx: lock_tx,
-                        kind: TransactionKind::Lock,
-                    })
-                    .instrument(span)
-                    .await?;
-
-                self.monitor_after_contract_setup
-                    .send_async_safe(MonitorAfterContractSetup::new(event.id, &dlc))
-                    .await?;
-
-                self.monitor_attestation
-                    .send_async_safe(oracle::MonitorAttestations {
-                        event_ids: dlc.event_ids(),
-                    })
-                    .await?;
+                deliver(
+                    MONITOR,
+                    self.try_broadcast_transaction
+                        .send_async_safe(TryBroadcastTransaction {
+                            order_id: event.id,
+                            tx: lock_tx,
+                            kind: TransactionKind::Lock,
+                            initiator: BroadcastInitiator::Protocol,
+                        })
+                        .instrument(span),
+                )
+                .await?;
+
+                deliver(
+                    MONITOR,
+                    self.monitor_after_contract_setup
+                        .send_async_safe(MonitorAfterContractSetup::new(event.id, &dlc)),
+                )
+                .await?;
+
+                deliver(
+                    ORACLE,
+                    self.monitor_attestation
+                        .send_async_safe(oracle::MonitorAttestations {
+                            event_ids: dlc.event_ids(),
+                        }),
+                )
+                .await?;
             }
             CollaborativeSettlementCompleted {
                 spend_tx, script, ..
@@ -112,13 +248,18 @@ impl Actor {
                             "Broadcast collaborative settlement TX",
                             order_id = %event.id
                         );
-                        self.try_broadcast_transaction
-                            .send_async_safe(TryBroadcastTransaction {
-                                tx: spend_tx,
-                                kind: TransactionKind::CollaborativeClose,
-                            })
-                            .instrument(span)
-                            .await?;
+                        deliver(
+                            MONITOR,
+                            self.try_broadcast_transaction
+                                .send_async_safe(TryBroadcastTransaction {
+                                    order_id: event.id,
+                                    tx: spend_tx,
+                                    kind: TransactionKind::CollaborativeClose,
+                                    initiator: BroadcastInitiator::Protocol,
+                                })
+                                .instrument(span),
+                        )
+                        .await?;
                     }
                     Role::Taker => {
                         // TODO: Publish the tx once the collaborative settlement is symmetric,
@@ -126,78 +267,121 @@ impl Actor {
                     }
                 };
 
-                self.monitor_collaborative_settlement
-                    .send_async_safe(MonitorCollaborativeSettlement {
-                        order_id: event.id,
-                        tx: (txid, script),
-                    })
-                    .await?;
+                deliver(
+                    MONITOR,
+                    self.monitor_collaborative_settlement.send_async_safe(
+                        MonitorCollaborativeSettlement {
+                            order_id: event.id,
+                            tx: (txid, script),
+                        },
+                    ),
+                )
+                .await?;
             }
             CetTimelockExpiredPostOracleAttestation { cet }
             | OracleAttestedPostCetTimelock { cet, .. } => {
-                let _ = self
-                    .monitor_cet_finality
-                    .send_async_safe(MonitorCetFinality {
-                        order_id: event.id,
-                        cet: cet.clone(),
-                    })
-                    .await?;
+                deliver(
+                    MONITOR,
+                    self.monitor_cet_finality
+                        .send_async_safe(MonitorCetFinality {
+                            order_id: event.id,
+                            cet: cet.clone(),
+                        }),
+                )
+                .await?;
                 let span = tracing::debug_span!("Broadcast CET", order_id = %event.id);
-                self.try_broadcast_transaction
-                    .send_async_safe(TryBroadcastTransaction {
-                        tx: cet,
-                        kind: TransactionKind::Cet,
-                    })
-                    .instrument(span)
-                    .await?;
+                deliver(
+                    MONITOR,
+                    self.try_broadcast_transaction
+                        .send_async_safe(TryBroadcastTransaction {
+                            order_id: event.id,
+                            tx: cet,
+                            kind: TransactionKind::Cet,
+                            initiator: BroadcastInitiator::Protocol,
+                        })
+                        .instrument(span),
+                )
+                .await?;
             }
             OracleAttestedPriorCetTimelock {
                 commit_tx: Some(tx),
                 ..
+            } => {
+                let span = tracing::debug_span!("Broadcast commit TX", order_id = %event.id);
+                deliver(
+                    MONITOR,
+                    self.try_broadcast_transaction
+                        .send_async_safe(TryBroadcastTransaction {
+                            order_id: event.id,
+                            tx,
+                            kind: TransactionKind::Commit,
+                            initiator: BroadcastInitiator::Protocol,
+                        })
+                        .instrument(span),
+                )
+                .await?;
             }
-            | ManualCommit { tx } => {
+            ManualCommit { tx } => {
                 let span = tracing::debug_span!("Broadcast commit TX", order_id = %event.id);
-                self.try_broadcast_transaction
-                    .send_async_safe(TryBroadcastTransaction {
-                        tx,
-                        kind: TransactionKind::Commit,
-                    })
-                    .instrument(span)
-                    .await?;
+                deliver(
+                    MONITOR,
+                    self.try_broadcast_transaction
+                        .send_async_safe(TryBroadcastTransaction {
+                            order_id: event.id,
+                            tx,
+                            kind: TransactionKind::Commit,
+                            initiator: BroadcastInitiator::ManualCommit,
+                        })
+                        .instrument(span),
+                )
+                .await?;
             }
             OracleAttestedPriorCetTimelock {
                 commit_tx: None,
                 timelocked_cet: cet,
                 ..
             } => {
-                let _ = self
-                    .monitor_cet_finality
-                    .send_async_safe(MonitorCetFinality {
-                        order_id: event.id,
-                        cet,
-                    })
-                    .await?;
+                deliver(
+                    MONITOR,
+                    self.monitor_cet_finality
+                        .send_async_safe(MonitorCetFinality {
+                            order_id: event.id,
+                            cet,
+                        }),
+                )
+                .await?;
             }
             RolloverCompleted { dlc: Some(dlc), .. } => {
-                self.monitor_after_rollover
-                    .send_async_safe(MonitorAfterRollover::new(event.id, &dlc))
-                    .await?;
-
-                self.monitor_attestation
-                    .send_async_safe(oracle::MonitorAttestations {
-                        event_ids: dlc.event_ids(),
-                    })
-                    .await?;
+                deliver(
+                    MONITOR,
+                    self.monitor_after_rollover
+                        .send_async_safe(MonitorAfterRollover::new(event.id, &dlc)),
+                )
+                .await?;
+
+                deliver(
+                    ORACLE,
+                    self.monitor_attestation
+                        .send_async_safe(oracle::MonitorAttestations {
+                            event_ids: dlc.event_ids(),
+                        }),
+                )
+                .await?;
             }
             RefundTimelockExpired { refund_tx: tx } => {
                 let span = tracing::debug_span!("Broadcast refund TX", order_id = %event.id);
-                self.try_broadcast_transaction
-                    .send_async_safe(TryBroadcastTransaction {
-                        tx,
-                        kind: TransactionKind::Refund,
-                    })
-                    .instrument(span)
-                    .await?;
+                deliver(
+                    MONITOR,
+                    self.try_broadcast_transaction
+                        .send_async_safe(TryBroadcastTransaction {
+                            order_id: event.id,
+                            tx,
+                            kind: TransactionKind::Refund,
+                            initiator: BroadcastInitiator::Protocol,
+                        })
+                        .instrument(span),
+                )
+                .await?;
             }
             ContractSetupCompleted { dlc: None, .. }
             | RolloverCompleted { dlc: None, .. }
@@ -205,7 +389,7 @@ impl Actor {
             | CollaborativeSettlementStarted { .. }
             | ContractSetupStarted
             | ContractSetupFailed
-            | OfferRejected
+            | OfferRejected(_)
             | RolloverStarted
             | RolloverAccepted
             | RolloverRejected
@@ -219,18 +403,30 @@ impl Actor {
             | CollaborativeSettlementConfirmed
             | CollaborativeSettlementRejected
             | CollaborativeSettlementFailed
-            | CetTimelockExpiredPriorOracleAttestation => {}
+            | CollaborativeSettlementAbandoned { .. }
+            | CollaborativeSettlementProposalExpired
+            | CetTimelockExpiredPriorOracleAttestation
+            | PriceTriggered { .. } => {}
         }
 
-        // 3. Update UI
-        self.cfds_changed
-            .send_async_safe(projection::CfdChanged(event.id))
-            .await?;
-
-        // 4. Update metrics
-        self.cfd_changed_metrics
-            .send_async_safe(position_metrics::CfdChanged(event.id))
-            .await?;
+        // 3. Update UI and metrics
+        //
+        // Both only reload the current state of the CFD, hence they can
+        // miss an event and do not need to be delivered to in order.
+        let (projection, position_metrics) = tokio::join!(
+            self.deliver_or_skip(
+                PROJECTION,
+                self.cfds_changed
+                    .send_async_safe(projection::CfdChanged(event.id)),
+            ),
+            self.deliver_or_skip(
+                POSITION_METRICS,
+                self.cfd_changed_metrics
+                    .send_async_safe(position_metrics::CfdChanged(event.id)),
+            ),
+        );
+        projection?;
+        position_metrics?;
 
         Ok(())
     }
@@ -242,3 +438,26 @@ impl xtra::Actor for Actor {
 
     async fn stopped(self) -> Self::Stop {}
 }
+
+const SUBSCRIBER_LABEL: &str = "subscriber";
+
+static DELIVERY_DURATION_HISTOGRAM: conquer_once::Lazy<prometheus::HistogramVec> =
+    conquer_once::Lazy::new(|| {
+        prometheus::register_histogram_vec!(
+            "process_manager_event_delivery_seconds",
+            "The time it took to deliver an event to a subscriber of the process manager.",
+            &[SUBSCRIBER_LABEL],
+            vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0]
+        )
+        .unwrap()
+    });
+
+static DELIVERIES_SKIPPED_COUNTER: conquer_once::Lazy<prometheus::IntCounterVec> =
+    conquer_once::Lazy::new(|| {
+        prometheus::register_int_counter_vec!(
+            "process_manager_event_deliveries_skipped_total",
+            "The number of events not delivered to a subscriber because it was too slow.",
+            &[SUBSCRIBER_LABEL]
+        )
+        .unwrap()
+    });
@@ -1,8 +1,12 @@
+use crate::block_explorer::BlockExplorer;
+use crate::feed_lag::Freshness;
+use crate::fiat_rates::FiatAmount;
+use crate::fiat_rates::FiatRate;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
 use bdk::bitcoin::Amount;
-use bdk::bitcoin::Network;
 use bdk::bitcoin::Script;
 use bdk::bitcoin::SignedAmount;
 use bdk::bitcoin::Transaction;
@@ -13,14 +17,20 @@ use derivative::Derivative;
 use futures::StreamExt;
 use itertools::Itertools;
 use maia_core::TransactionExt;
+use model::calculate_effective_entry_price;
+use model::calculate_effective_exit_price;
 use model::calculate_long_liquidation_price;
 use model::calculate_margin;
 use model::calculate_payout_at_price;
 use model::calculate_profit;
 use model::calculate_short_liquidation_price;
+use model::contracts_to_sats;
 use model::libp2p::PeerId;
 use model::long_and_short_leverage;
 use model::market_closing_price;
+use model::sats_to_contracts;
+use model::sats_to_usd;
+use model::validate_quantity;
 use model::CfdEvent;
 use model::ClosedCfd;
 use model::ContractSymbol;
@@ -30,20 +40,24 @@ use model::EventKind;
 use model::FailedCfd;
 use model::FailedKind;
 use model::FeeAccount;
+use model::FeeSubsidy;
 use model::FundingFee;
 use model::FundingRate;
 use model::Leverage;
 use model::LotSize;
 use model::OfferId;
+use model::OfferPair;
 use model::OrderId;
 use model::Position;
 use model::Price;
+use model::RejectReason;
 use model::Role;
 use model::Settlement;
 use model::Timestamp;
 use model::SETTLEMENT_INTERVAL;
 use parse_display::Display;
 use parse_display::FromStr;
+use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use serde::Deserialize;
@@ -75,59 +89,194 @@ pub struct CfdChanged(pub OrderId);
 #[derive(Clone, Copy)]
 struct Initialize;
 
+/// Load the archived CFDs, which are not part of the CFD feed.
+#[derive(Clone, Copy)]
+pub struct GetArchivedCfds;
+
+/// Get the CFDs as currently published on the CFD feed.
+///
+/// Fails if the CFD feed has not been initialized yet.
+#[derive(Clone, Copy)]
+pub struct GetCfds;
+
+/// Rebuild the open CFDs from their events in the background and
+/// reinitialise the CFD feed from them.
+///
+/// The CFD feed keeps serving the current state until the rebuild is
+/// complete. Fails if a rebuild is already running.
+#[derive(Clone, Copy)]
+pub struct Rebuild;
+
+/// The background rebuild started by [`Rebuild`] finished.
+struct RebuildCompleted(Result<sqlite_db::rebuild::Summary>);
+
+/// Closed and failed CFDs whose final event is older than this are
+/// archived: they are not loaded into the CFD feed on startup, but
+/// only on request through [`GetArchivedCfds`].
+pub const ARCHIVE_AFTER: time::Duration = time::Duration::days(30);
+
 pub struct Actor {
     db: sqlite_db::Connection,
     tx: Tx,
     state: State,
     price_feed: MessageChannel<GetLatestQuotes, xtra_bitmex_price_feed::LatestQuotes>,
     role: Role,
+    liquidation_alert_threshold: Decimal,
+    /// CFDs which were closed or failed before this point in time are
+    /// archived.
+    archived_before: Timestamp,
+    /// Whether a [`Rebuild`] is running
+    rebuilding: bool,
+    /// The exchange rate to value amounts in the fiat currency selected by the user
+    fiat_rate: Option<watch::Receiver<Option<FiatRate>>>,
 }
 
 pub struct FeedReceivers {
     pub quote: watch::Receiver<LatestQuotes>,
     pub offers: watch::Receiver<MakerOffers>,
     pub cfds: watch::Receiver<Option<Vec<Cfd>>>,
+    pub alerts: watch::Receiver<Vec<LiquidationAlert>>,
+    pub freshness: FeedFreshness,
 }
 
 pub struct FeedSenders {
     pub quote: watch::Sender<LatestQuotes>,
     pub offers: watch::Sender<MakerOffers>,
     pub cfds: watch::Sender<Option<Vec<Cfd>>>,
+    pub alerts: watch::Sender<Vec<LiquidationAlert>>,
+    pub freshness: FeedFreshness,
+}
+
+/// Tracks whether the consumers of each feed keep up with its updates.
+#[derive(Clone, Default)]
+pub struct FeedFreshness {
+    pub quote: Freshness,
+    pub offers: Freshness,
+    pub cfds: Freshness,
+    pub alerts: Freshness,
+}
+
+impl FeedFreshness {
+    /// All feeds by name, to be watched by a [`crate::feed_lag::Actor`].
+    pub fn feeds(&self) -> Vec<(&'static str, Freshness)> {
+        vec![
+            ("quote", self.quote.clone()),
+            ("offers", self.offers.clone()),
+            ("cfds", self.cfds.clone()),
+            ("alerts", self.alerts.clone()),
+        ]
+    }
 }
 
 pub fn feeds() -> (FeedSenders, FeedReceivers) {
     let (tx_quote, rx_quote) = watch::channel(LatestQuotes::default());
     let (tx_offers, rx_offers) = watch::channel(MakerOffers::default());
     let (tx_cfds, rx_cfds) = watch::channel(None);
+    let (tx_alerts, rx_alerts) = watch::channel(Vec::new());
+    let freshness = FeedFreshness::default();
 
     (
         FeedSenders {
             quote: tx_quote,
             offers: tx_offers,
             cfds: tx_cfds,
+            alerts: tx_alerts,
+            freshness: freshness.clone(),
         },
         FeedReceivers {
             quote: rx_quote,
             offers: rx_offers,
             cfds: rx_cfds,
+            alerts: rx_alerts,
+            freshness,
         },
     )
 }
 
 impl Actor {
+    /// Create the projection actor.
+    ///
+    /// A [`LiquidationAlert`] is raised for every open CFD whose
+    /// current price is less than `liquidation_alert_threshold`
+    /// percent away from its liquidation price.
     pub fn new(
         db: sqlite_db::Connection,
-        network: Network,
+        block_explorer: BlockExplorer,
         price_feed: MessageChannel<GetLatestQuotes, xtra_bitmex_price_feed::LatestQuotes>,
         role: Role,
         feed_senders: Arc<FeedSenders>,
+        liquidation_alert_threshold: Decimal,
     ) -> Self {
         Self {
             db,
             tx: Tx(feed_senders),
-            state: State::new(network),
+            state: State::new(block_explorer),
             price_feed,
             role,
+            liquidation_alert_threshold,
+            archived_before: Timestamp::new(
+                (OffsetDateTime::now_utc() - ARCHIVE_AFTER).unix_timestamp(),
+            ),
+            rebuilding: false,
+            fiat_rate: None,
+        }
+    }
+
+    /// Value the margin, profit and payout of the CFDs in fiat at the
+    /// exchange rates published on `fiat_rate`.
+    #[must_use]
+    pub fn with_fiat_rates(mut self, fiat_rate: watch::Receiver<Option<FiatRate>>) -> Self {
+        self.fiat_rate = Some(fiat_rate);
+        self
+    }
+
+    fn current_fiat_rate(&self) -> Option<FiatRate> {
+        self.fiat_rate.as_ref().and_then(|rate| *rate.borrow())
+    }
+
+    /// Load all recent CFDs into the CFD feed, replacing its current
+    /// state.
+    async fn initialize_cfds(&mut self) {
+        let mut stream = self
+            .db
+            .load_recent_cfds::<Cfd>(self.state.block_explorer.clone(), self.archived_before);
+
+        let mut cfds = HashMap::new();
+
+        while let Some(cfd) = stream.next().await {
+            let cfd = match cfd {
+                Ok(cfd) => cfd,
+                Err(e) => {
+                    tracing::error!("Failed to rehydrate CFD: {e:#}");
+                    continue;
+                }
+            };
+
+            let cfd = self.with_funding_history(cfd).await;
+
+            cfds.insert(cfd.order_id, cfd);
+        }
+
+        self.state.cfds = Some(cfds);
+
+        self.tx.send_cfds_update(
+            self.state
+                .cfds
+                .clone()
+                .expect("we initialized the state above; qed"),
+            &self.state.latest_quotes,
+            self.current_fiat_rate().as_ref(),
+            self.liquidation_alert_threshold,
+        );
+    }
+
+    async fn with_funding_history(&self, cfd: Cfd) -> Cfd {
+        match self.db.load_funding_history(cfd.order_id).await {
+            Ok(history) => cfd.with_funding_history(history),
+            Err(e) => {
+                tracing::error!(order_id = %cfd.order_id, "Failed to load funding history: {e:#}");
+                cfd
+            }
         }
     }
 }
@@ -153,6 +302,13 @@ pub struct Cfd {
     pub position: Position,
     #[serde(with = "round_to_two_dp")]
     pub liquidation_price: Decimal,
+    /// Distance between the current price and the liquidation price
+    ///
+    /// In percent of the current price. Negative if the current price is already past the
+    /// liquidation price. Only known for CFDs without a final payout while we have a current
+    /// price.
+    #[serde(with = "round_to_two_dp::opt")]
+    pub distance_to_liquidation: Option<Decimal>,
 
     #[serde(with = "round_to_two_dp")]
     pub quantity: Contracts,
@@ -179,8 +335,29 @@ pub struct Cfd {
     pub payout: Option<Amount>,
     pub closing_price: Option<Price>,
 
+    /// Margin valued in the fiat currency selected by the user
+    ///
+    /// Bitcoin is valued at the current BTCUSD price, hence this is only known while we have a
+    /// current price and an exchange rate. The same applies to the other fiat values.
+    pub margin_fiat: Option<FiatAmount>,
+    /// Projected or final profit valued in the fiat currency selected by the user
+    pub profit_fiat: Option<FiatAmount>,
+    /// Projected or final payout valued in the fiat currency selected by the user
+    pub payout_fiat: Option<FiatAmount>,
+
+    /// Initial price including all fees paid so far, i.e. the break-even price
+    #[serde(with = "round_to_two_dp::opt")]
+    pub effective_entry_price: Option<Price>,
+    /// Closing price including all fees, once the final payout is known
+    ///
+    /// The difference between the effective entry and exit price makes up the realized profit.
+    #[serde(with = "round_to_two_dp::opt")]
+    pub effective_exit_price: Option<Price>,
+
     pub state: CfdState,
     pub actions: HashSet<CfdAction>,
+    /// Why the maker rejected the order, if known
+    pub reject_reason: Option<RejectReason>,
 
     // TODO: This `CfdDetails` wrapper is useless and could be removed, but that would be a
     // breaking API change
@@ -193,13 +370,20 @@ pub struct Cfd {
 
     #[serde(with = "round_to_two_dp::opt")]
     pub pending_settlement_proposal_price: Option<Price>,
+    /// How the latest settlement proposal ended, if it did not lead to a settlement
+    ///
+    /// Cleared once settlement is proposed again.
+    pub settlement_proposal_outcome: Option<SettlementProposalOutcome>,
+
+    /// Funding fees charged during rollovers, oldest first
+    pub funding_history: Vec<FundingPayment>,
 
     #[serde(skip)]
     #[derivative(PartialEq = "ignore")]
     aggregated: Aggregated,
 
     #[serde(skip)]
-    network: Network,
+    block_explorer: BlockExplorer,
 }
 
 /// Bundle all state extracted from the events in one struct.
@@ -311,6 +495,15 @@ fn extract_payout_amount(tx: Transaction, script: Script) -> Amount {
         .unwrap_or(Amount::ZERO)
 }
 
+/// Why a settlement proposal did not lead to a settlement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SettlementProposalOutcome {
+    Rejected,
+    Failed,
+    /// The proposal was not decided upon within its time-to-live
+    Expired,
+}
+
 /// Capture state of protocol negotiation for the UI purposes.
 #[derive(Clone, Copy, Debug)]
 enum ProtocolNegotiationState {
@@ -336,7 +529,7 @@ impl Cfd {
             contract_symbol,
             ..
         }: sqlite_db::Cfd,
-        network: Network,
+        block_explorer: BlockExplorer,
     ) -> Self {
         let (our_leverage, counterparty_leverage) = match role {
             Role::Maker => (Leverage::ONE, taker_leverage),
@@ -386,6 +579,14 @@ impl Cfd {
             HashSet::new()
         };
 
+        let effective_entry_price = calculate_effective_entry_price(
+            contract_symbol,
+            position,
+            initial_price,
+            quantity,
+            fee_account.balance(),
+        );
+
         Self {
             order_id: id,
             offer_id,
@@ -395,6 +596,7 @@ impl Cfd {
             contract_symbol,
             position,
             liquidation_price,
+            distance_to_liquidation: None,
             quantity,
             margin,
             margin_counterparty,
@@ -404,6 +606,11 @@ impl Cfd {
             profit_percent: None,
             payout: None,
             closing_price: None,
+            margin_fiat: None,
+            profit_fiat: None,
+            payout_fiat: None,
+            effective_entry_price,
+            effective_exit_price: None,
 
             state: CfdState::PendingSetup,
             actions: initial_actions,
@@ -413,8 +620,11 @@ impl Cfd {
             expiry_timestamp: None,
             counterparty: counterparty_peer_id.unwrap_or_else(PeerId::placeholder),
             pending_settlement_proposal_price: None,
+            settlement_proposal_outcome: None,
+            reject_reason: None,
+            funding_history: Vec::new(),
             aggregated: Aggregated::new(fee_account),
-            network,
+            block_explorer,
         }
     }
 
@@ -444,8 +654,9 @@ impl Cfd {
             ContractSetupFailed => {
                 self.aggregated.state = CfdState::SetupFailed;
             }
-            OfferRejected => {
+            OfferRejected(reason) => {
                 self.aggregated.state = CfdState::Rejected;
+                self.reject_reason = reason;
             }
             RolloverCompleted {
                 dlc,
@@ -469,6 +680,13 @@ impl Cfd {
                 };
 
                 self.accumulated_fees = self.aggregated.fee_account.balance();
+                self.effective_entry_price = calculate_effective_entry_price(
+                    self.contract_symbol,
+                    self.position,
+                    self.initial_price,
+                    self.quantity,
+                    self.accumulated_fees,
+                );
 
                 self.aggregated.state = CfdState::Open;
             }
@@ -480,6 +698,7 @@ impl Cfd {
             }
             CollaborativeSettlementStarted { proposal } => {
                 self.aggregated.settlement_state = Some(ProtocolNegotiationState::Started);
+                self.settlement_proposal_outcome = None;
                 if let Role::Maker = self.role {
                     self.pending_settlement_proposal_price = Some(proposal.price);
                 };
@@ -502,10 +721,17 @@ impl Cfd {
             CollaborativeSettlementRejected => {
                 self.aggregated.settlement_state = None;
                 self.pending_settlement_proposal_price = None;
+                self.settlement_proposal_outcome = Some(SettlementProposalOutcome::Rejected);
+            }
+            CollaborativeSettlementFailed | CollaborativeSettlementAbandoned { .. } => {
+                self.aggregated.settlement_state = None;
+                self.pending_settlement_proposal_price = None;
+                self.settlement_proposal_outcome = Some(SettlementProposalOutcome::Failed);
             }
-            CollaborativeSettlementFailed => {
+            CollaborativeSettlementProposalExpired => {
                 self.aggregated.settlement_state = None;
                 self.pending_settlement_proposal_price = None;
+                self.settlement_proposal_outcome = Some(SettlementProposalOutcome::Expired);
             }
             LockConfirmed => {
                 self.aggregated.state = CfdState::Open;
@@ -569,6 +795,7 @@ impl Cfd {
 
                 self.aggregated.state = CfdState::PendingCommit;
             }
+            PriceTriggered { .. } => {}
             RevokeConfirmed => {
                 // TODO: Implement revoked logic
                 self.aggregated.state = CfdState::OpenCommitted;
@@ -578,19 +805,19 @@ impl Cfd {
         self.state = self.aggregated.derive_cfd_state(self.role);
         self.actions = self.derive_actions();
 
-        if let Some(lock_tx_url) = self.lock_tx_url(self.network) {
+        if let Some(lock_tx_url) = self.lock_tx_url() {
             self.details.tx_url_list.insert(lock_tx_url);
         }
-        if let Some(commit_tx_url) = self.commit_tx_url(self.network) {
+        if let Some(commit_tx_url) = self.commit_tx_url() {
             self.details.tx_url_list.insert(commit_tx_url);
         }
-        if let Some(collab_settlement_tx_url) = self.collab_settlement_tx_url(self.network) {
+        if let Some(collab_settlement_tx_url) = self.collab_settlement_tx_url() {
             self.details.tx_url_list.insert(collab_settlement_tx_url);
         }
-        if let Some(refund_tx_url) = self.refund_tx_url(self.network) {
+        if let Some(refund_tx_url) = self.refund_tx_url() {
             self.details.tx_url_list.insert(refund_tx_url);
         }
-        if let Some(cet_url) = self.cet_url(self.network) {
+        if let Some(cet_url) = self.cet_url() {
             self.details.tx_url_list.insert(cet_url);
         }
 
@@ -599,6 +826,36 @@ impl Cfd {
         self
     }
 
+    /// The effective exit price if the realized profit is `profit_btc`.
+    fn effective_exit_price(&self, profit_btc: SignedAmount) -> Option<Price> {
+        calculate_effective_exit_price(
+            self.contract_symbol,
+            self.position,
+            self.effective_entry_price?,
+            self.quantity,
+            profit_btc,
+        )
+    }
+
+    fn with_funding_history(self, history: Vec<sqlite_db::FundingPayment>) -> Self {
+        let funding_history = history
+            .into_iter()
+            .map(|payment| FundingPayment {
+                timestamp: payment.timestamp,
+                funding_rate_hourly_percent: HourlyFundingPercent::from(payment.funding_fee.rate)
+                    .to_string(),
+                fee: FeeAccount::new(self.position, self.role)
+                    .add_funding_fee(payment.funding_fee)
+                    .balance(),
+            })
+            .collect();
+
+        Self {
+            funding_history,
+            ..self
+        }
+    }
+
     pub fn with_current_quote(self, latest_quotes: Option<&LatestQuotes>) -> Self {
         // If the payout was already set we don't care about the current quote, this applies to
         // closed CFDs
@@ -609,11 +866,14 @@ impl Cfd {
         // If we have a dedicated closing price, use that one.
         if let Some(payout) = self.aggregated.clone().payout(self.role) {
             let (profit_btc, profit_percent) = calculate_profit(payout, self.margin);
+            let effective_exit_price = self.effective_exit_price(profit_btc);
 
             return Self {
                 payout: Some(payout),
                 profit_btc: Some(profit_btc),
                 profit_percent: Some(profit_percent.to_string()),
+                effective_exit_price,
+                distance_to_liquidation: None,
                 ..self
             };
         }
@@ -630,6 +890,7 @@ impl Cfd {
                     payout: None,
                     profit_btc: None,
                     profit_percent: None,
+                    distance_to_liquidation: None,
                     ..self
                 };
             }
@@ -646,12 +907,18 @@ impl Cfd {
                     payout: None,
                     profit_btc: None,
                     profit_percent: None,
+                    distance_to_liquidation: None,
                     ..self
                 };
             }
         };
 
         let closing_price = market_closing_price(bid, ask, self.role, self.position);
+        let distance_to_liquidation = Some(distance_to_liquidation(
+            self.position,
+            closing_price,
+            self.liquidation_price,
+        ));
 
         let (long_leverage, short_leverage) =
             long_and_short_leverage(self.leverage_taker, self.role, self.position);
@@ -677,6 +944,7 @@ impl Cfd {
                     payout: None,
                     profit_btc: None,
                     profit_percent: None,
+                    distance_to_liquidation,
                     ..self
                 };
             }
@@ -686,10 +954,121 @@ impl Cfd {
             payout: Some(payout),
             profit_btc: Some(profit_btc),
             profit_percent: Some(profit_percent),
+            distance_to_liquidation,
             ..self
         }
     }
 
+    /// Value the margin, profit and payout in fiat at the current BTCUSD
+    /// price and `fiat_rate`.
+    fn with_fiat_values(self, quotes: &LatestQuotes, fiat_rate: Option<&FiatRate>) -> Self {
+        let (fiat_rate, btc_usd_price) = match (fiat_rate, btc_usd_price(quotes)) {
+            (Some(fiat_rate), Some(btc_usd_price)) => (fiat_rate, btc_usd_price),
+            _ => {
+                return Self {
+                    margin_fiat: None,
+                    profit_fiat: None,
+                    payout_fiat: None,
+                    ..self
+                }
+            }
+        };
+
+        let value = |amount: SignedAmount| fiat_rate.value(amount, btc_usd_price);
+
+        Self {
+            margin_fiat: self.margin.to_signed().ok().map(value),
+            profit_fiat: self.profit_btc.map(value),
+            payout_fiat: self
+                .payout
+                .and_then(|payout| payout.to_signed().ok())
+                .map(value),
+            ..self
+        }
+    }
+
+    /// The alert to raise if the CFD is within `threshold` percent of
+    /// being liquidated.
+    fn liquidation_alert(&self, threshold: Decimal) -> Option<LiquidationAlert> {
+        if !self.state.has_open_position() {
+            return None;
+        }
+
+        let distance_to_liquidation = self.distance_to_liquidation?;
+        if distance_to_liquidation >= threshold {
+            return None;
+        }
+
+        Some(LiquidationAlert {
+            order_id: self.order_id,
+            contract_symbol: self.contract_symbol,
+            position: self.position,
+            liquidation_price: self.liquidation_price,
+            distance_to_liquidation,
+        })
+    }
+
+    /// Project the fees of the CFD over the next `n_rollovers` rollovers.
+    ///
+    /// Every rollover extends the CFD by [`SETTLEMENT_INTERVAL`] and is charged at the funding rate
+    /// scheduled for it in `funding_rates`. The last scheduled rate applies to all rollovers
+    /// beyond the end of the schedule.
+    pub fn simulate_rollovers(
+        &self,
+        funding_rates: &[FundingRate],
+        n_rollovers: u32,
+    ) -> Result<RolloverSimulation> {
+        let (long_leverage, short_leverage) =
+            long_and_short_leverage(self.leverage_taker, self.role, self.position);
+        let margin = self.margin.to_signed()?;
+
+        let mut fee_account = self.aggregated.fee_account;
+        let mut rollovers = Vec::new();
+        for rollover in 1..=n_rollovers {
+            let funding_rate = *funding_rates
+                .get(rollover as usize - 1)
+                .or_else(|| funding_rates.last())
+                .context("No funding rate scheduled")?;
+
+            let funding_fee = FundingFee::calculate(
+                self.initial_price,
+                self.quantity,
+                long_leverage,
+                short_leverage,
+                funding_rate,
+                SETTLEMENT_INTERVAL.whole_hours(),
+                self.contract_symbol,
+            )?;
+
+            let fees_before = fee_account.balance();
+            fee_account = fee_account.add_funding_fee(funding_fee);
+            let accumulated_fees = fee_account.balance();
+            let remaining_margin = margin - accumulated_fees;
+
+            rollovers.push(SimulatedRollover {
+                rollover,
+                funding_rate_hourly_percent: HourlyFundingPercent::from(funding_rate).to_string(),
+                funding_fee: accumulated_fees - fees_before,
+                accumulated_fees,
+                remaining_margin,
+                breaches_margin: !remaining_margin.is_positive(),
+            });
+        }
+
+        let first_breach = rollovers
+            .iter()
+            .find(|rollover| rollover.breaches_margin)
+            .map(|rollover| rollover.rollover);
+
+        Ok(RolloverSimulation {
+            order_id: self.order_id,
+            margin: self.margin,
+            accumulated_fees: self.accumulated_fees,
+            rollovers,
+            first_breach,
+        })
+    }
+
     fn derive_actions(&self) -> HashSet<CfdAction> {
         match (self.state, self.role) {
             (CfdState::PendingSetup, Role::Maker) => {
@@ -720,42 +1099,61 @@ impl Cfd {
     /// Returns the URL to the lock transaction.
     ///
     /// If we have a DLC, we also have a lock transaction.
-    fn lock_tx_url(&self, network: Network) -> Option<TxUrl> {
+    fn lock_tx_url(&self) -> Option<TxUrl> {
         let dlc = self.aggregated.latest_dlc.as_ref()?;
         let url = TxUrl::from_transaction(
             &dlc.lock.0.clone(),
             &dlc.lock.1.script_pubkey(),
-            network,
+            &self.block_explorer,
             TxLabel::Lock,
         );
 
         Some(url)
     }
 
-    fn commit_tx_url(&self, network: Network) -> Option<TxUrl> {
+    fn commit_tx_url(&self) -> Option<TxUrl> {
         if !self.aggregated.commit_published {
             return None;
         }
 
         let dlc = self.aggregated.latest_dlc.as_ref()?;
-        let url = TxUrl::new(dlc.commit.0.txid(), network, TxLabel::Commit);
+        let url = TxUrl::new(dlc.commit.0.txid(), &self.block_explorer, TxLabel::Commit);
 
         Some(url)
     }
 
+    /// When the order for this CFD was placed
+    pub fn creation_timestamp(&self) -> Timestamp {
+        self.aggregated.creation_timestamp
+    }
+
+    /// The DLC of the CFD, once the contract has been set up
+    pub fn dlc(&self) -> Option<&Dlc> {
+        self.aggregated.latest_dlc.as_ref()
+    }
+
+    /// The transactions of the CFD which have been published, from the
+    /// lock transaction to the one which settled it
+    pub fn transactions(&self) -> Vec<TxUrl> {
+        let mut transactions = self.details.tx_url_list.iter().cloned().collect::<Vec<_>>();
+        transactions.sort_by_key(|tx| tx.label);
+
+        transactions
+    }
+
     // Only used in integration tests
     pub fn aggregated(&self) -> &Aggregated {
         &self.aggregated
     }
 
-    fn collab_settlement_tx_url(&self, network: Network) -> Option<TxUrl> {
+    fn collab_settlement_tx_url(&self) -> Option<TxUrl> {
         let (tx, script) = self.aggregated.collab_settlement_tx.as_ref()?;
-        let url = TxUrl::from_transaction(tx, script, network, TxLabel::Collaborative);
+        let url = TxUrl::from_transaction(tx, script, &self.block_explorer, TxLabel::Collaborative);
 
         Some(url)
     }
 
-    fn refund_tx_url(&self, network: Network) -> Option<TxUrl> {
+    fn refund_tx_url(&self) -> Option<TxUrl> {
         if !self.aggregated.refund_published {
             return None;
         }
@@ -765,49 +1163,166 @@ impl Cfd {
         let url = TxUrl::from_transaction(
             &dlc.refund.0.clone(),
             &dlc.script_pubkey_for(self.role),
-            network,
+            &self.block_explorer,
             TxLabel::Refund,
         );
 
         Some(url)
     }
 
-    fn cet_url(&self, network: Network) -> Option<TxUrl> {
+    fn cet_url(&self) -> Option<TxUrl> {
         let tx = self.aggregated.cet.as_ref()?;
         let dlc = self.aggregated.latest_dlc.as_ref()?;
 
-        let url =
-            TxUrl::from_transaction(tx, &dlc.script_pubkey_for(self.role), network, TxLabel::Cet);
+        let url = TxUrl::from_transaction(
+            tx,
+            &dlc.script_pubkey_for(self.role),
+            &self.block_explorer,
+            TxLabel::Cet,
+        );
 
         Some(url)
     }
 }
 
+/// Distance between `price` and `liquidation_price` in percent of `price`.
+///
+/// Positive as long as a `position` entered at a price between the
+/// two would not be liquidated at `price`.
+fn distance_to_liquidation(
+    position: Position,
+    price: Price,
+    liquidation_price: Decimal,
+) -> Decimal {
+    let price = price.into_decimal();
+
+    let distance = match position {
+        Position::Long => price - liquidation_price,
+        Position::Short => liquidation_price - price,
+    };
+
+    distance / price * dec!(100)
+}
+
+/// Warning that an open CFD is about to be liquidated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LiquidationAlert {
+    pub order_id: OrderId,
+    pub contract_symbol: ContractSymbol,
+    pub position: Position,
+    #[serde(with = "round_to_two_dp")]
+    pub liquidation_price: Decimal,
+    /// Distance between the current price and the liquidation price
+    /// in percent of the current price
+    #[serde(with = "round_to_two_dp")]
+    pub distance_to_liquidation: Decimal,
+}
+
+/// Projection of the fees of a CFD over future rollovers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RolloverSimulation {
+    pub order_id: OrderId,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub margin: Amount,
+    /// Fees accumulated before the first simulated rollover
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub accumulated_fees: SignedAmount,
+    pub rollovers: Vec<SimulatedRollover>,
+    /// The first rollover after which the accumulated fees would consume the whole margin
+    ///
+    /// `None` if the margin lasts for all simulated rollovers.
+    pub first_breach: Option<u32>,
+}
+
+/// A simulated rollover, from our perspective
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SimulatedRollover {
+    /// The number of the rollover, starting at 1 for the next one
+    pub rollover: u32,
+    pub funding_rate_hourly_percent: String,
+    /// The funding fee charged for the rollover
+    ///
+    /// A positive fee is paid by us, a negative fee is paid to us.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub funding_fee: SignedAmount,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub accumulated_fees: SignedAmount,
+    /// The margin left after deducting the accumulated fees
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub remaining_margin: SignedAmount,
+    /// Whether the accumulated fees consume the whole margin
+    pub breaches_margin: bool,
+}
+
 /// Internal struct to keep all the senders around in one place
 struct Tx(Arc<FeedSenders>);
 
 impl Tx {
-    fn send_cfds_update(&self, cfds: HashMap<OrderId, Cfd>, quotes: &LatestQuotes) {
+    fn send_cfds_update(
+        &self,
+        cfds: HashMap<OrderId, Cfd>,
+        quotes: &LatestQuotes,
+        fiat_rate: Option<&FiatRate>,
+        liquidation_alert_threshold: Decimal,
+    ) {
         let cfds_with_quote = cfds
             .into_iter()
-            .map(|(_, cfd)| cfd.with_current_quote(Some(quotes)))
+            .map(|(_, cfd)| {
+                cfd.with_current_quote(Some(quotes))
+                    .with_fiat_values(quotes, fiat_rate)
+            })
             .sorted_by(|a, b| {
                 Ord::cmp(
                     &b.aggregated.creation_timestamp,
                     &a.aggregated.creation_timestamp,
                 )
             })
-            .collect();
+            .collect::<Vec<_>>();
+
+        self.send_alerts_update(&cfds_with_quote, liquidation_alert_threshold);
 
         let _ = self.0.cfds.send(Some(cfds_with_quote));
+        self.0.freshness.cfds.produced();
+    }
+
+    /// Publish the liquidation alerts of `cfds`.
+    ///
+    /// Consumers are only notified if a CFD crossed the threshold in
+    /// either direction, not whenever the distance of an alerted CFD
+    /// changes.
+    fn send_alerts_update(&self, cfds: &[Cfd], threshold: Decimal) {
+        let alerts = cfds
+            .iter()
+            .filter_map(|cfd| cfd.liquidation_alert(threshold))
+            .collect::<Vec<_>>();
+
+        let crossed = self.0.alerts.send_if_modified(|current| {
+            let order_ids = |alerts: &[LiquidationAlert]| {
+                alerts
+                    .iter()
+                    .map(|alert| alert.order_id)
+                    .collect::<HashSet<_>>()
+            };
+            let crossed = order_ids(current) != order_ids(&alerts);
+
+            *current = alerts;
+
+            crossed
+        });
+
+        if crossed {
+            self.0.freshness.alerts.produced();
+        }
     }
 
     fn send_quotes_update(&self, quotes: LatestQuotes) {
         let _ = self.0.quote.send(quotes);
+        self.0.freshness.quote.produced();
     }
 
     fn send_offer_update(&self, offers: MakerOffers) -> Result<()> {
         self.0.offers.send(offers)?;
+        self.0.freshness.offers.produced();
 
         Ok(())
     }
@@ -815,7 +1330,7 @@ impl Tx {
 
 /// Internal struct to keep state in one place
 struct State {
-    network: Network,
+    block_explorer: BlockExplorer,
     latest_quotes: LatestQuotes,
     offers: MakerOffers,
     /// All hydrated CFDs.
@@ -823,7 +1338,7 @@ struct State {
 }
 
 impl sqlite_db::CfdAggregate for Cfd {
-    type CtorArgs = Network;
+    type CtorArgs = BlockExplorer;
 
     fn new(args: Self::CtorArgs, cfd: sqlite_db::Cfd) -> Self {
         Cfd::new(cfd, args)
@@ -839,7 +1354,7 @@ impl sqlite_db::CfdAggregate for Cfd {
 }
 
 impl sqlite_db::ClosedCfdAggregate for Cfd {
-    fn new_closed(network: Self::CtorArgs, closed_cfd: ClosedCfd) -> Self {
+    fn new_closed(block_explorer: Self::CtorArgs, closed_cfd: ClosedCfd) -> Self {
         let ClosedCfd {
             id,
             offer_id,
@@ -884,7 +1399,7 @@ impl sqlite_db::ClosedCfdAggregate for Cfd {
             let mut tx_url_list = HashSet::default();
 
             tx_url_list.insert(
-                TxUrl::new(lock.txid, network, TxLabel::Lock)
+                TxUrl::new(lock.txid, &block_explorer, TxLabel::Lock)
                     .with_output_index(lock.dlc_vout.into()),
             );
 
@@ -896,7 +1411,7 @@ impl sqlite_db::ClosedCfdAggregate for Cfd {
                     price,
                 } => {
                     tx_url_list.insert(
-                        TxUrl::new(txid, network, TxLabel::Collaborative)
+                        TxUrl::new(txid, &block_explorer, TxLabel::Collaborative)
                             .with_output_index(vout.into()),
                     );
                     (Some(price), payout, CfdState::Closed)
@@ -909,11 +1424,13 @@ impl sqlite_db::ClosedCfdAggregate for Cfd {
                     price,
                 } => {
                     tx_url_list.insert(
-                        TxUrl::new(commit_txid, network, TxLabel::Commit).with_output_index(0),
+                        TxUrl::new(commit_txid, &block_explorer, TxLabel::Commit)
+                            .with_output_index(0),
                     );
 
                     tx_url_list.insert(
-                        TxUrl::new(txid, network, TxLabel::Cet).with_output_index(vout.into()),
+                        TxUrl::new(txid, &block_explorer, TxLabel::Cet)
+                            .with_output_index(vout.into()),
                     );
                     (Some(price), payout, CfdState::Closed)
                 }
@@ -924,11 +1441,13 @@ impl sqlite_db::ClosedCfdAggregate for Cfd {
                     payout,
                 } => {
                     tx_url_list.insert(
-                        TxUrl::new(commit_txid, network, TxLabel::Commit).with_output_index(0),
+                        TxUrl::new(commit_txid, &block_explorer, TxLabel::Commit)
+                            .with_output_index(0),
                     );
 
                     tx_url_list.insert(
-                        TxUrl::new(txid, network, TxLabel::Refund).with_output_index(vout.into()),
+                        TxUrl::new(txid, &block_explorer, TxLabel::Refund)
+                            .with_output_index(vout.into()),
                     );
                     (None, payout, CfdState::Refunded)
                 }
@@ -939,6 +1458,23 @@ impl sqlite_db::ClosedCfdAggregate for Cfd {
 
         let (profit_btc, profit_percent) = calculate_profit(payout.inner(), margin);
 
+        let effective_entry_price = calculate_effective_entry_price(
+            contract_symbol,
+            position,
+            initial_price,
+            quantity,
+            fees.into(),
+        );
+        let effective_exit_price = effective_entry_price.and_then(|effective_entry_price| {
+            calculate_effective_exit_price(
+                contract_symbol,
+                position,
+                effective_entry_price,
+                quantity,
+                profit_btc,
+            )
+        });
+
         // there are no events to apply at this stage for closed CFDs,
         // which is why this field is mostly ignored
         let mut aggregated = Aggregated::new(FeeAccount::new(position, role));
@@ -955,6 +1491,7 @@ impl sqlite_db::ClosedCfdAggregate for Cfd {
             contract_symbol,
             position,
             liquidation_price,
+            distance_to_liquidation: None,
             quantity,
             margin,
             margin_counterparty,
@@ -964,6 +1501,11 @@ impl sqlite_db::ClosedCfdAggregate for Cfd {
             profit_percent: Some(profit_percent.to_string()),
             payout: Some(payout.inner()),
             closing_price,
+            margin_fiat: None,
+            profit_fiat: None,
+            payout_fiat: None,
+            effective_entry_price,
+            effective_exit_price,
 
             state,
             actions: HashSet::default(),
@@ -971,14 +1513,17 @@ impl sqlite_db::ClosedCfdAggregate for Cfd {
             expiry_timestamp: Some(expiry_timestamp),
             counterparty: counterparty_peer_id,
             pending_settlement_proposal_price: None,
+            settlement_proposal_outcome: None,
+            reject_reason: None,
+            funding_history: Vec::new(),
             aggregated,
-            network,
+            block_explorer,
         }
     }
 }
 
 impl sqlite_db::FailedCfdAggregate for Cfd {
-    fn new_failed(network: Self::CtorArgs, failed_cfd: FailedCfd) -> Self {
+    fn new_failed(block_explorer: Self::CtorArgs, failed_cfd: FailedCfd) -> Self {
         let FailedCfd {
             id,
             offer_id,
@@ -992,6 +1537,7 @@ impl sqlite_db::FailedCfdAggregate for Cfd {
             kind,
             creation_timestamp,
             contract_symbol,
+            reject_reason,
             ..
         } = failed_cfd;
 
@@ -1038,6 +1584,7 @@ impl sqlite_db::FailedCfdAggregate for Cfd {
             contract_symbol,
             position,
             liquidation_price,
+            distance_to_liquidation: None,
             quantity,
             margin,
             margin_counterparty,
@@ -1047,6 +1594,11 @@ impl sqlite_db::FailedCfdAggregate for Cfd {
             profit_percent: None,
             payout: None,
             closing_price: None,
+            margin_fiat: None,
+            profit_fiat: None,
+            payout_fiat: None,
+            effective_entry_price: None,
+            effective_exit_price: None,
 
             state,
             actions: HashSet::default(),
@@ -1056,16 +1608,19 @@ impl sqlite_db::FailedCfdAggregate for Cfd {
             expiry_timestamp: None,
             counterparty: counterparty_peer_id,
             pending_settlement_proposal_price: None,
+            settlement_proposal_outcome: None,
+            reject_reason,
+            funding_history: Vec::new(),
             aggregated,
-            network,
+            block_explorer,
         }
     }
 }
 
 impl State {
-    fn new(network: Network) -> Self {
+    fn new(block_explorer: BlockExplorer) -> Self {
         Self {
-            network,
+            block_explorer,
             latest_quotes: LatestQuotes::default(),
             cfds: None,
             offers: MakerOffers::default(),
@@ -1073,7 +1628,10 @@ impl State {
     }
 
     async fn update_cfd(&mut self, db: sqlite_db::Connection, id: OrderId) -> Result<()> {
-        let cfd = db.load_open_cfd(id, self.network).await?;
+        let cfd = db
+            .load_open_cfd::<Cfd>(id, self.block_explorer.clone())
+            .await?;
+        let cfd = cfd.with_funding_history(db.load_funding_history(id).await?);
 
         let cfds = self
             .cfds
@@ -1089,29 +1647,21 @@ impl State {
         self.latest_quotes = quotes;
     }
 
-    fn update_offers(&mut self, new_offers: Vec<CfdOffer>) {
-        for new_offer in new_offers.into_iter() {
-            match &new_offer {
-                CfdOffer {
-                    contract_symbol: ContractSymbol::BtcUsd,
-                    position_maker: Position::Long,
-                    ..
-                } => self.offers.btcusd_long = Some(new_offer),
-                CfdOffer {
-                    contract_symbol: ContractSymbol::BtcUsd,
-                    position_maker: Position::Short,
-                    ..
-                } => self.offers.btcusd_short = Some(new_offer),
-                CfdOffer {
-                    contract_symbol: ContractSymbol::EthUsd,
-                    position_maker: Position::Long,
-                    ..
-                } => self.offers.ethusd_long = Some(new_offer),
-                CfdOffer {
-                    contract_symbol: ContractSymbol::EthUsd,
-                    position_maker: Position::Short,
-                    ..
-                } => self.offers.ethusd_short = Some(new_offer),
+    /// Replace both offers of `contract_symbol`.
+    fn update_offers(
+        &mut self,
+        contract_symbol: ContractSymbol,
+        long: Option<CfdOffer>,
+        short: Option<CfdOffer>,
+    ) {
+        match contract_symbol {
+            ContractSymbol::BtcUsd => {
+                self.offers.btcusd_long = long;
+                self.offers.btcusd_short = short;
+            }
+            ContractSymbol::EthUsd => {
+                self.offers.ethusd_long = long;
+                self.offers.ethusd_short = short;
             }
         }
     }
@@ -1120,31 +1670,91 @@ impl State {
 #[xtra_productivity]
 impl Actor {
     async fn handle(&mut self, _: Initialize) {
-        let mut stream = self.db.load_all_cfds::<Cfd>(self.state.network);
+        self.initialize_cfds().await;
+    }
 
-        let mut cfds = HashMap::new();
+    async fn handle(&mut self, _: GetArchivedCfds) -> Vec<Cfd> {
+        let mut stream = self
+            .db
+            .load_archived_cfds::<Cfd>(self.state.block_explorer.clone(), self.archived_before);
+
+        let mut cfds = Vec::new();
 
         while let Some(cfd) = stream.next().await {
             let cfd = match cfd {
                 Ok(cfd) => cfd,
                 Err(e) => {
-                    tracing::error!("Failed to rehydrate CFD: {e:#}");
+                    tracing::error!("Failed to rehydrate archived CFD: {e:#}");
                     continue;
                 }
             };
 
-            cfds.insert(cfd.order_id, cfd);
+            let cfd = self.with_funding_history(cfd).await;
+
+            cfds.push(cfd.with_current_quote(None));
         }
 
-        self.state.cfds = Some(cfds);
+        cfds.sort_by(|a, b| {
+            Ord::cmp(
+                &b.aggregated.creation_timestamp,
+                &a.aggregated.creation_timestamp,
+            )
+        });
 
-        self.tx.send_cfds_update(
-            self.state
-                .cfds
-                .clone()
-                .expect("we initialized the state above; qed"),
-            &self.state.latest_quotes,
-        );
+        cfds
+    }
+
+    fn handle(&mut self, _: GetCfds) -> Result<Vec<Cfd>> {
+        self.tx
+            .0
+            .cfds
+            .borrow()
+            .clone()
+            .context("CFD feed has not been initialized yet")
+    }
+
+    async fn handle(&mut self, _: Rebuild, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        if self.rebuilding {
+            bail!("The projection is already being rebuilt");
+        }
+
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(&this.clone(), {
+            let db = self.db.clone();
+            let block_explorer = self.state.block_explorer.clone();
+
+            async move {
+                let summary = db.rebuild_open_cfds::<Cfd>(block_explorer).await;
+                let _ = this.send(RebuildCompleted(summary)).await;
+            }
+        });
+
+        self.rebuilding = true;
+        tracing::info!("Rebuilding the projection in the background");
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, msg: RebuildCompleted) {
+        self.rebuilding = false;
+
+        match msg.0 {
+            Ok(summary) => {
+                // The cached aggregates have been replaced by the rebuilt
+                // ones, loading the CFDs again applies any events which
+                // were appended during the rebuild
+                self.initialize_cfds().await;
+
+                tracing::info!(
+                    rebuilt = summary.rebuilt,
+                    failed = summary.failed,
+                    "Rebuilt the projection"
+                );
+            }
+            Err(e) => {
+                tracing::error!("Failed to rebuild the projection: {e:#}");
+            }
+        }
     }
 
     async fn handle(&mut self, msg: CfdChanged) {
@@ -1159,23 +1769,28 @@ impl Actor {
                 .clone()
                 .expect("update_cfd fails if the CFDs have not been initialized yet"),
             &self.state.latest_quotes,
+            self.current_fiat_rate().as_ref(),
+            self.liquidation_alert_threshold,
         );
     }
 
-    fn handle(&mut self, msg: Update<Vec<model::Offer>>) {
-        let new_offers = msg
-            .0
-            .into_iter()
-            .filter_map(|offer| match CfdOffer::new(offer, self.role) {
-                Ok(offer) => Some(offer),
-                Err(e) => {
-                    tracing::warn!("Failed to build CfdOffer from model::Offer: {e:#}");
-                    None
-                }
-            })
-            .collect_vec();
+    fn handle(&mut self, msg: Update<Vec<OfferPair>>) {
+        let role = self.role;
+        let into_cfd_offer = |offer: Option<model::Offer>| match CfdOffer::new(offer?, role) {
+            Ok(offer) => Some(offer),
+            Err(e) => {
+                tracing::warn!("Failed to build CfdOffer from model::Offer: {e:#}");
+                None
+            }
+        };
 
-        self.state.update_offers(new_offers);
+        for pair in msg.0.into_iter() {
+            self.state.update_offers(
+                pair.contract_symbol,
+                into_cfd_offer(pair.long),
+                into_cfd_offer(pair.short),
+            );
+        }
 
         if let Err(e) = self.tx.send_offer_update(self.state.offers.clone()) {
             tracing::error!("Failed to propagate offer update: {e:#}");
@@ -1194,7 +1809,12 @@ impl Actor {
             Some(cfds) => cfds,
         };
 
-        self.tx.send_cfds_update(hydrated_cfds, &msg.0);
+        self.tx.send_cfds_update(
+            hydrated_cfds,
+            &msg.0,
+            self.current_fiat_rate().as_ref(),
+            self.liquidation_alert_threshold,
+        );
     }
 }
 
@@ -1262,8 +1882,17 @@ impl From<xtra_bitmex_price_feed::Quote> for Quote {
 
 pub type LatestQuotes = HashMap<ContractSymbol, Quote>;
 
+/// The price of bitcoin in US dollars, i.e. the mid price of the BTCUSD quote.
+pub fn btc_usd_price(quotes: &LatestQuotes) -> Option<Price> {
+    let quote = quotes.get(&ContractSymbol::BtcUsd)?;
+
+    Price::new((quote.bid + quote.ask) / Decimal::TWO).ok()
+}
+
 /// Converts between ContractSymbol types
-fn as_contract_symbol(symbol: &xtra_bitmex_price_feed::ContractSymbol) -> ContractSymbol {
+pub(crate) fn as_contract_symbol(
+    symbol: &xtra_bitmex_price_feed::ContractSymbol,
+) -> ContractSymbol {
     match symbol {
         xtra_bitmex_price_feed::ContractSymbol::BtcUsd => ContractSymbol::BtcUsd,
         xtra_bitmex_price_feed::ContractSymbol::EthUsd => ContractSymbol::EthUsd,
@@ -1286,6 +1915,21 @@ pub struct MakerOffers {
     pub ethusd_short: Option<CfdOffer>,
 }
 
+impl MakerOffers {
+    /// Look up the offer with the given id.
+    pub fn get(&self, id: OfferId) -> Option<&CfdOffer> {
+        [
+            &self.btcusd_long,
+            &self.btcusd_short,
+            &self.ethusd_long,
+            &self.ethusd_short,
+        ]
+        .into_iter()
+        .flatten()
+        .find(|offer| offer.id == id)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct CfdOffer {
     pub id: OfferId,
@@ -1305,6 +1949,16 @@ pub struct CfdOffer {
     #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
     pub opening_fee: Option<Amount>,
 
+    /// Fees the maker waives as part of a promotion
+    ///
+    /// The `opening_fee` and `initial_funding_fee_per_lot` already reflect the subsidy.
+    pub fee_subsidy: Option<FeeSubsidy>,
+
+    /// The balance the taker's wallet needs to take the offer, if the
+    /// maker requires more than the margin
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub min_taker_balance: Option<Amount>,
+
     /// The interest as annualized percentage
     ///
     /// This is an estimate as the funding rate can fluctuate
@@ -1396,7 +2050,7 @@ impl CfdOffer {
                     lot_size.into(),
                     long_leverage,
                     short_leverage,
-                    offer.funding_rate,
+                    offer.charged_initial_funding_rate(),
                     SETTLEMENT_INTERVAL.whole_hours(),
                     offer.contract_symbol,
                 )
@@ -1432,12 +2086,219 @@ impl CfdOffer {
                 .whole_seconds()
                 .try_into()
                 .context("unable to convert settlement interval")?,
-            opening_fee: Some(offer.opening_fee.to_inner()),
+            opening_fee: Some(offer.charged_opening_fee().to_inner()),
+            fee_subsidy: offer.fee_subsidy,
+            min_taker_balance: offer.min_taker_balance,
             funding_rate_annualized_percent: AnnualisedFundingPercent::from(offer.funding_rate)
                 .to_string(),
             funding_rate_hourly_percent: HourlyFundingPercent::from(offer.funding_rate).to_string(),
         })
     }
+
+    /// Convert a quantity entered for this offer into all denominations and validate it.
+    ///
+    /// Quantities entered in sats are rounded down to a multiple of the lot size. The value in US
+    /// dollars of an ETHUSD offer is only known if we have a BTCUSD quote.
+    pub fn quantity_details(
+        &self,
+        input: QuantityInput,
+        latest_quotes: &LatestQuotes,
+    ) -> QuantityDetails {
+        let quantity = match input {
+            QuantityInput::Contracts(quantity) => quantity,
+            QuantityInput::Sats(amount) => {
+                sats_to_contracts(self.contract_symbol, self.price, amount, self.lot_size)
+            }
+        };
+
+        let sats = contracts_to_sats(self.contract_symbol, self.price, quantity);
+
+        let btc_price = match self.contract_symbol {
+            ContractSymbol::BtcUsd => Some(self.price),
+            ContractSymbol::EthUsd => latest_quotes
+                .get(&ContractSymbol::BtcUsd)
+                .and_then(|quote| Price::new((quote.bid + quote.ask) / dec!(2)).ok()),
+        };
+
+        let error = validate_quantity(
+            quantity,
+            self.min_quantity,
+            self.max_quantity,
+            self.lot_size,
+        )
+        .err()
+        .map(|e| e.to_string());
+
+        QuantityDetails {
+            quantity,
+            sats,
+            usd: btc_price.map(|btc_price| sats_to_usd(sats, btc_price)),
+            error,
+        }
+    }
+
+    /// Size a taker position on this offer, so that closing it at the stop price loses at most
+    /// the given share of the balance.
+    ///
+    /// The position opens at the entry price, which defaults to the price of the offer. We pick
+    /// the highest leverage whose liquidation price lies beyond the stop price, so that the stop
+    /// is reached before the position is liquidated. The loss is computed with the same payout
+    /// formulas as the CFD itself, without fees.
+    pub fn position_size(&self, input: PositionSizeInput) -> Result<PositionSize> {
+        let PositionSizeInput {
+            balance,
+            risk_percent,
+            entry_price,
+            stop_price,
+        } = input;
+
+        let position = self.position_maker.counter_position();
+        let entry_price = entry_price.unwrap_or(self.price);
+
+        if risk_percent <= Decimal::ZERO || risk_percent > dec!(100) {
+            bail!("Risk of {risk_percent}% is not between 0% and 100%");
+        }
+
+        let stop_is_on_losing_side = match position {
+            Position::Long => stop_price < entry_price,
+            Position::Short => stop_price > entry_price,
+        };
+        if !stop_is_on_losing_side {
+            bail!(
+                "Stop price {stop_price} does not limit the loss of a {position:?} position at \
+                 {entry_price}"
+            );
+        }
+
+        let (leverage, liquidation_price) = self
+            .leverage_details
+            .iter()
+            .map(|details| {
+                let liquidation_price = match position {
+                    Position::Long => calculate_long_liquidation_price(
+                        entry_price,
+                        details.leverage,
+                        self.contract_symbol,
+                    ),
+                    Position::Short => calculate_short_liquidation_price(
+                        entry_price,
+                        details.leverage,
+                        self.contract_symbol,
+                    ),
+                };
+
+                (details.leverage, liquidation_price)
+            })
+            .filter(|(_, liquidation_price)| match position {
+                Position::Long => *liquidation_price < stop_price.into_decimal(),
+                Position::Short => *liquidation_price > stop_price.into_decimal(),
+            })
+            .max_by_key(|(leverage, _)| leverage.get())
+            .with_context(|| {
+                format!("Stop price {stop_price} is beyond the liquidation price of all leverages")
+            })?;
+
+        let loss_at_stop = |quantity: Contracts| -> Result<Amount> {
+            let (long_leverage, short_leverage) =
+                long_and_short_leverage(leverage, Role::Taker, position);
+
+            let payout = calculate_payout_at_price(
+                self.contract_symbol,
+                entry_price,
+                stop_price,
+                quantity,
+                long_leverage,
+                short_leverage,
+                FeeAccount::new(position, Role::Taker),
+            )?;
+            let margin = calculate_margin(self.contract_symbol, entry_price, quantity, leverage);
+
+            Ok(margin.checked_sub(payout).unwrap_or(Amount::ZERO))
+        };
+
+        let lot_size = Contracts::from(self.lot_size);
+        let loss_per_lot = loss_at_stop(lot_size)?;
+        if loss_per_lot == Amount::ZERO {
+            bail!("Stop price {stop_price} is too close to the entry price {entry_price}");
+        }
+
+        let risk = (Decimal::from(balance.as_sat()) * risk_percent / dec!(100))
+            .floor()
+            .to_u64()
+            .expect("risk to fit into u64");
+        let lots = risk / loss_per_lot.as_sat();
+
+        let mut quantity = Contracts::new(lots * lot_size.to_u64());
+        if quantity > self.max_quantity {
+            quantity = self.max_quantity;
+        }
+        if quantity < self.min_quantity {
+            bail!(
+                "Risking {risk} sats is not enough for the minimum quantity of {}",
+                self.min_quantity
+            );
+        }
+
+        let margin = calculate_margin(self.contract_symbol, entry_price, quantity, leverage);
+        if margin > balance {
+            bail!("Balance of {balance} does not cover the margin of {margin}");
+        }
+
+        Ok(PositionSize {
+            quantity,
+            leverage,
+            margin,
+            liquidation_price,
+            loss_at_stop: loss_at_stop(quantity)?,
+        })
+    }
+}
+
+/// A quantity entered by the user, either in contracts or in sats.
+#[derive(Debug, Clone, Copy)]
+pub enum QuantityInput {
+    Contracts(Contracts),
+    Sats(Amount),
+}
+
+/// A quantity entered for an offer, in all denominations.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QuantityDetails {
+    #[serde(with = "round_to_two_dp")]
+    pub quantity: Contracts,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
+    pub sats: Amount,
+    /// Value of the quantity in US dollars, if known
+    #[serde(with = "round_to_two_dp::opt")]
+    pub usd: Option<Decimal>,
+    /// Why the quantity cannot be entered for the offer
+    pub error: Option<String>,
+}
+
+/// The risk parameters to size a position with.
+#[derive(Debug, Clone, Copy)]
+pub struct PositionSizeInput {
+    pub balance: Amount,
+    /// Share of the balance which may be lost at the stop price
+    pub risk_percent: Decimal,
+    /// The price the position opens at, if not the price of the offer
+    pub entry_price: Option<Price>,
+    pub stop_price: Price,
+}
+
+/// A position sized according to [`PositionSizeInput`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PositionSize {
+    #[serde(with = "round_to_two_dp")]
+    pub quantity: Contracts,
+    pub leverage: Leverage,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub margin: Amount,
+    #[serde(with = "round_to_two_dp")]
+    pub liquidation_price: Decimal,
+    /// What closing the position at the stop price loses, excluding fees
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub loss_at_stop: Amount,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
@@ -1460,11 +2321,181 @@ pub enum CfdState {
     SetupFailed,
 }
 
+impl CfdState {
+    /// Whether the CFD's margin is locked up, i.e. the CFD contributes
+    /// to our exposure.
+    pub fn has_open_position(&self) -> bool {
+        matches!(
+            self,
+            CfdState::PendingOpen
+                | CfdState::Open
+                | CfdState::PendingCommit
+                | CfdState::PendingCet
+                | CfdState::PendingClose
+                | CfdState::OpenCommitted
+                | CfdState::IncomingSettlementProposal
+                | CfdState::OutgoingSettlementProposal
+                | CfdState::RolloverSetup
+                | CfdState::PendingRefund
+        )
+    }
+}
+
+/// All open CFDs with the same contract symbol netted into a single
+/// synthetic position.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AggregatedPosition {
+    pub contract_symbol: ContractSymbol,
+    /// The direction of the net position
+    ///
+    /// `None` if long and short CFDs cancel each other out.
+    pub position: Option<Position>,
+    #[serde(with = "round_to_two_dp")]
+    pub net_quantity: Contracts,
+    /// Average entry price of the CFDs on the side of the net position
+    #[serde(with = "round_to_two_dp::opt")]
+    pub average_entry_price: Option<Price>,
+    /// Lowest and highest liquidation price across all CFDs
+    #[serde(with = "round_to_two_dp")]
+    pub min_liquidation_price: Decimal,
+    #[serde(with = "round_to_two_dp")]
+    pub max_liquidation_price: Decimal,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub total_margin: Amount,
+    pub n_cfds: usize,
+}
+
+/// Net all open CFDs into one [`AggregatedPosition`] per contract
+/// symbol.
+pub fn aggregate_positions(cfds: &[Cfd]) -> Vec<AggregatedPosition> {
+    let legs = cfds
+        .iter()
+        .filter(|cfd| cfd.state.has_open_position())
+        .map(|cfd| Leg {
+            contract_symbol: cfd.contract_symbol,
+            position: cfd.position,
+            quantity: cfd.quantity.into_decimal(),
+            initial_price: cfd.initial_price.into_decimal(),
+            liquidation_price: cfd.liquidation_price,
+            margin: cfd.margin,
+        });
+
+    aggregate(legs)
+}
+
+/// The part of an open CFD relevant to its contribution to the
+/// aggregated position.
+#[derive(Debug, Clone, Copy)]
+struct Leg {
+    contract_symbol: ContractSymbol,
+    position: Position,
+    quantity: Decimal,
+    initial_price: Decimal,
+    liquidation_price: Decimal,
+    margin: Amount,
+}
+
+fn aggregate(legs: impl IntoIterator<Item = Leg>) -> Vec<AggregatedPosition> {
+    legs.into_iter()
+        .into_group_map_by(|leg| leg.contract_symbol)
+        .into_iter()
+        .map(|(contract_symbol, legs)| {
+            let quantity = |position| {
+                legs.iter()
+                    .filter(|leg| leg.position == position)
+                    .map(|leg| leg.quantity)
+                    .sum::<Decimal>()
+            };
+            let net = quantity(Position::Long) - quantity(Position::Short);
+
+            let position = match net.cmp(&Decimal::ZERO) {
+                std::cmp::Ordering::Greater => Some(Position::Long),
+                std::cmp::Ordering::Less => Some(Position::Short),
+                std::cmp::Ordering::Equal => None,
+            };
+
+            let average_entry_price = position.and_then(|position| {
+                let side = legs.iter().filter(|leg| leg.position == position);
+                average_entry_price(contract_symbol, side)
+            });
+
+            let liquidation_prices = legs.iter().map(|leg| leg.liquidation_price);
+
+            AggregatedPosition {
+                contract_symbol,
+                position,
+                net_quantity: net_quantity(net),
+                average_entry_price,
+                min_liquidation_price: liquidation_prices.clone().min().unwrap_or_default(),
+                max_liquidation_price: liquidation_prices.max().unwrap_or_default(),
+                total_margin: legs
+                    .iter()
+                    .fold(Amount::ZERO, |total, leg| total + leg.margin),
+                n_cfds: legs.len(),
+            }
+        })
+        .sorted_by_key(|aggregated| aggregated.contract_symbol.to_string())
+        .collect()
+}
+
+fn net_quantity(net: Decimal) -> Contracts {
+    net.abs()
+        .to_string()
+        .parse()
+        .expect("decimal to be valid contracts")
+}
+
+/// Average the entry prices of `legs`, weighted by their quantity.
+///
+/// The payout of inverse contracts is linear in the inverse of the
+/// price, hence their average entry price is the harmonic mean.
+fn average_entry_price<'a>(
+    contract_symbol: ContractSymbol,
+    legs: impl Iterator<Item = &'a Leg>,
+) -> Option<Price> {
+    let (quantity, weighted) = legs.fold(
+        (Decimal::ZERO, Decimal::ZERO),
+        |(quantity, weighted), leg| match contract_symbol {
+            ContractSymbol::BtcUsd => (
+                quantity + leg.quantity,
+                weighted + leg.quantity / leg.initial_price,
+            ),
+            ContractSymbol::EthUsd => (
+                quantity + leg.quantity,
+                weighted + leg.quantity * leg.initial_price,
+            ),
+        },
+    );
+
+    if quantity.is_zero() || weighted.is_zero() {
+        return None;
+    }
+
+    let average = match contract_symbol {
+        ContractSymbol::BtcUsd => quantity / weighted,
+        ContractSymbol::EthUsd => weighted / quantity,
+    };
+
+    Price::new(average).ok()
+}
+
 #[derive(Debug, Clone, Serialize, PartialEq, Eq)]
 pub struct CfdDetails {
     tx_url_list: HashSet<TxUrl>,
 }
 
+/// A funding fee charged during a rollover
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FundingPayment {
+    pub timestamp: Timestamp,
+    pub funding_rate_hourly_percent: String,
+    /// The funding fee from our perspective
+    ///
+    /// A positive fee was paid by us, a negative fee was paid to us.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub fee: SignedAmount,
+}
+
 #[derive(Debug, Clone, Copy, Display, FromStr, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 #[display(style = "camelCase")]
@@ -1561,28 +2592,20 @@ mod round_to_two_dp {
     }
 }
 
-/// Construct a mempool.space URL for a given txid
-pub fn to_mempool_url(txid: Txid, network: Network) -> String {
-    match network {
-        Network::Bitcoin => format!("https://mempool.space/tx/{txid}"),
-        Network::Testnet => format!("https://mempool.space/testnet/tx/{txid}"),
-        Network::Signet => format!("https://mempool.space/signet/tx/{txid}"),
-        Network::Regtest => txid.to_string(),
-    }
-}
-
-/// Link to transaction on mempool.space for UI representation
+/// Link to a transaction on the block explorer for UI representation
 #[derive(Debug, Clone, Serialize, PartialEq, Eq, Hash)]
-struct TxUrl {
+pub struct TxUrl {
     pub label: TxLabel,
+    pub txid: Txid,
     pub url: String,
 }
 
 impl TxUrl {
-    fn new(txid: Txid, network: Network, label: TxLabel) -> Self {
+    fn new(txid: Txid, block_explorer: &BlockExplorer, label: TxLabel) -> Self {
         Self {
             label,
-            url: to_mempool_url(txid, network),
+            txid,
+            url: block_explorer.tx_url(txid),
         }
     }
 
@@ -1597,11 +2620,11 @@ impl TxUrl {
     fn from_transaction(
         transaction: &Transaction,
         script_pubkey: &Script,
-        network: Network,
+        block_explorer: &BlockExplorer,
         label: TxLabel,
     ) -> Self {
         debug_assert!(label != TxLabel::Commit, "commit transaction has a single output which does not belong to either party - this won't highlight anything");
-        let tx_url = Self::new(transaction.txid(), network, label);
+        let tx_url = Self::new(transaction.txid(), block_explorer, label);
         if let Ok(outpoint) = transaction.outpoint(script_pubkey) {
             tx_url.with_output_index(outpoint.vout)
         } else {
@@ -1610,7 +2633,7 @@ impl TxUrl {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Eq, Hash, PartialOrd, Ord)]
 pub enum TxLabel {
     Lock,
     Commit,
@@ -1695,6 +2718,163 @@ mod tests {
         assert_eq!(json, "\"SetupFailed\"");
     }
 
+    #[test]
+    fn long_and_short_cfds_are_netted_per_symbol() {
+        let leg = |contract_symbol, position, quantity, initial_price, liquidation_price| Leg {
+            contract_symbol,
+            position,
+            quantity,
+            initial_price,
+            liquidation_price,
+            margin: Amount::from_sat(1_000),
+        };
+
+        let aggregated = aggregate([
+            leg(
+                ContractSymbol::BtcUsd,
+                Position::Long,
+                dec!(100),
+                dec!(20_000),
+                dec!(13_000),
+            ),
+            leg(
+                ContractSymbol::BtcUsd,
+                Position::Long,
+                dec!(300),
+                dec!(60_000),
+                dec!(40_000),
+            ),
+            leg(
+                ContractSymbol::BtcUsd,
+                Position::Short,
+                dec!(50),
+                dec!(25_000),
+                dec!(50_000),
+            ),
+            leg(
+                ContractSymbol::EthUsd,
+                Position::Short,
+                dec!(10),
+                dec!(1_000),
+                dec!(2_000),
+            ),
+            leg(
+                ContractSymbol::EthUsd,
+                Position::Long,
+                dec!(10),
+                dec!(1_500),
+                dec!(750),
+            ),
+        ]);
+
+        let btc = &aggregated[0];
+        assert_eq!(btc.contract_symbol, ContractSymbol::BtcUsd);
+        assert_eq!(btc.position, Some(Position::Long));
+        assert_eq!(btc.net_quantity, Contracts::new(350));
+        assert_eq!(
+            btc.average_entry_price,
+            Some(Price::new(dec!(40_000)).unwrap())
+        );
+        assert_eq!(btc.min_liquidation_price, dec!(13_000));
+        assert_eq!(btc.max_liquidation_price, dec!(50_000));
+        assert_eq!(btc.total_margin, Amount::from_sat(3_000));
+        assert_eq!(btc.n_cfds, 3);
+
+        let eth = &aggregated[1];
+        assert_eq!(eth.contract_symbol, ContractSymbol::EthUsd);
+        assert_eq!(eth.position, None);
+        assert_eq!(eth.net_quantity, Contracts::new(0));
+        assert_eq!(eth.average_entry_price, None);
+    }
+
+    #[test]
+    fn distance_to_liquidation_is_negative_past_liquidation_price() {
+        let price = Price::new(dec!(20_000)).unwrap();
+
+        let long = distance_to_liquidation(Position::Long, price, dec!(15_000));
+        let short = distance_to_liquidation(Position::Short, price, dec!(25_000));
+        let short_liquidated = distance_to_liquidation(Position::Short, price, dec!(19_000));
+
+        assert_eq!(long, dec!(25));
+        assert_eq!(short, dec!(25));
+        assert_eq!(short_liquidated, dec!(-5));
+    }
+
+    #[test]
+    fn position_size_risks_share_of_balance_at_stop_price() {
+        let offer = dummy_offer(Position::Short);
+
+        let size = offer
+            .position_size(PositionSizeInput {
+                balance: Amount::from_sat(1_000_000),
+                risk_percent: dec!(10),
+                entry_price: None,
+                stop_price: Price::new(dec!(19_000)).unwrap(),
+            })
+            .unwrap();
+
+        // Losing 1 / 19_000 - 1 / 20_000 BTC per contract, each lot of 100 contracts loses
+        // about 26_316 sats
+        assert_eq!(size.quantity, Contracts::new(300));
+        // With 20x leverage the position would be liquidated above the stop price
+        assert_eq!(size.leverage, Leverage::new(10).unwrap());
+        assert!(size.liquidation_price < dec!(19_000));
+        assert!(size.loss_at_stop <= Amount::from_sat(100_000));
+        assert_eq!(
+            size.margin,
+            calculate_margin(
+                ContractSymbol::BtcUsd,
+                offer.price,
+                size.quantity,
+                size.leverage
+            )
+        );
+    }
+
+    #[test]
+    fn position_size_rejects_stop_price_on_winning_side() {
+        let offer = dummy_offer(Position::Long);
+
+        let result = offer.position_size(PositionSizeInput {
+            balance: Amount::from_sat(1_000_000),
+            risk_percent: dec!(10),
+            entry_price: None,
+            stop_price: Price::new(dec!(19_000)).unwrap(),
+        });
+
+        assert!(result.is_err());
+    }
+
+    fn dummy_offer(position_maker: Position) -> CfdOffer {
+        let leverage_details = [1, 2, 5, 10, 20]
+            .into_iter()
+            .map(|leverage| LeverageDetails {
+                leverage: Leverage::new(leverage).unwrap(),
+                liquidation_price: Decimal::ZERO,
+                margin_per_lot: Amount::ZERO,
+                initial_funding_fee_per_lot: SignedAmount::ZERO,
+            })
+            .collect();
+
+        CfdOffer {
+            id: OfferId::default(),
+            contract_symbol: ContractSymbol::BtcUsd,
+            position_maker,
+            price: Price::new(dec!(20_000)).unwrap(),
+            opening_fee: None,
+            fee_subsidy: None,
+            min_taker_balance: None,
+            funding_rate_annualized_percent: "0".to_owned(),
+            funding_rate_hourly_percent: "0".to_owned(),
+            min_quantity: Contracts::new(100),
+            max_quantity: Contracts::new(10_000),
+            lot_size: LotSize::new(100),
+            leverage_details,
+            creation_timestamp: Timestamp::now(),
+            settlement_time_interval_in_secs: 86_400,
+        }
+    }
+
     pub fn dummy_cfd() -> model::Cfd {
         model::Cfd::new(
             OrderId::default(),
@@ -1728,7 +2908,7 @@ mod tests {
         CfdEvent {
             timestamp: Timestamp::now(),
             id: cfd.id(),
-            event: EventKind::OfferRejected,
+            event: EventKind::OfferRejected(None),
         }
     }
 
@@ -1794,6 +2974,10 @@ mod tests {
         }
     }
 
+    fn testnet_explorer() -> BlockExplorer {
+        BlockExplorer::mempool_space(bdk::bitcoin::Network::Testnet)
+    }
+
     #[tokio::test]
     async fn given_contract_setup_failed_when_move_cfds_to_failed_table_then_projection_aggregate_stays_the_same(
     ) {
@@ -1808,7 +2992,7 @@ mod tests {
 
         let projection_open = {
             let projection_open = db
-                .load_open_cfd::<Cfd>(order_id, bdk::bitcoin::Network::Testnet)
+                .load_open_cfd::<Cfd>(order_id, testnet_explorer())
                 .await
                 .unwrap();
             projection_open.with_current_quote(None) // unconditional processing in `projection`
@@ -1818,7 +3002,7 @@ mod tests {
 
         let projection_failed = {
             let projection_failed = db
-                .load_failed_cfd::<Cfd>(order_id, bdk::bitcoin::Network::Testnet)
+                .load_failed_cfd::<Cfd>(order_id, testnet_explorer())
                 .await
                 .unwrap();
             projection_failed.with_current_quote(None) // unconditional processing in `projection`
@@ -1844,7 +3028,7 @@ mod tests {
 
         let projection_open = {
             let projection_open = db
-                .load_open_cfd::<Cfd>(order_id, bdk::bitcoin::Network::Testnet)
+                .load_open_cfd::<Cfd>(order_id, testnet_explorer())
                 .await
                 .unwrap();
             projection_open.with_current_quote(None) // unconditional processing in `projection`
@@ -1854,7 +3038,7 @@ mod tests {
 
         let projection_failed = {
             let projection_failed = db
-                .load_failed_cfd::<Cfd>(order_id, bdk::bitcoin::Network::Testnet)
+                .load_failed_cfd::<Cfd>(order_id, testnet_explorer())
                 .await
                 .unwrap();
             projection_failed.with_current_quote(None) // unconditional processing in `projection`
@@ -1888,7 +3072,7 @@ mod tests {
 
         let projection_open = {
             let projection_open = db
-                .load_open_cfd::<Cfd>(order_id, bdk::bitcoin::Network::Testnet)
+                .load_open_cfd::<Cfd>(order_id, testnet_explorer())
                 .await
                 .unwrap();
             projection_open.with_current_quote(None) // unconditional processing in `projection`
@@ -1898,7 +3082,7 @@ mod tests {
 
         let projection_closed = {
             let projection_closed = db
-                .load_closed_cfd::<Cfd>(order_id, bdk::bitcoin::Network::Testnet)
+                .load_closed_cfd::<Cfd>(order_id, testnet_explorer())
                 .await
                 .unwrap();
             let mut projection_closed = projection_closed.with_current_quote(None); // unconditional processing in `projection`
@@ -1923,4 +3107,75 @@ mod tests {
         // from a closed CFD
         assert_eq!(projection_open, projection_closed);
     }
+
+    #[tokio::test]
+    async fn given_funding_payments_then_projection_contains_signed_funding_history() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        let order_id = cfd.id();
+
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let long_pays = FundingFee {
+            fee: Amount::from_sat(100),
+            rate: FundingRate::new(dec!(0.001)).unwrap(),
+        };
+        let short_pays = FundingFee {
+            fee: Amount::from_sat(50),
+            rate: FundingRate::new(dec!(-0.001)).unwrap(),
+        };
+        db.insert_funding_payment(order_id, Timestamp::new(1), long_pays)
+            .await
+            .unwrap();
+        db.insert_funding_payment(order_id, Timestamp::new(2), short_pays)
+            .await
+            .unwrap();
+
+        let projection = db
+            .load_open_cfd::<Cfd>(order_id, testnet_explorer())
+            .await
+            .unwrap()
+            .with_funding_history(db.load_funding_history(order_id).await.unwrap());
+
+        let fees = projection
+            .funding_history
+            .iter()
+            .map(|payment| payment.fee)
+            .collect::<Vec<_>>();
+
+        // the dummy CFD is long
+        assert_eq!(
+            fees,
+            vec![SignedAmount::from_sat(100), SignedAmount::from_sat(-50)]
+        );
+    }
+
+    #[tokio::test]
+    async fn given_funding_schedule_then_simulation_flags_first_rollover_exhausting_margin() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        let order_id = cfd.id();
+
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let projection = db
+            .load_open_cfd::<Cfd>(order_id, testnet_explorer())
+            .await
+            .unwrap();
+
+        // the dummy CFD is long, so it pays a quarter of its margin per
+        // rollover from the second rollover onwards
+        let schedule = [
+            FundingRate::default(),
+            FundingRate::new(dec!(0.25)).unwrap(),
+        ];
+        let simulation = projection.simulate_rollovers(&schedule, 6).unwrap();
+
+        assert_eq!(simulation.rollovers.len(), 6);
+        assert_eq!(simulation.rollovers[0].funding_fee, SignedAmount::ZERO);
+        assert!(simulation.rollovers[1].funding_fee.is_positive());
+        assert_eq!(simulation.first_breach, Some(5));
+    }
 }
@@ -0,0 +1,176 @@
+//! Readiness of the subsystems which finish starting in the background.
+//!
+//! The API is served before every subsystem is ready, e.g. before the
+//! wallet has synced for the first time or the CFDs have been loaded
+//! from the database. The [`Tracker`] records the [`Stage`] of every
+//! [`Subsystem`]: each one starts out as [`Stage::Starting`] and moves
+//! to either [`Stage::Ready`] or [`Stage::Failed`], where it stays.
+
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    /// The database has been migrated
+    Database,
+    /// The wallet has synced for the first time
+    Wallet,
+    /// The first quote has been received from the price feed
+    PriceFeed,
+    /// The CFDs have been loaded from the database
+    Cfds,
+    /// The open CFDs have been rehydrated
+    Rehydration,
+}
+
+impl Subsystem {
+    const ALL: [Subsystem; 5] = [
+        Subsystem::Database,
+        Subsystem::Wallet,
+        Subsystem::PriceFeed,
+        Subsystem::Cfds,
+        Subsystem::Rehydration,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "stage", content = "reason", rename_all = "snake_case")]
+pub enum Stage {
+    Starting,
+    Ready,
+    Failed(String),
+}
+
+/// The stage of every subsystem.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Readiness(BTreeMap<Subsystem, Stage>);
+
+impl Readiness {
+    fn new() -> Self {
+        Self(
+            Subsystem::ALL
+                .into_iter()
+                .map(|subsystem| (subsystem, Stage::Starting))
+                .collect(),
+        )
+    }
+
+    /// Whether all subsystems are ready.
+    pub fn is_ready(&self) -> bool {
+        self.0.values().all(|stage| *stage == Stage::Ready)
+    }
+
+    pub fn stage(&self, subsystem: Subsystem) -> &Stage {
+        self.0
+            .get(&subsystem)
+            .expect("all subsystems to be tracked")
+    }
+
+    fn transition(&mut self, subsystem: Subsystem, stage: Stage) {
+        let current = self.0.entry(subsystem).or_insert(Stage::Starting);
+
+        if *current != Stage::Starting {
+            tracing::debug!(?subsystem, ?current, ?stage, "Subsystem already started");
+            return;
+        }
+
+        match &stage {
+            Stage::Starting => {}
+            Stage::Ready => tracing::info!(?subsystem, "Subsystem ready"),
+            Stage::Failed(reason) => {
+                tracing::warn!(?subsystem, %reason, "Subsystem failed to start")
+            }
+        }
+
+        *current = stage;
+    }
+}
+
+/// Records the stage of every subsystem as it starts.
+#[derive(Clone)]
+pub struct Tracker(Arc<watch::Sender<Readiness>>);
+
+impl Tracker {
+    pub fn new() -> (Self, watch::Receiver<Readiness>) {
+        let (sender, receiver) = watch::channel(Readiness::new());
+
+        (Self(Arc::new(sender)), receiver)
+    }
+
+    pub fn ready(&self, subsystem: Subsystem) {
+        self.transition(subsystem, Stage::Ready);
+    }
+
+    pub fn failed(&self, subsystem: Subsystem, error: &anyhow::Error) {
+        self.transition(subsystem, Stage::Failed(format!("{error:#}")));
+    }
+
+    /// Mark `subsystem` as ready once the value of `receiver` satisfies
+    /// `is_ready`.
+    ///
+    /// The subsystem is marked as failed if the sender of `receiver` is
+    /// dropped before that.
+    pub async fn ready_when<T>(
+        self,
+        subsystem: Subsystem,
+        mut receiver: watch::Receiver<T>,
+        is_ready: impl Fn(&T) -> bool,
+    ) {
+        loop {
+            let ready = is_ready(&*receiver.borrow());
+            if ready {
+                self.ready(subsystem);
+                return;
+            }
+
+            if receiver.changed().await.is_err() {
+                self.failed(subsystem, &anyhow!("Stopped before becoming ready"));
+                return;
+            }
+        }
+    }
+
+    fn transition(&self, subsystem: Subsystem, stage: Stage) {
+        self.0
+            .send_modify(|readiness| readiness.transition(subsystem, stage));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_ready_once_all_subsystems_are_ready() {
+        let (tracker, receiver) = Tracker::new();
+
+        for subsystem in Subsystem::ALL {
+            assert!(!receiver.borrow().is_ready());
+            tracker.ready(subsystem);
+        }
+
+        assert!(receiver.borrow().is_ready());
+    }
+
+    #[test]
+    fn subsystem_stays_in_final_stage() {
+        let (tracker, receiver) = Tracker::new();
+
+        tracker.ready(Subsystem::Wallet);
+        tracker.failed(Subsystem::Wallet, &anyhow!("Electrum unreachable"));
+        tracker.failed(Subsystem::Cfds, &anyhow!("Database locked"));
+        tracker.ready(Subsystem::Cfds);
+
+        let readiness = receiver.borrow();
+        assert_eq!(readiness.stage(Subsystem::Wallet), &Stage::Ready);
+        assert_eq!(
+            readiness.stage(Subsystem::Cfds),
+            &Stage::Failed("Database locked".to_owned())
+        );
+    }
+}
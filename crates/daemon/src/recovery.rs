@@ -0,0 +1,298 @@
+//! Recovery of the funds of open CFDs after restoring from a backup.
+//!
+//! The identity and the wallet keys are re-derived from the seed. The
+//! keys of every DLC are stored in the database, from which the lock and
+//! commit descriptors are re-created to check that they match the
+//! stored transactions. The chain is then scanned for the lock, commit,
+//! CET and refund transactions of every open CFD to report what can
+//! still be claimed.
+//!
+//! The database may be missing the most recent events, e.g. a rollover
+//! which completed after the backup was taken. Transactions which spend
+//! the funds of a CFD but are not known to the database are therefore
+//! reported as such rather than treated as an error.
+
+use crate::seed::Seed;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::secp256k1;
+use bdk::bitcoin::secp256k1::SecretKey;
+use bdk::bitcoin::Network;
+use bdk::bitcoin::PublicKey;
+use bdk::bitcoin::Script;
+use bdk::bitcoin::Txid;
+use bdk::database::MemoryDatabase;
+use bdk::electrum_client;
+use bdk::electrum_client::ElectrumApi;
+use bdk::miniscript::DescriptorTrait;
+use bdk::wallet::AddressIndex;
+use bdk::KeychainKind;
+use maia::commit_descriptor;
+use maia::lock_descriptor;
+use maia_core::secp256k1_zkp::SECP256K1;
+use model::libp2p::PeerId;
+use model::CfdEvent;
+use model::Dlc;
+use model::EventKind;
+use model::OrderId;
+use model::Role;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Number of addresses derived from the seed when looking for our
+/// payout addresses.
+const ADDRESS_LOOKAHEAD: u32 = 1_000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// The peer id derived from the seed
+    pub peer_id: PeerId,
+    pub cfds: Vec<CfdReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CfdReport {
+    pub order_id: OrderId,
+    pub role: Role,
+    /// Whether the descriptors re-created from the keys in the database
+    /// match the ones of the stored transactions
+    ///
+    /// `None` if there is no DLC.
+    pub descriptors_match: Option<bool>,
+    /// Whether our payout address belongs to the wallet derived from the
+    /// seed
+    ///
+    /// Addresses of a wallet which was not derived from the seed, e.g.
+    /// one given with `--wallet-xprv`, are never ours. `None` if there
+    /// is no DLC.
+    pub payout_address_is_ours: Option<bool>,
+    pub claim: Claim,
+}
+
+/// What happened to the funds of a CFD, and what can still be claimed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Claim {
+    /// The database does not contain a DLC, either because the contract
+    /// setup did not complete or because the backup predates it
+    NoDlc,
+    /// The lock transaction was never published, so nothing is at stake
+    LockNotPublished,
+    /// The funds are locked and can be claimed by settling
+    /// collaboratively or by publishing the commit transaction
+    Locked,
+    /// The commit transaction is on chain; the CET can be published once
+    /// the oracle has attested, or the refund after its timelock
+    Committed { commit: Txid },
+    /// The counterparty published a revoked commit transaction, which
+    /// we can punish
+    Punishable { revoked_commit: Txid },
+    /// The funds have been paid out by a known transaction, i.e. a
+    /// collaborative settlement, CET, refund or punishment
+    Closed { txid: Txid },
+    /// The lock output was spent by a transaction not known to the
+    /// database, e.g. a commit transaction of a rollover which is
+    /// missing from the backup
+    SpentByUnknown { txid: Txid },
+}
+
+/// Report what can still be claimed of every open CFD in `db`.
+pub async fn report(
+    seed: &dyn Seed,
+    network: Network,
+    db: &sqlite_db::Connection,
+    electrum: &electrum_client::Client,
+) -> Result<Report> {
+    let peer_id = seed.derive_identities().peer_id();
+    let wallet_scripts = wallet_scripts(seed, network)?;
+
+    let mut cfds = Vec::new();
+    for order_id in db.load_open_cfd_ids().await? {
+        let cfd = db
+            .load_open_cfd::<Recoverable>(order_id, ())
+            .await
+            .with_context(|| format!("Failed to load CFD {order_id}"))?;
+
+        let report = match &cfd.dlc {
+            Some(dlc) => CfdReport {
+                order_id,
+                role: cfd.role,
+                descriptors_match: Some(descriptors_match(dlc, cfd.role)),
+                payout_address_is_ours: Some(
+                    wallet_scripts.contains(&dlc.script_pubkey_for(cfd.role)),
+                ),
+                claim: claim(electrum, dlc, cfd.collaborative_settlement)
+                    .with_context(|| format!("Failed to scan chain for CFD {order_id}"))?,
+            },
+            None => CfdReport {
+                order_id,
+                role: cfd.role,
+                descriptors_match: None,
+                payout_address_is_ours: None,
+                claim: Claim::NoDlc,
+            },
+        };
+
+        cfds.push(report);
+    }
+
+    Ok(Report { peer_id, cfds })
+}
+
+/// The scripts of the first addresses of the wallet derived from the
+/// seed.
+fn wallet_scripts(seed: &dyn Seed, network: Network) -> Result<HashSet<Script>> {
+    let ext_priv_key = seed.derive_extended_priv_key(network)?;
+    let wallet = bdk::Wallet::new(
+        bdk::template::Bip84(ext_priv_key, KeychainKind::External),
+        Some(bdk::template::Bip84(ext_priv_key, KeychainKind::Internal)),
+        network,
+        MemoryDatabase::new(),
+    )?;
+
+    (0..ADDRESS_LOOKAHEAD)
+        .map(|index| {
+            let address = wallet.get_address(AddressIndex::Peek(index))?;
+
+            Ok(address.script_pubkey())
+        })
+        .collect()
+}
+
+/// Re-create the lock and commit descriptors from the keys of the DLC
+/// and compare them to the stored ones.
+fn descriptors_match(dlc: &Dlc, role: Role) -> bool {
+    let public_key =
+        |sk: &SecretKey| PublicKey::new(secp256k1::PublicKey::from_secret_key(SECP256K1, sk));
+
+    let ours = (
+        dlc.identity_pk(),
+        public_key(&dlc.revocation),
+        public_key(&dlc.publish),
+    );
+    let theirs = (
+        dlc.identity_counterparty,
+        dlc.revocation_pk_counterparty,
+        dlc.publish_pk_counterparty,
+    );
+    let (maker, taker) = match role {
+        Role::Maker => (ours, theirs),
+        Role::Taker => (theirs, ours),
+    };
+
+    lock_descriptor(maker.0, taker.0) == dlc.lock.1
+        && commit_descriptor(maker, taker) == dlc.commit.2
+}
+
+fn claim(
+    electrum: &electrum_client::Client,
+    dlc: &Dlc,
+    collaborative_settlement: Option<Txid>,
+) -> Result<Claim> {
+    let lock_txid = dlc.lock.0.txid();
+    let lock_history = history(electrum, &dlc.lock.1.script_pubkey())?;
+
+    if !lock_history.contains(&lock_txid) {
+        return Ok(Claim::LockNotPublished);
+    }
+
+    let spender = match lock_history.into_iter().find(|txid| *txid != lock_txid) {
+        Some(spender) => spender,
+        None => return Ok(Claim::Locked),
+    };
+
+    let commit_txid = dlc.commit.0.txid();
+    if spender == commit_txid {
+        let claim = match find_spender(electrum, &dlc.commit.2.script_pubkey(), commit_txid)? {
+            Some(txid) => Claim::Closed { txid },
+            None => Claim::Committed {
+                commit: commit_txid,
+            },
+        };
+
+        return Ok(claim);
+    }
+
+    if let Some(revoked) = dlc.revoked_commit.iter().find(|r| r.txid == spender) {
+        let claim = match find_spender(electrum, &revoked.script_pubkey, revoked.txid)? {
+            Some(txid) => Claim::Closed { txid },
+            None => Claim::Punishable {
+                revoked_commit: revoked.txid,
+            },
+        };
+
+        return Ok(claim);
+    }
+
+    if collaborative_settlement == Some(spender) {
+        return Ok(Claim::Closed { txid: spender });
+    }
+
+    Ok(Claim::SpentByUnknown { txid: spender })
+}
+
+/// The transactions which pay to or spend from `script`.
+fn history(electrum: &electrum_client::Client, script: &Script) -> Result<Vec<Txid>> {
+    let history = electrum
+        .script_get_history(script)
+        .context("Failed to get script history")?;
+
+    Ok(history.into_iter().map(|entry| entry.tx_hash).collect())
+}
+
+/// The transaction spending the output of `txid` to `script`, if any.
+fn find_spender(
+    electrum: &electrum_client::Client,
+    script: &Script,
+    txid: Txid,
+) -> Result<Option<Txid>> {
+    let spender = history(electrum, script)?
+        .into_iter()
+        .find(|other| *other != txid);
+
+    Ok(spender)
+}
+
+/// Read-model of a CFD which is only interested in the transactions
+/// which can spend its funds.
+#[derive(Debug, Clone)]
+struct Recoverable {
+    role: Role,
+    dlc: Option<Dlc>,
+    collaborative_settlement: Option<Txid>,
+    version: u32,
+}
+
+impl sqlite_db::CfdAggregate for Recoverable {
+    type CtorArgs = ();
+
+    fn new(_: Self::CtorArgs, cfd: sqlite_db::Cfd) -> Self {
+        Self {
+            role: cfd.role,
+            dlc: None,
+            collaborative_settlement: None,
+            version: 0,
+        }
+    }
+
+    fn apply(mut self, event: CfdEvent) -> Self {
+        self.version += 1;
+
+        match event.event {
+            EventKind::ContractSetupCompleted { dlc: Some(dlc) }
+            | EventKind::RolloverCompleted { dlc: Some(dlc), .. } => Self {
+                dlc: Some(dlc),
+                ..self
+            },
+            EventKind::CollaborativeSettlementCompleted { spend_tx, .. } => Self {
+                collaborative_settlement: Some(spend_tx.txid()),
+                ..self
+            },
+            _ => self,
+        }
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
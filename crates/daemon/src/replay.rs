@@ -0,0 +1,229 @@
+//! Replay of substreams recorded with [`xtra_libp2p::recorder`].
+//!
+//! Replaying runs the handlers of both parties of the protocol on the
+//! frames the other party sent, independent of any actors or network
+//! connections. The handlers receive the messages in the order they
+//! expect them in a live session and reject unexpected ones. This makes
+//! it possible to check whether a session recorded by a user (e.g. with
+//! a different version of the taker) is understood by this version of
+//! the daemon.
+//!
+//! Signatures and keys are not checked, because the secrets they
+//! depend on are redacted in recordings.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use xtra_libp2p::recorder::Direction;
+use xtra_libp2p::recorder::Frame;
+use xtra_libp2p::recorder::Header;
+use xtra_libp2p::recorder::Payload;
+use xtra_libp2p::recorder::ReplayedSubstream;
+use xtra_libp2p::recorder::Role;
+use xtra_libp2p::recorder::REDACTED;
+
+/// The result of replaying a recorded substream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The handlers of both parties accepted all frames.
+    Accepted,
+    /// The handlers accepted all frames before `frame`, which contains
+    /// redacted fields and can thus not be replayed.
+    Redacted { frame: usize },
+}
+
+/// Run the handlers of the protocol described by `header` on the
+/// recorded `frames`.
+pub async fn replay(header: &Header, frames: &[Frame]) -> Result<Outcome> {
+    let (frames, outcome) = match frames
+        .iter()
+        .position(|frame| contains_redacted(&frame.payload))
+    {
+        Some(frame) => (&frames[..frame], Outcome::Redacted { frame }),
+        None => (frames, Outcome::Accepted),
+    };
+
+    let mut dialer_frames = Vec::new();
+    let mut listener_frames = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        let value = match &frame.payload {
+            Payload::Json(value) => value,
+            Payload::Raw(hex) => bail!("Frame {i} is not JSON: {hex}"),
+        };
+
+        if sent_by_dialer(header.role, frame.direction) {
+            dialer_frames.push(value);
+        } else {
+            listener_frames.push(value);
+        }
+    }
+
+    run_handler(
+        &header.protocol,
+        Role::Dialer,
+        ReplayedSubstream::new(listener_frames),
+    )
+    .await
+    .context("Dialer rejected the frames sent by the listener")?;
+    run_handler(
+        &header.protocol,
+        Role::Listener,
+        ReplayedSubstream::new(dialer_frames),
+    )
+    .await
+    .context("Listener rejected the frames sent by the dialer")?;
+
+    Ok(outcome)
+}
+
+async fn run_handler(protocol: &str, role: Role, stream: ReplayedSubstream) -> Result<()> {
+    use crate::collab_settlement;
+    use crate::identify;
+    use crate::order;
+    use crate::watchtower;
+
+    match (protocol, role) {
+        (identify::PROTOCOL, Role::Dialer) => identify::replay_dialer(stream).await,
+        (identify::PROTOCOL, Role::Listener) => identify::replay_listener(stream).await,
+        (order::PROTOCOL, Role::Dialer) => order::replay_dialer(stream).await,
+        (order::PROTOCOL, Role::Listener) => order::replay_listener(stream).await,
+        (collab_settlement::PROTOCOL, Role::Dialer) => {
+            collab_settlement::replay_dialer(stream).await
+        }
+        (collab_settlement::PROTOCOL, Role::Listener) => {
+            collab_settlement::replay_listener(stream).await
+        }
+        (offer::PROTOCOL | offer::PROTOCOL_WITHOUT_FEE_SUBSIDY, Role::Dialer) => {
+            offer::replay_dialer(stream).await
+        }
+        (offer::PROTOCOL | offer::PROTOCOL_WITHOUT_FEE_SUBSIDY, Role::Listener) => {
+            offer::replay_listener(stream).await
+        }
+        (rollover::PROTOCOL, Role::Dialer) => rollover::replay_dialer(stream).await,
+        (rollover::PROTOCOL, Role::Listener) => rollover::replay_listener(stream).await,
+        (watchtower::PROTOCOL, Role::Dialer) => watchtower::replay_dialer(stream).await,
+        (watchtower::PROTOCOL, Role::Listener) => watchtower::replay_listener(stream).await,
+        (other, _) => bail!("Replaying protocol {other} is not supported"),
+    }
+}
+
+fn sent_by_dialer(role: Role, direction: Direction) -> bool {
+    matches!(
+        (role, direction),
+        (Role::Dialer, Direction::Outbound) | (Role::Listener, Direction::Inbound)
+    )
+}
+
+fn contains_redacted(payload: &Payload) -> bool {
+    match payload {
+        Payload::Json(value) => value.to_string().contains(REDACTED),
+        Payload::Raw(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(protocol: &str, role: Role) -> Header {
+        Header {
+            protocol: protocol.to_owned(),
+            role,
+            peer_id: "12D3KooWP3BN6bq9jPy8cP7Grj1QyUBfr7U6BeQFgMwfTTu12wuY".to_owned(),
+            started_at: 0,
+        }
+    }
+
+    fn frame(direction: Direction, value: serde_json::Value) -> Frame {
+        Frame {
+            elapsed_ms: 0,
+            direction,
+            payload: Payload::Json(value),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_settlement_is_accepted() {
+        let header = header(crate::collab_settlement::PROTOCOL, Role::Dialer);
+        let frames = [frame(
+            Direction::Inbound,
+            serde_json::json!({ "Decision": "Reject" }),
+        )];
+
+        let outcome = replay(&header, &frames).await.unwrap();
+
+        assert_eq!(outcome, Outcome::Accepted);
+    }
+
+    #[tokio::test]
+    async fn message_after_rejected_settlement_is_refused() {
+        let header = header(crate::collab_settlement::PROTOCOL, Role::Dialer);
+        let frames = [
+            frame(
+                Direction::Inbound,
+                serde_json::json!({ "Decision": "Reject" }),
+            ),
+            frame(
+                Direction::Inbound,
+                serde_json::json!({ "Decision": "Accept" }),
+            ),
+        ];
+
+        assert!(replay(&header, &frames).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn message_sent_by_wrong_party_is_refused() {
+        let header = header(crate::collab_settlement::PROTOCOL, Role::Listener);
+        let frames = [frame(
+            Direction::Inbound,
+            serde_json::json!({ "Decision": "Accept" }),
+        )];
+
+        assert!(replay(&header, &frames).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn decision_instead_of_contract_setup_msg_is_refused() {
+        let header = header(crate::order::PROTOCOL, Role::Dialer);
+        let frames = [
+            frame(
+                Direction::Inbound,
+                serde_json::json!({ "Decision": "Accept" }),
+            ),
+            frame(
+                Direction::Inbound,
+                serde_json::json!({ "Decision": "Accept" }),
+            ),
+        ];
+
+        assert!(replay(&header, &frames).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn replay_stops_at_frame_with_redacted_fields() {
+        let header = header(crate::order::PROTOCOL, Role::Dialer);
+        let frames = [
+            frame(
+                Direction::Inbound,
+                serde_json::json!({ "Decision": "Accept" }),
+            ),
+            frame(
+                Direction::Outbound,
+                serde_json::json!({ "ContractSetupMsg": { "revocation_sk": REDACTED } }),
+            ),
+        ];
+
+        let outcome = replay(&header, &frames).await.unwrap();
+
+        assert_eq!(outcome, Outcome::Redacted { frame: 1 });
+    }
+
+    #[tokio::test]
+    async fn unknown_protocol_is_not_supported() {
+        let header = header("/foo/1.0.0", Role::Dialer);
+        let frames = [frame(Direction::Outbound, serde_json::json!({}))];
+
+        assert!(replay(&header, &frames).await.is_err());
+    }
+}
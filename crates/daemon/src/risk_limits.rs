@@ -0,0 +1,359 @@
+//! Responsible-trading limits enforced on the taker.
+//!
+//! The taker can configure the maximum amount of bitcoin they are
+//! willing to lose within a rolling time window. Once the losses
+//! realised by CFDs closed within that window reach the limit, new
+//! orders are refused until the window moves on or the user
+//! explicitly overrides the limit.
+//!
+//! The limit and its override are persisted, restarting the daemon
+//! does not lift them.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bdk::bitcoin::Amount;
+use bdk::bitcoin::SignedAmount;
+use model::calculate_margin;
+use model::ClosedCfd;
+use model::Leverage;
+use model::Role;
+use model::Timestamp;
+use serde::Serialize;
+use sqlite_db;
+use time::Duration;
+use time::OffsetDateTime;
+use xtra_productivity::xtra_productivity;
+
+/// How long an override of the loss limit stays active.
+pub const OVERRIDE_DURATION: Duration = Duration::hours(24);
+
+/// The maximum loss the user is willing to realise within a rolling
+/// window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LossLimit {
+    pub max_loss: Amount,
+    pub window: Duration,
+}
+
+impl LossLimit {
+    pub fn weekly(max_loss: Amount) -> Self {
+        Self {
+            max_loss,
+            window: Duration::weeks(1),
+        }
+    }
+
+    /// Whether `realised_loss` exhausts this limit.
+    ///
+    /// Without any realised loss there is nothing to limit, which
+    /// allows a limit of zero to mean "stop after the first loss".
+    fn is_reached(&self, realised_loss: Amount) -> bool {
+        realised_loss > Amount::ZERO && realised_loss >= self.max_loss
+    }
+}
+
+/// The loss limit and until when it is overridden, as persisted.
+struct Settings {
+    limit: Option<LossLimit>,
+    override_until: Option<OffsetDateTime>,
+}
+
+impl Settings {
+    async fn load(db: &sqlite_db::Connection) -> Result<Self> {
+        let sqlite_db::loss_limit::LossLimit {
+            limit,
+            override_until,
+        } = db.load_loss_limit().await?;
+
+        let limit = limit.map(|(max_loss, window_secs)| LossLimit {
+            max_loss,
+            window: Duration::seconds(window_secs),
+        });
+        let override_until = override_until
+            .map(|until| OffsetDateTime::from_unix_timestamp(until.seconds()))
+            .transpose()?;
+
+        Ok(Self {
+            limit,
+            override_until,
+        })
+    }
+
+    fn is_overridden(&self) -> bool {
+        matches!(self.override_until, Some(until) if until > OffsetDateTime::now_utc())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Loss limit of {max_loss} within the last {window_hours} hours reached: realised loss is {realised_loss}")]
+    LossLimitReached {
+        max_loss: Amount,
+        window_hours: i64,
+        realised_loss: Amount,
+    },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Check whether a new order may be placed.
+#[derive(Clone, Copy)]
+pub struct CheckNewOrder;
+
+/// Configure the loss limit. `None` disables it.
+#[derive(Clone, Copy)]
+pub struct SetLossLimit(pub Option<LossLimit>);
+
+/// Temporarily allow new orders even if the loss limit has been
+/// reached.
+#[derive(Clone, Copy)]
+pub struct OverrideLossLimit;
+
+/// Query the current state of the risk limits.
+#[derive(Clone, Copy)]
+pub struct GetRiskStatus;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RiskStatus {
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub max_loss: Option<Amount>,
+    pub window_secs: Option<i64>,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub realised_loss: Amount,
+    pub override_until: Option<Timestamp>,
+}
+
+pub struct Actor {
+    db: sqlite_db::Connection,
+}
+
+impl Actor {
+    pub fn new(db: sqlite_db::Connection) -> Self {
+        Self { db }
+    }
+
+    /// Sum up the losses of all CFDs closed within `window`.
+    ///
+    /// Profits are not netted against losses: a limit on losses is
+    /// meant to be a hard cap which profitable trades cannot lift.
+    async fn realised_loss(&self, window: Duration) -> Result<Amount> {
+        let since = Timestamp::new((OffsetDateTime::now_utc() - window).unix_timestamp());
+        let ids = self.db.load_closed_cfd_ids_closed_since(since).await?;
+
+        let mut loss = Amount::ZERO;
+        for id in ids {
+            let cfd = self.db.load_closed_cfd::<RealisedPnl>(id, ()).await?;
+
+            if let Some(pnl) = cfd.0 {
+                if pnl < SignedAmount::ZERO {
+                    loss += pnl.abs().to_unsigned()?;
+                }
+            }
+        }
+
+        Ok(loss)
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _: CheckNewOrder) -> Result<(), Error> {
+        let settings = Settings::load(&self.db).await?;
+        let limit = match settings.limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let realised_loss = self.realised_loss(limit.window).await?;
+
+        if !limit.is_reached(realised_loss) {
+            return Ok(());
+        }
+
+        if settings.is_overridden() {
+            tracing::warn!(
+                max_loss = %limit.max_loss,
+                %realised_loss,
+                "Loss limit reached but overridden by the user"
+            );
+            return Ok(());
+        }
+
+        Err(Error::LossLimitReached {
+            max_loss: limit.max_loss,
+            window_hours: limit.window.whole_hours(),
+            realised_loss,
+        })
+    }
+
+    async fn handle(&mut self, msg: SetLossLimit) -> Result<()> {
+        tracing::info!(limit = ?msg.0, "Updating loss limit");
+
+        let limit = msg
+            .0
+            .map(|limit| (limit.max_loss, limit.window.whole_seconds()));
+        self.db.save_loss_limit(limit).await?;
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, _: OverrideLossLimit) -> Result<()> {
+        let until = OffsetDateTime::now_utc() + OVERRIDE_DURATION;
+
+        tracing::info!(%until, "Overriding loss limit");
+
+        self.db
+            .save_loss_limit_override(Timestamp::new(until.unix_timestamp()))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, _: GetRiskStatus) -> Result<RiskStatus> {
+        let settings = Settings::load(&self.db).await?;
+        let realised_loss = match settings.limit {
+            Some(limit) => self.realised_loss(limit.window).await?,
+            None => Amount::ZERO,
+        };
+
+        let override_until = settings
+            .override_until
+            .filter(|_| settings.is_overridden())
+            .map(|until| Timestamp::new(until.unix_timestamp()));
+
+        Ok(RiskStatus {
+            max_loss: settings.limit.map(|limit| limit.max_loss),
+            window_secs: settings.limit.map(|limit| limit.window.whole_seconds()),
+            realised_loss,
+            override_until,
+        })
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// Read-model of a closed CFD which only knows about its realised
+/// profit or loss.
+///
+/// Only closed CFDs have realised their profit or loss, so any open
+/// CFD is represented by `None`.
+#[derive(Debug, Clone, Copy)]
+struct RealisedPnl(Option<SignedAmount>);
+
+impl sqlite_db::CfdAggregate for RealisedPnl {
+    type CtorArgs = ();
+
+    fn new(_: Self::CtorArgs, _: sqlite_db::Cfd) -> Self {
+        Self(None)
+    }
+
+    fn apply(self, _: model::CfdEvent) -> Self {
+        self
+    }
+
+    fn version(&self) -> u32 {
+        0
+    }
+}
+
+impl sqlite_db::ClosedCfdAggregate for RealisedPnl {
+    fn new_closed(_: Self::CtorArgs, cfd: ClosedCfd) -> Self {
+        let ClosedCfd {
+            initial_price,
+            taker_leverage,
+            n_contracts,
+            role,
+            settlement,
+            contract_symbol,
+            ..
+        } = cfd;
+
+        let our_leverage = match role {
+            Role::Maker => Leverage::ONE,
+            Role::Taker => taker_leverage,
+        };
+        let margin = calculate_margin(contract_symbol, initial_price, n_contracts, our_leverage);
+
+        let payout = match settlement {
+            model::Settlement::Collaborative { payout, .. }
+            | model::Settlement::Cet { payout, .. }
+            | model::Settlement::Refund { payout, .. } => payout.inner(),
+        };
+
+        let pnl = match (payout.to_signed(), margin.to_signed()) {
+            (Ok(payout), Ok(margin)) => Some(payout - margin),
+            _ => None,
+        };
+
+        Self(pnl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_extras::Tasks;
+    use xtra::Actor as _;
+
+    #[tokio::test]
+    async fn no_limit_allows_new_orders() {
+        let mut tasks = Tasks::default();
+        let db = sqlite_db::memory().await.unwrap();
+        let actor = Actor::new(db).create(None).spawn(&mut tasks);
+
+        actor.send(CheckNewOrder).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn zero_limit_without_losses_allows_new_orders() {
+        let mut tasks = Tasks::default();
+        let db = sqlite_db::memory().await.unwrap();
+        let actor = Actor::new(db).create(None).spawn(&mut tasks);
+
+        actor
+            .send(SetLossLimit(Some(LossLimit::weekly(Amount::ZERO))))
+            .await
+            .unwrap()
+            .unwrap();
+
+        actor.send(CheckNewOrder).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn limit_and_override_survive_restart() {
+        let mut tasks = Tasks::default();
+        let db = sqlite_db::memory().await.unwrap();
+        let limit = LossLimit::weekly(Amount::from_sat(10_000));
+
+        let actor = Actor::new(db.clone()).create(None).spawn(&mut tasks);
+        actor
+            .send(SetLossLimit(Some(limit)))
+            .await
+            .unwrap()
+            .unwrap();
+        actor.send(OverrideLossLimit).await.unwrap().unwrap();
+
+        let restarted = Actor::new(db).create(None).spawn(&mut tasks);
+        let status = restarted.send(GetRiskStatus).await.unwrap().unwrap();
+
+        assert_eq!(status.max_loss, Some(limit.max_loss));
+        assert_eq!(status.window_secs, Some(limit.window.whole_seconds()));
+        assert!(status.override_until.is_some());
+    }
+
+    #[test]
+    fn limit_is_reached_once_losses_are_realised() {
+        let zero = LossLimit::weekly(Amount::ZERO);
+        let limit = LossLimit::weekly(Amount::from_sat(10_000));
+
+        assert!(!zero.is_reached(Amount::ZERO));
+        assert!(zero.is_reached(Amount::from_sat(1)));
+        assert!(!limit.is_reached(Amount::from_sat(9_999)));
+        assert!(limit.is_reached(Amount::from_sat(10_000)));
+    }
+}
@@ -0,0 +1,297 @@
+//! Settlements of the taker scheduled for a point in time.
+//!
+//! A scheduled settlement closes a CFD at a given time, or shortly before
+//! the next rollover so that no further funding fee is paid. The CFD is
+//! settled collaboratively at the current market price. If the maker is
+//! offline, the proposal cannot be sent, or the maker does not settle
+//! within [`SETTLEMENT_TIMEOUT`], the CFD is force-closed by committing
+//! to the blockchain instead.
+//!
+//! Scheduled settlements are stored in the database. If the taker is not
+//! running at the scheduled time, the CFD is settled once it is started
+//! again.
+
+use crate::command;
+use crate::into_price_feed_symbol;
+use crate::online_status::ConnectionStatus;
+use crate::taker_cfd;
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use model::OrderId;
+use model::Price;
+use model::Timestamp;
+use serde::Deserialize;
+use sqlite_db::scheduled_settlement::ScheduledSettlement;
+use std::collections::HashMap;
+use time::ext::NumericalDuration;
+use time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::watch;
+use xtra::prelude::MessageChannel;
+use xtra::Address;
+use xtra_bitmex_price_feed::GetLatestQuotes;
+use xtra_bitmex_price_feed::LatestQuotes;
+use xtra_bitmex_price_feed::QUOTE_INTERVAL_MINUTES;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often the scheduled settlements are checked.
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long to wait for a collaborative settlement after it was proposed
+/// before committing to the blockchain.
+pub const SETTLEMENT_TIMEOUT: Duration = Duration::minutes(2);
+
+/// How long before the next rollover a settlement scheduled for the next
+/// funding window is proposed, so that the rollover does not happen
+/// first.
+const FUNDING_WINDOW_MARGIN: Duration = Duration::minutes(10);
+
+/// When to settle a CFD.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    /// At the given point in time
+    At(Timestamp),
+    /// Before the next rollover, which would charge the funding fee for
+    /// another settlement interval
+    NextFundingWindow,
+}
+
+/// Schedule the settlement of an open CFD, replacing any previous one.
+///
+/// Returns the time at which the CFD will be settled.
+#[derive(Clone, Copy)]
+pub struct ScheduleSettlement {
+    pub order_id: OrderId,
+    pub schedule: Schedule,
+}
+
+/// Cancel the scheduled settlement of a CFD.
+#[derive(Clone, Copy)]
+pub struct CancelScheduledSettlement {
+    pub order_id: OrderId,
+}
+
+/// Message sent to ourselves at an interval to check whether any
+/// scheduled settlement is due.
+#[derive(Clone, Copy)]
+struct CheckScheduledSettlements;
+
+pub struct Actor {
+    db: sqlite_db::Connection,
+    executor: command::Executor,
+    cfd_actor: Address<taker_cfd::Actor>,
+    price_feed: MessageChannel<GetLatestQuotes, LatestQuotes>,
+    maker_online_status: watch::Receiver<ConnectionStatus>,
+    /// Due settlements which were proposed, and when.
+    settling: HashMap<OrderId, OffsetDateTime>,
+}
+
+impl Actor {
+    pub fn new(
+        db: sqlite_db::Connection,
+        executor: command::Executor,
+        cfd_actor: Address<taker_cfd::Actor>,
+        price_feed: MessageChannel<GetLatestQuotes, LatestQuotes>,
+        maker_online_status: watch::Receiver<ConnectionStatus>,
+    ) -> Self {
+        Self {
+            db,
+            executor,
+            cfd_actor,
+            price_feed,
+            maker_online_status,
+            settling: HashMap::default(),
+        }
+    }
+
+    async fn check_scheduled_settlements(&mut self) -> Result<()> {
+        let now = Timestamp::now();
+        let due = self
+            .db
+            .load_scheduled_settlements()
+            .await?
+            .into_iter()
+            .filter(|scheduled| scheduled.settle_at <= now)
+            .collect::<Vec<_>>();
+
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        let quotes = self
+            .price_feed
+            .send(GetLatestQuotes)
+            .await
+            .context("Price feed not available")?;
+
+        for scheduled in due {
+            let order_id = scheduled.order_id;
+
+            if let Err(e) = self.settle(scheduled, &quotes).await {
+                tracing::warn!(%order_id, "Failed to execute scheduled settlement: {e:#}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn settle(
+        &mut self,
+        ScheduledSettlement { order_id, .. }: ScheduledSettlement,
+        quotes: &LatestQuotes,
+    ) -> Result<()> {
+        let cfd = match self.db.load_open_cfd::<model::Cfd>(order_id, ()).await {
+            Ok(cfd) => cfd,
+            Err(sqlite_db::Error::OpenCfdNotFound) => {
+                tracing::debug!(%order_id, "Removing scheduled settlement of closed CFD");
+                return self.remove(order_id).await;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if !cfd.is_position_open() {
+            tracing::debug!(%order_id, "Removing scheduled settlement of CFD being closed");
+            return self.remove(order_id).await;
+        }
+
+        if let Some(proposed_at) = self.settling.get(&order_id) {
+            if OffsetDateTime::now_utc() - *proposed_at >= SETTLEMENT_TIMEOUT {
+                tracing::warn!(
+                    %order_id,
+                    "Scheduled settlement was not settled in time, committing to the blockchain"
+                );
+                return self.commit(order_id).await;
+            }
+
+            return Ok(());
+        }
+
+        tracing::info!(%order_id, "Executing scheduled settlement");
+
+        if *self.maker_online_status.borrow() == ConnectionStatus::Offline {
+            tracing::info!(%order_id, "Maker is offline, committing to the blockchain");
+            return self.commit(order_id).await;
+        }
+
+        let quote = quotes
+            .get(&into_price_feed_symbol(cfd.contract_symbol()))
+            .context("No quote available")?;
+
+        if quote.is_older_than(QUOTE_INTERVAL_MINUTES.minutes() * 2) {
+            tracing::debug!(%order_id, "Latest quote is too old to propose settlement");
+            return Ok(());
+        }
+
+        let quote_timestamp = quote
+            .timestamp
+            .format(&time::format_description::well_known::Rfc3339)
+            .context("Failed to format timestamp")?;
+
+        let proposal = self
+            .cfd_actor
+            .send(taker_cfd::ProposeSettlement {
+                order_id,
+                bid: Price::new(quote.bid())?,
+                ask: Price::new(quote.ask())?,
+                quote_timestamp,
+            })
+            .await
+            .context("CFD actor disconnected")
+            .and_then(|result| result);
+
+        match proposal {
+            Ok(()) => {
+                self.settling.insert(order_id, OffsetDateTime::now_utc());
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!(
+                    %order_id,
+                    "Failed to propose settlement, committing to the blockchain: {e:#}"
+                );
+                self.commit(order_id).await
+            }
+        }
+    }
+
+    async fn commit(&mut self, order_id: OrderId) -> Result<()> {
+        self.executor
+            .execute(order_id, |cfd| cfd.manual_commit_to_blockchain())
+            .await?;
+
+        self.remove(order_id).await
+    }
+
+    async fn remove(&mut self, order_id: OrderId) -> Result<()> {
+        self.settling.remove(&order_id);
+        self.db.delete_scheduled_settlement(order_id).await
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, msg: ScheduleSettlement) -> Result<Timestamp> {
+        let ScheduleSettlement { order_id, schedule } = msg;
+
+        let cfd = self.db.load_open_cfd::<model::Cfd>(order_id, ()).await?;
+        anyhow::ensure!(
+            cfd.is_position_open(),
+            "Cannot schedule settlement of CFD {order_id} without an open position"
+        );
+
+        let settle_at = match schedule {
+            Schedule::At(settle_at) => settle_at,
+            Schedule::NextFundingWindow => {
+                let funding_window = cfd
+                    .next_funding_window()
+                    .context("CFD does not have a DLC")?;
+
+                Timestamp::new((funding_window - FUNDING_WINDOW_MARGIN).unix_timestamp())
+            }
+        };
+
+        tracing::info!(%order_id, settle_at = settle_at.seconds(), "Scheduling settlement");
+
+        self.db
+            .set_scheduled_settlement(order_id, settle_at)
+            .await?;
+
+        Ok(settle_at)
+    }
+
+    async fn handle(&mut self, msg: CancelScheduledSettlement) -> Result<()> {
+        let CancelScheduledSettlement { order_id } = msg;
+
+        tracing::info!(%order_id, "Cancelling scheduled settlement");
+
+        self.remove(order_id).await
+    }
+
+    async fn handle(&mut self, _: CheckScheduledSettlements) {
+        if let Err(e) = self.check_scheduled_settlements().await {
+            tracing::warn!("Failed to check scheduled settlements: {e:#}");
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(
+                CHECK_INTERVAL,
+                || CheckScheduledSettlements,
+                xtras::IncludeSpan::Never,
+            ),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
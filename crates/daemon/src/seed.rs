@@ -1,3 +1,4 @@
+use crate::cfd_keys::CfdKeys;
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Result;
@@ -22,6 +23,7 @@ pub struct Identities {
     pub identity_sk: x25519_dalek::StaticSecret,
     pub identity_pk: x25519_dalek::PublicKey,
     pub libp2p: Keypair,
+    pub cfd_keys: CfdKeys,
 }
 
 impl Identities {
@@ -69,6 +71,23 @@ pub trait Seed {
         )
     }
 
+    /// The keys of CFDs are derived from their own extended private key,
+    /// independent of the wallet.
+    ///
+    /// The network of the key is irrelevant, as it is never serialized.
+    fn derive_cfd_keys(&self) -> CfdKeys {
+        let mut ext_priv_key_seed = [0u8; 64];
+
+        Hkdf::<Sha256>::new(None, &self.seed())
+            .expand(b"CFD_KEYS", &mut ext_priv_key_seed)
+            .expect("okm array is of correct length");
+
+        let root = ExtendedPrivKey::new_master(Network::Bitcoin, &ext_priv_key_seed)
+            .expect("HKDF output to be a valid private key");
+
+        CfdKeys::new(root)
+    }
+
     fn derive_identities(&self) -> Identities {
         let (identity_pk, identity_sk) = self.derive_identity();
         let keypair_libp2p = self.derive_ed25519_keypair();
@@ -77,12 +96,16 @@ pub trait Seed {
             identity_sk,
             identity_pk,
             libp2p: Keypair::Ed25519(keypair_libp2p),
+            cfd_keys: self.derive_cfd_keys(),
         }
     }
 }
 
+/// Size of the seed file in bytes.
+pub const RANDOM_SEED_LENGTH: usize = 256;
+
 #[derive(Copy, Clone)]
-pub struct RandomSeed([u8; 256]);
+pub struct RandomSeed([u8; RANDOM_SEED_LENGTH]);
 
 impl Seed for RandomSeed {
     fn seed(&self) -> Vec<u8> {
@@ -111,7 +134,8 @@ impl RandomSeed {
         Ok(seed)
     }
 
-    async fn read_from(path: &Path) -> Result<Self> {
+    /// Read an existing seed from `path`.
+    pub async fn read_from(path: &Path) -> Result<Self> {
         let bytes = tokio::fs::read(path).await?;
 
         let bytes = bytes
@@ -135,7 +159,7 @@ impl RandomSeed {
 
 impl Default for RandomSeed {
     fn default() -> Self {
-        let mut seed = [0u8; 256];
+        let mut seed = [0u8; RANDOM_SEED_LENGTH];
         rand::thread_rng().fill(&mut seed);
 
         Self(seed)
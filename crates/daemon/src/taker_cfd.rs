@@ -1,29 +1,44 @@
 use crate::collab_settlement;
 use crate::collab_settlement::taker::Settle;
+use crate::maker_connection;
 use crate::order;
 use crate::projection;
+use crate::risk_limits;
+use crate::wallet;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
+use bdk::bitcoin::Amount;
 use model::libp2p::PeerId;
 use model::market_closing_price;
+use model::validate_quantity;
 use model::Cfd;
+use model::ContractSymbol;
 use model::Contracts;
+use model::FundingRate;
 use model::Identity;
+use model::InvalidQuantity;
 use model::Leverage;
 use model::OfferId;
+use model::OfferPair;
 use model::OrderId;
+use model::Position;
 use model::Price;
 use model::Role;
+use offer::OfferUnavailable;
 use sqlite_db;
 use std::collections::HashMap;
 use time::OffsetDateTime;
+use xtra::prelude::MessageChannel;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncSafe;
 
 #[derive(Clone, Copy)]
 pub struct PlaceOrder {
+    /// Caller-supplied ID for the order, which makes placing the
+    /// order idempotent. A new ID is generated if `None`.
+    pub order_id: Option<OrderId>,
     pub offer_id: OfferId,
     pub quantity: Contracts,
     pub leverage: Leverage,
@@ -37,14 +52,66 @@ pub struct ProposeSettlement {
     pub quote_timestamp: String,
 }
 
+/// Reasons why an order cannot be placed for an offer, detected before
+/// sending the order to the maker.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidOrder {
+    #[error(transparent)]
+    Quantity(#[from] InvalidQuantity),
+    #[error("Leverage {leverage} is not one of the leverage choices of the offer")]
+    LeverageNotOffered { leverage: Leverage },
+}
+
+/// An order was already placed with the caller-supplied ID, but for a
+/// different offer, quantity or leverage.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Order {order_id} was already placed with different parameters")]
+pub struct ConflictingOrder {
+    pub order_id: OrderId,
+}
+
+/// Check that the maker would accept an order for `quantity` and
+/// `leverage` given the bounds of `offer`.
+fn validate_order(
+    offer: &model::Offer,
+    quantity: Contracts,
+    leverage: Leverage,
+) -> Result<(), InvalidOrder> {
+    validate_quantity(
+        quantity,
+        offer.min_quantity,
+        offer.max_quantity,
+        offer.lot_size,
+    )?;
+
+    if !offer.leverage_choices.contains(&leverage) {
+        return Err(InvalidOrder::LeverageNotOffered { leverage });
+    }
+
+    Ok(())
+}
+
+/// Query the funding rate the maker currently offers for CFDs in which
+/// the maker holds `position_maker`.
+#[derive(Clone, Copy)]
+pub struct GetFundingRate {
+    pub contract_symbol: ContractSymbol,
+    pub position_maker: Position,
+}
+
 pub struct Actor {
     db: sqlite_db::Connection,
     projection_actor: xtra::Address<projection::Actor>,
     collab_settlement_actor: xtra::Address<collab_settlement::taker::Actor>,
     order_actor: xtra::Address<order::taker::Actor>,
+    risk_limits_actor: xtra::Address<risk_limits::Actor>,
+    list_utxos: MessageChannel<wallet::ListUtxos, Result<Vec<wallet::Utxo>>>,
     offers: Offers,
     maker_identity: Identity,
     maker_peer_id: PeerId,
+    /// Orders placed with a caller-supplied ID since we started, which
+    /// may not have made it into the database yet.
+    placed_orders: HashMap<OrderId, (OfferId, Contracts, Leverage)>,
 }
 
 impl Actor {
@@ -53,6 +120,8 @@ impl Actor {
         projection_actor: xtra::Address<projection::Actor>,
         collab_settlement_actor: xtra::Address<collab_settlement::taker::Actor>,
         order_actor: xtra::Address<order::taker::Actor>,
+        risk_limits_actor: xtra::Address<risk_limits::Actor>,
+        list_utxos: MessageChannel<wallet::ListUtxos, Result<Vec<wallet::Utxo>>>,
         maker_identity: Identity,
         maker_peer_id: PeerId,
     ) -> Self {
@@ -61,23 +130,98 @@ impl Actor {
             projection_actor,
             collab_settlement_actor,
             order_actor,
+            risk_limits_actor,
+            list_utxos,
             offers: Offers::default(),
             maker_identity,
             maker_peer_id,
+            placed_orders: HashMap::new(),
         }
     }
+
+    /// The offer, quantity and leverage with which an order with the
+    /// caller-supplied `order_id` has already been placed, if it has.
+    async fn already_placed(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Option<(OfferId, Contracts, Leverage)>> {
+        if let Some(parameters) = self.placed_orders.get(&order_id) {
+            return Ok(Some(*parameters));
+        }
+
+        self.db.load_order_parameters(order_id).await
+    }
+
+    /// Ensure that the wallet holds enough funds to take `offer`.
+    ///
+    /// UTXOs which are frozen or reserved for another lock transaction
+    /// are not counted, so we don't send an order to the maker which we
+    /// would fail to fund during contract setup.
+    async fn check_balance(
+        &self,
+        offer: &model::Offer,
+        quantity: Contracts,
+        leverage: Leverage,
+    ) -> Result<()> {
+        let utxos = self
+            .list_utxos
+            .send(wallet::ListUtxos)
+            .await
+            .context("Wallet actor disconnected")??;
+        let available = utxos
+            .iter()
+            .filter(|utxo| utxo.is_spendable())
+            .fold(Amount::ZERO, |total, utxo| total + utxo.amount);
+
+        let required = offer.required_taker_balance(quantity, leverage);
+
+        if available < required {
+            match offer.min_taker_balance {
+                Some(min_taker_balance) if min_taker_balance == required => bail!(
+                    "The maker requires a balance of at least {min_taker_balance} to take this \
+                     offer, but only {available} is available in the wallet"
+                ),
+                _ => bail!(
+                    "Insufficient funds to cover the margin of {required}, only {available} is \
+                     available in the wallet"
+                ),
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[xtra_productivity]
 impl Actor {
     async fn handle_latest_offers(&mut self, msg: offer::taker::LatestOffers) {
-        self.offers.insert(msg.0.clone());
+        let offers = msg.0.iter().flat_map(OfferPair::offers).cloned();
+        self.offers.insert(offers.collect());
 
         if let Err(e) = self.projection_actor.send(projection::Update(msg.0)).await {
             tracing::warn!("Failed to send current offers to projection actor: {e:#}");
         };
     }
 
+    async fn handle_probe(&mut self, _: xtras::Probe) {}
+
+    async fn handle_update_maker_address(&mut self, msg: maker_connection::UpdateMakerAddress) {
+        let maker_peer_id = PeerId::from(msg.peer_id);
+
+        // Offers received from the previous maker cannot be taken anymore
+        if maker_peer_id != self.maker_peer_id {
+            self.offers = Offers::default();
+        }
+
+        self.maker_peer_id = maker_peer_id;
+    }
+
+    async fn handle_get_funding_rate(&mut self, msg: GetFundingRate) -> Option<FundingRate> {
+        self.offers
+            .latest(msg.contract_symbol, msg.position_maker)
+            .map(|offer| offer.funding_rate)
+    }
+
     async fn handle_propose_settlement(&mut self, msg: ProposeSettlement) -> Result<()> {
         let ProposeSettlement {
             order_id,
@@ -108,21 +252,40 @@ impl Actor {
 
     async fn handle(&mut self, msg: PlaceOrder) -> Result<OrderId> {
         let PlaceOrder {
+            order_id,
             offer_id,
             quantity,
             leverage,
         } = msg;
 
-        let offer = self
-            .offers
-            .get(&offer_id)
-            .context("Offer to take could not be found in current maker offers, you might have an outdated offer")?;
+        if let Some(order_id) = order_id {
+            if let Some(parameters) = self.already_placed(order_id).await? {
+                if parameters != (offer_id, quantity, leverage) {
+                    return Err(ConflictingOrder { order_id }.into());
+                }
+
+                tracing::info!(%order_id, "Order already placed, returning existing CFD");
+                return Ok(order_id);
+            }
+        }
+
+        let offer = self.offers.get(offer_id, OffsetDateTime::now_utc())?;
 
         if !offer.is_safe_to_take(OffsetDateTime::now_utc()) {
             bail!("The maker's offer appears to be outdated, refusing to place order");
         }
 
-        let order_id = OrderId::default();
+        validate_order(&offer, quantity, leverage)?;
+
+        self.risk_limits_actor
+            .send(risk_limits::CheckNewOrder)
+            .await
+            .context("Failed to check risk limits")??;
+
+        self.check_balance(&offer, quantity, leverage).await?;
+
+        let is_caller_supplied = order_id.is_some();
+        let order_id = order_id.unwrap_or_default();
         let place_order = order::taker::PlaceOrder::new(
             order_id,
             offer,
@@ -136,6 +299,11 @@ impl Actor {
             .await
             .context("Failed to place order")?;
 
+        if is_caller_supplied {
+            self.placed_orders
+                .insert(order_id, (offer_id, quantity, leverage));
+        }
+
         Ok(order_id)
     }
 }
@@ -150,10 +318,49 @@ impl Offers {
         }
     }
 
-    fn get(&mut self, id: &OfferId) -> Option<model::Offer> {
+    /// Get the offer with `id` if it can still be taken.
+    ///
+    /// Offers are kept around until they expire, so an offer which was
+    /// replaced by a newer one for the same contract symbol and position
+    /// is reported as superseded.
+    fn get(&mut self, id: OfferId, now: OffsetDateTime) -> Result<model::Offer, OfferUnavailable> {
+        let offer = self
+            .0
+            .get(&id)
+            .cloned()
+            .ok_or(OfferUnavailable::NotFound(id))?;
+
+        if offer.is_expired(now) {
+            return Err(OfferUnavailable::Expired(id));
+        }
+
+        let newer = self
+            .latest(offer.contract_symbol, offer.position_maker)
+            .filter(|latest| latest.creation_timestamp_maker > offer.creation_timestamp_maker);
+
+        match newer {
+            Some(newer) => Err(OfferUnavailable::Superseded {
+                offer_id: id,
+                superseded_by: newer.id,
+            }),
+            None => Ok(offer),
+        }
+    }
+
+    fn latest(
+        &mut self,
+        contract_symbol: ContractSymbol,
+        position_maker: Position,
+    ) -> Option<model::Offer> {
         self.remove_old_offers();
 
-        self.0.get(id).cloned()
+        self.0
+            .values()
+            .filter(|offer| {
+                offer.contract_symbol == contract_symbol && offer.position_maker == position_maker
+            })
+            .max_by_key(|offer| offer.creation_timestamp_maker)
+            .cloned()
     }
 
     fn remove_old_offers(&mut self) {
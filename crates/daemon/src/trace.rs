@@ -0,0 +1,75 @@
+//! Traceability of a position across subsystems.
+//!
+//! A [`Trace`] links the offer a CFD was created from, the order, the
+//! events recorded for the CFD and the transactions which were
+//! published for it, so that the complete lineage of a position can be
+//! followed in one place. Annotations of the operator about the period
+//! in which the CFD was open are included alongside.
+
+use crate::block_explorer::BlockExplorer;
+use crate::projection;
+use crate::projection::TxUrl;
+use anyhow::Result;
+use model::OfferId;
+use model::OrderId;
+use model::Timestamp;
+use serde::Serialize;
+use sqlite_db::annotations::Annotation;
+use sqlite_db::trace::Lifecycle;
+use sqlite_db::trace::TracedEvent;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Trace {
+    pub offer_id: OfferId,
+    pub order_id: OrderId,
+    pub lifecycle: Lifecycle,
+    pub events: Vec<TracedEvent>,
+    /// The published transactions, ending with the settlement
+    /// transaction once the CFD is closed
+    pub transactions: Vec<TxUrl>,
+    /// Annotations of periods which overlap with the lifetime of the CFD
+    pub annotations: Vec<Annotation>,
+}
+
+/// Load the trace of the CFD with `order_id`, if there is such a CFD.
+pub async fn load(
+    db: &sqlite_db::Connection,
+    block_explorer: &BlockExplorer,
+    order_id: OrderId,
+) -> Result<Option<Trace>> {
+    let trace = match db.load_trace(order_id).await? {
+        Some(trace) => trace,
+        None => return Ok(None),
+    };
+
+    let transactions = match trace.lifecycle {
+        Lifecycle::Open => db
+            .load_open_cfd::<projection::Cfd>(order_id, block_explorer.clone())
+            .await?
+            .transactions(),
+        Lifecycle::Closed => db
+            .load_closed_cfd::<projection::Cfd>(order_id, block_explorer.clone())
+            .await?
+            .transactions(),
+        // Failed CFDs never got to publish a transaction
+        Lifecycle::Failed => Vec::new(),
+    };
+
+    let first_event_at = trace.events.first().map(|event| event.timestamp);
+    let last_event_at = trace.events.last().map(|event| event.timestamp);
+    let since = first_event_at.unwrap_or_else(Timestamp::now);
+    let until = match trace.lifecycle {
+        Lifecycle::Open => Timestamp::now(),
+        Lifecycle::Closed | Lifecycle::Failed => last_event_at.unwrap_or(since),
+    };
+    let annotations = db.load_annotations_between(since, until).await?;
+
+    Ok(Some(Trace {
+        offer_id: trace.offer_id,
+        order_id,
+        lifecycle: trace.lifecycle,
+        events: trace.events,
+        transactions,
+        annotations,
+    }))
+}
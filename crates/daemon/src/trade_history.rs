@@ -0,0 +1,463 @@
+//! Export of the trade history, e.g. for tax reporting.
+//!
+//! Every closed CFD, including the ones that have been archived, is
+//! reported as one [`Trade`]. Amounts are reported in bitcoin and,
+//! where possible, in US dollars valued at the closing price. Only
+//! BTCUSD prices can be used to value bitcoin in US dollars, so the US
+//! dollar amounts are unknown for other contracts and for refunded CFDs.
+//!
+//! If a fiat currency was selected, the US dollar amounts are also
+//! converted into that currency at the exchange rate of the day the CFD
+//! was closed.
+//!
+//! Annotations of the operator about the period in which a CFD was open
+//! are reported with the trade, to explain odd histories.
+
+use crate::fiat_rates;
+use crate::fiat_rates::FiatAmount;
+use crate::fiat_rates::FiatRate;
+use anyhow::Result;
+use bdk::bitcoin::Denomination;
+use bdk::bitcoin::SignedAmount;
+use model::calculate_effective_entry_price;
+use model::calculate_effective_exit_price;
+use model::calculate_margin;
+use model::sats_to_usd;
+use model::ClosedCfd;
+use model::ContractSymbol;
+use model::Contracts;
+use model::FeeAccount;
+use model::Leverage;
+use model::OrderId;
+use model::Position;
+use model::Price;
+use model::Role;
+use model::Settlement;
+use model::Timestamp;
+use parse_display::Display;
+use parse_display::FromStr;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlite_db::annotations::Annotation;
+use std::collections::HashMap;
+use std::fmt::Write;
+use time::format_description::well_known::Rfc3339;
+use time::Date;
+use time::OffsetDateTime;
+
+/// The format in which the trade history can be exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, FromStr)]
+#[display(style = "lowercase")]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+/// How a CFD was closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+#[display(style = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum SettlementKind {
+    /// Settled collaboratively with the counterparty
+    Collaborative,
+    /// Settled on-chain through a CET, after liquidation or expiry
+    Cet,
+    /// Refunded because the oracle did not attest
+    Refund,
+}
+
+/// A closed CFD as reported in the trade history.
+#[derive(Debug, Clone, Serialize)]
+pub struct Trade {
+    pub order_id: OrderId,
+    pub contract_symbol: ContractSymbol,
+    pub position: Position,
+    pub role: Role,
+    pub quantity: Contracts,
+    pub opened_at: Timestamp,
+    pub closed_at: Timestamp,
+    pub open_price: Price,
+    /// `None` if the CFD was refunded.
+    pub close_price: Option<Price>,
+    pub settlement: SettlementKind,
+
+    /// Sum of all fees paid by us, including the funding fees
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub fees_btc: SignedAmount,
+    /// Sum of the funding fees paid by us during rollovers
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub funding_paid_btc: SignedAmount,
+    /// Realized profit or loss, net of all fees
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub realized_pnl_btc: SignedAmount,
+
+    pub fees_usd: Option<Decimal>,
+    pub funding_paid_usd: Option<Decimal>,
+    pub realized_pnl_usd: Option<Decimal>,
+    /// Amounts valued in the fiat currency selected by the user
+    ///
+    /// Unknown if the US dollar amounts are, or if the exchange rate
+    /// could not be fetched.
+    pub fees_fiat: Option<FiatAmount>,
+    pub funding_paid_fiat: Option<FiatAmount>,
+    pub realized_pnl_fiat: Option<FiatAmount>,
+
+    /// Open price including all fees, i.e. the break-even price
+    pub effective_open_price: Option<Price>,
+    /// Close price including all fees, such that the realized profit
+    /// is made between the effective open and close price
+    pub effective_close_price: Option<Price>,
+
+    /// Annotations of periods which overlap with the lifetime of the CFD
+    pub annotations: Vec<Annotation>,
+}
+
+impl Trade {
+    fn new(
+        cfd: ClosedCfd,
+        closed_at: Timestamp,
+        funding_history: Vec<sqlite_db::FundingPayment>,
+        fiat_rate: Option<&FiatRate>,
+        annotations: Vec<Annotation>,
+    ) -> Result<Self> {
+        let ClosedCfd {
+            id,
+            position,
+            initial_price,
+            taker_leverage,
+            n_contracts,
+            role,
+            fees,
+            settlement,
+            creation_timestamp,
+            contract_symbol,
+            ..
+        } = cfd;
+
+        let our_leverage = match role {
+            Role::Maker => Leverage::ONE,
+            Role::Taker => taker_leverage,
+        };
+        let margin = calculate_margin(contract_symbol, initial_price, n_contracts, our_leverage);
+
+        let (settlement, close_price, payout) = match settlement {
+            Settlement::Collaborative { payout, price, .. } => {
+                (SettlementKind::Collaborative, Some(price), payout.inner())
+            }
+            Settlement::Cet { payout, price, .. } => {
+                (SettlementKind::Cet, Some(price), payout.inner())
+            }
+            Settlement::Refund { payout, .. } => (SettlementKind::Refund, None, payout.inner()),
+        };
+
+        let funding_paid = funding_history
+            .into_iter()
+            .fold(FeeAccount::new(position, role), |account, payment| {
+                account.add_funding_fee(payment.funding_fee)
+            })
+            .balance();
+        let realized_pnl = payout.to_signed()? - margin.to_signed()?;
+
+        let effective_open_price = calculate_effective_entry_price(
+            contract_symbol,
+            position,
+            initial_price,
+            n_contracts,
+            fees.inner(),
+        );
+        let effective_close_price = effective_open_price.and_then(|effective_open_price| {
+            calculate_effective_exit_price(
+                contract_symbol,
+                position,
+                effective_open_price,
+                n_contracts,
+                realized_pnl,
+            )
+        });
+
+        let to_usd = |amount| match (contract_symbol, close_price) {
+            (ContractSymbol::BtcUsd, Some(btc_price)) => {
+                Some(signed_sats_to_usd(amount, btc_price))
+            }
+            _ => None,
+        };
+        let to_fiat = |amount| match (contract_symbol, close_price, fiat_rate) {
+            (ContractSymbol::BtcUsd, Some(btc_price), Some(fiat_rate)) => {
+                Some(fiat_rate.value(amount, btc_price))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            order_id: id,
+            contract_symbol,
+            position,
+            role,
+            quantity: n_contracts,
+            opened_at: creation_timestamp,
+            closed_at,
+            open_price: initial_price,
+            close_price,
+            settlement,
+            fees_btc: fees.inner(),
+            funding_paid_btc: funding_paid,
+            realized_pnl_btc: realized_pnl,
+            fees_usd: to_usd(fees.inner()),
+            funding_paid_usd: to_usd(funding_paid),
+            realized_pnl_usd: to_usd(realized_pnl),
+            fees_fiat: to_fiat(fees.inner()),
+            funding_paid_fiat: to_fiat(funding_paid),
+            realized_pnl_fiat: to_fiat(realized_pnl),
+            effective_open_price: effective_open_price.map(round_price),
+            effective_close_price: effective_close_price.map(round_price),
+            annotations,
+        })
+    }
+
+    /// The currency of the fiat amounts, if they are known.
+    fn fiat_currency(&self) -> Option<fiat_rates::Currency> {
+        self.realized_pnl_fiat.map(|fiat| fiat.currency)
+    }
+}
+
+/// Load all closed CFDs as trades, in the order in which they were
+/// closed.
+///
+/// The amounts are valued in fiat with the exchange rates of
+/// `fiat_rate_provider`, if given.
+pub async fn load(
+    db: &sqlite_db::Connection,
+    fiat_rate_provider: Option<&fiat_rates::Provider>,
+) -> Result<Vec<Trade>> {
+    let mut trades = Vec::new();
+    let mut rates_by_date = HashMap::new();
+
+    for id in db.load_closed_cfd_ids().await? {
+        let cfd = match db.load_closed_cfd::<Closed>(id, ()).await?.0 {
+            Some(cfd) => cfd,
+            None => continue,
+        };
+        let closed_at = db.load_closed_timestamp(id).await?;
+        let funding_history = db.load_funding_history(id).await?;
+        let annotations = db
+            .load_annotations_between(cfd.creation_timestamp, closed_at)
+            .await?;
+
+        let fiat_rate = match fiat_rate_provider {
+            Some(provider) => {
+                let date = OffsetDateTime::from_unix_timestamp(closed_at.seconds())?.date();
+
+                match rates_by_date.get(&date) {
+                    Some(rate) => *rate,
+                    None => {
+                        let rate = fetch_rate(provider, date).await;
+                        rates_by_date.insert(date, rate);
+                        rate
+                    }
+                }
+            }
+            None => None,
+        };
+
+        trades.push(Trade::new(
+            cfd,
+            closed_at,
+            funding_history,
+            fiat_rate.as_ref(),
+            annotations,
+        )?);
+    }
+
+    trades.sort_by_key(|trade| trade.closed_at);
+
+    Ok(trades)
+}
+
+/// The exchange rate on `date`, or `None` if it cannot be fetched.
+async fn fetch_rate(provider: &fiat_rates::Provider, date: Date) -> Option<FiatRate> {
+    match provider.on(date).await {
+        Ok(rate) => Some(rate),
+        Err(e) => {
+            tracing::warn!(%date, "Failed to fetch exchange rate: {e:#}");
+            None
+        }
+    }
+}
+
+/// Render `trades` in the given `format`.
+pub fn render(trades: &[Trade], format: Format) -> Result<String> {
+    match format {
+        Format::Csv => to_csv(trades),
+        Format::Json => Ok(serde_json::to_string(trades)?),
+    }
+}
+
+/// Render `trades` as CSV, with one line per trade and a header line.
+///
+/// Amounts of bitcoin are given in BTC, timestamps in RFC 3339 format.
+/// The texts of all annotations of a trade share one field.
+fn to_csv(trades: &[Trade]) -> Result<String> {
+    let mut csv = String::new();
+
+    writeln!(
+        csv,
+        "order_id,contract_symbol,position,role,quantity,opened_at,closed_at,open_price,\
+         close_price,settlement,fees_btc,funding_paid_btc,realized_pnl_btc,fees_usd,\
+         funding_paid_usd,realized_pnl_usd,fiat_currency,fees_fiat,funding_paid_fiat,\
+         realized_pnl_fiat,effective_open_price,effective_close_price,annotations"
+    )?;
+
+    for trade in trades {
+        writeln!(
+            csv,
+            "{},{},{:?},{:?},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            trade.order_id,
+            trade.contract_symbol,
+            trade.position,
+            trade.role,
+            trade.quantity,
+            format_timestamp(trade.opened_at)?,
+            format_timestamp(trade.closed_at)?,
+            trade.open_price,
+            optional(trade.close_price),
+            trade.settlement,
+            trade.fees_btc.to_string_in(Denomination::Bitcoin),
+            trade.funding_paid_btc.to_string_in(Denomination::Bitcoin),
+            trade.realized_pnl_btc.to_string_in(Denomination::Bitcoin),
+            optional(trade.fees_usd),
+            optional(trade.funding_paid_usd),
+            optional(trade.realized_pnl_usd),
+            optional(trade.fiat_currency()),
+            optional(trade.fees_fiat.map(|fiat| fiat.amount)),
+            optional(trade.funding_paid_fiat.map(|fiat| fiat.amount)),
+            optional(trade.realized_pnl_fiat.map(|fiat| fiat.amount)),
+            optional(trade.effective_open_price),
+            optional(trade.effective_close_price),
+            annotations_field(&trade.annotations),
+        )?;
+    }
+
+    Ok(csv)
+}
+
+fn format_timestamp(timestamp: Timestamp) -> Result<String> {
+    let timestamp = OffsetDateTime::from_unix_timestamp(timestamp.seconds())?.format(&Rfc3339)?;
+
+    Ok(timestamp)
+}
+
+/// Round `price` to cents, like the prices reported elsewhere.
+fn round_price(price: Price) -> Price {
+    Price::new(price.into_decimal().round_dp(2)).unwrap_or(price)
+}
+
+/// Render the texts of `annotations` as one CSV field, quoted if needed.
+fn annotations_field(annotations: &[Annotation]) -> String {
+    let texts = annotations
+        .iter()
+        .map(|annotation| annotation.text.as_str())
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    if texts.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", texts.replace('"', "\"\""))
+    } else {
+        texts
+    }
+}
+
+/// Render a value which may be unknown as an empty CSV field.
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// The value of `amount` in US dollars at the bitcoin price
+/// `btc_price`, rounded to cents.
+fn signed_sats_to_usd(amount: SignedAmount, btc_price: Price) -> Decimal {
+    let abs = amount
+        .abs()
+        .to_unsigned()
+        .expect("absolute amount to be positive");
+    let usd = sats_to_usd(abs, btc_price).round_dp(2);
+
+    if amount.is_negative() {
+        -usd
+    } else {
+        usd
+    }
+}
+
+/// Read-model of a CFD which is only interested in closed CFDs.
+#[derive(Debug, Clone, Copy)]
+struct Closed(Option<ClosedCfd>);
+
+impl sqlite_db::CfdAggregate for Closed {
+    type CtorArgs = ();
+
+    fn new(_: Self::CtorArgs, _: sqlite_db::Cfd) -> Self {
+        Self(None)
+    }
+
+    fn apply(self, _: model::CfdEvent) -> Self {
+        self
+    }
+
+    fn version(&self) -> u32 {
+        0
+    }
+}
+
+impl sqlite_db::ClosedCfdAggregate for Closed {
+    fn new_closed(_: Self::CtorArgs, cfd: ClosedCfd) -> Self {
+        Self(Some(cfd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn losses_are_negative_in_usd() {
+        let btc_price = Price::new(dec!(20_000)).unwrap();
+
+        assert_eq!(
+            signed_sats_to_usd(SignedAmount::from_sat(-50_000), btc_price),
+            dec!(-10)
+        );
+        assert_eq!(
+            signed_sats_to_usd(SignedAmount::from_sat(50_000), btc_price),
+            dec!(10)
+        );
+    }
+
+    #[test]
+    fn empty_history_only_has_header() {
+        let csv = to_csv(&[]).unwrap();
+
+        assert_eq!(csv.lines().count(), 1);
+        assert!(csv.starts_with("order_id,"));
+    }
+
+    #[test]
+    fn annotations_share_one_quoted_field() {
+        let annotation = |text: &str| Annotation {
+            id: 0,
+            text: text.to_string(),
+            starts_at: Timestamp::new(0),
+            ends_at: Timestamp::new(0),
+            created_at: Timestamp::new(0),
+        };
+
+        assert_eq!(annotations_field(&[]), "");
+        assert_eq!(
+            annotations_field(&[annotation("restart"), annotation("upgrade")]),
+            "restart; upgrade"
+        );
+        assert_eq!(
+            annotations_field(&[annotation(r#"electrum outage, "14:00-15:00""#)]),
+            r#""electrum outage, ""14:00-15:00""""#
+        );
+    }
+}
@@ -1,4 +1,6 @@
 use crate::bitcoin::secp256k1::Secp256k1;
+use crate::electrum;
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Context;
@@ -6,6 +8,7 @@ use anyhow::Result;
 use async_trait::async_trait;
 use bdk::bitcoin::blockdata::constants;
 use bdk::bitcoin::hashes::Hash;
+use bdk::bitcoin::util::address::Payload;
 use bdk::bitcoin::util::bip32::ExtendedPrivKey;
 use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
 use bdk::bitcoin::Address;
@@ -14,9 +17,12 @@ use bdk::bitcoin::BlockHash;
 use bdk::bitcoin::Network;
 use bdk::bitcoin::OutPoint;
 use bdk::bitcoin::PublicKey;
+use bdk::bitcoin::Script;
+use bdk::bitcoin::Transaction;
 use bdk::bitcoin::Txid;
 use bdk::blockchain::Blockchain;
 use bdk::blockchain::ElectrumBlockchain;
+use bdk::blockchain::GetHeight;
 use bdk::database::BatchDatabase;
 use bdk::electrum_client;
 use bdk::electrum_client::ElectrumApi;
@@ -28,14 +34,17 @@ use bdk::FeeRate;
 use bdk::KeychainKind;
 use bdk::SignOptions;
 use bdk::SyncOptions;
+use bdk::TransactionDetails;
 use maia_core::PartyParams;
 use maia_core::TxBuilderExt;
 use model::Timestamp;
 use model::TxFeeRate;
 use model::WalletInfo;
+use serde::Serialize;
 use statrs::statistics::*;
 use std::collections::HashSet;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 use std::time::Instant;
 use tokio::runtime::Handle;
@@ -45,6 +54,10 @@ use xtra_productivity::xtra_productivity;
 use xtras::SendInterval;
 
 const SYNC_INTERVAL: Duration = Duration::from_secs(3 * 60);
+
+/// Expected virtual size of a child transaction spending a single P2WPKH
+/// output into a single P2WPKH output.
+const CPFP_CHILD_VBYTES: u64 = 110;
 pub const MAKER_WALLET_ID: &str = "maker-wallet";
 pub const TAKER_WALLET_ID: &str = "taker-wallet";
 
@@ -100,17 +113,27 @@ static STD_DEV_UTXO_VALUE_GAUGE: conquer_once::Lazy<prometheus::Gauge> =
 pub struct Actor<B, DB> {
     wallet: bdk::Wallet<DB>,
     blockchain_client: B,
+    /// The Electrum server to follow, and the one `blockchain_client` is
+    /// connected to.
+    active_electrum: electrum::ActiveServer,
+    electrum_url: String,
     used_utxos: LockedUtxos,
+    /// UTXOs which the user does not want to be spent into a lock
+    /// transaction.
+    frozen_utxos: HashSet<OutPoint>,
+    address_reuse: AddressReusePolicy,
     sender: watch::Sender<Option<WalletInfo>>,
 }
 
 impl Actor<ElectrumBlockchain, sled::Tree> {
     pub fn spawn(
-        electrum_rpc_url: &str,
+        active_electrum: electrum::ActiveServer,
         ext_priv_key: ExtendedPrivKey,
         db_path: PathBuf,
+        address_reuse: AddressReusePolicy,
     ) -> Result<(xtra::Address<Self>, watch::Receiver<Option<WalletInfo>>)> {
-        let client = electrum_client::Client::new(electrum_rpc_url)
+        let electrum_url = active_electrum.url();
+        let client = electrum_client::Client::new(&electrum_url)
             .context("Failed to initialize Electrum RPC client")?;
 
         ensure!(
@@ -149,7 +172,11 @@ impl Actor<ElectrumBlockchain, sled::Tree> {
             wallet,
             sender,
             used_utxos: LockedUtxos::new(time_to_lock),
+            frozen_utxos: HashSet::default(),
+            address_reuse,
             blockchain_client: ElectrumBlockchain::from(client),
+            active_electrum,
+            electrum_url,
         };
 
         let (addr, fut) = actor.create(None).run();
@@ -164,11 +191,162 @@ impl<DB> Actor<ElectrumBlockchain, DB>
 where
     DB: BatchDatabase,
 {
+    /// Reconnect to the active Electrum server if we failed over to a
+    /// different one.
+    fn follow_active_electrum(&mut self) -> Result<()> {
+        let url = self.active_electrum.url();
+
+        if url == self.electrum_url {
+            return Ok(());
+        }
+
+        let client = electrum_client::Client::new(&url)
+            .context("Failed to initialize Electrum RPC client")?;
+
+        ensure!(
+            seed_and_rpc_on_same_network(&client, self.wallet.network())?,
+            "Wallet and Electrum server {url} on different networks"
+        );
+
+        tracing::info!(%url, "Wallet switched to Electrum server");
+
+        self.blockchain_client = ElectrumBlockchain::from(client);
+        self.electrum_url = url;
+
+        Ok(())
+    }
+
+    /// Sign and broadcast a transaction paying to `outputs`.
+    fn withdraw(
+        &mut self,
+        outputs: Outputs,
+        fee: Option<FeeRate>,
+        utxos: &[OutPoint],
+        exclude: &[OutPoint],
+    ) -> Result<Txid> {
+        self.sync_internal()?;
+
+        outputs.validate(self.wallet.network())?;
+        check_address_reuse(&outputs, &self.used_scripts()?, self.address_reuse)?;
+
+        let unspent = self
+            .wallet
+            .list_unspent()?
+            .into_iter()
+            .map(|utxo| utxo.outpoint)
+            .collect::<HashSet<_>>();
+        let reserved = self.used_utxos.list().into_iter().collect::<HashSet<_>>();
+        validate_coin_control(utxos, exclude, &unspent, &self.frozen_utxos, &reserved)?;
+
+        let fee_rate = fee.unwrap_or_else(FeeRate::default_min_relay_fee);
+
+        // Lock the transaction to the current block height so that miners gain nothing from
+        // reorganising the chain to take its fee
+        let current_height = self
+            .blockchain_client
+            .get_height()
+            .context("Failed to get current block height")?;
+
+        let mut psbt = {
+            let mut tx_builder = self.wallet.build_tx();
+
+            let unspendable = exclude
+                .iter()
+                .chain(self.frozen_utxos.iter())
+                .chain(reserved.iter())
+                .copied()
+                .collect();
+
+            tx_builder
+                .fee_rate(fee_rate)
+                .unspendable(unspendable)
+                .nlocktime(current_height)
+                // Turn on RBF signaling
+                .enable_rbf();
+
+            if !utxos.is_empty() {
+                tracing::info!(?utxos, "Spending selected UTXOs only");
+
+                tx_builder.add_utxos(utxos)?.manually_selected_only();
+            }
+
+            match outputs {
+                Outputs::Recipients(recipients) => {
+                    for (address, amount) in recipients {
+                        tracing::info!(%amount, %address, "Withdrawing from wallet");
+
+                        tx_builder.add_recipient(address.script_pubkey(), amount.as_sat());
+                    }
+                }
+                Outputs::Drain(address) if !utxos.is_empty() => {
+                    tracing::info!(%address, "Draining selected UTXOs");
+
+                    tx_builder.drain_to(address.script_pubkey());
+                }
+                Outputs::Drain(address) => {
+                    tracing::info!(%address, "Draining wallet");
+
+                    tx_builder.drain_wallet().drain_to(address.script_pubkey());
+                }
+            }
+
+            let (psbt, _) = tx_builder.finish()?;
+
+            psbt
+        };
+
+        self.wallet.sign(&mut psbt, SignOptions::default())?;
+
+        let tx = psbt.extract_tx();
+        let txid = tx.txid();
+        self.blockchain_client.broadcast(&tx)?;
+
+        tracing::info!(%txid, "Withdraw successful");
+
+        Ok(txid)
+    }
+
+    /// The output scripts of all transactions of the wallet, i.e. the
+    /// addresses it has already paid to or received on.
+    fn used_scripts(&self) -> Result<HashSet<Script>> {
+        let scripts = self
+            .wallet
+            .list_transactions(true)?
+            .into_iter()
+            .filter_map(|details| details.transaction)
+            .flat_map(|tx| tx.output)
+            .map(|output| output.script_pubkey)
+            .collect();
+
+        Ok(scripts)
+    }
+
+    /// The unconfirmed transaction of the wallet spending `outpoint`,
+    /// e.g. the child of an earlier fee bump.
+    fn unconfirmed_spending_tx(&self, outpoint: OutPoint) -> Result<Option<TransactionDetails>> {
+        let details = self
+            .wallet
+            .list_transactions(true)?
+            .into_iter()
+            .find(|details| {
+                details.confirmation_time.is_none()
+                    && details.transaction.as_ref().map_or(false, |tx| {
+                        tx.input
+                            .iter()
+                            .any(|input| input.previous_output == outpoint)
+                    })
+            });
+
+        Ok(details)
+    }
+
     #[tracing::instrument(name = "Sync wallet", skip_all, err)]
     fn sync_internal(&mut self) -> Result<WalletInfo> {
         let now = Instant::now();
         tracing::trace!(target : "wallet", "Wallet sync started");
 
+        self.follow_active_electrum()?;
+
         tracing::debug_span!("Sync wallet database with blockchain").in_scope(|| {
             self.wallet
                 .sync(&self.blockchain_client, SyncOptions::default())
@@ -234,43 +412,104 @@ where
     }
 
     pub fn handle_withdraw(&mut self, msg: Withdraw) -> Result<Txid> {
+        let outputs = match msg.amount {
+            Some(amount) => Outputs::Recipients(vec![(msg.address, amount)]),
+            None => Outputs::Drain(msg.address),
+        };
+
+        self.withdraw(outputs, msg.fee, &msg.utxos, &msg.exclude)
+    }
+
+    pub fn handle_batch_withdraw(&mut self, msg: BatchWithdraw) -> Result<Txid> {
+        let outputs = Outputs::Recipients(msg.recipients);
+
+        self.withdraw(outputs, msg.fee, &msg.utxos, &msg.exclude)
+    }
+
+    pub fn handle_get_block_height(&mut self, _msg: GetBlockHeight) -> Result<u32> {
+        self.follow_active_electrum()?;
+
+        let height = self
+            .blockchain_client
+            .get_height()
+            .context("Failed to get current block height")?;
+
+        Ok(height)
+    }
+
+    pub fn handle_cpfp_transaction(&mut self, msg: CpfpTransaction) -> Result<Txid> {
         self.sync_internal()?;
 
-        if msg.address.network != self.wallet.network() {
-            bail!(
-                "Address has invalid network. It was {} but the wallet is connected to {}",
-                msg.address.network,
-                self.wallet.network()
-            )
+        let CpfpTransaction {
+            parent,
+            parent_fee,
+            target_fee_rate,
+        } = msg;
+        let parent_txid = parent.txid();
+
+        let mut outpoint = None;
+        for (vout, output) in parent.output.iter().enumerate() {
+            if self.wallet.is_mine(&output.script_pubkey)? {
+                outpoint = Some(OutPoint::new(parent_txid, vout as u32));
+                break;
+            }
         }
+        let outpoint = outpoint.with_context(|| {
+            format!("Transaction {parent_txid} has no output belonging to the wallet")
+        })?;
 
-        let fee_rate = msg.fee.unwrap_or_else(FeeRate::default_min_relay_fee);
-        let address = msg.address;
+        let parent_vbytes = (parent.weight() as u64 + 3) / 4;
+        let child_fee =
+            cpfp_child_fee(parent_vbytes, parent_fee, target_fee_rate).with_context(|| {
+                format!(
+                    "Transaction {parent_txid} already pays enough fees to reach {} sat/vbyte",
+                    target_fee_rate.as_sat_vb()
+                )
+            })?;
+
+        // The output can only be spent once, so a child of an earlier
+        // fee bump is replaced by one paying for the whole package
+        let (mut psbt, child_fee) = match self.unconfirmed_spending_tx(outpoint)? {
+            Some(previous_child) => {
+                let previous_txid = previous_child.txid;
+                let previous_fee = Amount::from_sat(previous_child.fee.unwrap_or_default());
+
+                if previous_fee >= child_fee {
+                    tracing::debug!(
+                        %previous_txid,
+                        %parent_txid,
+                        %previous_fee,
+                        "CPFP transaction already pays enough fees"
+                    );
+                    return Ok(previous_txid);
+                }
 
-        let mut psbt = {
-            let mut tx_builder = self.wallet.build_tx();
+                let child_fee = cpfp_replacement_fee(child_fee, previous_fee);
 
-            tx_builder
-                .fee_rate(fee_rate)
-                // Turn on RBF signaling
-                .enable_rbf();
+                let mut tx_builder = self.wallet.build_fee_bump(previous_txid)?;
+                tx_builder.fee_absolute(child_fee.as_sat()).enable_rbf();
 
-            match msg.amount {
-                Some(amount) => {
-                    tracing::info!(%amount, %address, "Withdrawing from wallet");
+                let (psbt, _) = tx_builder.finish()?;
 
-                    tx_builder.add_recipient(address.script_pubkey(), amount.as_sat());
-                }
-                None => {
-                    tracing::info!(%address, "Draining wallet");
+                tracing::debug!(%previous_txid, %parent_txid, "Replacing CPFP transaction");
 
-                    tx_builder.drain_wallet().drain_to(address.script_pubkey());
-                }
+                (psbt, child_fee)
             }
+            None => {
+                let address = self.wallet.get_address(AddressIndex::New)?.address;
 
-            let (psbt, _) = tx_builder.finish()?;
+                let mut tx_builder = self.wallet.build_tx();
+                tx_builder
+                    .add_utxo(outpoint)?
+                    .manually_selected_only()
+                    .fee_absolute(child_fee.as_sat())
+                    .drain_to(address.script_pubkey())
+                    .enable_rbf();
 
-            psbt
+                let (psbt, _) = tx_builder.finish()?;
+
+                (psbt, child_fee)
+            }
         };
 
         self.wallet.sign(&mut psbt, SignOptions::default())?;
@@ -279,7 +518,7 @@ where
         let txid = tx.txid();
         self.blockchain_client.broadcast(&tx)?;
 
-        tracing::info!(%txid, "Withdraw successful");
+        tracing::info!(%txid, %parent_txid, %child_fee, "Published CPFP transaction");
 
         Ok(txid)
     }
@@ -291,6 +530,8 @@ where
     Self: xtra::Actor,
     DB: BatchDatabase,
 {
+    pub fn handle_probe(&mut self, _: xtras::Probe) {}
+
     pub fn handle_sign(&mut self, msg: Sign) -> Result<PartiallySignedTransaction> {
         let mut psbt = msg.psbt;
 
@@ -315,9 +556,12 @@ where
             fee_rate,
         }: BuildPartyParams,
     ) -> Result<PartyParams> {
-        let psbt = self
-            .wallet
-            .build_lock_tx(amount, &mut self.used_utxos, fee_rate.into())?;
+        let psbt = self.wallet.build_lock_tx(
+            amount,
+            &mut self.used_utxos,
+            &self.frozen_utxos,
+            fee_rate.into(),
+        )?;
 
         Ok(PartyParams {
             lock_psbt: psbt,
@@ -326,6 +570,58 @@ where
             address: self.wallet.get_address(AddressIndex::New)?.address,
         })
     }
+
+    pub fn handle_list_utxos(&mut self, _msg: ListUtxos) -> Result<Vec<Utxo>> {
+        let reserved = self.used_utxos.list().into_iter().collect::<HashSet<_>>();
+
+        let utxos = self
+            .wallet
+            .list_unspent()?
+            .into_iter()
+            .map(|utxo| Utxo {
+                outpoint: utxo.outpoint,
+                amount: Amount::from_sat(utxo.txout.value),
+                frozen: self.frozen_utxos.contains(&utxo.outpoint),
+                reserved: reserved.contains(&utxo.outpoint),
+            })
+            .collect();
+
+        Ok(utxos)
+    }
+
+    pub fn handle_freeze_utxos(&mut self, msg: FreezeUtxos) -> Result<()> {
+        let unspent = self
+            .wallet
+            .list_unspent()?
+            .into_iter()
+            .map(|utxo| utxo.outpoint)
+            .collect::<HashSet<_>>();
+
+        for outpoint in msg.outpoints.iter() {
+            ensure!(
+                unspent.contains(outpoint),
+                "Cannot freeze {outpoint}: not an unspent output of the wallet"
+            );
+        }
+
+        tracing::info!(outpoints = ?msg.outpoints, "Freezing UTXOs");
+        self.frozen_utxos.extend(msg.outpoints);
+
+        Ok(())
+    }
+
+    pub fn handle_unfreeze_utxos(&mut self, msg: UnfreezeUtxos) {
+        tracing::info!(outpoints = ?msg.outpoints, "Unfreezing UTXOs");
+
+        for outpoint in msg.outpoints.iter() {
+            self.frozen_utxos.remove(outpoint);
+        }
+    }
+
+    pub fn handle_restore_frozen_utxos(&mut self, msg: RestoreFrozenUtxos) {
+        tracing::debug!(outpoints = ?msg.outpoints, "Restoring frozen UTXOs");
+        self.frozen_utxos.extend(msg.outpoints);
+    }
 }
 
 #[async_trait]
@@ -361,10 +657,247 @@ pub struct Sign {
     pub psbt: PartiallySignedTransaction,
 }
 
+/// Withdraw from the wallet to an external address.
+///
+/// Frozen UTXOs and UTXOs reserved for a lock transaction are never
+/// withdrawn.
 pub struct Withdraw {
+    /// The amount to withdraw. If `None`, all spendable UTXOs are
+    /// withdrawn.
     pub amount: Option<Amount>,
     pub fee: Option<FeeRate>,
     pub address: Address,
+    /// Only spend these UTXOs. If empty, UTXOs are selected
+    /// automatically.
+    pub utxos: Vec<OutPoint>,
+    /// Never spend these UTXOs.
+    pub exclude: Vec<OutPoint>,
+}
+
+/// Withdraw from the wallet to several external addresses in a single
+/// transaction.
+///
+/// Coin control works as for [`Withdraw`]. The change, if any, is sent
+/// back to the wallet.
+pub struct BatchWithdraw {
+    /// The addresses to pay to and the amount each of them receives.
+    pub recipients: Vec<(Address, Amount)>,
+    pub fee: Option<FeeRate>,
+    /// Only spend these UTXOs. If empty, UTXOs are selected
+    /// automatically.
+    pub utxos: Vec<OutPoint>,
+    /// Never spend these UTXOs.
+    pub exclude: Vec<OutPoint>,
+}
+
+/// Bump the fee of an unconfirmed transaction by spending one of its
+/// outputs belonging to the wallet (child-pays-for-parent).
+pub struct CpfpTransaction {
+    pub parent: Transaction,
+    /// Absolute fee already paid by the parent transaction.
+    pub parent_fee: Amount,
+    /// Fee rate which the package of parent and child should reach.
+    pub target_fee_rate: FeeRate,
+}
+
+/// Message to list the unspent outputs of the wallet.
+#[derive(Clone, Copy)]
+pub struct ListUtxos;
+
+/// Message to query the height of the tip of the chain, as seen by the
+/// Electrum server of the wallet.
+#[derive(Clone, Copy)]
+pub struct GetBlockHeight;
+
+/// Exclude the given UTXOs from being used to fund lock transactions or
+/// withdrawals.
+///
+/// Frozen UTXOs stay frozen until they are explicitly unfrozen. The
+/// wallet only keeps them in memory, persisting them is up to the
+/// sender.
+pub struct FreezeUtxos {
+    pub outpoints: Vec<OutPoint>,
+}
+
+/// Freeze the given UTXOs which were frozen before the wallet was
+/// started.
+///
+/// Unlike [`FreezeUtxos`], they are not checked to be unspent outputs
+/// of the wallet, which they might not be anymore.
+pub struct RestoreFrozenUtxos {
+    pub outpoints: Vec<OutPoint>,
+}
+
+/// Allow the given UTXOs to be used to fund lock transactions and
+/// withdrawals again.
+pub struct UnfreezeUtxos {
+    pub outpoints: Vec<OutPoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub amount: Amount,
+    pub frozen: bool,
+    /// Whether the UTXO is reserved for a lock transaction which is
+    /// being set up.
+    pub reserved: bool,
+}
+
+impl Utxo {
+    /// Whether the UTXO can be spent into a new lock transaction or
+    /// withdrawal.
+    pub fn is_spendable(&self) -> bool {
+        !self.frozen && !self.reserved
+    }
+}
+
+/// What to do when asked to withdraw to an address which the wallet has
+/// already used.
+///
+/// Reusing an address links the transactions paying to it, which makes it
+/// easier to follow the funds of the wallet on-chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressReusePolicy {
+    /// Withdraw anyway, but log a warning.
+    #[default]
+    Warn,
+    /// Refuse to withdraw.
+    Block,
+}
+
+impl FromStr for AddressReusePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "warn" => Ok(AddressReusePolicy::Warn),
+            "block" => Ok(AddressReusePolicy::Block),
+            other => Err(anyhow!(
+                "Unknown address reuse policy '{other}', expected 'warn' or 'block'"
+            )),
+        }
+    }
+}
+
+/// The outputs of a withdrawal.
+enum Outputs {
+    /// Pay each address the given amount.
+    Recipients(Vec<(Address, Amount)>),
+    /// Send everything that is spent to the address.
+    Drain(Address),
+}
+
+impl Outputs {
+    /// Check that the outputs can be paid to from a wallet on `network`.
+    fn validate(&self, network: Network) -> Result<()> {
+        match self {
+            Outputs::Recipients(recipients) => {
+                ensure!(!recipients.is_empty(), "Cannot withdraw without recipients");
+
+                for (address, amount) in recipients {
+                    validate_address_network(address, network)?;
+                    ensure!(
+                        *amount > Amount::ZERO,
+                        "Cannot withdraw nothing to {address}"
+                    );
+                }
+            }
+            Outputs::Drain(address) => validate_address_network(address, network)?,
+        }
+
+        Ok(())
+    }
+
+    fn addresses(&self) -> Vec<&Address> {
+        match self {
+            Outputs::Recipients(recipients) => {
+                recipients.iter().map(|(address, _)| address).collect()
+            }
+            Outputs::Drain(address) => vec![address],
+        }
+    }
+}
+
+/// Check the addresses of a withdrawal against the output scripts the
+/// wallet has already used, according to `policy`.
+fn check_address_reuse(
+    outputs: &Outputs,
+    used_scripts: &HashSet<Script>,
+    policy: AddressReusePolicy,
+) -> Result<()> {
+    for address in outputs.addresses() {
+        if !used_scripts.contains(&address.script_pubkey()) {
+            continue;
+        }
+
+        match policy {
+            AddressReusePolicy::Warn => {
+                tracing::warn!(%address, "Withdrawing to an address which was used before");
+            }
+            AddressReusePolicy::Block => {
+                bail!("Refusing to withdraw to {address}: the address was used before")
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_address_network(address: &Address, network: Network) -> Result<()> {
+    ensure!(
+        is_address_for_network(address, network),
+        "Address {address} has invalid network. It was {} but the wallet is connected to {network}",
+        address.network
+    );
+
+    Ok(())
+}
+
+/// Whether `address` can be paid to from a wallet on `network`.
+///
+/// Signet shares all address prefixes with testnet and regtest only has its own prefix for segwit
+/// addresses, so their addresses may be parsed as testnet addresses.
+fn is_address_for_network(address: &Address, network: Network) -> bool {
+    let is_segwit = matches!(address.payload, Payload::WitnessProgram { .. });
+
+    match (address.network, network) {
+        (address_network, _) if address_network == network => true,
+        (Network::Testnet, Network::Signet) => true,
+        (Network::Testnet, Network::Regtest) => !is_segwit,
+        _ => false,
+    }
+}
+
+/// Check that the UTXOs selected for a withdrawal can be spent.
+fn validate_coin_control(
+    utxos: &[OutPoint],
+    exclude: &[OutPoint],
+    unspent: &HashSet<OutPoint>,
+    frozen: &HashSet<OutPoint>,
+    reserved: &HashSet<OutPoint>,
+) -> Result<()> {
+    for outpoint in utxos {
+        ensure!(
+            unspent.contains(outpoint),
+            "Cannot spend {outpoint}: not an unspent output of the wallet"
+        );
+        ensure!(
+            !frozen.contains(outpoint),
+            "Cannot spend {outpoint}: UTXO is frozen"
+        );
+        ensure!(
+            !reserved.contains(outpoint),
+            "Cannot spend {outpoint}: UTXO is reserved for a lock transaction"
+        );
+        ensure!(
+            !exclude.contains(outpoint),
+            "Cannot spend {outpoint}: UTXO is both selected and excluded"
+        );
+    }
+
+    Ok(())
 }
 
 /// Bitcoin error codes: <https://github.com/bitcoin/bitcoin/blob/97d3500601c1d28642347d014a6de1e38f53ae4e/src/rpc/protocol.h#L23>
@@ -394,6 +927,7 @@ trait BuildLockTx {
         &mut self,
         amount: Amount,
         used_utxos: &mut LockedUtxos,
+        frozen_utxos: &HashSet<OutPoint>,
         fee_rate: FeeRate,
     ) -> Result<PartiallySignedTransaction>;
 }
@@ -406,14 +940,21 @@ where
         &mut self,
         amount: Amount,
         used_utxos: &mut LockedUtxos,
+        frozen_utxos: &HashSet<OutPoint>,
         fee_rate: FeeRate,
     ) -> Result<PartiallySignedTransaction> {
         let mut builder = self.build_tx();
 
+        let unspendable = used_utxos
+            .list()
+            .into_iter()
+            .chain(frozen_utxos.iter().copied())
+            .collect();
+
         builder
             .ordering(TxOrdering::Bip69Lexicographic) // TODO: I think this is pointless but we did this in maia.
             .fee_rate(fee_rate)
-            .unspendable(used_utxos.list())
+            .unspendable(unspendable)
             .add_2of2_multisig_recipient(amount);
 
         let (psbt, _) = builder.finish()?;
@@ -484,6 +1025,38 @@ fn seed_and_rpc_on_same_network(rpc: &electrum_client::Client, network: Network)
     Ok(network_hash == rpc_hash)
 }
 
+/// The fee a child spending an output of a parent of `parent_vbytes`, which pays `parent_fee`,
+/// has to pay for the package of both to reach `target_fee_rate`.
+///
+/// `None` if the parent pays almost enough by itself, i.e. if the child would not even pay the
+/// minimum relay fee for its own size.
+fn cpfp_child_fee(
+    parent_vbytes: u64,
+    parent_fee: Amount,
+    target_fee_rate: FeeRate,
+) -> Option<Amount> {
+    let package_vbytes = parent_vbytes + CPFP_CHILD_VBYTES;
+    let package_fee = (target_fee_rate.as_sat_vb() * package_vbytes as f32).ceil() as u64;
+    let child_fee = package_fee.saturating_sub(parent_fee.as_sat());
+
+    let min_child_fee =
+        (FeeRate::default_min_relay_fee().as_sat_vb() * CPFP_CHILD_VBYTES as f32).ceil() as u64;
+
+    (child_fee > min_child_fee).then(|| Amount::from_sat(child_fee))
+}
+
+/// The fee a child replacing a previous child, which pays `previous_fee`, has to pay to reach
+/// `child_fee`.
+///
+/// A replacement has to pay at least the minimum relay fee for its own size on top of what it
+/// replaces (BIP125).
+fn cpfp_replacement_fee(child_fee: Amount, previous_fee: Amount) -> Amount {
+    let min_increment =
+        (FeeRate::default_min_relay_fee().as_sat_vb() * CPFP_CHILD_VBYTES as f32).ceil() as u64;
+
+    child_fee.max(previous_fee + Amount::from_sat(min_increment))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -492,6 +1065,7 @@ mod tests {
     use itertools::Itertools;
     use rand::thread_rng;
     use std::collections::HashSet;
+    use std::str::FromStr;
     use tokio_extras::Tasks;
 
     impl Actor<(), bdk::database::MemoryDatabase> {
@@ -511,7 +1085,11 @@ mod tests {
                     inner: HashSet::default(),
                     time_to_lock,
                 },
+                frozen_utxos: HashSet::default(),
+                address_reuse: AddressReusePolicy::default(),
                 blockchain_client: (),
+                active_electrum: electrum::ActiveServer::fixed(String::new()),
+                electrum_url: String::new(),
             })
         }
     }
@@ -538,6 +1116,7 @@ mod tests {
             .build_lock_tx(
                 Amount::from_sat(2500),
                 &mut used_utxos,
+                &HashSet::default(),
                 FeeRate::default_min_relay_fee(),
             )
             .unwrap();
@@ -545,6 +1124,7 @@ mod tests {
             .build_lock_tx(
                 Amount::from_sat(2500),
                 &mut used_utxos,
+                &HashSet::default(),
                 FeeRate::default_min_relay_fee(),
             )
             .unwrap();
@@ -666,4 +1246,191 @@ mod tests {
             .unwrap()
             .expect("single UTXO to be available after unlocking it");
     }
+
+    #[tokio::test]
+    async fn frozen_utxo_is_not_used_for_lock_transaction() {
+        let mut tasks = Tasks::default();
+
+        let actor = Actor::new_offline(Amount::ONE_BTC, 1, Duration::from_secs(120))
+            .unwrap()
+            .create(None)
+            .spawn(&mut tasks);
+
+        let (_, identity_pk) = keypair::new(&mut thread_rng());
+
+        let utxos = actor.send(ListUtxos).await.unwrap().unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert!(!utxos[0].frozen);
+
+        actor
+            .send(FreezeUtxos {
+                outpoints: vec![utxos[0].outpoint],
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let utxos = actor.send(ListUtxos).await.unwrap().unwrap();
+        assert!(utxos[0].frozen);
+
+        actor
+            .send(BuildPartyParams {
+                amount: Amount::from_btc(0.2).unwrap(),
+                identity_pk,
+                fee_rate: TxFeeRate::default(),
+            })
+            .await
+            .unwrap()
+            .expect_err("single UTXO to be frozen");
+
+        actor
+            .send(UnfreezeUtxos {
+                outpoints: vec![utxos[0].outpoint],
+            })
+            .await
+            .unwrap();
+
+        actor
+            .send(BuildPartyParams {
+                amount: Amount::from_btc(0.2).unwrap(),
+                identity_pk,
+                fee_rate: TxFeeRate::default(),
+            })
+            .await
+            .unwrap()
+            .expect("single UTXO to be available after unfreezing it");
+    }
+
+    #[test]
+    fn only_available_utxos_can_be_selected_for_withdrawal() {
+        let outpoint = |vout| OutPoint {
+            txid: OutPoint::null().txid,
+            vout,
+        };
+        let unspent = HashSet::from([outpoint(0), outpoint(1), outpoint(2), outpoint(3)]);
+        let frozen = HashSet::from([outpoint(1)]);
+        let reserved = HashSet::from([outpoint(2)]);
+        let exclude = [outpoint(3)];
+
+        let validate =
+            |selected| validate_coin_control(&[selected], &exclude, &unspent, &frozen, &reserved);
+
+        validate(outpoint(0)).expect("UTXO to be available");
+        validate(outpoint(1)).expect_err("frozen UTXO to be rejected");
+        validate(outpoint(2)).expect_err("reserved UTXO to be rejected");
+        validate(outpoint(3)).expect_err("excluded UTXO to be rejected");
+        validate(outpoint(4)).expect_err("unknown UTXO to be rejected");
+    }
+
+    #[test]
+    fn batch_withdrawal_only_pays_to_addresses_on_wallet_network() {
+        let mainnet = Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").unwrap();
+        let testnet = Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap();
+        let amount = Amount::from_sat(10_000);
+
+        Outputs::Recipients(vec![(mainnet.clone(), amount), (mainnet.clone(), amount)])
+            .validate(Network::Bitcoin)
+            .expect("mainnet addresses to be accepted");
+        Outputs::Recipients(vec![(mainnet.clone(), amount), (testnet.clone(), amount)])
+            .validate(Network::Bitcoin)
+            .expect_err("testnet address to be rejected");
+        Outputs::Recipients(vec![(mainnet, Amount::ZERO)])
+            .validate(Network::Bitcoin)
+            .expect_err("empty output to be rejected");
+        Outputs::Recipients(vec![])
+            .validate(Network::Bitcoin)
+            .expect_err("withdrawal without recipients to be rejected");
+        Outputs::Drain(testnet)
+            .validate(Network::Testnet)
+            .expect("testnet address to be accepted");
+    }
+
+    #[test]
+    fn signet_and_regtest_wallets_accept_addresses_with_testnet_prefixes() {
+        let segwit = Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap();
+        let legacy = Address::from_str("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn").unwrap();
+        let regtest = Address::from_str("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080").unwrap();
+
+        assert!(is_address_for_network(&segwit, Network::Signet));
+        assert!(is_address_for_network(&legacy, Network::Signet));
+        assert!(!is_address_for_network(&regtest, Network::Signet));
+
+        assert!(is_address_for_network(&regtest, Network::Regtest));
+        assert!(is_address_for_network(&legacy, Network::Regtest));
+        assert!(!is_address_for_network(&segwit, Network::Regtest));
+
+        assert!(!is_address_for_network(&regtest, Network::Testnet));
+    }
+
+    #[test]
+    fn withdrawal_to_used_address_is_only_blocked_by_policy() {
+        let used = Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").unwrap();
+        let fresh = Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        let used_scripts = HashSet::from([used.script_pubkey()]);
+        let amount = Amount::from_sat(10_000);
+
+        check_address_reuse(
+            &Outputs::Recipients(vec![(fresh.clone(), amount)]),
+            &used_scripts,
+            AddressReusePolicy::Block,
+        )
+        .expect("fresh address to be accepted");
+        check_address_reuse(
+            &Outputs::Drain(used.clone()),
+            &used_scripts,
+            AddressReusePolicy::Warn,
+        )
+        .expect("used address to be accepted with a warning");
+        check_address_reuse(
+            &Outputs::Recipients(vec![(fresh, amount), (used, amount)]),
+            &used_scripts,
+            AddressReusePolicy::Block,
+        )
+        .expect_err("used address to be rejected");
+    }
+
+    #[test]
+    fn cpfp_child_pays_for_the_whole_package() {
+        let child_fee = cpfp_child_fee(200, Amount::from_sat(200), FeeRate::from_sat_per_vb(10.0));
+
+        assert_eq!(
+            child_fee,
+            Some(Amount::from_sat(10 * (200 + CPFP_CHILD_VBYTES) - 200))
+        );
+    }
+
+    #[test]
+    fn cpfp_child_fee_is_rounded_up() {
+        // (101 + 110) vbytes * 1.5 sat/vbyte = 316.5 sat
+        let child_fee = cpfp_child_fee(101, Amount::ZERO, FeeRate::from_sat_per_vb(1.5));
+
+        assert_eq!(child_fee, Some(Amount::from_sat(317)));
+    }
+
+    #[test]
+    fn no_cpfp_child_if_it_would_pay_less_than_the_minimum_relay_fee() {
+        let target_fee_rate = FeeRate::from_sat_per_vb(10.0);
+        let package_fee = 10 * (200 + CPFP_CHILD_VBYTES);
+
+        let fully_paid = cpfp_child_fee(200, Amount::from_sat(package_fee), target_fee_rate);
+        let min_relay_fee_left = cpfp_child_fee(
+            200,
+            Amount::from_sat(package_fee - CPFP_CHILD_VBYTES),
+            target_fee_rate,
+        );
+
+        assert_eq!(fully_paid, None);
+        assert_eq!(min_relay_fee_left, None);
+    }
+
+    #[test]
+    fn cpfp_replacement_pays_at_least_the_minimum_relay_fee_on_top() {
+        let previous_fee = Amount::from_sat(1_000);
+
+        let higher = cpfp_replacement_fee(Amount::from_sat(2_000), previous_fee);
+        let barely_higher = cpfp_replacement_fee(Amount::from_sat(1_001), previous_fee);
+
+        assert_eq!(higher, Amount::from_sat(2_000));
+        assert_eq!(barely_higher, Amount::from_sat(1_000 + CPFP_CHILD_VBYTES));
+    }
 }
@@ -0,0 +1,231 @@
+//! Detection of unresponsive actors.
+//!
+//! A deadlocked actor is silent: messages pile up in its mailbox and
+//! every caller waits forever. The watchdog periodically sends an
+//! [`xtras::Probe`] to each critical actor. If an actor fails to
+//! respond in time, the watchdog logs a diagnostic bundle with the
+//! mailbox stats of all watched actors.
+//!
+//! Unresponsive actors are not restarted: none of the watched actors
+//! runs under an [`xtras::supervisor::Supervisor`]. The wallet is
+//! spawned once by the binary and holds the lock on its database, the
+//! endpoint owns all connections, and the CFD and monitor actors are
+//! built by one-shot constructors from state which is not rebuilt on a
+//! restart.
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use xtra::Address;
+use xtra::Handler;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often all watched actors are probed.
+const PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a watched actor has to respond to a probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An actor observed by the watchdog.
+pub struct Watched {
+    name: &'static str,
+    probe: Box<dyn Fn() -> BoxFuture<'static, Result<(), xtra::Error>> + Send + Sync>,
+    mailbox: Box<dyn Fn() -> MailboxStats + Send + Sync>,
+}
+
+impl Watched {
+    pub fn new<A>(name: &'static str, address: Address<A>) -> Self
+    where
+        A: Handler<xtras::Probe, Return = ()>,
+    {
+        let probe = {
+            let address = address.clone();
+            move || {
+                let address = address.clone();
+                async move { address.send(xtras::Probe).await }.boxed()
+            }
+        };
+        let mailbox = move || MailboxStats {
+            len: address.len(),
+            capacity: address.capacity(),
+            connected: address.is_connected(),
+        };
+
+        Self {
+            name,
+            probe: Box::new(probe),
+            mailbox: Box::new(mailbox),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MailboxStats {
+    len: usize,
+    capacity: Option<usize>,
+    connected: bool,
+}
+
+impl fmt::Display for MailboxStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.capacity {
+            Some(capacity) => write!(f, "{}/{capacity} queued", self.len)?,
+            None => write!(f, "{} queued", self.len)?,
+        }
+
+        if !self.connected {
+            write!(f, ", disconnected")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CheckActors;
+
+pub struct Actor {
+    watched: Vec<Watched>,
+    missed_probes: HashMap<&'static str, u32>,
+}
+
+impl Actor {
+    pub fn new(watched: Vec<Watched>) -> Self {
+        Self {
+            watched,
+            missed_probes: HashMap::default(),
+        }
+    }
+
+    fn report_unresponsive(&mut self, name: &'static str) {
+        let missed = self.missed_probes.entry(name).or_default();
+        *missed += 1;
+
+        let mailboxes = self
+            .watched
+            .iter()
+            .map(|watched| format!("{}: {}", watched.name, (watched.mailbox)()))
+            .join(", ");
+
+        tracing::error!(
+            actor = name,
+            consecutive_missed_probes = *missed,
+            %mailboxes,
+            "Actor did not respond to probe within {PROBE_TIMEOUT:?}, it might be deadlocked"
+        );
+
+        MISSED_PROBES_COUNTER
+            .with(&HashMap::from([(ACTOR_LABEL, name)]))
+            .inc();
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _: CheckActors) {
+        let results = futures::future::join_all(self.watched.iter().map(|watched| async move {
+            let result = tokio_extras::time::timeout(
+                PROBE_TIMEOUT,
+                (watched.probe)(),
+                tokio_extras::time::already_instrumented,
+            )
+            .await;
+
+            (watched.name, result)
+        }))
+        .await;
+
+        for (name, result) in results {
+            match result {
+                Ok(Ok(())) => {
+                    if let Some(missed) = self.missed_probes.remove(name) {
+                        tracing::info!(
+                            actor = name,
+                            "Actor is responsive again after {missed} missed probes"
+                        );
+                    }
+                }
+                Ok(Err(e)) => {
+                    tracing::error!(actor = name, "Watched actor is unreachable: {e:#}");
+                }
+                Err(_) => self.report_unresponsive(name),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(PROBE_INTERVAL, || CheckActors, xtras::IncludeSpan::Never),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+const ACTOR_LABEL: &str = "actor";
+
+static MISSED_PROBES_COUNTER: conquer_once::Lazy<prometheus::IntCounterVec> =
+    conquer_once::Lazy::new(|| {
+        prometheus::register_int_counter_vec!(
+            "watchdog_missed_probes_total",
+            "The number of probes which were not answered in time.",
+            &[ACTOR_LABEL]
+        )
+        .unwrap()
+    });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_extras::Tasks;
+    use xtra::Actor as _;
+
+    struct Dummy;
+
+    #[async_trait]
+    impl xtra::Actor for Dummy {
+        type Stop = ();
+
+        async fn stopped(self) -> Self::Stop {}
+    }
+
+    #[xtra_productivity]
+    impl Dummy {
+        fn handle(&mut self, _: xtras::Probe) {}
+    }
+
+    #[tokio::test]
+    async fn probing_responsive_actor_completes() {
+        let mut tasks = Tasks::default();
+        let dummy = Dummy.create(None).spawn(&mut tasks);
+        let watchdog = Actor::new(vec![Watched::new("dummy", dummy)])
+            .create(None)
+            .spawn(&mut tasks);
+
+        watchdog.send(CheckActors).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn missed_probes_are_counted_per_actor() {
+        let mut tasks = Tasks::default();
+        let dummy = Dummy.create(None).spawn(&mut tasks);
+        let mut watchdog = Actor::new(vec![Watched::new("dummy", dummy)]);
+
+        watchdog.report_unresponsive("dummy");
+        watchdog.report_unresponsive("dummy");
+
+        assert_eq!(watchdog.missed_probes.get("dummy"), Some(&2));
+    }
+}
@@ -0,0 +1,54 @@
+//! Watchtowers, which punish revoked commit transactions while we are
+//! offline.
+//!
+//! If the counterparty publishes a revoked commit transaction, we have
+//! to publish the punish transaction before they can publish a CET
+//! spending it. To not miss this while offline, the [`client`] uploads a
+//! [`model::punish::JusticeKit`] for every revoked commit transaction to
+//! a [`tower`], e.g. the standalone `watchtower` binary. The maker runs a
+//! tower as well, which a taker must not use to watch that very maker.
+//!
+//! A kit is uploaded as an encrypted [`protocol::Blob`], keyed by the
+//! txid of the revoked commit transaction and stored under the script
+//! pubkey of its output. The watchtower learns neither which CFD a blob
+//! belongs to nor what it contains until the revoked commit transaction
+//! is published.
+
+use anyhow::Result;
+use asynchronous_codec::Framed;
+use asynchronous_codec::JsonCodec;
+use xtra_libp2p::recorder::ensure_replay_finished;
+use xtra_libp2p::recorder::next_replayed;
+use xtra_libp2p::recorder::ReplayedSubstream;
+
+pub mod client;
+pub mod protocol;
+pub mod tower;
+
+pub const PROTOCOL: &str = "/itchysats/watchtower/1.0.0";
+
+/// Run the client's handling of a recorded substream on the frames the
+/// tower sent.
+pub async fn replay_dialer(stream: ReplayedSubstream) -> Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        JsonCodec::<protocol::ClientMessage, protocol::TowerMessage>::new(),
+    );
+
+    next_replayed(&mut framed).await?;
+
+    ensure_replay_finished(&mut framed).await
+}
+
+/// Run the tower's handling of a recorded substream on the frames the
+/// client sent.
+pub async fn replay_listener(stream: ReplayedSubstream) -> Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        JsonCodec::<protocol::TowerMessage, protocol::ClientMessage>::new(),
+    );
+
+    next_replayed(&mut framed).await?;
+
+    ensure_replay_finished(&mut framed).await
+}
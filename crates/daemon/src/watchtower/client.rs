@@ -0,0 +1,199 @@
+use crate::watchtower::protocol::Blob;
+use crate::watchtower::protocol::ClientMessage;
+use crate::watchtower::protocol::TowerMessage;
+use crate::watchtower::PROTOCOL;
+use anyhow::bail;
+use anyhow::Context as _;
+use anyhow::Result;
+use async_trait::async_trait;
+use asynchronous_codec::Framed;
+use asynchronous_codec::JsonCodec;
+use futures::SinkExt;
+use futures::StreamExt;
+use libp2p_core::Multiaddr;
+use libp2p_core::PeerId;
+use model::punish::JusticeKit;
+use std::time::Duration;
+use tokio_extras::FutureExt;
+use xtra::Address;
+use xtra::Context;
+use xtra_libp2p::multiaddress_ext::MultiaddrExt;
+use xtra_libp2p::Connect;
+use xtra_libp2p::Endpoint;
+use xtra_libp2p::GetConnectionStats;
+use xtra_libp2p::OpenSubstream;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often we upload the justice kits of new revoked commit
+/// transactions.
+const UPLOAD_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long we wait for the watchtower to confirm that it stored our
+/// blobs.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Uploads the justice kits of the revoked commit transactions of our
+/// open CFDs to a watchtower.
+///
+/// Which kits were uploaded to which watchtower is recorded in the
+/// database, so that they are not uploaded again after a restart.
+pub struct Actor {
+    endpoint: Address<Endpoint>,
+    db: sqlite_db::Connection,
+    tower_multiaddr: Multiaddr,
+    tower_peer_id: PeerId,
+}
+
+impl Actor {
+    pub fn new(
+        endpoint: Address<Endpoint>,
+        db: sqlite_db::Connection,
+        tower_multiaddr: Multiaddr,
+    ) -> Result<Self> {
+        let tower_peer_id = tower_multiaddr
+            .clone()
+            .extract_peer_id()
+            .context("Watchtower address does not contain a peer id")?;
+
+        Ok(Self {
+            endpoint,
+            db,
+            tower_multiaddr,
+            tower_peer_id,
+        })
+    }
+
+    async fn justice_kits(&self) -> Result<Vec<JusticeKit>> {
+        let mut kits = Vec::new();
+
+        for order_id in self.db.load_open_cfd_ids().await? {
+            let cfd = match self.db.load_open_cfd::<model::Cfd>(order_id, ()).await {
+                Ok(cfd) => cfd,
+                Err(e) => {
+                    tracing::warn!(%order_id, "Failed to load CFD: {e:#}");
+                    continue;
+                }
+            };
+
+            match cfd.justice_kits() {
+                Ok(cfd_kits) => kits.extend(cfd_kits),
+                Err(e) => tracing::warn!(%order_id, "Failed to build justice kits: {e:#}"),
+            }
+        }
+
+        let uploaded = self
+            .db
+            .load_watchtower_uploads(self.tower_peer_id.into())
+            .await?;
+        kits.retain(|kit| !uploaded.contains(&kit.revoked_commit_txid()));
+
+        Ok(kits)
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        let stats = self
+            .endpoint
+            .send(GetConnectionStats)
+            .await
+            .context("Endpoint actor is disconnected")?;
+
+        Ok(stats.connected_peers.contains(&self.tower_peer_id))
+    }
+
+    async fn upload(&mut self) -> Result<()> {
+        let kits = self.justice_kits().await?;
+        if kits.is_empty() {
+            return Ok(());
+        }
+
+        if !self.is_connected().await? {
+            let result = self
+                .endpoint
+                .send(Connect(self.tower_multiaddr.clone()))
+                .await
+                .context("Endpoint actor is disconnected")?;
+
+            // Failing to connect is expected while a previous attempt is
+            // still in flight
+            if let Err(e) = result {
+                tracing::debug!("Failed to request connection to watchtower: {e:#}");
+            }
+
+            bail!("Not connected to watchtower, uploading on the next attempt");
+        }
+
+        let blobs = kits.iter().map(Blob::seal).collect::<Result<Vec<_>>>()?;
+
+        let stream = self
+            .endpoint
+            .send(OpenSubstream::single_protocol(self.tower_peer_id, PROTOCOL))
+            .await
+            .context("Endpoint is disconnected")?
+            .context("No connection to peer")?
+            .await
+            .context("Failed to open substream")?;
+        let mut framed = Framed::new(stream, JsonCodec::<ClientMessage, TowerMessage>::new());
+
+        framed.send(ClientMessage::StoreBlobs(blobs)).await?;
+
+        let response = framed
+            .next()
+            .timeout(TIMEOUT, || tracing::debug_span!("Receive response"))
+            .await
+            .context("Waiting for response timed out")?
+            .context("Stream terminated")??;
+
+        match response {
+            TowerMessage::Stored => {
+                tracing::info!(
+                    peer_id = %self.tower_peer_id,
+                    n_blobs = %kits.len(),
+                    "Uploaded blobs to watchtower"
+                );
+
+                let txids = kits
+                    .iter()
+                    .map(JusticeKit::revoked_commit_txid)
+                    .collect::<Vec<_>>();
+                self.db
+                    .save_watchtower_uploads(self.tower_peer_id.into(), &txids)
+                    .await?;
+            }
+            TowerMessage::Rejected { reason } => {
+                bail!("Watchtower rejected blobs: {reason}")
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(UPLOAD_INTERVAL, || Upload, xtras::IncludeSpan::Never),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// Message sent to ourselves at an interval to upload the justice kits
+/// of new revoked commit transactions.
+#[derive(Clone, Copy)]
+struct Upload;
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _: Upload) {
+        if let Err(e) = self.upload().await {
+            tracing::warn!("Failed to upload justice kits to watchtower: {e:#}");
+        }
+    }
+}
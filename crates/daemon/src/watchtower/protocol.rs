@@ -0,0 +1,101 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::hashes::hex::FromHex;
+use bdk::bitcoin::hashes::hex::ToHex;
+use bdk::bitcoin::Script;
+use bdk::bitcoin::Txid;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::KeyInit;
+use chacha20poly1305::Nonce;
+use model::punish::JusticeKit;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+/// Every key encrypts a single justice kit, so a fixed nonce is safe.
+const NONCE: [u8; 12] = [0u8; 12];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum ClientMessage {
+    StoreBlobs(Vec<Blob>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum TowerMessage {
+    Stored,
+    Rejected { reason: String },
+}
+
+/// A justice kit, encrypted with a key derived from the txid of the
+/// revoked commit transaction it punishes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blob {
+    /// The script pubkey of the output of the revoked commit
+    /// transaction, which tells the watchtower where to look for it
+    pub hint: Script,
+    /// The hex-encoded encrypted justice kit
+    pub ciphertext: String,
+}
+
+impl Blob {
+    pub fn seal(kit: &JusticeKit) -> Result<Self> {
+        let plaintext = serde_json::to_vec(kit).context("Failed to serialize justice kit")?;
+        let ciphertext = cipher(kit.revoked_commit_txid())
+            .encrypt(Nonce::from_slice(&NONCE), plaintext.as_slice())
+            .map_err(|_| anyhow!("Failed to encrypt justice kit"))?;
+
+        Ok(Self {
+            hint: kit.revoked_commit_script_pubkey(),
+            ciphertext: ciphertext.to_hex(),
+        })
+    }
+
+    /// Decrypt the justice kit, if `txid` is the txid of the revoked
+    /// commit transaction it punishes.
+    pub fn open(&self, txid: Txid) -> Result<JusticeKit> {
+        let ciphertext = Vec::<u8>::from_hex(&self.ciphertext).context("Invalid ciphertext")?;
+        let plaintext = cipher(txid)
+            .decrypt(Nonce::from_slice(&NONCE), ciphertext.as_slice())
+            .map_err(|_| anyhow!("Blob does not punish transaction {txid}"))?;
+
+        serde_json::from_slice(&plaintext).context("Failed to deserialize justice kit")
+    }
+}
+
+fn cipher(revoked_commit_txid: Txid) -> ChaCha20Poly1305 {
+    let key = Sha256::digest(&revoked_commit_txid[..]);
+
+    ChaCha20Poly1305::new(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blob_is_only_opened_with_txid_of_revoked_commit_tx() {
+        let txid = "c2bc1ad9e6ac7e1d2a0bd9e7b2fe4cd74c9d1e84a7d1dd4a2c6a8e6a3f1b2c3d"
+            .parse::<Txid>()
+            .unwrap();
+        let other = "07a0b0b6c0ba0e9b2bfe5e4d10a5f5ff0e3dc9c3b8d4e0a1a5b5e7e60c4dd0a2"
+            .parse::<Txid>()
+            .unwrap();
+
+        let ciphertext = cipher(txid)
+            .encrypt(Nonce::from_slice(&NONCE), b"kit".as_slice())
+            .unwrap();
+
+        assert_eq!(
+            cipher(txid)
+                .decrypt(Nonce::from_slice(&NONCE), ciphertext.as_slice())
+                .unwrap(),
+            b"kit"
+        );
+        assert!(cipher(other)
+            .decrypt(Nonce::from_slice(&NONCE), ciphertext.as_slice())
+            .is_err());
+    }
+}
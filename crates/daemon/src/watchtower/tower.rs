@@ -0,0 +1,432 @@
+use crate::electrum;
+use crate::watchtower::protocol::Blob;
+use crate::watchtower::protocol::ClientMessage;
+use crate::watchtower::protocol::TowerMessage;
+use anyhow::ensure;
+use anyhow::Context as _;
+use anyhow::Result;
+use async_trait::async_trait;
+use asynchronous_codec::Framed;
+use asynchronous_codec::JsonCodec;
+use bdk::bitcoin::Txid;
+use bdk::electrum_client;
+use bdk::electrum_client::ElectrumApi;
+use futures::SinkExt;
+use futures::StreamExt;
+use model::libp2p::PeerId;
+use model::punish::JusticeKit;
+use model::Timestamp;
+use model::CET_TIMELOCK;
+use sqlite_db::watchtower::WatchtowerBlob;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio_extras::spawn_fallible;
+use tokio_extras::FutureExt;
+use xtra::Context;
+use xtra_libp2p::NewInboundSubstream;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How often the watchtower looks for revoked commit transactions.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long the watchtower waits for the blobs after the substream was
+/// opened.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many blobs the watchtower stores on behalf of a single peer.
+pub const MAX_BLOBS_PER_PEER: usize = 10_000;
+
+/// After how many confirmations of a revoked commit transaction it is
+/// punished regardless of the [`PunishPolicy`].
+///
+/// The counterparty can spend the revoked commit transaction with a CET
+/// after [`CET_TIMELOCK`] confirmations, so this leaves the punish
+/// transaction time to be mined before that.
+const URGENT_CONFIRMATIONS: usize = (CET_TIMELOCK / 2) as usize;
+
+/// When the watchtower publishes the punish transaction of a revoked
+/// commit transaction it saw published.
+///
+/// The punishment can be confirmed via [`ConfirmPunishment`] at any
+/// time, which publishes it right away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PunishPolicy {
+    /// Punish right away.
+    Immediate,
+    /// Announce the punishment and punish after `delay`.
+    Delayed { delay: Duration },
+    /// Wait for the punishment to be confirmed by the operator, but
+    /// punish after `deadline` regardless.
+    Manual { deadline: Duration },
+}
+
+impl Default for PunishPolicy {
+    fn default() -> Self {
+        Self::Immediate
+    }
+}
+
+impl PunishPolicy {
+    /// How long after seeing a revoked commit transaction we punish it
+    /// without confirmation.
+    fn delay(&self) -> Duration {
+        match *self {
+            PunishPolicy::Immediate => Duration::ZERO,
+            PunishPolicy::Delayed { delay } => delay,
+            PunishPolicy::Manual { deadline } => deadline,
+        }
+    }
+
+    /// Whether to punish a revoked commit transaction which we saw
+    /// `waited` ago and which has `confirmations` by now.
+    fn is_due(&self, waited: Duration, is_confirmed: bool, confirmations: usize) -> bool {
+        is_confirmed || waited >= self.delay() || confirmations >= URGENT_CONFIRMATIONS
+    }
+}
+
+/// A revoked commit transaction which was published, but not punished
+/// yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingPunishment {
+    pub revoked_commit_txid: Txid,
+    pub peer_id: PeerId,
+    pub seen_at: Timestamp,
+    /// When it is punished without confirmation.
+    pub punish_at: Timestamp,
+    pub is_confirmed: bool,
+}
+
+/// List the revoked commit transactions which were published, but not
+/// punished yet.
+#[derive(Clone, Copy)]
+pub struct GetPendingPunishments;
+
+/// Punish the revoked commit transaction with `revoked_commit_txid`
+/// right away, regardless of the [`PunishPolicy`].
+#[derive(Clone, Copy)]
+pub struct ConfirmPunishment {
+    pub revoked_commit_txid: Txid,
+}
+
+/// Stores blobs on behalf of its peers and publishes the punish
+/// transaction of a blob once its revoked commit transaction is
+/// published.
+pub struct Actor {
+    db: sqlite_db::Connection,
+    active_electrum: electrum::ActiveServer,
+    policy: PunishPolicy,
+    /// Revoked commit transactions whose punishment was confirmed by
+    /// the operator.
+    confirmed: HashSet<Txid>,
+}
+
+impl Actor {
+    pub fn new(db: sqlite_db::Connection, active_electrum: electrum::ActiveServer) -> Self {
+        Self {
+            db,
+            active_electrum,
+            policy: PunishPolicy::default(),
+            confirmed: HashSet::default(),
+        }
+    }
+
+    pub fn with_punish_policy(self, policy: PunishPolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    async fn check(&mut self) -> Result<()> {
+        let blobs = self.db.load_watchtower_blobs().await?;
+        if blobs.is_empty() {
+            return Ok(());
+        }
+
+        let seen_revoked_commits = self
+            .db
+            .load_seen_revoked_commits()
+            .await?
+            .into_iter()
+            .map(|seen| (seen.hint, seen.seen_at))
+            .collect::<HashMap<_, _>>();
+
+        let client = electrum_client::Client::new(&self.active_electrum.url())
+            .context("Failed to initialize Electrum RPC client")?;
+        let histories = client
+            .batch_script_get_history(blobs.iter().map(|blob| &blob.hint))
+            .context("Failed to get script histories")?;
+        let tip = client
+            .block_headers_subscribe()
+            .context("Failed to get block height")?
+            .height;
+
+        for (blob, history) in blobs.iter().zip(histories) {
+            // The history also contains the transaction spending the
+            // revoked commit transaction, if any, which the blob cannot
+            // be opened with
+            let revoked_commit = history.iter().find_map(|entry| {
+                let kit = open(blob, entry.tx_hash).ok()?;
+                Some((entry.tx_hash, entry.height, kit))
+            });
+            let (txid, height, kit) = match revoked_commit {
+                Some(revoked_commit) => revoked_commit,
+                None => continue,
+            };
+
+            let peer_id = blob.peer_id;
+            if history.len() > 1 {
+                tracing::info!(
+                    %peer_id,
+                    revoked_commit_txid = %txid,
+                    "Revoked commit transaction was already spent"
+                );
+                self.db.delete_watchtower_blob(&blob.hint).await?;
+                continue;
+            }
+
+            let seen_at = match seen_revoked_commits.get(&blob.hint) {
+                Some(seen_at) => *seen_at,
+                None => {
+                    let seen_at = Timestamp::now();
+                    self.db
+                        .save_seen_revoked_commit(&blob.hint, txid, seen_at)
+                        .await?;
+
+                    tracing::warn!(
+                        %peer_id,
+                        revoked_commit_txid = %txid,
+                        punish_in_secs = %self.policy.delay().as_secs(),
+                        policy = ?self.policy,
+                        "Revoked commit transaction published"
+                    );
+
+                    seen_at
+                }
+            };
+
+            let waited = Timestamp::now().seconds().saturating_sub(seen_at.seconds());
+            let waited = Duration::from_secs(waited.try_into().unwrap_or_default());
+            // Unconfirmed transactions have a height of 0 or less
+            let confirmations = match usize::try_from(height) {
+                Ok(height) if height > 0 => (tip + 1).saturating_sub(height),
+                _ => 0,
+            };
+            if !self
+                .policy
+                .is_due(waited, self.confirmed.contains(&txid), confirmations)
+            {
+                continue;
+            }
+
+            match punish(&client, &kit, txid) {
+                Ok(punish_txid) => {
+                    tracing::info!(%peer_id, %punish_txid, "Published punish transaction");
+                    self.db.delete_watchtower_blob(&blob.hint).await?;
+                    self.confirmed.remove(&txid);
+                }
+                Err(e) => {
+                    tracing::error!(
+                        %peer_id,
+                        revoked_commit_txid = %txid,
+                        "Failed to punish: {e:#}"
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(CHECK_INTERVAL, || Check, xtras::IncludeSpan::Never),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// Message sent to ourselves at an interval to look for revoked commit
+/// transactions.
+#[derive(Clone, Copy)]
+struct Check;
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, msg: NewInboundSubstream, ctx: &mut Context<Self>) {
+        let NewInboundSubstream { stream, peer_id } = msg;
+        let db = self.db.clone();
+
+        let store_blobs_fut = async move {
+            let mut framed = Framed::new(stream, JsonCodec::<TowerMessage, ClientMessage>::new());
+
+            let ClientMessage::StoreBlobs(blobs) = framed
+                .next()
+                .timeout(TIMEOUT, || tracing::debug_span!("Receive blobs"))
+                .await
+                .context("Waiting for blobs timed out")?
+                .context("Stream terminated")??;
+
+            let response = match store(&db, peer_id.into(), blobs).await {
+                Ok(()) => TowerMessage::Stored,
+                Err(e) => TowerMessage::Rejected {
+                    reason: format!("{e:#}"),
+                },
+            };
+
+            framed.send(response).await?;
+
+            anyhow::Ok(())
+        };
+
+        let err_handler = move |e| async move {
+            tracing::debug!(%peer_id, "Watchtower protocol failed upon request: {e:#}")
+        };
+
+        let this = ctx.address().expect("we are alive");
+        spawn_fallible(&this, store_blobs_fut, err_handler);
+    }
+
+    async fn handle(&mut self, _: Check) {
+        if let Err(e) = self.check().await {
+            tracing::warn!("Failed to check for revoked commit transactions: {e:#}");
+        }
+    }
+
+    async fn handle(&mut self, _: GetPendingPunishments) -> Result<Vec<PendingPunishment>> {
+        let delay = i64::try_from(self.policy.delay().as_secs())?;
+
+        let pending = self
+            .db
+            .load_seen_revoked_commits()
+            .await?
+            .into_iter()
+            .map(|seen| PendingPunishment {
+                revoked_commit_txid: seen.txid,
+                peer_id: seen.peer_id,
+                seen_at: seen.seen_at,
+                punish_at: Timestamp::new(seen.seen_at.seconds().saturating_add(delay)),
+                is_confirmed: self.confirmed.contains(&seen.txid),
+            })
+            .collect();
+
+        Ok(pending)
+    }
+
+    async fn handle(&mut self, msg: ConfirmPunishment) -> Result<()> {
+        let txid = msg.revoked_commit_txid;
+
+        let is_pending = self
+            .db
+            .load_seen_revoked_commits()
+            .await?
+            .iter()
+            .any(|seen| seen.txid == txid);
+        ensure!(
+            is_pending,
+            "No pending punishment of revoked commit transaction {txid}"
+        );
+
+        tracing::info!(revoked_commit_txid = %txid, "Punishment confirmed");
+        self.confirmed.insert(txid);
+
+        // The punishment is published with the next check otherwise
+        if let Err(e) = self.check().await {
+            tracing::warn!("Failed to check for revoked commit transactions: {e:#}");
+        }
+
+        Ok(())
+    }
+}
+
+async fn store(db: &sqlite_db::Connection, peer_id: PeerId, blobs: Vec<Blob>) -> Result<()> {
+    let stored = db.count_watchtower_blobs(peer_id).await?;
+    ensure!(
+        stored + blobs.len() <= MAX_BLOBS_PER_PEER,
+        "Storing {} more blobs exceeds the limit of {MAX_BLOBS_PER_PEER} blobs",
+        blobs.len()
+    );
+
+    let n_blobs = blobs.len();
+    for Blob { hint, ciphertext } in blobs {
+        db.save_watchtower_blob(&WatchtowerBlob {
+            hint,
+            peer_id,
+            ciphertext,
+        })
+        .await?;
+    }
+
+    tracing::info!(%peer_id, %n_blobs, "Stored blobs");
+
+    Ok(())
+}
+
+fn open(blob: &WatchtowerBlob, txid: Txid) -> Result<JusticeKit> {
+    Blob {
+        hint: blob.hint.clone(),
+        ciphertext: blob.ciphertext.clone(),
+    }
+    .open(txid)
+}
+
+fn punish(client: &electrum_client::Client, kit: &JusticeKit, txid: Txid) -> Result<Txid> {
+    let revoked_commit_tx = client
+        .transaction_get(&txid)
+        .context("Failed to get revoked commit transaction")?;
+    let punish_tx = kit.punish_transaction(&revoked_commit_tx)?;
+
+    client
+        .transaction_broadcast(&punish_tx)
+        .context("Failed to broadcast punish transaction")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn immediate_punishment_is_always_due() {
+        let policy = PunishPolicy::Immediate;
+
+        assert!(policy.is_due(Duration::ZERO, false, 0));
+    }
+
+    #[test]
+    fn delayed_punishment_is_due_after_delay() {
+        let policy = PunishPolicy::Delayed {
+            delay: Duration::from_secs(600),
+        };
+
+        assert!(!policy.is_due(Duration::from_secs(599), false, 0));
+        assert!(policy.is_due(Duration::from_secs(600), false, 0));
+    }
+
+    #[test]
+    fn manual_punishment_is_due_once_confirmed_or_after_deadline() {
+        let policy = PunishPolicy::Manual {
+            deadline: Duration::from_secs(600),
+        };
+
+        assert!(!policy.is_due(Duration::ZERO, false, 0));
+        assert!(policy.is_due(Duration::ZERO, true, 0));
+        assert!(policy.is_due(Duration::from_secs(600), false, 0));
+    }
+
+    #[test]
+    fn punishment_is_due_regardless_of_policy_before_cet_can_be_published() {
+        let policy = PunishPolicy::Manual {
+            deadline: Duration::from_secs(600),
+        };
+
+        assert!(!policy.is_due(Duration::ZERO, false, URGENT_CONFIRMATIONS - 1));
+        assert!(policy.is_due(Duration::ZERO, false, URGENT_CONFIRMATIONS));
+    }
+}
@@ -0,0 +1,178 @@
+//! Deterministic keys and attestations following olivia's `olivia-v1`
+//! scheme.
+//!
+//! The key of the oracle and the nonces of every event are derived from
+//! a seed, so that the same seed always results in the same public key
+//! and announcements, e.g. across restarts of the oracle.
+//!
+//! Every digit of an event is attested with a Schnorr signature on the
+//! value of the digit, using the announced nonce of the digit. Only the
+//! scalar of the signature is published, which is the secret key the
+//! CETs of the outcome are encrypted to.
+
+use anyhow::ensure;
+use anyhow::Result;
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::hashes::Hash;
+use bdk::bitcoin::hashes::HashEngine;
+use bdk::bitcoin::secp256k1::PublicKey;
+use bdk::bitcoin::secp256k1::SecretKey;
+use bdk::bitcoin::secp256k1::SECP256K1;
+use bdk::bitcoin::XOnlyPublicKey;
+use model::olivia::BitMexPriceEventId;
+
+pub struct Keys {
+    seed: String,
+    oracle_sk: SecretKey,
+}
+
+impl Keys {
+    pub fn new(seed: &str) -> Self {
+        Self {
+            seed: seed.to_owned(),
+            oracle_sk: derive_key(seed, "oracle"),
+        }
+    }
+
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        x_only(&self.oracle_sk)
+    }
+
+    /// The nonces announced for `event_id`, one per digit.
+    pub fn nonce_pks(&self, event_id: BitMexPriceEventId) -> Vec<XOnlyPublicKey> {
+        (0..event_id.digits())
+            .map(|digit| x_only(&self.nonce_sk(event_id, digit)))
+            .collect()
+    }
+
+    /// Attest to `price` being the outcome of `event_id`.
+    ///
+    /// Returns one scalar per digit, starting with the most significant
+    /// one.
+    pub fn attest(&self, event_id: BitMexPriceEventId, price: u64) -> Result<Vec<SecretKey>> {
+        let digits = event_id.digits();
+        ensure!(
+            digits < 64 && price < 1 << digits,
+            "Price {price} does not fit into {digits} digits"
+        );
+
+        let oracle_pk = self.public_key();
+
+        (0..digits)
+            .map(|digit| {
+                let bit = (price >> (digits - 1 - digit)) & 1;
+                let nonce_sk = self.nonce_sk(event_id, digit);
+                let challenge = challenge(&x_only(&nonce_sk), &oracle_pk, &[bit as u8]);
+
+                let mut scalar = self.oracle_sk;
+                scalar.mul_assign(&challenge)?;
+                scalar.add_assign(nonce_sk.as_ref())?;
+
+                Ok(scalar)
+            })
+            .collect()
+    }
+
+    fn nonce_sk(&self, event_id: BitMexPriceEventId, digit: usize) -> SecretKey {
+        derive_key(&self.seed, &format!("nonce{event_id}/{digit}"))
+    }
+}
+
+/// Derive the secret key at `path` from `seed`.
+///
+/// The key is negated if necessary so that its public key has an even
+/// y-coordinate, as x-only public keys are assumed to.
+fn derive_key(seed: &str, path: &str) -> SecretKey {
+    let hash = sha256::Hash::hash(format!("{seed}/{path}").as_bytes());
+    let mut sk = SecretKey::from_slice(&hash.into_inner()).expect("hash to be a valid key");
+
+    let is_odd = PublicKey::from_secret_key(SECP256K1, &sk).serialize()[0] == 0x03;
+    if is_odd {
+        sk.negate_assign();
+    }
+
+    sk
+}
+
+fn x_only(sk: &SecretKey) -> XOnlyPublicKey {
+    let pk = PublicKey::from_secret_key(SECP256K1, sk);
+
+    XOnlyPublicKey::from_slice(&pk.serialize()[1..]).expect("valid x-only public key")
+}
+
+/// The BIP340 challenge of a signature on `msg` with `nonce_pk`.
+fn challenge(nonce_pk: &XOnlyPublicKey, oracle_pk: &XOnlyPublicKey, msg: &[u8]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(b"BIP0340/challenge");
+
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    engine.input(&nonce_pk.serialize());
+    engine.input(&oracle_pk.serialize());
+    engine.input(msg);
+
+    sha256::Hash::from_engine(engine).into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maia::compute_adaptor_pk;
+    use model::olivia::IndexPrice;
+    use time::macros::datetime;
+
+    fn event_id() -> BitMexPriceEventId {
+        BitMexPriceEventId::with_20_digits(
+            datetime!(2022-10-17 12:00:00).assume_utc(),
+            IndexPrice::Bxbt,
+        )
+    }
+
+    #[test]
+    fn default_seed_results_in_public_key_known_to_daemons() {
+        assert_eq!(
+            Keys::new("dev-oracle").public_key(),
+            *model::olivia::DEV_PUBLIC_KEY
+        );
+    }
+
+    #[test]
+    fn same_seed_results_in_same_keys() {
+        let keys = Keys::new("seed");
+        let same_keys = Keys::new("seed");
+        let other_keys = Keys::new("other seed");
+
+        assert_eq!(keys.public_key(), same_keys.public_key());
+        assert_eq!(keys.nonce_pks(event_id()), same_keys.nonce_pks(event_id()));
+        assert_ne!(keys.public_key(), other_keys.public_key());
+    }
+
+    #[test]
+    fn attestation_decrypts_cets_of_the_attested_price() {
+        let keys = Keys::new("seed");
+        let price = 20_123;
+
+        let scalars = keys.attest(event_id(), price).unwrap();
+
+        let bits = (0..20).map(|digit| ((price >> (19 - digit)) & 1) as usize);
+        let index_nonce_pairs = bits.zip(keys.nonce_pks(event_id())).collect::<Vec<_>>();
+        let adaptor_pk = compute_adaptor_pk(&keys.public_key(), &index_nonce_pairs).unwrap();
+
+        let mut decryption_sk = scalars[0];
+        for scalar in scalars[1..].iter() {
+            decryption_sk.add_assign(scalar.as_ref()).unwrap();
+        }
+
+        assert_eq!(
+            PublicKey::from_secret_key(SECP256K1, &decryption_sk),
+            adaptor_pk
+        );
+    }
+
+    #[test]
+    fn price_must_fit_into_digits() {
+        let keys = Keys::new("seed");
+
+        assert!(keys.attest(event_id(), 1 << 20).is_err());
+    }
+}
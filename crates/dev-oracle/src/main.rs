@@ -0,0 +1,357 @@
+//! A deterministic oracle for local development.
+//!
+//! Serves the announcements and attestations of BitMEX price events
+//! under the same paths and in the same format as olivia, so that CFDs
+//! can go through their whole lifecycle without depending on olivia.
+//! Run the daemons with `--oracle-url` and `--oracle-pk` pointing to
+//! this oracle; its public key is logged on startup and served at `/`.
+//!
+//! The keys are derived from `--seed`, so restarting the oracle with
+//! the same seed results in the same announcements. Every event is
+//! attested to with the price of its index at the time the event is
+//! first requested after it occurred. The prices can be changed at
+//! runtime, e.g. to liquidate a CFD:
+//!
+//! ```text
+//! curl -X PUT localhost:8100/prices/BXBT -H 'Content-Type: application/json' -d '{"price":15000}'
+//! ```
+//!
+//! With `--speed` the clock of the oracle runs faster than real time,
+//! starting from the time the oracle was started. The daemons only ask
+//! for attestations of events which occurred according to their own
+//! clock, so they have to be run with an accelerated clock as well,
+//! e.g. using `faketime`.
+
+mod keys;
+
+use crate::keys::Keys;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use model::olivia::BitMexPriceEventId;
+use model::olivia::IndexPrice;
+use model::olivia::EVENT_TIME_FORMAT;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing_subscriber::filter::LevelFilter;
+
+#[derive(Parser)]
+struct Opts {
+    /// The IP address and port to listen on for HTTP requests.
+    #[clap(long, default_value = "127.0.0.1:8100")]
+    http_address: SocketAddr,
+
+    /// The seed all keys of the oracle are derived from.
+    #[clap(long, default_value = "dev-oracle")]
+    seed: String,
+
+    /// The price to attest to for BTCUSD events until it is changed via the API.
+    #[clap(long, default_value = "20000")]
+    btc_price: u64,
+
+    /// The price to attest to for ETHUSD events until it is changed via the API.
+    #[clap(long, default_value = "1500")]
+    eth_price: u64,
+
+    /// How many times faster than real time the clock of the oracle runs.
+    #[clap(long, default_value = "1")]
+    speed: u32,
+
+    /// Verbosity level of the logs.
+    #[clap(long, default_value = "info")]
+    log_level: LevelFilter,
+}
+
+#[rocket::main]
+async fn main() -> Result<()> {
+    let opts = Opts::parse();
+
+    tracing_subscriber::fmt()
+        .with_max_level(opts.log_level)
+        .init();
+
+    let oracle = Oracle::new(
+        Keys::new(&opts.seed),
+        Clock::new(opts.speed),
+        HashMap::from([
+            (IndexPrice::Bxbt, opts.btc_price),
+            (IndexPrice::Beth, opts.eth_price),
+        ]),
+    );
+
+    tracing::info!(
+        "Run the daemons with `--oracle-url http://{} --oracle-pk {}`",
+        opts.http_address,
+        oracle.keys.public_key()
+    );
+
+    let figment = rocket::Config::figment()
+        .merge(("address", opts.http_address.ip()))
+        .merge(("port", opts.http_address.port()))
+        .merge(("cli_colors", false));
+
+    rocket::custom(figment)
+        .manage(oracle)
+        .mount("/", rocket::routes![info, event, put_price])
+        .launch()
+        .await?;
+
+    Ok(())
+}
+
+struct Oracle {
+    keys: Keys,
+    clock: Clock,
+    /// The prices at which upcoming events are attested
+    prices: Mutex<HashMap<IndexPrice, u64>>,
+    /// The prices of the events attested so far
+    attested: Mutex<HashMap<BitMexPriceEventId, u64>>,
+}
+
+impl Oracle {
+    fn new(keys: Keys, clock: Clock, prices: HashMap<IndexPrice, u64>) -> Self {
+        Self {
+            keys,
+            clock,
+            prices: Mutex::new(prices),
+            attested: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The announcement of `event_id`, including the attestation if the
+    /// event already occurred, in olivia's format.
+    fn event(&self, event_id: BitMexPriceEventId) -> Result<Value> {
+        let data = json!({
+            "id": event_id.to_string(),
+            "expected-outcome-time": event_id.timestamp().format(&EVENT_TIME_FORMAT)?,
+            "descriptor": {
+                "type": "digit-decomposition",
+                "is_signed": false,
+                "n_digits": event_id.digits(),
+                "unit": null,
+            },
+            "schemes": {
+                "olivia-v1": {
+                    "nonces": self.keys.nonce_pks(event_id),
+                },
+            },
+        });
+
+        let now = self.clock.now();
+        let attestation = if event_id.timestamp() <= now {
+            let price = self.attested_price(event_id)?;
+            let scalars = self.keys.attest(event_id, price)?;
+
+            json!({
+                "outcome": price.to_string(),
+                "schemes": {
+                    "olivia-v1": {
+                        "scalars": scalars,
+                    },
+                },
+                "time": now.format(&EVENT_TIME_FORMAT)?,
+            })
+        } else {
+            Value::Null
+        };
+
+        Ok(json!({
+            "announcement": {
+                "oracle_event": {
+                    "encoding": "json",
+                    "data": data.to_string(),
+                },
+                // The daemons do not verify the signature of announcements yet
+                "signature": null,
+            },
+            "attestation": attestation,
+        }))
+    }
+
+    /// The price `event_id` is attested to, which is fixed once the
+    /// event has been attested for the first time.
+    fn attested_price(&self, event_id: BitMexPriceEventId) -> Result<u64> {
+        let index = event_id.index_price();
+        let price = *self
+            .prices
+            .lock()
+            .expect("not poisoned")
+            .get(&index)
+            .with_context(|| format!("No price for {index}"))?;
+
+        let attested_price = *self
+            .attested
+            .lock()
+            .expect("not poisoned")
+            .entry(event_id)
+            .or_insert_with(|| {
+                tracing::info!(%event_id, price, "Attesting to event");
+                price
+            });
+
+        Ok(attested_price)
+    }
+}
+
+/// The clock of the oracle, running `speed` times faster than real time
+/// since it was started.
+struct Clock {
+    started_at: OffsetDateTime,
+    speed: u32,
+}
+
+impl Clock {
+    fn new(speed: u32) -> Self {
+        Self {
+            started_at: OffsetDateTime::now_utc(),
+            speed,
+        }
+    }
+
+    fn now(&self) -> OffsetDateTime {
+        self.started_at + (OffsetDateTime::now_utc() - self.started_at) * self.speed
+    }
+}
+
+#[derive(Serialize)]
+struct Info {
+    public_key: String,
+    now: String,
+    prices: HashMap<String, u64>,
+}
+
+#[rocket::get("/")]
+fn info(oracle: &State<Oracle>) -> Result<Json<Info>, (Status, String)> {
+    let now = oracle
+        .clock
+        .now()
+        .format(&Rfc3339)
+        .map_err(|e| (Status::InternalServerError, e.to_string()))?;
+    let prices = oracle
+        .prices
+        .lock()
+        .expect("not poisoned")
+        .iter()
+        .map(|(index, price)| (index.to_string(), *price))
+        .collect();
+
+    Ok(Json(Info {
+        public_key: oracle.keys.public_key().to_string(),
+        now,
+        prices,
+    }))
+}
+
+#[rocket::get("/x/BitMEX/<index>/<event>?<n>")]
+fn event(
+    index: &str,
+    event: &str,
+    n: usize,
+    oracle: &State<Oracle>,
+) -> Result<Json<Value>, (Status, String)> {
+    let event_id = BitMexPriceEventId::from_str(&format!("/x/BitMEX/{index}/{event}?n={n}"))
+        .map_err(|e| (Status::NotFound, format!("{e:#}")))?;
+
+    let event = oracle
+        .event(event_id)
+        .map_err(|e| (Status::InternalServerError, format!("{e:#}")))?;
+
+    Ok(Json(event))
+}
+
+#[derive(Deserialize)]
+struct PriceRequest {
+    price: u64,
+}
+
+/// Set the price at which upcoming events of `index` are attested.
+#[rocket::put("/prices/<index>", data = "<request>")]
+fn put_price(
+    index: &str,
+    request: Json<PriceRequest>,
+    oracle: &State<Oracle>,
+) -> Result<(), (Status, String)> {
+    let index = IndexPrice::from_str(index).map_err(|e| (Status::NotFound, e.to_string()))?;
+    let price = request.into_inner().price;
+
+    oracle
+        .prices
+        .lock()
+        .expect("not poisoned")
+        .insert(index, price);
+    tracing::info!(%index, price, "Updated price");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::olivia;
+    use time::ext::NumericalDuration;
+    use time::Time;
+
+    fn oracle() -> Oracle {
+        Oracle::new(
+            Keys::new("seed"),
+            Clock::new(1),
+            HashMap::from([(IndexPrice::Bxbt, 20_000)]),
+        )
+    }
+
+    fn event_id(timestamp: OffsetDateTime) -> BitMexPriceEventId {
+        let timestamp = timestamp.replace_time(Time::from_hms(timestamp.hour(), 0, 0).unwrap());
+
+        BitMexPriceEventId::with_20_digits(timestamp, IndexPrice::Bxbt)
+    }
+
+    #[test]
+    fn upcoming_event_is_announced_but_not_attested() {
+        let oracle = oracle();
+        let event_id = event_id(OffsetDateTime::now_utc() + 2.hours());
+
+        let event = oracle.event(event_id).unwrap();
+
+        let announcement = serde_json::from_value::<olivia::Announcement>(event.clone()).unwrap();
+        assert_eq!(announcement.id, event_id);
+        assert_eq!(announcement.nonce_pks, oracle.keys.nonce_pks(event_id));
+        assert!(serde_json::from_value::<olivia::Attestation>(event).is_err());
+    }
+
+    #[test]
+    fn past_event_is_attested_at_the_price_of_the_first_request() {
+        let oracle = oracle();
+        let event_id = event_id(OffsetDateTime::now_utc() - 2.hours());
+
+        let event = oracle.event(event_id).unwrap();
+        oracle
+            .prices
+            .lock()
+            .unwrap()
+            .insert(IndexPrice::Bxbt, 15_000);
+        let event_after_price_change = oracle.event(event_id).unwrap();
+
+        let attestation = serde_json::from_value::<olivia::Attestation>(event).unwrap();
+        assert_eq!(attestation.id, event_id);
+        assert_eq!(attestation.price, 20_000);
+        assert_eq!(
+            attestation.scalars,
+            oracle.keys.attest(event_id, 20_000).unwrap()
+        );
+        assert_eq!(
+            serde_json::from_value::<olivia::Attestation>(event_after_price_change).unwrap(),
+            attestation
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! A front for the p2p port of the maker.
+//!
+//! Two instances of the maker cannot run at the same time: both would lock the same wallet and
+//! write to the same database. Restarting the maker hence leaves a gap in which nothing listens on
+//! its p2p port, and takers connecting meanwhile would be refused.
+//!
+//! The front listens on the p2p port in place of the maker and forwards every connection to the
+//! maker, which listens on a different port. While the maker restarts, the front keeps connections
+//! waiting until the next instance listens, instead of refusing them.
+
+use anyhow::Context;
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio_extras::FutureExt;
+
+/// How often to try to connect to the maker while it is not listening.
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Forward all connections accepted by `listener` to the maker listening on `maker`.
+///
+/// A connection is kept waiting for up to `restart_timeout` if the maker is not listening, e.g.
+/// because it is being restarted.
+pub async fn run(listener: TcpListener, maker: SocketAddr, restart_timeout: Duration) {
+    loop {
+        let (taker, taker_address) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::warn!("Failed to accept connection: {e:#}");
+                continue;
+            }
+        };
+
+        // The connection is independent of all others, it ends when either side closes it
+        async move {
+            if let Err(e) = forward(taker, maker, restart_timeout).await {
+                tracing::debug!(%taker_address, "Failed to forward connection: {e:#}");
+            }
+        }
+        .spawn_with_handle()
+        .forget();
+    }
+}
+
+async fn forward(mut taker: TcpStream, maker: SocketAddr, restart_timeout: Duration) -> Result<()> {
+    let mut maker = connect(maker, restart_timeout).await?;
+
+    tokio::io::copy_bidirectional(&mut taker, &mut maker).await?;
+
+    Ok(())
+}
+
+/// Connect to the maker, waiting for up to `restart_timeout` for it to listen.
+async fn connect(maker: SocketAddr, restart_timeout: Duration) -> Result<TcpStream> {
+    let started_at = Instant::now();
+
+    loop {
+        match TcpStream::connect(maker).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) if started_at.elapsed() < restart_timeout => {
+                tracing::trace!(%maker, "Maker is not listening, retrying: {e}");
+                tokio_extras::time::sleep_silent(RECONNECT_INTERVAL).await;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Maker at {maker} did not listen within {}s",
+                        restart_timeout.as_secs()
+                    )
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Holds the p2p port of the maker while it restarts.
+//!
+//! Run the maker with `--p2p-port` set to the port given as `--maker` here, and advertise the
+//! `--listen` address of the front to takers instead.
+
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use shared_bin::logger;
+use shared_bin::logger::LevelFilter;
+use shared_bin::logger::LogFormat;
+use shared_bin::logger::LOCAL_COLLECTOR_ENDPOINT;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+#[derive(Parser)]
+struct Opts {
+    /// The address to listen on for connections of takers.
+    #[clap(long, default_value = "0.0.0.0:10000")]
+    listen: SocketAddr,
+
+    /// The address the maker listens on for p2p connections.
+    #[clap(long, default_value = "127.0.0.1:10001")]
+    maker: SocketAddr,
+
+    /// How long to keep a connection waiting for the maker to listen, in seconds, e.g. while it
+    /// restarts.
+    #[clap(long, default_value = "60")]
+    restart_timeout_secs: u64,
+
+    /// Configure the log level, e.g.: one of Error, Warn, Info, Debug, Trace
+    #[clap(short, long, default_value = "Info")]
+    log_level: LevelFilter,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opts = Opts::parse();
+
+    let _logging = logger::init(
+        opts.log_level,
+        LogFormat::Text,
+        false,
+        false,
+        false,
+        false,
+        "maker-front",
+        LOCAL_COLLECTOR_ENDPOINT,
+        false,
+        ".",
+    )
+    .context("initialize logger")?;
+    tracing::info!("Running version: {}", env!("CARGO_PKG_VERSION"));
+
+    let listener = TcpListener::bind(opts.listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", opts.listen))?;
+
+    tracing::info!(listen = %opts.listen, maker = %opts.maker, "Forwarding connections to the maker");
+
+    tokio::select! {
+        () = maker_front::run(
+            listener,
+            opts.maker,
+            Duration::from_secs(opts.restart_timeout_secs),
+        ) => {}
+        result = tokio::signal::ctrl_c() => result?,
+    }
+
+    Ok(())
+}
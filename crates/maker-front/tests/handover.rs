@@ -0,0 +1,131 @@
+//! Restarting the maker behind the front, with the old and the new maker being separate processes
+//! which never run at the same time.
+//!
+//! The makers are stood in for by this test binary itself, re-run with [`STAND_IN_MAKER`] set to
+//! only execute [`stand_in_maker`]. A stand-in maker answers every connection with its name.
+
+use std::io::Read;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+const STAND_IN_MAKER: &str = "MAKER_FRONT_STAND_IN_MAKER";
+
+#[test]
+fn connections_during_restart_reach_next_maker() {
+    let front_address = unused_address();
+    let maker_address = unused_address();
+
+    let old_maker = Process::stand_in_maker(maker_address, "old");
+    wait_until_listening(maker_address);
+    let _front = Process::front(front_address, maker_address);
+    wait_until_listening(front_address);
+
+    assert_eq!(connect_and_read(front_address), "old");
+
+    old_maker.exit();
+
+    let during_restart = thread::spawn(move || connect_and_read(front_address));
+    thread::sleep(Duration::from_secs(1));
+    assert!(!during_restart.is_finished());
+
+    let _new_maker = Process::stand_in_maker(maker_address, "new");
+
+    assert_eq!(during_restart.join().unwrap(), "new");
+    assert_eq!(connect_and_read(front_address), "new");
+}
+
+/// Runs as a stand-in maker if [`STAND_IN_MAKER`] is set, otherwise does nothing.
+#[test]
+fn stand_in_maker() {
+    let config = match std::env::var(STAND_IN_MAKER) {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+    let (address, name) = config.split_once(',').unwrap();
+
+    let listener = TcpListener::bind(address).unwrap();
+    for stream in listener.incoming() {
+        let _ = stream.and_then(|mut stream| stream.write_all(name.as_bytes()));
+    }
+}
+
+/// A child process which is killed when dropped.
+struct Process(Child);
+
+impl Process {
+    fn front(listen: SocketAddr, maker: SocketAddr) -> Self {
+        let child = Command::new(env!("CARGO_BIN_EXE_maker-front"))
+            .args(["--listen", &listen.to_string()])
+            .args(["--maker", &maker.to_string()])
+            .args(["--restart-timeout-secs", "10"])
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        Self(child)
+    }
+
+    fn stand_in_maker(address: SocketAddr, name: &str) -> Self {
+        let child = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "stand_in_maker", "--nocapture"])
+            .env(STAND_IN_MAKER, format!("{address},{name}"))
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        Self(child)
+    }
+
+    /// Kill the process and wait until it exited.
+    fn exit(mut self) {
+        self.0.kill().unwrap();
+        self.0.wait().unwrap();
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn connect_and_read(address: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+
+    let mut name = String::new();
+    stream.read_to_string(&mut name).unwrap();
+
+    name
+}
+
+fn wait_until_listening(address: SocketAddr) {
+    let started_at = Instant::now();
+
+    while TcpStream::connect(address).is_err() {
+        assert!(
+            started_at.elapsed() < Duration::from_secs(10),
+            "{address} is not listening"
+        );
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn unused_address() -> SocketAddr {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
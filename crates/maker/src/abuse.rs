@@ -0,0 +1,128 @@
+//! Detection of takers which waste the maker's resources.
+//!
+//! The following signals are derived from the CFDs of every taker:
+//!
+//! - the orders which were never filled, i.e. rejected or failed during contract setup;
+//! - the contract setups which failed after the order was accepted, at which point we have already
+//!   built and signed our part of the contract;
+//! - the number of orders placed during the last minute.
+//!
+//! Once a signal of a taker reaches its threshold in the configured
+//! [`Policy`], the [`Penalty`] of the policy is applied to the taker.
+
+use daemon::projection::Cfd;
+use daemon::projection::CfdState;
+use model::Timestamp;
+use serde::Serialize;
+use std::collections::HashMap;
+use xtra_libp2p::libp2p::PeerId;
+
+/// The window over which the order rate is measured.
+const ORDER_RATE_WINDOW_SECS: i64 = 60;
+
+/// What happens to a taker once one of its signals reaches its
+/// threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Penalty {
+    /// Only log a warning
+    Warn,
+    /// Block the taker, which drops its connection and refuses new ones
+    Block,
+}
+
+/// The thresholds at which takers are penalized.
+///
+/// Signals without a threshold never lead to a penalty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Penalize takers which placed this many orders without any of
+    /// them being filled
+    pub max_unfilled_orders: Option<u32>,
+    /// Penalize takers which aborted this many contract setups
+    pub max_aborted_setups: Option<u32>,
+    /// Penalize takers which placed this many orders within a minute
+    pub max_orders_per_minute: Option<u32>,
+    pub penalty: Penalty,
+}
+
+impl Policy {
+    /// Whether any signal can lead to a penalty.
+    pub fn is_enabled(&self) -> bool {
+        self.max_unfilled_orders.is_some()
+            || self.max_aborted_setups.is_some()
+            || self.max_orders_per_minute.is_some()
+    }
+}
+
+/// The signals of one taker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Signals {
+    pub orders: u32,
+    pub filled_orders: u32,
+    pub unfilled_orders: u32,
+    pub aborted_setups: u32,
+    pub orders_last_minute: u32,
+}
+
+impl Signals {
+    fn add(&mut self, cfd: &Cfd, now: Timestamp) {
+        self.orders += 1;
+
+        match cfd.state {
+            CfdState::PendingSetup | CfdState::ContractSetup => {}
+            CfdState::Rejected => self.unfilled_orders += 1,
+            CfdState::SetupFailed => {
+                self.unfilled_orders += 1;
+                self.aborted_setups += 1;
+            }
+            _ => self.filled_orders += 1,
+        }
+
+        if now.seconds() - cfd.creation_timestamp().seconds() < ORDER_RATE_WINDOW_SECS {
+            self.orders_last_minute += 1;
+        }
+    }
+
+    /// The thresholds of `policy` which the taker has reached.
+    ///
+    /// Empty if the taker is not to be penalized.
+    pub fn violations(&self, policy: &Policy) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        if let Some(max) = policy.max_unfilled_orders {
+            if self.filled_orders == 0 && self.unfilled_orders >= max {
+                violations.push(format!("{} orders without a fill", self.unfilled_orders));
+            }
+        }
+        if let Some(max) = policy.max_aborted_setups {
+            if self.aborted_setups >= max {
+                violations.push(format!("{} aborted contract setups", self.aborted_setups));
+            }
+        }
+        if let Some(max) = policy.max_orders_per_minute {
+            if self.orders_last_minute >= max {
+                violations.push(format!(
+                    "{} orders within a minute",
+                    self.orders_last_minute
+                ));
+            }
+        }
+
+        violations
+    }
+}
+
+/// Derive the signals of every taker from the maker's `cfds`.
+pub fn signals(cfds: &[Cfd], now: Timestamp) -> HashMap<PeerId, Signals> {
+    let mut signals = HashMap::<PeerId, Signals>::new();
+
+    for cfd in cfds {
+        signals
+            .entry(cfd.counterparty.inner())
+            .or_default()
+            .add(cfd, now);
+    }
+
+    signals
+}
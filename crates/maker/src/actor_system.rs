@@ -1,14 +1,23 @@
+use crate::abuse;
 use crate::cfd;
+use crate::exposure::ExposureLimits;
 use crate::metrics::time_to_first_position;
+use crate::quoting;
+use anyhow::Context as _;
 use anyhow::Result;
 use bdk::bitcoin;
 use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
 use bdk::bitcoin::Amount;
+use bdk::bitcoin::OutPoint;
 use bdk::bitcoin::Txid;
+use daemon::analytics;
 use daemon::archive_closed_cfds;
 use daemon::archive_failed_cfds;
+use daemon::block_explorer::BlockExplorer;
 use daemon::collab_settlement;
 use daemon::command;
+use daemon::electrum;
+use daemon::fiat_rates;
 use daemon::identify;
 use daemon::listen_protocols::MAKER_LISTEN_PROTOCOLS;
 use daemon::monitor;
@@ -16,46 +25,64 @@ use daemon::oracle;
 use daemon::oracle::NoAnnouncement;
 use daemon::order;
 use daemon::position_metrics;
+use daemon::price_feed::GetTwap;
+use daemon::price_feed::Twap;
 use daemon::process_manager;
 use daemon::projection;
 use daemon::seed::Identities;
+use daemon::trace;
+use daemon::trade_history;
 use daemon::wallet;
+use daemon::watchdog;
+use daemon::watchtower;
+use daemon::ConnectionSettings;
 use daemon::Environment;
-use libp2p_tcp::TokioTcpConfig;
-use maia_core::secp256k1_zkp::XOnlyPublicKey;
 use maia_core::PartyParams;
 use model::olivia::Announcement;
+use model::olivia::OracleKeys;
 use model::ContractSymbol;
 use model::Contracts;
+use model::FeeSubsidy;
 use model::FundingRate;
+use model::Identity;
 use model::Leverage;
 use model::LotSize;
+use model::OfferTier;
 use model::OpeningFee;
 use model::OrderId;
 use model::Price;
+use model::RejectReason;
 use model::Role;
+use model::Timestamp;
 use model::TxFeeRate;
 use ping_pong::ping;
 use ping_pong::pong;
+use sqlite_db::annotations::Annotation;
+use sqlite_db::rehydration;
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::watch;
 use tokio_extras::Tasks;
+use xtra::prelude::MessageChannel;
 use xtra::Actor;
 use xtra::Address;
 use xtra::Context;
 use xtra::Handler;
+use xtra_bitmex_price_feed::GetLatestQuotes;
+use xtra_bitmex_price_feed::LatestQuotes;
 use xtra_libp2p::endpoint;
 use xtra_libp2p::libp2p::Multiaddr;
 use xtra_libp2p::libp2p::PeerId;
 use xtra_libp2p::listener;
+use xtra_libp2p::recorder::Recorder;
 use xtra_libp2p::Endpoint;
+use xtra_libp2p::RateLimits;
 use xtras::supervisor::always_restart_after;
 use xtras::supervisor::Supervisor;
 
-const ENDPOINT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(20);
-const PING_INTERVAL: Duration = Duration::from_secs(30);
-
 /// Duration between the restart attempts after a supervised actor has quit with
 /// a failure.
 pub const RESTART_INTERVAL: Duration = Duration::from_secs(5);
@@ -77,7 +104,16 @@ pub struct ActorSystem<O: 'static, W: 'static> {
     _oracle_actor: Address<O>,
     _archive_closed_cfds_actor: Address<archive_closed_cfds::Actor>,
     _archive_failed_cfds_actor: Address<archive_failed_cfds::Actor>,
+    _watchdog_actor: Address<watchdog::Actor>,
+    quoting_actor: Address<quoting::Actor>,
+    offer_actor: Address<offer::maker::Actor>,
+    endpoint_actor: Address<Endpoint>,
+    projection_actor: Address<projection::Actor>,
+    watchtower_actor: Address<watchtower::tower::Actor>,
     executor: command::Executor,
+    db: sqlite_db::Connection,
+    /// How far the rehydration of the open CFDs on startup has progressed
+    pub rehydration_progress: watch::Receiver<rehydration::Progress>,
     _tasks: Tasks,
     _pong_actor: Address<pong::Actor>,
 }
@@ -91,21 +127,44 @@ where
         + Handler<wallet::Sign, Return = Result<PartiallySignedTransaction>>
         + Handler<wallet::Withdraw, Return = Result<Txid>>
         + Handler<wallet::Sync, Return = ()>
+        + Handler<wallet::ListUtxos, Return = Result<Vec<wallet::Utxo>>>
+        + Handler<wallet::FreezeUtxos, Return = Result<()>>
+        + Handler<wallet::UnfreezeUtxos, Return = ()>
+        + Handler<wallet::GetBlockHeight, Return = Result<u32>>
+        + Handler<xtras::Probe, Return = ()>
         + Actor<Stop = ()>,
 {
     #[allow(clippy::too_many_arguments)]
     pub fn new<M>(
         db: sqlite_db::Connection,
         wallet_addr: Address<W>,
-        oracle_pk: XOnlyPublicKey,
+        oracle_keys: OracleKeys,
         oracle_constructor: impl FnOnce(command::Executor) -> O,
         monitor_constructor: impl FnOnce(command::Executor) -> Result<M>,
         settlement_interval: time::Duration,
         n_payouts: usize,
+        offer_hysteresis: cfd::OfferHysteresis,
+        (price_feed, price_feed_twap, quoting_interval): (
+            MessageChannel<GetLatestQuotes, LatestQuotes>,
+            MessageChannel<GetTwap, Option<Twap>>,
+            Duration,
+        ),
         projection_actor: Address<projection::Actor>,
         identity: Identities,
-        listen_multiaddr: Multiaddr,
+        listen_multiaddrs: Vec<Multiaddr>,
+        connection: ConnectionSettings,
         blocked_peers: HashSet<PeerId>,
+        offer_tiers: HashMap<PeerId, OfferTier>,
+        restricted_protocols: HashMap<String, HashSet<PeerId>>,
+        rate_limits: Option<RateLimits>,
+        taker_limits: Option<(
+            order::maker::TakerLimits,
+            watch::Receiver<Option<Vec<projection::Cfd>>>,
+        )>,
+        protocol_recorder: Option<Recorder>,
+        event_delivery_timeout: Duration,
+        active_electrum: electrum::ActiveServer,
+        punish_policy: watchtower::tower::PunishPolicy,
     ) -> Result<Self>
     where
         M: Handler<monitor::MonitorAfterContractSetup, Return = ()>
@@ -114,6 +173,7 @@ where
             + Handler<monitor::MonitorCollaborativeSettlement, Return = ()>
             + Handler<monitor::TryBroadcastTransaction, Return = Result<()>>
             + Handler<monitor::MonitorCetFinality, Return = Result<()>>
+            + Handler<xtras::Probe, Return = ()>
             + Actor<Stop = ()>,
     {
         let (monitor_addr, monitor_ctx) = Context::new(None);
@@ -121,10 +181,24 @@ where
         let (process_manager_addr, process_manager_ctx) = Context::new(None);
         let (time_to_first_position_addr, time_to_first_position_ctx) = Context::new(None);
 
-        let executor = command::Executor::new(db.clone(), process_manager_addr.clone());
+        let executor = command::Executor::new(db.clone(), process_manager_addr.clone().into());
 
         let mut tasks = Tasks::default();
 
+        let (rehydration_progress_sender, rehydration_progress) =
+            watch::channel(rehydration::Progress::default());
+        tasks.add_fallible(
+            {
+                let executor = executor.clone();
+                let db = db.clone();
+                async move {
+                    executor.rehydrate(rehydration_progress_sender).await?;
+                    collab_settlement::resume_interrupted(&db, &executor).await
+                }
+            },
+            |e| async move { tracing::error!("{e:#}") },
+        );
+
         let position_metrics_actor = position_metrics::Actor::new(db.clone())
             .create(None)
             .spawn(&mut tasks);
@@ -132,13 +206,14 @@ where
         tasks.add(process_manager_ctx.run(process_manager::Actor::new(
             db.clone(),
             Role::Maker,
+            event_delivery_timeout,
             projection_actor.clone().into(),
-            position_metrics_actor.into(),
+            position_metrics_actor.clone().into(),
+            monitor_addr.clone().into(),
             monitor_addr.clone().into(),
             monitor_addr.clone().into(),
             monitor_addr.clone().into(),
             monitor_addr.clone().into(),
-            monitor_addr.into(),
             oracle_addr.clone().into(),
         )));
 
@@ -152,10 +227,11 @@ where
 
         let (supervisor, maker_offer_address) = Supervisor::new({
             let endpoint_addr = endpoint_addr.clone();
-            move || offer::maker::Actor::new(endpoint_addr.clone())
+            move || offer::maker::Actor::new(endpoint_addr.clone()).with_tiers(offer_tiers.clone())
         });
         tasks.add(supervisor.run_log_summary());
 
+        let cfd_keys = identity.cfd_keys;
         let (order_supervisor, order) = Supervisor::new({
             let oracle = oracle_addr.clone();
             let db = db.clone();
@@ -164,15 +240,21 @@ where
             let projection = projection_actor.clone();
             let maker_offer_address = maker_offer_address.clone();
             move || {
-                order::maker::Actor::new(
+                let actor = order::maker::Actor::new(
                     n_payouts,
-                    oracle_pk,
+                    oracle_keys,
+                    cfd_keys,
                     oracle.clone().into(),
                     (db.clone(), process_manager.clone()),
                     (wallet.clone().into(), wallet.clone().into()),
                     projection.clone(),
                     maker_offer_address.clone().into(),
-                )
+                );
+
+                match taker_limits.clone() {
+                    Some((limits, cfds)) => actor.with_taker_limits(limits, cfds),
+                    None => actor,
+                }
             }
         });
         tasks.add(order_supervisor.run_log_summary());
@@ -187,7 +269,8 @@ where
             move || {
                 order::deprecated::maker::Actor::new(
                     n_payouts,
-                    oracle_pk,
+                    oracle_keys,
+                    cfd_keys,
                     oracle.clone().into(),
                     (db.clone(), process_manager.clone()),
                     (wallet.clone().into(), wallet.clone().into()),
@@ -200,22 +283,38 @@ where
 
         let (collab_settlement_supervisor, collab_settlement_addr) = Supervisor::new({
             let executor = executor.clone();
-            move || collab_settlement::maker::Actor::new(executor.clone(), n_payouts)
+            let db = db.clone();
+            let wallet = wallet_addr.clone();
+            move || {
+                collab_settlement::maker::Actor::new(
+                    executor.clone(),
+                    db.clone(),
+                    n_payouts,
+                    wallet.clone().into(),
+                )
+            }
         });
         tasks.add(collab_settlement_supervisor.run_log_summary());
 
         let (collab_settlement_deprecated_supervisor, collab_settlement_deprecated_addr) =
             Supervisor::new({
                 let executor = executor.clone();
+                let wallet = wallet_addr.clone();
                 move || {
-                    collab_settlement::deprecated::maker::Actor::new(executor.clone(), n_payouts)
+                    collab_settlement::deprecated::maker::Actor::new(
+                        executor.clone(),
+                        n_payouts,
+                        wallet.clone().into(),
+                    )
                 }
             });
         tasks.add(collab_settlement_deprecated_supervisor.run_log_summary());
 
         let cfd_actor_addr = cfd::Actor::new(
             settlement_interval,
-            projection_actor,
+            offer_hysteresis,
+            projection_actor.clone(),
+            position_metrics_actor,
             time_to_first_position_addr,
             (
                 collab_settlement_addr.clone(),
@@ -230,6 +329,15 @@ where
         .create(None)
         .spawn(&mut tasks);
 
+        let quoting_actor = quoting::Actor::new(
+            cfd_actor_addr.clone().into(),
+            price_feed,
+            price_feed_twap,
+            quoting_interval,
+        )
+        .create(None)
+        .spawn(&mut tasks);
+
         let (rollover_deprecated_supervisor, rollover_deprecated_addr) = Supervisor::new({
             let executor = executor.clone();
             let oracle_addr = oracle_addr.clone();
@@ -237,7 +345,7 @@ where
             move || {
                 rollover::deprecated::maker::Actor::new(
                     executor.clone(),
-                    oracle_pk,
+                    oracle_keys,
                     oracle::AnnouncementsChannel::new(oracle_addr.clone().into()),
                     cfd::RatesChannel::new(cfd_actor_addr.clone().into()),
                     n_payouts,
@@ -253,7 +361,7 @@ where
             move || {
                 rollover::maker::Actor::new(
                     executor.clone(),
-                    oracle_pk,
+                    oracle_keys,
                     oracle::AnnouncementsChannel::new(oracle_addr.clone().into()),
                     cfd::RatesChannel::new(cfd_actor_addr.clone().into()),
                     n_payouts,
@@ -264,47 +372,68 @@ where
 
         let (ping_supervisor, ping_address) = Supervisor::new({
             let endpoint_addr = endpoint_addr.clone();
-            move || ping::Actor::new(endpoint_addr.clone(), PING_INTERVAL)
+            move || ping::Actor::new(endpoint_addr.clone(), connection.ping_interval)
         });
 
-        let (listener_supervisor, listener_actor) = Supervisor::<_, listener::Error>::with_policy(
-            {
-                let listen_multiaddr = listen_multiaddr.clone();
-                let endpoint_addr = endpoint_addr.clone();
-                move || listener::Actor::new(endpoint_addr.clone(), listen_multiaddr.clone())
-            },
-            always_restart_after(RESTART_INTERVAL),
-        );
+        let mut listener_actors = Vec::new();
+        for listen_multiaddr in listen_multiaddrs.iter().cloned() {
+            let (listener_supervisor, listener_actor) =
+                Supervisor::<_, listener::Error>::with_policy(
+                    {
+                        let endpoint_addr = endpoint_addr.clone();
+                        move || {
+                            listener::Actor::new(endpoint_addr.clone(), listen_multiaddr.clone())
+                        }
+                    },
+                    always_restart_after(RESTART_INTERVAL),
+                );
+            tasks.add(listener_supervisor.run_log_summary());
+            listener_actors.push(listener_actor.into());
+        }
 
         // TODO: Shouldn't this actor also be supervised?
         let pong_address = pong::Actor.create(None).spawn(&mut tasks);
 
         let (identify_listener_supervisor, identify_listener_actor) = Supervisor::new({
             let identity = identity.libp2p.clone();
+            let endpoint_addr = endpoint_addr.clone();
             move || {
                 identify::listener::Actor::new(
                     daemon::version(),
                     Environment::unknown(),
                     identity.public(),
-                    HashSet::from([listen_multiaddr.clone()]),
+                    HashSet::from_iter(listen_multiaddrs.clone()),
                     MAKER_LISTEN_PROTOCOLS.into(),
                 )
+                .with_restricted_protocols(endpoint_addr.clone())
             }
         });
 
-        let (identify_dialer_supervisor, identify_dialer_actor) =
-            Supervisor::new(move || identify::dialer::Actor::new(endpoint_addr.clone()));
+        let (identify_dialer_supervisor, identify_dialer_actor) = Supervisor::new({
+            let endpoint_addr = endpoint_addr.clone();
+            move || identify::dialer::Actor::new(endpoint_addr.clone())
+        });
+
+        let (watchtower_supervisor, watchtower_actor) = Supervisor::new({
+            let db = db.clone();
+            move || {
+                watchtower::tower::Actor::new(db.clone(), active_electrum.clone())
+                    .with_punish_policy(punish_policy)
+            }
+        });
+        tasks.add(watchtower_supervisor.run_log_summary());
 
         let endpoint = Endpoint::new(
-            Box::new(TokioTcpConfig::new),
+            Box::new(daemon::libp2p_utils::tcp_or_websocket_transport),
             identity.libp2p,
-            ENDPOINT_CONNECTION_TIMEOUT,
+            connection.connection_timeout,
             MAKER_LISTEN_PROTOCOLS.inbound_substream_handlers(
                 pong_address.clone(),
                 identify_listener_actor,
                 (order, order_deprecated),
                 (rollover_addr.clone(), rollover_deprecated_addr.clone()),
                 (collab_settlement_addr, collab_settlement_deprecated_addr),
+                watchtower_actor.clone(),
             ),
             endpoint::Subscribers::new(
                 vec![
@@ -315,19 +444,27 @@ where
                 ],
                 vec![
                     ping_address.into(),
-                    maker_offer_address.into(),
+                    maker_offer_address.clone().into(),
                     maker_offer_address_deprecated.into(),
                     identify_dialer_actor.into(),
                 ],
                 vec![],
-                vec![listener_actor.into()],
+                listener_actors,
             ),
             Arc::new(blocked_peers),
-        );
+        )
+        .with_restricted_protocols(restricted_protocols)?;
+        let endpoint = match protocol_recorder {
+            Some(recorder) => endpoint.with_recorder(recorder),
+            None => endpoint,
+        };
+        let endpoint = match rate_limits {
+            Some(rate_limits) => endpoint.with_rate_limits(rate_limits),
+            None => endpoint,
+        };
 
         tasks.add(endpoint_context.run(endpoint));
 
-        tasks.add(listener_supervisor.run_log_summary());
         tasks.add(ping_supervisor.run_log_summary());
         tasks.add(identify_listener_supervisor.run_log_summary());
         tasks.add(identify_dialer_supervisor.run_log_summary());
@@ -344,7 +481,16 @@ where
             .create(None)
             .spawn(&mut tasks);
 
-        tasks.add(time_to_first_position_ctx.run(time_to_first_position::Actor::new(db)));
+        tasks.add(time_to_first_position_ctx.run(time_to_first_position::Actor::new(db.clone())));
+
+        let watchdog_actor = watchdog::Actor::new(vec![
+            watchdog::Watched::new("wallet", wallet_addr.clone()),
+            watchdog::Watched::new("cfd", cfd_actor_addr.clone()),
+            watchdog::Watched::new("monitor", monitor_addr),
+            watchdog::Watched::new("endpoint", endpoint_addr.clone()),
+        ])
+        .create(None)
+        .spawn(&mut tasks);
 
         tracing::debug!("Maker actor system ready");
 
@@ -355,7 +501,15 @@ where
             rollover_actor_deprecated: rollover_deprecated_addr,
             _archive_closed_cfds_actor: archive_closed_cfds_actor,
             _archive_failed_cfds_actor: archive_failed_cfds_actor,
+            _watchdog_actor: watchdog_actor,
+            quoting_actor,
+            offer_actor: maker_offer_address,
+            endpoint_actor: endpoint_addr,
+            projection_actor,
+            watchtower_actor,
             executor,
+            db,
+            rehydration_progress,
             _oracle_actor: oracle_addr,
             _tasks: tasks,
             _pong_actor: pong_address,
@@ -376,10 +530,22 @@ where
         funding_rate_long: FundingRate,
         funding_rate_short: FundingRate,
         opening_fee: OpeningFee,
-        leverage_choices: Vec<Leverage>,
+        leverage_choices_long: Vec<Leverage>,
+        leverage_choices_short: Vec<Leverage>,
         contract_symbol: ContractSymbol,
         lot_size: LotSize,
+        fee_subsidy: Option<FeeSubsidy>,
+        min_taker_balance: Option<Amount>,
+        refund_timelock: Option<u32>,
+        tier: OfferTier,
     ) -> Result<()> {
+        // Offers posted manually take precedence over quoted ones, which are always public
+        if tier == OfferTier::Public {
+            self.quoting_actor
+                .send(quoting::StopQuoting(contract_symbol))
+                .await?;
+        }
+
         self.cfd_actor
             .send(cfd::OfferParams {
                 price_long,
@@ -390,22 +556,57 @@ where
                 funding_rate_long,
                 funding_rate_short,
                 opening_fee,
-                leverage_choices,
+                leverage_choices_long,
+                leverage_choices_short,
                 contract_symbol,
                 lot_size,
+                fee_subsidy,
+                min_taker_balance,
+                refund_timelock,
+                tier,
             })
             .await??;
 
         Ok(())
     }
 
+    /// Derive the prices of the offers of `params.terms.contract_symbol`
+    /// from the price feed from now on, until offers are posted manually.
+    pub async fn start_quoting(&self, params: quoting::QuotingParams) -> Result<()> {
+        self.quoting_actor
+            .send(quoting::StartQuoting(params))
+            .await??;
+
+        Ok(())
+    }
+
+    pub async fn stop_quoting(&self, contract_symbol: ContractSymbol) -> Result<()> {
+        self.quoting_actor
+            .send(quoting::StopQuoting(contract_symbol))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Configure the exposure limits which are applied to new offers.
+    pub async fn set_exposure_limits(&self, limits: ExposureLimits) -> Result<()> {
+        self.cfd_actor.send(cfd::SetExposureLimits(limits)).await?;
+        Ok(())
+    }
+
     pub async fn accept_order(&self, order_id: OrderId) -> Result<()> {
         self.cfd_actor.send(cfd::AcceptOrder { order_id }).await??;
         Ok(())
     }
 
-    pub async fn reject_order(&self, order_id: OrderId) -> Result<()> {
-        self.cfd_actor.send(cfd::RejectOrder { order_id }).await??;
+    pub async fn reject_order(
+        &self,
+        order_id: OrderId,
+        reason: Option<RejectReason>,
+    ) -> Result<()> {
+        self.cfd_actor
+            .send(cfd::RejectOrder { order_id, reason })
+            .await??;
         Ok(())
     }
 
@@ -423,7 +624,99 @@ where
         Ok(())
     }
 
+    /// Load the archived CFDs, which are not part of the CFD feed.
+    pub async fn archived_cfds(&self) -> Result<Vec<projection::Cfd>> {
+        let cfds = self
+            .projection_actor
+            .send(projection::GetArchivedCfds)
+            .await?;
+
+        Ok(cfds)
+    }
+
+    /// Rebuild the projection from the event store in the background.
+    ///
+    /// The CFD feed keeps serving the current state until the rebuild is complete.
+    pub async fn rebuild_projection(&self) -> Result<()> {
+        self.projection_actor.send(projection::Rebuild).await??;
+
+        Ok(())
+    }
+
+    /// Load all closed CFDs, including the archived ones, as trades.
+    pub async fn trade_history(
+        &self,
+        fiat_rate_provider: Option<&fiat_rates::Provider>,
+    ) -> Result<Vec<trade_history::Trade>> {
+        trade_history::load(&self.db, fiat_rate_provider).await
+    }
+
+    /// The statistics of the market of `contract_symbol` over the `window` up to now.
+    pub async fn market_statistics(
+        &self,
+        contract_symbol: ContractSymbol,
+        window: time::Duration,
+    ) -> Result<analytics::Statistics> {
+        analytics::statistics(&self.db, contract_symbol, window).await
+    }
+
+    /// Load the lineage of a CFD, from the offer to its settlement.
+    pub async fn trace(
+        &self,
+        order_id: OrderId,
+        block_explorer: &BlockExplorer,
+    ) -> Result<Option<trace::Trace>> {
+        trace::load(&self.db, block_explorer, order_id).await
+    }
+
+    /// Annotate the period from `starts_at` to `ends_at`, e.g. with an
+    /// outage which affected the CFDs open at the time.
+    pub async fn annotate(
+        &self,
+        text: String,
+        starts_at: Timestamp,
+        ends_at: Timestamp,
+    ) -> Result<Annotation> {
+        let annotation = self.db.insert_annotation(text, starts_at, ends_at).await?;
+
+        tracing::info!(id = annotation.id, text = %annotation.text, "Added annotation");
+
+        Ok(annotation)
+    }
+
+    /// Delete the annotation with `id`, returning whether there was one.
+    pub async fn delete_annotation(&self, id: i64) -> Result<bool> {
+        self.db.delete_annotation(id).await
+    }
+
+    pub async fn annotations(&self) -> Result<Vec<Annotation>> {
+        self.db.load_annotations().await
+    }
+
+    /// Stop accepting connections from takers, keeping the established ones.
+    ///
+    /// The returned future does not borrow the actor system, so that it can be awaited on
+    /// shutdown. If another instance listens on the same port, it accepts all new connections
+    /// from then on.
+    pub fn stop_listening(&self) -> impl Future<Output = Result<()>> + Send + 'static {
+        let endpoint = self.endpoint_actor.clone();
+
+        async move {
+            endpoint.send(xtra_libp2p::StopListening).await?;
+
+            Ok(())
+        }
+    }
+
     pub async fn commit(&self, order_id: OrderId) -> Result<()> {
+        let commit_txid = self
+            .executor
+            .query(order_id, |cfd| {
+                cfd.commit_txid().context("Cannot commit without a DLC")
+            })
+            .await?;
+        monitor::ensure_not_broadcast(&self.db, commit_txid).await?;
+
         self.executor
             .execute(order_id, |cfd| cfd.manual_commit_to_blockchain())
             .await?;
@@ -431,17 +724,222 @@ where
         Ok(())
     }
 
+    /// Forget everything we know about the taker with `identity`
+    /// except for the financial record of past CFDs.
+    ///
+    /// Refused while there are still open CFDs with the taker.
+    pub async fn purge_counterparty(&self, identity: Identity) -> Result<u64> {
+        let n_purged = self.db.purge_counterparty(identity).await?;
+
+        tracing::info!(taker_id = %identity, n_purged, "Purged data about counterparty");
+
+        Ok(n_purged)
+    }
+
+    /// Refuse connections from and to the taker with `peer_id`.
+    ///
+    /// An existing connection is dropped immediately. The peer stays
+    /// blocked across restarts.
+    pub async fn block_peer(&self, peer_id: PeerId) -> Result<()> {
+        self.db.insert_blocked_peer(peer_id.into()).await?;
+        self.endpoint_actor
+            .send(xtra_libp2p::BlockPeer(peer_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Watch the `cfds` for takers which waste our resources and
+    /// penalize them according to `policy`.
+    ///
+    /// Every taker is penalized at most once, so a taker which was
+    /// unblocked manually is not blocked again for the same signals.
+    pub fn detect_abuse(
+        &self,
+        mut cfds: watch::Receiver<Option<Vec<projection::Cfd>>>,
+        policy: abuse::Policy,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let db = self.db.clone();
+        let endpoint = self.endpoint_actor.clone();
+
+        async move {
+            let mut penalized = HashSet::new();
+
+            while cfds.changed().await.is_ok() {
+                let signals = match cfds.borrow().as_deref() {
+                    Some(cfds) => abuse::signals(cfds, Timestamp::now()),
+                    None => continue,
+                };
+
+                for (peer_id, signals) in signals {
+                    let violations = signals.violations(&policy);
+                    if violations.is_empty() || !penalized.insert(peer_id) {
+                        continue;
+                    }
+
+                    let violations = violations.join(", ");
+                    tracing::warn!(
+                        %peer_id,
+                        ?signals,
+                        penalty = ?policy.penalty,
+                        "Taker shows signs of abuse: {violations}"
+                    );
+
+                    if policy.penalty == abuse::Penalty::Block {
+                        let result = async {
+                            db.insert_blocked_peer(peer_id.into()).await?;
+                            endpoint.send(xtra_libp2p::BlockPeer(peer_id)).await?;
+
+                            anyhow::Ok(())
+                        };
+
+                        if let Err(e) = result.await {
+                            tracing::error!(%peer_id, "Failed to block abusive taker: {e:#}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Allow the taker with `peer_id` to connect again.
+    ///
+    /// Peers blocked via `blocked_peers.toml` are blocked again on the
+    /// next restart unless they are removed from the file.
+    pub async fn unblock_peer(&self, peer_id: PeerId) -> Result<()> {
+        self.db.delete_blocked_peer(peer_id.into()).await?;
+        self.endpoint_actor
+            .send(xtra_libp2p::UnblockPeer(peer_id))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn blocked_peers(&self) -> Result<HashSet<PeerId>> {
+        let blocked_peers = self
+            .endpoint_actor
+            .send(xtra_libp2p::GetBlockedPeers)
+            .await?;
+
+        Ok(blocked_peers)
+    }
+
+    /// The revoked commit transactions of takers which were published,
+    /// but not punished yet.
+    pub async fn pending_punishments(&self) -> Result<Vec<watchtower::tower::PendingPunishment>> {
+        self.watchtower_actor
+            .send(watchtower::tower::GetPendingPunishments)
+            .await?
+    }
+
+    /// Punish the revoked commit transaction with `revoked_commit_txid`
+    /// right away.
+    pub async fn confirm_punishment(&self, revoked_commit_txid: Txid) -> Result<()> {
+        self.watchtower_actor
+            .send(watchtower::tower::ConfirmPunishment {
+                revoked_commit_txid,
+            })
+            .await?
+    }
+
+    /// Show the offers of `tier` to the taker with `peer_id`.
+    ///
+    /// The tier is kept across restarts. Assigning the public tier
+    /// removes the taker from its previous tier.
+    pub async fn set_offer_tier(&self, peer_id: PeerId, tier: OfferTier) -> Result<()> {
+        if tier == OfferTier::Public {
+            self.db.delete_offer_tier(peer_id.into()).await?;
+        } else {
+            self.db.insert_offer_tier(peer_id.into(), tier).await?;
+        }
+
+        self.offer_actor
+            .send(offer::maker::SetTier { peer_id, tier })
+            .await?;
+
+        Ok(())
+    }
+
+    /// The takers which are not shown the public offers.
+    pub async fn offer_tiers(&self) -> Result<Vec<(PeerId, OfferTier)>> {
+        let tiers = self.db.load_offer_tiers().await?;
+
+        Ok(tiers
+            .into_iter()
+            .map(|(peer_id, tier)| (peer_id.inner(), tier))
+            .collect())
+    }
+
+    /// Negotiate the restricted `protocol` with the taker with `peer_id`.
+    ///
+    /// If the protocol was not restricted so far, it is only negotiated
+    /// with allowed takers from now on. The allowlist is kept across
+    /// restarts.
+    pub async fn allow_protocol(&self, protocol: String, peer_id: PeerId) -> Result<()> {
+        self.endpoint_actor
+            .send(xtra_libp2p::AllowProtocol {
+                protocol: protocol.clone(),
+                peer_id,
+            })
+            .await??;
+        self.db
+            .insert_allowed_peer(&protocol, peer_id.into())
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn disallow_protocol(&self, protocol: String, peer_id: PeerId) -> Result<()> {
+        self.endpoint_actor
+            .send(xtra_libp2p::DisallowProtocol {
+                protocol: protocol.clone(),
+                peer_id,
+            })
+            .await??;
+        self.db
+            .delete_allowed_peer(&protocol, peer_id.into())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Negotiate the restricted `protocol` with all takers.
+    ///
+    /// Protocols restricted via `--restricted-protocol` are restricted
+    /// again on the next restart unless the flag is removed.
+    pub async fn release_protocol(&self, protocol: String) -> Result<()> {
+        self.endpoint_actor
+            .send(xtra_libp2p::LiftProtocolRestriction(protocol.clone()))
+            .await??;
+        self.db.delete_restricted_protocol(&protocol).await?;
+
+        Ok(())
+    }
+
+    pub async fn restricted_protocols(&self) -> Result<HashMap<String, HashSet<PeerId>>> {
+        let restricted_protocols = self
+            .endpoint_actor
+            .send(xtra_libp2p::GetRestrictedProtocols)
+            .await?;
+
+        Ok(restricted_protocols)
+    }
+
     pub async fn withdraw(
         &self,
         amount: Option<Amount>,
         address: bitcoin::Address,
         fee: f32,
+        utxos: Vec<OutPoint>,
+        exclude: Vec<OutPoint>,
     ) -> Result<Txid> {
         self.wallet_actor
             .send(wallet::Withdraw {
                 amount,
                 address,
                 fee: Some(bdk::FeeRate::from_sat_per_vb(fee)),
+                utxos,
+                exclude,
             })
             .await?
     }
@@ -451,6 +949,31 @@ where
         Ok(())
     }
 
+    pub async fn list_utxos(&self) -> Result<Vec<wallet::Utxo>> {
+        self.wallet_actor.send(wallet::ListUtxos).await?
+    }
+
+    pub async fn freeze_utxos(&self, outpoints: Vec<bitcoin::OutPoint>) -> Result<()> {
+        self.wallet_actor
+            .send(wallet::FreezeUtxos {
+                outpoints: outpoints.clone(),
+            })
+            .await??;
+
+        self.db.insert_frozen_utxos(&outpoints).await?;
+
+        Ok(())
+    }
+
+    pub async fn unfreeze_utxos(&self, outpoints: Vec<bitcoin::OutPoint>) -> Result<()> {
+        self.db.delete_frozen_utxos(&outpoints).await?;
+
+        self.wallet_actor
+            .send(wallet::UnfreezeUtxos { outpoints })
+            .await?;
+        Ok(())
+    }
+
     pub async fn update_rollover_configuration(&self, is_accepting_rollovers: bool) -> Result<()> {
         self.rollover_actor_deprecated
             .send(rollover::deprecated::maker::UpdateConfiguration::new(
@@ -1,23 +1,32 @@
+use crate::exposure;
+use crate::exposure::ExposureLimits;
 use crate::metrics::time_to_first_position;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
+use bdk::bitcoin::Amount;
 use daemon::order;
+use daemon::position_metrics;
 use daemon::projection;
 use model::ContractSymbol;
 use model::Contracts;
+use model::FeeSubsidy;
 use model::FundingRate;
 use model::Identity;
 use model::Leverage;
 use model::LotSize;
+use model::OfferPair;
+use model::OfferTier;
 use model::OpeningFee;
 use model::OrderId;
 use model::Position;
 use model::Price;
+use model::RejectReason;
 use model::Timestamp;
 use model::TxFeeRate;
 use nonempty::NonEmpty;
+use rust_decimal::Decimal;
 use std::collections::HashMap;
 use time::Duration;
 use time::OffsetDateTime;
@@ -40,6 +49,7 @@ pub struct AcceptOrder {
 #[derive(Clone, Copy)]
 pub struct RejectOrder {
     pub order_id: OrderId,
+    pub reason: Option<RejectReason>,
 }
 
 #[derive(Clone, Copy)]
@@ -65,7 +75,11 @@ pub struct TakerDisconnected {
 #[derive(Clone, Copy)]
 pub struct GetRolloverParams(ContractSymbol);
 
-#[derive(Clone, Debug)]
+/// Configure the exposure limits which are applied to new offers.
+#[derive(Clone)]
+pub struct SetExposureLimits(pub ExposureLimits);
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct OfferParams {
     pub price_long: Option<Price>,
     pub price_short: Option<Price>,
@@ -75,13 +89,37 @@ pub struct OfferParams {
     pub funding_rate_long: FundingRate,
     pub funding_rate_short: FundingRate,
     pub opening_fee: OpeningFee,
-    pub leverage_choices: Vec<Leverage>,
+    /// The leverages the taker can choose from when taking the maker's long offer
+    pub leverage_choices_long: Vec<Leverage>,
+    /// The leverages the taker can choose from when taking the maker's short offer
+    pub leverage_choices_short: Vec<Leverage>,
     pub contract_symbol: ContractSymbol,
     pub lot_size: LotSize,
+    pub fee_subsidy: Option<FeeSubsidy>,
+    pub min_taker_balance: Option<Amount>,
+    /// The refund timelock in blocks, defaults to one and a half settlement intervals
+    pub refund_timelock: Option<u32>,
+    /// The takers the offers are shown to
+    pub tier: OfferTier,
 }
 
 impl OfferParams {
-    fn into_offers(self, settlement_interval: Duration) -> Vec<model::Offer> {
+    /// Whether the offers differ in anything but their prices.
+    ///
+    /// Withdrawing or adding an offer for a position counts as a change of terms.
+    fn has_same_terms(&self, other: &OfferParams) -> bool {
+        let without_prices = |params: &OfferParams| OfferParams {
+            price_long: None,
+            price_short: None,
+            ..params.clone()
+        };
+
+        self.price_long.is_some() == other.price_long.is_some()
+            && self.price_short.is_some() == other.price_short.is_some()
+            && without_prices(self) == without_prices(other)
+    }
+
+    fn into_offer_pair(self, settlement_interval: Duration) -> OfferPair {
         let Self {
             price_long,
             price_short,
@@ -91,50 +129,116 @@ impl OfferParams {
             funding_rate_long,
             funding_rate_short,
             opening_fee,
-            leverage_choices,
+            leverage_choices_long,
+            leverage_choices_short,
             contract_symbol,
             lot_size,
+            fee_subsidy,
+            min_taker_balance,
+            refund_timelock,
+            tier: _,
         } = self;
 
-        let mut offers = Vec::new();
-
-        if let Some(price_long) = price_long {
-            let long = model::Offer::new(
+        let long = price_long.map(|price_long| {
+            model::Offer::new(
                 Position::Long,
                 price_long,
                 min_quantity,
                 max_quantity,
                 settlement_interval,
+                refund_timelock,
                 tx_fee_rate,
                 funding_rate_long,
                 opening_fee,
-                leverage_choices.clone(),
+                leverage_choices_long,
                 contract_symbol,
                 lot_size,
-            );
+                fee_subsidy,
+                min_taker_balance,
+            )
+        });
 
-            offers.push(long);
-        }
-
-        if let Some(price_short) = price_short {
-            let short = model::Offer::new(
+        let short = price_short.map(|price_short| {
+            model::Offer::new(
                 Position::Short,
                 price_short,
                 min_quantity,
                 max_quantity,
                 settlement_interval,
+                refund_timelock,
                 tx_fee_rate,
                 funding_rate_short,
                 opening_fee,
-                leverage_choices,
+                leverage_choices_short,
                 contract_symbol,
                 lot_size,
-            );
+                fee_subsidy,
+                min_taker_balance,
+            )
+        });
+
+        OfferPair {
+            contract_symbol,
+            long,
+            short,
+        }
+    }
+}
 
-            offers.push(short);
+/// Settings to reduce the churn caused by republishing offers whose
+/// price barely moved.
+///
+/// Offers are republished right away if any of their terms other than
+/// the prices change, or if the published offers are older than
+/// `max_age`. Otherwise, they are only republished if one of the prices
+/// moved by at least `price_threshold_percent` and the published offers
+/// are at least `min_republish_interval` old.
+#[derive(Debug, Clone, Copy)]
+pub struct OfferHysteresis {
+    pub price_threshold_percent: Decimal,
+    pub min_republish_interval: Duration,
+    pub max_age: Duration,
+}
+
+impl OfferHysteresis {
+    /// Republish offers on every update.
+    pub const DISABLED: Self = Self {
+        price_threshold_percent: Decimal::ZERO,
+        min_republish_interval: Duration::ZERO,
+        max_age: Duration::ZERO,
+    };
+
+    fn should_republish(
+        &self,
+        (published, published_at): (&OfferParams, OffsetDateTime),
+        new: &OfferParams,
+        now: OffsetDateTime,
+    ) -> bool {
+        let age = now - published_at;
+
+        if !published.has_same_terms(new) || age >= self.max_age {
+            return true;
         }
 
-        offers
+        if age < self.min_republish_interval {
+            return false;
+        }
+
+        self.price_moved(published.price_long, new.price_long)
+            || self.price_moved(published.price_short, new.price_short)
+    }
+
+    fn price_moved(&self, published: Option<Price>, new: Option<Price>) -> bool {
+        match (published, new) {
+            (Some(published), Some(new)) => {
+                let published = published.into_decimal();
+                let change =
+                    (new.into_decimal() - published).abs() / published * Decimal::ONE_HUNDRED;
+
+                change >= self.price_threshold_percent
+            }
+            _ => false,
+        }
     }
 }
 
@@ -161,6 +265,12 @@ pub struct Actor {
     settlement_interval: Duration,
     projection: xtra::Address<projection::Actor>,
     rollover_params: RolloverParams,
+    offer_hysteresis: OfferHysteresis,
+    /// The last published offer parameters per contract symbol and tier and when they were
+    /// published
+    published_offers: HashMap<(ContractSymbol, OfferTier), (OfferParams, OffsetDateTime)>,
+    exposure_limits: ExposureLimits,
+    position_metrics: xtra::Address<position_metrics::Actor>,
     time_to_first_position: xtra::Address<time_to_first_position::Actor>,
     collab_settlement: xtra::Address<daemon::collab_settlement::maker::Actor>,
     collab_settlement_deprecated:
@@ -174,7 +284,9 @@ pub struct Actor {
 impl Actor {
     pub fn new(
         settlement_interval: Duration,
+        offer_hysteresis: OfferHysteresis,
         projection: xtra::Address<projection::Actor>,
+        position_metrics: xtra::Address<position_metrics::Actor>,
         time_to_first_position: xtra::Address<time_to_first_position::Actor>,
         (collab_settlement, collab_settlement_deprecated): (
             xtra::Address<daemon::collab_settlement::maker::Actor>,
@@ -193,6 +305,10 @@ impl Actor {
             settlement_interval,
             projection,
             rollover_params: RolloverParams::default(),
+            offer_hysteresis,
+            published_offers: HashMap::new(),
+            exposure_limits: ExposureLimits::default(),
+            position_metrics,
             time_to_first_position,
             collab_settlement,
             collab_settlement_deprecated,
@@ -218,6 +334,23 @@ impl Actor {
             .insert(contract_symbol, (funding_rates, expiry));
         self.rollover_params.tx_fee_rate = tx_fee_rate;
     }
+
+    /// Withhold the offers which would exceed the exposure limit of
+    /// their contract symbol.
+    async fn limit_exposure(&self, params: OfferParams) -> Result<OfferParams> {
+        let limit = match self.exposure_limits.get(params.contract_symbol) {
+            Some(limit) => limit,
+            None => return Ok(params),
+        };
+
+        let exposure = self
+            .position_metrics
+            .send(position_metrics::GetExposure(params.contract_symbol))
+            .await
+            .context("Position metrics actor disconnected")?;
+
+        Ok(exposure::limit_offers(params, exposure, limit))
+    }
 }
 
 impl Actor {
@@ -264,11 +397,11 @@ impl Actor {
     }
 
     async fn handle_reject_order(&mut self, msg: RejectOrder) -> Result<()> {
-        let RejectOrder { order_id } = msg;
+        let RejectOrder { order_id, reason } = msg;
 
         let res = self
             .order
-            .send(order::maker::Decision::Reject(order_id))
+            .send(order::maker::Decision::Reject(order_id, reason))
             .await
             .map_err(anyhow::Error::new);
 
@@ -369,37 +502,79 @@ impl Actor {
 
 #[xtra_productivity]
 impl Actor {
+    async fn handle_probe(&mut self, _: xtras::Probe) {}
+
     async fn handle_offer_params(&mut self, offer_params: OfferParams) -> Result<()> {
-        // 1. Update internal state for rollovers
-        self.udpate_rollover_params(
-            offer_params.contract_symbol,
-            offer_params.funding_rate_long,
-            offer_params.funding_rate_short,
-            offer_params.tx_fee_rate,
-        );
+        if let Some(refund_timelock) = offer_params.refund_timelock {
+            model::Offer::validate_refund_timelock(refund_timelock, self.settlement_interval)?;
+        }
 
-        let offers = offer_params.into_offers(self.settlement_interval);
+        let tier = offer_params.tier;
 
-        // 2. Notify UI via feed
-        self.projection
-            .send(projection::Update(offers.clone()))
-            .await?;
+        // 1. Update internal state for rollovers, which are based on the public offers
+        if tier == OfferTier::Public {
+            self.udpate_rollover_params(
+                offer_params.contract_symbol,
+                offer_params.funding_rate_long,
+                offer_params.funding_rate_short,
+                offer_params.tx_fee_rate,
+            );
+        }
+
+        let offer_params = self.limit_exposure(offer_params).await?;
+
+        let now = OffsetDateTime::now_utc();
+        let contract_symbol = offer_params.contract_symbol;
+        let key = (contract_symbol, tier);
 
-        // 3. Broadcast to all peers via offer actor
+        if let Some((published, published_at)) = self.published_offers.get(&key) {
+            if !self.offer_hysteresis.should_republish(
+                (published, *published_at),
+                &offer_params,
+                now,
+            ) {
+                tracing::trace!(%contract_symbol, "Price did not move enough to republish offers");
+                return Ok(());
+            }
+        }
+
+        self.published_offers
+            .insert(key, (offer_params.clone(), now));
+
+        let offers = offer_params.into_offer_pair(self.settlement_interval);
+
+        // 2. Broadcast to the peers of the tier via offer actor
         if let Err(e) = self
             .offer
-            .send_async_safe(offer::maker::NewOffers::new(offers.clone()))
+            .send_async_safe(offer::maker::NewOffers::for_tier(offers.clone(), tier))
             .await
         {
             tracing::warn!("{e:#}");
         }
 
+        // Only the public offers are shown in the UI and offered via the deprecated protocol
+        if tier != OfferTier::Public {
+            return Ok(());
+        }
+
+        // 3. Notify UI via feed
+        self.projection
+            .send(projection::Update(vec![offers.clone()]))
+            .await?;
+
         // 4. Broadcast to all peers via deprecated offer actor
         {
-            // Takers on the deprecated version only care (and know how to handle) BTCUSD offers
+            // Takers on the deprecated version only care (and know how to handle) BTCUSD offers.
+            // They also assume the default refund timelock, because the deprecated protocol cannot
+            // carry it.
             let btcusd_offers = offers
+                .into_offers()
                 .into_iter()
                 .filter(|offer| offer.contract_symbol == ContractSymbol::BtcUsd)
+                .filter(|offer| {
+                    offer.refund_timelock
+                        == model::Offer::default_refund_timelock(offer.settlement_interval)
+                })
                 .collect::<Vec<_>>();
 
             if let Some(btcusd_offers) = NonEmpty::from_vec(btcusd_offers) {
@@ -416,6 +591,10 @@ impl Actor {
         Ok(())
     }
 
+    fn handle(&mut self, msg: SetExposureLimits) {
+        self.exposure_limits = msg.0;
+    }
+
     async fn handle(&mut self, msg: TakerConnected) -> Result<()> {
         self.handle_taker_connected(msg.id).await
     }
@@ -0,0 +1,59 @@
+//! Limits on the exposure of the maker.
+//!
+//! The net exposure of the maker in a contract symbol is the quantity of
+//! its long CFDs minus the quantity of its short CFDs, counting all CFDs
+//! which are open or being set up. Once the net exposure reaches the
+//! configured limit in one direction, the offer which would increase it
+//! further is withheld until the exposure has come down again.
+
+use crate::cfd::OfferParams;
+use daemon::position_metrics::Exposure;
+use model::ContractSymbol;
+use model::Contracts;
+use std::collections::HashMap;
+
+/// The maximum net exposure of the maker per contract symbol.
+///
+/// Contract symbols without a limit are not limited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExposureLimits(HashMap<ContractSymbol, Contracts>);
+
+impl ExposureLimits {
+    pub fn new(limits: HashMap<ContractSymbol, Contracts>) -> Self {
+        Self(limits)
+    }
+
+    pub fn get(&self, contract_symbol: ContractSymbol) -> Option<Contracts> {
+        self.0.get(&contract_symbol).copied()
+    }
+}
+
+/// Withhold the offers of `params` which would increase `exposure`
+/// beyond `limit`.
+pub fn limit_offers(params: OfferParams, exposure: Exposure, limit: Contracts) -> OfferParams {
+    let net = exposure.net();
+    let limit = limit.into_decimal();
+    let contract_symbol = params.contract_symbol;
+
+    let price_long = match params.price_long {
+        Some(_) if net >= limit => {
+            tracing::debug!(%contract_symbol, %net, %limit, "Withholding long offer");
+            None
+        }
+        price_long => price_long,
+    };
+
+    let price_short = match params.price_short {
+        Some(_) if -net >= limit => {
+            tracing::debug!(%contract_symbol, %net, %limit, "Withholding short offer");
+            None
+        }
+        price_short => price_short,
+    };
+
+    OfferParams {
+        price_long,
+        price_short,
+        ..params
+    }
+}
@@ -1,21 +1,43 @@
+use anyhow::ensure;
+use anyhow::Result;
 use bdk::bitcoin::util::bip32::ExtendedPrivKey;
+use clap::Args;
 use clap::Parser;
 use daemon::bdk;
+use daemon::watchtower::tower::PunishPolicy;
+use rust_decimal::Decimal;
+use shared_bin::cli::AddressReuse;
+use shared_bin::cli::Connection;
+use shared_bin::cli::DatabaseBackup;
+use shared_bin::cli::EventDelivery;
+use shared_bin::cli::Explorer;
+use shared_bin::cli::FeeEstimation;
+use shared_bin::cli::FiatDisplay;
 use shared_bin::cli::Network;
+use shared_bin::cli::Oracle;
+use shared_bin::cli::OracleKeyRotation;
+use shared_bin::cli::PriceFeed;
 use shared_bin::logger::LevelFilter;
+use shared_bin::logger::LogFormat;
 use shared_bin::logger::LOCAL_COLLECTOR_ENDPOINT;
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
+use xtra_libp2p::RateLimits;
 
 pub use actor_system::ActorSystem;
 pub use blocked_peers::load_blocked_peers;
 
+pub mod abuse;
 mod actor_system;
 mod blocked_peers;
 pub mod cfd;
+pub mod exposure;
 mod metrics;
+pub mod quoting;
 pub mod routes;
+pub mod treasury;
 
 #[derive(Debug)]
 pub struct Password(String);
@@ -46,6 +68,20 @@ pub struct Opts {
     #[clap(long, default_value = "10000")]
     pub p2p_port: u16,
 
+    /// The port to additionally listen on for libp2p connections over WebSocket.
+    ///
+    /// Allows takers behind restrictive firewalls to connect. If not specified, the maker only
+    /// listens for plain TCP connections.
+    #[clap(long)]
+    pub p2p_websocket_port: Option<u16>,
+
+    /// How long to keep serving established connections on shutdown, in seconds, after no longer
+    /// accepting new ones.
+    ///
+    /// Behind `maker-front`, new connections wait for the next instance of the maker meanwhile.
+    #[clap(long, default_value = "5")]
+    pub shutdown_drain_secs: u64,
+
     /// The IP address to listen on for the HTTP API.
     #[clap(long, default_value = "127.0.0.1:8001")]
     pub http_address: SocketAddr,
@@ -54,12 +90,19 @@ pub struct Opts {
     #[clap(long)]
     pub data_dir: Option<PathBuf>,
 
-    /// If enabled logs will be in json format
+    /// Format of the logs, one of `text` or `json`.
+    ///
+    /// JSON logs carry the `order_id`, `offer_id` and `peer_id` of the protocol a log event
+    /// belongs to, which allows to follow the lifecycle of a single CFD in a log aggregator.
+    #[clap(long, default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// Same as `--log-format=json`.
     #[clap(short, long)]
     pub json: bool,
 
     /// If enabled, logs in json format will contain a list of all ancestor spans of log events.
-    /// This **only** has an effect when `json` is also enabled.
+    /// This **only** has an effect when the log format is `json`.
     #[clap(long)]
     pub json_span_list: bool,
 
@@ -118,4 +161,206 @@ pub struct Opts {
     /// If enabled, the log will be printed to {service_name}.log in the data dir
     #[clap(long)]
     pub log_to_file: bool,
+
+    /// If enabled, the daemon starts even if the startup diagnostics detect a problem.
+    #[clap(long)]
+    pub skip_diagnostics: bool,
+
+    /// If enabled, all frames exchanged with peers are recorded to the `recordings` folder in
+    /// the data dir. Secret keys are redacted.
+    #[clap(long)]
+    pub record_protocols: bool,
+
+    /// Warn if a consumer of the UI feeds has not observed an update for this many seconds.
+    #[clap(long, default_value = "60")]
+    pub feed_stale_warning_secs: u64,
+
+    /// Raise a liquidation alert if the current price is less than this many percent away from
+    /// the liquidation price of an open CFD.
+    #[clap(long, default_value = "10")]
+    pub liquidation_alert_threshold_percent: Decimal,
+
+    /// Only republish offers if one of their prices moved by at least this many percent.
+    ///
+    /// Offers are republished regardless if any of their other terms change or if they are older
+    /// than `--offer-max-age-secs`.
+    #[clap(long, default_value = "0")]
+    pub offer_price_hysteresis_percent: Decimal,
+
+    /// Wait at least this many seconds before republishing offers because their price moved.
+    #[clap(long, default_value = "0")]
+    pub offer_min_republish_interval_secs: u64,
+
+    /// Republish offers at least this often, even if their price did not move enough.
+    #[clap(long, default_value = "60")]
+    pub offer_max_age_secs: u64,
+
+    /// How often the quoting engine republishes the offers of the quoted contract symbols with
+    /// prices derived from the latest quotes.
+    #[clap(long, default_value = "10")]
+    pub quoting_interval_secs: u64,
+
+    /// Stop offering long BTCUSD positions once our long BTCUSD positions exceed our short ones
+    /// by this many contracts, and vice versa.
+    #[clap(long)]
+    pub max_btcusd_exposure: Option<u64>,
+
+    /// Stop offering long ETHUSD positions once our long ETHUSD positions exceed our short ones
+    /// by this many contracts, and vice versa.
+    #[clap(long)]
+    pub max_ethusd_exposure: Option<u64>,
+
+    /// Penalize takers which placed this many orders without any of them being filled.
+    #[clap(long)]
+    pub abuse_max_unfilled_orders: Option<u32>,
+
+    /// Penalize takers which aborted this many contract setups after we accepted their order.
+    #[clap(long)]
+    pub abuse_max_aborted_setups: Option<u32>,
+
+    /// Penalize takers which placed this many orders within a minute.
+    #[clap(long)]
+    pub abuse_max_orders_per_minute: Option<u32>,
+
+    /// How to penalize abusive takers, one of `warn` or `block`.
+    ///
+    /// Blocked takers stay blocked until they are unblocked via the API.
+    #[clap(long, default_value = "warn")]
+    pub abuse_penalty: abuse::Penalty,
+
+    /// Reject orders of takers which aborted this many contract setups.
+    #[clap(long)]
+    pub taker_max_aborted_setups: Option<u32>,
+
+    /// Reject orders of takers which failed this many rollovers.
+    #[clap(long)]
+    pub taker_max_failed_rollovers: Option<u32>,
+
+    /// Reject orders which would take the contracts a taker has open with us above this cap.
+    #[clap(long)]
+    pub taker_max_open_notional: Option<u64>,
+
+    #[clap(flatten)]
+    pub oracle: Oracle,
+
+    #[clap(flatten)]
+    pub oracle_key_rotation: OracleKeyRotation,
+
+    #[clap(flatten)]
+    pub fee_estimation: FeeEstimation,
+
+    #[clap(flatten)]
+    pub fiat_display: FiatDisplay,
+
+    #[clap(flatten)]
+    pub price_feed: PriceFeed,
+
+    #[clap(flatten)]
+    pub address_reuse: AddressReuse,
+
+    #[clap(flatten)]
+    pub explorer: Explorer,
+
+    #[clap(flatten)]
+    pub connection: Connection,
+
+    #[clap(flatten)]
+    pub db_backup: DatabaseBackup,
+
+    #[clap(flatten)]
+    pub event_delivery: EventDelivery,
+
+    /// Only negotiate the given protocol with takers which are explicitly allowed to use it.
+    ///
+    /// Can be passed multiple times. Takers are allowed to use a restricted protocol via the
+    /// protocol allowlist API.
+    #[clap(long = "restricted-protocol")]
+    pub restricted_protocols: Vec<String>,
+
+    #[clap(flatten)]
+    pub inbound_rate_limits: InboundRateLimits,
+
+    #[clap(flatten)]
+    pub punishment: Punishment,
+}
+
+/// When to punish takers which published a revoked commit transaction.
+#[derive(Args, Clone, Copy, Debug)]
+pub struct Punishment {
+    /// When to publish the punish transaction, one of `immediate`, `delayed` or `manual`.
+    ///
+    /// Delayed punishments are published after `--punish-delay-secs`. Manual punishments are
+    /// published once confirmed via the API, or after `--punish-delay-secs` regardless. Any
+    /// punishment is published right away once the revoked commit transaction is close to allowing
+    /// the taker to publish a CET.
+    #[clap(long, default_value = "immediate")]
+    punish_policy: PunishMode,
+
+    /// How long to wait before publishing a delayed or manual punishment, in seconds.
+    #[clap(long, default_value = "1800")]
+    punish_delay_secs: u64,
+}
+
+impl Punishment {
+    pub fn policy(&self) -> PunishPolicy {
+        let delay = Duration::from_secs(self.punish_delay_secs);
+
+        match self.punish_policy {
+            PunishMode::Immediate => PunishPolicy::Immediate,
+            PunishMode::Delayed => PunishPolicy::Delayed { delay },
+            PunishMode::Manual => PunishPolicy::Manual { deadline: delay },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::EnumString)]
+#[strum(serialize_all = "lowercase")]
+enum PunishMode {
+    Immediate,
+    Delayed,
+    Manual,
+}
+
+/// Limits on the substreams takers may open to us.
+///
+/// Substreams exceeding any of the limits are rejected, protecting us against takers which open
+/// substreams without bounds.
+#[derive(Args, Clone, Copy, Debug)]
+pub struct InboundRateLimits {
+    /// How many substreams a single taker may open per minute.
+    #[clap(long, default_value = "120")]
+    max_substreams_per_taker_per_minute: usize,
+
+    /// How many substreams all takers together may open per minute.
+    #[clap(long, default_value = "10000")]
+    max_substreams_per_minute: usize,
+
+    /// How many substreams a single taker may have open at the same time.
+    #[clap(long, default_value = "20")]
+    max_concurrent_substreams_per_taker: usize,
+
+    /// How many substreams all takers together may have open at the same time.
+    #[clap(long, default_value = "2000")]
+    max_concurrent_substreams: usize,
+}
+
+impl InboundRateLimits {
+    pub fn rate_limits(&self) -> Result<RateLimits> {
+        let rate_limits = RateLimits {
+            per_peer_substreams_per_minute: self.max_substreams_per_taker_per_minute,
+            global_substreams_per_minute: self.max_substreams_per_minute,
+            per_peer_concurrent_substreams: self.max_concurrent_substreams_per_taker,
+            global_concurrent_substreams: self.max_concurrent_substreams,
+        };
+
+        ensure!(
+            rate_limits.per_peer_substreams_per_minute > 0
+                && rate_limits.global_substreams_per_minute > 0
+                && rate_limits.per_peer_concurrent_substreams > 0
+                && rate_limits.global_concurrent_substreams > 0,
+            "Substream rate limits must not be zero"
+        );
+
+        Ok(rate_limits)
+    }
 }
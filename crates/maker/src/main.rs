@@ -2,30 +2,52 @@ use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use clap::StructOpt;
+use daemon::analytics;
 use daemon::bdk::FeeRate;
+use daemon::db_backup;
+use daemon::electrum;
+use daemon::feed_lag;
+use daemon::fiat_rates;
+use daemon::listen_protocols::MAKER_LISTEN_PROTOCOLS;
 use daemon::monitor;
 use daemon::oracle;
+use daemon::order;
+use daemon::price_feed;
 use daemon::projection;
+use daemon::readiness;
+use daemon::readiness::Subsystem;
 use daemon::seed::RandomSeed;
 use daemon::seed::Seed;
 use daemon::wallet;
 use daemon::wallet::MAKER_WALLET_ID;
 use daemon::N_PAYOUTS;
+use maker::abuse;
+use maker::cfd::OfferHysteresis;
+use maker::exposure::ExposureLimits;
 use maker::load_blocked_peers;
 use maker::routes;
 use maker::ActorSystem;
 use maker::Opts;
-use model::olivia;
+use model::ContractSymbol;
+use model::Contracts;
 use model::Role;
 use model::SETTLEMENT_INTERVAL;
 use rocket_cookie_auth::users::Users;
 use shared_bin::catchers::default_catchers;
 use shared_bin::cli::Withdraw;
+use shared_bin::dashboard::dashboard_routes;
+use shared_bin::diagnostics::Diagnostics;
 use shared_bin::fairings;
 use shared_bin::logger;
+use sqlite_db::rehydration;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::future::Future;
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio_extras::Tasks;
-use xtras::supervisor::always_restart;
+use xtra::Actor as _;
+use xtra_libp2p::recorder::Recorder;
 use xtras::supervisor::Supervisor;
 
 #[rocket::main]
@@ -43,9 +65,15 @@ async fn main() -> Result<()> {
         tokio::fs::create_dir_all(&data_dir).await?;
     }
 
-    let _guard = logger::init(
+    let log_format = if opts.json {
+        logger::LogFormat::Json
+    } else {
+        opts.log_format
+    };
+
+    let logging = logger::init(
         opts.log_level,
-        opts.json,
+        log_format,
         opts.json_span_list,
         opts.instrumentation,
         opts.tokio_console,
@@ -63,9 +91,29 @@ async fn main() -> Result<()> {
         "CFDs created with this release will settle after {settlement_interval_hours} hours"
     );
 
+    let p2p_port = opts.p2p_port;
+    let p2p_socket = format!("0.0.0.0:{p2p_port}").parse::<SocketAddr>().unwrap();
+
+    Diagnostics::new(&data_dir)
+        .seed_file(data_dir.join("maker_seed"))
+        .database(data_dir.join("maker.sqlite"))
+        .listen(opts.http_address)
+        .listen(p2p_socket)
+        .electrum(opts.network.electrum())
+        .oracle(opts.oracle.url(opts.network.bitcoin_network()).to_string())
+        .run()
+        .await
+        .ensure_ok(opts.skip_diagnostics)?;
+
+    let protocol_recorder = opts
+        .record_protocols
+        .then(|| Recorder::new(data_dir.join("recordings")))
+        .transpose()?;
+
     let seed = RandomSeed::initialize(&data_dir.join("maker_seed")).await?;
 
     let bitcoin_network = opts.network.bitcoin_network();
+    let block_explorer = opts.explorer.block_explorer(bitcoin_network)?;
 
     let ext_priv_key = match opts.wallet_xprv {
         Some(wallet_xprv) => {
@@ -80,16 +128,64 @@ async fn main() -> Result<()> {
 
     let mut tasks = Tasks::default();
 
+    #[cfg(unix)]
+    if let Some(log_filter) = logging.filter.clone() {
+        let path = data_dir.join(format!("{}.log-filter", opts.service_name));
+        tasks.add_fallible(logger::reload_on_sighup(log_filter, path), |e| async move {
+            tracing::error!("Stopped reloading log filter on SIGHUP: {e:#}")
+        });
+    }
+
+    let (readiness, readiness_receiver) = readiness::Tracker::new();
+
     let mut wallet_dir = data_dir.clone();
 
     wallet_dir.push(MAKER_WALLET_ID);
-    let (wallet, wallet_feed_receiver) =
-        wallet::Actor::spawn(opts.network.electrum(), ext_priv_key, wallet_dir)?;
+    let (electrum_actor, active_electrum) = electrum::Actor::new(opts.network.electrum().to_vec())?;
+    let _electrum_actor = electrum_actor.create(None).spawn(&mut tasks);
+
+    // Connecting to Electrum and migrating the database are independent
+    // of each other, so we do both at the same time
+    let spawn_wallet = {
+        let active_electrum = active_electrum.clone();
+        let address_reuse = opts.address_reuse.policy();
+        async move {
+            let wallet = tokio::task::spawn_blocking(move || {
+                wallet::Actor::spawn(active_electrum, ext_priv_key, wallet_dir, address_reuse)
+            })
+            .await??;
+
+            anyhow::Ok(wallet)
+        }
+    };
+    let connect_db = async {
+        let db =
+            sqlite_db::connect(data_dir.join("maker.sqlite"), opts.ignore_migration_errors).await?;
+
+        for order_id in db.migrate_legacy_cfds().await? {
+            tracing::warn!(
+                %order_id,
+                "Counterparty of legacy CFD cannot be reached over libp2p, commit it to close it"
+            );
+        }
+
+        anyhow::Ok(db)
+    };
+    let ((wallet, wallet_feed_receiver), db) = tokio::try_join!(spawn_wallet, connect_db)?;
+    readiness.ready(Subsystem::Database);
+
+    wallet
+        .send(wallet::RestoreFrozenUtxos {
+            outpoints: db.load_frozen_utxos().await?,
+        })
+        .await?;
 
     if let Some(Withdraw::Withdraw {
         amount,
         address,
         fee,
+        utxos,
+        exclude,
     }) = opts.network.withdraw()
     {
         wallet
@@ -97,6 +193,8 @@ async fn main() -> Result<()> {
                 amount: *amount,
                 address: address.clone(),
                 fee: fee.map(FeeRate::from_sat_per_vb),
+                utxos: utxos.clone(),
+                exclude: exclude.clone(),
             })
             .await??;
 
@@ -113,66 +211,223 @@ async fn main() -> Result<()> {
         .merge(("address", opts.http_address.ip()))
         .merge(("port", opts.http_address.port()))
         .merge(("cli_colors", false))
-        .merge(("secret_key", RandomSeed::default().seed()));
-
-    let p2p_port = opts.p2p_port;
-    let p2p_socket = format!("0.0.0.0:{p2p_port}").parse::<SocketAddr>().unwrap();
-
-    let db =
-        sqlite_db::connect(data_dir.join("maker.sqlite"), opts.ignore_migration_errors).await?;
+        .merge(("secret_key", RandomSeed::default().seed()))
+        // Shutting down is up to `shutdown_gracefully`, which drains p2p connections first
+        .merge(("shutdown.ctrlc", false))
+        .merge(("shutdown.signals", Vec::<String>::new()));
 
-    let blocked_peers = load_blocked_peers(&data_dir)
+    let mut blocked_peers = load_blocked_peers(&data_dir)
         .await
         .context("Failed to load blocked peers")?;
+    blocked_peers.extend(
+        db.load_blocked_peers()
+            .await
+            .context("Failed to load blocked peers from database")?
+            .into_iter()
+            .map(|peer_id| peer_id.inner()),
+    );
+
+    let offer_tiers = db
+        .load_offer_tiers()
+        .await
+        .context("Failed to load offer tiers")?
+        .into_iter()
+        .map(|(peer_id, tier)| (peer_id.inner(), tier))
+        .collect();
+
+    // Protocols restricted via the API are kept across restarts, unless
+    // this version no longer supports them
+    let supported_protocols = HashSet::<String>::from(MAKER_LISTEN_PROTOCOLS);
+    let mut restricted_protocols = HashMap::new();
+    for (protocol, allowed_peers) in db
+        .load_restricted_protocols()
+        .await
+        .context("Failed to load restricted protocols")?
+    {
+        if !supported_protocols.contains(&protocol) {
+            tracing::warn!(%protocol, "Ignoring restricted protocol which is no longer supported");
+            continue;
+        }
+
+        let allowed_peers = allowed_peers
+            .into_iter()
+            .map(|peer_id| peer_id.inner())
+            .collect::<HashSet<_>>();
+        restricted_protocols.insert(protocol, allowed_peers);
+    }
+    for protocol in opts.restricted_protocols {
+        restricted_protocols.entry(protocol).or_default();
+    }
 
     // Create actors
-    let endpoint_listen =
-        daemon::libp2p_utils::create_listen_tcp_multiaddr(&p2p_socket.ip(), p2p_socket.port())
-            .expect("to parse properly");
-
-    let (supervisor, price_feed) = Supervisor::with_policy(
-        {
-            let network = opts.network.bitmex_network();
-            move || xtra_bitmex_price_feed::Actor::new(network)
-        },
-        always_restart::<xtra_bitmex_price_feed::Error>(),
+    let mut endpoint_listen = vec![daemon::libp2p_utils::create_listen_tcp_multiaddr(
+        &p2p_socket.ip(),
+        p2p_socket.port(),
+    )
+    .expect("to parse properly")];
+    if let Some(port) = opts.p2p_websocket_port {
+        endpoint_listen.push(
+            daemon::libp2p_utils::create_listen_websocket_multiaddr(&p2p_socket.ip(), port)
+                .expect("to parse properly"),
+        );
+    }
+
+    let price_feed = price_feed::spawn(
+        opts.network.bitmex_network(),
+        opts.price_feed.exchanges(),
+        opts.price_feed.policy(),
+        opts.price_feed.history_window(),
+        &mut tasks,
     );
-    tasks.add(supervisor.run_log_summary());
+
+    let _quote_recorder = analytics::QuoteRecorder::new(db.clone(), price_feed.clone().into())
+        .create(None)
+        .spawn(&mut tasks);
+
+    let fiat_rate_provider = opts.fiat_display.provider();
+    let fiat_rate_feed_receiver = match fiat_rate_provider.clone() {
+        Some(provider) => {
+            let (fiat_rates_actor, feed_receiver) = fiat_rates::Actor::new(provider);
+            let _fiat_rates_actor = fiat_rates_actor.create(None).spawn(&mut tasks);
+
+            feed_receiver
+        }
+        None => tokio::sync::watch::channel(None).1,
+    };
 
     let (feed_senders, feed_receivers) = projection::feeds();
     let feed_senders = std::sync::Arc::new(feed_senders);
 
+    let _feed_lag_actor = feed_lag::Actor::new(
+        feed_receivers.freshness.feeds(),
+        Duration::from_secs(opts.feed_stale_warning_secs),
+    )
+    .create(None)
+    .spawn(&mut tasks);
+
+    tasks.add(readiness.clone().ready_when(
+        Subsystem::Wallet,
+        wallet_feed_receiver.clone(),
+        Option::is_some,
+    ));
+    tasks.add(readiness.clone().ready_when(
+        Subsystem::PriceFeed,
+        feed_receivers.quote.clone(),
+        |quotes| !quotes.is_empty(),
+    ));
+    tasks.add(readiness.clone().ready_when(
+        Subsystem::Cfds,
+        feed_receivers.cfds.clone(),
+        Option::is_some,
+    ));
+
     let (supervisor, projection_actor) = Supervisor::new({
         let db = db.clone();
+        let price_feed = price_feed.clone();
+        let block_explorer = block_explorer.clone();
+        let liquidation_alert_threshold = opts.liquidation_alert_threshold_percent;
+        let fiat_rate_feed_receiver = fiat_rate_feed_receiver.clone();
         move || {
             projection::Actor::new(
                 db.clone(),
-                bitcoin_network,
+                block_explorer.clone(),
                 price_feed.clone().into(),
                 Role::Maker,
                 feed_senders.clone(),
+                liquidation_alert_threshold,
             )
+            .with_fiat_rates(fiat_rate_feed_receiver.clone())
         }
     });
     tasks.add(supervisor.run_log_summary());
 
+    if let Some(backup) = opts.db_backup.settings()? {
+        tasks.add(db_backup::run(db.clone(), "maker", backup));
+    }
+
+    let fee_estimator = opts.fee_estimation.fee_estimator();
+    let connection = opts.connection.settings()?;
+    let taker_limits = order::maker::TakerLimits {
+        max_aborted_setups: opts.taker_max_aborted_setups,
+        max_failed_rollovers: opts.taker_max_failed_rollovers,
+        max_open_notional: opts.taker_max_open_notional.map(Contracts::new),
+    };
     let maker = ActorSystem::new(
         db.clone(),
         wallet.clone(),
-        *olivia::PUBLIC_KEY,
-        |executor| oracle::Actor::new(db.clone(), executor),
+        opts.oracle_key_rotation
+            .oracle_keys(opts.oracle.public_key(bitcoin_network))?,
+        |executor| {
+            oracle::Actor::new(db.clone(), executor).with_base_url(opts.oracle.url(bitcoin_network))
+        },
         |executor| {
-            let electrum = opts.network.electrum().to_string();
-            monitor::Actor::new(db.clone(), electrum, executor)
+            let electrum = active_electrum.clone();
+            let monitor =
+                monitor::Actor::new(db.clone(), electrum, executor, wallet.clone().into())?;
+
+            Ok(monitor
+                .with_fee_estimator(fee_estimator)
+                .with_finality_confirmations(monitor::FinalityConfirmations::for_network(
+                    bitcoin_network,
+                )))
         },
         SETTLEMENT_INTERVAL,
         N_PAYOUTS,
+        OfferHysteresis {
+            price_threshold_percent: opts.offer_price_hysteresis_percent,
+            min_republish_interval: Duration::from_secs(opts.offer_min_republish_interval_secs)
+                .try_into()?,
+            max_age: Duration::from_secs(opts.offer_max_age_secs).try_into()?,
+        },
+        (
+            price_feed.clone().into(),
+            price_feed.into(),
+            Duration::from_secs(opts.quoting_interval_secs),
+        ),
         projection_actor.clone(),
         identities,
         endpoint_listen,
+        connection,
         blocked_peers,
+        offer_tiers,
+        restricted_protocols,
+        Some(opts.inbound_rate_limits.rate_limits()?),
+        taker_limits
+            .is_enabled()
+            .then(|| (taker_limits, feed_receivers.cfds.clone())),
+        protocol_recorder,
+        opts.event_delivery.timeout()?,
+        active_electrum.clone(),
+        opts.punishment.policy(),
     )?;
 
+    tasks.add(readiness.ready_when(
+        Subsystem::Rehydration,
+        maker.rehydration_progress.clone(),
+        rehydration::Progress::is_complete,
+    ));
+
+    let exposure_limits = [
+        (ContractSymbol::BtcUsd, opts.max_btcusd_exposure),
+        (ContractSymbol::EthUsd, opts.max_ethusd_exposure),
+    ]
+    .into_iter()
+    .filter_map(|(contract_symbol, limit)| Some((contract_symbol, Contracts::new(limit?))))
+    .collect();
+    maker
+        .set_exposure_limits(ExposureLimits::new(exposure_limits))
+        .await?;
+
+    let abuse_policy = abuse::Policy {
+        max_unfilled_orders: opts.abuse_max_unfilled_orders,
+        max_aborted_setups: opts.abuse_max_aborted_setups,
+        max_orders_per_minute: opts.abuse_max_orders_per_minute,
+        penalty: opts.abuse_penalty,
+    };
+    if abuse_policy.is_enabled() {
+        tasks.add(maker.detect_abuse(feed_receivers.cfds.clone(), abuse_policy));
+    }
+
     if let Some(password) = opts.password {
         db.clone()
             .update_password(rocket_cookie_auth::user::create_password(
@@ -184,23 +439,63 @@ async fn main() -> Result<()> {
     let rocket_auth_db_connection = RocketAuthDbConnection::new(db.clone());
     let users = Users::new(Box::new(rocket_auth_db_connection));
 
-    let mission_success = rocket::custom(figment)
+    let stop_listening = maker.stop_listening();
+
+    let rocket = rocket::custom(figment)
         .manage(feed_receivers)
         .manage(wallet_feed_receiver)
+        .manage(fiat_rate_feed_receiver)
+        .manage(fiat_rate_provider)
+        .manage(readiness_receiver)
+        .manage(active_electrum)
+        .manage(connection)
         .manage(maker)
         .manage(users)
         .manage(bitcoin_network)
+        .manage(block_explorer)
+        .manage(abuse_policy)
+        .manage(logging.filter.clone())
         .mount(
             "/api",
             rocket::routes![
                 routes::maker_feed,
                 routes::put_offer_params,
                 routes::put_offer_params_for_symbol,
+                routes::put_quoting_params,
+                routes::delete_quoting_params,
                 routes::post_cfd_action,
+                routes::delete_counterparty,
+                routes::get_blocked_peers,
+                routes::put_blocked_peer,
+                routes::delete_blocked_peer,
+                routes::get_offer_tiers,
+                routes::put_offer_tier,
+                routes::get_abuse_signals,
+                routes::get_protocol_allowlist,
+                routes::put_protocol_allowlist_entry,
+                routes::delete_protocol_allowlist_entry,
+                routes::post_protocol_release,
                 routes::get_health_check,
+                routes::get_readiness,
                 routes::get_cfds,
+                routes::get_archived_cfds,
+                routes::get_trade_history,
+                routes::get_market_statistics,
+                routes::get_trace,
+                routes::get_annotations,
+                routes::post_annotation,
+                routes::delete_annotation,
+                routes::get_log_filter,
+                routes::put_log_filter,
+                routes::get_treasury_report,
                 routes::get_metrics,
                 routes::put_sync_wallet,
+                routes::get_utxos,
+                routes::put_freeze_utxos,
+                routes::put_unfreeze_utxos,
+                routes::get_punishments,
+                routes::post_confirm_punishment,
+                routes::post_rebuild_projection,
                 routes::get_version,
                 routes::change_password,
                 routes::post_login,
@@ -210,13 +505,22 @@ async fn main() -> Result<()> {
         )
         .register("/api", default_catchers())
         .mount("/", rocket::routes![routes::dist, routes::index])
+        .mount("/", dashboard_routes())
         .register("/", default_catchers())
         .attach(fairings::log_launch())
         .attach(fairings::log_requests())
         .attach(fairings::ui_browser_launch(!opts.headless))
-        .launch()
+        .ignite()
         .await?;
 
+    tasks.add(shutdown_gracefully(
+        rocket.shutdown(),
+        stop_listening,
+        Duration::from_secs(opts.shutdown_drain_secs),
+    ));
+
+    let mission_success = rocket.launch().await?;
+
     tracing::trace!(?mission_success, "Rocket has landed");
 
     db.close().await;
@@ -224,6 +528,55 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Shut down once we are asked to, after draining the established p2p connections.
+///
+/// We stop listening first, so that `maker-front` holds new connections for the next maker while
+/// the takers connected to us finish what they are doing.
+async fn shutdown_gracefully(
+    shutdown: rocket::Shutdown,
+    stop_listening: impl Future<Output = Result<()>>,
+    drain: Duration,
+) {
+    if let Err(e) = shutdown_signal().await {
+        tracing::error!("Failed to listen for shutdown signals: {e:#}");
+        return;
+    }
+
+    tracing::info!(
+        "Shutting down, draining p2p connections for {}s",
+        drain.as_secs()
+    );
+
+    if let Err(e) = stop_listening.await {
+        tracing::warn!("Failed to stop listening for p2p connections: {e:#}");
+    }
+    tokio_extras::time::sleep(drain).await;
+
+    shutdown.notify();
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() -> Result<()> {
+    use tokio::signal::unix::signal;
+    use tokio::signal::unix::SignalKind;
+
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> Result<()> {
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
+
 struct RocketAuthDbConnection {
     inner: sqlite_db::Connection,
 }
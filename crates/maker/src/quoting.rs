@@ -0,0 +1,260 @@
+//! The quoting engine of the maker.
+//!
+//! Instead of posting offers with fixed prices, the maker can let the
+//! quoting engine derive the prices from the BitMEX price feed. At every
+//! interval the offers of each quoted contract symbol are republished
+//! with prices around the mid price of the latest quote, `spread_percent`
+//! apart and shifted by `skew_percent`.
+//!
+//! With a TWAP window, offers are priced around the time-weighted
+//! average price of that window instead, which keeps short spikes of
+//! the price feed out of the offers.
+//!
+//! Republishing offers whose price barely moved is subject to the
+//! [`crate::cfd::OfferHysteresis`] of the CFD actor, so the interval can
+//! be shorter than the one at which takers should see new offers.
+
+use crate::cfd;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use daemon::price_feed::GetTwap;
+use daemon::price_feed::Twap;
+use model::ContractSymbol;
+use model::Price;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::time::Duration;
+use time::ext::NumericalDuration;
+use xtra::prelude::MessageChannel;
+use xtra_bitmex_price_feed::GetLatestQuotes;
+use xtra_bitmex_price_feed::LatestQuotes;
+use xtra_bitmex_price_feed::QUOTE_INTERVAL_MINUTES;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// How the offers of a contract symbol are quoted.
+#[derive(Debug, Clone)]
+pub struct QuotingParams {
+    /// How far apart the prices of the long and the short offer are, in
+    /// percent of the mid price
+    pub spread_percent: Decimal,
+    /// How far both prices are shifted from the mid price, in percent of
+    /// the mid price.
+    ///
+    /// A positive skew makes the long offer of the maker more attractive
+    /// and the short offer less attractive, i.e. it encourages takers to
+    /// go short.
+    pub skew_percent: Decimal,
+    pub quote_long: bool,
+    pub quote_short: bool,
+    /// Price the offers around the time-weighted average price of this
+    /// window instead of the latest quote
+    pub twap: Option<time::Duration>,
+    /// The terms of the quoted offers. Their prices are replaced by the
+    /// quoted prices.
+    pub terms: cfd::OfferParams,
+}
+
+impl QuotingParams {
+    /// Apply the quoting parameters to the latest `bid` and `ask`.
+    fn offer_params(&self, bid: Decimal, ask: Decimal) -> Result<cfd::OfferParams> {
+        let mid = (bid + ask) / Decimal::TWO;
+        let half_spread = mid * self.spread_percent / Decimal::ONE_HUNDRED / Decimal::TWO;
+        let skew = mid * self.skew_percent / Decimal::ONE_HUNDRED;
+
+        let price_long = self
+            .quote_long
+            .then(|| Price::new((mid - half_spread + skew).round_dp(2)))
+            .transpose()?;
+        let price_short = self
+            .quote_short
+            .then(|| Price::new((mid + half_spread + skew).round_dp(2)))
+            .transpose()?;
+
+        Ok(cfd::OfferParams {
+            price_long,
+            price_short,
+            ..self.terms.clone()
+        })
+    }
+}
+
+/// Start quoting the offers of `terms.contract_symbol`, replacing the
+/// previous quoting parameters of the contract symbol.
+#[derive(Clone)]
+pub struct StartQuoting(pub QuotingParams);
+
+/// Stop quoting the offers of a contract symbol.
+///
+/// The offers which were published last remain until they are replaced.
+#[derive(Clone, Copy)]
+pub struct StopQuoting(pub ContractSymbol);
+
+/// Message sent to ourselves at an interval to republish the quoted
+/// offers.
+#[derive(Clone, Copy)]
+struct RepublishOffers;
+
+pub struct Actor {
+    cfd_actor: MessageChannel<cfd::OfferParams, Result<()>>,
+    price_feed: MessageChannel<GetLatestQuotes, LatestQuotes>,
+    price_feed_twap: MessageChannel<GetTwap, Option<Twap>>,
+    interval: Duration,
+    params: HashMap<ContractSymbol, QuotingParams>,
+}
+
+impl Actor {
+    pub fn new(
+        cfd_actor: MessageChannel<cfd::OfferParams, Result<()>>,
+        price_feed: MessageChannel<GetLatestQuotes, LatestQuotes>,
+        price_feed_twap: MessageChannel<GetTwap, Option<Twap>>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            cfd_actor,
+            price_feed,
+            price_feed_twap,
+            interval,
+            params: HashMap::new(),
+        }
+    }
+
+    async fn republish_offers(&self) -> Result<()> {
+        if self.params.is_empty() {
+            return Ok(());
+        }
+
+        let quotes = self
+            .price_feed
+            .send(GetLatestQuotes)
+            .await
+            .context("Price feed not available")?;
+
+        for (contract_symbol, params) in self.params.iter() {
+            if let Err(e) = self.republish(params, &quotes).await {
+                tracing::warn!(%contract_symbol, "Failed to republish quoted offers: {e:#}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn republish(&self, params: &QuotingParams, quotes: &LatestQuotes) -> Result<()> {
+        let symbol = into_price_feed_symbol(params.terms.contract_symbol);
+        let max_quote_age = QUOTE_INTERVAL_MINUTES.minutes() * 2;
+
+        // Better to let the offers expire than to publish outdated prices
+        let (bid, ask) = match params.twap {
+            Some(duration) => {
+                let twap = self
+                    .price_feed_twap
+                    .send(GetTwap { symbol, duration })
+                    .await
+                    .context("Price feed not available")?
+                    .context("No quotes available for TWAP")?;
+                ensure!(
+                    !twap.is_older_than(max_quote_age),
+                    "Latest quote is too old"
+                );
+
+                (twap.bid, twap.ask)
+            }
+            None => {
+                let quote = quotes.get(&symbol).context("No quote available")?;
+                ensure!(
+                    !quote.is_older_than(max_quote_age),
+                    "Latest quote is too old"
+                );
+
+                (quote.bid(), quote.ask())
+            }
+        };
+
+        let offer_params = params.offer_params(bid, ask)?;
+
+        self.cfd_actor
+            .send(offer_params)
+            .await
+            .context("CFD actor disconnected")??;
+
+        Ok(())
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, msg: StartQuoting) -> Result<()> {
+        let StartQuoting(params) = msg;
+
+        ensure!(
+            params.spread_percent >= Decimal::ZERO,
+            "Spread must not be negative"
+        );
+        ensure!(
+            params.quote_long || params.quote_short,
+            "At least one position has to be quoted"
+        );
+        ensure!(
+            params.twap.map_or(true, |twap| twap.is_positive()),
+            "TWAP window must be positive"
+        );
+
+        let contract_symbol = params.terms.contract_symbol;
+        tracing::info!(
+            %contract_symbol,
+            spread_percent = %params.spread_percent,
+            skew_percent = %params.skew_percent,
+            twap = ?params.twap,
+            "Quoting offers"
+        );
+
+        let quotes = self
+            .price_feed
+            .send(GetLatestQuotes)
+            .await
+            .context("Price feed not available")?;
+        self.republish(&params, &quotes).await?;
+
+        self.params.insert(contract_symbol, params);
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, msg: StopQuoting) {
+        let StopQuoting(contract_symbol) = msg;
+
+        if self.params.remove(&contract_symbol).is_some() {
+            tracing::info!(%contract_symbol, "Stopped quoting offers");
+        }
+    }
+
+    async fn handle(&mut self, _: RepublishOffers) {
+        if let Err(e) = self.republish_offers().await {
+            tracing::warn!("Failed to republish quoted offers: {e:#}");
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(self.interval, || RepublishOffers, xtras::IncludeSpan::Never),
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+fn into_price_feed_symbol(symbol: ContractSymbol) -> xtra_bitmex_price_feed::ContractSymbol {
+    match symbol {
+        ContractSymbol::BtcUsd => xtra_bitmex_price_feed::ContractSymbol::BtcUsd,
+        ContractSymbol::EthUsd => xtra_bitmex_price_feed::ContractSymbol::EthUsd,
+    }
+}
@@ -1,22 +1,44 @@
 #![allow(clippy::let_unit_value)] // see: https://github.com/SergioBenitez/Rocket/issues/2211
+use crate::abuse;
 use crate::actor_system::ActorSystem;
+use crate::cfd;
+use crate::quoting;
+use crate::treasury;
 use anyhow::Result;
+use bdk::bitcoin::Amount;
+use bdk::bitcoin::OutPoint;
+use bdk::bitcoin::Txid;
 use bdk::sled;
+use daemon::analytics;
 use daemon::bdk::blockchain::ElectrumBlockchain;
+use daemon::block_explorer::BlockExplorer;
+use daemon::electrum;
+use daemon::fiat_rates;
+use daemon::fiat_rates::FiatRate;
 use daemon::oracle;
 use daemon::projection::Cfd;
 use daemon::projection::CfdAction;
 use daemon::projection::FeedReceivers;
+use daemon::projection::LatestQuotes;
+use daemon::readiness;
+use daemon::trace;
+use daemon::trade_history;
 use daemon::wallet;
+use daemon::ConnectionSettings;
 use http_api_problem::HttpApiProblem;
 use http_api_problem::StatusCode;
 use model::Contracts;
+use model::FeeSubsidy;
 use model::FundingRate;
+use model::Identity;
 use model::Leverage;
 use model::LotSize;
+use model::OfferTier;
 use model::OpeningFee;
 use model::OrderId;
 use model::Price;
+use model::RejectReason;
+use model::Timestamp;
 use model::TxFeeRate;
 use model::WalletInfo;
 use rocket::form::Form;
@@ -32,17 +54,28 @@ use rocket_cookie_auth::auth::Auth;
 use rocket_cookie_auth::forms::ChangePassword;
 use rocket_cookie_auth::forms::Login;
 use rocket_cookie_auth::user::User;
+use rust_decimal::Decimal;
 use rust_embed::RustEmbed;
 use rust_embed_rocket::EmbeddedFileExt;
 use serde::Deserialize;
 use serde::Serialize;
+use shared_bin::logger::LogFilter;
+use shared_bin::sse;
+use shared_bin::sse::Delivery;
+use shared_bin::sse::Disconnected;
+use shared_bin::sse::QueueSender;
+use shared_bin::wallet_event;
 use shared_bin::ToSseEvent;
+use sqlite_db::annotations::Annotation;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use tokio::select;
 use tokio::sync::watch;
+use tokio_extras::Tasks;
 use tracing::instrument;
 use uuid::Uuid;
+use xtra_libp2p::libp2p::PeerId;
 
 pub type Maker = ActorSystem<oracle::Actor, wallet::Actor<ElectrumBlockchain, sled::Tree>>;
 
@@ -52,62 +85,127 @@ pub type Maker = ActorSystem<oracle::Actor, wallet::Actor<ElectrumBlockchain, sl
 pub async fn maker_feed(
     rx: &State<FeedReceivers>,
     rx_wallet: &State<watch::Receiver<Option<WalletInfo>>>,
+    rx_fiat_rate: &State<watch::Receiver<Option<FiatRate>>>,
     _user: User,
 ) -> EventStream![] {
     let rx = rx.inner();
     let mut rx_cfds = rx.cfds.clone();
     let mut rx_wallet = rx_wallet.inner().clone();
+    let mut rx_fiat_rate = rx_fiat_rate.inner().clone();
     let mut rx_offers = rx.offers.clone();
+    let cfds_consumer = rx.freshness.cfds.consumer();
+    let offers_consumer = rx.freshness.offers.consumer();
+    let mut rx_alerts = rx.alerts.clone();
+    let alerts_consumer = rx.freshness.alerts.consumer();
     let mut rx_quote = rx.quote.clone();
+    let quote_consumer = rx.freshness.quote.consumer();
 
-    EventStream! {
-        let wallet_info = rx_wallet.borrow().clone();
-        yield wallet_info.to_sse_event();
-
-        let offers = rx_offers.borrow().clone();
-        yield Event::json(&offers.btcusd_long).event("btcusd_long_offer");
-        yield Event::json(&offers.btcusd_short).event("btcusd_short_offer");
-        yield Event::json(&offers.ethusd_long).event("ethusd_long_offer");
-        yield Event::json(&offers.ethusd_short).event("ethusd_short_offer");
-
-        let quote = rx_quote.borrow().clone();
-        yield Event::json(&quote.get(&model::ContractSymbol::BtcUsd)).event("btcusd_quote");
-        yield Event::json(&quote.get(&model::ContractSymbol::EthUsd)).event("ethusd_quote");
-
-        let cfds = rx_cfds.borrow().clone();
-        if let Some(cfds) = cfds {
-            yield cfds.to_sse_event()
-        }
+    let (events, mut queue) = sse::client_queue("maker");
+    let mut producer = Tasks::default();
+    producer.add(async move {
+        // Only fails once the client is gone, which ends the producer
+        let _: Result<(), Disconnected> = async move {
+            events.send(
+                "wallet",
+                Delivery::Guaranteed,
+                wallet_event(&rx_wallet, &rx_quote, &rx_fiat_rate),
+            )?;
 
-        loop{
-            select! {
-                Ok(()) = rx_wallet.changed() => {
-                    let wallet_info = rx_wallet.borrow().clone();
-                    yield wallet_info.to_sse_event();
-                },
-                Ok(()) = rx_offers.changed() => {
-                    let offers = rx_offers.borrow().clone();
-                    yield Event::json(&offers.btcusd_long).event("btcusd_long_offer");
-                    yield Event::json(&offers.btcusd_short).event("btcusd_short_offer");
-                    yield Event::json(&offers.ethusd_long).event("ethusd_long_offer");
-                    yield Event::json(&offers.ethusd_short).event("ethusd_short_offer");
-                }
-                Ok(()) = rx_cfds.changed() => {
-                    let cfds = rx_cfds.borrow().clone();
-                    if let Some(cfds) = cfds {
-                        yield cfds.to_sse_event()
+            let offers = rx_offers.borrow().clone();
+            sse::send_offers(&events, &offers)?;
+            offers_consumer.observed();
+
+            let quote = rx_quote.borrow().clone();
+            send_quotes(&events, &quote)?;
+            quote_consumer.observed();
+
+            let cfds = rx_cfds.borrow().clone();
+            if let Some(cfds) = cfds {
+                events.send("cfds", Delivery::Guaranteed, cfds.to_sse_event())?;
+            }
+            cfds_consumer.observed();
+
+            let alerts = rx_alerts.borrow().clone();
+            events.send(
+                "liquidation_alerts",
+                Delivery::Guaranteed,
+                Event::json(&alerts).event("liquidation_alerts"),
+            )?;
+            alerts_consumer.observed();
+
+            loop {
+                select! {
+                    Ok(()) = rx_wallet.changed() => {
+                        events.send(
+                            "wallet",
+                            Delivery::Guaranteed,
+                            wallet_event(&rx_wallet, &rx_quote, &rx_fiat_rate),
+                        )?;
+                    },
+                    Ok(()) = rx_fiat_rate.changed() => {
+                        events.send(
+                            "wallet",
+                            Delivery::Guaranteed,
+                            wallet_event(&rx_wallet, &rx_quote, &rx_fiat_rate),
+                        )?;
+                    },
+                    Ok(()) = rx_offers.changed() => {
+                        let offers = rx_offers.borrow().clone();
+                        sse::send_offers(&events, &offers)?;
+                        offers_consumer.observed();
+                    }
+                    Ok(()) = rx_cfds.changed() => {
+                        let cfds = rx_cfds.borrow().clone();
+                        if let Some(cfds) = cfds {
+                            events.send("cfds", Delivery::Guaranteed, cfds.to_sse_event())?;
+                        }
+                        cfds_consumer.observed();
+                    }
+                    Ok(()) = rx_alerts.changed() => {
+                        let alerts = rx_alerts.borrow().clone();
+                        events.send(
+                            "liquidation_alerts",
+                            Delivery::Guaranteed,
+                            Event::json(&alerts).event("liquidation_alerts"),
+                        )?;
+                        alerts_consumer.observed();
+                    }
+                    Ok(()) = rx_quote.changed() => {
+                        let quote = rx_quote.borrow().clone();
+                        send_quotes(&events, &quote)?;
+                        quote_consumer.observed();
                     }
-                }
-                Ok(()) = rx_quote.changed() => {
-                    let quote = rx_quote.borrow().clone();
-                    yield Event::json(&quote.get(&model::ContractSymbol::BtcUsd)).event("btcusd_quote");
-                    yield Event::json(&quote.get(&model::ContractSymbol::EthUsd)).event("ethusd_quote");
                 }
             }
         }
+        .await;
+    });
+
+    EventStream! {
+        // Stop producing events once the client is gone
+        let _producer = producer;
+
+        while let Some(event) = queue.recv().await {
+            yield event;
+        }
     }
 }
 
+/// Quotes are updated frequently and superseded by the next update, hence
+/// they are dropped if the client falls behind.
+fn send_quotes(events: &QueueSender, quote: &LatestQuotes) -> Result<(), Disconnected> {
+    events.send(
+        "btcusd_quote",
+        Delivery::DropOldest,
+        Event::json(&quote.get(&model::ContractSymbol::BtcUsd)).event("btcusd_quote"),
+    )?;
+    events.send(
+        "ethusd_quote",
+        Delivery::DropOldest,
+        Event::json(&quote.get(&model::ContractSymbol::EthUsd)).event("ethusd_quote"),
+    )
+}
+
 /// The maker PUTs this to set the offer params
 #[derive(Debug, Clone, Deserialize)]
 pub struct CfdNewOfferParamsRequest {
@@ -125,8 +223,42 @@ pub struct CfdNewOfferParamsRequest {
     pub opening_fee: OpeningFee,
     #[serde(default = "empty_leverage")]
     pub leverage_choices: Vec<Leverage>,
+    /// Overrides `leverage_choices` for the maker's long offer
+    #[serde(default)]
+    pub leverage_choices_long: Option<Vec<Leverage>>,
+    /// Overrides `leverage_choices` for the maker's short offer
+    #[serde(default)]
+    pub leverage_choices_short: Option<Vec<Leverage>>,
     #[serde(default = "default_lot_size")]
     pub lot_size: LotSize,
+    /// Fees waived for the taker as part of a promotion
+    #[serde(default)]
+    pub fee_subsidy: Option<FeeSubsidy>,
+    /// The balance a taker needs to take the offers, if more than the margin
+    #[serde(default, with = "bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub min_taker_balance: Option<Amount>,
+    /// The refund timelock in blocks, if not one and a half settlement intervals
+    #[serde(default)]
+    pub refund_timelock: Option<u32>,
+    /// The takers the offers are shown to
+    #[serde(default)]
+    pub tier: OfferTier,
+}
+
+/// The leverage choices of the maker's long and short offer
+fn leverage_choices_per_position(
+    leverage_choices: &[Leverage],
+    leverage_choices_long: &Option<Vec<Leverage>>,
+    leverage_choices_short: &Option<Vec<Leverage>>,
+) -> (Vec<Leverage>, Vec<Leverage>) {
+    let long = leverage_choices_long
+        .clone()
+        .unwrap_or_else(|| leverage_choices.to_vec());
+    let short = leverage_choices_short
+        .clone()
+        .unwrap_or_else(|| leverage_choices.to_vec());
+
+    (long, short)
 }
 
 fn empty_leverage() -> Vec<Leverage> {
@@ -146,6 +278,11 @@ pub async fn put_offer_params(
     _user: User,
 ) -> Result<(), HttpApiProblem> {
     tracing::warn!("Deprecated /offer was called. Please use /<contract_symbol>/offer from now.");
+    let (leverage_choices_long, leverage_choices_short) = leverage_choices_per_position(
+        &offer_params.leverage_choices,
+        &offer_params.leverage_choices_long,
+        &offer_params.leverage_choices_short,
+    );
     maker
         .set_offer_params(
             offer_params.price_long,
@@ -156,9 +293,14 @@ pub async fn put_offer_params(
             offer_params.daily_funding_rate_long,
             offer_params.daily_funding_rate_short,
             offer_params.opening_fee,
-            offer_params.leverage_choices.clone(),
+            leverage_choices_long,
+            leverage_choices_short,
             ContractSymbol::BtcUsd.into(),
             offer_params.lot_size,
+            offer_params.fee_subsidy,
+            offer_params.min_taker_balance,
+            offer_params.refund_timelock,
+            offer_params.tier,
         )
         .await
         .map_err(|e| {
@@ -197,7 +339,9 @@ impl<'r> FromParam<'r> for ContractSymbol {
     }
 }
 
-#[rocket::put("/<symbol>/offer", data = "<offer_params>")]
+// Ranked lower than the routes starting with a static segment, e.g.
+// `/blocked-peers/<peer_id>`, which would collide with it otherwise
+#[rocket::put("/<symbol>/offer", data = "<offer_params>", rank = 2)]
 #[instrument(name = "PUT /offer", skip(maker, _user), err)]
 pub async fn put_offer_params_for_symbol(
     symbol: Result<ContractSymbol>,
@@ -211,6 +355,11 @@ pub async fn put_offer_params_for_symbol(
             .title("Unknown ContractSymbol provided")
             .detail(format!("{e:#}"))
     })?;
+    let (leverage_choices_long, leverage_choices_short) = leverage_choices_per_position(
+        &offer_params.leverage_choices,
+        &offer_params.leverage_choices_long,
+        &offer_params.leverage_choices_short,
+    );
     maker
         .set_offer_params(
             offer_params.price_long,
@@ -221,9 +370,14 @@ pub async fn put_offer_params_for_symbol(
             offer_params.daily_funding_rate_long,
             offer_params.daily_funding_rate_short,
             offer_params.opening_fee,
-            offer_params.leverage_choices.clone(),
+            leverage_choices_long,
+            leverage_choices_short,
             symbol.into(),
             offer_params.lot_size,
+            offer_params.fee_subsidy,
+            offer_params.min_taker_balance,
+            offer_params.refund_timelock,
+            offer_params.tier,
         )
         .await
         .map_err(|e| {
@@ -235,11 +389,146 @@ pub async fn put_offer_params_for_symbol(
     Ok(())
 }
 
-#[rocket::post("/cfd/<order_id>/<action>")]
+/// Parameters of the quoting engine, which derives the prices of the
+/// offers from the price feed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuotingParamsRequest {
+    /// How far apart the prices of the long and the short offer are, in percent of the mid price
+    pub spread_percent: Decimal,
+    /// How far both prices are shifted from the mid price, in percent of the mid price
+    #[serde(default)]
+    pub skew_percent: Decimal,
+    #[serde(default = "quote_position")]
+    pub quote_long: bool,
+    #[serde(default = "quote_position")]
+    pub quote_short: bool,
+    /// Price around the time-weighted average price of this many seconds instead of the latest
+    /// quote
+    #[serde(default)]
+    pub twap_secs: Option<u32>,
+    pub min_quantity: Contracts,
+    pub max_quantity: Contracts,
+    /// The current _daily_ funding rate for the maker's long position
+    pub daily_funding_rate_long: FundingRate,
+    /// The current _daily_ funding rate for the maker's short position
+    pub daily_funding_rate_short: FundingRate,
+    pub tx_fee_rate: TxFeeRate,
+    pub opening_fee: OpeningFee,
+    #[serde(default = "empty_leverage")]
+    pub leverage_choices: Vec<Leverage>,
+    /// Overrides `leverage_choices` for the maker's long offer
+    #[serde(default)]
+    pub leverage_choices_long: Option<Vec<Leverage>>,
+    /// Overrides `leverage_choices` for the maker's short offer
+    #[serde(default)]
+    pub leverage_choices_short: Option<Vec<Leverage>>,
+    #[serde(default = "default_lot_size")]
+    pub lot_size: LotSize,
+    /// Fees waived for the taker as part of a promotion
+    #[serde(default)]
+    pub fee_subsidy: Option<FeeSubsidy>,
+    /// The balance a taker needs to take the offers, if more than the margin
+    #[serde(default, with = "bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub min_taker_balance: Option<Amount>,
+    /// The refund timelock in blocks, if not one and a half settlement intervals
+    #[serde(default)]
+    pub refund_timelock: Option<u32>,
+}
+
+fn quote_position() -> bool {
+    true
+}
+
+/// Quote the offers of `symbol` with the quoting engine, until offers
+/// are posted manually.
+#[rocket::put("/<symbol>/quoting", data = "<params>", rank = 2)]
+#[instrument(name = "PUT /<symbol>/quoting", skip(maker, _user), err)]
+pub async fn put_quoting_params(
+    symbol: Result<ContractSymbol>,
+    params: Json<QuotingParamsRequest>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let symbol = symbol.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Unknown ContractSymbol provided")
+            .detail(format!("{e:#}"))
+    })?;
+    let params = params.into_inner();
+    let (leverage_choices_long, leverage_choices_short) = leverage_choices_per_position(
+        &params.leverage_choices,
+        &params.leverage_choices_long,
+        &params.leverage_choices_short,
+    );
+
+    maker
+        .start_quoting(quoting::QuotingParams {
+            spread_percent: params.spread_percent,
+            skew_percent: params.skew_percent,
+            quote_long: params.quote_long,
+            quote_short: params.quote_short,
+            twap: params
+                .twap_secs
+                .map(|secs| time::Duration::seconds(secs.into())),
+            terms: cfd::OfferParams {
+                price_long: None,
+                price_short: None,
+                min_quantity: params.min_quantity,
+                max_quantity: params.max_quantity,
+                tx_fee_rate: params.tx_fee_rate,
+                funding_rate_long: params.daily_funding_rate_long,
+                funding_rate_short: params.daily_funding_rate_short,
+                opening_fee: params.opening_fee,
+                leverage_choices_long,
+                leverage_choices_short,
+                contract_symbol: symbol.into(),
+                lot_size: params.lot_size,
+                fee_subsidy: params.fee_subsidy,
+                min_taker_balance: params.min_taker_balance,
+                refund_timelock: params.refund_timelock,
+                tier: OfferTier::Public,
+            },
+        })
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not start quoting")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+/// Stop quoting the offers of `symbol`. The offers published last remain
+/// until they are replaced.
+#[rocket::delete("/<symbol>/quoting", rank = 2)]
+#[instrument(name = "DELETE /<symbol>/quoting", skip(maker, _user), err)]
+pub async fn delete_quoting_params(
+    symbol: Result<ContractSymbol>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let symbol = symbol.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Unknown ContractSymbol provided")
+            .detail(format!("{e:#}"))
+    })?;
+
+    maker.stop_quoting(symbol.into()).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not stop quoting")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
+#[rocket::post("/cfd/<order_id>/<action>?<reason>")]
 #[instrument(name = "POST /cfd/<order_id>/<action>", skip(maker, _user), err)]
 pub async fn post_cfd_action(
     order_id: Uuid,
     action: String,
+    reason: Option<String>,
     maker: &State<Maker>,
     _user: User,
 ) -> Result<(), HttpApiProblem> {
@@ -248,9 +537,18 @@ pub async fn post_cfd_action(
         HttpApiProblem::new(StatusCode::BAD_REQUEST).detail(format!("Invalid action: {}", action))
     })?;
 
+    let reason = reason
+        .map(|reason| {
+            reason.parse::<RejectReason>().map_err(|_| {
+                HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                    .detail(format!("Invalid reject reason: {reason}"))
+            })
+        })
+        .transpose()?;
+
     let result = match action {
         CfdAction::AcceptOrder => maker.accept_order(order_id).await,
-        CfdAction::RejectOrder => maker.reject_order(order_id).await,
+        CfdAction::RejectOrder => maker.reject_order(order_id, reason).await,
         CfdAction::AcceptSettlement => maker.accept_settlement(order_id).await,
         CfdAction::RejectSettlement => maker.reject_settlement(order_id).await,
         CfdAction::Commit => maker.commit(order_id).await,
@@ -269,8 +567,329 @@ pub async fn post_cfd_action(
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PurgeSummary {
+    n_purged_cfds: u64,
+}
+
+#[rocket::delete("/counterparty/<identity>")]
+#[instrument(name = "DELETE /counterparty/<identity>", skip(maker, _user), err)]
+pub async fn delete_counterparty(
+    identity: String,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<PurgeSummary>, HttpApiProblem> {
+    let identity = identity.parse::<Identity>().map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid counterparty identity")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let n_purged_cfds = maker.purge_counterparty(identity).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::CONFLICT)
+            .title("Could not purge counterparty")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(PurgeSummary { n_purged_cfds }))
+}
+
+#[rocket::get("/blocked-peers")]
+#[instrument(name = "GET /blocked-peers", skip(maker, _user), err)]
+pub async fn get_blocked_peers(
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<Vec<String>>, HttpApiProblem> {
+    let blocked_peers = maker.blocked_peers().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not load blocked peers")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let mut blocked_peers = blocked_peers
+        .iter()
+        .map(PeerId::to_string)
+        .collect::<Vec<_>>();
+    blocked_peers.sort();
+
+    Ok(Json(blocked_peers))
+}
+
+#[rocket::put("/blocked-peers/<peer_id>")]
+#[instrument(name = "PUT /blocked-peers/<peer_id>", skip(maker, _user), err)]
+pub async fn put_blocked_peer(
+    peer_id: String,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    maker.block_peer(peer_id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not block peer")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
+#[rocket::delete("/blocked-peers/<peer_id>")]
+#[instrument(name = "DELETE /blocked-peers/<peer_id>", skip(maker, _user), err)]
+pub async fn delete_blocked_peer(
+    peer_id: String,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    maker.unblock_peer(peer_id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not unblock peer")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
+/// A taker which is shown the offers of a tier other than the public one.
+#[derive(Debug, Clone, Serialize)]
+pub struct TakerOfferTier {
+    peer_id: String,
+    tier: OfferTier,
+}
+
+#[rocket::get("/offer-tiers")]
+#[instrument(name = "GET /offer-tiers", skip(maker, _user), err)]
+pub async fn get_offer_tiers(
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<Vec<TakerOfferTier>>, HttpApiProblem> {
+    let tiers = maker.offer_tiers().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not load offer tiers")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let mut tiers = tiers
+        .into_iter()
+        .map(|(peer_id, tier)| TakerOfferTier {
+            peer_id: peer_id.to_string(),
+            tier,
+        })
+        .collect::<Vec<_>>();
+    tiers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+
+    Ok(Json(tiers))
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct OfferTierRequest {
+    pub tier: OfferTier,
+}
+
+/// Show the offers of a tier to the taker with `peer_id`.
+///
+/// Assigning the public tier removes the taker from its previous tier.
+#[rocket::put("/offer-tiers/<peer_id>", data = "<request>")]
+#[instrument(name = "PUT /offer-tiers/<peer_id>", skip(maker, _user), err)]
+pub async fn put_offer_tier(
+    peer_id: String,
+    request: Json<OfferTierRequest>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    maker
+        .set_offer_tier(peer_id, request.tier)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not set offer tier")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+/// The abuse signals of a taker.
+#[derive(Debug, Clone, Serialize)]
+pub struct TakerSignals {
+    peer_id: String,
+    #[serde(flatten)]
+    signals: abuse::Signals,
+    /// The thresholds of the abuse policy which the taker has reached
+    violations: Vec<String>,
+}
+
+#[rocket::get("/abuse-signals")]
+#[instrument(name = "GET /abuse-signals", skip_all, err)]
+pub async fn get_abuse_signals(
+    rx: &State<FeedReceivers>,
+    policy: &State<abuse::Policy>,
+    _user: User,
+) -> Result<Json<Vec<TakerSignals>>, HttpApiProblem> {
+    let cfds = rx.cfds.borrow().clone().ok_or_else(|| {
+        HttpApiProblem::new(StatusCode::SERVICE_UNAVAILABLE)
+            .title("CFDs not yet available")
+            .detail("CFDs are still being loaded from the database. Please retry later.")
+    })?;
+
+    let mut signals = abuse::signals(&cfds, Timestamp::now())
+        .into_iter()
+        .map(|(peer_id, signals)| TakerSignals {
+            peer_id: peer_id.to_string(),
+            signals,
+            violations: signals.violations(policy),
+        })
+        .collect::<Vec<_>>();
+    signals.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+
+    Ok(Json(signals))
+}
+
+#[rocket::get("/protocol-allowlist")]
+#[instrument(name = "GET /protocol-allowlist", skip(maker, _user), err)]
+pub async fn get_protocol_allowlist(
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<BTreeMap<String, Vec<String>>>, HttpApiProblem> {
+    let restricted_protocols = maker.restricted_protocols().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not load protocol allowlist")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let allowlist = restricted_protocols
+        .into_iter()
+        .map(|(protocol, allowed_peers)| {
+            let mut allowed_peers = allowed_peers
+                .iter()
+                .map(PeerId::to_string)
+                .collect::<Vec<_>>();
+            allowed_peers.sort();
+
+            (protocol, allowed_peers)
+        })
+        .collect();
+
+    Ok(Json(allowlist))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProtocolAllowlistEntry {
+    protocol: String,
+    peer_id: String,
+}
+
+#[rocket::put("/protocol-allowlist", data = "<entry>")]
+#[instrument(name = "PUT /protocol-allowlist", skip(maker, _user), err)]
+pub async fn put_protocol_allowlist_entry(
+    entry: Json<ProtocolAllowlistEntry>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let ProtocolAllowlistEntry { protocol, peer_id } = entry.into_inner();
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    maker.allow_protocol(protocol, peer_id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Could not allow protocol")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
+#[rocket::delete("/protocol-allowlist", data = "<entry>")]
+#[instrument(name = "DELETE /protocol-allowlist", skip(maker, _user), err)]
+pub async fn delete_protocol_allowlist_entry(
+    entry: Json<ProtocolAllowlistEntry>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let ProtocolAllowlistEntry { protocol, peer_id } = entry.into_inner();
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    maker
+        .disallow_protocol(protocol, peer_id)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not disallow protocol")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProtocolRelease {
+    protocol: String,
+}
+
+#[rocket::post("/protocol-allowlist/release", data = "<release>")]
+#[instrument(name = "POST /protocol-allowlist/release", skip(maker, _user), err)]
+pub async fn post_protocol_release(
+    release: Json<ProtocolRelease>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    maker
+        .release_protocol(release.into_inner().protocol)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not release protocol")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+fn parse_peer_id(peer_id: &str) -> Result<PeerId, HttpApiProblem> {
+    peer_id.parse().map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid peer id")
+            .detail(format!("{e:#}"))
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Health {
+    electrum: electrum::ElectrumStatus,
+    ping_interval_secs: u64,
+    connection_timeout_secs: u64,
+}
+
 #[rocket::get("/alive")]
-pub fn get_health_check() {}
+pub fn get_health_check(
+    active_electrum: &State<electrum::ActiveServer>,
+    connection: &State<ConnectionSettings>,
+) -> Json<Health> {
+    Json(Health {
+        electrum: active_electrum.status(),
+        ping_interval_secs: connection.ping_interval.as_secs(),
+        connection_timeout_secs: connection.connection_timeout.as_secs(),
+    })
+}
+
+/// Report the stage of every subsystem.
+///
+/// Responds with `503 Service Unavailable` until all subsystems are ready.
+#[rocket::get("/readiness")]
+pub fn get_readiness(
+    readiness: &State<watch::Receiver<readiness::Readiness>>,
+) -> (Status, Json<readiness::Readiness>) {
+    let readiness = readiness.borrow().clone();
+    let status = if readiness.is_ready() {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    (status, Json(readiness))
+}
 
 #[derive(RustEmbed)]
 #[folder = "../../maker-frontend/dist/maker"]
@@ -302,6 +921,148 @@ pub async fn put_sync_wallet(maker: &State<Maker>, _user: User) -> Result<(), Ht
     Ok(())
 }
 
+#[rocket::get("/utxos")]
+#[instrument(name = "GET /utxos", skip_all, err)]
+pub async fn get_utxos(
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<Vec<wallet::Utxo>>, HttpApiProblem> {
+    let utxos = maker.list_utxos().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not list UTXOs")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(utxos))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UtxosRequest {
+    outpoints: Vec<OutPoint>,
+}
+
+#[rocket::put("/utxos/freeze", data = "<request>")]
+#[instrument(name = "PUT /utxos/freeze", skip(maker, _user), err)]
+pub async fn put_freeze_utxos(
+    request: Json<UtxosRequest>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    maker
+        .freeze_utxos(request.into_inner().outpoints)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not freeze UTXOs")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::put("/utxos/unfreeze", data = "<request>")]
+#[instrument(name = "PUT /utxos/unfreeze", skip(maker, _user), err)]
+pub async fn put_unfreeze_utxos(
+    request: Json<UtxosRequest>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    maker
+        .unfreeze_utxos(request.into_inner().outpoints)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not unfreeze UTXOs")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingPunishment {
+    revoked_commit_txid: Txid,
+    peer_id: String,
+    seen_at: Timestamp,
+    /// When the punishment is published without confirmation
+    punish_at: Timestamp,
+    is_confirmed: bool,
+}
+
+#[rocket::get("/punishments")]
+#[instrument(name = "GET /punishments", skip_all, err)]
+pub async fn get_punishments(
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<Vec<PendingPunishment>>, HttpApiProblem> {
+    let punishments = maker.pending_punishments().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not load pending punishments")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let punishments = punishments
+        .into_iter()
+        .map(|punishment| PendingPunishment {
+            revoked_commit_txid: punishment.revoked_commit_txid,
+            peer_id: punishment.peer_id.to_string(),
+            seen_at: punishment.seen_at,
+            punish_at: punishment.punish_at,
+            is_confirmed: punishment.is_confirmed,
+        })
+        .collect();
+
+    Ok(Json(punishments))
+}
+
+#[rocket::post("/punishments/<revoked_commit_txid>/confirm")]
+#[instrument(
+    name = "POST /punishments/<revoked_commit_txid>/confirm",
+    skip(maker, _user),
+    err
+)]
+pub async fn post_confirm_punishment(
+    revoked_commit_txid: String,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let revoked_commit_txid = revoked_commit_txid.parse::<Txid>().map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid transaction id")
+            .detail(format!("{e:#}"))
+    })?;
+
+    maker
+        .confirm_punishment(revoked_commit_txid)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not confirm punishment")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+/// Rebuild the projection from the event store, e.g. to recover from a bug in it.
+///
+/// The rebuild runs in the background. The CFD feed keeps serving the current state until it is
+/// complete.
+#[rocket::post("/projection/rebuild")]
+#[instrument(name = "POST /projection/rebuild", skip_all, err)]
+pub async fn post_rebuild_projection(
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    maker.rebuild_projection().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::CONFLICT)
+            .title("Could not rebuild projection")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
 #[rocket::get("/cfds")]
 #[instrument(name = "GET /cfds", skip_all, err)]
 pub async fn get_cfds<'r>(
@@ -320,6 +1081,319 @@ pub async fn get_cfds<'r>(
     }
 }
 
+#[rocket::get("/cfds/archived")]
+#[instrument(name = "GET /cfds/archived", skip_all, err)]
+pub async fn get_archived_cfds(
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<Vec<Cfd>>, HttpApiProblem> {
+    let cfds = maker.archived_cfds().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not load archived CFDs")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(cfds))
+}
+
+/// Statistics of the market of a contract symbol, e.g. `BTCUSD`, over the last `window_hours`.
+#[rocket::get("/analytics/<symbol>?<window_hours>")]
+#[instrument(name = "GET /analytics/<symbol>", skip(maker, _user), err)]
+pub async fn get_market_statistics(
+    symbol: &str,
+    window_hours: Option<u32>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<analytics::Statistics>, HttpApiProblem> {
+    let contract_symbol = analytics::parse_contract_symbol(symbol).ok_or_else(|| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid contract symbol")
+            .detail(format!("Unknown contract symbol {symbol}"))
+    })?;
+    let window = window_hours
+        .map(|hours| time::Duration::hours(hours.into()))
+        .unwrap_or(analytics::DEFAULT_WINDOW);
+
+    let statistics = maker
+        .market_statistics(contract_symbol, window)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not compute market statistics")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(Json(statistics))
+}
+
+/// Export all closed CFDs, including the archived ones, as CSV (the
+/// default) or JSON.
+#[rocket::get("/cfds/export?<format>")]
+#[instrument(name = "GET /cfds/export", skip(maker, fiat_rate_provider, _user), err)]
+pub async fn get_trade_history(
+    format: Option<&str>,
+    maker: &State<Maker>,
+    fiat_rate_provider: &State<Option<fiat_rates::Provider>>,
+    _user: User,
+) -> Result<(ContentType, String), HttpApiProblem> {
+    let format = format
+        .unwrap_or("csv")
+        .parse::<trade_history::Format>()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Invalid export format")
+                .detail(format!("{e:#}"))
+        })?;
+
+    let export = maker
+        .trade_history(fiat_rate_provider.inner().as_ref())
+        .await
+        .and_then(|trades| trade_history::render(&trades, format))
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not export trade history")
+                .detail(format!("{e:#}"))
+        })?;
+
+    let content_type = match format {
+        trade_history::Format::Csv => ContentType::CSV,
+        trade_history::Format::Json => ContentType::JSON,
+    };
+
+    Ok((content_type, export))
+}
+
+/// The lineage of a CFD: the offer it was created from, its events and the
+/// transactions published for it.
+#[rocket::get("/trace/<order_id>")]
+#[instrument(
+    name = "GET /trace/<order_id>",
+    skip(maker, block_explorer, _user),
+    err
+)]
+pub async fn get_trace(
+    order_id: Uuid,
+    maker: &State<Maker>,
+    block_explorer: &State<BlockExplorer>,
+    _user: User,
+) -> Result<Json<trace::Trace>, HttpApiProblem> {
+    let order_id = OrderId::from(order_id);
+
+    let trace = maker
+        .trace(order_id, block_explorer.inner())
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not load trace")
+                .detail(format!("{e:#}"))
+        })?
+        .ok_or_else(|| {
+            HttpApiProblem::new(StatusCode::NOT_FOUND)
+                .title("CFD not found")
+                .detail(format!("There is no CFD for order id {order_id}"))
+        })?;
+
+    Ok(Json(trace))
+}
+
+#[rocket::get("/annotations")]
+#[instrument(name = "GET /annotations", skip(maker, _user), err)]
+pub async fn get_annotations(
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<Vec<Annotation>>, HttpApiProblem> {
+    let annotations = maker.annotations().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not load annotations")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(annotations))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnnotationRequest {
+    pub text: String,
+    /// Defaults to now
+    pub starts_at: Option<Timestamp>,
+    /// Defaults to `starts_at`, i.e. annotating a point in time
+    pub ends_at: Option<Timestamp>,
+}
+
+/// Annotate a period of time, e.g. with an outage.
+///
+/// The annotation is shown in the traces and the exported trade history
+/// of all CFDs which were open during that period.
+#[rocket::post("/annotations", data = "<request>")]
+#[instrument(name = "POST /annotations", skip(maker, _user), err)]
+pub async fn post_annotation(
+    request: Json<AnnotationRequest>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<Json<Annotation>, HttpApiProblem> {
+    let AnnotationRequest {
+        text,
+        starts_at,
+        ends_at,
+    } = request.into_inner();
+
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err(HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid annotation")
+            .detail("The text of an annotation must not be empty"));
+    }
+
+    let starts_at = starts_at.unwrap_or_else(Timestamp::now);
+    let ends_at = ends_at.unwrap_or(starts_at);
+
+    let annotation = maker
+        .annotate(text, starts_at, ends_at)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not add annotation")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(Json(annotation))
+}
+
+#[rocket::delete("/annotations/<id>")]
+#[instrument(name = "DELETE /annotations/<id>", skip(maker, _user), err)]
+pub async fn delete_annotation(
+    id: i64,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let deleted = maker.delete_annotation(id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not delete annotation")
+            .detail(format!("{e:#}"))
+    })?;
+
+    if !deleted {
+        return Err(HttpApiProblem::new(StatusCode::NOT_FOUND)
+            .title("Annotation not found")
+            .detail(format!("There is no annotation with id {id}")));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogDirectives {
+    /// Directives in the format of `RUST_LOG`, e.g. `xtra_libp2p=trace`
+    directives: String,
+}
+
+fn require_log_filter(log_filter: &Option<LogFilter>) -> Result<&LogFilter, HttpApiProblem> {
+    log_filter.as_ref().ok_or_else(|| {
+        HttpApiProblem::new(StatusCode::CONFLICT)
+            .title("Logging is turned off")
+            .detail("The log filter cannot be adjusted if the log level is `off`")
+    })
+}
+
+#[rocket::get("/log-filter")]
+#[instrument(name = "GET /log-filter", skip_all, err)]
+pub async fn get_log_filter(
+    log_filter_state: &State<Option<LogFilter>>,
+    _user: User,
+) -> Result<Json<LogDirectives>, HttpApiProblem> {
+    let directives = require_log_filter(log_filter_state)?
+        .current()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not read log filter")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(Json(LogDirectives { directives }))
+}
+
+/// Replace the log directives the daemon was started with. An empty
+/// string goes back to the directives it was started with.
+#[rocket::put("/log-filter", data = "<request>")]
+#[instrument(name = "PUT /log-filter", skip(log_filter_state, _user), err)]
+pub async fn put_log_filter(
+    request: Json<LogDirectives>,
+    log_filter_state: &State<Option<LogFilter>>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let log_filter = require_log_filter(log_filter_state)?;
+    let directives = request.into_inner().directives;
+
+    let result = if directives.trim().is_empty() {
+        log_filter.reset()
+    } else {
+        log_filter.reload(&directives)
+    };
+
+    result.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Could not reload log filter")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
+/// Revenue of the maker per period and contract symbol, attributed to
+/// opening fees, funding fees, spread capture and liquidations. The
+/// report is summed up per month by default and exported as JSON (the
+/// default) or CSV.
+#[rocket::get("/treasury?<period>&<format>")]
+#[instrument(name = "GET /treasury", skip(maker, _user), err)]
+pub async fn get_treasury_report(
+    period: Option<&str>,
+    format: Option<&str>,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(ContentType, String), HttpApiProblem> {
+    let period = period
+        .unwrap_or("month")
+        .parse::<treasury::Period>()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Invalid period")
+                .detail(format!("{e:#}"))
+        })?;
+    let format = format
+        .unwrap_or("json")
+        .parse::<trade_history::Format>()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Invalid export format")
+                .detail(format!("{e:#}"))
+        })?;
+
+    let report = async {
+        let trades = maker.trade_history(None).await?;
+        let report = treasury::report(&trades, period)?;
+
+        let report = match format {
+            trade_history::Format::Csv => treasury::to_csv(&report)?,
+            trade_history::Format::Json => rocket::serde::json::to_string(&report)?,
+        };
+
+        anyhow::Ok(report)
+    }
+    .await
+    .map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not compute treasury report")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let content_type = match format {
+        trade_history::Format::Csv => ContentType::CSV,
+        trade_history::Format::Json => ContentType::JSON,
+    };
+
+    Ok((content_type, report))
+}
+
 // TODO: Use non-cookie auth for /metrics endpoint as Prometheus does not
 // support cookie-auth (for now, leave unauthenticated)
 #[rocket::get("/metrics")]
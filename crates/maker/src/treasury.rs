@@ -0,0 +1,163 @@
+//! Attribution of the maker's revenue to its sources.
+//!
+//! The realized profit or loss of every closed CFD is split into:
+//!
+//! - the opening fee paid by the taker;
+//! - the funding fees paid by the taker during rollovers;
+//! - the price movement of CFDs which were settled collaboratively, which is where the spread
+//!   between the maker's offers and the market is captured;
+//! - the price movement of CFDs which were settled on-chain, i.e. after liquidation or expiry, or
+//!   refunded.
+//!
+//! The sources are summed up per period and contract symbol, where a CFD
+//! counts towards the period in which it was closed.
+
+use anyhow::Result;
+use bdk::bitcoin::Denomination;
+use bdk::bitcoin::SignedAmount;
+use daemon::trade_history::SettlementKind;
+use daemon::trade_history::Trade;
+use model::ContractSymbol;
+use model::Role;
+use serde::Serialize;
+use std::fmt::Write;
+use time::Date;
+use time::OffsetDateTime;
+
+/// The length of the periods over which revenue is summed up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    /// The first day of the period which contains `date`.
+    fn start(&self, date: Date) -> Result<Date> {
+        let start = match self {
+            Period::Day => date,
+            Period::Week => {
+                date - time::Duration::days(date.weekday().number_days_from_monday().into())
+            }
+            Period::Month => Date::from_calendar_date(date.year(), date.month(), 1)?,
+        };
+
+        Ok(start)
+    }
+}
+
+/// The revenue of the maker in one contract symbol during one period.
+///
+/// Positive amounts were earned by the maker, negative amounts were lost.
+#[derive(Debug, Clone, Serialize)]
+pub struct Revenue {
+    /// The first day of the period, e.g. `2022-10-01`
+    pub period: String,
+    pub contract_symbol: ContractSymbol,
+    /// The number of CFDs closed during the period
+    pub n_cfds: usize,
+
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub opening_fees_btc: SignedAmount,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub funding_fees_btc: SignedAmount,
+    /// Profit or loss of collaboratively settled CFDs, excluding fees
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub spread_btc: SignedAmount,
+    /// Profit or loss of CFDs settled on-chain, excluding fees
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub liquidation_btc: SignedAmount,
+    /// Sum of all sources, equal to the realized profit or loss
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub total_btc: SignedAmount,
+}
+
+impl Revenue {
+    fn new(period: String, contract_symbol: ContractSymbol) -> Self {
+        Self {
+            period,
+            contract_symbol,
+            n_cfds: 0,
+            opening_fees_btc: SignedAmount::ZERO,
+            funding_fees_btc: SignedAmount::ZERO,
+            spread_btc: SignedAmount::ZERO,
+            liquidation_btc: SignedAmount::ZERO,
+            total_btc: SignedAmount::ZERO,
+        }
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        // Fees are reported from our point of view, i.e. fees paid to
+        // us are negative
+        let funding_fees = -trade.funding_paid_btc;
+        let opening_fees = -(trade.fees_btc - trade.funding_paid_btc);
+        let price_movement = trade.realized_pnl_btc + trade.fees_btc;
+
+        self.n_cfds += 1;
+        self.opening_fees_btc += opening_fees;
+        self.funding_fees_btc += funding_fees;
+        match trade.settlement {
+            SettlementKind::Collaborative => self.spread_btc += price_movement,
+            SettlementKind::Cet | SettlementKind::Refund => self.liquidation_btc += price_movement,
+        }
+        self.total_btc += trade.realized_pnl_btc;
+    }
+}
+
+/// Sum up the revenue of the maker's `trades` per `period` and contract
+/// symbol, ordered by period.
+pub fn report(trades: &[Trade], period: Period) -> Result<Vec<Revenue>> {
+    let mut report = Vec::<Revenue>::new();
+
+    for trade in trades.iter().filter(|trade| trade.role == Role::Maker) {
+        let closed_on = OffsetDateTime::from_unix_timestamp(trade.closed_at.seconds())?.date();
+        let start = period.start(closed_on)?.to_string();
+
+        let revenue = match report
+            .iter_mut()
+            .position(|r| r.period == start && r.contract_symbol == trade.contract_symbol)
+        {
+            Some(index) => &mut report[index],
+            None => {
+                report.push(Revenue::new(start, trade.contract_symbol));
+                report.last_mut().expect("just pushed")
+            }
+        };
+        revenue.add(trade);
+    }
+
+    report.sort_by(|a, b| a.period.cmp(&b.period));
+
+    Ok(report)
+}
+
+/// Render the `report` as CSV, with one line per period and contract
+/// symbol and a header line.
+pub fn to_csv(report: &[Revenue]) -> Result<String> {
+    let mut csv = String::new();
+
+    writeln!(
+        csv,
+        "period,contract_symbol,n_cfds,opening_fees_btc,funding_fees_btc,spread_btc,\
+         liquidation_btc,total_btc"
+    )?;
+
+    for revenue in report {
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{}",
+            revenue.period,
+            revenue.contract_symbol,
+            revenue.n_cfds,
+            revenue.opening_fees_btc.to_string_in(Denomination::Bitcoin),
+            revenue.funding_fees_btc.to_string_in(Denomination::Bitcoin),
+            revenue.spread_btc.to_string_in(Denomination::Bitcoin),
+            revenue.liquidation_btc.to_string_in(Denomination::Bitcoin),
+            revenue.total_btc.to_string_in(Denomination::Bitcoin),
+        )?;
+    }
+
+    Ok(csv)
+}
@@ -60,12 +60,31 @@ use std::collections::HashMap;
 use std::fmt;
 use std::ops::RangeInclusive;
 use std::str;
+use strum::IntoEnumIterator;
 use time::Duration;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
 pub const CET_TIMELOCK: u32 = 12;
 
+/// How long after the settlement event of a CFD expired we wait for a rollover onto a superseding
+/// event, before committing the CFD so that its refund timelock starts.
+///
+/// A committed CFD can still be settled with a CET if the attestation shows up late.
+pub const EMERGENCY_COMMIT_AFTER: Duration = Duration::hours(6);
+
+/// Lock times below this threshold are block heights, the ones above are UNIX timestamps.
+const LOCK_TIME_THRESHOLD: u32 = 500_000_000;
+
+/// How far above the maker's block height the lock time of a proposed collab settlement may be.
+///
+/// The taker locks the settlement to its block height, which can be slightly ahead of ours.
+const MAX_LOCK_TIME_AHEAD_OF_MAKER: u32 = 6;
+
+/// The sequence number of inputs which enables the lock time of a transaction without signalling
+/// replaceability.
+const SEQUENCE_ENABLE_LOCK_TIME: u32 = 0xFFFF_FFFE;
+
 // TODO: Clean this up to be a separate type
 pub type OfferId = OrderId;
 
@@ -161,9 +180,16 @@ pub struct Offer {
     /// The creation timestamp as set by the maker
     pub creation_timestamp_maker: Timestamp,
 
+    /// The offer must not be taken after this time
+    pub valid_until: Timestamp,
+
     /// The duration that will be used for calculating the settlement timestamp
     pub settlement_interval: Duration,
 
+    /// The number of blocks after the confirmation of the commit transaction until the refund
+    /// transaction can be published
+    pub refund_timelock: u32,
+
     /// The id of the event to be used for price attestation
     ///
     /// The maker includes this into the Order based on the Oracle announcement to be used.
@@ -173,6 +199,91 @@ pub struct Offer {
     pub funding_rate: FundingRate,
     pub opening_fee: OpeningFee,
     pub lot_size: LotSize,
+
+    /// Fees the maker pays on behalf of the taker, if this is a promotional offer
+    pub fee_subsidy: Option<FeeSubsidy>,
+
+    /// The balance the taker's wallet must hold to take the offer, on top of the margin
+    /// requirement
+    pub min_taker_balance: Option<Amount>,
+}
+
+/// The offers of a maker for one contract symbol, one for each position of the maker
+///
+/// The maker publishes both offers of a contract symbol together, so that withdrawing the
+/// offer for one position is as explicit as replacing it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OfferPair {
+    pub contract_symbol: ContractSymbol,
+    /// The offer in which the maker goes long, i.e. the one takers can go short on
+    pub long: Option<Offer>,
+    /// The offer in which the maker goes short, i.e. the one takers can go long on
+    pub short: Option<Offer>,
+}
+
+impl OfferPair {
+    /// A pair without any offers, withdrawing both offers of `contract_symbol`
+    pub fn empty(contract_symbol: ContractSymbol) -> Self {
+        Self {
+            contract_symbol,
+            long: None,
+            short: None,
+        }
+    }
+
+    /// Group the complete set of offers of a maker into one pair per contract symbol
+    ///
+    /// Contract symbols without offers result in empty pairs. If there are several offers for
+    /// the same contract symbol and position, the last one wins.
+    pub fn from_offers(offers: Vec<Offer>) -> Vec<OfferPair> {
+        let mut pairs = ContractSymbol::iter()
+            .map(OfferPair::empty)
+            .collect::<Vec<_>>();
+
+        for offer in offers.into_iter() {
+            if let Some(pair) = pairs
+                .iter_mut()
+                .find(|pair| pair.contract_symbol == offer.contract_symbol)
+            {
+                match offer.position_maker {
+                    Position::Long => pair.long = Some(offer),
+                    Position::Short => pair.short = Some(offer),
+                }
+            }
+        }
+
+        pairs
+    }
+
+    pub fn get(&self, position_maker: Position) -> Option<&Offer> {
+        match position_maker {
+            Position::Long => self.long.as_ref(),
+            Position::Short => self.short.as_ref(),
+        }
+    }
+
+    pub fn offers(&self) -> impl Iterator<Item = &Offer> {
+        self.long.iter().chain(self.short.iter())
+    }
+
+    pub fn into_offers(self) -> Vec<Offer> {
+        self.long.into_iter().chain(self.short).collect()
+    }
+}
+
+/// Fees which the maker waives for the taker as part of a promotion
+///
+/// Both parties derive the fee account of the CFD from the offer, so a subsidy is reflected in
+/// the payouts of all transactions built during contract setup.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeeSubsidy {
+    /// The taker is not charged the opening fee
+    pub opening_fee: bool,
+    /// The taker is not charged the funding fee for the first settlement interval
+    ///
+    /// This only applies if the taker would pay funding. Funding owed to the taker is never
+    /// waived. Subsequent rollovers are charged as usual.
+    pub initial_funding_fee: bool,
 }
 
 impl Offer {
@@ -183,17 +294,21 @@ impl Offer {
         min_quantity: Contracts,
         max_quantity: Contracts,
         settlement_interval: Duration,
+        refund_timelock: Option<u32>,
         tx_fee_rate: TxFeeRate,
         funding_rate: FundingRate,
         opening_fee: OpeningFee,
         leverage_choices: Vec<Leverage>,
         contract_symbol: ContractSymbol,
         lot_size: LotSize,
+        fee_subsidy: Option<FeeSubsidy>,
+        min_taker_balance: Option<Amount>,
     ) -> Self {
         let oracle_event_id = olivia::next_announcement_after(
             time::OffsetDateTime::now_utc() + settlement_interval,
             contract_symbol,
         );
+        let creation_timestamp_maker = Timestamp::now();
 
         Offer {
             id: OfferId::default(),
@@ -203,36 +318,138 @@ impl Offer {
             leverage_choices,
             contract_symbol,
             position_maker,
-            creation_timestamp_maker: Timestamp::now(),
+            creation_timestamp_maker,
+            valid_until: Self::default_valid_until(creation_timestamp_maker),
             settlement_interval,
+            refund_timelock: refund_timelock
+                .unwrap_or_else(|| Self::default_refund_timelock(settlement_interval)),
             oracle_event_id,
             tx_fee_rate,
             funding_rate,
             opening_fee,
             lot_size,
+            fee_subsidy,
+            min_taker_balance,
+        }
+    }
+
+    /// The balance the taker needs to take the offer with the given `quantity` and `leverage`
+    ///
+    /// This is the margin the taker has to lock up, or the minimum balance the maker requires,
+    /// whichever is higher.
+    pub fn required_taker_balance(&self, quantity: Contracts, leverage: Leverage) -> Amount {
+        let margin = calculate_margin(self.contract_symbol, self.price, quantity, leverage);
+
+        margin.max(self.min_taker_balance.unwrap_or(Amount::ZERO))
+    }
+
+    /// The opening fee the taker is actually charged
+    pub fn charged_opening_fee(&self) -> OpeningFee {
+        match self.fee_subsidy {
+            Some(FeeSubsidy {
+                opening_fee: true, ..
+            }) => OpeningFee::default(),
+            _ => self.opening_fee,
         }
     }
 
-    /// Defines when we consider an order to be outdated
+    /// The funding rate used to compute the initial funding fee the taker is actually charged
+    pub fn charged_initial_funding_rate(&self) -> FundingRate {
+        let taker_pays_funding = match self.position_maker.counter_position() {
+            Position::Long => !self.funding_rate.short_pays_long(),
+            Position::Short => self.funding_rate.short_pays_long(),
+        };
+
+        match self.fee_subsidy {
+            Some(FeeSubsidy {
+                initial_funding_fee: true,
+                ..
+            }) if taker_pays_funding => FundingRate::default(),
+            _ => self.funding_rate,
+        }
+    }
+
+    /// Defines how long an offer is valid for
     ///
-    /// If the maker's offer creation timestamp is older than `OUTDATED_AFTER_MINS` minutes then we
-    /// consider an order to be outdated.
+    /// Offers are valid for `OUTDATED_AFTER_MINS` minutes after their creation, unless the maker
+    /// specifies otherwise.
     const OUTDATED_AFTER_MINS: i64 = 10;
 
+    /// The end of the validity of an offer created at `creation_timestamp_maker`
+    ///
+    /// Used for offers from makers which do not specify the validity of their offers.
+    pub fn default_valid_until(creation_timestamp_maker: Timestamp) -> Timestamp {
+        Timestamp::new(creation_timestamp_maker.seconds() + Self::OUTDATED_AFTER_MINS * 60)
+    }
+
+    /// A factor to be added to the settlement interval for calculating the default refund
+    /// timelock.
+    ///
+    /// The refund timelock is important in case the oracle disappears or never publishes a
+    /// signature. Ideally, both users collaboratively settle in the refund scenario. This
+    /// factor is important if the users do not settle collaboratively.
+    /// `1.5` times the settlement_interval as defined in CFD order should be safe in the
+    /// extreme case where a user publishes the commit transaction right after the contract was
+    /// initialized. In this case, the oracle still has `1.0 *
+    /// cfdorder.settlement_interval` time to attest and no one can publish the refund
+    /// transaction.
+    /// The downside is that if the oracle disappears: the users would only notice at the end
+    /// of the cfd settlement_interval. In this case the users has to wait for another
+    /// `1.5` times of the settlement_interval to get his funds back.
+    const REFUND_THRESHOLD: f32 = 1.5;
+
+    /// The longest refund timelock in blocks we agree to, roughly four weeks
+    ///
+    /// If the oracle disappears, the funds of both parties are locked up until the refund
+    /// timelock expires.
+    pub const MAX_REFUND_TIMELOCK: u32 = 4 * 7 * 144;
+
+    /// The refund timelock in blocks of an offer with the given `settlement_interval`
+    ///
+    /// Used for offers from makers which do not specify the refund timelock of their offers.
+    pub fn default_refund_timelock(settlement_interval: Duration) -> u32 {
+        (settlement_interval * Self::REFUND_THRESHOLD)
+            .as_blocks()
+            .ceil() as u32
+    }
+
+    /// Check that `refund_timelock` leaves enough time to settle a CFD with the given
+    /// `settlement_interval` through a CET before it can be refunded
+    pub fn validate_refund_timelock(
+        refund_timelock: u32,
+        settlement_interval: Duration,
+    ) -> Result<()> {
+        let settlement_interval_in_blocks = settlement_interval.as_blocks().ceil() as u32;
+
+        ensure!(
+            refund_timelock > CET_TIMELOCK,
+            "Refund timelock of {refund_timelock} blocks must exceed the CET timelock of \
+             {CET_TIMELOCK} blocks"
+        );
+        ensure!(
+            refund_timelock >= settlement_interval_in_blocks,
+            "Refund timelock of {refund_timelock} blocks is shorter than the settlement interval \
+             of {settlement_interval_in_blocks} blocks"
+        );
+        ensure!(
+            refund_timelock <= Self::MAX_REFUND_TIMELOCK,
+            "Refund timelock of {refund_timelock} blocks exceeds the maximum of {} blocks",
+            Self::MAX_REFUND_TIMELOCK
+        );
+
+        Ok(())
+    }
+
     /// Defines when we consider the order to be outdated.
     ///
     /// This is used as a safety net to prevent the taker from taking an outdated order.
     pub fn is_safe_to_take(&self, now: OffsetDateTime) -> bool {
-        !self.is_creation_timestamp_outdated(now) && self.is_oracle_event_timestamp_sane(now)
+        !self.is_expired(now) && self.is_oracle_event_timestamp_sane(now)
     }
 
-    /// Check if the the maker's offer creation timestamp is outdated
-    ///
-    /// If the creation timestamp is older than `OUTDATED_AFTER_MINS` minutes the offer is
-    /// considered outdated
-    fn is_creation_timestamp_outdated(&self, now: OffsetDateTime) -> bool {
-        self.creation_timestamp_maker.seconds() + (Self::OUTDATED_AFTER_MINS * 60)
-            < now.unix_timestamp()
+    /// Check if the offer is no longer valid
+    pub fn is_expired(&self, now: OffsetDateTime) -> bool {
+        self.valid_until.seconds() < now.unix_timestamp()
     }
 
     /// Check the oracle event's timestamp for sanity
@@ -323,7 +540,11 @@ pub enum EventKind {
     },
 
     ContractSetupFailed,
-    OfferRejected,
+    /// The maker rejected the order.
+    ///
+    /// This is a newtype variant so that events recorded before rejection reasons existed, which
+    /// carry no data, still deserialize.
+    OfferRejected(Option<RejectReason>),
 
     RolloverStarted,
     RolloverAccepted,
@@ -352,9 +573,19 @@ pub enum EventKind {
         price: Price,
     },
     CollaborativeSettlementRejected,
+    /// The settlement proposal was neither accepted nor rejected within its time-to-live.
+    ///
+    /// Unlike a failed settlement this is an expected outcome. The CFD returns to being open and
+    /// settlement can be proposed again right away.
+    CollaborativeSettlementProposalExpired,
     // TODO: We can distinguish different "failed" scenarios and potentially decide to publish the
     // commit transaction for some
     CollaborativeSettlementFailed,
+    /// The maker did not complete a settlement after we had signed the settlement transaction,
+    /// upon which the CFD is committed to the blockchain.
+    CollaborativeSettlementAbandoned {
+        reason: String,
+    },
 
     LockConfirmed,
     /// The lock transaction is confirmed after CFD was closed
@@ -401,6 +632,48 @@ pub enum EventKind {
         #[serde(with = "hex_transaction")]
         tx: Transaction,
     },
+    /// The market price reached a trigger price configured by the user, upon which the CFD is
+    /// closed automatically.
+    PriceTriggered {
+        trigger: PriceTrigger,
+        price: Price,
+    },
+}
+
+/// Why the maker rejected an order.
+#[derive(
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    Debug,
+    Clone,
+    Copy,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum RejectReason {
+    /// The maker cannot fund its margin.
+    InsufficientMakerBalance,
+    /// The offer expired or was replaced by a newer one.
+    OfferOutdated,
+    /// The quantity is not within the bounds of the offer.
+    QuantityOutOfBounds,
+    /// The maker does not trade with the taker.
+    TakerBlocked,
+    /// The taker failed too many protocols or has too much open
+    /// notional with the maker.
+    TakerLimitExceeded,
+}
+
+/// Kinds of trigger prices which close a CFD automatically.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum PriceTrigger {
+    /// Limit the loss if the price moves against the position.
+    StopLoss,
+    /// Realise the profit if the price moves in favour of the position.
+    TakeProfit,
 }
 
 impl fmt::Display for EventKind {
@@ -410,7 +683,7 @@ impl fmt::Display for EventKind {
             ContractSetupStarted => "ContractSetupStarted",
             ContractSetupCompleted { .. } => "ContractSetupCompleted",
             ContractSetupFailed => "ContractSetupFailed",
-            OfferRejected => "OfferRejected",
+            OfferRejected(_) => "OfferRejected",
             RolloverStarted => "RolloverStarted",
             RolloverAccepted => "RolloverAccepted",
             RolloverRejected => "RolloverRejected",
@@ -420,7 +693,9 @@ impl fmt::Display for EventKind {
             CollaborativeSettlementProposalAccepted => "CollaborativeSettlementProposalAccepted",
             CollaborativeSettlementCompleted { .. } => "CollaborativeSettlementCompleted",
             CollaborativeSettlementRejected => "CollaborativeSettlementRejected",
+            CollaborativeSettlementProposalExpired => "CollaborativeSettlementProposalExpired",
             CollaborativeSettlementFailed => "CollaborativeSettlementFailed",
+            CollaborativeSettlementAbandoned { .. } => "CollaborativeSettlementAbandoned",
             LockConfirmed => "LockConfirmed",
             LockConfirmedAfterFinality => "LockConfirmedAfterFinality",
             CommitConfirmed => "CommitConfirmed",
@@ -436,6 +711,7 @@ impl fmt::Display for EventKind {
             OracleAttestedPriorCetTimelock { .. } => "OracleAttestedPriorCetTimelock",
             OracleAttestedPostCetTimelock { .. } => "OracleAttestedPostCetTimelock",
             ManualCommit { .. } => "ManualCommit",
+            PriceTriggered { .. } => "PriceTriggered",
         };
 
         s.fmt(f)
@@ -488,7 +764,7 @@ impl EventKind {
 /// we apply the event to the aggregate producing a new aggregate (representing the latest state
 /// `version`). To bring a cfd into a certain state version we load all events from the
 /// database and apply them in order (order by version).
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Cfd {
     version: u32,
 
@@ -501,6 +777,12 @@ pub struct Cfd {
     long_leverage: Leverage,
     short_leverage: Leverage,
     settlement_interval: Duration,
+    /// The refund timelock in blocks agreed upon in the offer
+    ///
+    /// Not known for CFDs which were set up before the refund timelock was part of the offer,
+    /// they fall back to the default refund timelock of their settlement interval.
+    #[serde(default)]
+    refund_timelock: Option<u32>,
     quantity: Contracts,
     counterparty_network_identity: Identity,
     counterparty_peer_id: Option<PeerId>,
@@ -517,6 +799,7 @@ pub struct Cfd {
     ///
     /// There is not guarantee that the transaction is confirmed if this is set to `Some`.
     /// However, if this is set to `Some`, there is no need to re-emit it as part of another event.
+    #[serde(with = "hex_transaction::opt")]
     cet: Option<Transaction>,
 
     /// Holds the decrypted commit transaction if we have previously emitted it as part of an
@@ -524,9 +807,12 @@ pub struct Cfd {
     ///
     /// There is not guarantee that the transaction is confirmed if this is set to `Some`.
     /// However, if this is set to `Some`, there is no need to re-emit it as part of another event.
+    #[serde(with = "hex_transaction::opt")]
     commit_tx: Option<Transaction>,
 
+    #[serde(with = "hex_transaction::opt")]
     collaborative_settlement_spend_tx: Option<Transaction>,
+    #[serde(with = "hex_transaction::opt")]
     refund_tx: Option<Transaction>,
 
     lock_finality: bool,
@@ -587,6 +873,7 @@ impl Cfd {
             long_leverage,
             short_leverage,
             settlement_interval,
+            refund_timelock: None,
             quantity,
             counterparty_network_identity,
             counterparty_peer_id,
@@ -642,11 +929,18 @@ impl Cfd {
             quantity,
             counterparty_network_identity,
             counterparty_peer_id,
-            offer.opening_fee,
-            offer.funding_rate,
+            offer.charged_opening_fee(),
+            offer.charged_initial_funding_rate(),
             offer.tx_fee_rate,
             offer.contract_symbol,
         )
+        .with_refund_timelock(offer.refund_timelock)
+    }
+
+    /// Set the refund timelock in blocks agreed upon in the offer
+    pub fn with_refund_timelock(mut self, refund_timelock: u32) -> Self {
+        self.refund_timelock = Some(refund_timelock);
+        self
     }
 
     fn margin(&self) -> Amount {
@@ -708,6 +1002,16 @@ impl Cfd {
         Ok((dlc.commit.0.txid(), dlc.settlement_event_id))
     }
 
+    /// When the taker's next rollover becomes due, at which point the
+    /// funding fee for the following settlement interval is paid.
+    ///
+    /// `None` if there is no DLC.
+    pub fn next_funding_window(&self) -> Option<OffsetDateTime> {
+        let dlc = self.dlc.as_ref()?;
+
+        Some(dlc.settlement_event_id.timestamp() - (SETTLEMENT_INTERVAL - Duration::HOUR))
+    }
+
     fn can_rollover(&self) -> Result<(), CannotRollover> {
         if self.is_closed() {
             return Err(CannotRollover::Closed);
@@ -796,6 +1100,9 @@ impl Cfd {
             bail!("Start contract not allowed in version {}", self.version)
         }
 
+        Offer::validate_refund_timelock(self.refund_timelock_in_blocks(), self.settlement_interval)
+            .context("Invalid refund timelock")?;
+
         Ok((
             CfdEvent::new(self.id(), EventKind::ContractSetupStarted),
             SetupParams::new(
@@ -1012,18 +1319,31 @@ impl Cfd {
         ))
     }
 
+    /// Propose to settle the CFD collaboratively at `current_price`.
+    ///
+    /// The settlement transaction is locked to the block height `lock_time` to discourage fee
+    /// sniping, unless it is `0`.
     pub fn start_collab_settlement_taker(
         self,
         current_price: Price,
         n_payouts: usize,
+        lock_time: u32,
     ) -> Result<(CfdEvent, SettlementTransaction, SettlementProposal)> {
         ensure!(!self.is_in_collaborative_settlement());
         ensure!(self.role == Role::Taker);
         self.can_settle_collaboratively()
             .context("Cannot collaboratively settle")?;
+        ensure!(
+            lock_time < LOCK_TIME_THRESHOLD,
+            "Lock time {lock_time} of settlement transaction is not a block height"
+        );
 
-        let (collab_settlement_tx, proposal) =
-            self.make_proposal(current_price, n_payouts, InverseMaxPrice::OliviaMax)?;
+        let (collab_settlement_tx, proposal) = self.make_proposal(
+            current_price,
+            n_payouts,
+            lock_time,
+            InverseMaxPrice::OliviaMax,
+        )?;
 
         Ok((
             CfdEvent::new(
@@ -1045,11 +1365,13 @@ impl Cfd {
         current_price: Price,
         n_payouts: usize,
         proposed_settlement_transaction: &Transaction,
+        block_height: u32,
     ) -> Result<(CfdEvent, SettlementTransaction, SettlementProposal)> {
         self.start_collab_settlement_maker(
             current_price,
             n_payouts,
             proposed_settlement_transaction,
+            block_height,
             InverseMaxPrice::OliviaMax,
         )
     }
@@ -1064,11 +1386,13 @@ impl Cfd {
         current_price: Price,
         n_payouts: usize,
         proposed_settlement_transaction: &Transaction,
+        block_height: u32,
     ) -> Result<(CfdEvent, SettlementTransaction, SettlementProposal)> {
         self.start_collab_settlement_maker(
             current_price,
             n_payouts,
             proposed_settlement_transaction,
+            block_height,
             InverseMaxPrice::DoubleOfInitial,
         )
     }
@@ -1078,6 +1402,7 @@ impl Cfd {
         current_price: Price,
         n_payouts: usize,
         proposed_settlement_transaction: &Transaction,
+        block_height: u32,
         inverse_max_price_config: InverseMaxPrice,
     ) -> Result<(CfdEvent, SettlementTransaction, SettlementProposal)> {
         ensure!(!self.is_in_collaborative_settlement());
@@ -1085,8 +1410,24 @@ impl Cfd {
         self.can_settle_collaboratively()
             .context("Cannot collaboratively settle")?;
 
-        let (settlement_tx, proposal) =
-            self.make_proposal(current_price, n_payouts, inverse_max_price_config)?;
+        // Takers which do not protect against fee sniping propose a lock time of 0
+        let lock_time = proposed_settlement_transaction.lock_time;
+        ensure!(
+            lock_time < LOCK_TIME_THRESHOLD,
+            "Lock time {lock_time} of proposed collab settlement is not a block height"
+        );
+        // Otherwise the settlement could not be mined for a long time, blocking the CFD
+        ensure!(
+            lock_time <= block_height.saturating_add(MAX_LOCK_TIME_AHEAD_OF_MAKER),
+            "Lock time {lock_time} of proposed collab settlement is too far above our block height {block_height}"
+        );
+
+        let (settlement_tx, proposal) = self.make_proposal(
+            current_price,
+            n_payouts,
+            lock_time,
+            inverse_max_price_config,
+        )?;
 
         let local_settlement_transaction = settlement_tx.unsigned_transaction();
 
@@ -1109,6 +1450,7 @@ impl Cfd {
         self,
         current_price: Price,
         n_payouts: usize,
+        lock_time: u32,
         inverse_max_price_config: InverseMaxPrice,
     ) -> Result<(SettlementTransaction, SettlementProposal)> {
         let payouts = match self.contract_symbol {
@@ -1148,6 +1490,7 @@ impl Cfd {
             *payout.taker_amount(),
             current_price,
             self.role,
+            lock_time,
         )?;
 
         let proposal = SettlementProposal {
@@ -1189,14 +1532,19 @@ impl Cfd {
         Ok(self.event(EventKind::ContractSetupCompleted { dlc: Some(dlc) }))
     }
 
-    pub fn reject_contract_setup(self, reason: anyhow::Error) -> Result<CfdEvent> {
+    pub fn reject_contract_setup(self, reason: Option<RejectReason>) -> Result<CfdEvent> {
         let version = self.version;
         ensure!(
             version <= 1,
             "Rejecting contract setup not allowed because cfd in version {version}",
         );
 
-        Ok(self.event_with_error(EventKind::OfferRejected, reason))
+        let error = match reason {
+            Some(reason) => anyhow!("{reason}"),
+            None => anyhow!("Unknown"),
+        };
+
+        Ok(self.event_with_error(EventKind::OfferRejected(reason), error))
     }
 
     pub fn fail_contract_setup(self, error: anyhow::Error) -> CfdEvent {
@@ -1249,6 +1597,56 @@ impl Cfd {
         self.event_with_error(EventKind::CollaborativeSettlementFailed, error)
     }
 
+    /// Give up on a collaborative settlement which the maker did not complete, recording why,
+    /// before committing the CFD to the blockchain.
+    ///
+    /// Having signed the settlement transaction, we cannot just fail the settlement: the maker
+    /// could still publish the transaction, hence the CFD must not be settled at another price.
+    pub fn abandon_collaborative_settlement(&self, reason: String) -> Result<CfdEvent> {
+        ensure!(
+            self.is_in_collaborative_settlement(),
+            "Cannot abandon collaborative settlement which is not in progress"
+        );
+
+        Ok(self.event(EventKind::CollaborativeSettlementAbandoned { reason }))
+    }
+
+    /// Expire the pending settlement proposal because it was not decided upon within its
+    /// time-to-live.
+    ///
+    /// Fails if there is no pending proposal, e.g. because the settlement already completed.
+    pub fn expire_collaborative_settlement_proposal(self) -> Result<CfdEvent> {
+        ensure!(
+            self.is_in_collaborative_settlement(),
+            "No pending settlement proposal to expire"
+        );
+
+        Ok(self.event(EventKind::CollaborativeSettlementProposalExpired))
+    }
+
+    /// Conclude a collaborative settlement which was interrupted by a restart.
+    ///
+    /// The protocol cannot be continued on a new connection. If both signatures were collected
+    /// before the interruption, the `settlement` is completed anyway, otherwise it fails. Returns
+    /// `None` if the CFD is not in collaborative settlement.
+    pub fn resume_collaborative_settlement(
+        self,
+        settlement: Option<CollaborativeSettlement>,
+    ) -> Option<CfdEvent> {
+        if !self.is_in_collaborative_settlement() {
+            return None;
+        }
+
+        let event = match settlement {
+            Some(settlement) => self.complete_collaborative_settlement(settlement),
+            None => self.fail_collaborative_settlement(anyhow!(
+                "Collaborative settlement was interrupted by a restart"
+            )),
+        };
+
+        Some(event)
+    }
+
     /// Given an attestation, find and decrypt the relevant CET.
     ///
     /// In case the Cfd was already closed we return `Ok(None)`, because then the attestation is not
@@ -1358,6 +1756,11 @@ impl Cfd {
         self.event(EventKind::RevokeConfirmed)
     }
 
+    /// The txid of the commit transaction, once the CFD has a DLC.
+    pub fn commit_txid(&self) -> Option<Txid> {
+        self.dlc.as_ref().map(|dlc| dlc.commit.0.txid())
+    }
+
     pub fn manual_commit_to_blockchain(&self) -> Result<CfdEvent> {
         ensure!(!self.is_closed());
 
@@ -1368,6 +1771,59 @@ impl Cfd {
         }))
     }
 
+    /// The settlement event of the CFD if it expired by `now` without being attested.
+    ///
+    /// The CFD is stuck until it is rolled over onto a superseding event, or committed.
+    pub fn expired_settlement_event(&self, now: OffsetDateTime) -> Option<BitMexPriceEventId> {
+        if !self.is_position_open() || self.is_attested() {
+            return None;
+        }
+
+        let settlement_event_id = self.dlc.as_ref()?.settlement_event_id;
+
+        settlement_event_id
+            .is_expired(now)
+            .then_some(settlement_event_id)
+    }
+
+    /// Commit the CFD if its settlement event expired more than [`EMERGENCY_COMMIT_AFTER`] ago,
+    /// without the CFD being rolled over onto a superseding event.
+    pub fn emergency_commit(&self, now: OffsetDateTime) -> Result<Option<CfdEvent>> {
+        let settlement_event_id = match self.expired_settlement_event(now) {
+            Some(settlement_event_id) => settlement_event_id,
+            None => return Ok(None),
+        };
+
+        let expired_at = settlement_event_id.timestamp() + olivia::ATTESTATION_DEADLINE;
+        if now < expired_at + EMERGENCY_COMMIT_AFTER {
+            return Ok(None);
+        }
+
+        tracing::warn!(
+            order_id = %self.id,
+            %settlement_event_id,
+            "Committing CFD because its settlement event was not attested"
+        );
+
+        self.manual_commit_to_blockchain().map(Some)
+    }
+
+    /// Record that the market price reached a trigger price, before closing the CFD.
+    pub fn record_price_trigger(&self, trigger: PriceTrigger, price: Price) -> Result<CfdEvent> {
+        ensure!(
+            self.is_position_open(),
+            "Cannot trigger {trigger:?} of CFD without an open position"
+        );
+
+        Ok(self.event(EventKind::PriceTriggered { trigger, price }))
+    }
+
+    /// Whether the position of the CFD is open, i.e. it can still be
+    /// closed by settling collaboratively or committing to the blockchain.
+    pub fn is_position_open(&self) -> bool {
+        self.dlc.is_some() && !self.is_closed() && !self.is_in_force_close()
+    }
+
     fn event_with_error(&self, event: EventKind, error: anyhow::Error) -> CfdEvent {
         self.log_cfd_event(&event, Some(error));
         CfdEvent::new(self.id, event)
@@ -1388,7 +1844,8 @@ impl Cfd {
             EventKind::ContractSetupFailed
             | EventKind::RolloverFailed
             | EventKind::CollaborativeSettlementFailed
-            | EventKind::OfferRejected
+            | EventKind::CollaborativeSettlementAbandoned { .. }
+            | EventKind::OfferRejected(_)
             | EventKind::RolloverRejected
             | EventKind::CollaborativeSettlementRejected
             | EventKind::CetConfirmed
@@ -1402,26 +1859,10 @@ impl Cfd {
         }
     }
 
-    /// A factor to be added to the CFD order settlement_interval for calculating the
-    /// refund timelock.
-    ///
-    /// The refund timelock is important in case the oracle disappears or never publishes a
-    /// signature. Ideally, both users collaboratively settle in the refund scenario. This
-    /// factor is important if the users do not settle collaboratively.
-    /// `1.5` times the settlement_interval as defined in CFD order should be safe in the
-    /// extreme case where a user publishes the commit transaction right after the contract was
-    /// initialized. In this case, the oracle still has `1.0 *
-    /// cfdorder.settlement_interval` time to attest and no one can publish the refund
-    /// transaction.
-    /// The downside is that if the oracle disappears: the users would only notice at the end
-    /// of the cfd settlement_interval. In this case the users has to wait for another
-    /// `1.5` times of the settlement_interval to get his funds back.
-    const REFUND_THRESHOLD: f32 = 1.5;
-
-    fn refund_timelock_in_blocks(&self) -> u32 {
-        (self.settlement_interval * Self::REFUND_THRESHOLD)
-            .as_blocks()
-            .ceil() as u32
+    /// The refund timelock in blocks the DLC of this CFD is set up and rolled over with
+    pub fn refund_timelock_in_blocks(&self) -> u32 {
+        self.refund_timelock
+            .unwrap_or_else(|| Offer::default_refund_timelock(self.settlement_interval))
     }
 
     pub fn id(&self) -> OrderId {
@@ -1467,6 +1908,15 @@ impl Cfd {
         self.role
     }
 
+    /// The justice kits to punish the revoked commit transactions of
+    /// this CFD, see [`crate::punish`].
+    pub fn justice_kits(&self) -> Result<Vec<crate::punish::JusticeKit>> {
+        match &self.dlc {
+            Some(dlc) => dlc.justice_kits(self.role),
+            None => Ok(Vec::new()),
+        }
+    }
+
     pub fn initial_funding_rate(&self) -> FundingRate {
         self.initial_funding_rate
     }
@@ -1596,7 +2046,10 @@ impl Cfd {
                 self.settlement_proposal = None;
                 self.collaborative_settlement_spend_tx = Some(spend_tx);
             }
-            CollaborativeSettlementRejected | CollaborativeSettlementFailed => {
+            CollaborativeSettlementRejected
+            | CollaborativeSettlementFailed
+            | CollaborativeSettlementAbandoned { .. }
+            | CollaborativeSettlementProposalExpired => {
                 self.settlement_proposal = None;
             }
             CetConfirmed => self.cet_finality = true,
@@ -1610,11 +2063,12 @@ impl Cfd {
             | CetTimelockExpiredPostOracleAttestation { .. } => {
                 self.cet_timelock_expired = true;
             }
-            OfferRejected => {
+            OfferRejected(_) => {
                 // nothing to do here? A rejection means it should be impossible to issue any
                 // commands
             }
             ManualCommit { tx } => self.commit_tx = Some(tx),
+            PriceTriggered { .. } => {}
             RevokeConfirmed => {
                 tracing::error!(order_id = %self.id, "Revoked logic not implemented");
                 // TODO: we should punish the other party instead. For now, we pretend we are in
@@ -1637,6 +2091,20 @@ pub trait ExecuteOnCfd {
     where
         T: ExtractEventFromTuple + Send,
         T::Rest: Send;
+
+    /// Like [`ExecuteOnCfd::execute`], but executes `command` again on the latest state of the
+    /// CFD if another event was stored for the CFD while executing it.
+    ///
+    /// Use for commands which race with commands of other protocols on the same CFD, e.g.
+    /// starting a rollover while a collaborative settlement is being started.
+    async fn execute_with_retry<T>(
+        &self,
+        id: OrderId,
+        command: impl Fn(Cfd) -> Result<T> + Send + Sync,
+    ) -> Result<T::Rest>
+    where
+        T: ExtractEventFromTuple + Send,
+        T::Rest: Send;
 }
 
 // TODO: Delete this weird thing once all our commands return only an `Event` and not other stuff as
@@ -1855,6 +2323,90 @@ pub fn calculate_profit(payout: Amount, margin: Amount) -> (SignedAmount, Percen
     (profit, Percent(percent))
 }
 
+/// Compute the effective entry price of a CFD, including all `fees` paid so far.
+///
+/// Fees are positive if we paid them. The effective entry price is the opening price of a CFD
+/// without fees whose profit equals the profit of our CFD after fees at any closing price, i.e. it
+/// is the break-even price.
+///
+/// Returns `None` if the fees are too large to be expressed as a price, e.g. if they exceed the
+/// notional value of an inverse long position.
+pub fn calculate_effective_entry_price(
+    contract_symbol: ContractSymbol,
+    position: Position,
+    initial_price: Price,
+    quantity: Contracts,
+    fees: SignedAmount,
+) -> Option<Price> {
+    // Paying fees has the same effect on our profit as opening at the price at which the
+    // opposite position would have lost the fees
+    price_at_profit(
+        contract_symbol,
+        position.counter_position(),
+        initial_price,
+        quantity,
+        SignedAmount::from_sat(-fees.as_sat()),
+    )
+}
+
+/// Compute the effective exit price of a closed CFD from its `realized_pnl`.
+///
+/// The effective exit price is the closing price at which a CFD without fees opened at the
+/// `effective_entry_price` (see [`calculate_effective_entry_price`]) would have made the realized
+/// profit. Everything by which the payout differs from the theoretical payout at the closing
+/// price, e.g. the transaction fees deducted from it, is therefore reflected in this price.
+pub fn calculate_effective_exit_price(
+    contract_symbol: ContractSymbol,
+    position: Position,
+    effective_entry_price: Price,
+    quantity: Contracts,
+    realized_pnl: SignedAmount,
+) -> Option<Price> {
+    price_at_profit(
+        contract_symbol,
+        position,
+        effective_entry_price,
+        quantity,
+        realized_pnl,
+    )
+}
+
+/// The closing price at which a `position` without fees opened at `initial_price` makes `profit`.
+fn price_at_profit(
+    contract_symbol: ContractSymbol,
+    position: Position,
+    initial_price: Price,
+    quantity: Contracts,
+    profit: SignedAmount,
+) -> Option<Price> {
+    let profit = Decimal::from(profit.as_sat()) / dec!(100_000_000);
+    let initial_price = initial_price.into_decimal();
+    let quantity = quantity.into_decimal();
+
+    let price = match contract_symbol {
+        // The profit of a long position is `quantity * (1 / initial_price - 1 / price)`
+        ContractSymbol::BtcUsd => {
+            let profit_per_contract = profit.checked_div(quantity)?;
+            let inverse_price = match position {
+                Position::Long => Decimal::ONE / initial_price - profit_per_contract,
+                Position::Short => Decimal::ONE / initial_price + profit_per_contract,
+            };
+
+            Decimal::ONE.checked_div(inverse_price)?
+        }
+        // The profit of a long position is `(price - initial_price) * multiplier * quantity`
+        ContractSymbol::EthUsd => {
+            let price_difference = profit.checked_div(ETHUSD_MULTIPLIER * quantity)?;
+            match position {
+                Position::Long => initial_price + price_difference,
+                Position::Short => initial_price - price_difference,
+            }
+        }
+    };
+
+    Price::new(price).ok()
+}
+
 /// Compute the liquidation price for the party going long.
 pub fn calculate_long_liquidation_price(
     initial_price: Price,
@@ -2069,12 +2621,18 @@ impl SettlementTransaction {
 }
 
 impl Dlc {
+    /// Build and sign the transaction settling the CFD collaboratively.
+    ///
+    /// Unless `lock_time` is `0`, the transaction cannot be mined before the block at that height.
+    /// Locking it to the current height, like the wallet does for its own spends, means that
+    /// miners gain nothing by reorganising the chain to take its fee.
     pub fn collab_settlement_transaction(
         &self,
         payout_maker: Amount,
         payout_taker: Amount,
         current_price: Price,
         role: Role,
+        lock_time: u32,
     ) -> Result<SettlementTransaction> {
         let (lock_tx, lock_desc) = &self.lock;
         let (lock_outpoint, lock_amount) = {
@@ -2086,7 +2644,7 @@ impl Dlc {
             (outpoint, amount)
         };
 
-        let (tx, sighash) = maia::close_transaction(
+        let (mut tx, sighash) = maia::close_transaction(
             lock_desc,
             lock_outpoint,
             lock_amount,
@@ -2096,6 +2654,18 @@ impl Dlc {
         )
         .context("Unable to build collaborative close transaction")?;
 
+        let sighash = if lock_time == 0 {
+            sighash
+        } else {
+            tx.lock_time = lock_time;
+            for input in tx.input.iter_mut() {
+                input.sequence = SEQUENCE_ENABLE_LOCK_TIME;
+            }
+
+            spending_tx_sighash(&tx, lock_desc, lock_amount)
+                .context("Could not obtain sighash of collaborative close transaction")?
+        };
+
         let own_signature = SECP256K1.sign_ecdsa(&sighash, &self.identity);
 
         let own_pk = bitcoin::PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(
@@ -2180,6 +2750,30 @@ impl Dlc {
         Ok(signed_refund_tx)
     }
 
+    /// The number of confirmations of the commit transaction after which the refund transaction
+    /// can be published
+    ///
+    /// This is the relative timelock of the refund transaction itself, which is what the network
+    /// enforces. The refund timelock recorded with the DLC is only used if the refund transaction
+    /// does not carry a relative timelock in blocks.
+    pub fn refund_timelock_in_blocks(&self) -> u32 {
+        // See BIP 68
+        const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+        const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+        const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+
+        match self.refund.0.input.first() {
+            Some(input)
+                if input.sequence
+                    & (SEQUENCE_LOCKTIME_DISABLE_FLAG | SEQUENCE_LOCKTIME_TYPE_FLAG)
+                    == 0 =>
+            {
+                input.sequence & SEQUENCE_LOCKTIME_MASK
+            }
+            _ => self.refund_timelock,
+        }
+    }
+
     pub fn signed_commit_tx(&self) -> Result<Transaction> {
         let sig_hash = spending_tx_sighash(
             &self.commit.0,
@@ -2378,6 +2972,14 @@ pub struct RevokedCommit {
     pub txid: Txid,
     pub script_pubkey: Script,
 
+    /// The descriptor of the output of the revoked commit tx
+    ///
+    /// This is used to punish the revoked commit tx without access to
+    /// our keys, e.g. by a watchtower. `None` for commit txs which were
+    /// revoked before the descriptor was recorded.
+    #[serde(default)]
+    pub commit_descriptor: Option<Descriptor<PublicKey>>,
+
     /// The settlement_event_id that was associated to this commit tx
     ///
     /// This is used to enable rolling over from a previous commit-txid.
@@ -2647,20 +3249,126 @@ mod tests {
         );
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn assert_profit_loss_values(
-        contract_symbol: ContractSymbol,
-        initial_price: Price,
-        closing_price: Price,
-        quantity: Contracts,
-        leverage_long: Leverage,
-        leverage_short: Leverage,
-        fee_account: FeeAccount,
-        should_profit: SignedAmount,
-        should_profit_in_percent: Percent,
-        msg: &str,
-    ) {
-        // TODO: Assert on payout as well
+    #[test]
+    fn effective_entry_price_is_worse_than_initial_price_by_the_fees() {
+        let effective_entry_price = |contract_symbol, position, price, quantity, fees| {
+            calculate_effective_entry_price(
+                contract_symbol,
+                position,
+                Price::new(price).unwrap(),
+                Contracts::new(quantity),
+                SignedAmount::from_sat(fees),
+            )
+            .unwrap()
+            .into_decimal()
+            .round_dp(2)
+        };
+
+        assert_eq!(
+            effective_entry_price(
+                ContractSymbol::BtcUsd,
+                Position::Long,
+                dec!(10_000),
+                10_000,
+                0
+            ),
+            dec!(10_000)
+        );
+        assert_eq!(
+            effective_entry_price(
+                ContractSymbol::BtcUsd,
+                Position::Long,
+                dec!(10_000),
+                10_000,
+                100_000
+            ),
+            dec!(10_010.01)
+        );
+        assert_eq!(
+            effective_entry_price(
+                ContractSymbol::BtcUsd,
+                Position::Short,
+                dec!(10_000),
+                10_000,
+                100_000
+            ),
+            dec!(9_990.01)
+        );
+        assert_eq!(
+            effective_entry_price(
+                ContractSymbol::EthUsd,
+                Position::Long,
+                dec!(1_500),
+                100,
+                1_000
+            ),
+            dec!(1_500.1)
+        );
+        assert_eq!(
+            effective_entry_price(
+                ContractSymbol::EthUsd,
+                Position::Short,
+                dec!(1_500),
+                100,
+                1_000
+            ),
+            dec!(1_499.9)
+        );
+        assert_eq!(
+            effective_entry_price(
+                ContractSymbol::EthUsd,
+                Position::Short,
+                dec!(1_500),
+                100,
+                -1_000
+            ),
+            dec!(1_500.1),
+            "fees received improve the effective entry price"
+        );
+    }
+
+    #[test]
+    fn effective_exit_price_without_fees_is_closing_price() {
+        // A long position of 10_000 contracts from 10_000 to 12_500 makes 0.2 BTC
+        let effective_exit_price = calculate_effective_exit_price(
+            ContractSymbol::BtcUsd,
+            Position::Long,
+            Price::new(dec!(10_000)).unwrap(),
+            Contracts::new(10_000),
+            SignedAmount::from_sat(20_000_000),
+        )
+        .unwrap();
+
+        assert_eq!(effective_exit_price, Price::new(dec!(12_500)).unwrap());
+    }
+
+    #[test]
+    fn given_fees_larger_than_notional_then_no_effective_entry_price() {
+        let effective_entry_price = calculate_effective_entry_price(
+            ContractSymbol::BtcUsd,
+            Position::Long,
+            Price::new(dec!(10_000)).unwrap(),
+            Contracts::new(100),
+            SignedAmount::from_btc(1.0).unwrap(),
+        );
+
+        assert_eq!(effective_entry_price, None);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn assert_profit_loss_values(
+        contract_symbol: ContractSymbol,
+        initial_price: Price,
+        closing_price: Price,
+        quantity: Contracts,
+        leverage_long: Leverage,
+        leverage_short: Leverage,
+        fee_account: FeeAccount,
+        should_profit: SignedAmount,
+        should_profit_in_percent: Percent,
+        msg: &str,
+    ) {
+        // TODO: Assert on payout as well
 
         let margin = match fee_account.position {
             Position::Long => {
@@ -2847,6 +3555,60 @@ mod tests {
         assert_eq!(event, EventKind::ContractSetupFailed);
     }
 
+    #[test]
+    fn given_collab_settlement_when_abandoned_then_can_commit() {
+        let taker_keys = new_keypair();
+        let maker_keys = new_keypair();
+        let price = Price::new(dec!(10000)).unwrap();
+
+        let cfd = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .with_lock(taker_keys, maker_keys);
+        let (started, _, _) = cfd
+            .clone()
+            .start_collab_settlement_taker(price, N_PAYOUTS, 0)
+            .unwrap();
+        let cfd = cfd.apply(started);
+
+        let abandoned = cfd
+            .abandon_collaborative_settlement("Maker went away".to_owned())
+            .unwrap();
+        let cfd = cfd.apply(abandoned.clone());
+        let commit = cfd.manual_commit_to_blockchain().unwrap();
+
+        assert_eq!(
+            abandoned.event,
+            EventKind::CollaborativeSettlementAbandoned {
+                reason: "Maker went away".to_owned()
+            }
+        );
+        assert!(!cfd.is_in_collaborative_settlement());
+        assert!(matches!(commit.event, EventKind::ManualCommit { .. }));
+    }
+
+    #[test]
+    fn given_no_collab_settlement_then_cannot_abandon_it() {
+        let cfd = Cfd::dummy_taker_long().dummy_open(dummy_event_id());
+
+        let result = cfd.abandon_collaborative_settlement("Maker went away".to_owned());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn price_triggered_event_records_trigger_reason() {
+        let event = EventKind::PriceTriggered {
+            trigger: PriceTrigger::TakeProfit,
+            price: Price::new(dec!(21_000)).unwrap(),
+        };
+
+        let (name, data) = event.to_json();
+        let deserialized = EventKind::from_json(name.clone(), data).unwrap();
+
+        assert_eq!(name, "PriceTriggered");
+        assert_eq!(deserialized, event);
+    }
+
     #[test]
     fn cfd_ensure_stable_names_for_expensive_events() {
         let (rollover_event_name, _) = EventKind::RolloverCompleted {
@@ -2878,7 +3640,7 @@ mod tests {
         let (cet_confirmed, _) = EventKind::CetConfirmed.to_json();
         let (refund_confirmed, _) = EventKind::RefundConfirmed.to_json();
         let (setup_failed, _) = EventKind::ContractSetupFailed.to_json();
-        let (rejected, _) = EventKind::OfferRejected.to_json();
+        let (rejected, _) = EventKind::OfferRejected(None).to_json();
 
         assert_eq!(
             collaborative_settlement_confirmed,
@@ -2897,7 +3659,17 @@ mod tests {
 
         let event = EventKind::from_json(name, data).unwrap();
 
-        assert_eq!(event, EventKind::OfferRejected);
+        assert_eq!(event, EventKind::OfferRejected(None));
+    }
+
+    #[test]
+    fn offer_rejected_with_reason_roundtrips_through_json() {
+        let event = EventKind::OfferRejected(Some(RejectReason::QuantityOutOfBounds));
+
+        let (name, data) = event.to_json();
+        let deserialized = EventKind::from_json(name, data).unwrap();
+
+        assert_eq!(deserialized, event);
     }
 
     #[test]
@@ -3113,6 +3885,39 @@ mod tests {
     /// If we trigger a collaborative settlement during rollover, the settlement will have priority
     /// over the rollover. Upon finishing the rollover we fail because the cfd was already
     /// settled.
+    #[test]
+    fn given_settlement_event_not_attested_past_deadline_then_expired() {
+        let event_id = dummy_event_id();
+        let cfd = Cfd::dummy_taker_long().dummy_open(event_id);
+
+        let within_deadline = event_id.timestamp() + olivia::ATTESTATION_DEADLINE;
+        let past_deadline = within_deadline + Duration::seconds(1);
+
+        assert_eq!(cfd.expired_settlement_event(within_deadline), None);
+        assert_eq!(cfd.expired_settlement_event(past_deadline), Some(event_id));
+    }
+
+    #[test]
+    fn given_committed_cfd_then_settlement_event_does_not_expire() {
+        let event_id = dummy_event_id();
+        let cfd = Cfd::dummy_taker_long().dummy_open(event_id).dummy_commit();
+
+        let past_deadline = event_id.timestamp() + olivia::ATTESTATION_DEADLINE + Duration::HOUR;
+
+        assert_eq!(cfd.expired_settlement_event(past_deadline), None);
+    }
+
+    #[test]
+    fn given_settlement_event_expired_recently_then_no_emergency_commit() {
+        let event_id = dummy_event_id();
+        let cfd = Cfd::dummy_maker_short().dummy_open(event_id);
+
+        let now = event_id.timestamp() + olivia::ATTESTATION_DEADLINE + EMERGENCY_COMMIT_AFTER
+            - Duration::seconds(1);
+
+        assert!(cfd.emergency_commit(now).unwrap().is_none());
+    }
+
     #[test]
     fn given_ongoing_collab_settlement_then_cannot_finish_rollover() {
         let cfd = Cfd::dummy_taker_long()
@@ -3151,6 +3956,103 @@ mod tests {
         );
     }
 
+    #[test]
+    fn given_collab_settlement_interrupted_before_signing_then_resuming_fails_it() {
+        let cfd = Cfd::dummy_maker_short()
+            .dummy_open(dummy_event_id())
+            .dummy_start_collab_settlement();
+
+        let event = cfd.resume_collaborative_settlement(None).unwrap();
+
+        assert_eq!(event.event, EventKind::CollaborativeSettlementFailed);
+    }
+
+    #[test]
+    fn given_collab_settlement_interrupted_after_signing_then_resuming_completes_it() {
+        let cfd = Cfd::dummy_maker_short()
+            .dummy_open(dummy_event_id())
+            .dummy_start_collab_settlement();
+        let settlement = dummy_settlement();
+
+        let event = cfd
+            .resume_collaborative_settlement(Some(settlement.clone()))
+            .unwrap();
+
+        assert_eq!(
+            event.event,
+            EventKind::CollaborativeSettlementCompleted {
+                spend_tx: settlement.tx,
+                script: settlement.script_pubkey,
+                price: settlement.price,
+            }
+        );
+    }
+
+    #[test]
+    fn given_collab_settlement_completed_before_interruption_then_nothing_to_resume() {
+        let cfd = Cfd::dummy_maker_short()
+            .dummy_open(dummy_event_id())
+            .dummy_start_collab_settlement();
+        let settlement = dummy_settlement();
+
+        let completed = cfd
+            .clone()
+            .complete_collaborative_settlement(settlement.clone());
+        let cfd = cfd.apply(completed);
+
+        assert!(cfd
+            .resume_collaborative_settlement(Some(settlement))
+            .is_none());
+    }
+
+    #[test]
+    fn given_settlement_proposal_expired_then_settlement_can_be_proposed_again() {
+        let cfd = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .dummy_start_collab_settlement();
+
+        let expired = cfd
+            .clone()
+            .expire_collaborative_settlement_proposal()
+            .unwrap();
+        let cfd = cfd.apply(expired.clone());
+
+        assert_eq!(
+            expired.event,
+            EventKind::CollaborativeSettlementProposalExpired
+        );
+        assert!(!cfd.is_in_collaborative_settlement());
+        assert!(cfd.can_settle_collaboratively().is_ok());
+    }
+
+    #[test]
+    fn given_no_settlement_proposal_then_cannot_expire_it() {
+        let cfd = Cfd::dummy_taker_long().dummy_open(dummy_event_id());
+
+        assert!(cfd.expire_collaborative_settlement_proposal().is_err());
+    }
+
+    #[test]
+    fn given_no_collab_settlement_then_nothing_to_resume() {
+        let cfd = Cfd::dummy_maker_short().dummy_open(dummy_event_id());
+
+        assert!(cfd.resume_collaborative_settlement(None).is_none());
+    }
+
+    fn dummy_settlement() -> CollaborativeSettlement {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![TxOut {
+                value: 10_000,
+                script_pubkey: Script::new(),
+            }],
+        };
+
+        CollaborativeSettlement::new(tx, Script::new(), Price::new(dec!(10000)).unwrap()).unwrap()
+    }
+
     #[test]
     fn given_ongoing_rollover_then_can_start_collaborative_settlement() {
         let quantity = Contracts::new(10);
@@ -3308,7 +4210,7 @@ mod tests {
         // Extract unsigned tx to be able to trigger collab settlement in the maker
         let unsigned_tx = taker_long
             .clone()
-            .start_collab_settlement_taker(price, N_PAYOUTS)
+            .start_collab_settlement_taker(price, N_PAYOUTS, 0)
             .unwrap()
             .1
             .unsigned_transaction()
@@ -3321,11 +4223,12 @@ mod tests {
             .with_lock(taker_keys, maker_keys)
             .dummy_commit();
 
-        let result_taker = taker_long.start_collab_settlement_taker(price, N_PAYOUTS);
+        let result_taker = taker_long.start_collab_settlement_taker(price, N_PAYOUTS, 0);
         let result_maker = maker_short.start_collab_settlement_maker(
             Price::dummy(),
             N_PAYOUTS,
             &unsigned_tx,
+            DUMMY_LOCK_TIME,
             InverseMaxPrice::OliviaMax,
         );
 
@@ -3333,6 +4236,109 @@ mod tests {
         assert!(result_maker.is_err(), "When having commit tx available we should not be able to trigger collaborative settlement");
     }
 
+    #[test]
+    fn collab_settlement_transaction_is_locked_to_proposed_block_height() {
+        let taker_keys = new_keypair();
+        let maker_keys = new_keypair();
+        let opening_price = Price::new(dec!(10000)).unwrap();
+
+        let taker_long = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .with_lock(taker_keys, maker_keys);
+        let maker_short = Cfd::dummy_maker_short()
+            .dummy_open(dummy_event_id())
+            .with_lock(taker_keys, maker_keys);
+
+        let (_, _, settlement_transaction, settlement) =
+            taker_long.dummy_collab_settlement_taker(opening_price, maker_short);
+
+        let unsigned_tx = settlement_transaction.unsigned_transaction();
+        assert_eq!(unsigned_tx.lock_time, DUMMY_LOCK_TIME);
+        assert!(unsigned_tx
+            .input
+            .iter()
+            .all(|input| input.sequence == SEQUENCE_ENABLE_LOCK_TIME));
+        assert_eq!(settlement.tx.lock_time, DUMMY_LOCK_TIME);
+    }
+
+    #[test]
+    fn given_no_lock_time_then_collab_settlement_transaction_is_not_locked() {
+        let taker_keys = new_keypair();
+        let maker_keys = new_keypair();
+        let price = Price::new(dec!(10000)).unwrap();
+
+        let (_, settlement_transaction, _) = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .with_lock(taker_keys, maker_keys)
+            .start_collab_settlement_taker(price, N_PAYOUTS, 0)
+            .unwrap();
+
+        let unsigned_tx = settlement_transaction.unsigned_transaction();
+        assert_eq!(unsigned_tx.lock_time, 0);
+        assert!(unsigned_tx
+            .input
+            .iter()
+            .all(|input| input.sequence != SEQUENCE_ENABLE_LOCK_TIME));
+    }
+
+    #[test]
+    fn maker_rejects_collab_settlement_locked_to_timestamp() {
+        let taker_keys = new_keypair();
+        let maker_keys = new_keypair();
+        let price = Price::new(dec!(10000)).unwrap();
+
+        let (_, settlement_transaction, _) = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .with_lock(taker_keys, maker_keys)
+            .start_collab_settlement_taker(price, N_PAYOUTS, 0)
+            .unwrap();
+        let mut proposed_tx = settlement_transaction.unsigned_transaction().clone();
+        proposed_tx.lock_time = LOCK_TIME_THRESHOLD;
+
+        let result = Cfd::dummy_maker_short()
+            .dummy_open(dummy_event_id())
+            .with_lock(taker_keys, maker_keys)
+            .start_collab_settlement_maker(
+                price,
+                N_PAYOUTS,
+                &proposed_tx,
+                DUMMY_LOCK_TIME,
+                InverseMaxPrice::OliviaMax,
+            );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn maker_rejects_collab_settlement_locked_far_above_its_block_height() {
+        let taker_keys = new_keypair();
+        let maker_keys = new_keypair();
+        let price = Price::new(dec!(10000)).unwrap();
+        let maker_short = Cfd::dummy_maker_short()
+            .dummy_open(dummy_event_id())
+            .with_lock(taker_keys, maker_keys);
+
+        let propose = |lock_time| {
+            let (_, settlement_transaction, _) = Cfd::dummy_taker_long()
+                .dummy_open(dummy_event_id())
+                .with_lock(taker_keys, maker_keys)
+                .start_collab_settlement_taker(price, N_PAYOUTS, lock_time)
+                .unwrap();
+
+            maker_short.clone().start_collab_settlement_maker(
+                price,
+                N_PAYOUTS,
+                settlement_transaction.unsigned_transaction(),
+                DUMMY_LOCK_TIME,
+                InverseMaxPrice::OliviaMax,
+            )
+        };
+
+        assert!(propose(DUMMY_LOCK_TIME + MAX_LOCK_TIME_AHEAD_OF_MAKER).is_ok());
+        assert!(propose(DUMMY_LOCK_TIME + MAX_LOCK_TIME_AHEAD_OF_MAKER + 1).is_err());
+        assert!(propose(LOCK_TIME_THRESHOLD - 1).is_err());
+    }
+
     #[test]
     fn given_no_rollover_then_no_rollover_fee() {
         let quantity = Contracts::new(10);
@@ -3689,6 +4695,112 @@ mod tests {
         (taker_payout.as_sat(), maker_payout.as_sat())
     }
 
+    #[test]
+    fn given_fully_subsidized_offer_then_no_fees_flow_between_parties() {
+        let offer = Offer::dummy_btc_usd_short()
+            .with_opening_fee(OpeningFee::new(Amount::from_sat(500)))
+            .with_funding_rate(FundingRate::new(dec!(0.01)).unwrap())
+            .with_fee_subsidy(FeeSubsidy {
+                opening_fee: true,
+                initial_funding_fee: true,
+            });
+
+        let taker_long =
+            Cfd::taker_long_from_order(offer.clone(), Contracts::new(1000), Leverage::TWO);
+        let maker_short = Cfd::maker_short_from_order(offer, Contracts::new(1000), Leverage::TWO);
+
+        assert_eq!(taker_long.fee_account.settle(), CompleteFee::None);
+        assert_eq!(maker_short.fee_account.settle(), CompleteFee::None);
+    }
+
+    #[test]
+    fn given_subsidized_offer_when_maker_pays_funding_then_funding_fee_is_not_waived() {
+        let offer = Offer::dummy_btc_usd_short()
+            .with_funding_rate(FundingRate::new(dec!(-0.01)).unwrap())
+            .with_fee_subsidy(FeeSubsidy {
+                opening_fee: true,
+                initial_funding_fee: true,
+            });
+
+        let taker_long =
+            Cfd::taker_long_from_order(offer.clone(), Contracts::new(1000), Leverage::TWO);
+        let maker_short = Cfd::maker_short_from_order(offer, Contracts::new(1000), Leverage::TWO);
+
+        assert!(matches!(
+            taker_long.fee_account.settle(),
+            CompleteFee::ShortPaysLong(_)
+        ));
+        assert_eq!(
+            taker_long.fee_account.settle(),
+            maker_short.fee_account.settle()
+        );
+    }
+
+    #[test]
+    fn taker_needs_margin_or_minimum_balance_whichever_is_higher() {
+        let offer = Offer::dummy_btc_usd_short();
+        let margin =
+            Cfd::taker_long_from_order(offer.clone(), Contracts::new(1000), Leverage::TWO).margin();
+
+        assert_eq!(
+            offer.required_taker_balance(Contracts::new(1000), Leverage::TWO),
+            margin
+        );
+
+        let offer = offer.with_min_taker_balance(margin * 2);
+        assert_eq!(
+            offer.required_taker_balance(Contracts::new(1000), Leverage::TWO),
+            margin * 2
+        );
+        assert_eq!(
+            offer.required_taker_balance(Contracts::new(10_000), Leverage::ONE),
+            calculate_margin(
+                ContractSymbol::BtcUsd,
+                offer.price,
+                Contracts::new(10_000),
+                Leverage::ONE
+            )
+        );
+    }
+
+    #[test]
+    fn given_offer_without_refund_timelock_then_one_and_a_half_settlement_intervals() {
+        let offer = Offer::dummy_btc_usd_short();
+
+        assert_eq!(offer.refund_timelock, 216);
+    }
+
+    #[test]
+    fn given_offer_with_refund_timelock_then_contract_is_set_up_with_it() {
+        let offer = Offer::dummy_btc_usd_short().with_refund_timelock(300);
+        let taker_long = Cfd::taker_long_from_order(offer, Contracts::new(1000), Leverage::TWO);
+
+        let (_, setup_params, _) = taker_long.start_contract_setup().unwrap();
+
+        assert_eq!(setup_params.refund_timelock, 300);
+    }
+
+    #[test]
+    fn given_refund_timelock_shorter_than_settlement_interval_then_contract_setup_fails() {
+        let offer = Offer::dummy_btc_usd_short().with_refund_timelock(100);
+        let taker_long = Cfd::taker_long_from_order(offer, Contracts::new(1000), Leverage::TWO);
+
+        assert!(taker_long.start_contract_setup().is_err());
+    }
+
+    #[test]
+    fn refund_timelock_of_dlc_is_read_from_refund_transaction() {
+        let mut dlc = Dlc::dummy(None);
+        dlc.refund_timelock = 216;
+        assert_eq!(dlc.refund_timelock_in_blocks(), 216);
+
+        dlc.refund.0.input = vec![TxIn {
+            sequence: 300,
+            ..Default::default()
+        }];
+        assert_eq!(dlc.refund_timelock_in_blocks(), 300);
+    }
+
     proptest! {
         #[test]
         fn rollover_funding_fee_collected_incrementally_should_not_be_smaller_than_collected_once_per_settlement_interval(
@@ -3748,7 +4860,7 @@ mod tests {
     }
 
     #[test]
-    fn given_order_creation_timestamp_outdated_then_order_outdated() {
+    fn given_offer_validity_passed_then_offer_expired() {
         let creation_timestamp = Timestamp::now();
         let order =
             Offer::dummy_short(ContractSymbol::BtcUsd).with_creation_timestamp(creation_timestamp);
@@ -3756,11 +4868,11 @@ mod tests {
         let now =
             OffsetDateTime::now_utc() + Duration::seconds(Offer::OUTDATED_AFTER_MINS * 60 + 1);
 
-        assert!(order.is_creation_timestamp_outdated(now))
+        assert!(order.is_expired(now))
     }
 
     #[test]
-    fn given_order_creation_timestamp_not_outdated_then_order_not_outdated() {
+    fn given_offer_validity_not_passed_then_offer_not_expired() {
         let creation_timestamp = Timestamp::now();
         let order =
             Offer::dummy_short(ContractSymbol::BtcUsd).with_creation_timestamp(creation_timestamp);
@@ -3768,7 +4880,28 @@ mod tests {
         let now =
             OffsetDateTime::now_utc() + Duration::seconds(Offer::OUTDATED_AFTER_MINS * 60 - 1);
 
-        assert!(!order.is_creation_timestamp_outdated(now))
+        assert!(!order.is_expired(now))
+    }
+
+    #[test]
+    fn offers_are_grouped_into_one_pair_per_contract_symbol() {
+        let mut btc_usd_long = Offer::dummy_short(ContractSymbol::BtcUsd);
+        btc_usd_long.position_maker = Position::Long;
+        let btc_usd_short = Offer::dummy_short(ContractSymbol::BtcUsd);
+
+        let pairs = OfferPair::from_offers(vec![btc_usd_long.clone(), btc_usd_short.clone()]);
+
+        assert_eq!(
+            pairs,
+            vec![
+                OfferPair {
+                    contract_symbol: ContractSymbol::BtcUsd,
+                    long: Some(btc_usd_long),
+                    short: Some(btc_usd_short),
+                },
+                OfferPair::empty(ContractSymbol::EthUsd),
+            ]
+        );
     }
 
     #[test]
@@ -4124,7 +5257,7 @@ mod tests {
 
             let (propose, settlement_transaction, settlement_proposal) = self
                 .clone()
-                .start_collab_settlement_taker(price, N_PAYOUTS)
+                .start_collab_settlement_taker(price, N_PAYOUTS, DUMMY_LOCK_TIME)
                 .unwrap();
             events.push(propose);
 
@@ -4133,6 +5266,7 @@ mod tests {
                     price,
                     N_PAYOUTS,
                     settlement_transaction.unsigned_transaction(),
+                    DUMMY_LOCK_TIME,
                     InverseMaxPrice::OliviaMax,
                 )
                 .unwrap();
@@ -4171,6 +5305,7 @@ mod tests {
                     price,
                     N_PAYOUTS,
                     taker_unsigned_tx,
+                    DUMMY_LOCK_TIME,
                     InverseMaxPrice::OliviaMax,
                 )
                 .unwrap();
@@ -4271,12 +5406,15 @@ mod tests {
                 Contracts::new(100),
                 Contracts::new(1000),
                 time::Duration::hours(24),
+                None,
                 TxFeeRate::default(),
                 FundingRate::default(),
                 OpeningFee::default(),
                 vec![Leverage::TWO],
                 contract_symbol,
                 LotSize::new(100),
+                None,
+                None,
             )
         }
 
@@ -4294,8 +5432,29 @@ mod tests {
             self
         }
 
+        fn with_opening_fee(mut self, opening_fee: OpeningFee) -> Self {
+            self.opening_fee = opening_fee;
+            self
+        }
+
+        fn with_min_taker_balance(mut self, min_taker_balance: Amount) -> Self {
+            self.min_taker_balance = Some(min_taker_balance);
+            self
+        }
+
+        fn with_fee_subsidy(mut self, fee_subsidy: FeeSubsidy) -> Self {
+            self.fee_subsidy = Some(fee_subsidy);
+            self
+        }
+
+        fn with_refund_timelock(mut self, refund_timelock: u32) -> Self {
+            self.refund_timelock = refund_timelock;
+            self
+        }
+
         fn with_creation_timestamp(mut self, creation_timestamp: Timestamp) -> Self {
             self.creation_timestamp_maker = creation_timestamp;
+            self.valid_until = Offer::default_valid_until(creation_timestamp);
             self
         }
 
@@ -4518,6 +5677,8 @@ mod tests {
 
     const N_PAYOUTS: usize = 200;
 
+    const DUMMY_LOCK_TIME: u32 = 750_000;
+
     fn new_keypair() -> (SecretKey, PublicKey) {
         let (sk, pk) = keypair::new(&mut thread_rng());
         (sk, pk)
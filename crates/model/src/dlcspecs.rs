@@ -0,0 +1,423 @@
+//! Conversion of contracts to and from the TLV format of the DLC
+//! specifications, see <https://github.com/discreetlogcontracts/dlcspecs>.
+//!
+//! Only the payouts of a contract can be converted. They are encoded as
+//! a numeric outcome contract descriptor, from the maker's point of
+//! view, i.e. the maker takes the role of the party which offers the
+//! contract. The remaining parts of the `offer_dlc` and `accept_dlc`
+//! messages cannot be converted:
+//!
+//! - the funding inputs and signatures are exchanged by our own contract setup protocol;
+//! - olivia does not sign its announcements in the format of the specifications, so the oracle can
+//!   only be identified by its public key and the id of the event.
+//!
+//! The payout curve of a CFD is a step function, where every CET pays a
+//! fixed amount for a range of outcomes. It is encoded as a sequence of
+//! linear pieces: one piece with a constant payout for every range, and
+//! one piece between the last outcome of a range and the first outcome
+//! of the next range. As there is no outcome between these two, the
+//! linear pieces describe the step function exactly. Importing is only
+//! possible for payout functions of this shape.
+
+use crate::Cet;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::Amount;
+use std::ops::RangeInclusive;
+
+/// TLV type of `contract_descriptor_v1`, i.e. a numeric outcome contract
+const CONTRACT_DESCRIPTOR_TYPE: u64 = 42784;
+const ROUNDING_INTERVALS_TYPE: u64 = 42788;
+const PAYOUT_FUNCTION_TYPE: u64 = 42790;
+const POLYNOMIAL_PAYOUT_CURVE_PIECE_TYPE: u64 = 42792;
+
+/// The payout to the maker for every outcome in `outcomes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayoutRange {
+    pub outcomes: RangeInclusive<u64>,
+    pub maker: Amount,
+}
+
+/// The payouts of a contract on a numeric outcome, e.g. a price.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumericContract {
+    /// Number of binary digits in which the oracle attests to the outcome
+    pub num_digits: u16,
+    /// The payouts, ordered by outcome and covering all outcomes
+    ///
+    /// Adjacent ranges never pay the same amount.
+    pub payouts: Vec<PayoutRange>,
+}
+
+impl NumericContract {
+    /// Derive the payouts from the CETs for one oracle event.
+    pub fn from_cets(cets: &[Cet]) -> Result<Self> {
+        let mut cets = cets.iter().collect::<Vec<_>>();
+        cets.sort_by_key(|cet| *cet.range.start());
+
+        let n_bits = cets.first().context("No CETs")?.n_bits;
+        ensure!(
+            cets.iter().all(|cet| cet.n_bits == n_bits),
+            "CETs are based on different numbers of digits"
+        );
+
+        let payouts = cets
+            .into_iter()
+            .map(|cet| PayoutRange {
+                outcomes: cet.range.clone(),
+                maker: cet.maker_amount,
+            })
+            .collect();
+
+        Self::new(u16::try_from(n_bits)?, payouts)
+    }
+
+    fn new(num_digits: u16, payouts: Vec<PayoutRange>) -> Result<Self> {
+        ensure!(
+            (1..64).contains(&num_digits),
+            "Unsupported number of digits: {num_digits}"
+        );
+
+        let mut merged = Vec::<PayoutRange>::with_capacity(payouts.len());
+        let mut next_outcome = 0;
+
+        for payout in payouts {
+            ensure!(
+                *payout.outcomes.start() == next_outcome,
+                "Payouts are not contiguous at outcome {next_outcome}"
+            );
+            ensure!(
+                payout.outcomes.start() <= payout.outcomes.end(),
+                "Empty range of outcomes"
+            );
+            ensure!(
+                *payout.outcomes.end() < 1 << num_digits,
+                "Outcome {} exceeds {num_digits} digits",
+                payout.outcomes.end()
+            );
+            next_outcome = payout.outcomes.end() + 1;
+
+            match merged.last_mut() {
+                Some(last) if last.maker == payout.maker => {
+                    last.outcomes = *last.outcomes.start()..=*payout.outcomes.end();
+                }
+                _ => merged.push(payout),
+            }
+        }
+
+        ensure!(
+            next_outcome == 1 << num_digits,
+            "Payouts do not cover all outcomes of {num_digits} digits"
+        );
+
+        Ok(Self {
+            num_digits,
+            payouts: merged,
+        })
+    }
+
+    /// Encode the payouts as `contract_descriptor_v1` TLV.
+    pub fn to_tlv(&self) -> Vec<u8> {
+        let endpoints = self
+            .payouts
+            .iter()
+            .flat_map(|payout| {
+                let (start, end) = (*payout.outcomes.start(), *payout.outcomes.end());
+                let payout = payout.maker.as_sat();
+
+                if start == end {
+                    vec![(start, payout)]
+                } else {
+                    vec![(start, payout), (end, payout)]
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut payout_function = Vec::new();
+        write_bigsize(&mut payout_function, endpoints.len() as u64 - 1);
+        for (i, (outcome, payout)) in endpoints.iter().enumerate() {
+            write_bigsize(&mut payout_function, *outcome);
+            write_bigsize(&mut payout_function, *payout);
+            payout_function.extend(0u16.to_be_bytes()); // extra_precision
+
+            if i + 1 < endpoints.len() {
+                // A linear piece has no points besides its endpoints
+                let mut piece = Vec::new();
+                write_bigsize(&mut piece, 0);
+                write_tlv(
+                    &mut payout_function,
+                    POLYNOMIAL_PAYOUT_CURVE_PIECE_TYPE,
+                    &piece,
+                );
+            }
+        }
+
+        // A single interval without rounding
+        let mut rounding_intervals = Vec::new();
+        write_bigsize(&mut rounding_intervals, 1);
+        write_bigsize(&mut rounding_intervals, 0);
+        write_bigsize(&mut rounding_intervals, 1);
+
+        let mut descriptor = Vec::new();
+        descriptor.extend(self.num_digits.to_be_bytes());
+        write_tlv(&mut descriptor, PAYOUT_FUNCTION_TYPE, &payout_function);
+        write_tlv(
+            &mut descriptor,
+            ROUNDING_INTERVALS_TYPE,
+            &rounding_intervals,
+        );
+
+        let mut tlv = Vec::new();
+        write_tlv(&mut tlv, CONTRACT_DESCRIPTOR_TYPE, &descriptor);
+
+        tlv
+    }
+
+    /// Decode the payouts from a `contract_descriptor_v1` TLV.
+    ///
+    /// Fails if the payout function is not a step function, as CFDs
+    /// cannot represent any other payout curve.
+    pub fn from_tlv(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader(bytes);
+        let mut descriptor = Reader(reader.read_tlv(CONTRACT_DESCRIPTOR_TYPE)?);
+        reader.finish()?;
+
+        let num_digits = descriptor.read_u16()?;
+        let mut payout_function = Reader(descriptor.read_tlv(PAYOUT_FUNCTION_TYPE)?);
+        let mut rounding_intervals = Reader(descriptor.read_tlv(ROUNDING_INTERVALS_TYPE)?);
+        descriptor.finish()?;
+
+        let num_pieces = payout_function.read_bigsize()?;
+        let mut endpoints = Vec::new();
+        for i in 0..=num_pieces {
+            let outcome = payout_function.read_bigsize()?;
+            let payout = payout_function.read_bigsize()?;
+            ensure!(
+                payout_function.read_u16()? == 0,
+                "Fractional payouts are not supported"
+            );
+            endpoints.push((outcome, payout));
+
+            if i < num_pieces {
+                let mut piece =
+                    Reader(payout_function.read_tlv(POLYNOMIAL_PAYOUT_CURVE_PIECE_TYPE)?);
+                ensure!(
+                    piece.read_bigsize()? == 0,
+                    "Only linear payout curve pieces are supported"
+                );
+                piece.finish()?;
+            }
+        }
+        payout_function.finish()?;
+
+        for _ in 0..rounding_intervals.read_bigsize()? {
+            let _begin_interval = rounding_intervals.read_bigsize()?;
+            ensure!(
+                rounding_intervals.read_bigsize()? == 1,
+                "Rounding of payouts is not supported"
+            );
+        }
+        rounding_intervals.finish()?;
+
+        let mut payouts = Vec::<PayoutRange>::new();
+        for pair in endpoints.windows(2) {
+            let ((from, from_payout), (to, to_payout)) = (pair[0], pair[1]);
+            ensure!(from < to, "Endpoints are not ordered by outcome");
+
+            let continues_last = payouts.last().map_or(false, |last| {
+                *last.outcomes.end() == from && last.maker.as_sat() == from_payout
+            });
+            if !continues_last {
+                payouts.push(PayoutRange {
+                    outcomes: from..=from,
+                    maker: Amount::from_sat(from_payout),
+                });
+            }
+
+            if from_payout == to_payout {
+                let last = payouts.last_mut().expect("just pushed");
+                last.outcomes = *last.outcomes.start()..=to;
+            } else if to != from + 1 {
+                bail!("Payout changes gradually between outcomes {from} and {to}");
+            }
+        }
+
+        match (payouts.last_mut(), endpoints.last()) {
+            (Some(last), Some((outcome, payout))) if *last.outcomes.end() == *outcome => {
+                ensure!(
+                    last.maker.as_sat() == *payout,
+                    "Inconsistent payout at {outcome}"
+                );
+            }
+            (_, Some((outcome, payout))) => payouts.push(PayoutRange {
+                outcomes: *outcome..=*outcome,
+                maker: Amount::from_sat(*payout),
+            }),
+            (_, None) => bail!("Payout function without endpoints"),
+        }
+
+        Self::new(num_digits, payouts)
+    }
+}
+
+fn write_bigsize(buffer: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => buffer.push(value as u8),
+        0xfd..=0xffff => {
+            buffer.push(0xfd);
+            buffer.extend((value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buffer.push(0xfe);
+            buffer.extend((value as u32).to_be_bytes());
+        }
+        _ => {
+            buffer.push(0xff);
+            buffer.extend(value.to_be_bytes());
+        }
+    }
+}
+
+fn write_tlv(buffer: &mut Vec<u8>, tlv_type: u64, value: &[u8]) {
+    write_bigsize(buffer, tlv_type);
+    write_bigsize(buffer, value.len() as u64);
+    buffer.extend(value);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        ensure!(self.0.len() >= n, "Unexpected end of TLV");
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+
+        Ok(bytes)
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.read_bytes(2)?.try_into()?))
+    }
+
+    fn read_bigsize(&mut self) -> Result<u64> {
+        let (value, min) = match self.read_bytes(1)?[0] {
+            0xfd => (
+                u16::from_be_bytes(self.read_bytes(2)?.try_into()?) as u64,
+                0xfd,
+            ),
+            0xfe => (
+                u32::from_be_bytes(self.read_bytes(4)?.try_into()?) as u64,
+                0x1_0000,
+            ),
+            0xff => (
+                u64::from_be_bytes(self.read_bytes(8)?.try_into()?),
+                0x1_0000_0000,
+            ),
+            value => return Ok(value as u64),
+        };
+        ensure!(value >= min, "Non-canonical BigSize encoding of {value}");
+
+        Ok(value)
+    }
+
+    fn read_tlv(&mut self, expected_type: u64) -> Result<&'a [u8]> {
+        let tlv_type = self.read_bigsize()?;
+        ensure!(
+            tlv_type == expected_type,
+            "Expected TLV type {expected_type}, got {tlv_type}"
+        );
+        let length = self.read_bigsize()?;
+
+        self.read_bytes(usize::try_from(length)?)
+    }
+
+    fn finish(self) -> Result<()> {
+        ensure!(self.0.is_empty(), "{} trailing bytes in TLV", self.0.len());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payout(outcomes: RangeInclusive<u64>, maker: u64) -> PayoutRange {
+        PayoutRange {
+            outcomes,
+            maker: Amount::from_sat(maker),
+        }
+    }
+
+    #[test]
+    fn payouts_roundtrip_through_tlv() {
+        let contract = NumericContract::new(
+            4,
+            vec![
+                payout(0..=2, 200_000),
+                payout(3..=3, 150_000),
+                payout(4..=9, 100_000),
+                payout(10..=15, 0),
+            ],
+        )
+        .unwrap();
+
+        let decoded = NumericContract::from_tlv(&contract.to_tlv()).unwrap();
+
+        assert_eq!(decoded, contract);
+    }
+
+    #[test]
+    fn adjacent_ranges_with_same_payout_are_merged() {
+        let contract =
+            NumericContract::new(2, vec![payout(0..=1, 1_000), payout(2..=3, 1_000)]).unwrap();
+
+        assert_eq!(contract.payouts, vec![payout(0..=3, 1_000)]);
+    }
+
+    #[test]
+    fn payouts_must_cover_all_outcomes() {
+        let result = NumericContract::new(2, vec![payout(0..=1, 1_000), payout(3..=3, 0)]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn gradual_payout_curve_cannot_be_imported() {
+        let mut payout_function = Vec::new();
+        write_bigsize(&mut payout_function, 1);
+        for (outcome, payout) in [(0, 1_000), (3, 0)] {
+            write_bigsize(&mut payout_function, outcome);
+            write_bigsize(&mut payout_function, payout);
+            payout_function.extend(0u16.to_be_bytes());
+            if outcome == 0 {
+                write_tlv(
+                    &mut payout_function,
+                    POLYNOMIAL_PAYOUT_CURVE_PIECE_TYPE,
+                    &[0],
+                );
+            }
+        }
+        let mut descriptor = 2u16.to_be_bytes().to_vec();
+        write_tlv(&mut descriptor, PAYOUT_FUNCTION_TYPE, &payout_function);
+        write_tlv(&mut descriptor, ROUNDING_INTERVALS_TYPE, &[1, 0, 1]);
+        let mut tlv = Vec::new();
+        write_tlv(&mut tlv, CONTRACT_DESCRIPTOR_TYPE, &descriptor);
+
+        assert!(NumericContract::from_tlv(&tlv).is_err());
+    }
+
+    #[test]
+    fn bigsize_roundtrips() {
+        for value in [0, 0xfc, 0xfd, 0xffff, 0x1_0000, 0xffff_ffff, u64::MAX] {
+            let mut buffer = Vec::new();
+            write_bigsize(&mut buffer, value);
+
+            let mut reader = Reader(&buffer);
+            assert_eq!(reader.read_bigsize().unwrap(), value);
+            reader.finish().unwrap();
+        }
+    }
+}
@@ -32,10 +32,13 @@ use time::OffsetDateTime;
 
 mod cfd;
 mod contract_setup;
+pub mod dlcspecs;
 pub mod hex_transaction;
 pub mod libp2p;
 pub mod olivia;
 pub mod payout_curve;
+pub mod punish;
+mod quantity;
 mod rollover;
 pub mod shared_protocol;
 pub mod transaction_ext;
@@ -44,8 +47,15 @@ pub use cfd::*;
 pub use contract_setup::SetupParams;
 pub use payout_curve::OraclePayouts;
 pub use payout_curve::Payouts;
+pub use quantity::contracts_to_sats;
+pub use quantity::sats_to_contracts;
+pub use quantity::sats_to_usd;
+pub use quantity::usd_to_sats;
+pub use quantity::validate_quantity;
+pub use quantity::InvalidQuantity;
 pub use rollover::BaseDlcParams;
 pub use rollover::RolloverParams;
+pub use rollover::RolloverPolicy;
 pub use transaction_ext::TransactionExt;
 
 /// The time-to-live of a CFD after it is first created or rolled
@@ -385,6 +395,23 @@ pub enum Position {
     Short,
 }
 
+/// The group of takers an offer is shown to.
+///
+/// Takers which were not assigned to a tier see the public offers.
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash, EnumIter, Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum OfferTier {
+    #[default]
+    Public,
+    /// Verified partners of the maker
+    Partner,
+    /// The maker's own takers, e.g. for testing
+    Internal,
+}
+
 impl Position {
     /// Determines the counter position to the current position.
     pub fn counter_position(&self) -> Position {
@@ -703,8 +730,9 @@ impl CompleteFee {
 /// The balance being positive means we owe this amount to the other party.
 /// The balance being negative means that the other party owes this amount to us.
 /// The counterparty fee-account balance is always the inverse of the balance.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FeeAccount {
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
     balance: SignedAmount,
     position: Position,
     role: Role,
@@ -978,6 +1006,8 @@ pub struct FailedCfd {
     pub role: Role,
     pub fees: Fees,
     pub kind: FailedKind,
+    /// Why the maker rejected the order, if known
+    pub reject_reason: Option<RejectReason>,
     pub creation_timestamp: Timestamp,
     pub contract_symbol: ContractSymbol,
 }
@@ -24,13 +24,70 @@ use url::Url;
 
 use crate::ContractSymbol;
 
+/// Base URL of the olivia oracle.
+pub const BASE_URL: &str = "https://h00.ooo";
+
 pub const EVENT_TIME_FORMAT: &[FormatItem] =
     format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]");
 
-pub static PUBLIC_KEY: Lazy<XOnlyPublicKey> = Lazy::new(|| {
-    XOnlyPublicKey::from_str("ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7")
-        .expect("static key to be valid")
-});
+/// Public key of the olivia oracle, as a hex string.
+pub const PUBLIC_KEY_HEX: &str = "ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7";
+
+pub static PUBLIC_KEY: Lazy<XOnlyPublicKey> =
+    Lazy::new(|| XOnlyPublicKey::from_str(PUBLIC_KEY_HEX).expect("static key to be valid"));
+
+/// Base URL the `dev-oracle` serves olivia's API at by default, used on regtest.
+pub const DEV_BASE_URL: &str = "http://127.0.0.1:8100";
+
+/// How long after an event we wait for its attestation before we consider the event expired.
+///
+/// Olivia attests an event within seconds. An event which is not attested within this window was
+/// most likely replaced, and the CETs depending on it cannot be relied upon anymore.
+pub const ATTESTATION_DEADLINE: Duration = Duration::hours(1);
+
+/// Public key of the `dev-oracle` with its default seed, as a hex string.
+pub const DEV_PUBLIC_KEY_HEX: &str =
+    "3c5624d3e60aa4c6f7963f27cee7ec996d1643a00e112900ddff0853d99170d1";
+
+pub static DEV_PUBLIC_KEY: Lazy<XOnlyPublicKey> =
+    Lazy::new(|| XOnlyPublicKey::from_str(DEV_PUBLIC_KEY_HEX).expect("static key to be valid"));
+
+/// The public keys of the oracle, including an upcoming key rotation.
+///
+/// Events which occur before the next key becomes effective are
+/// attested with the current key. Existing CFDs therefore keep using
+/// the key they were set up with, whereas contract setups and rollovers
+/// onto events after the rotation use the next key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OracleKeys {
+    current: XOnlyPublicKey,
+    next: Option<(XOnlyPublicKey, OffsetDateTime)>,
+}
+
+impl OracleKeys {
+    pub fn new(current: XOnlyPublicKey) -> Self {
+        Self {
+            current,
+            next: None,
+        }
+    }
+
+    /// Rotate to `next` for all events at or after `effective_from`.
+    pub fn with_next_key(self, next: XOnlyPublicKey, effective_from: OffsetDateTime) -> Self {
+        Self {
+            next: Some((next, effective_from)),
+            ..self
+        }
+    }
+
+    /// The key the oracle uses to attest to the given event.
+    pub fn for_event(&self, event_id: BitMexPriceEventId) -> XOnlyPublicKey {
+        match self.next {
+            Some((next, effective_from)) if event_id.timestamp() >= effective_from => next,
+            _ => self.current,
+        }
+    }
+}
 
 #[derive(Debug, Clone, serde::Deserialize, PartialEq, Eq)]
 #[serde(try_from = "olivia_api::Response")]
@@ -107,10 +164,21 @@ impl BitMexPriceEventId {
         now > self.timestamp + Duration::minutes(1)
     }
 
+    /// Whether this event should have been attested by `now`.
+    ///
+    /// See [`ATTESTATION_DEADLINE`].
+    pub fn is_expired(&self, now: OffsetDateTime) -> bool {
+        now > self.timestamp + ATTESTATION_DEADLINE
+    }
+
     pub fn to_olivia_url(self) -> Url {
-        "https://h00.ooo"
-            .parse::<Url>()
-            .expect("valid URL from constant")
+        self.to_url(&BASE_URL.parse::<Url>().expect("valid URL from constant"))
+    }
+
+    /// The URL of this event at an oracle which serves the same API as
+    /// olivia under `base_url`.
+    pub fn to_url(self, base_url: &Url) -> Url {
+        base_url
             .join(&self.to_string())
             .expect("Event id can be joined")
     }
@@ -546,6 +614,28 @@ mod tests {
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn events_before_rotation_use_current_key() {
+        let next = XOnlyPublicKey::from_str(
+            "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let keys = OracleKeys::new(*PUBLIC_KEY)
+            .with_next_key(next, datetime!(2022-11-01 00:00:00).assume_utc());
+
+        let before = BitMexPriceEventId::with_20_digits(
+            datetime!(2022-10-31 23:00:00).assume_utc(),
+            IndexPrice::Bxbt,
+        );
+        let after = BitMexPriceEventId::with_20_digits(
+            datetime!(2022-11-01 00:00:00).assume_utc(),
+            IndexPrice::Bxbt,
+        );
+
+        assert_eq!(keys.for_event(before), *PUBLIC_KEY);
+        assert_eq!(keys.for_event(after), next);
+    }
+
     #[test]
     fn new_event_has_no_nanos() {
         let now = BitMexPriceEventId::with_20_digits(OffsetDateTime::now_utc(), IndexPrice::Bxbt);
@@ -563,6 +653,17 @@ mod tests {
         assert!(past_event.has_likely_occurred());
     }
 
+    #[test]
+    fn event_expires_once_attestation_deadline_passed() {
+        let event_id = BitMexPriceEventId::with_20_digits(
+            datetime!(2021-09-23 10:00:00).assume_utc(),
+            IndexPrice::Bxbt,
+        );
+
+        assert!(!event_id.is_expired(datetime!(2021-09-23 11:00:00).assume_utc()));
+        assert!(event_id.is_expired(datetime!(2021-09-23 11:00:01).assume_utc()));
+    }
+
     #[test]
     fn next_event_id_after_timestamp() {
         let event_id = next_announcement_after(
@@ -0,0 +1,397 @@
+//! Punishing a revoked commit transaction without access to our keys.
+//!
+//! If the counterparty publishes a revoked commit transaction, we can
+//! claim its output with our identity key, their publish key and their
+//! revocation key. We know their revocation key once the commit
+//! transaction was revoked, and their publish key can be extracted from
+//! the revoked commit transaction as soon as it is published, because it
+//! carries our decrypted adaptor signature.
+//!
+//! A [`JusticeKit`] contains the punish transaction, already signed with
+//! our identity key, and everything needed to complete it once the
+//! revoked commit transaction is published. It can therefore be handed
+//! to a watchtower, which punishes the counterparty while we are offline.
+
+use crate::Dlc;
+use crate::Role;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::secp256k1::SecretKey;
+use bdk::bitcoin::util::key::PublicKey;
+use bdk::bitcoin::Amount;
+use bdk::bitcoin::EcdsaSig;
+use bdk::bitcoin::OutPoint;
+use bdk::bitcoin::Script;
+use bdk::bitcoin::Transaction;
+use bdk::bitcoin::TxIn;
+use bdk::bitcoin::TxOut;
+use bdk::bitcoin::Txid;
+use bdk::descriptor::Descriptor;
+use bdk::miniscript::DescriptorTrait;
+use bdk::miniscript::MiniscriptKey;
+use maia::spending_tx_sighash;
+use maia_core::secp256k1_zkp;
+use maia_core::secp256k1_zkp::ecdsa::Signature;
+use maia_core::secp256k1_zkp::EcdsaAdaptorSignature;
+use maia_core::secp256k1_zkp::SECP256K1;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The fee in satoshis the punish transaction pays.
+///
+/// The punish transaction has to confirm before the counterparty can
+/// publish a CET spending the revoked commit transaction, so it pays a
+/// generous fixed fee rather than relying on a fee estimate from the
+/// time the commit transaction was revoked.
+pub const PUNISH_TX_FEE_SATS: u64 = 10_000;
+
+/// Everything needed to punish the publication of a revoked commit
+/// transaction.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct JusticeKit {
+    /// The punish transaction, without a witness
+    punish_tx: Transaction,
+    commit_descriptor: Descriptor<PublicKey>,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
+    commit_amount: Amount,
+
+    identity_pk_ours: PublicKey,
+    /// Our signature of the punish transaction
+    sig_ours: Signature,
+
+    encsig_ours: EcdsaAdaptorSignature,
+    revocation_sk_theirs: SecretKey,
+    publication_pk_theirs: PublicKey,
+}
+
+impl JusticeKit {
+    /// Build the kit to punish the revoked commit transaction with
+    /// `revoked_commit_txid`, paying to `script_pubkey`.
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        revoked_commit_txid: Txid,
+        commit_descriptor: Descriptor<PublicKey>,
+        commit_amount: Amount,
+        identity_sk_ours: &SecretKey,
+        script_pubkey: Script,
+        encsig_ours: EcdsaAdaptorSignature,
+        revocation_sk_theirs: SecretKey,
+        publication_pk_theirs: PublicKey,
+    ) -> Result<Self> {
+        let value = commit_amount
+            .checked_sub(Amount::from_sat(PUNISH_TX_FEE_SATS))
+            .context("Commit output does not cover the fee of the punish transaction")?;
+
+        let punish_tx = Transaction {
+            version: 2,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(revoked_commit_txid, 0),
+                ..Default::default()
+            }],
+            lock_time: 0,
+            output: vec![TxOut {
+                value: value.as_sat(),
+                script_pubkey,
+            }],
+        };
+
+        let sighash = spending_tx_sighash(&punish_tx, &commit_descriptor, commit_amount)
+            .context("could not obtain sighash")?;
+        let sig_ours = SECP256K1.sign_ecdsa(&sighash, identity_sk_ours);
+
+        let identity_pk_ours = PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(
+            SECP256K1,
+            identity_sk_ours,
+        ));
+
+        Ok(Self {
+            punish_tx,
+            commit_descriptor,
+            commit_amount,
+            identity_pk_ours,
+            sig_ours,
+            encsig_ours,
+            revocation_sk_theirs,
+            publication_pk_theirs,
+        })
+    }
+
+    /// The txid of the revoked commit transaction this kit punishes.
+    pub fn revoked_commit_txid(&self) -> Txid {
+        self.punish_tx.input[0].previous_output.txid
+    }
+
+    /// The script pubkey of the output of the revoked commit
+    /// transaction, which shows up on chain once it is published.
+    pub fn revoked_commit_script_pubkey(&self) -> Script {
+        self.commit_descriptor.script_pubkey()
+    }
+
+    /// Complete the punish transaction, given the revoked commit
+    /// transaction as it was published by the counterparty.
+    pub fn punish_transaction(&self, revoked_commit_tx: &Transaction) -> Result<Transaction> {
+        ensure!(
+            revoked_commit_tx.txid() == self.revoked_commit_txid(),
+            "Transaction {} is not the revoked commit transaction {}",
+            revoked_commit_tx.txid(),
+            self.revoked_commit_txid()
+        );
+
+        let publish_sk_theirs = self
+            .extract_publish_sk(revoked_commit_tx)
+            .context("Failed to extract publish key from revoked commit transaction")?;
+
+        let mut punish_tx = self.punish_tx.clone();
+        let sighash = spending_tx_sighash(&punish_tx, &self.commit_descriptor, self.commit_amount)
+            .context("could not obtain sighash")?;
+
+        let revocation_pk_theirs = PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(
+            SECP256K1,
+            &self.revocation_sk_theirs,
+        ));
+        let sig_publish_theirs = SECP256K1.sign_ecdsa(&sighash, &publish_sk_theirs);
+        let sig_revocation_theirs = SECP256K1.sign_ecdsa(&sighash, &self.revocation_sk_theirs);
+
+        let satisfier = [
+            (self.identity_pk_ours, self.sig_ours),
+            (self.publication_pk_theirs, sig_publish_theirs),
+            (revocation_pk_theirs, sig_revocation_theirs),
+        ]
+        .into_iter()
+        .map(|(pk, sig)| (pk.to_pubkeyhash(), (pk, EcdsaSig::sighash_all(sig))))
+        .collect::<HashMap<_, _>>();
+
+        self.commit_descriptor
+            .satisfy(&mut punish_tx.input[0], satisfier)
+            .context("Failed to satisfy commit descriptor")?;
+
+        Ok(punish_tx)
+    }
+
+    /// Recover the publish key of the counterparty from our signature
+    /// on the revoked commit transaction, which they decrypted with it.
+    fn extract_publish_sk(&self, revoked_commit_tx: &Transaction) -> Option<SecretKey> {
+        let input = revoked_commit_tx.input.first()?;
+
+        input
+            .witness
+            .iter()
+            .filter_map(|element| {
+                let (_sighash_type, der) = element.split_last()?;
+                Signature::from_der(der).ok()
+            })
+            .find_map(|sig| {
+                let sk = self
+                    .encsig_ours
+                    .recover(SECP256K1, &sig, &self.publication_pk_theirs.inner)
+                    .ok()?;
+                let pk = secp256k1_zkp::PublicKey::from_secret_key(SECP256K1, &sk);
+
+                (pk == self.publication_pk_theirs.inner).then_some(sk)
+            })
+    }
+}
+
+impl Dlc {
+    /// The justice kits for all revoked commit transactions whose
+    /// descriptor is known, paying to our address.
+    ///
+    /// All commit transactions of a CFD spend the same lock output and
+    /// pay the same fee, so the output of a revoked commit transaction
+    /// has the same amount as the output of the latest one.
+    pub fn justice_kits(&self, role: Role) -> Result<Vec<JusticeKit>> {
+        let commit_amount = Amount::from_sat(self.commit.0.output[0].value);
+
+        self.revoked_commit
+            .iter()
+            .filter_map(|revoked| Some((revoked, revoked.commit_descriptor.clone()?)))
+            .map(|(revoked, commit_descriptor)| {
+                JusticeKit::new(
+                    revoked.txid,
+                    commit_descriptor,
+                    commit_amount,
+                    &self.identity,
+                    self.script_pubkey_for(role),
+                    revoked.encsig_ours,
+                    revoked.revocation_sk_theirs,
+                    revoked.publication_pk_theirs,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::Witness;
+    use bdk_ext::keypair;
+    use maia::commit_descriptor;
+    use maia_core::secp256k1_zkp::Message;
+    use rand::thread_rng;
+
+    struct Party {
+        identity: (SecretKey, PublicKey),
+        revocation: (SecretKey, PublicKey),
+        publish: (SecretKey, PublicKey),
+    }
+
+    impl Party {
+        fn new() -> Self {
+            Self {
+                identity: keypair::new(&mut thread_rng()),
+                revocation: keypair::new(&mut thread_rng()),
+                publish: keypair::new(&mut thread_rng()),
+            }
+        }
+
+        fn keys(&self) -> (PublicKey, PublicKey, PublicKey) {
+            (self.identity.1, self.revocation.1, self.publish.1)
+        }
+    }
+
+    /// A commit transaction as the counterparty publishes it: its witness
+    /// carries our adaptor signature, decrypted with their publish key.
+    fn published_commit_tx(
+        commit_descriptor: &Descriptor<PublicKey>,
+        commit_amount: Amount,
+        encsig_ours: &EcdsaAdaptorSignature,
+        publish_sk_theirs: &SecretKey,
+    ) -> Transaction {
+        let mut sig_ours = encsig_ours
+            .decrypt(publish_sk_theirs)
+            .unwrap()
+            .serialize_der()
+            .to_vec();
+        sig_ours.push(0x01);
+
+        Transaction {
+            version: 2,
+            input: vec![TxIn {
+                witness: Witness::from_vec(vec![Vec::new(), sig_ours]),
+                ..Default::default()
+            }],
+            lock_time: 0,
+            output: vec![TxOut {
+                value: commit_amount.as_sat(),
+                script_pubkey: commit_descriptor.script_pubkey(),
+            }],
+        }
+    }
+
+    fn ecdsa_signatures(tx: &Transaction) -> Vec<Signature> {
+        tx.input[0]
+            .witness
+            .iter()
+            .filter_map(|element| {
+                let (_sighash_type, der) = element.split_last()?;
+                Signature::from_der(der).ok()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn punish_transaction_spends_revoked_commit_transaction_with_punish_keys() {
+        let maker = Party::new();
+        let taker = Party::new();
+        let commit_descriptor = commit_descriptor(maker.keys(), taker.keys());
+        let commit_amount = Amount::from_sat(100_000);
+
+        // The taker punishes the maker
+        let lock_sighash = Message::from_slice(&[1u8; 32]).unwrap();
+        let encsig_taker = EcdsaAdaptorSignature::encrypt_no_aux_rand(
+            SECP256K1,
+            &lock_sighash,
+            &taker.identity.0,
+            &maker.publish.1.inner,
+        );
+        let revoked_commit_tx = published_commit_tx(
+            &commit_descriptor,
+            commit_amount,
+            &encsig_taker,
+            &maker.publish.0,
+        );
+
+        let kit = JusticeKit::new(
+            revoked_commit_tx.txid(),
+            commit_descriptor.clone(),
+            commit_amount,
+            &taker.identity.0,
+            Script::new(),
+            encsig_taker,
+            maker.revocation.0,
+            maker.publish.1,
+        )
+        .unwrap();
+
+        let punish_tx = kit.punish_transaction(&revoked_commit_tx).unwrap();
+
+        assert_eq!(
+            punish_tx.input[0].previous_output,
+            OutPoint::new(revoked_commit_tx.txid(), 0)
+        );
+        assert_eq!(
+            punish_tx.output[0].value,
+            commit_amount.as_sat() - PUNISH_TX_FEE_SATS
+        );
+        assert_eq!(
+            punish_tx.input[0].witness.iter().last(),
+            Some(commit_descriptor.explicit_script().as_bytes())
+        );
+
+        let sighash = spending_tx_sighash(&punish_tx, &commit_descriptor, commit_amount).unwrap();
+        let sigs = ecdsa_signatures(&punish_tx);
+        for pk in [taker.identity.1, maker.publish.1, maker.revocation.1] {
+            assert!(
+                sigs.iter()
+                    .any(|sig| SECP256K1.verify_ecdsa(&sighash, sig, &pk.inner).is_ok()),
+                "No valid signature for {pk}"
+            );
+        }
+    }
+
+    #[test]
+    fn punish_transaction_fails_for_other_commit_transaction() {
+        let maker = Party::new();
+        let taker = Party::new();
+        let commit_descriptor = commit_descriptor(maker.keys(), taker.keys());
+        let commit_amount = Amount::from_sat(100_000);
+
+        let lock_sighash = Message::from_slice(&[1u8; 32]).unwrap();
+        let encsig_taker = EcdsaAdaptorSignature::encrypt_no_aux_rand(
+            SECP256K1,
+            &lock_sighash,
+            &taker.identity.0,
+            &maker.publish.1.inner,
+        );
+        let revoked_commit_tx = published_commit_tx(
+            &commit_descriptor,
+            commit_amount,
+            &encsig_taker,
+            &maker.publish.0,
+        );
+
+        let kit = JusticeKit::new(
+            revoked_commit_tx.txid(),
+            commit_descriptor.clone(),
+            commit_amount,
+            &taker.identity.0,
+            Script::new(),
+            encsig_taker,
+            maker.revocation.0,
+            maker.publish.1,
+        )
+        .unwrap();
+
+        let other_commit_tx = published_commit_tx(
+            &commit_descriptor,
+            Amount::from_sat(200_000),
+            &encsig_taker,
+            &maker.publish.0,
+        );
+
+        assert!(kit.punish_transaction(&other_commit_tx).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Conversions between quantities of contracts, bitcoin and US dollars.
+//!
+//! The value of a contract depends on the contract symbol: a BTCUSD
+//! contract (inverse) is worth one US dollar, whereas an ETHUSD contract
+//! (quanto) is worth `ETHUSD_MULTIPLIER` bitcoin per US dollar of the
+//! ether price.
+
+use crate::payout_curve::ETHUSD_MULTIPLIER;
+use crate::ContractSymbol;
+use crate::Contracts;
+use crate::LotSize;
+use crate::Price;
+use bdk::bitcoin::Amount;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use rust_decimal::RoundingStrategy;
+use rust_decimal_macros::dec;
+
+const SATS_PER_BTC: Decimal = dec!(100_000_000);
+
+/// The value of `quantity` in bitcoin at `price`.
+///
+/// Rounded to the nearest satoshi.
+pub fn contracts_to_sats(
+    contract_symbol: ContractSymbol,
+    price: Price,
+    quantity: Contracts,
+) -> Amount {
+    let btc = match contract_symbol {
+        ContractSymbol::BtcUsd => quantity.into_decimal() / price.into_decimal(),
+        ContractSymbol::EthUsd => {
+            quantity.into_decimal() * price.into_decimal() * ETHUSD_MULTIPLIER
+        }
+    };
+
+    to_sats(btc)
+}
+
+/// The largest quantity which is worth at most `amount` at `price`.
+///
+/// The quantity is rounded down to a multiple of `lot_size`, i.e. it can
+/// be entered as is.
+pub fn sats_to_contracts(
+    contract_symbol: ContractSymbol,
+    price: Price,
+    amount: Amount,
+    lot_size: LotSize,
+) -> Contracts {
+    let btc = Decimal::from(amount.as_sat()) / SATS_PER_BTC;
+
+    let quantity = match contract_symbol {
+        ContractSymbol::BtcUsd => btc * price.into_decimal(),
+        ContractSymbol::EthUsd => btc / (price.into_decimal() * ETHUSD_MULTIPLIER),
+    };
+
+    let lot_size = Contracts::from(lot_size).into_decimal();
+    let lots = (quantity / lot_size).floor();
+
+    Contracts::new(
+        (lots * lot_size)
+            .to_u64()
+            .expect("quantity to fit into u64"),
+    )
+}
+
+/// The value of `amount` in US dollars at the bitcoin price `btc_price`.
+pub fn sats_to_usd(amount: Amount, btc_price: Price) -> Decimal {
+    Decimal::from(amount.as_sat()) / SATS_PER_BTC * btc_price.into_decimal()
+}
+
+/// The value of `usd` US dollars in bitcoin at the bitcoin price `btc_price`.
+///
+/// Rounded to the nearest satoshi.
+pub fn usd_to_sats(usd: Decimal, btc_price: Price) -> Amount {
+    to_sats(usd / btc_price.into_decimal())
+}
+
+fn to_sats(btc: Decimal) -> Amount {
+    let sats =
+        (btc * SATS_PER_BTC).round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero);
+
+    Amount::from_sat(sats.to_u64().expect("amount to fit into u64"))
+}
+
+/// Reasons why a quantity cannot be entered for an offer.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidQuantity {
+    #[error("Quantity {quantity} is not a multiple of the lot size {lot_size}")]
+    NotMultipleOfLotSize {
+        quantity: Contracts,
+        lot_size: Contracts,
+    },
+    #[error("Quantity {quantity} is below the minimum of {min}")]
+    BelowMinimum { quantity: Contracts, min: Contracts },
+    #[error("Quantity {quantity} is above the maximum of {max}")]
+    AboveMaximum { quantity: Contracts, max: Contracts },
+}
+
+/// Check whether `quantity` can be entered for an offer with the given
+/// bounds and lot size.
+pub fn validate_quantity(
+    quantity: Contracts,
+    min: Contracts,
+    max: Contracts,
+    lot_size: LotSize,
+) -> Result<(), InvalidQuantity> {
+    let lot_size = Contracts::from(lot_size);
+
+    if lot_size.into_decimal().is_zero()
+        || !(quantity.into_decimal() % lot_size.into_decimal()).is_zero()
+    {
+        return Err(InvalidQuantity::NotMultipleOfLotSize { quantity, lot_size });
+    }
+
+    if quantity < min {
+        return Err(InvalidQuantity::BelowMinimum { quantity, min });
+    }
+
+    if quantity > max {
+        return Err(InvalidQuantity::AboveMaximum { quantity, max });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn btcusd_contract_is_worth_one_dollar() {
+        let price = Price::new(dec!(20_000)).unwrap();
+
+        let amount = contracts_to_sats(ContractSymbol::BtcUsd, price, Contracts::new(100));
+
+        assert_eq!(amount, Amount::from_sat(500_000));
+        assert_eq!(sats_to_usd(amount, price), dec!(100));
+    }
+
+    #[test]
+    fn ethusd_contract_is_worth_multiplier_per_dollar() {
+        let price = Price::new(dec!(1_500)).unwrap();
+
+        let amount = contracts_to_sats(ContractSymbol::EthUsd, price, Contracts::new(100));
+
+        assert_eq!(amount, Amount::from_btc(0.15).unwrap());
+    }
+
+    #[test]
+    fn quantity_is_validated_against_offer() {
+        let min = Contracts::new(300);
+        let max = Contracts::new(800);
+        let lot_size = LotSize::new(100);
+
+        assert!(validate_quantity(Contracts::new(500), min, max, lot_size).is_ok());
+        assert!(matches!(
+            validate_quantity(Contracts::new(550), min, max, lot_size),
+            Err(InvalidQuantity::NotMultipleOfLotSize { .. })
+        ));
+        assert!(matches!(
+            validate_quantity(Contracts::new(200), min, max, lot_size),
+            Err(InvalidQuantity::BelowMinimum { .. })
+        ));
+        assert!(matches!(
+            validate_quantity(Contracts::new(900), min, max, lot_size),
+            Err(InvalidQuantity::AboveMaximum { .. })
+        ));
+    }
+
+    fn contract_symbol() -> impl Strategy<Value = ContractSymbol> {
+        prop_oneof![Just(ContractSymbol::BtcUsd), Just(ContractSymbol::EthUsd)]
+    }
+
+    proptest! {
+        #[test]
+        fn converting_contracts_to_sats_and_back_loses_at_most_one_lot(
+            contract_symbol in contract_symbol(),
+            price in 100u64..100_000u64,
+            lots in 1u64..10_000u64,
+            lot_size in 1u8..100u8,
+        ) {
+            let price = Price::new(Decimal::from(price)).unwrap();
+            let quantity = Contracts::new(lots * u64::from(lot_size));
+            let lot_size = LotSize::new(lot_size);
+
+            let amount = contracts_to_sats(contract_symbol, price, quantity);
+            let round_trip = sats_to_contracts(contract_symbol, price, amount, lot_size);
+
+            prop_assert!(round_trip <= quantity);
+            prop_assert!(round_trip + Contracts::from(lot_size) >= quantity);
+        }
+
+        #[test]
+        fn contracts_from_sats_can_be_entered_as_is(
+            contract_symbol in contract_symbol(),
+            price in 100u64..100_000u64,
+            sats in 0u64..10_000_000_000u64,
+            lot_size in 1u8..100u8,
+        ) {
+            let price = Price::new(Decimal::from(price)).unwrap();
+            let lot_size = LotSize::new(lot_size);
+
+            let amount = Amount::from_sat(sats);
+
+            let quantity = sats_to_contracts(contract_symbol, price, amount, lot_size);
+
+            prop_assert!(
+                validate_quantity(quantity, Contracts::ZERO, quantity, lot_size).is_ok()
+            );
+        }
+
+        #[test]
+        fn converting_usd_to_sats_and_back_loses_at_most_one_sat(
+            usd in 0u64..1_000_000u64,
+            btc_price in 100u64..100_000u64,
+        ) {
+            let usd = Decimal::from(usd);
+            let btc_price = Price::new(Decimal::from(btc_price)).unwrap();
+
+            let round_trip = sats_to_usd(usd_to_sats(usd, btc_price), btc_price);
+            let one_sat = sats_to_usd(Amount::from_sat(1), btc_price);
+
+            prop_assert!((round_trip - usd).abs() <= one_sat);
+        }
+    }
+}
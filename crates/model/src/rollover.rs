@@ -4,7 +4,9 @@ use crate::Contracts;
 use crate::Dlc;
 use crate::FeeAccount;
 use crate::FundingFee;
+use crate::FundingRate;
 use crate::Leverage;
+use crate::Position;
 use crate::Price;
 use crate::RevokedCommit;
 use crate::TxFeeRate;
@@ -15,11 +17,14 @@ use anyhow::Result;
 use bdk::bitcoin::secp256k1::SecretKey;
 use bdk::bitcoin::PublicKey;
 use bdk::bitcoin::Script;
+use bdk::descriptor::Descriptor;
 use bdk::miniscript::DescriptorTrait;
 use bdk_ext::SecretKeyExt;
 use maia_core::secp256k1_zkp;
 use maia_core::secp256k1_zkp::EcdsaAdaptorSignature;
 use maia_core::secp256k1_zkp::SECP256K1;
+use serde::Deserialize;
+use serde::Serialize;
 
 #[derive(Debug, Clone, Copy)]
 pub struct RolloverParams {
@@ -94,6 +99,9 @@ pub struct BaseCommitParams {
     pub commit_txid: Txid,
     pub commit_script_pubkey: Script,
 
+    // To let a watchtower punish.
+    pub commit_descriptor: Option<Descriptor<PublicKey>>,
+
     // To allow rolling over from arbitrary base.
     pub settlement_event_id: BitMexPriceEventId,
     pub revocation_sk_ours: SecretKey,
@@ -128,6 +136,7 @@ impl Dlc {
                 publish_pk_theirs: self.publish_pk_counterparty,
                 commit_txid: self.commit.0.txid(),
                 commit_script_pubkey: self.commit.2.script_pubkey(),
+                commit_descriptor: Some(self.commit.2.clone()),
                 settlement_event_id: self.settlement_event_id,
                 revocation_sk_ours: self.revocation,
                 complete_fee,
@@ -152,6 +161,7 @@ impl Dlc {
             publication_pk_theirs: publish_pk_theirs,
             txid: commit_txid,
             script_pubkey: commit_script_pubkey,
+            commit_descriptor,
             settlement_event_id,
             complete_fee,
         } = self
@@ -180,6 +190,7 @@ impl Dlc {
                 publish_pk_theirs,
                 commit_txid,
                 commit_script_pubkey,
+                commit_descriptor,
                 settlement_event_id,
                 complete_fee,
             },
@@ -212,6 +223,7 @@ impl BaseDlcParams {
             publication_pk_theirs: self.base_commit_params.publish_pk_theirs,
             txid: self.base_commit_params.commit_txid,
             script_pubkey: self.base_commit_params.commit_script_pubkey,
+            commit_descriptor: self.base_commit_params.commit_descriptor,
             settlement_event_id: Some(self.base_commit_params.settlement_event_id),
             complete_fee: Some(self.base_commit_params.complete_fee),
         };
@@ -234,3 +246,95 @@ impl BaseDlcParams {
         self.base_commit_params.complete_fee
     }
 }
+
+/// Determines whether the taker automatically rolls over a CFD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum RolloverPolicy {
+    Always,
+    Never,
+    /// Only roll over if the funding rate we would pay is below `max_funding_rate`.
+    ///
+    /// A negative funding rate means that we would be paid funding.
+    FundingRateBelow {
+        max_funding_rate: FundingRate,
+    },
+}
+
+impl RolloverPolicy {
+    /// Decide whether to roll over a CFD in which we hold `position`.
+    ///
+    /// `funding_rate` is the funding rate currently offered by the maker for our position, if
+    /// known.
+    pub fn allows_rollover(&self, position: Position, funding_rate: Option<FundingRate>) -> bool {
+        match self {
+            RolloverPolicy::Always => true,
+            RolloverPolicy::Never => false,
+            RolloverPolicy::FundingRateBelow { max_funding_rate } => {
+                let funding_rate = match funding_rate {
+                    Some(funding_rate) => funding_rate.to_decimal(),
+                    None => return false,
+                };
+
+                // A positive funding rate means that long pays short
+                let rate_paid = match position {
+                    Position::Long => funding_rate,
+                    Position::Short => -funding_rate,
+                };
+
+                rate_paid < max_funding_rate.to_decimal()
+            }
+        }
+    }
+}
+
+impl Default for RolloverPolicy {
+    fn default() -> Self {
+        Self::Always
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn given_funding_rate_below_policy_then_only_cheap_rollovers_are_allowed() {
+        let policy = RolloverPolicy::FundingRateBelow {
+            max_funding_rate: FundingRate::new(dec!(0.001)).unwrap(),
+        };
+
+        let cheap = FundingRate::new(dec!(0.0005)).unwrap();
+        let expensive = FundingRate::new(dec!(0.002)).unwrap();
+
+        assert!(policy.allows_rollover(Position::Long, Some(cheap)));
+        assert!(!policy.allows_rollover(Position::Long, Some(expensive)));
+        assert!(!policy.allows_rollover(Position::Long, None));
+    }
+
+    #[test]
+    fn given_funding_rate_below_policy_when_short_then_rate_is_paid_in_reverse() {
+        let policy = RolloverPolicy::FundingRateBelow {
+            max_funding_rate: FundingRate::new(dec!(0.001)).unwrap(),
+        };
+
+        let long_pays = FundingRate::new(dec!(0.002)).unwrap();
+        let short_pays = FundingRate::new(dec!(-0.002)).unwrap();
+
+        assert!(policy.allows_rollover(Position::Short, Some(long_pays)));
+        assert!(!policy.allows_rollover(Position::Short, Some(short_pays)));
+    }
+
+    #[test]
+    fn rollover_policy_serde_roundtrip() {
+        let policy = RolloverPolicy::FundingRateBelow {
+            max_funding_rate: FundingRate::new(dec!(0.001)).unwrap(),
+        };
+
+        let json = serde_json::to_string(&policy).unwrap();
+        let deserialized = serde_json::from_str::<RolloverPolicy>(&json).unwrap();
+
+        assert_eq!(policy, deserialized);
+    }
+}
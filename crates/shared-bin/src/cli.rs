@@ -1,19 +1,42 @@
 use crate::MAINNET_ELECTRUM;
 use crate::TESTNET_ELECTRUM;
+use anyhow::bail;
+use anyhow::Result;
+use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
 use daemon::bdk::bitcoin;
 use daemon::bdk::bitcoin::Address;
 use daemon::bdk::bitcoin::Amount;
+use daemon::bdk::bitcoin::OutPoint;
+use daemon::bdk::bitcoin::XOnlyPublicKey;
+use daemon::block_explorer::BlockExplorer;
+use daemon::db_backup::BackupSettings;
+use daemon::fee_estimation::FeeEstimator;
+use daemon::fiat_rates;
+use daemon::fiat_rates::Currency;
+use daemon::price_feed;
+use daemon::process_manager;
+use daemon::wallet::AddressReusePolicy;
+use daemon::ConnectionSettings;
+use model::olivia;
+use model::olivia::OracleKeys;
+use reqwest::Url;
 use std::path::PathBuf;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 
 #[derive(Parser, Clone)]
 pub enum Network {
     /// Run on mainnet (default)
     Mainnet {
         /// URL to the electrum backend to use for the wallet.
+        ///
+        /// Can be given multiple times to fail over to the next healthy server if the current one
+        /// is unreachable or lags behind.
         #[clap(long, default_value = MAINNET_ELECTRUM)]
-        electrum: String,
+        electrum: Vec<String>,
 
         #[clap(subcommand)]
         withdraw: Option<Withdraw>,
@@ -21,8 +44,11 @@ pub enum Network {
     /// Run on testnet
     Testnet {
         /// URL to the electrum backend to use for the wallet.
+        ///
+        /// Can be given multiple times to fail over to the next healthy server if the current one
+        /// is unreachable or lags behind.
         #[clap(long, default_value = TESTNET_ELECTRUM)]
-        electrum: String,
+        electrum: Vec<String>,
 
         #[clap(subcommand)]
         withdraw: Option<Withdraw>,
@@ -30,8 +56,11 @@ pub enum Network {
     /// Run on signet
     Signet {
         /// URL to the electrum backend to use for the wallet.
-        #[clap(long)]
-        electrum: String,
+        ///
+        /// Can be given multiple times to fail over to the next healthy server if the current one
+        /// is unreachable or lags behind.
+        #[clap(long, required = true)]
+        electrum: Vec<String>,
 
         #[clap(subcommand)]
         withdraw: Option<Withdraw>,
@@ -39,8 +68,11 @@ pub enum Network {
     /// Run on regtest
     Regtest {
         /// URL to the electrum backend to use for the wallet.
-        #[clap(long)]
-        electrum: String,
+        ///
+        /// Can be given multiple times to fail over to the next healthy server if the current one
+        /// is unreachable or lags behind.
+        #[clap(long, required = true)]
+        electrum: Vec<String>,
 
         #[clap(subcommand)]
         withdraw: Option<Withdraw>,
@@ -50,7 +82,7 @@ pub enum Network {
 impl Default for Network {
     fn default() -> Self {
         Network::Mainnet {
-            electrum: MAINNET_ELECTRUM.to_string(),
+            electrum: vec![MAINNET_ELECTRUM.to_string()],
             withdraw: None,
         }
     }
@@ -70,11 +102,19 @@ pub enum Withdraw {
         /// The address to receive the Bitcoin.
         #[clap(long)]
         address: Address,
+        /// Only spend the given UTXO, e.g. "<txid>:<vout>". Can be given multiple times. If not
+        /// specified, UTXOs are selected automatically.
+        #[clap(long = "utxo")]
+        utxos: Vec<OutPoint>,
+        /// Never spend the given UTXO, e.g. "<txid>:<vout>". Can be given multiple times.
+        #[clap(long)]
+        exclude: Vec<OutPoint>,
     },
 }
 
 impl Network {
-    pub fn electrum(&self) -> &str {
+    /// The configured electrum servers, in order of preference.
+    pub fn electrum(&self) -> &[String] {
         match self {
             Network::Mainnet { electrum, .. } => electrum,
             Network::Testnet { electrum, .. } => electrum,
@@ -140,3 +180,331 @@ impl std::fmt::Debug for Network {
         }
     }
 }
+
+/// The oracle attesting to the prices CFDs settle at.
+#[derive(Args, Clone, Debug, Default)]
+pub struct Oracle {
+    /// URL of the oracle.
+    ///
+    /// Defaults to olivia, or to the `dev-oracle` at its default address on regtest. Only point
+    /// this to an oracle other than olivia for local development. CFDs set up with one oracle can
+    /// only settle with its attestations.
+    #[clap(long)]
+    oracle_url: Option<Url>,
+
+    /// The public key of the oracle, as a 32 byte hex string.
+    ///
+    /// Defaults to olivia's public key, or to the one of the `dev-oracle` with its default seed on
+    /// regtest.
+    #[clap(long)]
+    oracle_pk: Option<XOnlyPublicKey>,
+}
+
+impl Oracle {
+    pub fn url(&self, network: bitcoin::Network) -> Url {
+        self.oracle_url.clone().unwrap_or_else(|| {
+            let url = match network {
+                bitcoin::Network::Regtest => olivia::DEV_BASE_URL,
+                _ => olivia::BASE_URL,
+            };
+
+            url.parse().expect("valid URL from constant")
+        })
+    }
+
+    pub fn public_key(&self, network: bitcoin::Network) -> XOnlyPublicKey {
+        self.oracle_pk.unwrap_or_else(|| match network {
+            bitcoin::Network::Regtest => *olivia::DEV_PUBLIC_KEY,
+            _ => *olivia::PUBLIC_KEY,
+        })
+    }
+}
+
+/// Rotation to a new oracle public key.
+#[derive(Args, Clone, Debug, Default)]
+pub struct OracleKeyRotation {
+    /// The public key the oracle rotates to, as a 32 byte hex string.
+    ///
+    /// Events at or after `--next-oracle-pk-effective-from` are expected to be attested with this
+    /// key. CFDs settling before that keep using the current key.
+    #[clap(long)]
+    next_oracle_pk: Option<XOnlyPublicKey>,
+
+    /// When the next oracle public key becomes effective, e.g. `2022-11-01T00:00:00Z`.
+    #[clap(long, parse(try_from_str = parse_rfc3339))]
+    next_oracle_pk_effective_from: Option<OffsetDateTime>,
+}
+
+impl OracleKeyRotation {
+    /// The keys of the oracle, rotating away from `current` if configured.
+    pub fn oracle_keys(&self, current: XOnlyPublicKey) -> Result<OracleKeys> {
+        let keys = match (self.next_oracle_pk, self.next_oracle_pk_effective_from) {
+            (Some(next), Some(effective_from)) => {
+                OracleKeys::new(current).with_next_key(next, effective_from)
+            }
+            (None, None) => OracleKeys::new(current),
+            _ => bail!(
+                "--next-oracle-pk and --next-oracle-pk-effective-from must be specified together"
+            ),
+        };
+
+        Ok(keys)
+    }
+}
+
+/// Timings of the connections between taker and maker.
+#[derive(Args, Clone, Debug)]
+pub struct Connection {
+    /// How often to ping the counterparty, in seconds.
+    #[clap(long, default_value = "30")]
+    ping_interval_secs: u64,
+
+    /// How long to wait for a connection to be established, in seconds.
+    ///
+    /// Increase this for links with a high latency, e.g. over Tor or satellite.
+    #[clap(long, default_value = "20")]
+    connection_timeout_secs: u64,
+}
+
+impl Connection {
+    pub fn settings(&self) -> Result<ConnectionSettings> {
+        ConnectionSettings::new(
+            Duration::from_secs(self.ping_interval_secs),
+            Duration::from_secs(self.connection_timeout_secs),
+        )
+    }
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        let settings = ConnectionSettings::default();
+
+        Self {
+            ping_interval_secs: settings.ping_interval.as_secs(),
+            connection_timeout_secs: settings.connection_timeout.as_secs(),
+        }
+    }
+}
+
+/// Where to estimate the fee rates of transactions we broadcast.
+#[derive(Args, Clone, Debug, Default)]
+pub struct FeeEstimation {
+    /// Estimate fee rates with the mempool.space API at this URL, e.g. `https://mempool.space`.
+    ///
+    /// If not specified, or if the API cannot be reached, fee rates are estimated by the Electrum
+    /// server.
+    #[clap(long)]
+    mempool_space_url: Option<String>,
+}
+
+impl FeeEstimation {
+    pub fn fee_estimator(&self) -> FeeEstimator {
+        match &self.mempool_space_url {
+            Some(url) => FeeEstimator::mempool_space(url.clone()),
+            None => FeeEstimator::Electrum,
+        }
+    }
+}
+
+/// The fiat currency amounts are valued in, in addition to bitcoin.
+#[derive(Args, Clone, Debug)]
+pub struct FiatDisplay {
+    /// Value balances, margins and profits in this fiat currency: 'USD', 'EUR', 'GBP' or 'CHF'.
+    ///
+    /// Bitcoin is valued at the BTCUSD price and converted at the daily reference rates of the
+    /// European Central Bank. If not specified, amounts are only reported in bitcoin and, for
+    /// BTCUSD contracts, in US dollars.
+    #[clap(long)]
+    fiat_currency: Option<Currency>,
+
+    /// URL of the exchange rate provider, which has to serve the API of frankfurter.app.
+    #[clap(long, default_value = fiat_rates::DEFAULT_PROVIDER_URL)]
+    fiat_rates_url: String,
+}
+
+impl FiatDisplay {
+    /// `None` if no fiat currency was selected.
+    pub fn provider(&self) -> Option<fiat_rates::Provider> {
+        self.fiat_currency
+            .map(|currency| fiat_rates::Provider::new(self.fiat_rates_url.clone(), currency))
+    }
+}
+
+impl Default for FiatDisplay {
+    fn default() -> Self {
+        Self {
+            fiat_currency: None,
+            fiat_rates_url: fiat_rates::DEFAULT_PROVIDER_URL.to_owned(),
+        }
+    }
+}
+
+/// Where quotes come from, in addition to BitMEX.
+#[derive(Args, Clone, Debug)]
+pub struct PriceFeed {
+    /// Also poll the ticker of this exchange for quotes: 'kraken', 'coinbase' or 'deribit'. Can
+    /// be given multiple times.
+    ///
+    /// The exchanges always quote mainnet prices, regardless of the network of the daemon.
+    #[clap(long = "price-feed-exchange")]
+    exchanges: Vec<price_feed::Exchange>,
+
+    /// How to combine the quotes of BitMEX and the additional exchanges: 'fallback' or 'median'.
+    ///
+    /// With 'fallback' the quote of the first exchange with a fresh quote is used, starting with
+    /// BitMEX and continuing in the order the exchanges were given. With 'median' the median bid
+    /// and ask of all fresh quotes are used.
+    #[clap(long, default_value = "fallback")]
+    price_feed_policy: price_feed::Policy,
+
+    /// For how many minutes to keep the combined quotes in memory, which limits the window of
+    /// time-weighted average prices.
+    #[clap(long, default_value = "60")]
+    price_history_mins: u64,
+}
+
+impl PriceFeed {
+    pub fn exchanges(&self) -> &[price_feed::Exchange] {
+        &self.exchanges
+    }
+
+    pub fn policy(&self) -> price_feed::Policy {
+        self.price_feed_policy
+    }
+
+    pub fn history_window(&self) -> time::Duration {
+        time::Duration::minutes(self.price_history_mins as i64)
+    }
+}
+
+impl Default for PriceFeed {
+    fn default() -> Self {
+        Self {
+            exchanges: Vec::new(),
+            price_feed_policy: price_feed::Policy::default(),
+            price_history_mins: price_feed::DEFAULT_HISTORY_WINDOW.whole_minutes() as u64,
+        }
+    }
+}
+
+/// How to treat withdrawals to addresses the wallet has already used.
+#[derive(Args, Clone, Debug, Default)]
+pub struct AddressReuse {
+    /// What to do when withdrawing to an address which the wallet has already paid to or received
+    /// on: 'warn' or 'block'.
+    ///
+    /// Reusing addresses makes it easier to link transactions on-chain.
+    #[clap(long, default_value = "warn")]
+    address_reuse: AddressReusePolicy,
+}
+
+impl AddressReuse {
+    pub fn policy(&self) -> AddressReusePolicy {
+        self.address_reuse
+    }
+}
+
+/// Which block explorer the transactions of the CFDs link to.
+#[derive(Args, Clone, Debug)]
+pub struct Explorer {
+    /// The block explorer to link transactions to: 'mempool.space', 'blockstream.info' or the URL
+    /// of a self-hosted instance, e.g. `http://localhost:8080`.
+    ///
+    /// The public block explorers link to the network the daemon runs on. A self-hosted instance
+    /// is expected to serve that network at the given URL.
+    #[clap(long, default_value = "mempool.space")]
+    block_explorer: String,
+}
+
+impl Explorer {
+    pub fn block_explorer(&self, network: bitcoin::Network) -> Result<BlockExplorer> {
+        BlockExplorer::parse(&self.block_explorer, network)
+    }
+}
+
+impl Default for Explorer {
+    fn default() -> Self {
+        Self {
+            block_explorer: "mempool.space".to_owned(),
+        }
+    }
+}
+
+/// Periodic backups of the database.
+#[derive(Args, Clone, Debug)]
+pub struct DatabaseBackup {
+    /// Back up the database to this directory at every `--db-backup-interval-mins`.
+    ///
+    /// Every backup is checked for integrity. If not specified, the database is not backed up.
+    #[clap(long)]
+    db_backup_dir: Option<PathBuf>,
+
+    /// How often to back up the database, in minutes.
+    #[clap(long, default_value = "60")]
+    db_backup_interval_mins: u64,
+
+    /// How many backups to keep, older ones are deleted.
+    #[clap(long, default_value = "24")]
+    db_backup_retain: usize,
+}
+
+impl DatabaseBackup {
+    /// `None` if backups are disabled.
+    pub fn settings(&self) -> Result<Option<BackupSettings>> {
+        let dir = match &self.db_backup_dir {
+            Some(dir) => dir.clone(),
+            None => return Ok(None),
+        };
+
+        let settings = BackupSettings::new(
+            dir,
+            Duration::from_secs(self.db_backup_interval_mins * 60),
+            self.db_backup_retain,
+        )?;
+
+        Ok(Some(settings))
+    }
+}
+
+impl Default for DatabaseBackup {
+    fn default() -> Self {
+        Self {
+            db_backup_dir: None,
+            db_backup_interval_mins: 60,
+            db_backup_retain: 24,
+        }
+    }
+}
+
+/// Delivery of CFD events to the actors processing them.
+#[derive(Args, Clone, Debug)]
+pub struct EventDelivery {
+    /// How long to wait for the UI and the metrics to accept an event, in milliseconds.
+    ///
+    /// If they are too slow, the event is skipped so that the other actors are not held up. The
+    /// UI and the metrics catch up with the next event of the CFD.
+    #[clap(long, default_value = "5000")]
+    event_delivery_timeout_ms: u64,
+}
+
+impl EventDelivery {
+    pub fn timeout(&self) -> Result<Duration> {
+        if self.event_delivery_timeout_ms == 0 {
+            bail!("Event delivery timeout must not be zero");
+        }
+
+        Ok(Duration::from_millis(self.event_delivery_timeout_ms))
+    }
+}
+
+impl Default for EventDelivery {
+    fn default() -> Self {
+        Self {
+            event_delivery_timeout_ms: process_manager::DEFAULT_DELIVERY_TIMEOUT.as_millis() as u64,
+        }
+    }
+}
+
+fn parse_rfc3339(s: &str) -> Result<OffsetDateTime, time::error::Parse> {
+    OffsetDateTime::parse(s, &Rfc3339)
+}
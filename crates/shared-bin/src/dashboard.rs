@@ -0,0 +1,20 @@
+//! A minimal status dashboard which is served by the daemons.
+//!
+//! The dashboard gives node operators visibility into a daemon without the
+//! full UI, e.g. on a headless server. The page itself is static and
+//! contains no data: it asks for the password if the browser is not logged
+//! in yet, subscribes to the daemon's feed and polls its health.
+
+use rocket::http::ContentType;
+
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+#[rocket::get("/dashboard")]
+pub fn dashboard() -> (ContentType, &'static str) {
+    (ContentType::HTML, DASHBOARD)
+}
+
+/// Provide the routes serving the dashboard at `/dashboard`.
+pub fn dashboard_routes() -> Vec<rocket::Route> {
+    rocket::routes![dashboard]
+}
@@ -0,0 +1,556 @@
+//! Startup diagnostics.
+//!
+//! Misconfiguration usually surfaces deep inside an actor as a cryptic
+//! log line, long after the daemon has started. Before spawning any
+//! actors, the daemons run a set of checks against their environment
+//! and report every problem found with a stable error code and a hint
+//! on how to fix it.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use daemon::seed::RANDOM_SEED_LENGTH;
+use itertools::Itertools;
+use model::olivia;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::OffsetDateTime;
+use time::PrimitiveDateTime;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+
+/// How long we wait for a remote service to respond.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How far our clock may deviate from the oracle's clock.
+///
+/// Oracle events are attested on the hour, so a skew of a few minutes
+/// already makes us look for attestations which do not exist yet.
+const MAX_CLOCK_SKEW: time::Duration = time::Duration::minutes(2);
+
+/// Format of the HTTP `Date` header, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+const HTTP_DATE_FORMAT: &[FormatItem] = format_description!(
+    "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+);
+
+/// Stable identifier for each problem the diagnostics can detect.
+///
+/// Codes are never reused, so they can be referenced in support
+/// requests and documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    DataDirNotWritable,
+    SeedFileReadableByOthers,
+    SeedFileInvalid,
+    DatabaseNotWritable,
+    AddressInUse,
+    ElectrumUnreachable,
+    OracleUnreachable,
+    PeerUnreachable,
+    ClockSkew,
+}
+
+impl Code {
+    fn id(&self) -> &'static str {
+        match self {
+            Code::DataDirNotWritable => "E101",
+            Code::SeedFileReadableByOthers => "E102",
+            Code::SeedFileInvalid => "E103",
+            Code::DatabaseNotWritable => "E104",
+            Code::AddressInUse => "E105",
+            Code::ElectrumUnreachable => "E201",
+            Code::OracleUnreachable => "E202",
+            Code::PeerUnreachable => "E203",
+            Code::ClockSkew => "E301",
+        }
+    }
+
+    fn hint(&self) -> &'static str {
+        match self {
+            Code::DataDirNotWritable => {
+                "Check that the data directory exists and is owned by the user running the daemon, or pass a different one via --data-dir"
+            }
+            Code::SeedFileReadableByOthers => {
+                "Restrict access to the seed file, e.g. via `chmod 600`; anyone who can read it can spend your funds"
+            }
+            Code::SeedFileInvalid => {
+                "The seed file is corrupted. Restore it from your backup; do NOT delete it if you have open CFDs"
+            }
+            Code::DatabaseNotWritable => {
+                "Check the permissions of the database file and make sure no other instance of the daemon is running"
+            }
+            Code::AddressInUse => {
+                "Another process is listening on this address. Stop it or configure a different port"
+            }
+            Code::ElectrumUnreachable => {
+                "Check your internet connection or configure a different server via --electrum"
+            }
+            Code::OracleUnreachable => {
+                "Check your internet connection and firewall; without the oracle CFDs cannot be opened or settled"
+            }
+            Code::PeerUnreachable => {
+                "Check your internet connection and the configured --maker address"
+            }
+            Code::ClockSkew => {
+                "Synchronise your system clock, e.g. by enabling NTP"
+            }
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        match self {
+            // Remote services may be down only briefly, the daemon
+            // reconnects once they are back
+            Code::SeedFileReadableByOthers
+            | Code::ElectrumUnreachable
+            | Code::OracleUnreachable
+            | Code::PeerUnreachable => Severity::Warning,
+            Code::DataDirNotWritable
+            | Code::SeedFileInvalid
+            | Code::DatabaseNotWritable
+            | Code::AddressInUse
+            | Code::ClockSkew => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem detected by one of the checks.
+#[derive(Debug)]
+pub struct Finding {
+    pub code: Code,
+    pub detail: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}. {}", self.code, self.detail, self.code.hint())
+    }
+}
+
+/// The environment a daemon expects to find at startup.
+pub struct Diagnostics {
+    data_dir: PathBuf,
+    seed_file: Option<PathBuf>,
+    database: Option<PathBuf>,
+    listen_addresses: Vec<SocketAddr>,
+    electrum: Vec<String>,
+    oracle: String,
+    peers: Vec<(&'static str, String, Option<SocketAddr>)>,
+}
+
+impl Diagnostics {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            data_dir: data_dir.to_path_buf(),
+            seed_file: None,
+            database: None,
+            listen_addresses: Vec::new(),
+            electrum: Vec::new(),
+            oracle: olivia::BASE_URL.to_owned(),
+            peers: Vec::new(),
+        }
+    }
+
+    /// Check the seed file, if it exists already.
+    #[must_use]
+    pub fn seed_file(mut self, path: PathBuf) -> Self {
+        self.seed_file = Some(path);
+        self
+    }
+
+    /// Check the database file, if it exists already.
+    #[must_use]
+    pub fn database(mut self, path: PathBuf) -> Self {
+        self.database = Some(path);
+        self
+    }
+
+    /// Check that we can listen on `address`.
+    #[must_use]
+    pub fn listen(mut self, address: SocketAddr) -> Self {
+        self.listen_addresses.push(address);
+        self
+    }
+
+    /// Check that at least one of the electrum servers at `urls` can be
+    /// reached.
+    #[must_use]
+    pub fn electrum(mut self, urls: &[String]) -> Self {
+        self.electrum = urls.to_vec();
+        self
+    }
+
+    /// Check the oracle at `url` instead of olivia.
+    #[must_use]
+    pub fn oracle(mut self, url: String) -> Self {
+        self.oracle = url;
+        self
+    }
+
+    /// Check that `address` (`host:port`) of the peer called `name` can
+    /// be reached.
+    #[must_use]
+    pub fn peer(mut self, name: &'static str, address: String) -> Self {
+        self.peers.push((name, address, None));
+        self
+    }
+
+    /// Check that `address` (`host:port`) of the peer called `name` can
+    /// be reached through the SOCKS5 proxy at `proxy`.
+    ///
+    /// The proxy resolves `address`, so neither our IP address nor a DNS
+    /// request leaks, and `.onion` addresses can be checked.
+    #[must_use]
+    pub fn peer_via_socks5(
+        mut self,
+        name: &'static str,
+        address: String,
+        proxy: SocketAddr,
+    ) -> Self {
+        self.peers.push((name, address, Some(proxy)));
+        self
+    }
+
+    /// Run all checks and collect the problems found.
+    ///
+    /// The reachability of the oracle and the sanity of our clock are
+    /// always checked.
+    pub async fn run(self) -> Report {
+        let mut findings = Vec::new();
+
+        findings.extend(check_data_dir(&self.data_dir).await);
+        if let Some(seed_file) = &self.seed_file {
+            findings.extend(check_seed_file(seed_file).await);
+        }
+        if let Some(database) = &self.database {
+            findings.extend(check_database(database).await);
+        }
+        for address in &self.listen_addresses {
+            findings.extend(check_listen_address(*address).await);
+        }
+        if !self.electrum.is_empty() {
+            findings.extend(check_electrum(&self.electrum).await);
+        }
+        findings.extend(check_oracle_and_clock(&self.oracle).await);
+        for (name, address, proxy) in &self.peers {
+            findings.extend(check_peer(name, address, *proxy).await);
+        }
+
+        Report { findings }
+    }
+}
+
+/// The outcome of running [`Diagnostics`].
+#[derive(Debug)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Log all findings and fail if any of them is an error, unless
+    /// `ignore_errors` is set.
+    pub fn ensure_ok(self, ignore_errors: bool) -> Result<()> {
+        for finding in &self.findings {
+            match finding.code.severity() {
+                Severity::Warning => tracing::warn!(
+                    code = %finding.code,
+                    hint = finding.code.hint(),
+                    "{}",
+                    finding.detail
+                ),
+                Severity::Error => tracing::error!(
+                    code = %finding.code,
+                    hint = finding.code.hint(),
+                    "{}",
+                    finding.detail
+                ),
+            }
+        }
+
+        let errors = self
+            .findings
+            .iter()
+            .filter(|finding| finding.code.severity() == Severity::Error)
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            tracing::info!("Startup diagnostics passed");
+            return Ok(());
+        }
+
+        let codes = errors.iter().map(|finding| finding.code).join(", ");
+
+        if ignore_errors {
+            tracing::warn!(%codes, "Starting despite failed startup diagnostics");
+            return Ok(());
+        }
+
+        bail!(
+            "Startup diagnostics failed ({codes}):\n{}\nPass --skip-diagnostics to start anyway",
+            errors.iter().join("\n")
+        )
+    }
+}
+
+async fn check_data_dir(data_dir: &Path) -> Option<Finding> {
+    let probe = data_dir.join(".write-probe");
+
+    let result = async {
+        tokio::fs::write(&probe, b"").await?;
+        tokio::fs::remove_file(&probe).await?;
+
+        anyhow::Ok(())
+    }
+    .await;
+
+    result.err().map(|e| Finding {
+        code: Code::DataDirNotWritable,
+        detail: format!("Cannot write to {}: {e:#}", data_dir.display()),
+    })
+}
+
+async fn check_seed_file(seed_file: &Path) -> Option<Finding> {
+    let metadata = tokio::fs::metadata(seed_file).await.ok()?;
+
+    if metadata.len() != RANDOM_SEED_LENGTH as u64 {
+        return Some(Finding {
+            code: Code::SeedFileInvalid,
+            detail: format!(
+                "Seed file {} has {} bytes instead of {RANDOM_SEED_LENGTH}",
+                seed_file.display(),
+                metadata.len()
+            ),
+        });
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = metadata.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Some(Finding {
+                code: Code::SeedFileReadableByOthers,
+                detail: format!(
+                    "Seed file {} is accessible by other users (mode {:o})",
+                    seed_file.display(),
+                    mode & 0o777
+                ),
+            });
+        }
+    }
+
+    None
+}
+
+async fn check_database(database: &Path) -> Option<Finding> {
+    let metadata = tokio::fs::metadata(database).await.ok()?;
+
+    if metadata.permissions().readonly() {
+        return Some(Finding {
+            code: Code::DatabaseNotWritable,
+            detail: format!("Database {} is read-only", database.display()),
+        });
+    }
+
+    let result = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(database)
+        .await;
+
+    result.err().map(|e| Finding {
+        code: Code::DatabaseNotWritable,
+        detail: format!(
+            "Cannot open database {} for writing: {e:#}",
+            database.display()
+        ),
+    })
+}
+
+async fn check_listen_address(address: SocketAddr) -> Option<Finding> {
+    let result = TcpListener::bind(address).await;
+
+    result.err().map(|e| Finding {
+        code: Code::AddressInUse,
+        detail: format!("Cannot listen on {address}: {e:#}"),
+    })
+}
+
+async fn check_electrum(urls: &[String]) -> Option<Finding> {
+    let mut errors = Vec::new();
+
+    for url in urls {
+        match connect(electrum_address(url)).await {
+            Ok(()) => return None,
+            Err(e) => errors.push(format!("{url}: {e:#}")),
+        }
+    }
+
+    Some(Finding {
+        code: Code::ElectrumUnreachable,
+        detail: format!("No electrum server is reachable: {}", errors.join(", ")),
+    })
+}
+
+async fn check_peer(name: &str, address: &str, proxy: Option<SocketAddr>) -> Option<Finding> {
+    let result = match proxy {
+        Some(proxy) => connect_via_socks5(address, proxy).await,
+        None => connect(address).await,
+    };
+
+    result.err().map(|e| Finding {
+        code: Code::PeerUnreachable,
+        detail: format!("{name} at {address} is unreachable: {e:#}"),
+    })
+}
+
+/// Check that the oracle can be reached and use its clock to check
+/// ours.
+async fn check_oracle_and_clock(url: &str) -> Vec<Finding> {
+    let response = async {
+        let client = reqwest::Client::builder().timeout(CHECK_TIMEOUT).build()?;
+        let response = client.head(url).send().await?;
+
+        anyhow::Ok(response)
+    }
+    .await;
+
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            return vec![Finding {
+                code: Code::OracleUnreachable,
+                detail: format!("Oracle at {url} is unreachable: {e:#}"),
+            }]
+        }
+    };
+
+    let oracle_time = match response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .map(parse_http_date)
+    {
+        Some(Ok(oracle_time)) => oracle_time,
+        Some(Err(e)) => {
+            tracing::debug!("Unable to check clock against oracle: {e:#}");
+            return Vec::new();
+        }
+        None => return Vec::new(),
+    };
+
+    let skew = OffsetDateTime::now_utc() - oracle_time;
+
+    if skew.abs() > MAX_CLOCK_SKEW {
+        return vec![Finding {
+            code: Code::ClockSkew,
+            detail: format!(
+                "System clock is off by {} seconds compared to the oracle",
+                skew.whole_seconds()
+            ),
+        }];
+    }
+
+    Vec::new()
+}
+
+async fn connect(address: &str) -> Result<()> {
+    tokio_extras::time::timeout(
+        CHECK_TIMEOUT,
+        TcpStream::connect(address),
+        tokio_extras::time::already_instrumented,
+    )
+    .await
+    .with_context(|| format!("No response within {CHECK_TIMEOUT:?}"))??;
+
+    Ok(())
+}
+
+async fn connect_via_socks5(address: &str, proxy: SocketAddr) -> Result<()> {
+    tokio_extras::time::timeout(
+        CHECK_TIMEOUT,
+        Socks5Stream::connect(proxy, address),
+        tokio_extras::time::already_instrumented,
+    )
+    .await
+    .with_context(|| format!("No response within {CHECK_TIMEOUT:?}"))?
+    .with_context(|| format!("Failed to connect through SOCKS5 proxy {proxy}"))?;
+
+    Ok(())
+}
+
+/// Extract `host:port` from an electrum URL such as
+/// `ssl://blockstream.info:700`.
+fn electrum_address(url: &str) -> &str {
+    url.split_once("://")
+        .map(|(_, address)| address)
+        .unwrap_or(url)
+}
+
+fn parse_http_date(date: &str) -> Result<OffsetDateTime> {
+    let date = PrimitiveDateTime::parse(date, HTTP_DATE_FORMAT)
+        .with_context(|| format!("Invalid HTTP date: {date}"))?;
+
+    Ok(date.assume_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn electrum_address_strips_scheme() {
+        assert_eq!(
+            electrum_address("ssl://blockstream.info:700"),
+            "blockstream.info:700"
+        );
+        assert_eq!(electrum_address("localhost:50001"), "localhost:50001");
+    }
+
+    #[test]
+    fn parses_http_date() {
+        let date = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+
+        assert_eq!(date.unix_timestamp(), 784111777);
+    }
+
+    #[test]
+    fn unreachable_remote_services_do_not_fail_startup() {
+        let report = Report {
+            findings: vec![
+                Finding {
+                    code: Code::ElectrumUnreachable,
+                    detail: "electrum".to_owned(),
+                },
+                Finding {
+                    code: Code::OracleUnreachable,
+                    detail: "oracle".to_owned(),
+                },
+                Finding {
+                    code: Code::PeerUnreachable,
+                    detail: "peer".to_owned(),
+                },
+            ],
+        };
+
+        report.ensure_ok(false).unwrap();
+    }
+}
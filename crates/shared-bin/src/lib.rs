@@ -1,9 +1,18 @@
+#[cfg(feature = "api")]
 pub mod catchers;
 pub mod cli;
+#[cfg(feature = "api")]
+pub mod dashboard;
+pub mod diagnostics;
+#[cfg(feature = "api")]
 pub mod fairings;
 pub mod logger;
+#[cfg(feature = "api")]
+pub mod sse;
+#[cfg(feature = "api")]
 mod to_sse_event;
 
+#[cfg(feature = "api")]
 pub use crate::to_sse_event::*;
 
 pub const MAINNET_ELECTRUM: &str = "ssl://blockstream.info:700";
@@ -6,13 +6,22 @@ use opentelemetry::sdk::trace;
 use opentelemetry::sdk::Resource;
 use opentelemetry::KeyValue;
 use opentelemetry_otlp::WithExportConfig;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::str::FromStr;
 use time::macros::format_description;
+#[cfg(unix)]
+use tokio::signal::unix::signal;
+#[cfg(unix)]
+use tokio::signal::unix::SignalKind;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::filter::Directive;
 use tracing_subscriber::fmt;
 use tracing_subscriber::fmt::time::UtcTime;
+use tracing_subscriber::reload;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::Layer;
+use tracing_subscriber::Registry;
 
 pub use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::layer::SubscriberExt;
@@ -23,11 +32,36 @@ pub const LOCAL_COLLECTOR_ENDPOINT: &str = "http://localhost:4317";
 
 const RUST_LOG_ENV: &str = "RUST_LOG";
 
+/// How log events are written to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable, one line per event.
+    Text,
+    /// One JSON object per event, including the fields of the spans it
+    /// was emitted in, e.g. the `order_id`, `offer_id` and `peer_id` of
+    /// the protocol it belongs to.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(anyhow!(
+                "Unknown log format '{other}', expected 'text' or 'json'"
+            )),
+        }
+    }
+}
+
 // because the logger is only initialized at the end of this function but we want to print a warning
 #[allow(clippy::print_stdout, clippy::too_many_arguments)]
 pub fn init(
     level: LevelFilter,
-    json_format: bool,
+    format: LogFormat,
     json_span_list: bool,
     instrumentation: bool,
     use_tokio_console: bool,
@@ -36,19 +70,29 @@ pub fn init(
     collector_endpoint: &str,
     log_to_file: bool,
     data_dir: &str,
-) -> Result<Option<WorkerGuard>> {
+) -> Result<Logger> {
     if level == LevelFilter::OFF {
-        return Ok(None);
+        return Ok(Logger {
+            guard: None,
+            filter: None,
+        });
     }
 
     let is_terminal = atty::is(atty::Stream::Stderr);
 
+    // Only the valid directives are kept, so that we can go back to them
+    // after the filter was reloaded
+    let mut startup_directives = Vec::new();
+
     let filter = match std::env::var_os(RUST_LOG_ENV).map(|s| s.into_string()) {
         Some(Ok(env)) => {
             let mut filter = log_base_directives(EnvFilter::new(""))?;
             for directive in env.split(',') {
                 match directive.parse() {
-                    Ok(d) => filter = filter.add_directive(d),
+                    Ok(d) => {
+                        filter = filter.add_directive(d);
+                        startup_directives.push(directive.to_owned());
+                    }
                     Err(e) => println!("WARN ignoring log directive: `{directive}`: {e}"),
                 };
             }
@@ -57,32 +101,25 @@ pub fn init(
         _ => log_base_directives(EnvFilter::from_env(RUST_LOG_ENV))?,
     };
 
-    let filter = filter.add_directive(format!("{level}").parse()?);
-
-    let filter = if use_tokio_console {
-        filter
-            .add_directive("tokio=trace".parse()?)
-            .add_directive("runtime=trace".parse()?)
-    } else {
-        filter
-    };
+    let filter = add_level_directives(filter, level, use_tokio_console)?;
+    let (filter, filter_handle) = reload::Layer::new(filter);
 
     let fmt_layer = tracing_subscriber::fmt::layer()
         .with_writer(std::io::stderr)
         .with_ansi(is_terminal);
 
-    let fmt_layer = if json_format {
-        fmt_layer
+    let fmt_layer = match format {
+        LogFormat::Json => fmt_layer
             .json()
+            .with_current_span(true)
             .with_span_list(json_span_list)
             .with_timer(UtcTime::rfc_3339())
-            .boxed()
-    } else {
-        fmt_layer
+            .boxed(),
+        LogFormat::Text => fmt_layer
             .with_timer(UtcTime::new(format_description!(
                 "[year]-[month]-[day] [hour]:[minute]:[second]"
             )))
-            .boxed()
+            .boxed(),
     };
 
     let telemetry = if instrumentation {
@@ -132,10 +169,12 @@ pub fn init(
         (None, None)
     };
 
+    // The filter comes first so that the type of the reload handle does
+    // not depend on the other layers
     tracing_subscriber::registry()
+        .with(filter)
         .with(console_layer)
         .with(quiet_spans::disable_noisy_spans(verbose_spans))
-        .with(filter)
         .with(telemetry)
         .with(fmt_layer)
         .with(file_log)
@@ -144,7 +183,127 @@ pub fn init(
 
     tracing::info!("Initialized logger");
 
-    Ok(guard)
+    Ok(Logger {
+        guard,
+        filter: Some(LogFilter {
+            handle: filter_handle,
+            level,
+            use_tokio_console,
+            startup_directives: startup_directives.join(","),
+        }),
+    })
+}
+
+/// Keeps the logger running.
+pub struct Logger {
+    /// Flushes the log file once dropped
+    pub guard: Option<WorkerGuard>,
+    /// `None` if logging is turned off
+    pub filter: Option<LogFilter>,
+}
+
+/// The filter of the logger, which can be adjusted at runtime.
+///
+/// This allows to capture detailed logs of a misbehaving subsystem, e.g.
+/// with `xtra_libp2p=trace`, without restarting and thereby losing the
+/// state it is in.
+#[derive(Clone)]
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    level: LevelFilter,
+    use_tokio_console: bool,
+    /// The directives from `RUST_LOG` the logger was started with
+    startup_directives: String,
+}
+
+impl LogFilter {
+    /// Replace the directives the logger was started with by
+    /// `directives`, in the format of `RUST_LOG`.
+    ///
+    /// The directives are applied on top of the default directives and
+    /// the log level. Invalid directives are rejected and leave the
+    /// filter unchanged.
+    pub fn reload(&self, directives: &str) -> Result<()> {
+        let mut filter = add_level_directives(
+            log_base_directives(EnvFilter::new(""))?,
+            self.level,
+            self.use_tokio_console,
+        )?;
+
+        for directive in directives
+            .split(|c| c == ',' || c == '\n')
+            .map(str::trim)
+            .filter(|directive| !directive.is_empty())
+        {
+            let directive = directive
+                .parse()
+                .with_context(|| format!("Invalid log directive `{directive}`"))?;
+            filter = filter.add_directive(directive);
+        }
+
+        self.handle
+            .reload(filter)
+            .context("Failed to reload log filter")?;
+
+        tracing::info!(%directives, "Reloaded log filter");
+
+        Ok(())
+    }
+
+    /// Go back to the directives the logger was started with.
+    pub fn reset(&self) -> Result<()> {
+        self.reload(&self.startup_directives)
+    }
+
+    /// The directives which are currently in effect.
+    pub fn current(&self) -> Result<String> {
+        self.handle
+            .with_current(|filter| filter.to_string())
+            .context("Failed to read log filter")
+    }
+}
+
+/// Reload the log filter from the file at `path` whenever the process
+/// receives `SIGHUP`, forever.
+///
+/// The file contains directives in the format of `RUST_LOG`, separated
+/// by commas or newlines. If there is no such file, the filter is reset
+/// to the directives the logger was started with.
+#[cfg(unix)]
+pub async fn reload_on_sighup(filter: LogFilter, path: PathBuf) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup()).context("Failed to listen for SIGHUP")?;
+
+    while hangup.recv().await.is_some() {
+        let result = match tokio::fs::read_to_string(&path).await {
+            Ok(directives) => filter.reload(&directives),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => filter.reset(),
+            Err(e) => Err(anyhow!(e).context(format!("Failed to read {}", path.display()))),
+        };
+
+        if let Err(e) = result {
+            tracing::error!("Failed to reload log filter on SIGHUP: {e:#}");
+        }
+    }
+
+    Ok(())
+}
+
+fn add_level_directives(
+    filter: EnvFilter,
+    level: LevelFilter,
+    use_tokio_console: bool,
+) -> Result<EnvFilter> {
+    let filter = filter.add_directive(format!("{level}").parse()?);
+
+    let filter = if use_tokio_console {
+        filter
+            .add_directive("tokio=trace".parse()?)
+            .add_directive("runtime=trace".parse()?)
+    } else {
+        filter
+    };
+
+    Ok(filter)
 }
 
 fn log_base_directives(env: EnvFilter) -> Result<EnvFilter> {
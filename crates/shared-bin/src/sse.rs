@@ -0,0 +1,339 @@
+//! Fan-out of the feeds to SSE clients.
+//!
+//! Every client gets its own queue, filled by a producer task which
+//! watches the feeds and drained by the client's event stream. Queuing
+//! never waits for the client, so a client that reads slowly only ever
+//! delays itself.
+//!
+//! Events are queued with one of two [`Delivery`] guarantees: frequent
+//! updates such as quotes are superseded by the next update anyway and
+//! are dropped once too many of them are pending, whereas updates of
+//! CFDs must reach the client. A client that falls too far behind on
+//! the latter is disconnected instead; its `EventSource` reconnects and
+//! starts over with a fresh snapshot.
+
+use conquer_once::Lazy;
+use daemon::projection::MakerOffers;
+use rocket::response::stream::Event;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// How many events of topics with [`Delivery::DropOldest`] may be
+/// pending per client.
+const DROP_OLDEST_CAPACITY: usize = 16;
+
+/// How many events of topics with [`Delivery::Guaranteed`] may be
+/// pending per client before it is disconnected.
+const GUARANTEED_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Drop the oldest pending event of such topics once the client has
+    /// too many of them pending.
+    DropOldest,
+    /// Never drop events of such topics.
+    Guaranteed,
+}
+
+/// The client went away or was disconnected for falling behind.
+#[derive(Debug, Clone, Copy)]
+pub struct Disconnected;
+
+/// Create the queue of a new client of `feed`, e.g. `"taker"`.
+pub fn client_queue(feed: &'static str) -> (QueueSender, QueueReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue::default()),
+        notify: Notify::new(),
+    });
+
+    let sender = QueueSender {
+        feed,
+        shared: shared.clone(),
+    };
+    let receiver = QueueReceiver { shared };
+
+    (sender, receiver)
+}
+
+struct Shared {
+    queue: Mutex<Queue<Event>>,
+    notify: Notify,
+}
+
+pub struct QueueSender {
+    feed: &'static str,
+    shared: Arc<Shared>,
+}
+
+impl QueueSender {
+    /// Queue `event` of `topic` for the client.
+    pub fn send(
+        &self,
+        topic: &'static str,
+        delivery: Delivery,
+        event: Event,
+    ) -> Result<(), Disconnected> {
+        let pushed = self
+            .shared
+            .queue
+            .lock()
+            .expect("lock not to be poisoned")
+            .push(topic, delivery, event);
+
+        let result = match pushed {
+            Pushed::Queued => Ok(()),
+            Pushed::DroppedOldest(dropped_topic) => {
+                EVENTS_DROPPED_COUNTER
+                    .with_label_values(&[self.feed, dropped_topic])
+                    .inc();
+                Ok(())
+            }
+            Pushed::Overflowed => {
+                CLIENTS_DISCONNECTED_COUNTER
+                    .with_label_values(&[self.feed])
+                    .inc();
+                tracing::warn!(
+                    feed = self.feed,
+                    %topic,
+                    "Disconnecting SSE client which fell {GUARANTEED_CAPACITY} events behind"
+                );
+                Err(Disconnected)
+            }
+            Pushed::Closed => Err(Disconnected),
+        };
+
+        self.shared.notify.notify_one();
+
+        result
+    }
+}
+
+impl Drop for QueueSender {
+    fn drop(&mut self) {
+        self.shared
+            .queue
+            .lock()
+            .expect("lock not to be poisoned")
+            .close();
+        self.shared.notify.notify_one();
+    }
+}
+
+/// Queue the offers of the maker, one event per symbol and position.
+pub fn send_offers(events: &QueueSender, offers: &MakerOffers) -> Result<(), Disconnected> {
+    events.send(
+        "btcusd_long_offer",
+        Delivery::Guaranteed,
+        Event::json(&offers.btcusd_long).event("btcusd_long_offer"),
+    )?;
+    events.send(
+        "btcusd_short_offer",
+        Delivery::Guaranteed,
+        Event::json(&offers.btcusd_short).event("btcusd_short_offer"),
+    )?;
+    events.send(
+        "ethusd_long_offer",
+        Delivery::Guaranteed,
+        Event::json(&offers.ethusd_long).event("ethusd_long_offer"),
+    )?;
+    events.send(
+        "ethusd_short_offer",
+        Delivery::Guaranteed,
+        Event::json(&offers.ethusd_short).event("ethusd_short_offer"),
+    )
+}
+
+pub struct QueueReceiver {
+    shared: Arc<Shared>,
+}
+
+impl QueueReceiver {
+    /// The next event to send to the client.
+    ///
+    /// Returns `None` once the producer is gone and all events were
+    /// received, or right away if the client was disconnected.
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            let popped = self
+                .shared
+                .queue
+                .lock()
+                .expect("lock not to be poisoned")
+                .pop();
+
+            match popped {
+                Popped::Event(event) => return Some(event),
+                Popped::Closed => return None,
+                Popped::Empty => {}
+            }
+
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for QueueReceiver {
+    fn drop(&mut self) {
+        self.shared
+            .queue
+            .lock()
+            .expect("lock not to be poisoned")
+            .close();
+    }
+}
+
+/// The pending events of one client, generic over the event so that it
+/// can be tested without rendering SSE events.
+struct Queue<T> {
+    guaranteed: VecDeque<T>,
+    drop_oldest: VecDeque<(&'static str, T)>,
+    closed: bool,
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self {
+            guaranteed: VecDeque::new(),
+            drop_oldest: VecDeque::new(),
+            closed: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Pushed {
+    Queued,
+    /// The oldest pending event, of the given topic, was dropped.
+    DroppedOldest(&'static str),
+    /// Too many guaranteed events are pending; the queue was closed.
+    Overflowed,
+    Closed,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Popped<T> {
+    Event(T),
+    Empty,
+    Closed,
+}
+
+impl<T> Queue<T> {
+    fn push(&mut self, topic: &'static str, delivery: Delivery, event: T) -> Pushed {
+        if self.closed {
+            return Pushed::Closed;
+        }
+
+        match delivery {
+            Delivery::DropOldest => {
+                let dropped = if self.drop_oldest.len() >= DROP_OLDEST_CAPACITY {
+                    self.drop_oldest.pop_front().map(|(topic, _)| topic)
+                } else {
+                    None
+                };
+                self.drop_oldest.push_back((topic, event));
+
+                dropped.map_or(Pushed::Queued, Pushed::DroppedOldest)
+            }
+            Delivery::Guaranteed if self.guaranteed.len() >= GUARANTEED_CAPACITY => {
+                // Drop what is pending, the client starts over once it reconnects
+                self.close();
+                self.guaranteed.clear();
+                self.drop_oldest.clear();
+
+                Pushed::Overflowed
+            }
+            Delivery::Guaranteed => {
+                self.guaranteed.push_back(event);
+
+                Pushed::Queued
+            }
+        }
+    }
+
+    /// Guaranteed events are delivered first because they are what the
+    /// client must not miss.
+    fn pop(&mut self) -> Popped<T> {
+        if let Some(event) = self.guaranteed.pop_front() {
+            return Popped::Event(event);
+        }
+        if let Some((_, event)) = self.drop_oldest.pop_front() {
+            return Popped::Event(event);
+        }
+        if self.closed {
+            return Popped::Closed;
+        }
+
+        Popped::Empty
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+    }
+}
+
+const FEED_LABEL: &str = "feed";
+const TOPIC_LABEL: &str = "topic";
+
+static EVENTS_DROPPED_COUNTER: Lazy<prometheus::IntCounterVec> = Lazy::new(|| {
+    prometheus::register_int_counter_vec!(
+        "sse_events_dropped_total",
+        "The total number of SSE events which were dropped because a client had too many events of the topic pending, segregated by feed and topic.",
+        &[FEED_LABEL, TOPIC_LABEL]
+    )
+    .unwrap()
+});
+
+static CLIENTS_DISCONNECTED_COUNTER: Lazy<prometheus::IntCounterVec> = Lazy::new(|| {
+    prometheus::register_int_counter_vec!(
+        "sse_clients_disconnected_total",
+        "The total number of SSE clients which were disconnected because they fell behind on events which must not be dropped, segregated by feed.",
+        &[FEED_LABEL]
+    )
+    .unwrap()
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_event_is_dropped_once_full() {
+        let mut queue = Queue::default();
+        for i in 0..DROP_OLDEST_CAPACITY {
+            assert_eq!(queue.push("quote", Delivery::DropOldest, i), Pushed::Queued);
+        }
+
+        let pushed = queue.push("quote", Delivery::DropOldest, DROP_OLDEST_CAPACITY);
+
+        assert_eq!(pushed, Pushed::DroppedOldest("quote"));
+        assert_eq!(queue.pop(), Popped::Event(1));
+    }
+
+    #[test]
+    fn guaranteed_events_are_delivered_first() {
+        let mut queue = Queue::default();
+        queue.push("quote", Delivery::DropOldest, 1);
+        queue.push("cfds", Delivery::Guaranteed, 2);
+        queue.close();
+
+        assert_eq!(queue.pop(), Popped::Event(2));
+        assert_eq!(queue.pop(), Popped::Event(1));
+        assert_eq!(queue.pop(), Popped::Closed);
+    }
+
+    #[test]
+    fn client_falling_behind_on_guaranteed_events_is_disconnected() {
+        let mut queue = Queue::default();
+        for i in 0..GUARANTEED_CAPACITY {
+            assert_eq!(queue.push("cfds", Delivery::Guaranteed, i), Pushed::Queued);
+        }
+
+        let pushed = queue.push("cfds", Delivery::Guaranteed, GUARANTEED_CAPACITY);
+
+        assert_eq!(pushed, Pushed::Overflowed);
+        assert_eq!(queue.pop(), Popped::Closed);
+        assert_eq!(queue.push("quote", Delivery::DropOldest, 0), Pushed::Closed);
+    }
+}
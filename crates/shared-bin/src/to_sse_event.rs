@@ -2,15 +2,20 @@ use daemon::bdk::bitcoin::Amount;
 use daemon::bdk::bitcoin::Network;
 use daemon::bdk::bitcoin::Txid;
 use daemon::bdk::BlockTime;
+use daemon::block_explorer::BlockExplorer;
+use daemon::fiat_rates::FiatAmount;
+use daemon::fiat_rates::FiatRate;
 use daemon::identify;
-use daemon::listen_protocols::does_maker_satisfy_taker_needs;
-use daemon::listen_protocols::REQUIRED_MAKER_LISTEN_PROTOCOLS;
+use daemon::listen_protocols::Feature;
 use daemon::online_status;
+use daemon::projection;
 use daemon::projection::Cfd;
+use daemon::projection::LatestQuotes;
 use model::Timestamp;
 use rocket::response::stream::Event;
 use serde::Serialize;
 use std::collections::HashSet;
+use tokio::sync::watch;
 
 pub trait ToSseEvent {
     fn to_sse_event(&self) -> Event;
@@ -26,6 +31,8 @@ impl ToSseEvent for Vec<Cfd> {
 pub struct WalletInfo {
     #[serde(with = "daemon::bdk::bitcoin::util::amount::serde::as_btc")]
     balance: Amount,
+    /// The balance valued in the fiat currency selected by the user
+    balance_fiat: Option<FiatAmount>,
     address: String,
     last_updated_at: Timestamp,
     transactions: Vec<TransactionDetails>,
@@ -45,12 +52,7 @@ pub struct TransactionDetails {
 impl From<(Network, &daemon::bdk::TransactionDetails)> for TransactionDetails {
     fn from((network, tx): (Network, &daemon::bdk::TransactionDetails)) -> Self {
         let txid = tx.txid;
-        let link = match network {
-            Network::Bitcoin => Some(format!("https://mempool.space/tx/{txid}")),
-            Network::Testnet => Some(format!("https://mempool.space/testnet/tx/{txid}")),
-            Network::Signet => Some(format!("https://mempool.space/signet/tx/{txid}")),
-            Network::Regtest => None,
-        };
+        let link = BlockExplorer::mempool_space(network).tx_link(txid);
         Self {
             txid,
             received: Amount::from_sat(tx.received),
@@ -61,30 +63,69 @@ impl From<(Network, &daemon::bdk::TransactionDetails)> for TransactionDetails {
     }
 }
 
-impl ToSseEvent for Option<model::WalletInfo> {
-    fn to_sse_event(&self) -> Event {
-        let wallet_info = self.as_ref().map(|wallet_info| {
-            let transaction_details = wallet_info
-                .transactions
-                .iter()
-                .map(|tx| (wallet_info.network, tx).into())
-                .collect();
-
-            WalletInfo {
-                balance: wallet_info.balance,
-                address: wallet_info.address.to_string(),
-                last_updated_at: wallet_info.last_updated_at,
-                transactions: transaction_details,
+/// The `wallet` event, with the balance valued in fiat while we know the BTCUSD price and an
+/// exchange rate.
+pub fn wallet_event(
+    wallet_info: &watch::Receiver<Option<model::WalletInfo>>,
+    quotes: &watch::Receiver<LatestQuotes>,
+    fiat_rate: &watch::Receiver<Option<FiatRate>>,
+) -> Event {
+    let btc_usd_price = projection::btc_usd_price(&quotes.borrow());
+    let fiat_rate = *fiat_rate.borrow();
+
+    let wallet_info = wallet_info.borrow().as_ref().map(|wallet_info| {
+        let transaction_details = wallet_info
+            .transactions
+            .iter()
+            .map(|tx| (wallet_info.network, tx).into())
+            .collect();
+
+        let balance_fiat = match (fiat_rate, btc_usd_price, wallet_info.balance.to_signed()) {
+            (Some(fiat_rate), Some(btc_usd_price), Ok(balance)) => {
+                Some(fiat_rate.value(balance, btc_usd_price))
             }
-        });
+            _ => None,
+        };
 
-        Event::json(&wallet_info).event("wallet")
-    }
+        WalletInfo {
+            balance: wallet_info.balance,
+            balance_fiat,
+            address: wallet_info.address.to_string(),
+            last_updated_at: wallet_info.last_updated_at,
+            transactions: transaction_details,
+        }
+    });
+
+    Event::json(&wallet_info).event("wallet")
 }
 
 #[derive(Debug, Clone, Copy, Serialize)]
 pub struct ConnectionStatus {
     online: bool,
+    /// Whether the maker responds slowly to pings, e.g. because of a poor
+    /// connection
+    degraded: bool,
+    latency: Option<Latency>,
+}
+
+/// Round trip times to the maker in milliseconds.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Latency {
+    last_ms: u128,
+    p50_ms: u128,
+    p95_ms: u128,
+    samples: usize,
+}
+
+impl From<online_status::Latency> for Latency {
+    fn from(latency: online_status::Latency) -> Self {
+        Self {
+            last_ms: latency.last.as_millis(),
+            p50_ms: latency.p50.as_millis(),
+            p95_ms: latency.p95.as_millis(),
+            samples: latency.samples,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize)]
@@ -93,37 +134,52 @@ pub enum ConnectionCloseReason {
     TakerVersionOutdated,
 }
 
-impl ToSseEvent for online_status::ConnectionStatus {
-    fn to_sse_event(&self) -> Event {
-        let connected = match self {
-            online_status::ConnectionStatus::Online => ConnectionStatus { online: true },
-            online_status::ConnectionStatus::Offline => ConnectionStatus { online: false },
-        };
-
-        Event::json(&connected).event("maker_status")
-    }
+/// The `maker_status` event, with the latency to the maker while we are connected.
+pub fn maker_status_event(
+    status: &watch::Receiver<online_status::ConnectionStatus>,
+    latency: &watch::Receiver<Option<online_status::Latency>>,
+) -> Event {
+    let (online, degraded) = match *status.borrow() {
+        online_status::ConnectionStatus::Online => (true, false),
+        online_status::ConnectionStatus::Degraded => (true, true),
+        online_status::ConnectionStatus::Offline => (false, false),
+    };
+
+    let connection_status = ConnectionStatus {
+        online,
+        degraded,
+        latency: latency.borrow().map(Latency::from),
+    };
+
+    Event::json(&connection_status).event("maker_status")
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct MakerCompatibility {
     /// Protocols that the maker version does not support, but the taker version requires
     unsupported_protocols: Option<HashSet<String>>,
+    /// Features that are disabled because the maker version does not support them
+    disabled_features: Option<HashSet<Feature>>,
+    /// Whether the maker only supports newer versions of some protocols than the taker
+    maker_requires_newer_taker: Option<bool>,
 }
 
 impl MakerCompatibility {
     pub fn new(peer_info: &Option<identify::PeerInfo>) -> Self {
-        let unsupported_protocols = peer_info.as_ref().map(|peer_info| {
-            match does_maker_satisfy_taker_needs(
-                &peer_info.protocols,
-                REQUIRED_MAKER_LISTEN_PROTOCOLS,
-            ) {
-                Ok(_) => HashSet::new(),
-                Err(missing_protocols) => missing_protocols,
-            }
-        });
+        let compatibility = peer_info
+            .as_ref()
+            .map(|peer_info| peer_info.maker_compatibility());
 
         Self {
-            unsupported_protocols,
+            unsupported_protocols: compatibility
+                .as_ref()
+                .map(|compatibility| compatibility.missing_protocols.clone()),
+            disabled_features: compatibility
+                .as_ref()
+                .map(|compatibility| compatibility.disabled_features.clone()),
+            maker_requires_newer_taker: compatibility
+                .as_ref()
+                .map(|compatibility| compatibility.taker_outdated),
         }
     }
 }
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS funding_payments (
+    id integer PRIMARY KEY autoincrement,
+    order_id text NOT NULL,
+    timestamp integer NOT NULL,
+    funding_rate text NOT NULL,
+    fee_sat integer NOT NULL,
+    UNIQUE (order_id, timestamp)
+);
+CREATE INDEX IF NOT EXISTS funding_payments_order_id ON funding_payments (order_id);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS rollover_policies (
+    order_id text PRIMARY KEY NOT NULL,
+    policy text NOT NULL
+);
@@ -0,0 +1,3 @@
+CREATE TABLE IF NOT EXISTS blocked_peers (
+    peer_id text PRIMARY KEY NOT NULL
+);
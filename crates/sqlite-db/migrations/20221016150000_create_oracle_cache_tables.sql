@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS oracle_announcements (
+    event_id text PRIMARY KEY NOT NULL,
+    expected_outcome_time integer NOT NULL,
+    nonce_pks text NOT NULL,
+    expires_at integer NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS oracle_attestations (
+    event_id text PRIMARY KEY NOT NULL,
+    price integer NOT NULL,
+    scalars text NOT NULL,
+    expires_at integer NOT NULL
+);
@@ -0,0 +1,4 @@
+ALTER TABLE
+    failed_cfds
+ADD
+    COLUMN reject_reason text;
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS scheduled_settlements (
+    order_id text PRIMARY KEY NOT NULL,
+    settle_at integer NOT NULL
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS cfd_snapshots (
+    order_id text NOT NULL,
+    aggregate text NOT NULL,
+    data text NOT NULL,
+    PRIMARY KEY (order_id, aggregate)
+);
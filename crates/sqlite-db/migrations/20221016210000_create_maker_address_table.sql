@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS maker_address (
+    id integer PRIMARY KEY CHECK (id = 0),
+    multiaddr text NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS broadcasts (
+    txid text PRIMARY KEY NOT NULL,
+    order_id text NOT NULL,
+    kind text NOT NULL,
+    initiator text NOT NULL,
+    timestamp integer NOT NULL
+);
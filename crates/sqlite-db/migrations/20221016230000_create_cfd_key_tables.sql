@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS cfd_key_derivations (
+    order_id text NOT NULL,
+    kind text NOT NULL,
+    path text NOT NULL,
+    PRIMARY KEY (order_id, kind)
+);
+
+CREATE TABLE IF NOT EXISTS cfd_key_indices (
+    key_index integer PRIMARY KEY autoincrement,
+    order_id text NOT NULL UNIQUE
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS maker_identity (
+    id integer PRIMARY KEY CHECK (id = 0),
+    peer_id text NOT NULL,
+    identity text NOT NULL
+);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS revoked_commit_descriptors (
+    txid text PRIMARY KEY,
+    descriptor text NOT NULL
+);
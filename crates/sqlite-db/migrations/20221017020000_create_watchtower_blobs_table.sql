@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS watchtower_blobs (
+    hint text PRIMARY KEY,
+    peer_id text NOT NULL,
+    ciphertext text NOT NULL,
+    revoked_commit_txid text,
+    revoked_commit_seen_at integer
+);
@@ -0,0 +1,5 @@
+-- Null for CFDs inserted before the refund timelock was configurable
+ALTER TABLE
+    cfds
+ADD
+    COLUMN refund_timelock INTEGER;
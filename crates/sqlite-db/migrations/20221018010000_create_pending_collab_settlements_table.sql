@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS pending_collab_settlements (
+    order_id text PRIMARY KEY NOT NULL,
+    settlement text NOT NULL
+);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS offer_tiers (
+    peer_id text PRIMARY KEY NOT NULL,
+    tier text NOT NULL
+);
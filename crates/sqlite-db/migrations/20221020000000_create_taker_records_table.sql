@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS taker_records (
+    peer_id text PRIMARY KEY NOT NULL,
+    completed_cfds integer NOT NULL DEFAULT 0,
+    aborted_setups integer NOT NULL DEFAULT 0,
+    failed_rollovers integer NOT NULL DEFAULT 0
+);
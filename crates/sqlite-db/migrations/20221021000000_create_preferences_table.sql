@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS preferences (
+    key text PRIMARY KEY NOT NULL,
+    value text NOT NULL
+);
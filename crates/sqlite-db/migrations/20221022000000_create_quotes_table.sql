@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS quotes (
+    id integer PRIMARY KEY autoincrement,
+    contract_symbol text NOT NULL,
+    timestamp integer NOT NULL,
+    bid text NOT NULL,
+    ask text NOT NULL
+);
+CREATE INDEX IF NOT EXISTS quotes_contract_symbol_timestamp ON quotes (contract_symbol, timestamp);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS annotations (
+    id integer PRIMARY KEY autoincrement,
+    text text NOT NULL,
+    starts_at integer NOT NULL,
+    ends_at integer NOT NULL,
+    created_at integer NOT NULL
+);
+CREATE INDEX IF NOT EXISTS annotations_starts_at_ends_at ON annotations (starts_at, ends_at);
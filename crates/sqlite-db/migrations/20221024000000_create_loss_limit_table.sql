@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS loss_limit (
+    id integer PRIMARY KEY CHECK (id = 0),
+    max_loss integer,
+    window_secs integer,
+    override_until integer
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS restricted_protocols (
+    protocol text PRIMARY KEY NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS protocol_allowlist (
+    protocol text NOT NULL,
+    peer_id text NOT NULL,
+    PRIMARY KEY (protocol, peer_id),
+    FOREIGN KEY (protocol) REFERENCES restricted_protocols (protocol)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS price_triggers (
+    order_id text NOT NULL,
+    trigger text NOT NULL,
+    trigger_price text NOT NULL,
+    triggered_at integer,
+    PRIMARY KEY (order_id, trigger)
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS watchtower_uploads (
+    tower_peer_id text NOT NULL,
+    revoked_commit_txid text NOT NULL,
+    PRIMARY KEY (tower_peer_id, revoked_commit_txid)
+);
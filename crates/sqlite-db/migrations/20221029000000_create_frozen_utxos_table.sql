@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS frozen_utxos (
+    txid text NOT NULL,
+    vout integer NOT NULL,
+    PRIMARY KEY (txid, vout)
+);
@@ -0,0 +1,215 @@
+//! Notes of the operator about what happened during a period of time,
+//! e.g. an outage of the Electrum server, to make sense of the
+//! histories of the CFDs which were affected.
+
+use crate::Connection;
+use anyhow::ensure;
+use anyhow::Result;
+use model::Timestamp;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Annotation {
+    pub id: i64,
+    pub text: String,
+    pub starts_at: Timestamp,
+    /// Equal to `starts_at` for annotations of a point in time
+    pub ends_at: Timestamp,
+    pub created_at: Timestamp,
+}
+
+impl Connection {
+    /// Annotate the period from `starts_at` to `ends_at` with `text`.
+    pub async fn insert_annotation(
+        &self,
+        text: String,
+        starts_at: Timestamp,
+        ends_at: Timestamp,
+    ) -> Result<Annotation> {
+        ensure!(
+            starts_at <= ends_at,
+            "Annotated period must not end before it starts"
+        );
+
+        let mut conn = self.inner.acquire().await?;
+
+        let created_at = Timestamp::now();
+        let starts_at_seconds = starts_at.seconds();
+        let ends_at_seconds = ends_at.seconds();
+        let created_at_seconds = created_at.seconds();
+        let id = sqlx::query!(
+            r#"
+            INSERT INTO annotations
+            (
+                text,
+                starts_at,
+                ends_at,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4)
+            "#,
+            text,
+            starts_at_seconds,
+            ends_at_seconds,
+            created_at_seconds
+        )
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid();
+
+        Ok(Annotation {
+            id,
+            text,
+            starts_at,
+            ends_at,
+            created_at,
+        })
+    }
+
+    /// Delete the annotation with `id`.
+    ///
+    /// Returns whether there was such an annotation.
+    pub async fn delete_annotation(&self, id: i64) -> Result<bool> {
+        let mut conn = self.inner.acquire().await?;
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM
+                annotations
+            WHERE
+                id = $1
+            "#,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Load all annotations, ordered by the start of the annotated
+    /// period.
+    pub async fn load_annotations(&self) -> Result<Vec<Annotation>> {
+        self.load_annotations_between(Timestamp::new(i64::MIN), Timestamp::new(i64::MAX))
+            .await
+    }
+
+    /// Load the annotations of periods which overlap with the period
+    /// from `since` to `until`, ordered by the start of the annotated
+    /// period.
+    pub async fn load_annotations_between(
+        &self,
+        since: Timestamp,
+        until: Timestamp,
+    ) -> Result<Vec<Annotation>> {
+        let mut conn = self.inner.acquire().await?;
+        let since = since.seconds();
+        let until = until.seconds();
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                id as "id!",
+                text,
+                starts_at,
+                ends_at,
+                created_at
+            FROM
+                annotations
+            WHERE
+                starts_at <= $2 AND ends_at >= $1
+            ORDER BY
+                starts_at, id
+            "#,
+            since,
+            until
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let annotations = rows
+            .into_iter()
+            .map(|row| Annotation {
+                id: row.id,
+                text: row.text,
+                starts_at: Timestamp::new(row.starts_at),
+                ends_at: Timestamp::new(row.ends_at),
+                created_at: Timestamp::new(row.created_at),
+            })
+            .collect();
+
+        Ok(annotations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn annotations_overlapping_period_are_loaded() {
+        let db = memory().await.unwrap();
+
+        let before = db
+            .insert_annotation("before".to_string(), Timestamp::new(0), Timestamp::new(99))
+            .await
+            .unwrap();
+        let overlapping = db
+            .insert_annotation(
+                "electrum outage".to_string(),
+                Timestamp::new(50),
+                Timestamp::new(150),
+            )
+            .await
+            .unwrap();
+        let within = db
+            .insert_annotation(
+                "restart".to_string(),
+                Timestamp::new(120),
+                Timestamp::new(120),
+            )
+            .await
+            .unwrap();
+
+        let annotations = db
+            .load_annotations_between(Timestamp::new(100), Timestamp::new(200))
+            .await
+            .unwrap();
+
+        assert_eq!(annotations, vec![overlapping.clone(), within.clone()]);
+        assert_eq!(
+            db.load_annotations().await.unwrap(),
+            vec![before, overlapping, within]
+        );
+    }
+
+    #[tokio::test]
+    async fn deleted_annotation_is_gone() {
+        let db = memory().await.unwrap();
+
+        let annotation = db
+            .insert_annotation("typo".to_string(), Timestamp::new(0), Timestamp::new(0))
+            .await
+            .unwrap();
+
+        assert!(db.delete_annotation(annotation.id).await.unwrap());
+        assert!(!db.delete_annotation(annotation.id).await.unwrap());
+        assert_eq!(db.load_annotations().await.unwrap(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn period_must_not_end_before_it_starts() {
+        let db = memory().await.unwrap();
+
+        let result = db
+            .insert_annotation(
+                "backwards".to_string(),
+                Timestamp::new(1),
+                Timestamp::new(0),
+            )
+            .await;
+
+        assert!(result.is_err());
+    }
+}
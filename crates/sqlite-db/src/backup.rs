@@ -0,0 +1,88 @@
+use crate::Connection;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::ConnectOptions;
+use sqlx::Connection as _;
+use std::path::Path;
+
+impl Connection {
+    /// Write a consistent snapshot of the database to `path` while it is
+    /// in use.
+    ///
+    /// The snapshot is taken with `VACUUM INTO`, which copies the
+    /// database within a single read transaction, like the online backup
+    /// API of sqlite does. Fails if there already is a file at `path`.
+    pub async fn backup_to(&self, path: &Path) -> Result<()> {
+        ensure!(!path.exists(), "Refusing to overwrite {}", path.display());
+
+        let path = path.to_str().context("Backup path is not valid UTF-8")?;
+        let mut conn = self.inner.acquire().await?;
+
+        sqlx::query!("VACUUM INTO $1", path)
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Check the integrity of the database at `path`, e.g. a backup.
+pub async fn check_integrity(path: &Path) -> Result<()> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect()
+        .await
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    // sqlx cannot describe pragmas at compile time
+    let problems = sqlx::query_scalar::<_, String>("PRAGMA integrity_check")
+        .fetch_all(&mut conn)
+        .await?;
+    conn.close().await?;
+
+    ensure!(
+        problems == ["ok"],
+        "Integrity check of {} failed: {}",
+        path.display(),
+        problems.join("; ")
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use model::OrderId;
+    use model::Price;
+    use model::PriceTrigger;
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn backup_passes_integrity_check_and_contains_data() {
+        let order_id = OrderId::default();
+        let path = std::env::temp_dir().join(format!("backup-{order_id}.sqlite"));
+
+        let db = memory().await.unwrap();
+        db.set_price_trigger(
+            order_id,
+            PriceTrigger::StopLoss,
+            Price::new(dec!(19_000)).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        db.backup_to(&path).await.unwrap();
+        check_integrity(&path).await.unwrap();
+
+        let backup = crate::connect(path.clone(), false).await.unwrap();
+        assert_eq!(backup.load_price_triggers().await.unwrap().len(), 1);
+        assert!(db.backup_to(&path).await.is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
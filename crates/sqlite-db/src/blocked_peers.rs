@@ -0,0 +1,89 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::libp2p::PeerId;
+
+impl Connection {
+    /// Persist that connections from and to `peer_id` are refused.
+    pub async fn insert_blocked_peer(&self, peer_id: PeerId) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let peer_id = models::PeerId::from(peer_id);
+
+        sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO blocked_peers
+            (
+                peer_id
+            )
+            VALUES ($1)
+            "#,
+            peer_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove `peer_id` from the persisted blocked peers.
+    ///
+    /// Returns whether the peer was blocked.
+    pub async fn delete_blocked_peer(&self, peer_id: PeerId) -> Result<bool> {
+        let mut conn = self.inner.acquire().await?;
+        let peer_id = models::PeerId::from(peer_id);
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM
+                blocked_peers
+            WHERE
+                peer_id = $1
+            "#,
+            peer_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn load_blocked_peers(&self) -> Result<Vec<PeerId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let peer_ids = sqlx::query_scalar!(
+            r#"
+            SELECT
+                peer_id as "peer_id: models::PeerId"
+            FROM
+                blocked_peers
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        Ok(peer_ids.into_iter().map(PeerId::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn blocked_peers_can_be_unblocked() {
+        let db = memory().await.unwrap();
+        let blocked = PeerId::random();
+        let unblocked = PeerId::random();
+
+        db.insert_blocked_peer(blocked).await.unwrap();
+        db.insert_blocked_peer(blocked).await.unwrap();
+        db.insert_blocked_peer(unblocked).await.unwrap();
+        let was_blocked = db.delete_blocked_peer(unblocked).await.unwrap();
+
+        let blocked_peers = db.load_blocked_peers().await.unwrap();
+
+        assert!(was_blocked);
+        assert_eq!(blocked_peers, vec![blocked]);
+    }
+}
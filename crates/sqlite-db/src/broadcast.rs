@@ -0,0 +1,238 @@
+//! The transactions which were broadcast, so that concurrent broadcasts
+//! of the same transaction are suppressed.
+//!
+//! Several subsystems broadcast the transactions of a CFD, e.g. the
+//! process manager when a CFD is committed manually and the monitor for
+//! the transactions which are due when it starts. Before broadcasting a
+//! transaction, a subsystem claims it by its txid. A claim only blocks
+//! other claims for a short while, after which the transaction can be
+//! claimed and broadcast again, e.g. because it dropped out of the
+//! mempool. The broadcast on record includes the subsystem which
+//! initiated it.
+
+use crate::models;
+use crate::Connection;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::Txid;
+use model::OrderId;
+use model::Timestamp;
+use sqlx::Acquire;
+use sqlx::SqliteConnection;
+
+/// The broadcast of a transaction of a CFD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Broadcast {
+    pub txid: Txid,
+    pub order_id: OrderId,
+    /// The kind of the transaction, e.g. `commit`
+    pub kind: String,
+    /// The subsystem which initiated the broadcast
+    pub initiator: String,
+    pub timestamp: Timestamp,
+}
+
+impl Connection {
+    /// Claim the broadcast of a transaction.
+    ///
+    /// A previous claim of the transaction is taken over if it was made
+    /// before `expired_before`. Otherwise, the broadcast on record is
+    /// returned and the transaction should not be broadcast concurrently.
+    pub async fn claim_broadcast(
+        &self,
+        broadcast: &Broadcast,
+        expired_before: Timestamp,
+    ) -> Result<Option<Broadcast>> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let txid = models::Txid::from(broadcast.txid);
+        let order_id = models::OrderId::from(broadcast.order_id);
+        let timestamp = models::Timestamp::from(broadcast.timestamp);
+        let expired_before = models::Timestamp::from(expired_before);
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO broadcasts
+            (
+                txid,
+                order_id,
+                kind,
+                initiator,
+                timestamp
+            )
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (txid) DO UPDATE SET
+                order_id = excluded.order_id,
+                kind = excluded.kind,
+                initiator = excluded.initiator,
+                timestamp = excluded.timestamp
+            WHERE
+                broadcasts.timestamp < $6
+            "#,
+            txid,
+            order_id,
+            broadcast.kind,
+            broadcast.initiator,
+            timestamp,
+            expired_before
+        )
+        .execute(&mut *db_tx)
+        .await?;
+
+        let previous = if result.rows_affected() == 1 {
+            None
+        } else {
+            let previous = load_broadcast(&mut db_tx, broadcast.txid)
+                .await?
+                .context("Broadcast to be on record")?;
+
+            Some(previous)
+        };
+
+        db_tx.commit().await?;
+
+        Ok(previous)
+    }
+
+    /// Release the claim of the broadcast of a transaction, e.g. because
+    /// broadcasting it failed, so that it can be claimed again.
+    pub async fn release_broadcast(&self, txid: Txid) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let txid = models::Txid::from(txid);
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                broadcasts
+            WHERE
+                txid = $1
+            "#,
+            txid
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the latest broadcast of a transaction, if it was claimed.
+    pub async fn load_broadcast(&self, txid: Txid) -> Result<Option<Broadcast>> {
+        let mut conn = self.inner.acquire().await?;
+
+        load_broadcast(&mut conn, txid).await
+    }
+}
+
+async fn load_broadcast(conn: &mut SqliteConnection, txid: Txid) -> Result<Option<Broadcast>> {
+    let id = models::Txid::from(txid);
+
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            order_id as "order_id: models::OrderId",
+            kind,
+            initiator,
+            timestamp as "timestamp: models::Timestamp"
+        FROM
+            broadcasts
+        WHERE
+            txid = $1
+        "#,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let broadcast = row.map(|row| Broadcast {
+        txid,
+        order_id: row.order_id.into(),
+        kind: row.kind,
+        initiator: row.initiator,
+        timestamp: row.timestamp.into(),
+    });
+
+    Ok(broadcast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use std::str::FromStr;
+
+    fn broadcast(initiator: &str, timestamp: i64) -> Broadcast {
+        Broadcast {
+            txid: Txid::from_str(
+                "c2bc1ad9e6ac7e1d2a0bd9e7b2fe4cd74c9d1e84a7d1dd4a2c6a8e6a3f1b2c3d",
+            )
+            .unwrap(),
+            order_id: OrderId::default(),
+            kind: "commit".to_owned(),
+            initiator: initiator.to_owned(),
+            timestamp: Timestamp::new(timestamp),
+        }
+    }
+
+    #[tokio::test]
+    async fn given_broadcast_claimed_then_second_claim_gets_first_broadcast() {
+        let db = memory().await.unwrap();
+
+        let first = broadcast("manual-commit", 1_666_000_000);
+        let second = broadcast("monitor", 1_666_000_010);
+
+        assert_eq!(
+            db.claim_broadcast(&first, Timestamp::new(1_665_999_940))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            db.claim_broadcast(&second, Timestamp::new(1_665_999_950))
+                .await
+                .unwrap(),
+            Some(first)
+        );
+    }
+
+    #[tokio::test]
+    async fn given_claim_expired_then_broadcast_can_be_claimed_again() {
+        let db = memory().await.unwrap();
+
+        let first = broadcast("protocol", 1_666_000_000);
+        let second = broadcast("monitor", 1_666_000_120);
+
+        db.claim_broadcast(&first, Timestamp::new(1_665_999_940))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.claim_broadcast(&second, Timestamp::new(1_666_000_060))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(db.load_broadcast(second.txid).await.unwrap(), Some(second));
+    }
+
+    #[tokio::test]
+    async fn given_broadcast_released_then_it_can_be_claimed_again() {
+        let db = memory().await.unwrap();
+
+        let first = broadcast("manual-commit", 1_666_000_000);
+        let second = broadcast("monitor", 1_666_000_010);
+
+        db.claim_broadcast(&first, Timestamp::new(1_665_999_940))
+            .await
+            .unwrap();
+        db.release_broadcast(first.txid).await.unwrap();
+
+        assert_eq!(
+            db.claim_broadcast(&second, Timestamp::new(1_665_999_950))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(db.load_broadcast(second.txid).await.unwrap(), Some(second));
+    }
+}
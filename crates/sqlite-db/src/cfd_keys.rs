@@ -0,0 +1,197 @@
+//! The derivation paths of the keys of CFDs.
+//!
+//! The keys a CFD is set up with are derived from the seed along a path
+//! which is determined by a key index. Key indices are allocated
+//! locally, one per CFD, so that the counterparty cannot influence which
+//! keys we derive. Their derivation paths are recorded so that the keys
+//! can be recovered from the seed, even if the way paths are chosen
+//! changes.
+
+use crate::models;
+use crate::Connection;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::util::bip32::DerivationPath;
+use model::OrderId;
+use sqlx::Acquire;
+
+/// How a key of a CFD was derived from the seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDerivation {
+    /// What the key is used for, e.g. `identity`
+    pub kind: String,
+    pub path: DerivationPath,
+}
+
+impl Connection {
+    /// Allocate the key index of the CFD with `order_id`.
+    ///
+    /// Key indices are never reused. Fails if a key index was allocated
+    /// for the CFD before.
+    pub async fn allocate_key_index(&self, order_id: OrderId) -> Result<u32> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+
+        let key_index = sqlx::query!(
+            r#"
+            INSERT INTO cfd_key_indices
+            (
+                order_id
+            )
+            VALUES ($1)
+            "#,
+            order_id
+        )
+        .execute(&mut *conn)
+        .await
+        .with_context(|| format!("Failed to allocate key index for CFD {order_id}"))?
+        .last_insert_rowid();
+
+        let key_index = u32::try_from(key_index).context("Key index out of range")?;
+
+        Ok(key_index)
+    }
+
+    /// Record how the keys of the CFD with `order_id` were derived.
+    ///
+    /// Fails if the derivation of a key of the same kind was recorded for
+    /// the CFD before.
+    pub async fn save_key_derivations(
+        &self,
+        order_id: OrderId,
+        derivations: &[KeyDerivation],
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+        let order_id = models::OrderId::from(order_id);
+
+        for derivation in derivations {
+            let path = derivation.path.to_string();
+
+            sqlx::query!(
+                r#"
+                INSERT INTO cfd_key_derivations
+                (
+                    order_id,
+                    kind,
+                    path
+                )
+                VALUES ($1, $2, $3)
+                "#,
+                order_id,
+                derivation.kind,
+                path
+            )
+            .execute(&mut *db_tx)
+            .await?;
+        }
+
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Load how the keys of the CFD with `order_id` were derived.
+    pub async fn load_key_derivations(&self, order_id: OrderId) -> Result<Vec<KeyDerivation>> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                kind,
+                path
+            FROM
+                cfd_key_derivations
+            WHERE
+                order_id = $1
+            ORDER BY
+                kind
+            "#,
+            order_id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let path = row
+                    .path
+                    .parse::<DerivationPath>()
+                    .with_context(|| format!("Invalid derivation path {}", row.path))?;
+
+                Ok(KeyDerivation {
+                    kind: row.kind,
+                    path,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn given_key_derivations_saved_then_they_are_loaded_for_cfd() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        let derivations = vec![
+            KeyDerivation {
+                kind: "identity".to_owned(),
+                path: "m/1'/2'/0'".parse().unwrap(),
+            },
+            KeyDerivation {
+                kind: "publish".to_owned(),
+                path: "m/1'/2'/2'".parse().unwrap(),
+            },
+        ];
+        db.save_key_derivations(order_id, &derivations)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.load_key_derivations(order_id).await.unwrap(),
+            derivations
+        );
+        assert!(db
+            .load_key_derivations(OrderId::default())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn given_key_derivations_saved_then_saving_them_again_fails() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        let derivations = vec![KeyDerivation {
+            kind: "identity".to_owned(),
+            path: "m/1'/0'".parse().unwrap(),
+        }];
+        db.save_key_derivations(order_id, &derivations)
+            .await
+            .unwrap();
+
+        assert!(db
+            .save_key_derivations(order_id, &derivations)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn key_indices_are_allocated_once_per_cfd() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        let first = db.allocate_key_index(order_id).await.unwrap();
+        let second = db.allocate_key_index(OrderId::default()).await.unwrap();
+
+        assert_ne!(first, second);
+        assert!(db.allocate_key_index(order_id).await.is_err());
+    }
+}
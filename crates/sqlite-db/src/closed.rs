@@ -16,6 +16,7 @@ use crate::load_cfd_events;
 use crate::load_cfd_row;
 use crate::models;
 use crate::models::Txid;
+use crate::snapshot;
 use crate::Cfd;
 use crate::CfdAggregate;
 use crate::Connection;
@@ -89,6 +90,7 @@ impl Connection {
                 insert_settlement(&mut db_tx, id, closed_cfd.settlement).await?;
 
                 delete_from_events_table(&mut db_tx, id).await?;
+                snapshot::delete(&mut db_tx, id).await?;
                 delete_from_cfds_table(&mut db_tx, id).await?;
 
                 db_tx.commit().await?;
@@ -186,7 +188,8 @@ impl Connection {
         Ok(C::new_closed(args, cfd))
     }
 
-    pub(crate) async fn load_closed_cfd_ids(&self) -> Result<Vec<OrderId>> {
+    /// Load the IDs of all closed CFDs.
+    pub async fn load_closed_cfd_ids(&self) -> Result<Vec<OrderId>> {
         let mut conn = self.inner.acquire().await?;
 
         let ids = sqlx::query!(
@@ -205,6 +208,95 @@ impl Connection {
 
         Ok(ids)
     }
+
+    /// Load the time at which the final event of a closed CFD was
+    /// recorded, i.e. when it was closed.
+    pub async fn load_closed_timestamp(&self, id: OrderId) -> Result<Timestamp> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(id);
+
+        let closed_at = sqlx::query_scalar!(
+            r#"
+            SELECT
+                MAX(event_log.created_at) as "closed_at?: i64"
+            FROM
+                closed_cfds
+            JOIN
+                event_log ON event_log.cfd_id = closed_cfds.id
+            WHERE
+                closed_cfds.order_id = $1
+            "#,
+            order_id
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .with_context(|| format!("No event log for closed CFD {id}"))?;
+
+        Ok(Timestamp::new(closed_at))
+    }
+
+    /// Load the IDs of all closed CFDs whose final event was recorded
+    /// at or after `since`.
+    pub async fn load_closed_cfd_ids_closed_since(&self, since: Timestamp) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let since = since.seconds();
+        let ids = sqlx::query!(
+            r#"
+            SELECT
+                closed_cfds.order_id as "order_id: models::OrderId"
+            FROM
+                closed_cfds
+            JOIN
+                event_log ON event_log.cfd_id = closed_cfds.id
+            GROUP BY
+                closed_cfds.id
+            HAVING
+                MAX(event_log.created_at) >= $1
+            "#,
+            since
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| r.order_id.into())
+        .collect();
+
+        Ok(ids)
+    }
+
+    /// Load the IDs of all closed CFDs whose final event was recorded
+    /// before `before`.
+    pub async fn load_closed_cfd_ids_closed_before(
+        &self,
+        before: Timestamp,
+    ) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+        let before = before.seconds();
+
+        let ids = sqlx::query_scalar!(
+            r#"
+            SELECT
+                closed_cfds.order_id as "order_id: models::OrderId"
+            FROM
+                closed_cfds
+            JOIN
+                event_log ON event_log.cfd_id = closed_cfds.id
+            GROUP BY
+                closed_cfds.id
+            HAVING
+                MAX(event_log.created_at) < $1
+            "#,
+            before
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(OrderId::from)
+        .collect();
+
+        Ok(ids)
+    }
 }
 
 /// Auxiliary type used to gradually combine a `Cfd` with its list of
@@ -302,7 +394,7 @@ impl ClosedCfdInputAggregate {
                 self.latest_dlc = dlc;
             }
             ContractSetupFailed => {}
-            OfferRejected => {}
+            OfferRejected(_) => {}
             RolloverStarted => {}
             RolloverAccepted => {}
             RolloverRejected => {}
@@ -329,6 +421,8 @@ impl ClosedCfdInputAggregate {
             }
             CollaborativeSettlementRejected => {}
             CollaborativeSettlementFailed => {}
+            CollaborativeSettlementAbandoned { .. } => {}
+            CollaborativeSettlementProposalExpired => {}
             LockConfirmed => {}
             LockConfirmedAfterFinality => {}
             CommitConfirmed => {}
@@ -364,6 +458,7 @@ impl ClosedCfdInputAggregate {
                 self.cet = Some((cet, price));
             }
             ManualCommit { .. } => {}
+            PriceTriggered { .. } => {}
         }
 
         Ok(self)
@@ -0,0 +1,149 @@
+//! Collaborative settlements which were signed by both parties but not
+//! completed yet.
+//!
+//! Once the maker received the taker's signature of the settlement
+//! transaction, the settlement is stored here before the maker reveals
+//! its own signature. If the daemon restarts before the settlement is
+//! completed, it can still be completed and its transaction broadcast.
+
+use crate::models;
+use crate::Connection;
+use anyhow::Context;
+use anyhow::Result;
+use model::CollaborativeSettlement;
+use model::OrderId;
+
+impl Connection {
+    /// Store the fully signed settlement of a CFD until it is completed.
+    pub async fn insert_pending_collab_settlement(
+        &self,
+        order_id: OrderId,
+        settlement: &CollaborativeSettlement,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+
+        let settlement = serde_json::to_string(settlement)?;
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO pending_collab_settlements
+            (
+                order_id,
+                settlement
+            )
+            VALUES ($1, $2)
+            "#,
+            order_id,
+            settlement
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the pending settlement of a CFD, if any.
+    pub async fn load_pending_collab_settlement(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Option<CollaborativeSettlement>> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+
+        let settlement = sqlx::query_scalar!(
+            r#"
+            SELECT
+                settlement
+            FROM
+                pending_collab_settlements
+            WHERE
+                order_id = $1
+            "#,
+            order_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        settlement
+            .map(|settlement| {
+                serde_json::from_str(&settlement)
+                    .context("Failed to deserialize pending settlement")
+            })
+            .transpose()
+    }
+
+    /// Delete the pending settlement of a CFD once it was completed or
+    /// failed.
+    pub async fn delete_pending_collab_settlement(&self, order_id: OrderId) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                pending_collab_settlements
+            WHERE
+                order_id = $1
+            "#,
+            order_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use bdk::bitcoin::Script;
+    use bdk::bitcoin::Transaction;
+    use model::Price;
+    use rust_decimal_macros::dec;
+
+    fn settlement() -> CollaborativeSettlement {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+
+        CollaborativeSettlement::new(tx, Script::new(), Price::new(dec!(20_000)).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn given_pending_settlement_inserted_then_it_can_be_loaded() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+        let settlement = settlement();
+
+        db.insert_pending_collab_settlement(order_id, &settlement)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.load_pending_collab_settlement(order_id).await.unwrap(),
+            Some(settlement)
+        );
+    }
+
+    #[tokio::test]
+    async fn given_pending_settlement_deleted_then_none_is_loaded() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        db.insert_pending_collab_settlement(order_id, &settlement())
+            .await
+            .unwrap();
+        db.delete_pending_collab_settlement(order_id).await.unwrap();
+
+        assert_eq!(
+            db.load_pending_collab_settlement(order_id).await.unwrap(),
+            None
+        );
+    }
+}
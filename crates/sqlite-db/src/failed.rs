@@ -19,6 +19,7 @@ use crate::event_log::EventLogEntry;
 use crate::load_cfd_events;
 use crate::load_cfd_row;
 use crate::models;
+use crate::snapshot;
 use crate::Cfd;
 use crate::CfdAggregate;
 use crate::Connection;
@@ -31,6 +32,7 @@ use model::FailedCfd;
 use model::FeeAccount;
 use model::FundingFee;
 use model::OrderId;
+use model::RejectReason;
 use model::Timestamp;
 use models::FailedKind;
 use sqlx::Acquire;
@@ -60,11 +62,19 @@ impl Connection {
 
                 let events = load_cfd_events(&mut db_tx, id, 0).await?;
                 let event_log = EventLog::new(&events);
+                let reject_reason = events.iter().find_map(|event| match event.event {
+                    EventKind::OfferRejected(reason) => reason,
+                    _ => None,
+                });
 
                 insert_failed_cfd(&mut db_tx, cfd, &event_log).await?;
+                if let Some(reject_reason) = reject_reason {
+                    insert_reject_reason(&mut db_tx, id, reject_reason).await?;
+                }
                 insert_event_log(&mut db_tx, id, event_log).await?;
 
                 delete_from_events_table(&mut db_tx, id).await?;
+                snapshot::delete(&mut db_tx, id).await?;
                 delete_from_cfds_table(&mut db_tx, id).await?;
 
                 db_tx.commit().await?;
@@ -115,6 +125,7 @@ impl Connection {
         .await?;
 
         let creation_timestamp = load_creation_timestamp(&mut conn, id).await?;
+        let reject_reason = load_reject_reason(&mut conn, id).await?;
 
         let cfd = FailedCfd {
             id,
@@ -130,6 +141,7 @@ impl Connection {
             kind: cfd.kind.into(),
             creation_timestamp,
             contract_symbol: cfd.contract_symbol.into(),
+            reject_reason,
         };
 
         Ok(C::new_failed(args, cfd))
@@ -154,6 +166,69 @@ impl Connection {
 
         Ok(ids)
     }
+
+    /// Load the IDs of all failed CFDs whose final event was recorded
+    /// at or after `since`.
+    pub async fn load_failed_cfd_ids_failed_since(&self, since: Timestamp) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+        let since = since.seconds();
+
+        let ids = sqlx::query_scalar!(
+            r#"
+            SELECT
+                failed_cfds.order_id as "order_id: models::OrderId"
+            FROM
+                failed_cfds
+            JOIN
+                event_log_failed ON event_log_failed.cfd_id = failed_cfds.id
+            GROUP BY
+                failed_cfds.id
+            HAVING
+                MAX(event_log_failed.created_at) >= $1
+            "#,
+            since
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(OrderId::from)
+        .collect();
+
+        Ok(ids)
+    }
+
+    /// Load the IDs of all failed CFDs whose final event was recorded
+    /// before `before`.
+    pub async fn load_failed_cfd_ids_failed_before(
+        &self,
+        before: Timestamp,
+    ) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+        let before = before.seconds();
+
+        let ids = sqlx::query_scalar!(
+            r#"
+            SELECT
+                failed_cfds.order_id as "order_id: models::OrderId"
+            FROM
+                failed_cfds
+            JOIN
+                event_log_failed ON event_log_failed.cfd_id = failed_cfds.id
+            GROUP BY
+                failed_cfds.id
+            HAVING
+                MAX(event_log_failed.created_at) < $1
+            "#,
+            before
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(OrderId::from)
+        .collect();
+
+        Ok(ids)
+    }
 }
 
 async fn insert_failed_cfd(
@@ -161,7 +236,7 @@ async fn insert_failed_cfd(
     cfd: Cfd,
     event_log: &EventLog,
 ) -> Result<()> {
-    let kind = if event_log.contains(&EventKind::OfferRejected) {
+    let kind = if event_log.contains(&EventKind::OfferRejected(None)) {
         FailedKind::OfferRejected
     } else if event_log.contains(&EventKind::ContractSetupFailed) {
         FailedKind::ContractSetupFailed
@@ -251,6 +326,53 @@ async fn insert_failed_cfd(
     Ok(())
 }
 
+async fn insert_reject_reason(
+    conn: &mut SqliteConnection,
+    id: OrderId,
+    reject_reason: RejectReason,
+) -> Result<()> {
+    let reject_reason = models::RejectReason::from(reject_reason);
+    let order_id = models::OrderId::from(id);
+
+    let query_result = sqlx::query!(
+        r#"
+        UPDATE failed_cfds
+        SET reject_reason = $1
+        WHERE order_id = $2
+        "#,
+        reject_reason,
+        order_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if query_result.rows_affected() != 1 {
+        bail!("failed to set reject reason of failed CFD");
+    }
+
+    Ok(())
+}
+
+async fn load_reject_reason(
+    conn: &mut SqliteConnection,
+    id: OrderId,
+) -> Result<Option<RejectReason>> {
+    let order_id = models::OrderId::from(id);
+
+    let reject_reason = sqlx::query_scalar!(
+        r#"
+        SELECT reject_reason as "reject_reason?: models::RejectReason"
+        FROM failed_cfds
+        WHERE order_id = $1
+        "#,
+        order_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(reject_reason.map(RejectReason::from))
+}
+
 async fn insert_event_log(
     conn: &mut SqliteConnection,
     id: OrderId,
@@ -433,6 +555,30 @@ mod tests {
         assert_eq!(creation_timestamp, contract_setup_started_timestamp);
     }
 
+    #[tokio::test]
+    async fn given_offer_rejected_with_reason_when_move_cfds_to_failed_table_then_reason_is_kept() {
+        let db = memory().await.unwrap();
+        let mut conn = db.inner.acquire().await.unwrap();
+
+        let cfd = dummy_cfd();
+        let id = cfd.id();
+
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let offer_rejected = CfdEvent {
+            timestamp: Timestamp::now(),
+            id,
+            event: EventKind::OfferRejected(Some(RejectReason::QuantityOutOfBounds)),
+        };
+        db.append_event(offer_rejected).await.unwrap();
+
+        db.move_to_failed_cfds().await.unwrap();
+
+        let reject_reason = load_reject_reason(&mut *conn, id).await.unwrap();
+
+        assert_eq!(reject_reason, Some(RejectReason::QuantityOutOfBounds));
+    }
+
     #[derive(Debug, Clone)]
     struct DummyAggregate;
 
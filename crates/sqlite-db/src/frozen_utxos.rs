@@ -0,0 +1,113 @@
+//! UTXOs of the wallet which the user does not want to be spent into a
+//! lock transaction.
+
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use bdk::bitcoin::OutPoint;
+use bdk::bitcoin::Txid;
+use sqlx::Acquire;
+
+impl Connection {
+    pub async fn insert_frozen_utxos(&self, outpoints: &[OutPoint]) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        for outpoint in outpoints {
+            let txid = models::Txid::from(outpoint.txid);
+            let vout = models::Vout::new(outpoint.vout);
+
+            sqlx::query!(
+                r#"
+                INSERT OR IGNORE INTO frozen_utxos
+                (
+                    txid,
+                    vout
+                )
+                VALUES ($1, $2)
+                "#,
+                txid,
+                vout
+            )
+            .execute(&mut *db_tx)
+            .await?;
+        }
+
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_frozen_utxos(&self, outpoints: &[OutPoint]) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        for outpoint in outpoints {
+            let txid = models::Txid::from(outpoint.txid);
+            let vout = models::Vout::new(outpoint.vout);
+
+            sqlx::query!(
+                r#"
+                DELETE FROM
+                    frozen_utxos
+                WHERE
+                    txid = $1 AND vout = $2
+                "#,
+                txid,
+                vout
+            )
+            .execute(&mut *db_tx)
+            .await?;
+        }
+
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn load_frozen_utxos(&self) -> Result<Vec<OutPoint>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                txid as "txid: models::Txid",
+                vout as "vout: models::Vout"
+            FROM
+                frozen_utxos
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let outpoints = rows
+            .into_iter()
+            .map(|row| OutPoint::new(Txid::from(row.txid), u32::from(row.vout)))
+            .collect();
+
+        Ok(outpoints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use bdk::bitcoin::hashes::hex::FromHex;
+
+    #[tokio::test]
+    async fn frozen_utxos_can_be_unfrozen() {
+        let db = memory().await.unwrap();
+        let txid =
+            Txid::from_hex("c2bc1ad9e6ac7e1d2a0bd9e7b2fe4cd74c9d1e84a7d1dd4a2c6a8e6a3f1b2c3d")
+                .unwrap();
+        let frozen = OutPoint::new(txid, 0);
+        let unfrozen = OutPoint::new(txid, 1);
+
+        db.insert_frozen_utxos(&[frozen, unfrozen]).await.unwrap();
+        db.insert_frozen_utxos(&[frozen]).await.unwrap();
+        db.delete_frozen_utxos(&[unfrozen]).await.unwrap();
+
+        assert_eq!(db.load_frozen_utxos().await.unwrap(), vec![frozen]);
+    }
+}
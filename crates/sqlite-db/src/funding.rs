@@ -0,0 +1,260 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use bdk::bitcoin::Amount;
+use model::ContractSymbol;
+use model::FundingFee;
+use model::FundingRate;
+use model::OrderId;
+use model::Timestamp;
+use sqlx::SqliteExecutor;
+
+/// A funding fee charged during a rollover
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FundingPayment {
+    pub order_id: OrderId,
+    pub timestamp: Timestamp,
+    pub funding_fee: FundingFee,
+}
+
+impl Connection {
+    /// Record the funding fee charged for a CFD during a rollover.
+    ///
+    /// The funding history is kept after the CFD is closed.
+    pub async fn insert_funding_payment(
+        &self,
+        order_id: OrderId,
+        timestamp: Timestamp,
+        funding_fee: FundingFee,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        insert_funding_payment(&mut *conn, order_id, timestamp, funding_fee).await
+    }
+
+    /// Load all funding payments of a CFD, oldest first.
+    pub async fn load_funding_history(&self, order_id: OrderId) -> Result<Vec<FundingPayment>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let id = models::OrderId::from(order_id);
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                timestamp,
+                funding_rate as "funding_rate: models::FundingRate",
+                fee_sat
+            FROM
+                funding_payments
+            WHERE
+                order_id = $1
+            ORDER BY
+                timestamp, id
+            "#,
+            id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let history = rows
+            .into_iter()
+            .map(|row| {
+                Ok(FundingPayment {
+                    order_id,
+                    timestamp: Timestamp::new(row.timestamp),
+                    funding_fee: FundingFee {
+                        fee: Amount::from_sat(u64::try_from(row.fee_sat)?),
+                        rate: FundingRate::from(row.funding_rate),
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(history)
+    }
+
+    /// Load the funding rates charged during rollovers of CFDs of
+    /// `contract_symbol` at or after `since`, oldest first.
+    ///
+    /// Rollovers of CFDs which have been closed since are included.
+    pub async fn load_funding_rates(
+        &self,
+        contract_symbol: ContractSymbol,
+        since: Timestamp,
+    ) -> Result<Vec<(Timestamp, FundingRate)>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let contract_symbol = models::ContractSymbol::from(contract_symbol);
+        let since = since.seconds();
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                timestamp,
+                funding_rate as "funding_rate: models::FundingRate"
+            FROM
+                funding_payments
+            WHERE
+                timestamp >= $1
+                AND order_id IN (
+                    SELECT order_id FROM cfds WHERE contract_symbol = $2
+                    UNION
+                    SELECT order_id FROM closed_cfds WHERE contract_symbol = $2
+                )
+            ORDER BY
+                timestamp, id
+            "#,
+            since,
+            contract_symbol
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let funding_rates = rows
+            .into_iter()
+            .map(|row| (Timestamp::new(row.timestamp), row.funding_rate.into()))
+            .collect();
+
+        Ok(funding_rates)
+    }
+}
+
+/// Record a funding payment.
+///
+/// Fails if a payment for the same CFD was already recorded at
+/// `timestamp`.
+pub(crate) async fn insert_funding_payment(
+    conn: impl SqliteExecutor<'_>,
+    order_id: OrderId,
+    timestamp: Timestamp,
+    funding_fee: FundingFee,
+) -> Result<()> {
+    let order_id = models::OrderId::from(order_id);
+    let timestamp = timestamp.seconds();
+    let funding_rate = models::FundingRate::from(funding_fee.rate);
+    let fee_sat = i64::try_from(funding_fee.fee.as_sat())?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO funding_payments
+        (
+            order_id,
+            timestamp,
+            funding_rate,
+            fee_sat
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
+        order_id,
+        timestamp,
+        funding_rate,
+        fee_sat
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use crate::tests::dummy_taker_with_counterparty_peer_id;
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn given_funding_payments_when_loading_history_then_only_payments_of_cfd_in_order() {
+        let db = memory().await.unwrap();
+
+        let order_id = OrderId::default();
+        let first = FundingFee {
+            fee: Amount::from_sat(100),
+            rate: FundingRate::new(dec!(0.001)).unwrap(),
+        };
+        let second = FundingFee {
+            fee: Amount::from_sat(50),
+            rate: FundingRate::new(dec!(-0.0005)).unwrap(),
+        };
+
+        db.insert_funding_payment(order_id, Timestamp::new(2), second)
+            .await
+            .unwrap();
+        db.insert_funding_payment(order_id, Timestamp::new(1), first)
+            .await
+            .unwrap();
+        db.insert_funding_payment(OrderId::default(), Timestamp::new(3), first)
+            .await
+            .unwrap();
+
+        let history = db.load_funding_history(order_id).await.unwrap();
+
+        assert_eq!(
+            history,
+            vec![
+                FundingPayment {
+                    order_id,
+                    timestamp: Timestamp::new(1),
+                    funding_fee: first,
+                },
+                FundingPayment {
+                    order_id,
+                    timestamp: Timestamp::new(2),
+                    funding_fee: second,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn given_funding_payment_when_inserting_again_at_same_time_then_error() {
+        let db = memory().await.unwrap();
+
+        let order_id = OrderId::default();
+        let funding_fee = FundingFee {
+            fee: Amount::from_sat(100),
+            rate: FundingRate::new(dec!(0.001)).unwrap(),
+        };
+
+        db.insert_funding_payment(order_id, Timestamp::new(1), funding_fee)
+            .await
+            .unwrap();
+        let result = db
+            .insert_funding_payment(order_id, Timestamp::new(1), funding_fee)
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(db.load_funding_history(order_id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn given_funding_payments_when_loading_rates_then_only_rates_of_symbol_since() {
+        let db = memory().await.unwrap();
+        let cfd = dummy_taker_with_counterparty_peer_id();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let old = FundingFee {
+            fee: Amount::from_sat(100),
+            rate: FundingRate::new(dec!(0.001)).unwrap(),
+        };
+        let recent = FundingFee {
+            fee: Amount::from_sat(50),
+            rate: FundingRate::new(dec!(-0.0005)).unwrap(),
+        };
+
+        db.insert_funding_payment(cfd.id(), Timestamp::new(1), old)
+            .await
+            .unwrap();
+        db.insert_funding_payment(cfd.id(), Timestamp::new(2), recent)
+            .await
+            .unwrap();
+        db.insert_funding_payment(OrderId::default(), Timestamp::new(2), old)
+            .await
+            .unwrap();
+
+        let rates = db
+            .load_funding_rates(ContractSymbol::BtcUsd, Timestamp::new(2))
+            .await
+            .unwrap();
+
+        assert_eq!(rates, vec![(Timestamp::new(2), recent.rate)]);
+    }
+}
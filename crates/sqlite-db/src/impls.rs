@@ -1,7 +1,9 @@
+use anyhow::Result;
 use model::CfdEvent;
 
 impl crate::CfdAggregate for model::Cfd {
     type CtorArgs = ();
+    const SNAPSHOT: Option<&'static str> = Some("cfd");
 
     fn new(
         _: Self::CtorArgs,
@@ -20,9 +22,10 @@ impl crate::CfdAggregate for model::Cfd {
             initial_funding_rate,
             initial_tx_fee_rate,
             contract_symbol,
+            refund_timelock,
         }: crate::Cfd,
     ) -> Self {
-        model::Cfd::new(
+        let cfd = model::Cfd::new(
             id,
             offer_id,
             position,
@@ -37,7 +40,12 @@ impl crate::CfdAggregate for model::Cfd {
             initial_funding_rate,
             initial_tx_fee_rate,
             contract_symbol,
-        )
+        );
+
+        match refund_timelock {
+            Some(refund_timelock) => cfd.with_refund_timelock(refund_timelock),
+            None => cfd,
+        }
     }
 
     fn apply(self, event: CfdEvent) -> Self {
@@ -47,4 +55,19 @@ impl crate::CfdAggregate for model::Cfd {
     fn version(&self) -> u32 {
         self.version()
     }
+
+    fn to_snapshot(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    fn restore_snapshot(&self, snapshot: &str) -> Result<Self> {
+        let cfd = serde_json::from_str::<Self>(snapshot)?;
+        anyhow::ensure!(
+            cfd.id() == self.id(),
+            "Snapshot belongs to CFD {}",
+            cfd.id()
+        );
+
+        Ok(cfd)
+    }
 }
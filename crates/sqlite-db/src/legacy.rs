@@ -0,0 +1,85 @@
+//! Migration of CFDs created with the legacy wire protocol.
+//!
+//! Before the switch to libp2p, CFDs were stored with a placeholder
+//! peer-id. For the known makers the peer-id is derived from their
+//! legacy network identity whenever such a CFD is loaded. The migration
+//! stores the derived peer-id instead, so that the CFD can be rolled
+//! over and settled over libp2p like any other CFD. Open CFDs with any
+//! other counterparty cannot be reached over libp2p anymore and can only
+//! be closed by committing to the blockchain.
+
+use crate::derive_known_peer_id;
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::libp2p::PeerId;
+use model::OrderId;
+use sqlx::Acquire;
+
+impl Connection {
+    /// Store the peer-id of the counterparty of all open CFDs created
+    /// with the legacy wire protocol.
+    ///
+    /// Returns the IDs of the open CFDs whose counterparty peer-id is
+    /// not known.
+    pub async fn migrate_legacy_cfds(&self) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let placeholder_peer_id = models::PeerId::from(PeerId::placeholder());
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId",
+                counterparty_network_identity as "counterparty_network_identity: models::Identity",
+                role as "role: models::Role"
+            FROM
+                cfds
+            WHERE
+                counterparty_peer_id = $1
+            "#,
+            placeholder_peer_id
+        )
+        .fetch_all(&mut db_tx)
+        .await?;
+
+        let mut unknown = Vec::new();
+        for row in rows {
+            let order_id = OrderId::from(row.order_id);
+            let identity = row.counterparty_network_identity;
+
+            let peer_id = match derive_known_peer_id(identity.into(), row.role.into()) {
+                Some(peer_id) => peer_id,
+                None => {
+                    unknown.push(order_id);
+                    continue;
+                }
+            };
+
+            let counterparty_peer_id = models::PeerId::from(peer_id);
+            let cfd_order_id = models::OrderId::from(order_id);
+
+            sqlx::query!(
+                r#"
+                UPDATE
+                    cfds
+                SET
+                    counterparty_peer_id = $1
+                WHERE
+                    order_id = $2
+                "#,
+                counterparty_peer_id,
+                cfd_order_id
+            )
+            .execute(&mut db_tx)
+            .await?;
+
+            tracing::info!(%order_id, %peer_id, "Migrated legacy CFD to libp2p");
+        }
+
+        db_tx.commit().await?;
+
+        Ok(unknown)
+    }
+}
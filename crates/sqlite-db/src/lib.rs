@@ -7,6 +7,7 @@ use dashmap::DashMap;
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use futures::Stream;
+use futures::StreamExt;
 use model::libp2p::PeerId;
 use model::CfdEvent;
 use model::ContractSymbol;
@@ -21,6 +22,7 @@ use model::OrderId;
 use model::Position;
 use model::Price;
 use model::Role;
+use model::Timestamp;
 use model::TxFeeRate;
 use sqlx::migrate::MigrateError;
 use sqlx::sqlite::SqliteConnectOptions;
@@ -31,26 +33,64 @@ use std::any::Any;
 use std::any::TypeId;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use time::Duration;
 
 pub use closed::*;
 pub use failed::*;
+pub use funding::*;
 use model::EventKind::RolloverCompleted;
 
+pub mod annotations;
+pub mod backup;
+pub mod blocked_peers;
+pub mod broadcast;
+pub mod cfd_keys;
 pub mod closed;
+pub mod collab_settlement;
 pub mod event_log;
 pub mod failed;
+pub mod frozen_utxos;
+pub mod funding;
 mod impls;
+pub mod legacy;
+pub mod loss_limit;
+pub mod maker_address;
+pub mod maker_identity;
 mod models;
+pub mod offer_tiers;
+pub mod oracle_cache;
+pub mod preferences;
+pub mod price_trigger;
+pub mod protocol_allowlist;
+pub mod purge;
+pub mod quotes;
+pub mod rebuild;
+pub mod rehydration;
 mod rollover;
+pub mod rollover_policy;
+pub mod scheduled_settlement;
+pub mod snapshot;
+pub mod taker_records;
 pub mod time_to_first_position;
+pub mod trace;
 pub mod user;
+pub mod watchtower;
+
+/// How many open CFDs are rehydrated concurrently.
+///
+/// Every CFD being rehydrated holds a connection of the pool, so this
+/// stays well below its size.
+pub const REHYDRATION_PARALLELISM: usize = 4;
 
 #[derive(Clone)]
 pub struct Connection {
     inner: SqlitePool,
     aggregate_cache: Arc<DashMap<(TypeId, OrderId), Box<dyn Any + Send + Sync + 'static>>>,
+    /// Incremented whenever an aggregate is rebuilt, see [`rebuild`]
+    aggregate_generation: Arc<AtomicU64>,
 }
 
 impl Connection {
@@ -58,6 +98,7 @@ impl Connection {
         Self {
             inner: pool,
             aggregate_cache: Arc::new(DashMap::new()),
+            aggregate_generation: Arc::new(AtomicU64::new(0)),
         }
     }
 
@@ -185,8 +226,9 @@ impl Connection {
             opening_fee,
             initial_funding_rate,
             initial_tx_fee_rate,
-            contract_symbol
-        ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"#,
+            contract_symbol,
+            refund_timelock
+        ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)"#,
         )
         .bind(&order_id)
         .bind(&offer_id)
@@ -209,6 +251,7 @@ impl Connection {
         .bind(&initial_funding_rate)
         .bind(&tx_fee_rate)
         .bind(&contract_symbol)
+        .bind(i64::from(cfd.refund_timelock_in_blocks()))
         .execute(&mut conn)
         .await?;
 
@@ -223,6 +266,10 @@ impl Connection {
     ///
     /// To make handling of `None` events more ergonomic, you can pass anything in here that
     /// implements `Into<Option>` event.
+    ///
+    /// The event and all data derived from it (e.g. the new DLC and funding payment of a
+    /// `RolloverCompleted` event) are persisted in a single transaction: either all of it is
+    /// stored or none of it.
     pub async fn append_event(&self, event: impl Into<Option<CfdEvent>>) -> Result<()> {
         let mut conn = self.inner.acquire().await?;
         let mut db_tx = conn.begin().await?;
@@ -231,59 +278,42 @@ impl Connection {
             Some(event) => event,
             None => return Ok(()),
         };
+        let order_id = event.id;
 
-        let (event_name, event_data) = event.event.to_json();
+        let event_name = insert_event(&mut db_tx, event).await?;
 
-        let order_id = models::OrderId::from(event.id);
-        let timestamp = models::Timestamp::from(event.timestamp);
-        let query_result = sqlx::query(
-            r##"
-        insert into events (
-            cfd_id,
-            name,
-            data,
-            created_at
-        ) values (
-            (select id from cfds where cfds.order_id = $1),
-            $2, $3, $4
-        )"##,
-        )
-        .bind(&order_id)
-        .bind(&event_name)
-        .bind(&event_data)
-        .bind(&timestamp)
-        .execute(&mut db_tx)
-        .await?;
+        db_tx.commit().await?;
 
-        if query_result.rows_affected() != 1 {
-            bail!("failed to insert event");
-        }
+        tracing::info!(event = %event_name, %order_id, "Appended event to database");
 
-        match event.event {
-            // if we have a rollover completed event we store it additionally in its own table
-            RolloverCompleted {
-                dlc: Some(dlc),
-                funding_fee,
-                complete_fee,
-            } => {
-                rollover::overwrite(
-                    &mut db_tx,
-                    query_result.last_insert_rowid(),
-                    order_id,
-                    dlc,
-                    funding_fee,
-                    complete_fee,
-                )
-                .await?;
-            }
-            RolloverCompleted { dlc: None, .. } => {
-                tracing::error!(
-                    "Invalid RolloverCompleted event: Trying to insert a RolloverCompleted event without a DLC"
-                )
-            }
-            _ => {}
+        Ok(())
+    }
+
+    /// Appends an event to the `events` table, unless events were appended to the CFD since it
+    /// was loaded in `expected_version`.
+    ///
+    /// This allows to execute commands with optimistic concurrency control: the event is only
+    /// stored if it was derived from the latest state of the CFD.
+    pub async fn append_event_at_version(
+        &self,
+        event: CfdEvent,
+        expected_version: u32,
+    ) -> Result<(), Error> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let order_id = event.id;
+
+        let actual_version = count_cfd_events(&mut db_tx, order_id).await?;
+        if actual_version != expected_version {
+            return Err(Error::VersionConflict {
+                expected: expected_version,
+                actual: actual_version,
+            });
         }
 
+        let event_name = insert_event(&mut db_tx, event).await?;
+
         db_tx.commit().await?;
 
         tracing::info!(event = %event_name, %order_id, "Appended event to database");
@@ -301,14 +331,16 @@ impl Connection {
 
         let cache_key = (TypeId::of::<C>(), id);
         let aggregate = std::any::type_name::<C>();
+        let generation = self.aggregate_generation.load(Ordering::SeqCst);
 
         let cfd = match self.aggregate_cache.remove(&cache_key) {
             None => {
                 // No cache entry? Load the CFD row. Version will be 0 because we haven't applied
-                // any events, thus all events will be loaded.
+                // any events, thus all events will be loaded, unless there is a snapshot.
                 let cfd = load_cfd_row(&mut db_tx, id).await?;
+                let cfd = C::new(args, cfd);
 
-                C::new(args, cfd)
+                snapshot::restore(&mut db_tx, id, cfd).await?
             }
             Some((_, cfd)) => {
                 // Got a cache entry: Downcast it to the type at hand.
@@ -328,8 +360,24 @@ impl Connection {
 
         let cfd = events.into_iter().fold(cfd, C::apply);
 
-        self.aggregate_cache
-            .insert(cache_key, Box::new(cfd.clone()));
+        // If the aggregate was rebuilt in the meantime, `cfd` was derived from the old state and
+        // must neither be snapshotted nor replace the rebuilt aggregate in the cache. The entry is
+        // held while checking, so that a rebuild cannot slip in between.
+        let entry = self.aggregate_cache.entry(cache_key);
+        let is_current = self.aggregate_generation.load(Ordering::SeqCst) == generation;
+
+        if is_current {
+            entry.insert(Box::new(cfd.clone()));
+        } else {
+            drop(entry);
+            tracing::debug!(order_id = %id, %aggregate, "Not caching CFD loaded during rebuild");
+        }
+
+        if is_current && snapshot::is_due(cfd_version, cfd.version()) {
+            if let Err(e) = snapshot::save(&mut db_tx, id, &cfd).await {
+                tracing::warn!(order_id = %id, %aggregate, "Failed to save snapshot: {e:#}");
+            }
+        }
 
         db_tx.commit().await?;
 
@@ -346,8 +394,9 @@ impl Connection {
     {
         let stream = async_stream::stream! {
             let ids = self.load_open_cfd_ids().await?;
-            for id in ids {
-                let res = match self.load_open_cfd(id, args.clone()).await {
+            let mut open_cfds = self.load_open_cfds_concurrently::<C>(ids, args.clone());
+            while let Some((id, res)) = open_cfds.next().await {
+                let res = match res {
                     Err(Error::OpenCfdNotFound) => {
                         tracing::trace!(
                             order_id=%id,
@@ -378,6 +427,82 @@ impl Connection {
         Box::pin(stream)
     }
 
+    /// Loads all open CFDs, and all closed and failed CFDs whose final
+    /// event was recorded at or after `since`.
+    ///
+    /// The remaining CFDs can be loaded with [`Connection::load_archived_cfds`].
+    pub fn load_recent_cfds<'a, C>(
+        &'a self,
+        args: C::CtorArgs,
+        since: Timestamp,
+    ) -> impl Stream<Item = Result<C>> + Unpin + '_
+    where
+        C: CfdAggregate + ClosedCfdAggregate + FailedCfdAggregate,
+        C::CtorArgs: Clone + Send + Sync,
+    {
+        let stream = async_stream::stream! {
+            let ids = self.load_open_cfd_ids().await?;
+            let mut open_cfds = self.load_open_cfds_concurrently::<C>(ids, args.clone());
+            while let Some((id, res)) = open_cfds.next().await {
+                let res = match res {
+                    Err(Error::OpenCfdNotFound) => {
+                        tracing::trace!(
+                            order_id=%id,
+                            target="db",
+                            "Ignoring OpenCfdNotFound"
+                        );
+                        continue;
+                    }
+                    res => res.with_context(|| format!("Could not load open CFD {id}")),
+                };
+
+                yield res;
+            }
+
+            let ids = self.load_closed_cfd_ids_closed_since(since).await?;
+            for id in ids {
+                yield self.load_closed_cfd(id, args.clone()).await
+                    .with_context(|| format!("Failed to load closed CFD {id}"));
+            }
+
+            let ids = self.load_failed_cfd_ids_failed_since(since).await?;
+            for id in ids {
+                yield self.load_failed_cfd(id, args.clone()).await
+                    .with_context(|| format!("Failed to load failed CFD {id}"));
+            }
+        };
+
+        Box::pin(stream)
+    }
+
+    /// Loads all closed and failed CFDs whose final event was recorded
+    /// before `before`.
+    pub fn load_archived_cfds<'a, C>(
+        &'a self,
+        args: C::CtorArgs,
+        before: Timestamp,
+    ) -> impl Stream<Item = Result<C>> + Unpin + '_
+    where
+        C: ClosedCfdAggregate + FailedCfdAggregate,
+        C::CtorArgs: Clone + Send + Sync,
+    {
+        let stream = async_stream::stream! {
+            let ids = self.load_closed_cfd_ids_closed_before(before).await?;
+            for id in ids {
+                yield self.load_closed_cfd(id, args.clone()).await
+                    .with_context(|| format!("Failed to load closed CFD {id}"));
+            }
+
+            let ids = self.load_failed_cfd_ids_failed_before(before).await?;
+            for id in ids {
+                yield self.load_failed_cfd(id, args.clone()).await
+                    .with_context(|| format!("Failed to load failed CFD {id}"));
+            }
+        };
+
+        Box::pin(stream)
+    }
+
     /// Loads all CFDs where we are still able to append events
     ///
     /// This function is to be called when we only want to process CFDs where events can still be
@@ -398,9 +523,10 @@ impl Connection {
     {
         let stream = async_stream::stream! {
             let ids = self.load_open_cfd_ids().await?;
+            let mut open_cfds = self.load_open_cfds_concurrently::<C>(ids, args);
 
-            for id in ids {
-                let res = match self.load_open_cfd(id, args.clone()).await {
+            while let Some((id, res)) = open_cfds.next().await {
+                let res = match res {
                     Err(Error::OpenCfdNotFound) => {
                         tracing::trace!(
                             order_id=%id,
@@ -419,6 +545,29 @@ impl Connection {
         Box::pin(stream)
     }
 
+    /// Load the open CFDs with the given `ids`, up to
+    /// [`REHYDRATION_PARALLELISM`] of them at a time.
+    ///
+    /// The CFDs are yielded in the order of `ids`, together with their ID.
+    fn load_open_cfds_concurrently<'a, C>(
+        &'a self,
+        ids: Vec<OrderId>,
+        args: C::CtorArgs,
+    ) -> impl Stream<Item = (OrderId, Result<C, Error>)> + Unpin + 'a
+    where
+        C: CfdAggregate,
+        C::CtorArgs: Clone + Send + Sync,
+    {
+        let stream = futures::stream::iter(ids)
+            .map(move |id| {
+                let args = args.clone();
+                async move { (id, self.load_open_cfd(id, args).await) }
+            })
+            .buffered(REHYDRATION_PARALLELISM);
+
+        Box::pin(stream)
+    }
+
     /// Load the IDs for all the CFDs found in the `cfds` table.
     ///
     /// Importantly, callers **cannot** rely on the CFD IDs returned
@@ -443,6 +592,60 @@ impl Connection {
         Ok(ids)
     }
 
+    /// The offer, quantity and leverage with which the CFD with the
+    /// given `id` was ordered, no matter if it is open, closed or failed.
+    ///
+    /// `None` if no such CFD exists.
+    pub async fn load_order_parameters(
+        &self,
+        id: OrderId,
+    ) -> Result<Option<(OfferId, Contracts, Leverage)>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let id = models::OrderId::from(id);
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                offer_id as "offer_id!: models::OfferId",
+                contracts as "contracts!: models::Contracts",
+                leverage as "leverage!: models::Leverage"
+            FROM
+                cfds
+            WHERE
+                order_id = $1
+            UNION ALL
+            SELECT
+                offer_id,
+                n_contracts,
+                taker_leverage
+            FROM
+                closed_cfds
+            WHERE
+                order_id = $1
+            UNION ALL
+            SELECT
+                offer_id,
+                n_contracts,
+                taker_leverage
+            FROM
+                failed_cfds
+            WHERE
+                order_id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        Ok(row.map(|row| {
+            (
+                row.offer_id.into(),
+                row.contracts.into(),
+                row.leverage.into(),
+            )
+        }))
+    }
+
     async fn closed_cfd_ids_according_to_the_blockchain(&self) -> Result<Vec<OrderId>> {
         let mut conn = self.inner.acquire().await?;
 
@@ -526,12 +729,16 @@ pub struct Cfd {
     pub initial_funding_rate: FundingRate,
     pub initial_tx_fee_rate: TxFeeRate,
     pub contract_symbol: ContractSymbol,
+    /// `None` for CFDs inserted before the refund timelock was configurable
+    pub refund_timelock: Option<u32>,
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("The CFD requested was not found in the open CFDs")]
     OpenCfdNotFound,
+    #[error("Expected the CFD to be in version {expected} but it is in version {actual}")]
+    VersionConflict { expected: u32, actual: u32 },
     #[error("{0:#}")]
     Sqlx(#[source] sqlx::Error),
     #[error("{0:#}")]
@@ -558,9 +765,94 @@ impl From<anyhow::Error> for Error {
 pub trait CfdAggregate: Clone + Send + Sync + 'static {
     type CtorArgs;
 
+    /// The name snapshots of the aggregate are stored under, if it supports snapshots.
+    ///
+    /// An aggregate with snapshots is restored from its latest snapshot when it is not cached,
+    /// and only the events after the snapshot are applied. Snapshots are saved every
+    /// [`snapshot::INTERVAL`] events.
+    const SNAPSHOT: Option<&'static str> = None;
+
     fn new(args: Self::CtorArgs, cfd: Cfd) -> Self;
     fn apply(self, event: CfdEvent) -> Self;
     fn version(&self) -> u32;
+
+    /// Serialize the aggregate into a snapshot.
+    fn to_snapshot(&self) -> Result<String> {
+        bail!("Aggregate does not support snapshots")
+    }
+
+    /// Restore the aggregate from a snapshot of the same CFD.
+    fn restore_snapshot(&self, _snapshot: &str) -> Result<Self> {
+        bail!("Aggregate does not support snapshots")
+    }
+}
+
+/// Insert `event` and the data derived from it, returning the event's name.
+///
+/// Callers are expected to pass a transaction and only commit it if this succeeds.
+async fn insert_event(conn: &mut SqliteConnection, event: CfdEvent) -> Result<String> {
+    let (event_name, event_data) = event.event.to_json();
+
+    let order_id = models::OrderId::from(event.id);
+    let timestamp = models::Timestamp::from(event.timestamp);
+    let query_result = sqlx::query!(
+        r##"
+        insert into events (
+            cfd_id,
+            name,
+            data,
+            created_at
+        ) values (
+            (select id from cfds where cfds.order_id = $1),
+            $2, $3, $4
+        )"##,
+        order_id,
+        event_name,
+        event_data,
+        timestamp
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if query_result.rows_affected() != 1 {
+        bail!("failed to insert event");
+    }
+
+    match event.event {
+        // if we have a rollover completed event we store it additionally in its own table
+        RolloverCompleted {
+            dlc: Some(dlc),
+            funding_fee,
+            complete_fee,
+        } => {
+            rollover::overwrite(
+                &mut *conn,
+                query_result.last_insert_rowid(),
+                order_id,
+                dlc,
+                funding_fee,
+                complete_fee,
+            )
+            .await?;
+
+            // The funding history is informational, failing to record it must not fail the
+            // rollover
+            if let Err(e) =
+                funding::insert_funding_payment(&mut *conn, event.id, event.timestamp, funding_fee)
+                    .await
+            {
+                tracing::error!(order_id = %event.id, "Failed to record funding payment: {e:#}");
+            }
+        }
+        RolloverCompleted { dlc: None, .. } => {
+            tracing::error!(
+                "Invalid RolloverCompleted event: Trying to insert a RolloverCompleted event without a DLC"
+            )
+        }
+        _ => {}
+    }
+
+    Ok(event_name)
 }
 
 async fn load_cfd_row(conn: &mut SqliteConnection, id: OrderId) -> Result<Cfd, Error> {
@@ -583,7 +875,8 @@ async fn load_cfd_row(conn: &mut SqliteConnection, id: OrderId) -> Result<Cfd, E
                 opening_fee as "opening_fee: models::OpeningFee",
                 initial_funding_rate as "initial_funding_rate: models::FundingRate",
                 initial_tx_fee_rate as "initial_tx_fee_rate: models::TxFeeRate",
-                contract_symbol as "contract_symbol: models::ContractSymbol"
+                contract_symbol as "contract_symbol: models::ContractSymbol",
+                refund_timelock
             from
                 cfds
             where
@@ -595,6 +888,12 @@ async fn load_cfd_row(conn: &mut SqliteConnection, id: OrderId) -> Result<Cfd, E
     .await?
     .ok_or(Error::OpenCfdNotFound)?;
 
+    let refund_timelock = cfd_row
+        .refund_timelock
+        .map(u32::try_from)
+        .transpose()
+        .context("Refund timelock out of range")?;
+
     let role = cfd_row.role.into();
     let counterparty_network_identity = cfd_row.counterparty_network_identity.into();
     let counterparty_peer_id = if cfd_row.counterparty_peer_id
@@ -620,6 +919,7 @@ async fn load_cfd_row(conn: &mut SqliteConnection, id: OrderId) -> Result<Cfd, E
         initial_funding_rate: cfd_row.initial_funding_rate.into(),
         initial_tx_fee_rate: cfd_row.initial_tx_fee_rate.into(),
         contract_symbol: cfd_row.contract_symbol.into(),
+        refund_timelock,
     })
 }
 
@@ -650,6 +950,32 @@ fn derive_known_peer_id(legacy_network_identity: Identity, role: Role) -> Option
     }
 }
 
+/// The version of a CFD, i.e. the number of its events.
+async fn count_cfd_events(conn: &mut SqliteConnection, id: OrderId) -> Result<u32> {
+    let id = models::OrderId::from(id);
+
+    let n_events = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) as "count!: i64"
+        FROM
+            events
+        JOIN
+            cfds ON cfds.id = events.cfd_id
+        WHERE
+            cfds.order_id = $1
+        "#,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .count;
+
+    let version = u32::try_from(n_events).context("Number of events exceeds u32")?;
+
+    Ok(version)
+}
+
 /// Load events for a given CFD but only onwards from the specified version.
 ///
 /// The version of a CFD is the number of events that have been applied. If we have an aggregate
@@ -804,6 +1130,7 @@ mod tests {
             initial_funding_rate,
             initial_tx_fee_rate,
             contract_symbol,
+            refund_timelock,
         } = load_cfd_row(&mut *conn, cfd.id()).await.unwrap();
 
         assert_eq!(cfd.id(), id);
@@ -823,6 +1150,24 @@ mod tests {
         assert_eq!(cfd.initial_funding_rate(), initial_funding_rate);
         assert_eq!(cfd.initial_tx_fee_rate(), initial_tx_fee_rate);
         assert_eq!(cfd.contract_symbol(), contract_symbol);
+        assert_eq!(Some(cfd.refund_timelock_in_blocks()), refund_timelock);
+    }
+
+    #[tokio::test]
+    async fn given_inserted_cfd_then_order_parameters_are_loaded() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        let parameters_before = db.load_order_parameters(cfd.id()).await.unwrap();
+
+        db.insert_cfd(&cfd).await.unwrap();
+        let parameters_after = db.load_order_parameters(cfd.id()).await.unwrap();
+
+        assert_eq!(parameters_before, None);
+        assert_eq!(
+            parameters_after,
+            Some((cfd.offer_id(), cfd.quantity(), cfd.taker_leverage()))
+        );
     }
 
     #[tokio::test]
@@ -838,7 +1183,7 @@ mod tests {
         let event1 = CfdEvent {
             timestamp,
             id: cfd.id(),
-            event: EventKind::OfferRejected,
+            event: EventKind::OfferRejected(None),
         };
 
         db.append_event(event1.clone()).await.unwrap();
@@ -858,6 +1203,30 @@ mod tests {
         assert_eq!(events, vec![event1, event2])
     }
 
+    #[tokio::test]
+    async fn given_event_appended_since_version_then_conflict() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        db.append_event_at_version(lock_confirmed(&cfd), 0)
+            .await
+            .unwrap();
+        let result = db.append_event_at_version(lock_confirmed(&cfd), 0).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::VersionConflict {
+                expected: 0,
+                actual: 1
+            })
+        ));
+        db.append_event_at_version(lock_confirmed(&cfd), 1)
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn given_insert_cfd_with_peer_id_then_peer_id_loaded() {
         let db = memory().await.unwrap();
@@ -998,7 +1367,7 @@ mod tests {
         CfdEvent {
             timestamp: Timestamp::now(),
             id: cfd.id(),
-            event: EventKind::OfferRejected,
+            event: EventKind::OfferRejected(None),
         }
     }
 }
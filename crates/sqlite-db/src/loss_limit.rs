@@ -0,0 +1,144 @@
+//! The loss limit configured by the taker and its override.
+//!
+//! Both are persisted so that restarting the daemon neither lifts the
+//! limit nor forgets that the user chose to override it.
+
+use crate::Connection;
+use anyhow::Result;
+use bdk::bitcoin::Amount;
+use model::Timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LossLimit {
+    /// The maximum loss and the length of the window in seconds, if
+    /// the limit is enabled.
+    pub limit: Option<(Amount, i64)>,
+    pub override_until: Option<Timestamp>,
+}
+
+impl Connection {
+    /// Persist the loss limit, `None` disables it.
+    ///
+    /// An active override is kept.
+    pub async fn save_loss_limit(&self, limit: Option<(Amount, i64)>) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        let max_loss = limit.map(|(max_loss, _)| max_loss.as_sat() as i64);
+        let window_secs = limit.map(|(_, window_secs)| window_secs);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO loss_limit
+            (
+                id,
+                max_loss,
+                window_secs
+            )
+            VALUES (0, $1, $2)
+            ON CONFLICT(id) DO UPDATE SET
+                max_loss = excluded.max_loss,
+                window_secs = excluded.window_secs
+            "#,
+            max_loss,
+            window_secs
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Persist until when the loss limit is overridden.
+    pub async fn save_loss_limit_override(&self, until: Timestamp) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        let until = until.seconds();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO loss_limit
+            (
+                id,
+                override_until
+            )
+            VALUES (0, $1)
+            ON CONFLICT(id) DO UPDATE SET
+                override_until = excluded.override_until
+            "#,
+            until
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn load_loss_limit(&self) -> Result<LossLimit> {
+        let mut conn = self.inner.acquire().await?;
+
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                max_loss,
+                window_secs,
+                override_until
+            FROM
+                loss_limit
+            WHERE
+                id = 0
+            "#
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(LossLimit::default()),
+        };
+
+        let limit = match (row.max_loss, row.window_secs) {
+            (Some(max_loss), Some(window_secs)) => {
+                Some((Amount::from_sat(max_loss as u64), window_secs))
+            }
+            _ => None,
+        };
+
+        Ok(LossLimit {
+            limit,
+            override_until: row.override_until.map(Timestamp::new),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn given_nothing_saved_then_no_limit() {
+        let db = memory().await.unwrap();
+
+        assert_eq!(db.load_loss_limit().await.unwrap(), LossLimit::default());
+    }
+
+    #[tokio::test]
+    async fn given_override_then_disabling_limit_keeps_override() {
+        let db = memory().await.unwrap();
+
+        let until = Timestamp::new(1_000);
+        db.save_loss_limit(Some((Amount::from_sat(10_000), 3_600)))
+            .await
+            .unwrap();
+        db.save_loss_limit_override(until).await.unwrap();
+        db.save_loss_limit(None).await.unwrap();
+
+        assert_eq!(
+            db.load_loss_limit().await.unwrap(),
+            LossLimit {
+                limit: None,
+                override_until: Some(until),
+            }
+        );
+    }
+}
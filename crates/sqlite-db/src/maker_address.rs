@@ -0,0 +1,86 @@
+//! The address of the maker, if it was updated at runtime.
+//!
+//! The taker derives the address of the maker from its command line
+//! arguments. Once the address is updated at runtime it is persisted,
+//! and takes precedence over the command line arguments on subsequent
+//! starts.
+
+use crate::Connection;
+use anyhow::Context;
+use anyhow::Result;
+use libp2p_core::Multiaddr;
+
+impl Connection {
+    /// Persist the address of the maker, replacing any previous address.
+    pub async fn save_maker_address(&self, multiaddr: &Multiaddr) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let multiaddr = multiaddr.to_string();
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO maker_address
+            (
+                id,
+                multiaddr
+            )
+            VALUES (0, $1)
+            "#,
+            multiaddr
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the persisted address of the maker.
+    pub async fn load_maker_address(&self) -> Result<Option<Multiaddr>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let multiaddr = sqlx::query_scalar!(
+            r#"
+            SELECT
+                multiaddr
+            FROM
+                maker_address
+            WHERE
+                id = 0
+            "#
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        multiaddr
+            .map(|multiaddr| {
+                multiaddr
+                    .parse()
+                    .with_context(|| format!("Invalid maker address {multiaddr}"))
+            })
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn given_no_address_then_none() {
+        let db = memory().await.unwrap();
+
+        assert_eq!(db.load_maker_address().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn given_address_updated_then_latest_address_is_loaded() {
+        let db = memory().await.unwrap();
+
+        let old = "/ip4/127.0.0.1/tcp/10000".parse::<Multiaddr>().unwrap();
+        let new = "/ip4/127.0.0.2/tcp/10001".parse::<Multiaddr>().unwrap();
+        db.save_maker_address(&old).await.unwrap();
+        db.save_maker_address(&new).await.unwrap();
+
+        assert_eq!(db.load_maker_address().await.unwrap(), Some(new));
+    }
+}
@@ -0,0 +1,83 @@
+//! The identity of the maker the taker pinned on first use.
+//!
+//! The taker pins the libp2p peer id and the CFD identity of the maker
+//! the first time it starts and compares them to the maker it is
+//! configured to connect to on every subsequent start.
+
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::libp2p::PeerId;
+use model::Identity;
+
+impl Connection {
+    /// Pin the identity of the maker, replacing any previously pinned
+    /// identity.
+    pub async fn save_maker_identity(&self, peer_id: PeerId, identity: Identity) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let peer_id = models::PeerId::from(peer_id);
+        let identity = models::Identity::from(identity);
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO maker_identity
+            (
+                id,
+                peer_id,
+                identity
+            )
+            VALUES (0, $1, $2)
+            "#,
+            peer_id,
+            identity
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the pinned identity of the maker.
+    pub async fn load_maker_identity(&self) -> Result<Option<(PeerId, Identity)>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let pinned = sqlx::query!(
+            r#"
+            SELECT
+                peer_id as "peer_id: models::PeerId",
+                identity as "identity: models::Identity"
+            FROM
+                maker_identity
+            WHERE
+                id = 0
+            "#
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        Ok(pinned.map(|row| (row.peer_id.into(), row.identity.into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn given_identity_pinned_again_then_latest_identity_is_loaded() {
+        let db = memory().await.unwrap();
+        assert_eq!(db.load_maker_identity().await.unwrap(), None);
+
+        let identity = Identity::new(x25519_dalek::PublicKey::from([42u8; 32]));
+        let old = PeerId::random();
+        let new = PeerId::random();
+        db.save_maker_identity(old, identity).await.unwrap();
+        db.save_maker_identity(new, identity).await.unwrap();
+
+        assert_eq!(
+            db.load_maker_identity().await.unwrap(),
+            Some((new, identity))
+        );
+    }
+}
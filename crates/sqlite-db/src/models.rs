@@ -133,6 +133,33 @@ impl From<Role> for model::Role {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, sqlx::Type)]
+pub enum OfferTier {
+    Public,
+    Partner,
+    Internal,
+}
+
+impl From<model::OfferTier> for OfferTier {
+    fn from(tier: model::OfferTier) -> Self {
+        match tier {
+            model::OfferTier::Public => OfferTier::Public,
+            model::OfferTier::Partner => OfferTier::Partner,
+            model::OfferTier::Internal => OfferTier::Internal,
+        }
+    }
+}
+
+impl From<OfferTier> for model::OfferTier {
+    fn from(tier: OfferTier) -> Self {
+        match tier {
+            OfferTier::Public => model::OfferTier::Public,
+            OfferTier::Partner => model::OfferTier::Partner,
+            OfferTier::Internal => model::OfferTier::Internal,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct PublicKey(bitcoin::util::key::PublicKey);
 
@@ -868,6 +895,125 @@ impl From<FailedKind> for model::FailedKind {
 
 impl_sqlx_type_display_from_str!(FailedKind);
 
+/// Why the maker rejected an order.
+#[derive(Debug, Clone, Copy)]
+pub enum RejectReason {
+    InsufficientMakerBalance,
+    OfferOutdated,
+    QuantityOutOfBounds,
+    TakerBlocked,
+    TakerLimitExceeded,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            RejectReason::InsufficientMakerBalance => "InsufficientMakerBalance",
+            RejectReason::OfferOutdated => "OfferOutdated",
+            RejectReason::QuantityOutOfBounds => "QuantityOutOfBounds",
+            RejectReason::TakerBlocked => "TakerBlocked",
+            RejectReason::TakerLimitExceeded => "TakerLimitExceeded",
+        };
+
+        s.fmt(f)
+    }
+}
+
+impl FromStr for RejectReason {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let reason = match s {
+            "InsufficientMakerBalance" => RejectReason::InsufficientMakerBalance,
+            "OfferOutdated" => RejectReason::OfferOutdated,
+            "QuantityOutOfBounds" => RejectReason::QuantityOutOfBounds,
+            "TakerBlocked" => RejectReason::TakerBlocked,
+            "TakerLimitExceeded" => RejectReason::TakerLimitExceeded,
+            other => bail!("Not a reject reason: {other}"),
+        };
+
+        Ok(reason)
+    }
+}
+
+impl From<model::RejectReason> for RejectReason {
+    fn from(reason: model::RejectReason) -> Self {
+        match reason {
+            model::RejectReason::InsufficientMakerBalance => RejectReason::InsufficientMakerBalance,
+            model::RejectReason::OfferOutdated => RejectReason::OfferOutdated,
+            model::RejectReason::QuantityOutOfBounds => RejectReason::QuantityOutOfBounds,
+            model::RejectReason::TakerBlocked => RejectReason::TakerBlocked,
+            model::RejectReason::TakerLimitExceeded => RejectReason::TakerLimitExceeded,
+        }
+    }
+}
+
+impl From<RejectReason> for model::RejectReason {
+    fn from(reason: RejectReason) -> Self {
+        match reason {
+            RejectReason::InsufficientMakerBalance => model::RejectReason::InsufficientMakerBalance,
+            RejectReason::OfferOutdated => model::RejectReason::OfferOutdated,
+            RejectReason::QuantityOutOfBounds => model::RejectReason::QuantityOutOfBounds,
+            RejectReason::TakerBlocked => model::RejectReason::TakerBlocked,
+            RejectReason::TakerLimitExceeded => model::RejectReason::TakerLimitExceeded,
+        }
+    }
+}
+
+impl_sqlx_type_display_from_str!(RejectReason);
+
+/// Kinds of trigger prices which close a CFD automatically.
+#[derive(Debug, Clone, Copy)]
+pub enum PriceTrigger {
+    StopLoss,
+    TakeProfit,
+}
+
+impl fmt::Display for PriceTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            PriceTrigger::StopLoss => "StopLoss",
+            PriceTrigger::TakeProfit => "TakeProfit",
+        };
+
+        s.fmt(f)
+    }
+}
+
+impl FromStr for PriceTrigger {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trigger = match s {
+            "StopLoss" => PriceTrigger::StopLoss,
+            "TakeProfit" => PriceTrigger::TakeProfit,
+            other => bail!("Not a price trigger: {other}"),
+        };
+
+        Ok(trigger)
+    }
+}
+
+impl From<model::PriceTrigger> for PriceTrigger {
+    fn from(trigger: model::PriceTrigger) -> Self {
+        match trigger {
+            model::PriceTrigger::StopLoss => PriceTrigger::StopLoss,
+            model::PriceTrigger::TakeProfit => PriceTrigger::TakeProfit,
+        }
+    }
+}
+
+impl From<PriceTrigger> for model::PriceTrigger {
+    fn from(trigger: PriceTrigger) -> Self {
+        match trigger {
+            PriceTrigger::StopLoss => model::PriceTrigger::StopLoss,
+            PriceTrigger::TakeProfit => model::PriceTrigger::TakeProfit,
+        }
+    }
+}
+
+impl_sqlx_type_display_from_str!(PriceTrigger);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Settlement {
     Collaborative {
@@ -0,0 +1,106 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::libp2p::PeerId;
+use model::OfferTier;
+
+impl Connection {
+    /// Persist that `peer_id` is shown the offers of `tier`.
+    pub async fn insert_offer_tier(&self, peer_id: PeerId, tier: OfferTier) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let peer_id = models::PeerId::from(peer_id);
+        let tier = models::OfferTier::from(tier);
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO offer_tiers
+            (
+                peer_id,
+                tier
+            )
+            VALUES ($1, $2)
+            "#,
+            peer_id,
+            tier
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the tier of `peer_id`, so that it is shown the public
+    /// offers.
+    ///
+    /// Returns whether the peer was assigned to a tier.
+    pub async fn delete_offer_tier(&self, peer_id: PeerId) -> Result<bool> {
+        let mut conn = self.inner.acquire().await?;
+        let peer_id = models::PeerId::from(peer_id);
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM
+                offer_tiers
+            WHERE
+                peer_id = $1
+            "#,
+            peer_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn load_offer_tiers(&self) -> Result<Vec<(PeerId, OfferTier)>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                peer_id as "peer_id: models::PeerId",
+                tier as "tier: models::OfferTier"
+            FROM
+                offer_tiers
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let tiers = rows
+            .into_iter()
+            .map(|row| (row.peer_id.into(), row.tier.into()))
+            .collect();
+
+        Ok(tiers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn offer_tier_can_be_changed_and_removed() {
+        let db = memory().await.unwrap();
+        let partner = PeerId::random();
+        let removed = PeerId::random();
+
+        db.insert_offer_tier(partner, OfferTier::Internal)
+            .await
+            .unwrap();
+        db.insert_offer_tier(partner, OfferTier::Partner)
+            .await
+            .unwrap();
+        db.insert_offer_tier(removed, OfferTier::Internal)
+            .await
+            .unwrap();
+        let was_assigned = db.delete_offer_tier(removed).await.unwrap();
+
+        let tiers = db.load_offer_tiers().await.unwrap();
+
+        assert!(was_assigned);
+        assert_eq!(tiers, vec![(partner, OfferTier::Partner)]);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::XOnlyPublicKey;
+use model::olivia::Announcement;
+use model::olivia::Attestation;
+use model::olivia::BitMexPriceEventId;
+use std::str::FromStr;
+use time::OffsetDateTime;
+
+impl Connection {
+    /// Cache an announcement of the oracle until `expires_at`.
+    pub async fn insert_cached_announcement(
+        &self,
+        announcement: &Announcement,
+        expires_at: OffsetDateTime,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        let event_id = models::BitMexPriceEventId::from(announcement.id);
+        let expected_outcome_time = announcement.expected_outcome_time.unix_timestamp();
+        let nonce_pks = announcement
+            .nonce_pks
+            .iter()
+            .map(|pk| pk.to_string())
+            .collect::<Vec<_>>();
+        let nonce_pks = serde_json::to_string(&nonce_pks)?;
+        let expires_at = expires_at.unix_timestamp();
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO oracle_announcements
+            (
+                event_id,
+                expected_outcome_time,
+                nonce_pks,
+                expires_at
+            )
+            VALUES ($1, $2, $3, $4)
+            "#,
+            event_id,
+            expected_outcome_time,
+            nonce_pks,
+            expires_at
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load all cached announcements which have not expired at `now`.
+    pub async fn load_cached_announcements(
+        &self,
+        now: OffsetDateTime,
+    ) -> Result<Vec<Announcement>> {
+        let mut conn = self.inner.acquire().await?;
+        let now = now.unix_timestamp();
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                event_id as "event_id: models::BitMexPriceEventId",
+                expected_outcome_time,
+                nonce_pks
+            FROM
+                oracle_announcements
+            WHERE
+                expires_at > $1
+            "#,
+            now
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let announcements = rows
+            .into_iter()
+            .map(|row| {
+                let id = row.event_id;
+                let nonce_pks = serde_json::from_str::<Vec<String>>(&row.nonce_pks)?
+                    .iter()
+                    .map(|pk| XOnlyPublicKey::from_str(pk))
+                    .collect::<Result<Vec<_>, _>>()
+                    .with_context(|| format!("Invalid nonce public key in announcement {id}"))?;
+
+                Ok(Announcement {
+                    id: id.into(),
+                    expected_outcome_time: OffsetDateTime::from_unix_timestamp(
+                        row.expected_outcome_time,
+                    )?,
+                    nonce_pks,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(announcements)
+    }
+
+    /// Cache an attestation of the oracle until `expires_at`.
+    pub async fn insert_cached_attestation(
+        &self,
+        attestation: &Attestation,
+        expires_at: OffsetDateTime,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        let event_id = models::BitMexPriceEventId::from(attestation.id);
+        let price = i64::try_from(attestation.price)?;
+        let scalars = attestation
+            .scalars
+            .iter()
+            .map(|scalar| models::SecretKey::from(*scalar).to_string())
+            .collect::<Vec<_>>();
+        let scalars = serde_json::to_string(&scalars)?;
+        let expires_at = expires_at.unix_timestamp();
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO oracle_attestations
+            (
+                event_id,
+                price,
+                scalars,
+                expires_at
+            )
+            VALUES ($1, $2, $3, $4)
+            "#,
+            event_id,
+            price,
+            scalars,
+            expires_at
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the cached attestation of an event, unless it has expired at `now`.
+    pub async fn load_cached_attestation(
+        &self,
+        id: BitMexPriceEventId,
+        now: OffsetDateTime,
+    ) -> Result<Option<Attestation>> {
+        let mut conn = self.inner.acquire().await?;
+        let event_id = models::BitMexPriceEventId::from(id);
+        let now = now.unix_timestamp();
+
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                price,
+                scalars
+            FROM
+                oracle_attestations
+            WHERE
+                event_id = $1 AND expires_at > $2
+            "#,
+            event_id,
+            now
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let scalars = serde_json::from_str::<Vec<String>>(&row.scalars)?
+            .iter()
+            .map(|scalar| models::SecretKey::from_str(scalar).map(Into::into))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Invalid scalar in attestation {id}"))?;
+
+        Ok(Some(Attestation {
+            id,
+            price: u64::try_from(row.price)?,
+            scalars,
+        }))
+    }
+
+    /// Delete all cached announcements and attestations which have expired at `now`.
+    ///
+    /// Returns the number of deleted entries.
+    pub async fn delete_expired_oracle_cache(&self, now: OffsetDateTime) -> Result<u64> {
+        let mut conn = self.inner.acquire().await?;
+        let now = now.unix_timestamp();
+
+        let announcements = sqlx::query!(
+            r#"
+            DELETE FROM
+                oracle_announcements
+            WHERE
+                expires_at <= $1
+            "#,
+            now
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let attestations = sqlx::query!(
+            r#"
+            DELETE FROM
+                oracle_attestations
+            WHERE
+                expires_at <= $1
+            "#,
+            now
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(announcements.rows_affected() + attestations.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use maia_core::secp256k1_zkp::SecretKey;
+    use model::olivia::IndexPrice;
+    use time::macros::datetime;
+    use time::Duration;
+
+    const NONCE_PK: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const SCALAR: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+
+    fn event_id() -> BitMexPriceEventId {
+        BitMexPriceEventId::with_20_digits(
+            datetime!(2021-06-01 10:00:00).assume_utc(),
+            IndexPrice::Bxbt,
+        )
+    }
+
+    #[tokio::test]
+    async fn cached_announcement_is_loaded_until_it_expires() {
+        let db = memory().await.unwrap();
+        let id = event_id();
+        let announcement = Announcement {
+            id,
+            expected_outcome_time: id.timestamp(),
+            nonce_pks: vec![XOnlyPublicKey::from_str(NONCE_PK).unwrap()],
+        };
+
+        db.insert_cached_announcement(&announcement, id.timestamp())
+            .await
+            .unwrap();
+
+        let before_expiry = db
+            .load_cached_announcements(id.timestamp() - Duration::hours(1))
+            .await
+            .unwrap();
+        let after_expiry = db.load_cached_announcements(id.timestamp()).await.unwrap();
+
+        assert_eq!(before_expiry, vec![announcement]);
+        assert!(after_expiry.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_attestation_is_deleted() {
+        let db = memory().await.unwrap();
+        let id = event_id();
+        let attestation = Attestation {
+            id,
+            price: 30_000,
+            scalars: vec![SecretKey::from_str(SCALAR).unwrap()],
+        };
+        let expires_at = id.timestamp() + Duration::days(1);
+
+        db.insert_cached_attestation(&attestation, expires_at)
+            .await
+            .unwrap();
+
+        let cached = db
+            .load_cached_attestation(id, id.timestamp())
+            .await
+            .unwrap();
+        let deleted = db.delete_expired_oracle_cache(expires_at).await.unwrap();
+        let after_deletion = db
+            .load_cached_attestation(id, id.timestamp())
+            .await
+            .unwrap();
+
+        assert_eq!(cached, Some(attestation));
+        assert_eq!(deleted, 1);
+        assert_eq!(after_deletion, None);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::Connection;
+use anyhow::Result;
+use sqlx::Acquire;
+
+impl Connection {
+    /// Persist the given preferences, replacing the previous value of
+    /// every key.
+    ///
+    /// Values are stored as given, it is up to the caller to interpret
+    /// them. Keys which are not given keep their value.
+    pub async fn insert_preferences(&self, preferences: Vec<(String, String)>) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        for (key, value) in preferences {
+            sqlx::query!(
+                r#"
+                INSERT OR REPLACE INTO preferences
+                (
+                    key,
+                    value
+                )
+                VALUES ($1, $2)
+                "#,
+                key,
+                value
+            )
+            .execute(&mut db_tx)
+            .await?;
+        }
+
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn load_preferences(&self) -> Result<Vec<(String, String)>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                key,
+                value
+            FROM
+                preferences
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let preferences = rows.into_iter().map(|row| (row.key, row.value)).collect();
+
+        Ok(preferences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn preferences_are_replaced_per_key() {
+        let db = memory().await.unwrap();
+
+        db.insert_preferences(vec![
+            ("default_leverage".to_string(), "2".to_string()),
+            ("display_currency".to_string(), r#""EUR""#.to_string()),
+        ])
+        .await
+        .unwrap();
+        db.insert_preferences(vec![("default_leverage".to_string(), "3".to_string())])
+            .await
+            .unwrap();
+
+        let mut preferences = db.load_preferences().await.unwrap();
+        preferences.sort();
+
+        assert_eq!(
+            preferences,
+            vec![
+                ("default_leverage".to_string(), "3".to_string()),
+                ("display_currency".to_string(), r#""EUR""#.to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::OrderId;
+use model::Price;
+use model::PriceTrigger;
+use time::OffsetDateTime;
+
+/// A trigger price which closes a CFD automatically, i.e. a stop-loss or
+/// a take-profit order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceTriggerOrder {
+    pub order_id: OrderId,
+    pub trigger: PriceTrigger,
+    pub trigger_price: Price,
+    /// When the market price first reached the trigger price, if it has.
+    pub triggered_at: Option<OffsetDateTime>,
+}
+
+impl Connection {
+    /// Set the trigger price of a kind of `trigger` of a CFD, replacing any
+    /// previous one of the same kind.
+    pub async fn set_price_trigger(
+        &self,
+        order_id: OrderId,
+        trigger: PriceTrigger,
+        trigger_price: Price,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+        let trigger = models::PriceTrigger::from(trigger);
+        let trigger_price = models::Price::from(trigger_price);
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO price_triggers
+            (
+                order_id,
+                trigger,
+                trigger_price,
+                triggered_at
+            )
+            VALUES ($1, $2, $3, NULL)
+            "#,
+            order_id,
+            trigger,
+            trigger_price
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record that the market price reached the trigger price of a CFD.
+    pub async fn mark_price_triggered(
+        &self,
+        order_id: OrderId,
+        trigger: PriceTrigger,
+        triggered_at: OffsetDateTime,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+        let trigger = models::PriceTrigger::from(trigger);
+        let triggered_at = triggered_at.unix_timestamp();
+
+        sqlx::query!(
+            r#"
+            UPDATE
+                price_triggers
+            SET
+                triggered_at = $3
+            WHERE
+                order_id = $1 AND trigger = $2
+            "#,
+            order_id,
+            trigger,
+            triggered_at
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the trigger price of a kind of `trigger` of a CFD, if there
+    /// is one.
+    pub async fn delete_price_trigger(
+        &self,
+        order_id: OrderId,
+        trigger: PriceTrigger,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+        let trigger = models::PriceTrigger::from(trigger);
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                price_triggers
+            WHERE
+                order_id = $1 AND trigger = $2
+            "#,
+            order_id,
+            trigger
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove all trigger prices of a CFD.
+    pub async fn delete_price_triggers(&self, order_id: OrderId) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                price_triggers
+            WHERE
+                order_id = $1
+            "#,
+            order_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the trigger prices of all CFDs.
+    pub async fn load_price_triggers(&self) -> Result<Vec<PriceTriggerOrder>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId",
+                trigger as "trigger: models::PriceTrigger",
+                trigger_price as "trigger_price: models::Price",
+                triggered_at
+            FROM
+                price_triggers
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let price_triggers = rows
+            .into_iter()
+            .map(|row| {
+                Ok(PriceTriggerOrder {
+                    order_id: row.order_id.into(),
+                    trigger: row.trigger.into(),
+                    trigger_price: row.trigger_price.into(),
+                    triggered_at: row
+                        .triggered_at
+                        .map(OffsetDateTime::from_unix_timestamp)
+                        .transpose()?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(price_triggers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use rust_decimal_macros::dec;
+    use time::macros::datetime;
+
+    #[tokio::test]
+    async fn given_price_trigger_updated_then_latest_trigger_price_is_loaded() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        db.set_price_trigger(
+            order_id,
+            PriceTrigger::StopLoss,
+            Price::new(dec!(18_000)).unwrap(),
+        )
+        .await
+        .unwrap();
+        db.mark_price_triggered(
+            order_id,
+            PriceTrigger::StopLoss,
+            datetime!(2022-10-27 10:00:00).assume_utc(),
+        )
+        .await
+        .unwrap();
+        db.set_price_trigger(
+            order_id,
+            PriceTrigger::StopLoss,
+            Price::new(dec!(19_000)).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let price_triggers = db.load_price_triggers().await.unwrap();
+
+        assert_eq!(
+            price_triggers,
+            vec![PriceTriggerOrder {
+                order_id,
+                trigger: PriceTrigger::StopLoss,
+                trigger_price: Price::new(dec!(19_000)).unwrap(),
+                triggered_at: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn given_stop_loss_deleted_then_take_profit_is_still_loaded() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+        let triggered_at = datetime!(2022-10-27 10:00:00).assume_utc();
+
+        db.set_price_trigger(
+            order_id,
+            PriceTrigger::StopLoss,
+            Price::new(dec!(18_000)).unwrap(),
+        )
+        .await
+        .unwrap();
+        db.set_price_trigger(
+            order_id,
+            PriceTrigger::TakeProfit,
+            Price::new(dec!(21_000)).unwrap(),
+        )
+        .await
+        .unwrap();
+        db.mark_price_triggered(order_id, PriceTrigger::TakeProfit, triggered_at)
+            .await
+            .unwrap();
+        db.delete_price_trigger(order_id, PriceTrigger::StopLoss)
+            .await
+            .unwrap();
+
+        let price_triggers = db.load_price_triggers().await.unwrap();
+
+        assert_eq!(
+            price_triggers,
+            vec![PriceTriggerOrder {
+                order_id,
+                trigger: PriceTrigger::TakeProfit,
+                trigger_price: Price::new(dec!(21_000)).unwrap(),
+                triggered_at: Some(triggered_at),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn given_price_triggers_of_cfd_deleted_then_nothing_is_loaded() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        db.set_price_trigger(
+            order_id,
+            PriceTrigger::StopLoss,
+            Price::new(dec!(18_000)).unwrap(),
+        )
+        .await
+        .unwrap();
+        db.set_price_trigger(
+            order_id,
+            PriceTrigger::TakeProfit,
+            Price::new(dec!(21_000)).unwrap(),
+        )
+        .await
+        .unwrap();
+        db.delete_price_triggers(order_id).await.unwrap();
+
+        let price_triggers = db.load_price_triggers().await.unwrap();
+
+        assert!(price_triggers.is_empty());
+    }
+}
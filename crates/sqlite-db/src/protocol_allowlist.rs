@@ -0,0 +1,209 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::libp2p::PeerId;
+use sqlx::Acquire;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+impl Connection {
+    /// Persist that `protocol` is only negotiated with allowed peers.
+    pub async fn insert_restricted_protocol(&self, protocol: &str) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO restricted_protocols
+            (
+                protocol
+            )
+            VALUES ($1)
+            "#,
+            protocol
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Persist that `protocol` is negotiated with all peers again.
+    ///
+    /// Removes all peers allowed to negotiate the protocol.
+    pub async fn delete_restricted_protocol(&self, protocol: &str) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                protocol_allowlist
+            WHERE
+                protocol = $1
+            "#,
+            protocol
+        )
+        .execute(&mut *db_tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                restricted_protocols
+            WHERE
+                protocol = $1
+            "#,
+            protocol
+        )
+        .execute(&mut *db_tx)
+        .await?;
+
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Persist that `peer_id` is allowed to negotiate `protocol`.
+    ///
+    /// Restricts the protocol if it was not restricted so far.
+    pub async fn insert_allowed_peer(&self, protocol: &str, peer_id: PeerId) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+        let peer_id = models::PeerId::from(peer_id);
+
+        sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO restricted_protocols
+            (
+                protocol
+            )
+            VALUES ($1)
+            "#,
+            protocol
+        )
+        .execute(&mut *db_tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO protocol_allowlist
+            (
+                protocol,
+                peer_id
+            )
+            VALUES ($1, $2)
+            "#,
+            protocol,
+            peer_id
+        )
+        .execute(&mut *db_tx)
+        .await?;
+
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Persist that `peer_id` is no longer allowed to negotiate
+    /// `protocol`.
+    ///
+    /// The protocol stays restricted.
+    pub async fn delete_allowed_peer(&self, protocol: &str, peer_id: PeerId) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let peer_id = models::PeerId::from(peer_id);
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                protocol_allowlist
+            WHERE
+                protocol = $1 AND peer_id = $2
+            "#,
+            protocol,
+            peer_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the restricted protocols and the peers allowed to negotiate
+    /// them.
+    pub async fn load_restricted_protocols(&self) -> Result<HashMap<String, HashSet<PeerId>>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                restricted_protocols.protocol,
+                protocol_allowlist.peer_id as "peer_id?: models::PeerId"
+            FROM
+                restricted_protocols
+            LEFT JOIN
+                protocol_allowlist ON protocol_allowlist.protocol = restricted_protocols.protocol
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut restricted_protocols = HashMap::<_, HashSet<_>>::new();
+        for row in rows {
+            let allowed_peers = restricted_protocols.entry(row.protocol).or_default();
+            if let Some(peer_id) = row.peer_id {
+                allowed_peers.insert(peer_id.into());
+            }
+        }
+
+        Ok(restricted_protocols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    const EXPERIMENTAL: &str = "/experimental/1.0.0";
+    const STABLE: &str = "/stable/1.0.0";
+
+    #[tokio::test]
+    async fn restricted_protocols_are_loaded_with_allowed_peers() {
+        let db = memory().await.unwrap();
+        let friend = PeerId::random();
+        let former_friend = PeerId::random();
+
+        db.insert_allowed_peer(EXPERIMENTAL, friend).await.unwrap();
+        db.insert_allowed_peer(EXPERIMENTAL, former_friend)
+            .await
+            .unwrap();
+        db.delete_allowed_peer(EXPERIMENTAL, former_friend)
+            .await
+            .unwrap();
+        db.insert_restricted_protocol(STABLE).await.unwrap();
+
+        let restricted_protocols = db.load_restricted_protocols().await.unwrap();
+
+        assert_eq!(
+            restricted_protocols,
+            HashMap::from([
+                (EXPERIMENTAL.to_owned(), HashSet::from([friend])),
+                (STABLE.to_owned(), HashSet::new()),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn released_protocol_is_no_longer_restricted() {
+        let db = memory().await.unwrap();
+
+        db.insert_allowed_peer(EXPERIMENTAL, PeerId::random())
+            .await
+            .unwrap();
+        db.delete_restricted_protocol(EXPERIMENTAL).await.unwrap();
+
+        let restricted_protocols = db.load_restricted_protocols().await.unwrap();
+
+        assert!(restricted_protocols.is_empty());
+    }
+}
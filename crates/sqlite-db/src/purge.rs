@@ -0,0 +1,286 @@
+//! Erasing what we know about a counterparty.
+//!
+//! Once all CFDs with a counterparty are closed or failed, the user
+//! may want to forget about that counterparty. Purging replaces the
+//! counterparty's identifiers on all archived CFDs with placeholders
+//! and deletes the remaining metadata we keep about them. The
+//! financial record of each CFD, i.e. its terms, fees, settlement
+//! transactions and event log, is retained.
+
+use crate::models;
+use crate::Connection;
+use anyhow::ensure;
+use anyhow::Result;
+use model::libp2p::PeerId;
+use model::Identity;
+use sqlx::Acquire;
+
+impl Connection {
+    /// Erase all data associated with the counterparty identified by
+    /// `identity`.
+    ///
+    /// Fails if there are still open CFDs with the counterparty.
+    /// Returns the number of archived CFDs that were anonymised.
+    pub async fn purge_counterparty(&self, identity: Identity) -> Result<u64> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let identity = models::Identity::from(identity);
+        let purged_identity =
+            models::Identity::from(Identity::new(x25519_dalek::PublicKey::from([0u8; 32])));
+        let purged_peer_id = models::PeerId::from(PeerId::placeholder());
+
+        let n_open_cfds = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) as "count!: i64"
+            FROM
+                cfds
+            WHERE
+                counterparty_network_identity = $1
+            "#,
+            identity
+        )
+        .fetch_one(&mut db_tx)
+        .await?
+        .count;
+
+        ensure!(
+            n_open_cfds == 0,
+            "Cannot purge counterparty {identity} with {n_open_cfds} open CFDs"
+        );
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                rollover_policies
+            WHERE
+                order_id IN (
+                    SELECT order_id FROM closed_cfds WHERE counterparty_network_identity = $1
+                    UNION
+                    SELECT order_id FROM failed_cfds WHERE counterparty_network_identity = $1
+                )
+            "#,
+            identity
+        )
+        .execute(&mut db_tx)
+        .await?;
+
+        // The peer IDs are only known through the CFDs, so the metadata
+        // keyed by peer ID has to go before the CFDs are anonymised
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                blocked_peers
+            WHERE
+                peer_id IN (
+                    SELECT counterparty_peer_id FROM closed_cfds
+                    WHERE counterparty_network_identity = $1
+                    UNION
+                    SELECT counterparty_peer_id FROM failed_cfds
+                    WHERE counterparty_network_identity = $1
+                )
+            "#,
+            identity
+        )
+        .execute(&mut db_tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                taker_records
+            WHERE
+                peer_id IN (
+                    SELECT counterparty_peer_id FROM closed_cfds
+                    WHERE counterparty_network_identity = $1
+                    UNION
+                    SELECT counterparty_peer_id FROM failed_cfds
+                    WHERE counterparty_network_identity = $1
+                )
+            "#,
+            identity
+        )
+        .execute(&mut db_tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                protocol_allowlist
+            WHERE
+                peer_id IN (
+                    SELECT counterparty_peer_id FROM closed_cfds
+                    WHERE counterparty_network_identity = $1
+                    UNION
+                    SELECT counterparty_peer_id FROM failed_cfds
+                    WHERE counterparty_network_identity = $1
+                )
+            "#,
+            identity
+        )
+        .execute(&mut db_tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                watchtower_blobs
+            WHERE
+                peer_id IN (
+                    SELECT counterparty_peer_id FROM closed_cfds
+                    WHERE counterparty_network_identity = $1
+                    UNION
+                    SELECT counterparty_peer_id FROM failed_cfds
+                    WHERE counterparty_network_identity = $1
+                )
+            "#,
+            identity
+        )
+        .execute(&mut db_tx)
+        .await?;
+
+        let n_purged_closed = sqlx::query!(
+            r#"
+            UPDATE
+                closed_cfds
+            SET
+                counterparty_network_identity = $1,
+                counterparty_peer_id = $2
+            WHERE
+                counterparty_network_identity = $3
+            "#,
+            purged_identity,
+            purged_peer_id,
+            identity
+        )
+        .execute(&mut db_tx)
+        .await?
+        .rows_affected();
+
+        let n_purged_failed = sqlx::query!(
+            r#"
+            UPDATE
+                failed_cfds
+            SET
+                counterparty_network_identity = $1,
+                counterparty_peer_id = $2
+            WHERE
+                counterparty_network_identity = $3
+            "#,
+            purged_identity,
+            purged_peer_id,
+            identity
+        )
+        .execute(&mut db_tx)
+        .await?
+        .rows_affected();
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                time_to_first_position
+            WHERE
+                taker_id = $1
+            "#,
+            identity
+        )
+        .execute(&mut db_tx)
+        .await?;
+
+        db_tx.commit().await?;
+
+        Ok(n_purged_closed + n_purged_failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory;
+    use crate::taker_records::TakerOutcome;
+    use crate::taker_records::TakerRecord;
+    use crate::tests::dummy_cfd;
+    use crate::tests::dummy_taker_with_counterparty_peer_id;
+    use crate::tests::setup_failed;
+    use crate::time_to_first_position::sqlx_test_utils::load_first_seen_timestamp;
+    use crate::watchtower::WatchtowerBlob;
+    use bdk::bitcoin::Script;
+    use std::collections::HashMap;
+    use std::collections::HashSet;
+    use time::OffsetDateTime;
+
+    #[tokio::test]
+    async fn cannot_purge_counterparty_with_open_cfd() {
+        let db = memory().await.unwrap();
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let result = db
+            .purge_counterparty(cfd.counterparty_network_identity())
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn purging_unknown_counterparty_removes_metadata() {
+        let db = memory().await.unwrap();
+        let identity = dummy_cfd().counterparty_network_identity();
+
+        db.try_insert_first_seen(identity, OffsetDateTime::now_utc())
+            .await
+            .unwrap();
+
+        let n_purged = db.purge_counterparty(identity).await.unwrap();
+
+        let mut conn = db.inner.acquire().await.unwrap();
+        assert_eq!(n_purged, 0);
+        assert_eq!(
+            load_first_seen_timestamp(&mut conn, identity)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn purging_counterparty_removes_metadata_keyed_by_peer_id() {
+        let db = memory().await.unwrap();
+        let cfd = dummy_taker_with_counterparty_peer_id();
+        let peer_id = cfd.counterparty_peer_id().unwrap();
+        db.insert_cfd(&cfd).await.unwrap();
+        db.record_taker_outcome(cfd.id(), TakerOutcome::SetupAborted)
+            .await
+            .unwrap();
+        db.insert_blocked_peer(peer_id).await.unwrap();
+        db.insert_allowed_peer("/experimental/1.0.0", peer_id)
+            .await
+            .unwrap();
+        db.save_watchtower_blob(&WatchtowerBlob {
+            hint: Script::from(vec![0u8; 34]),
+            peer_id,
+            ciphertext: "blob".to_owned(),
+        })
+        .await
+        .unwrap();
+        db.append_event(setup_failed(&cfd)).await.unwrap();
+        db.move_to_failed_cfds().await.unwrap();
+
+        let n_purged = db
+            .purge_counterparty(cfd.counterparty_network_identity())
+            .await
+            .unwrap();
+
+        assert_eq!(n_purged, 1);
+        assert_eq!(db.load_blocked_peers().await.unwrap(), vec![]);
+        assert_eq!(
+            db.load_taker_record(peer_id).await.unwrap(),
+            TakerRecord::default()
+        );
+        assert_eq!(db.count_watchtower_blobs(peer_id).await.unwrap(), 0);
+        assert_eq!(
+            db.load_restricted_protocols().await.unwrap(),
+            HashMap::from([("/experimental/1.0.0".to_owned(), HashSet::new())])
+        );
+    }
+}
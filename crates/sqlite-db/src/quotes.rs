@@ -0,0 +1,153 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::ContractSymbol;
+use model::Price;
+use model::Timestamp;
+
+/// A quote of the price feed, sampled at `timestamp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampledQuote {
+    pub timestamp: Timestamp,
+    pub bid: Price,
+    pub ask: Price,
+}
+
+impl Connection {
+    pub async fn insert_quote(
+        &self,
+        contract_symbol: ContractSymbol,
+        quote: SampledQuote,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let contract_symbol = models::ContractSymbol::from(contract_symbol);
+        let timestamp = quote.timestamp.seconds();
+        let bid = models::Price::from(quote.bid);
+        let ask = models::Price::from(quote.ask);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO quotes
+            (
+                contract_symbol,
+                timestamp,
+                bid,
+                ask
+            )
+            VALUES ($1, $2, $3, $4)
+            "#,
+            contract_symbol,
+            timestamp,
+            bid,
+            ask
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the quotes of `contract_symbol` sampled at or after `since`,
+    /// oldest first.
+    pub async fn load_quotes(
+        &self,
+        contract_symbol: ContractSymbol,
+        since: Timestamp,
+    ) -> Result<Vec<SampledQuote>> {
+        let mut conn = self.inner.acquire().await?;
+        let contract_symbol = models::ContractSymbol::from(contract_symbol);
+        let since = since.seconds();
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                timestamp,
+                bid as "bid: models::Price",
+                ask as "ask: models::Price"
+            FROM
+                quotes
+            WHERE
+                contract_symbol = $1 AND timestamp >= $2
+            ORDER BY
+                timestamp, id
+            "#,
+            contract_symbol,
+            since
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let quotes = rows
+            .into_iter()
+            .map(|row| SampledQuote {
+                timestamp: Timestamp::new(row.timestamp),
+                bid: row.bid.into(),
+                ask: row.ask.into(),
+            })
+            .collect();
+
+        Ok(quotes)
+    }
+
+    /// Delete all quotes sampled before `before`.
+    ///
+    /// Returns how many quotes were deleted.
+    pub async fn delete_quotes_before(&self, before: Timestamp) -> Result<u64> {
+        let mut conn = self.inner.acquire().await?;
+        let before = before.seconds();
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM
+                quotes
+            WHERE
+                timestamp < $1
+            "#,
+            before
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use rust_decimal_macros::dec;
+
+    fn quote(timestamp: i64) -> SampledQuote {
+        SampledQuote {
+            timestamp: Timestamp::new(timestamp),
+            bid: Price::new(dec!(19_999.5)).unwrap(),
+            ask: Price::new(dec!(20_000.5)).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn quotes_of_symbol_since_timestamp_are_loaded_in_order() {
+        let db = memory().await.unwrap();
+
+        for (contract_symbol, timestamp) in [
+            (ContractSymbol::BtcUsd, 3),
+            (ContractSymbol::BtcUsd, 1),
+            (ContractSymbol::BtcUsd, 2),
+            (ContractSymbol::EthUsd, 2),
+        ] {
+            db.insert_quote(contract_symbol, quote(timestamp))
+                .await
+                .unwrap();
+        }
+        let deleted = db.delete_quotes_before(Timestamp::new(2)).await.unwrap();
+
+        let quotes = db
+            .load_quotes(ContractSymbol::BtcUsd, Timestamp::new(0))
+            .await
+            .unwrap();
+
+        assert_eq!(deleted, 1);
+        assert_eq!(quotes, vec![quote(2), quote(3)]);
+    }
+}
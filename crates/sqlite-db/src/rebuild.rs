@@ -0,0 +1,219 @@
+//! Rebuilding the derived state of an aggregate from the event store.
+//!
+//! The snapshots and the cached aggregates are derived from the events
+//! of the open CFDs. If an aggregate had a bug, they carry its results
+//! forward even once the bug is fixed. [`Connection::rebuild_open_cfds`]
+//! rehydrates all open CFDs from their complete event history, leaving
+//! the snapshots and the cache untouched while doing so, and only then
+//! replaces them with the rebuilt aggregates. Until then, loading CFDs
+//! keeps working off the old state.
+//!
+//! Events appended while the rebuild is running are not lost: a rebuilt
+//! aggregate remembers its version, so loading it applies the events
+//! after it.
+
+use crate::load_cfd_events;
+use crate::load_cfd_row;
+use crate::snapshot;
+use crate::CfdAggregate;
+use crate::Connection;
+use crate::Error;
+use anyhow::Context;
+use anyhow::Result;
+use futures::StreamExt;
+use model::OrderId;
+use serde::Serialize;
+use sqlx::Acquire;
+use std::any::TypeId;
+use std::sync::atomic::Ordering;
+
+/// The outcome of rebuilding an aggregate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    /// How many open CFDs were rebuilt
+    pub rebuilt: usize,
+    /// How many open CFDs failed to be rebuilt
+    ///
+    /// These are dropped from the cache and loaded from their events
+    /// the next time.
+    pub failed: usize,
+}
+
+impl Connection {
+    /// Rebuild all open CFDs as `C` from their events, then replace the
+    /// snapshots and the cached aggregates of `C` with the rebuilt ones.
+    ///
+    /// Up to [`crate::REHYDRATION_PARALLELISM`] CFDs are rebuilt at a
+    /// time.
+    pub async fn rebuild_open_cfds<C>(&self, args: C::CtorArgs) -> Result<Summary>
+    where
+        C: CfdAggregate,
+        C::CtorArgs: Clone + Send + Sync,
+    {
+        let aggregate = std::any::type_name::<C>();
+        let ids = self.load_open_cfd_ids().await?;
+
+        let mut cfds = futures::stream::iter(ids)
+            .map(|id| {
+                let args = args.clone();
+                async move { (id, self.rebuild_open_cfd::<C>(id, args).await) }
+            })
+            .buffered(crate::REHYDRATION_PARALLELISM);
+
+        let mut rebuilt = Vec::new();
+        let mut summary = Summary::default();
+
+        while let Some((id, res)) = cfds.next().await {
+            match res {
+                Ok(cfd) => {
+                    rebuilt.push((id, cfd));
+                    summary.rebuilt += 1;
+                }
+                // The CFD was closed in the meantime
+                Err(Error::OpenCfdNotFound) => {}
+                Err(e) => {
+                    tracing::error!(order_id = %id, %aggregate, "Failed to rebuild CFD: {e:#}");
+                    summary.failed += 1;
+                }
+            }
+        }
+
+        self.replace_aggregates(rebuilt)
+            .await
+            .with_context(|| format!("Failed to replace rebuilt {aggregate}"))?;
+
+        tracing::info!(
+            %aggregate,
+            rebuilt = summary.rebuilt,
+            failed = summary.failed,
+            "Rebuilt open CFDs from their events"
+        );
+
+        Ok(summary)
+    }
+
+    /// Rehydrate the open CFD with `id` from all of its events, bypassing
+    /// the cache and the snapshots.
+    async fn rebuild_open_cfd<C>(&self, id: OrderId, args: C::CtorArgs) -> Result<C, Error>
+    where
+        C: CfdAggregate,
+    {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let cfd = load_cfd_row(&mut db_tx, id).await?;
+        let cfd = C::new(args, cfd);
+
+        let events = load_cfd_events(&mut db_tx, id, 0)
+            .await
+            .with_context(|| format!("Could not load events for CFD {id}"))?;
+
+        db_tx.commit().await?;
+
+        Ok(events.into_iter().fold(cfd, C::apply))
+    }
+
+    /// Replace all snapshots and cached aggregates of `C` with `cfds`.
+    async fn replace_aggregates<C>(&self, cfds: Vec<(OrderId, C)>) -> Result<()>
+    where
+        C: CfdAggregate,
+    {
+        // Loads which are still working off the old state must not
+        // cache their result, see `Connection::load_open_cfd`
+        self.aggregate_generation.fetch_add(1, Ordering::SeqCst);
+
+        if let Some(aggregate) = C::SNAPSHOT {
+            let mut conn = self.inner.acquire().await?;
+            let mut db_tx = conn.begin().await?;
+
+            sqlx::query!(
+                r#"
+                DELETE FROM
+                    cfd_snapshots
+                WHERE
+                    aggregate = $1
+                "#,
+                aggregate
+            )
+            .execute(&mut *db_tx)
+            .await?;
+
+            for (id, cfd) in cfds.iter() {
+                if snapshot::is_due(0, cfd.version()) {
+                    snapshot::save(&mut db_tx, *id, cfd).await?;
+                }
+            }
+
+            db_tx.commit().await?;
+        }
+
+        let type_id = TypeId::of::<C>();
+        self.aggregate_cache.retain(|(t, _), _| *t != type_id);
+
+        for (id, cfd) in cfds {
+            self.aggregate_cache.insert((type_id, id), Box::new(cfd));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use crate::tests::dummy_cfd;
+    use model::CfdEvent;
+    use model::EventKind;
+    use model::Timestamp;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn given_broken_snapshot_when_rebuilding_then_snapshot_is_replaced() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        let order_id = cfd.id();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        for _ in 0..snapshot::INTERVAL + 5 {
+            db.append_event(CfdEvent {
+                timestamp: Timestamp::now(),
+                id: order_id,
+                event: EventKind::RevokeConfirmed,
+            })
+            .await
+            .unwrap();
+        }
+
+        let loaded = db.load_open_cfd::<model::Cfd>(order_id, ()).await.unwrap();
+
+        {
+            let mut conn = db.inner.acquire().await.unwrap();
+            sqlx::query!("UPDATE cfd_snapshots SET data = 'broken'")
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+        }
+
+        let summary = db.rebuild_open_cfds::<model::Cfd>(()).await.unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                rebuilt: 1,
+                failed: 0
+            }
+        );
+
+        let mut conn = db.inner.acquire().await.unwrap();
+        let snapshot = sqlx::query_scalar!("SELECT data FROM cfd_snapshots")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        let restored = loaded.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(restored, loaded);
+
+        let cached = db.load_open_cfd::<model::Cfd>(order_id, ()).await.unwrap();
+        assert_eq!(cached, loaded);
+    }
+}
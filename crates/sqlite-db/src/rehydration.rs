@@ -0,0 +1,115 @@
+//! Rehydration of the open CFDs on startup.
+//!
+//! Rehydrating a CFD means applying all of its events to the aggregate,
+//! which takes a while for CFDs with long histories. Rehydrated
+//! aggregates are cached, so rehydrating all open CFDs up front with
+//! [`Connection::rehydrate_open_cfds`] means that loading them later
+//! only applies the events which have been appended since.
+
+use crate::CfdAggregate;
+use crate::Connection;
+use crate::Error;
+use anyhow::Result;
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::sync::watch;
+
+/// How far the rehydration of the open CFDs has progressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    /// How many CFDs have been rehydrated, including the ones which
+    /// failed to be rehydrated
+    pub rehydrated: usize,
+    /// How many CFDs failed to be rehydrated
+    pub failed: usize,
+    /// How many open CFDs there are, once they have been counted
+    pub total: Option<usize>,
+}
+
+impl Progress {
+    pub fn is_complete(&self) -> bool {
+        self.total == Some(self.rehydrated)
+    }
+}
+
+impl Connection {
+    /// Rehydrate all open CFDs as `C`, reporting the progress on
+    /// `progress`.
+    ///
+    /// Up to [`crate::REHYDRATION_PARALLELISM`] CFDs are rehydrated at a
+    /// time. CFDs which fail to be rehydrated are skipped.
+    pub async fn rehydrate_open_cfds<C>(
+        &self,
+        args: C::CtorArgs,
+        progress: &watch::Sender<Progress>,
+    ) -> Result<Progress>
+    where
+        C: CfdAggregate,
+        C::CtorArgs: Clone + Send + Sync,
+    {
+        let ids = self.load_open_cfd_ids().await?;
+
+        progress.send_replace(Progress {
+            rehydrated: 0,
+            failed: 0,
+            total: Some(ids.len()),
+        });
+
+        let mut cfds = self.load_open_cfds_concurrently::<C>(ids, args);
+
+        while let Some((id, res)) = cfds.next().await {
+            let failed = match res {
+                Ok(_) | Err(Error::OpenCfdNotFound) => false,
+                Err(e) => {
+                    tracing::warn!(order_id = %id, "Failed to rehydrate CFD: {e:#}");
+                    true
+                }
+            };
+
+            progress.send_modify(|progress| {
+                progress.rehydrated += 1;
+                progress.failed += usize::from(failed);
+            });
+        }
+
+        Ok(*progress.borrow())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use crate::tests::dummy_cfd;
+    use crate::tests::lock_confirmed;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn given_open_cfds_when_rehydrating_then_progress_is_complete() {
+        let db = memory().await.unwrap();
+
+        for _ in 0..10 {
+            let cfd = dummy_cfd();
+            db.insert_cfd(&cfd).await.unwrap();
+            db.append_event(lock_confirmed(&cfd)).await.unwrap();
+        }
+
+        let (sender, receiver) = watch::channel(Progress::default());
+        assert!(!receiver.borrow().is_complete());
+
+        let progress = db
+            .rehydrate_open_cfds::<model::Cfd>((), &sender)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            progress,
+            Progress {
+                rehydrated: 10,
+                failed: 0,
+                total: Some(10),
+            }
+        );
+        assert!(receiver.borrow().is_complete());
+    }
+}
@@ -34,6 +34,7 @@ mod tests {
     use model::Timestamp;
     use model::TxFeeRate;
     use rust_decimal_macros::dec;
+    use sqlx::Acquire;
     use sqlx::SqliteConnection;
     use time::macros::datetime;
     use time::Duration;
@@ -286,6 +287,119 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn given_interrupted_rollover_then_previous_rollover_is_kept() -> Result<()> {
+        let db = memory().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+        let timestamp = Timestamp::now();
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+        let first_rollover_completed = CfdEvent {
+            timestamp,
+            id: cfd.id(),
+            event: event.clone(),
+        };
+        db.append_event(first_rollover_completed.clone()).await?;
+
+        let second_rollover_completed = update_event_id(
+            timestamp,
+            event,
+            datetime!(2021-06-01 10:00:00).assume_utc(),
+            cfd.id(),
+            cfd.contract_symbol(),
+        )?;
+
+        // Simulate the process dying after the second rollover was
+        // written, but before the transaction was committed
+        {
+            let mut conn = db.inner.acquire().await?;
+            let mut db_tx = conn.begin().await?;
+            crate::insert_event(&mut db_tx, second_rollover_completed).await?;
+            drop(db_tx);
+        }
+
+        let mut conn = db.inner.acquire().await?;
+        let order_id = models::OrderId::from(cfd.id());
+        let cfd_row_id = sqlx::query!(r#"select id from cfds where order_id = $1"#, order_id)
+            .fetch_one(&mut *conn)
+            .await?
+            .id
+            .unwrap();
+
+        let (loaded_dlc, ..) = load(&mut *conn, cfd_row_id, 1)
+            .await?
+            .context("Expect to find data of first rollover")?;
+        let (first_dlc, ..) = extract_rollover_completed_data(first_rollover_completed.event);
+
+        assert_eq!(
+            loaded_dlc.settlement_event_id,
+            first_dlc.settlement_event_id
+        );
+        assert_eq!(count_events(&mut *conn).await, 1);
+        assert_eq!(count_table_entries(&mut *conn).await, (1, 2, 2));
+        assert_eq!(db.load_funding_history(cfd.id()).await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_rollover_fails_at_last_step_then_nothing_is_persisted() -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+        let rollover_completed = CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event,
+        };
+
+        // Inserting the CETs is the last step of persisting a rollover
+        // which may fail it, make it fail
+        sqlx::query(
+            r#"
+            CREATE TRIGGER fail_open_cets BEFORE INSERT ON open_cets
+            BEGIN
+                SELECT RAISE(ABORT, 'failed to insert CET');
+            END
+            "#,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let result = db.append_event(rollover_completed).await;
+
+        assert!(result.is_err());
+        assert_eq!(count_events(&mut *conn).await, 0);
+        assert_eq!(
+            count_rows(&mut *conn, "rollover_completed_event_data").await,
+            0
+        );
+        assert_eq!(
+            count_rows(&mut *conn, "revoked_commit_transactions").await,
+            0
+        );
+        assert_eq!(count_rows(&mut *conn, "open_cets").await, 0);
+
+        Ok(())
+    }
+
+    async fn count_events(conn: &mut SqliteConnection) -> i64 {
+        count_rows(conn, "events").await
+    }
+
+    async fn count_rows(conn: &mut SqliteConnection, table: &str) -> i64 {
+        sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {table}"))
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap()
+    }
+
     async fn count_table_entries(conn: &mut SqliteConnection) -> (i32, i32, i32) {
         let row = sqlx::query!(
             r#"
@@ -5,7 +5,9 @@ use bdk::bitcoin::hashes::hex::FromHex;
 use bdk::bitcoin::secp256k1;
 use bdk::bitcoin::Address;
 use bdk::bitcoin::Amount;
+use bdk::bitcoin::PublicKey;
 use bdk::bitcoin::Script;
+use bdk::bitcoin::Txid;
 use bdk::descriptor::Descriptor;
 use model::olivia::BitMexPriceEventId;
 use model::Cet;
@@ -116,7 +118,7 @@ async fn load_revoked_commit_transactions(
     conn: &mut SqliteConnection,
     cfd_row_id: i64,
 ) -> Result<Vec<RevokedCommit>> {
-    let revoked_commit = sqlx::query!(
+    let mut revoked_commit = sqlx::query!(
         r#"
             SELECT
                 encsig_ours as "encsig_ours: models::AdaptorSignature",
@@ -153,12 +155,46 @@ async fn load_revoked_commit_transactions(
                 .settlement_event_id
                 .map(|settlement_event_id| settlement_event_id.into()),
             complete_fee: into_complete_fee(row.complete_fee_flow, row.complete_fee),
+            commit_descriptor: None,
         })
     })
     .collect::<Result<Vec<_>>>()?;
+
+    for revoked in revoked_commit.iter_mut() {
+        revoked.commit_descriptor = load_commit_descriptor(&mut *conn, revoked.txid).await?;
+    }
+
     Ok(revoked_commit)
 }
 
+/// Load the descriptor of the revoked commit transaction with `txid`, if
+/// it was recorded.
+async fn load_commit_descriptor(
+    conn: &mut SqliteConnection,
+    txid: Txid,
+) -> Result<Option<Descriptor<PublicKey>>> {
+    let txid = models::Txid::from(txid);
+
+    let descriptor = sqlx::query_scalar!(
+        r#"
+        SELECT
+            descriptor
+        FROM
+            revoked_commit_descriptors
+        WHERE
+            txid = $1
+        "#,
+        txid
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    descriptor
+        .map(|descriptor| Descriptor::from_str(descriptor.as_str()))
+        .transpose()
+        .map_err(Into::into)
+}
+
 async fn load_cets(
     conn: &mut SqliteConnection,
     cfd_row_id: i64,
@@ -212,6 +212,26 @@ async fn insert_revoked_commit_transaction(
     if query_result.rows_affected() != 1 {
         bail!("failed to insert revoked transaction data");
     }
+
+    if let Some(commit_descriptor) = revoked.commit_descriptor {
+        let commit_descriptor = commit_descriptor.to_string();
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO revoked_commit_descriptors
+            (
+                txid,
+                descriptor
+            )
+            VALUES ($1, $2)
+            "#,
+            txid,
+            commit_descriptor
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
     Ok(())
 }
 
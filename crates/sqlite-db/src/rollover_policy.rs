@@ -0,0 +1,101 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Context;
+use anyhow::Result;
+use model::OrderId;
+use model::RolloverPolicy;
+
+impl Connection {
+    /// Set the rollover policy of a CFD, replacing any previous policy.
+    pub async fn set_rollover_policy(
+        &self,
+        order_id: OrderId,
+        policy: RolloverPolicy,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        let id = models::OrderId::from(order_id);
+        let policy = serde_json::to_string(&policy)?;
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO rollover_policies
+            (
+                order_id,
+                policy
+            )
+            VALUES ($1, $2)
+            "#,
+            id,
+            policy
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the rollover policy of a CFD.
+    ///
+    /// Returns `None` for CFDs without an explicit policy, which use the
+    /// default policy.
+    pub async fn load_rollover_policy(&self, order_id: OrderId) -> Result<Option<RolloverPolicy>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let id = models::OrderId::from(order_id);
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                policy
+            FROM
+                rollover_policies
+            WHERE
+                order_id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        row.map(|row| {
+            serde_json::from_str(&row.policy)
+                .with_context(|| format!("Invalid rollover policy for CFD {order_id}"))
+        })
+        .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use model::FundingRate;
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn given_no_policy_then_no_policy_is_loaded() {
+        let db = memory().await.unwrap();
+
+        let policy = db.load_rollover_policy(OrderId::default()).await.unwrap();
+
+        assert_eq!(policy, None);
+    }
+
+    #[tokio::test]
+    async fn given_policy_updated_then_latest_policy_is_loaded() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        db.set_rollover_policy(order_id, RolloverPolicy::Never)
+            .await
+            .unwrap();
+        let policy = RolloverPolicy::FundingRateBelow {
+            max_funding_rate: FundingRate::new(dec!(0.001)).unwrap(),
+        };
+        db.set_rollover_policy(order_id, policy).await.unwrap();
+
+        let loaded = db.load_rollover_policy(order_id).await.unwrap();
+
+        assert_eq!(loaded, Some(policy));
+    }
+}
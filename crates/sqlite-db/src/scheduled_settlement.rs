@@ -0,0 +1,131 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::OrderId;
+use model::Timestamp;
+
+/// A settlement of a CFD scheduled for a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduledSettlement {
+    pub order_id: OrderId,
+    pub settle_at: Timestamp,
+}
+
+impl Connection {
+    /// Schedule the settlement of a CFD, replacing any previous schedule.
+    pub async fn set_scheduled_settlement(
+        &self,
+        order_id: OrderId,
+        settle_at: Timestamp,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+        let settle_at = models::Timestamp::from(settle_at);
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO scheduled_settlements
+            (
+                order_id,
+                settle_at
+            )
+            VALUES ($1, $2)
+            "#,
+            order_id,
+            settle_at
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the scheduled settlement of a CFD, if there is one.
+    pub async fn delete_scheduled_settlement(&self, order_id: OrderId) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let order_id = models::OrderId::from(order_id);
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                scheduled_settlements
+            WHERE
+                order_id = $1
+            "#,
+            order_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the scheduled settlements of all CFDs.
+    pub async fn load_scheduled_settlements(&self) -> Result<Vec<ScheduledSettlement>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId",
+                settle_at as "settle_at: models::Timestamp"
+            FROM
+                scheduled_settlements
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let scheduled_settlements = rows
+            .into_iter()
+            .map(|row| ScheduledSettlement {
+                order_id: row.order_id.into(),
+                settle_at: row.settle_at.into(),
+            })
+            .collect();
+
+        Ok(scheduled_settlements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn given_settlement_rescheduled_then_latest_time_is_loaded() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        db.set_scheduled_settlement(order_id, Timestamp::new(1_666_000_000))
+            .await
+            .unwrap();
+        db.set_scheduled_settlement(order_id, Timestamp::new(1_666_003_600))
+            .await
+            .unwrap();
+
+        let scheduled_settlements = db.load_scheduled_settlements().await.unwrap();
+
+        assert_eq!(
+            scheduled_settlements,
+            vec![ScheduledSettlement {
+                order_id,
+                settle_at: Timestamp::new(1_666_003_600),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn given_scheduled_settlement_deleted_then_nothing_is_loaded() {
+        let db = memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        db.set_scheduled_settlement(order_id, Timestamp::new(1_666_000_000))
+            .await
+            .unwrap();
+        db.delete_scheduled_settlement(order_id).await.unwrap();
+
+        assert!(db.load_scheduled_settlements().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+//! Snapshots of CFD aggregates.
+//!
+//! CFDs which have rolled over many times have long event histories, and
+//! rehydrating an aggregate from all of them takes a while. Aggregates
+//! which support snapshots (see [`CfdAggregate::SNAPSHOT`]) are therefore
+//! saved every [`INTERVAL`] events. When such an aggregate is loaded and
+//! not cached, it is restored from its latest snapshot and only the
+//! events after it are applied.
+//!
+//! A snapshot is only an optimisation: if it cannot be restored, e.g.
+//! because the aggregate changed, the aggregate is rehydrated from all
+//! events instead.
+
+use crate::models;
+use crate::CfdAggregate;
+use anyhow::Result;
+use model::OrderId;
+use sqlx::SqliteConnection;
+
+/// After how many events a new snapshot of an aggregate is saved.
+pub const INTERVAL: u32 = 100;
+
+/// Whether a snapshot has to be saved after the aggregate went from
+/// `old_version` to `new_version`.
+pub(crate) fn is_due(old_version: u32, new_version: u32) -> bool {
+    old_version / INTERVAL < new_version / INTERVAL
+}
+
+/// Restore `cfd` from its latest snapshot.
+///
+/// Returns `cfd` unchanged if the aggregate does not support snapshots,
+/// if there is no snapshot, or if it cannot be restored.
+pub(crate) async fn restore<C>(conn: &mut SqliteConnection, id: OrderId, cfd: C) -> Result<C>
+where
+    C: CfdAggregate,
+{
+    let aggregate = match C::SNAPSHOT {
+        Some(aggregate) => aggregate,
+        None => return Ok(cfd),
+    };
+
+    let order_id = models::OrderId::from(id);
+
+    let snapshot = sqlx::query_scalar!(
+        r#"
+        SELECT
+            data
+        FROM
+            cfd_snapshots
+        WHERE
+            order_id = $1 AND aggregate = $2
+        "#,
+        order_id,
+        aggregate
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let snapshot = match snapshot {
+        Some(snapshot) => snapshot,
+        None => return Ok(cfd),
+    };
+
+    match cfd.restore_snapshot(&snapshot) {
+        Ok(restored) => Ok(restored),
+        Err(e) => {
+            tracing::warn!(
+                order_id = %id,
+                %aggregate,
+                "Failed to restore snapshot, applying all events: {e:#}"
+            );
+            Ok(cfd)
+        }
+    }
+}
+
+/// Save a snapshot of `cfd`, replacing the previous one.
+pub(crate) async fn save<C>(conn: &mut SqliteConnection, id: OrderId, cfd: &C) -> Result<()>
+where
+    C: CfdAggregate,
+{
+    let aggregate = match C::SNAPSHOT {
+        Some(aggregate) => aggregate,
+        None => return Ok(()),
+    };
+
+    let order_id = models::OrderId::from(id);
+    let data = cfd.to_snapshot()?;
+
+    sqlx::query!(
+        r#"
+        INSERT OR REPLACE INTO cfd_snapshots
+        (
+            order_id,
+            aggregate,
+            data
+        )
+        VALUES ($1, $2, $3)
+        "#,
+        order_id,
+        aggregate,
+        data
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    tracing::debug!(order_id = %id, %aggregate, version = cfd.version(), "Saved snapshot");
+
+    Ok(())
+}
+
+/// Delete all snapshots of a CFD, e.g. once it is closed.
+pub(crate) async fn delete(conn: &mut SqliteConnection, id: OrderId) -> Result<()> {
+    let order_id = models::OrderId::from(id);
+
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            cfd_snapshots
+        WHERE
+            order_id = $1
+        "#,
+        order_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use crate::tests::dummy_cfd;
+    use crate::Connection;
+    use model::CfdEvent;
+    use model::EventKind;
+    use model::Timestamp;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn snapshot_is_due_when_crossing_interval() {
+        assert!(!is_due(0, INTERVAL - 1));
+        assert!(is_due(INTERVAL - 1, INTERVAL));
+        assert!(is_due(0, INTERVAL * 2 + 1));
+        assert!(!is_due(INTERVAL, INTERVAL * 2 - 1));
+    }
+
+    #[tokio::test]
+    async fn given_snapshot_when_loading_uncached_cfd_then_same_cfd_is_restored() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        let order_id = cfd.id();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        for _ in 0..INTERVAL + 5 {
+            db.append_event(CfdEvent {
+                timestamp: Timestamp::now(),
+                id: order_id,
+                event: EventKind::RevokeConfirmed,
+            })
+            .await
+            .unwrap();
+        }
+
+        let loaded = db.load_open_cfd::<model::Cfd>(order_id, ()).await.unwrap();
+
+        let mut conn = db.inner.acquire().await.unwrap();
+        let snapshot = sqlx::query_scalar!("SELECT data FROM cfd_snapshots")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        let snapshotted = loaded.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(snapshotted.version(), INTERVAL + 5);
+
+        // A new connection does not share the aggregate cache
+        let uncached = Connection::new(db.inner.clone());
+        let restored = uncached
+            .load_open_cfd::<model::Cfd>(order_id, ())
+            .await
+            .unwrap();
+
+        assert_eq!(restored, loaded);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::libp2p::PeerId;
+use model::OrderId;
+
+/// How a taker behaved across all CFDs it entered with the maker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TakerRecord {
+    pub completed_cfds: u32,
+    pub aborted_setups: u32,
+    pub failed_rollovers: u32,
+}
+
+/// The outcome of a protocol with a taker, as counted in its
+/// [`TakerRecord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TakerOutcome {
+    CfdCompleted,
+    SetupAborted,
+    RolloverFailed,
+}
+
+impl Connection {
+    /// Count `outcome` towards the record of the counterparty of the
+    /// CFD with `order_id`.
+    ///
+    /// Nothing is recorded for CFDs with a counterparty that predates
+    /// peer IDs.
+    pub async fn record_taker_outcome(
+        &self,
+        order_id: OrderId,
+        outcome: TakerOutcome,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        let (completed_cfds, aborted_setups, failed_rollovers) = match outcome {
+            TakerOutcome::CfdCompleted => (1, 0, 0),
+            TakerOutcome::SetupAborted => (0, 1, 0),
+            TakerOutcome::RolloverFailed => (0, 0, 1),
+        };
+        let order_id = models::OrderId::from(order_id);
+        let placeholder = models::PeerId::from(PeerId::placeholder());
+
+        sqlx::query!(
+            r#"
+            INSERT INTO taker_records
+            (
+                peer_id,
+                completed_cfds,
+                aborted_setups,
+                failed_rollovers
+            )
+            SELECT
+                counterparty_peer_id, $1, $2, $3
+            FROM
+                cfds
+            WHERE
+                order_id = $4 AND counterparty_peer_id != $5
+            ON CONFLICT(peer_id) DO UPDATE SET
+                completed_cfds = completed_cfds + excluded.completed_cfds,
+                aborted_setups = aborted_setups + excluded.aborted_setups,
+                failed_rollovers = failed_rollovers + excluded.failed_rollovers
+            "#,
+            completed_cfds,
+            aborted_setups,
+            failed_rollovers,
+            order_id,
+            placeholder
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the record of `peer_id`, which is empty if nothing was
+    /// recorded for the taker yet.
+    pub async fn load_taker_record(&self, peer_id: PeerId) -> Result<TakerRecord> {
+        let mut conn = self.inner.acquire().await?;
+        let peer_id = models::PeerId::from(peer_id);
+
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                completed_cfds,
+                aborted_setups,
+                failed_rollovers
+            FROM
+                taker_records
+            WHERE
+                peer_id = $1
+            "#,
+            peer_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(TakerRecord::default()),
+        };
+
+        Ok(TakerRecord {
+            completed_cfds: row.completed_cfds.try_into()?,
+            aborted_setups: row.aborted_setups.try_into()?,
+            failed_rollovers: row.failed_rollovers.try_into()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use crate::tests::dummy_taker_with_counterparty_peer_id;
+
+    #[tokio::test]
+    async fn outcomes_are_counted_towards_the_counterparty() {
+        let db = memory().await.unwrap();
+        let cfd = dummy_taker_with_counterparty_peer_id();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        db.record_taker_outcome(cfd.id(), TakerOutcome::SetupAborted)
+            .await
+            .unwrap();
+        db.record_taker_outcome(cfd.id(), TakerOutcome::RolloverFailed)
+            .await
+            .unwrap();
+        db.record_taker_outcome(cfd.id(), TakerOutcome::RolloverFailed)
+            .await
+            .unwrap();
+
+        let record = db
+            .load_taker_record(cfd.counterparty_peer_id().unwrap())
+            .await
+            .unwrap();
+        let unknown = db.load_taker_record(PeerId::random()).await.unwrap();
+
+        assert_eq!(
+            record,
+            TakerRecord {
+                completed_cfds: 0,
+                aborted_setups: 1,
+                failed_rollovers: 2,
+            }
+        );
+        assert_eq!(unknown, TakerRecord::default());
+    }
+}
@@ -72,7 +72,7 @@ impl Connection {
 // We cannot hide this under the `test` compilation flag because it
 // makes it much less convenient to call `cargo sqlx prepare`.
 #[allow(dead_code)]
-mod sqlx_test_utils {
+pub(crate) mod sqlx_test_utils {
     use super::*;
     use sqlx::SqliteConnection;
 
@@ -0,0 +1,257 @@
+//! The lineage of a CFD, from the offer it was created from through
+//! all of its events.
+//!
+//! Open, closed and failed CFDs are stored in different tables. Only
+//! the names and timestamps of the events of closed and failed CFDs
+//! are retained, which is also all we report for open CFDs.
+
+use crate::models;
+use crate::Connection;
+use anyhow::Result;
+use model::OfferId;
+use model::OrderId;
+use model::Timestamp;
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+/// Where a CFD is in its lifecycle, i.e. in which tables it is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lifecycle {
+    Open,
+    Closed,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TracedEvent {
+    pub name: String,
+    pub timestamp: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub order_id: OrderId,
+    pub offer_id: OfferId,
+    pub lifecycle: Lifecycle,
+    /// The events of the CFD in the order in which they were recorded
+    pub events: Vec<TracedEvent>,
+}
+
+impl Connection {
+    /// Load the lineage of the CFD with `order_id`.
+    ///
+    /// Returns `None` if there is no such CFD.
+    pub async fn load_trace(&self, order_id: OrderId) -> Result<Option<Trace>> {
+        let mut conn = self.inner.acquire().await?;
+
+        for lifecycle in [Lifecycle::Open, Lifecycle::Closed, Lifecycle::Failed] {
+            if let Some(trace) = load(&mut conn, order_id, lifecycle).await? {
+                return Ok(Some(trace));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+async fn load(
+    conn: &mut SqliteConnection,
+    order_id: OrderId,
+    lifecycle: Lifecycle,
+) -> Result<Option<Trace>> {
+    let id = models::OrderId::from(order_id);
+
+    let offer_id = match lifecycle {
+        Lifecycle::Open => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT
+                    offer_id as "offer_id: models::OfferId"
+                FROM
+                    cfds
+                WHERE
+                    order_id = $1
+                "#,
+                id
+            )
+            .fetch_optional(&mut *conn)
+            .await?
+        }
+        Lifecycle::Closed => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT
+                    offer_id as "offer_id: models::OfferId"
+                FROM
+                    closed_cfds
+                WHERE
+                    order_id = $1
+                "#,
+                id
+            )
+            .fetch_optional(&mut *conn)
+            .await?
+        }
+        Lifecycle::Failed => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT
+                    offer_id as "offer_id: models::OfferId"
+                FROM
+                    failed_cfds
+                WHERE
+                    order_id = $1
+                "#,
+                id
+            )
+            .fetch_optional(&mut *conn)
+            .await?
+        }
+    };
+
+    let offer_id = match offer_id {
+        Some(offer_id) => offer_id,
+        None => return Ok(None),
+    };
+
+    let events = match lifecycle {
+        Lifecycle::Open => sqlx::query!(
+            r#"
+            SELECT
+                events.name,
+                events.created_at as "created_at: models::Timestamp"
+            FROM
+                events
+            JOIN
+                cfds ON cfds.id = events.cfd_id
+            WHERE
+                cfds.order_id = $1
+            ORDER BY
+                events.id
+            "#,
+            id
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| TracedEvent {
+            name: row.name,
+            timestamp: row.created_at.into(),
+        })
+        .collect(),
+        Lifecycle::Closed => sqlx::query!(
+            r#"
+            SELECT
+                event_log.name,
+                event_log.created_at as "created_at: models::Timestamp"
+            FROM
+                event_log
+            JOIN
+                closed_cfds ON closed_cfds.id = event_log.cfd_id
+            WHERE
+                closed_cfds.order_id = $1
+            ORDER BY
+                event_log.id
+            "#,
+            id
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| TracedEvent {
+            name: row.name,
+            timestamp: row.created_at.into(),
+        })
+        .collect(),
+        Lifecycle::Failed => sqlx::query!(
+            r#"
+            SELECT
+                event_log_failed.name,
+                event_log_failed.created_at as "created_at: models::Timestamp"
+            FROM
+                event_log_failed
+            JOIN
+                failed_cfds ON failed_cfds.id = event_log_failed.cfd_id
+            WHERE
+                failed_cfds.order_id = $1
+            ORDER BY
+                event_log_failed.id
+            "#,
+            id
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| TracedEvent {
+            name: row.name,
+            timestamp: row.created_at.into(),
+        })
+        .collect(),
+    };
+
+    Ok(Some(Trace {
+        order_id,
+        offer_id: offer_id.into(),
+        lifecycle,
+        events,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use crate::tests::dummy_cfd;
+    use crate::tests::lock_confirmed;
+    use crate::tests::order_rejected;
+    use model::EventKind;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn given_open_cfd_then_trace_links_offer_and_events() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        let order_id = cfd.id();
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(lock_confirmed(&cfd)).await.unwrap();
+
+        let trace = db.load_trace(order_id).await.unwrap().unwrap();
+
+        assert_eq!(trace.offer_id, cfd.offer_id());
+        assert_eq!(trace.lifecycle, Lifecycle::Open);
+        assert_eq!(
+            trace
+                .events
+                .iter()
+                .map(|event| event.name.clone())
+                .collect::<Vec<_>>(),
+            vec![EventKind::LockConfirmed.to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn given_failed_cfd_then_trace_is_loaded_from_failed_tables() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        let order_id = cfd.id();
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(order_rejected(&cfd)).await.unwrap();
+        db.move_to_failed_cfds().await.unwrap();
+
+        let trace = db.load_trace(order_id).await.unwrap().unwrap();
+
+        assert_eq!(trace.offer_id, cfd.offer_id());
+        assert_eq!(trace.lifecycle, Lifecycle::Failed);
+        assert_eq!(trace.events.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn given_unknown_order_id_then_no_trace() {
+        let db = memory().await.unwrap();
+
+        assert_eq!(db.load_trace(OrderId::default()).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,349 @@
+//! The blobs a watchtower stores on behalf of its peers, and which
+//! justice kits we uploaded to our watchtower.
+//!
+//! A blob is stored under a hint, which tells the watchtower when to try
+//! to decrypt it. The contents of a blob are opaque to the database.
+//! Once the revoked commit transaction of a blob is published, the
+//! watchtower records when it saw it, to punish it in time even across
+//! restarts.
+
+use crate::models;
+use crate::Connection;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::hashes::hex::FromHex;
+use bdk::bitcoin::hashes::hex::ToHex;
+use bdk::bitcoin::Script;
+use bdk::bitcoin::Txid;
+use model::libp2p::PeerId;
+use model::Timestamp;
+use sqlx::Acquire;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchtowerBlob {
+    pub hint: Script,
+    pub peer_id: PeerId,
+    pub ciphertext: String,
+}
+
+/// A revoked commit transaction which the watchtower saw published, but
+/// did not punish yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeenRevokedCommit {
+    /// The hint of the blob to punish it with
+    pub hint: Script,
+    pub peer_id: PeerId,
+    pub txid: Txid,
+    pub seen_at: Timestamp,
+}
+
+impl Connection {
+    /// Store `blob`, unless a blob with the same hint is already stored.
+    pub async fn save_watchtower_blob(&self, blob: &WatchtowerBlob) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let hint = blob.hint.to_hex();
+        let peer_id = models::PeerId::from(blob.peer_id);
+
+        sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO watchtower_blobs
+            (
+                hint,
+                peer_id,
+                ciphertext
+            )
+            VALUES ($1, $2, $3)
+            "#,
+            hint,
+            peer_id,
+            blob.ciphertext
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// How many blobs are stored on behalf of `peer_id`.
+    pub async fn count_watchtower_blobs(&self, peer_id: PeerId) -> Result<usize> {
+        let mut conn = self.inner.acquire().await?;
+        let peer_id = models::PeerId::from(peer_id);
+
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT
+                COUNT(*) as "count!: i64"
+            FROM
+                watchtower_blobs
+            WHERE
+                peer_id = $1
+            "#,
+            peer_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(count as usize)
+    }
+
+    pub async fn load_watchtower_blobs(&self) -> Result<Vec<WatchtowerBlob>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                hint as "hint!",
+                peer_id as "peer_id: models::PeerId",
+                ciphertext
+            FROM
+                watchtower_blobs
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(WatchtowerBlob {
+                    hint: Script::from_hex(&row.hint)
+                        .with_context(|| format!("Invalid watchtower hint {}", row.hint))?,
+                    peer_id: row.peer_id.into(),
+                    ciphertext: row.ciphertext,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn delete_watchtower_blob(&self, hint: &Script) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let hint = hint.to_hex();
+
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                watchtower_blobs
+            WHERE
+                hint = $1
+            "#,
+            hint
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record that the revoked commit transaction with `txid`, which the
+    /// blob with `hint` punishes, was seen published at `seen_at`.
+    ///
+    /// Keeps the time it was seen first.
+    pub async fn save_seen_revoked_commit(
+        &self,
+        hint: &Script,
+        txid: Txid,
+        seen_at: Timestamp,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let hint = hint.to_hex();
+        let txid = models::Txid::from(txid);
+        let seen_at = seen_at.seconds();
+
+        sqlx::query!(
+            r#"
+            UPDATE
+                watchtower_blobs
+            SET
+                revoked_commit_txid = $2,
+                revoked_commit_seen_at = $3
+            WHERE
+                hint = $1 AND revoked_commit_seen_at IS NULL
+            "#,
+            hint,
+            txid,
+            seen_at
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn load_seen_revoked_commits(&self) -> Result<Vec<SeenRevokedCommit>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                hint as "hint!",
+                peer_id as "peer_id: models::PeerId",
+                revoked_commit_txid as "revoked_commit_txid!: models::Txid",
+                revoked_commit_seen_at as "revoked_commit_seen_at!: i64"
+            FROM
+                watchtower_blobs
+            WHERE
+                revoked_commit_seen_at IS NOT NULL
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(SeenRevokedCommit {
+                    hint: Script::from_hex(&row.hint)
+                        .with_context(|| format!("Invalid watchtower hint {}", row.hint))?,
+                    peer_id: row.peer_id.into(),
+                    txid: row.revoked_commit_txid.into(),
+                    seen_at: Timestamp::new(row.revoked_commit_seen_at),
+                })
+            })
+            .collect()
+    }
+
+    /// Record that the justice kits of the revoked commit transactions
+    /// with `txids` were uploaded to the watchtower with `tower_peer_id`.
+    pub async fn save_watchtower_uploads(
+        &self,
+        tower_peer_id: PeerId,
+        txids: &[Txid],
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+        let tower_peer_id = models::PeerId::from(tower_peer_id);
+
+        for txid in txids {
+            let txid = models::Txid::from(*txid);
+
+            sqlx::query!(
+                r#"
+                INSERT OR IGNORE INTO watchtower_uploads
+                (
+                    tower_peer_id,
+                    revoked_commit_txid
+                )
+                VALUES ($1, $2)
+                "#,
+                tower_peer_id,
+                txid
+            )
+            .execute(&mut *db_tx)
+            .await?;
+        }
+
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Load the txids of the revoked commit transactions whose justice
+    /// kits were uploaded to the watchtower with `tower_peer_id`.
+    pub async fn load_watchtower_uploads(&self, tower_peer_id: PeerId) -> Result<HashSet<Txid>> {
+        let mut conn = self.inner.acquire().await?;
+        let tower_peer_id = models::PeerId::from(tower_peer_id);
+
+        let txids = sqlx::query_scalar!(
+            r#"
+            SELECT
+                revoked_commit_txid as "revoked_commit_txid: models::Txid"
+            FROM
+                watchtower_uploads
+            WHERE
+                tower_peer_id = $1
+            "#,
+            tower_peer_id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        Ok(txids.into_iter().map(Txid::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[tokio::test]
+    async fn given_blob_with_same_hint_then_first_blob_is_kept() {
+        let db = memory().await.unwrap();
+
+        let peer_id = PeerId::random();
+        let first = WatchtowerBlob {
+            hint: Script::from(vec![0u8; 34]),
+            peer_id,
+            ciphertext: "first".to_owned(),
+        };
+        let second = WatchtowerBlob {
+            ciphertext: "second".to_owned(),
+            ..first.clone()
+        };
+        db.save_watchtower_blob(&first).await.unwrap();
+        db.save_watchtower_blob(&second).await.unwrap();
+
+        assert_eq!(db.count_watchtower_blobs(peer_id).await.unwrap(), 1);
+        assert_eq!(
+            db.load_watchtower_blobs().await.unwrap(),
+            vec![first.clone()]
+        );
+
+        db.delete_watchtower_blob(&first.hint).await.unwrap();
+        assert_eq!(db.load_watchtower_blobs().await.unwrap(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn revoked_commit_keeps_the_time_it_was_seen_first() {
+        let db = memory().await.unwrap();
+
+        let blob = WatchtowerBlob {
+            hint: Script::from(vec![0u8; 34]),
+            peer_id: PeerId::random(),
+            ciphertext: "ciphertext".to_owned(),
+        };
+        let txid =
+            Txid::from_hex("c2bc1ad9e6ac7e1d2a0bd9e7b2fe4cd74c9d1e84a7d1dd4a2c6a8e6a3f1b2c3d")
+                .unwrap();
+        db.save_watchtower_blob(&blob).await.unwrap();
+        assert_eq!(db.load_seen_revoked_commits().await.unwrap(), vec![]);
+
+        db.save_seen_revoked_commit(&blob.hint, txid, Timestamp::new(1))
+            .await
+            .unwrap();
+        db.save_seen_revoked_commit(&blob.hint, txid, Timestamp::new(2))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.load_seen_revoked_commits().await.unwrap(),
+            vec![SeenRevokedCommit {
+                hint: blob.hint,
+                peer_id: blob.peer_id,
+                txid,
+                seen_at: Timestamp::new(1),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn uploads_are_loaded_per_watchtower() {
+        let db = memory().await.unwrap();
+
+        let tower = PeerId::random();
+        let txid =
+            Txid::from_hex("c2bc1ad9e6ac7e1d2a0bd9e7b2fe4cd74c9d1e84a7d1dd4a2c6a8e6a3f1b2c3d")
+                .unwrap();
+        db.save_watchtower_uploads(tower, &[txid]).await.unwrap();
+        db.save_watchtower_uploads(tower, &[txid]).await.unwrap();
+
+        assert_eq!(
+            db.load_watchtower_uploads(tower).await.unwrap(),
+            HashSet::from([txid])
+        );
+        assert!(db
+            .load_watchtower_uploads(PeerId::random())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
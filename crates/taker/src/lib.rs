@@ -1,40 +1,75 @@
 use crate::bitcoin::util::bip32::ExtendedPrivKey;
+#[cfg(feature = "api")]
 use crate::routes::IdentityInfo;
 use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use clap::Parser;
+use daemon::analytics;
 use daemon::bdk::bitcoin;
 use daemon::bdk::FeeRate;
+use daemon::db_backup;
+use daemon::electrum;
+use daemon::feed_lag;
+use daemon::fiat_rates;
+use daemon::libp2p_utils::create_connect_socks5_multiaddr;
 use daemon::libp2p_utils::create_connect_tcp_multiaddr;
+use daemon::libp2p_utils::create_connect_websocket_multiaddr;
+use daemon::maker_connection;
 use daemon::monitor;
 use daemon::oracle;
+use daemon::price_feed;
 use daemon::projection;
+use daemon::readiness;
+use daemon::readiness::Subsystem;
+use daemon::risk_limits;
 use daemon::seed::AppSeed;
 use daemon::seed::RandomSeed;
 use daemon::seed::Seed;
 use daemon::seed::ThreadSafeSeed;
 use daemon::wallet;
 use daemon::wallet::TAKER_WALLET_ID;
+use daemon::ConnectionSettings;
 use daemon::Environment;
 use daemon::TakerActorSystem;
 use daemon::N_PAYOUTS;
+use libp2p_core::Multiaddr;
 use libp2p_core::PeerId;
-use model::olivia;
 use model::Identity;
 use model::Role;
 use model::SETTLEMENT_INTERVAL;
+#[cfg(feature = "api")]
 use rocket::async_trait;
+#[cfg(feature = "api")]
 use rocket_cookie_auth::users::Users;
+use rust_decimal::Decimal;
+#[cfg(feature = "api")]
 use shared_bin::catchers::default_catchers;
+use shared_bin::cli::AddressReuse;
+use shared_bin::cli::Connection;
+use shared_bin::cli::DatabaseBackup;
+use shared_bin::cli::EventDelivery;
+use shared_bin::cli::Explorer;
+use shared_bin::cli::FeeEstimation;
+use shared_bin::cli::FiatDisplay;
 use shared_bin::cli::Network;
+use shared_bin::cli::Oracle;
+use shared_bin::cli::OracleKeyRotation;
+use shared_bin::cli::PriceFeed;
 use shared_bin::cli::Withdraw;
+#[cfg(feature = "api")]
+use shared_bin::dashboard::dashboard_routes;
+use shared_bin::diagnostics::Diagnostics;
+#[cfg(feature = "api")]
 use shared_bin::fairings;
 use shared_bin::logger;
 use shared_bin::logger::LevelFilter;
+use shared_bin::logger::LogFormat;
 use shared_bin::logger::LOCAL_COLLECTOR_ENDPOINT;
 use shared_bin::MAINNET_ELECTRUM;
 use shared_bin::TESTNET_ELECTRUM;
+use sqlite_db::rehydration;
 use std::convert::Infallible;
 use std::env;
 use std::net::IpAddr;
@@ -45,9 +80,11 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio_extras::Tasks;
-use xtras::supervisor::always_restart;
+use xtra::Actor as _;
+use xtra_libp2p::recorder::Recorder;
 use xtras::supervisor::Supervisor;
 
+#[cfg(feature = "api")]
 mod routes;
 
 pub const ANNOUNCEMENT_LOOKAHEAD: time::Duration = time::Duration::hours(24);
@@ -103,6 +140,29 @@ pub struct Opts {
     #[clap(long)]
     maker_peer_id: Option<PeerId>,
 
+    /// If enabled, the connection to the maker is established over WebSocket.
+    ///
+    /// Useful if plain TCP connections are blocked by a firewall. The port given with `--maker`
+    /// has to be the port the maker listens on for WebSocket connections.
+    #[clap(long)]
+    maker_websocket: bool,
+
+    /// Address of a SOCKS5 proxy (e.g. `127.0.0.1:9050` for Tor) to connect to the maker through.
+    ///
+    /// Hides the IP address of the taker from the maker. The maker address is resolved by the
+    /// proxy, which allows to connect to a maker running as a `.onion` service.
+    #[clap(long)]
+    tor_socks5: Option<SocketAddr>,
+
+    /// Address of a watchtower to upload the means to punish revoked commit transactions to,
+    /// including its peer id, e.g. `/dns/example.com/tcp/10000/p2p/<peer-id>`.
+    ///
+    /// The watchtower publishes the punish transaction if the maker publishes a revoked commit
+    /// transaction while the taker is offline. Run it with the `watchtower` binary; the maker
+    /// cannot be used as the watchtower.
+    #[clap(long)]
+    watchtower: Option<Multiaddr>,
+
     /// The IP address to listen on for the HTTP API.
     #[clap(long, default_value = "127.0.0.1:8000")]
     http_address: SocketAddr,
@@ -111,12 +171,19 @@ pub struct Opts {
     #[clap(long)]
     data_dir: Option<PathBuf>,
 
-    /// If enabled logs will be in json format
+    /// Format of the logs, one of `text` or `json`.
+    ///
+    /// JSON logs carry the `order_id`, `offer_id` and `peer_id` of the protocol a log event
+    /// belongs to, which allows to follow the lifecycle of a single CFD in a log aggregator.
+    #[clap(long, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Same as `--log-format=json`.
     #[clap(short, long)]
     json: bool,
 
     /// If enabled, logs in json format will contain a list of all ancestor spans of log events.
-    /// This **only** has an effect when `json` is also enabled.
+    /// This **only** has an effect when the log format is `json`.
     #[clap(long)]
     pub json_span_list: bool,
 
@@ -173,6 +240,75 @@ pub struct Opts {
     /// If enabled, the log will be printed to {service_name}.log in the data dir
     #[clap(long)]
     pub log_to_file: bool,
+
+    /// Maximum loss in satoshis that can be realised within a rolling week.
+    ///
+    /// Once reached, placing new orders is refused unless the limit is explicitly overridden.
+    #[clap(long)]
+    pub max_weekly_loss_sats: Option<u64>,
+
+    /// Disconnect from the maker after this many minutes without activity while there are no
+    /// open CFDs.
+    ///
+    /// The connection is re-established on demand, e.g. when placing an order or while the web
+    /// interface is open. If not set, the taker stays connected at all times.
+    #[clap(long)]
+    pub idle_disconnect_after_mins: Option<u64>,
+
+    /// If enabled, the daemon starts even if the startup diagnostics detect a problem.
+    #[clap(long)]
+    pub skip_diagnostics: bool,
+
+    /// If enabled, all frames exchanged with peers are recorded to the `recordings` folder in
+    /// the data dir. Secret keys are redacted.
+    #[clap(long)]
+    pub record_protocols: bool,
+
+    /// Warn if a consumer of the UI feeds has not observed an update for this many seconds.
+    #[clap(long, default_value = "60")]
+    pub feed_stale_warning_secs: u64,
+
+    /// Raise a liquidation alert if the current price is less than this many percent away from
+    /// the liquidation price of an open CFD.
+    #[clap(long, default_value = "10")]
+    pub liquidation_alert_threshold_percent: Decimal,
+
+    /// Propose collaborative settlements at the time-weighted average price of this many seconds
+    /// instead of at the latest quote.
+    ///
+    /// The window is limited by how long the price feed keeps quotes, see `--price-history-mins`.
+    #[clap(long)]
+    pub settlement_twap_secs: Option<u32>,
+
+    #[clap(flatten)]
+    pub oracle: Oracle,
+
+    #[clap(flatten)]
+    pub oracle_key_rotation: OracleKeyRotation,
+
+    #[clap(flatten)]
+    pub fee_estimation: FeeEstimation,
+
+    #[clap(flatten)]
+    pub fiat_display: FiatDisplay,
+
+    #[clap(flatten)]
+    pub price_feed: PriceFeed,
+
+    #[clap(flatten)]
+    pub address_reuse: AddressReuse,
+
+    #[clap(flatten)]
+    pub explorer: Explorer,
+
+    #[clap(flatten)]
+    pub connection: Connection,
+
+    #[clap(flatten)]
+    pub db_backup: DatabaseBackup,
+
+    #[clap(flatten)]
+    pub event_delivery: EventDelivery,
 }
 
 impl Opts {
@@ -193,8 +329,12 @@ impl Opts {
             maker: Some(maker),
             maker_id: Some(maker_id),
             maker_peer_id: Some(maker_peer_id),
+            maker_websocket: false,
+            tor_socks5: None,
+            watchtower: None,
             http_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port),
             data_dir: Some(PathBuf::from(data_dir)),
+            log_format: LogFormat::Text,
             json: false,
             json_span_list: false,
             instrumentation: false,
@@ -209,6 +349,23 @@ impl Opts {
             app_seed: None,
             wallet_xprv: None,
             log_to_file: true,
+            max_weekly_loss_sats: None,
+            idle_disconnect_after_mins: None,
+            skip_diagnostics: false,
+            record_protocols: false,
+            feed_stale_warning_secs: 60,
+            liquidation_alert_threshold_percent: Decimal::TEN,
+            settlement_twap_secs: None,
+            oracle: Oracle::default(),
+            oracle_key_rotation: OracleKeyRotation::default(),
+            fee_estimation: FeeEstimation::default(),
+            fiat_display: FiatDisplay::default(),
+            price_feed: PriceFeed::default(),
+            address_reuse: AddressReuse::default(),
+            explorer: Explorer::default(),
+            connection: Connection::default(),
+            db_backup: DatabaseBackup::default(),
+            event_delivery: EventDelivery::default(),
         })
     }
 
@@ -217,7 +374,16 @@ impl Opts {
     }
 
     fn maker(&self) -> Result<(String, x25519_dalek::PublicKey, PeerId)> {
-        let network = PublicNetwork::try_from(self.network())?;
+        // There are no defaults to fall back to on private networks, e.g. regtest
+        if let (Some(maker_url), Some(maker_id), Some(maker_peer_id)) =
+            (self.maker.clone(), self.maker_id, self.maker_peer_id)
+        {
+            return Ok((maker_url, maker_id, maker_peer_id));
+        }
+
+        let network = PublicNetwork::try_from(self.network()).context(
+            "`--maker`, `--maker-id` and `--maker-peer-id` are required on private networks",
+        )?;
 
         let maker_url = self
             .maker
@@ -276,11 +442,11 @@ impl From<PublicNetwork> for Network {
     fn from(public: PublicNetwork) -> Self {
         match public {
             PublicNetwork::Mainnet => Network::Mainnet {
-                electrum: MAINNET_ELECTRUM.to_string(),
+                electrum: vec![MAINNET_ELECTRUM.to_string()],
                 withdraw: None,
             },
             PublicNetwork::Testnet => Network::Testnet {
-                electrum: TESTNET_ELECTRUM.to_string(),
+                electrum: vec![TESTNET_ELECTRUM.to_string()],
                 withdraw: None,
             },
         }
@@ -327,9 +493,15 @@ pub async fn run(opts: Opts) -> Result<()> {
         tokio::fs::create_dir_all(&data_dir).await?;
     }
 
-    let _guard = logger::init(
+    let log_format = if opts.json {
+        LogFormat::Json
+    } else {
+        opts.log_format
+    };
+
+    let logging = logger::init(
         opts.log_level,
-        opts.json,
+        log_format,
         opts.json_span_list,
         opts.instrumentation,
         opts.tokio_console,
@@ -347,9 +519,31 @@ pub async fn run(opts: Opts) -> Result<()> {
         "CFDs created with this release will settle after {settlement_interval_hours} hours"
     );
 
+    let diagnostics = Diagnostics::new(&data_dir)
+        .database(data_dir.join("taker.sqlite"))
+        .electrum(network.electrum())
+        .oracle(opts.oracle.url(network.bitcoin_network()).to_string());
+    let diagnostics = match opts.tor_socks5 {
+        Some(proxy) => diagnostics.peer_via_socks5("Maker", maker_url.clone(), proxy),
+        None => diagnostics.peer("Maker", maker_url.clone()),
+    };
+    let diagnostics = match opts.app_seed {
+        Some(_) => diagnostics,
+        None => diagnostics.seed_file(data_dir.join("taker_seed")),
+    };
+    #[cfg(feature = "api")]
+    let diagnostics = diagnostics.listen(opts.http_address);
+    diagnostics.run().await.ensure_ok(opts.skip_diagnostics)?;
+
+    let protocol_recorder = opts
+        .record_protocols
+        .then(|| Recorder::new(data_dir.join("recordings")))
+        .transpose()?;
+
     let maker_identity = Identity::new(maker_id);
 
     let bitcoin_network = network.bitcoin_network();
+    let block_explorer = opts.explorer.block_explorer(bitcoin_network)?;
 
     let seed: Arc<ThreadSafeSeed> = match opts.app_seed {
         Some(seed_bytes) => Arc::new(AppSeed::from(seed_bytes)),
@@ -371,15 +565,63 @@ pub async fn run(opts: Opts) -> Result<()> {
 
     let mut tasks = Tasks::default();
 
+    #[cfg(unix)]
+    if let Some(log_filter) = logging.filter.clone() {
+        let path = data_dir.join(format!("{}.log-filter", opts.service_name));
+        tasks.add_fallible(logger::reload_on_sighup(log_filter, path), |e| async move {
+            tracing::error!("Stopped reloading log filter on SIGHUP: {e:#}")
+        });
+    }
+
+    let (electrum_actor, active_electrum) = electrum::Actor::new(network.electrum().to_vec())?;
+    let _electrum_actor = electrum_actor.create(None).spawn(&mut tasks);
+
+    let (readiness, readiness_receiver) = readiness::Tracker::new();
+
     let mut wallet_dir = data_dir.clone();
     wallet_dir.push(TAKER_WALLET_ID);
-    let (wallet, wallet_feed_receiver) =
-        wallet::Actor::spawn(network.electrum(), ext_priv_key, wallet_dir)?;
+
+    // Connecting to Electrum and migrating the database are independent
+    // of each other, so we do both at the same time
+    let spawn_wallet = {
+        let active_electrum = active_electrum.clone();
+        let address_reuse = opts.address_reuse.policy();
+        async move {
+            let wallet = tokio::task::spawn_blocking(move || {
+                wallet::Actor::spawn(active_electrum, ext_priv_key, wallet_dir, address_reuse)
+            })
+            .await??;
+
+            anyhow::Ok(wallet)
+        }
+    };
+    let connect_db = async {
+        let db = sqlite_db::connect(data_dir.join("taker.sqlite"), true).await?;
+
+        for order_id in db.migrate_legacy_cfds().await? {
+            tracing::warn!(
+                %order_id,
+                "Counterparty of legacy CFD cannot be reached over libp2p, commit it to close it"
+            );
+        }
+
+        anyhow::Ok(db)
+    };
+    let ((wallet, wallet_feed_receiver), db) = tokio::try_join!(spawn_wallet, connect_db)?;
+    readiness.ready(Subsystem::Database);
+
+    wallet
+        .send(wallet::RestoreFrozenUtxos {
+            outpoints: db.load_frozen_utxos().await?,
+        })
+        .await?;
 
     if let Some(Withdraw::Withdraw {
         amount,
         address,
         fee,
+        utxos,
+        exclude,
     }) = network.withdraw()
     {
         wallet
@@ -387,36 +629,45 @@ pub async fn run(opts: Opts) -> Result<()> {
                 amount: *amount,
                 address: address.clone(),
                 fee: fee.map(FeeRate::from_sat_per_vb),
+                utxos: utxos.clone(),
+                exclude: exclude.clone(),
             })
             .await??;
 
         return Ok(());
     }
 
+    #[cfg(feature = "api")]
     let figment = rocket::Config::figment()
         .merge(("address", opts.http_address.ip()))
         .merge(("port", opts.http_address.port()))
         .merge(("cli_colors", false))
         .merge(("secret_key", RandomSeed::default().seed()));
 
-    let db = sqlite_db::connect(data_dir.join("taker.sqlite"), true).await?;
-
     // Create actors
 
-    let possible_addresses = resolve_maker_addresses(maker_url.as_str()).await?;
-
-    // Assume that the first resolved ipv4 address is good enough for libp2p.
-    let maker_libp2p_address = possible_addresses
-        .iter()
-        .find(|x| x.is_ipv4())
-        .context("Could not resolve maker URL")?;
-    let maker_multiaddr = create_connect_tcp_multiaddr(maker_libp2p_address, maker_peer_id)?;
+    let maker_multiaddr = match db.load_maker_address().await? {
+        Some(maker_multiaddr) => {
+            tracing::info!(%maker_multiaddr, "Connecting to the maker at its updated address");
+            maker_multiaddr
+        }
+        None => {
+            resolve_maker_multiaddr(
+                maker_url.as_str(),
+                maker_peer_id,
+                opts.tor_socks5,
+                opts.maker_websocket,
+            )
+            .await?
+        }
+    };
 
     let hex_pk = hex::encode(identities.identity_pk.to_bytes());
     let peer_id = identities.libp2p.public().to_peer_id().to_string();
 
     tracing::info!("Connection details: taker_id='{hex_pk}', peer_id='{peer_id}'");
 
+    #[cfg(feature = "api")]
     let identity_info = IdentityInfo {
         taker_id: hex_pk,
         taker_peer_id: peer_id,
@@ -427,109 +678,362 @@ pub async fn run(opts: Opts) -> Result<()> {
         Err(_) => Environment::new("binary"),
     };
 
-    let (supervisor, price_feed_actor) =
-        Supervisor::<_, xtra_bitmex_price_feed::Error>::with_policy(
-            {
-                let network = network.bitmex_network();
-                move || xtra_bitmex_price_feed::Actor::new(network)
-            },
-            always_restart(),
-        );
+    let price_feed_actor = price_feed::spawn(
+        network.bitmex_network(),
+        opts.price_feed.exchanges(),
+        opts.price_feed.policy(),
+        opts.price_feed.history_window(),
+        &mut tasks,
+    );
 
-    tasks.add(supervisor.run_log_summary());
+    let _quote_recorder =
+        analytics::QuoteRecorder::new(db.clone(), price_feed_actor.clone().into())
+            .create(None)
+            .spawn(&mut tasks);
+
+    let fiat_rate_provider = opts.fiat_display.provider();
+    let fiat_rate_feed_receiver = match fiat_rate_provider.clone() {
+        Some(provider) => {
+            let (fiat_rates_actor, feed_receiver) = fiat_rates::Actor::new(provider);
+            let _fiat_rates_actor = fiat_rates_actor.create(None).spawn(&mut tasks);
+
+            feed_receiver
+        }
+        None => tokio::sync::watch::channel(None).1,
+    };
 
     let (feed_senders, feed_receivers) = projection::feeds();
     let feed_senders = Arc::new(feed_senders);
 
+    let _feed_lag_actor = feed_lag::Actor::new(
+        feed_receivers.freshness.feeds(),
+        Duration::from_secs(opts.feed_stale_warning_secs),
+    )
+    .create(None)
+    .spawn(&mut tasks);
+
+    tasks.add(readiness.clone().ready_when(
+        Subsystem::Wallet,
+        wallet_feed_receiver.clone(),
+        Option::is_some,
+    ));
+    tasks.add(readiness.clone().ready_when(
+        Subsystem::PriceFeed,
+        feed_receivers.quote.clone(),
+        |quotes| !quotes.is_empty(),
+    ));
+    tasks.add(readiness.clone().ready_when(
+        Subsystem::Cfds,
+        feed_receivers.cfds.clone(),
+        Option::is_some,
+    ));
+
     let (supervisor, projection_actor) = Supervisor::new({
         let db = db.clone();
         let price_feed = price_feed_actor.clone();
+        let block_explorer = block_explorer.clone();
+        let liquidation_alert_threshold = opts.liquidation_alert_threshold_percent;
+        let fiat_rate_feed_receiver = fiat_rate_feed_receiver.clone();
         move || {
             projection::Actor::new(
                 db.clone(),
-                bitcoin_network,
+                block_explorer.clone(),
                 price_feed.clone().into(),
                 Role::Taker,
                 feed_senders.clone(),
+                liquidation_alert_threshold,
             )
+            .with_fiat_rates(fiat_rate_feed_receiver.clone())
         }
     });
     tasks.add(supervisor.run_log_summary());
 
+    if let Some(backup) = opts.db_backup.settings()? {
+        tasks.add(db_backup::run(db.clone(), "taker", backup));
+    }
+
+    let fee_estimator = opts.fee_estimation.fee_estimator();
+    let connection = opts.connection.settings()?;
     let taker = TakerActorSystem::new(
         db.clone(),
         wallet.clone(),
-        *olivia::PUBLIC_KEY,
+        opts.oracle_key_rotation
+            .oracle_keys(opts.oracle.public_key(bitcoin_network))?,
         identities,
-        |executor| oracle::Actor::new(db.clone(), executor),
         |executor| {
-            let electrum = network.electrum().to_string();
-            monitor::Actor::new(db.clone(), electrum, executor)
+            oracle::Actor::new(db.clone(), executor).with_base_url(opts.oracle.url(bitcoin_network))
+        },
+        |executor| {
+            let electrum = active_electrum.clone();
+            let monitor =
+                monitor::Actor::new(db.clone(), electrum, executor, wallet.clone().into())?;
+
+            Ok(monitor
+                .with_fee_estimator(fee_estimator)
+                .with_finality_confirmations(monitor::FinalityConfirmations::for_network(
+                    bitcoin_network,
+                )))
         },
         price_feed_actor,
         N_PAYOUTS,
-        Duration::from_secs(10),
+        connection,
         projection_actor.clone(),
         maker_identity,
         maker_multiaddr,
         environment,
+        opts.tor_socks5,
+        protocol_recorder,
+        opts.event_delivery.timeout()?,
+        opts.watchtower,
+        opts.settlement_twap_secs
+            .map(|secs| time::Duration::seconds(secs.into())),
     )?;
 
-    if let Some(password) = opts.password {
-        db.clone()
-            .update_password(rocket_cookie_auth::user::create_password(
-                password.to_string().as_str(),
-            )?)
+    tasks.add(readiness.ready_when(
+        Subsystem::Rehydration,
+        taker.rehydration_progress.clone(),
+        rehydration::Progress::is_complete,
+    ));
+
+    if let Some(idle_disconnect_after_mins) = opts.idle_disconnect_after_mins {
+        taker
+            .set_idle_policy(Some(maker_connection::IdlePolicy {
+                timeout: Duration::from_secs(idle_disconnect_after_mins * 60),
+            }))
             .await?;
     }
 
-    let rocket_auth_db_connection = RocketAuthDbConnection::new(db.clone());
-    let users = Users::new(Box::new(rocket_auth_db_connection));
-
-    let mission_success = rocket::custom(figment)
-        .manage(feed_receivers)
-        .manage(wallet_feed_receiver)
-        .manage(identity_info)
-        .manage(bitcoin_network)
-        .manage(taker.maker_online_status_feed_receiver.clone())
-        .manage(taker.identify_info_feed_receiver.clone())
-        .manage(taker)
-        .mount(
-            "/api",
-            rocket::routes![
-                routes::feed,
-                routes::post_order_request,
-                routes::get_health_check,
-                routes::post_cfd_action,
-                routes::post_withdraw_request,
-                routes::get_metrics,
-                routes::put_sync_wallet,
-                routes::get_version,
-                routes::change_password,
-                routes::post_login,
-                routes::logout,
-                routes::is_authenticated,
-                routes::get_seed_backup,
-            ],
-        )
-        .register("/api", default_catchers())
-        .manage(users)
-        .manage(seed)
-        .mount("/", rocket::routes![routes::dist, routes::index])
-        .register("/", default_catchers())
-        .attach(fairings::log_launch())
-        .attach(fairings::log_requests())
-        .attach(fairings::ui_browser_launch(!opts.headless))
-        .launch()
-        .await?;
+    if let Some(max_loss) = opts.max_weekly_loss_sats {
+        taker
+            .set_loss_limit(Some(risk_limits::LossLimit::weekly(
+                bitcoin::Amount::from_sat(max_loss),
+            )))
+            .await?;
+    }
+
+    #[cfg(not(feature = "api"))]
+    {
+        // The projection actor fails to publish updates if nobody
+        // listens to its feeds
+        let _feeds = (
+            feed_receivers,
+            wallet_feed_receiver,
+            readiness_receiver,
+            taker,
+        );
 
-    tracing::trace!(?mission_success, "Rocket has landed");
+        tracing::info!("Running headless without HTTP API, press Ctrl-C to stop");
+        tokio::signal::ctrl_c().await?;
+    }
+
+    #[cfg(feature = "api")]
+    Api {
+        password: opts.password,
+        headless: opts.headless,
+        figment,
+        db: db.clone(),
+        feed_receivers,
+        wallet_feed_receiver,
+        fiat_rate_feed_receiver,
+        fiat_rate_provider,
+        readiness_receiver,
+        active_electrum,
+        connection,
+        identity_info,
+        bitcoin_network,
+        block_explorer,
+        taker,
+        seed,
+        log_filter: logging.filter.clone(),
+    }
+    .serve()
+    .await?;
 
     db.close().await;
 
     Ok(())
 }
 
+/// The HTTP API and the web UI, together with everything they serve.
+#[cfg(feature = "api")]
+struct Api {
+    password: Option<Password>,
+    headless: bool,
+    figment: rocket::figment::Figment,
+    db: sqlite_db::Connection,
+    feed_receivers: projection::FeedReceivers,
+    wallet_feed_receiver: tokio::sync::watch::Receiver<Option<model::WalletInfo>>,
+    fiat_rate_feed_receiver: tokio::sync::watch::Receiver<Option<fiat_rates::FiatRate>>,
+    fiat_rate_provider: Option<fiat_rates::Provider>,
+    readiness_receiver: tokio::sync::watch::Receiver<readiness::Readiness>,
+    active_electrum: electrum::ActiveServer,
+    connection: ConnectionSettings,
+    identity_info: IdentityInfo,
+    bitcoin_network: bitcoin::Network,
+    block_explorer: daemon::block_explorer::BlockExplorer,
+    taker: routes::Taker,
+    seed: Arc<ThreadSafeSeed>,
+    log_filter: Option<logger::LogFilter>,
+}
+
+#[cfg(feature = "api")]
+impl Api {
+    async fn serve(self) -> Result<()> {
+        let Api {
+            password,
+            headless,
+            figment,
+            db,
+            feed_receivers,
+            wallet_feed_receiver,
+            fiat_rate_feed_receiver,
+            fiat_rate_provider,
+            readiness_receiver,
+            active_electrum,
+            connection,
+            identity_info,
+            bitcoin_network,
+            block_explorer,
+            taker,
+            seed,
+            log_filter,
+        } = self;
+
+        if let Some(password) = password {
+            db.clone()
+                .update_password(rocket_cookie_auth::user::create_password(
+                    password.to_string().as_str(),
+                )?)
+                .await?;
+        }
+
+        let rocket_auth_db_connection = RocketAuthDbConnection::new(db);
+        let users = Users::new(Box::new(rocket_auth_db_connection));
+
+        let mission_success = rocket::custom(figment)
+            .manage(feed_receivers)
+            .manage(wallet_feed_receiver)
+            .manage(fiat_rate_feed_receiver)
+            .manage(fiat_rate_provider)
+            .manage(readiness_receiver)
+            .manage(active_electrum)
+            .manage(connection)
+            .manage(identity_info)
+            .manage(bitcoin_network)
+            .manage(block_explorer)
+            .manage(taker.maker_online_status_feed_receiver.clone())
+            .manage(taker.maker_latency_feed_receiver.clone())
+            .manage(taker.identify_info_feed_receiver.clone())
+            .manage(taker.maker_identity_pin_receiver.clone())
+            .manage(taker)
+            .manage(log_filter)
+            .mount(
+                "/api",
+                rocket::routes![
+                    routes::feed,
+                    routes::post_order_request,
+                    routes::get_health_check,
+                    routes::get_readiness,
+                    routes::post_cfd_action,
+                    routes::get_aggregated_positions,
+                    routes::get_offer_quantity,
+                    routes::get_offer_position_size,
+                    routes::get_archived_cfds,
+                    routes::get_trade_history,
+                    routes::get_market_statistics,
+                    routes::get_trace,
+                    routes::get_log_filter,
+                    routes::put_log_filter,
+                    routes::put_rollover_policy,
+                    routes::get_preferences,
+                    routes::put_preferences,
+                    routes::get_rollover_simulation,
+                    routes::get_dlcspecs_contract,
+                    routes::put_stop_loss,
+                    routes::delete_stop_loss,
+                    routes::put_take_profit,
+                    routes::delete_take_profit,
+                    routes::put_scheduled_settlement,
+                    routes::delete_scheduled_settlement,
+                    routes::put_offer_filter,
+                    routes::put_maker_address,
+                    routes::post_trust_maker_identity,
+                    routes::get_pending_requests,
+                    routes::delete_pending_request,
+                    routes::post_withdraw_request,
+                    routes::get_metrics,
+                    routes::put_sync_wallet,
+                    routes::post_rebuild_projection,
+                    routes::get_utxos,
+                    routes::put_freeze_utxos,
+                    routes::put_unfreeze_utxos,
+                    routes::get_risk_status,
+                    routes::post_override_loss_limit,
+                    routes::get_version,
+                    routes::change_password,
+                    routes::post_login,
+                    routes::logout,
+                    routes::is_authenticated,
+                    routes::get_seed_backup,
+                ],
+            )
+            .register("/api", default_catchers())
+            .manage(users)
+            .manage(seed)
+            .mount("/", rocket::routes![routes::dist, routes::index])
+            .mount("/", dashboard_routes())
+            .register("/", default_catchers())
+            .attach(fairings::log_launch())
+            .attach(fairings::log_requests())
+            .attach(fairings::ui_browser_launch(!headless))
+            .launch()
+            .await?;
+
+        tracing::trace!(?mission_success, "Rocket has landed");
+
+        Ok(())
+    }
+}
+
+/// The address of the maker given its URL, connecting through the
+/// SOCKS5 proxy or over WebSocket if configured.
+async fn resolve_maker_multiaddr(
+    maker_url: &str,
+    maker_peer_id: PeerId,
+    tor_socks5: Option<SocketAddr>,
+    websocket: bool,
+) -> Result<Multiaddr> {
+    let maker_multiaddr = match tor_socks5 {
+        Some(proxy) => {
+            ensure!(
+                !websocket,
+                "Connecting to the maker over WebSocket is not supported via SOCKS5"
+            );
+            tracing::info!("Connecting to the maker through SOCKS5 proxy {proxy}");
+
+            // Resolving the maker URL ourselves would leak it to our DNS server.
+            create_connect_socks5_multiaddr(maker_url, maker_peer_id)?
+        }
+        None => {
+            let possible_addresses = resolve_maker_addresses(maker_url).await?;
+
+            // Assume that the first resolved ipv4 address is good enough for libp2p.
+            let maker_libp2p_address = possible_addresses
+                .iter()
+                .find(|x| x.is_ipv4())
+                .context("Could not resolve maker URL")?;
+
+            if websocket {
+                create_connect_websocket_multiaddr(maker_libp2p_address, maker_peer_id)?
+            } else {
+                create_connect_tcp_multiaddr(maker_libp2p_address, maker_peer_id)?
+            }
+        }
+    };
+
+    Ok(maker_multiaddr)
+}
+
 async fn resolve_maker_addresses(maker_addr: &str) -> Result<Vec<SocketAddr>> {
     let possible_addresses = tokio::net::lookup_host(maker_addr)
         .await?
@@ -543,16 +1047,19 @@ async fn resolve_maker_addresses(maker_addr: &str) -> Result<Vec<SocketAddr>> {
     Ok(possible_addresses)
 }
 
+#[cfg(feature = "api")]
 struct RocketAuthDbConnection {
     inner: sqlite_db::Connection,
 }
 
+#[cfg(feature = "api")]
 impl RocketAuthDbConnection {
     fn new(db: sqlite_db::Connection) -> Self {
         Self { inner: db }
     }
 }
 
+#[cfg(feature = "api")]
 #[async_trait]
 impl rocket_cookie_auth::Database for RocketAuthDbConnection {
     async fn load_user(&self) -> Result<Option<rocket_cookie_auth::user::User>> {
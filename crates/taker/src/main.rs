@@ -2,7 +2,9 @@ use anyhow::Result;
 use taker::run;
 use taker::Opts;
 
-#[rocket::main]
+// Rocket is only available with the HTTP API
+#[cfg_attr(feature = "api", rocket::main)]
+#[cfg_attr(not(feature = "api"), tokio::main)]
 async fn main() -> Result<()> {
     let opts = Opts::read();
     run(opts).await
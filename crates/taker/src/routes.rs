@@ -1,27 +1,53 @@
 #![allow(clippy::let_unit_value)]
 // see: https://github.com/SergioBenitez/Rocket/issues/2211
+use anyhow::Context;
+use daemon::analytics;
 use daemon::bdk;
 use daemon::bdk::bitcoin::Amount;
-use daemon::bdk::bitcoin::Network;
+use daemon::bdk::bitcoin::OutPoint;
 use daemon::bdk::blockchain::ElectrumBlockchain;
 use daemon::bdk::sled;
+use daemon::block_explorer::BlockExplorer;
+use daemon::electrum;
+use daemon::fiat_rates;
+use daemon::fiat_rates::FiatRate;
 use daemon::identify;
+use daemon::maker_connection;
+use daemon::maker_identity::PinStatus;
 use daemon::online_status::ConnectionStatus;
+use daemon::online_status::Latency;
 use daemon::oracle;
+use daemon::pending_requests::PendingRequest;
+use daemon::preferences;
+use daemon::price_feed;
 use daemon::projection;
 use daemon::projection::CfdAction;
 use daemon::projection::FeedReceivers;
+use daemon::readiness;
+use daemon::risk_limits;
+use daemon::scheduled_settlement;
 use daemon::seed::ThreadSafeSeed;
+use daemon::taker_cfd;
+use daemon::trace;
+use daemon::trade_history;
 use daemon::wallet;
+use daemon::ConnectionSettings;
 use daemon::TakerActorSystem;
 use http_api_problem::HttpApiProblem;
 use http_api_problem::StatusCode;
+use libp2p_core::Multiaddr;
+use model::dlcspecs;
+use model::olivia::BitMexPriceEventId;
 use model::Contracts;
+use model::FundingRate;
 use model::Leverage;
 use model::OrderId;
 use model::Price;
+use model::PriceTrigger;
+use model::RolloverPolicy;
 use model::Timestamp;
 use model::WalletInfo;
+use offer::taker::OfferFilter;
 use rocket::form::Form;
 use rocket::http::ContentType;
 use rocket::http::Status;
@@ -37,26 +63,39 @@ use rocket_cookie_auth::forms::Login;
 use rocket_cookie_auth::user::User;
 use rocket_download_response::mime;
 use rocket_download_response::DownloadResponsePro;
+use rust_decimal::Decimal;
 use rust_embed::RustEmbed;
 use rust_embed_rocket::EmbeddedFileExt;
 use serde::Deserialize;
 use serde::Serialize;
+use shared_bin::logger::LogFilter;
+use shared_bin::maker_status_event;
+use shared_bin::sse;
+use shared_bin::sse::Delivery;
+use shared_bin::wallet_event;
 use shared_bin::ToSseEvent;
 use std::borrow::Cow;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::select;
 use tokio::sync::watch;
+use tokio_extras::Tasks;
 use tracing::instrument;
 
-type Taker = TakerActorSystem<
+pub(crate) type Taker = TakerActorSystem<
     oracle::Actor,
     wallet::Actor<ElectrumBlockchain, sled::Tree>,
-    xtra_bitmex_price_feed::Actor,
+    price_feed::Aggregator,
 >;
 
 const HEARTBEAT_INTERVAL_SECS: u64 = 5;
 
+/// The number of rollovers simulated unless requested otherwise, about a month.
+const DEFAULT_SIMULATED_ROLLOVERS: u32 = 30;
+
+/// The maximum number of rollovers which can be simulated, about a year.
+const MAX_SIMULATED_ROLLOVERS: u32 = 365;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct IdentityInfo {
     /// legacy networking identity
@@ -70,78 +109,180 @@ pub struct IdentityInfo {
 pub async fn feed(
     rx: &State<FeedReceivers>,
     rx_wallet: &State<watch::Receiver<Option<WalletInfo>>>,
+    rx_fiat_rate: &State<watch::Receiver<Option<FiatRate>>>,
     rx_maker_status: &State<watch::Receiver<ConnectionStatus>>,
+    rx_maker_latency: &State<watch::Receiver<Option<Latency>>>,
     rx_maker_identity: &State<watch::Receiver<Option<identify::PeerInfo>>>,
+    rx_maker_identity_pin: &State<watch::Receiver<PinStatus>>,
     identity_info: &State<IdentityInfo>,
+    taker: &State<Taker>,
     _user: User,
 ) -> EventStream![] {
     let rx = rx.inner();
     let mut rx_cfds = rx.cfds.clone();
     let mut rx_offers = rx.offers.clone();
+    let cfds_consumer = rx.freshness.cfds.consumer();
+    let offers_consumer = rx.freshness.offers.consumer();
+    let mut rx_alerts = rx.alerts.clone();
+    let alerts_consumer = rx.freshness.alerts.consumer();
+    let rx_quote = rx.quote.clone();
 
     let mut rx_wallet = rx_wallet.inner().clone();
+    let mut rx_fiat_rate = rx_fiat_rate.inner().clone();
     let mut rx_maker_status = rx_maker_status.inner().clone();
+    let mut rx_maker_latency = rx_maker_latency.inner().clone();
     let mut rx_maker_identity = rx_maker_identity.inner().clone();
+    let mut rx_maker_identity_pin = rx_maker_identity_pin.inner().clone();
     let identity = identity_info.inner().clone();
+    let maker_connection = taker.maker_connection_actor.clone();
     let mut heartbeat =
         tokio::time::interval(std::time::Duration::from_secs(HEARTBEAT_INTERVAL_SECS));
 
-    EventStream! {
+    let (events, mut queue) = sse::client_queue("taker");
+    let mut producer = Tasks::default();
+    producer.add(async move {
+        // Only fails once the client is gone, which ends the producer
+        let _: Result<(), sse::Disconnected> = async move {
+            events.send(
+                "wallet",
+                Delivery::Guaranteed,
+                wallet_event(&rx_wallet, &rx_quote, &rx_fiat_rate),
+            )?;
 
-        let wallet_info = rx_wallet.borrow().clone();
-        yield wallet_info.to_sse_event();
+            events.send(
+                "maker_status",
+                Delivery::Guaranteed,
+                maker_status_event(&rx_maker_status, &rx_maker_latency),
+            )?;
 
-        let maker_status = rx_maker_status.borrow().clone();
-        yield maker_status.to_sse_event();
+            let maker_identity = rx_maker_identity.borrow().clone();
+            events.send(
+                "maker_identity",
+                Delivery::Guaranteed,
+                maker_identity.to_sse_event(),
+            )?;
 
-        let maker_identity = rx_maker_identity.borrow().clone();
-        yield maker_identity.to_sse_event();
+            let maker_identity_pin = *rx_maker_identity_pin.borrow();
+            events.send(
+                "maker_identity_pin",
+                Delivery::Guaranteed,
+                Event::json(&maker_identity_pin).event("maker_identity_pin"),
+            )?;
 
-        yield Event::json(&identity).event("identity");
+            events.send(
+                "identity",
+                Delivery::Guaranteed,
+                Event::json(&identity).event("identity"),
+            )?;
 
-        let offers = rx_offers.borrow().clone();
-        yield Event::json(&offers.btcusd_long).event("btcusd_long_offer");
-        yield Event::json(&offers.btcusd_short).event("btcusd_short_offer");
-        yield Event::json(&offers.ethusd_long).event("ethusd_long_offer");
-        yield Event::json(&offers.ethusd_short).event("ethusd_short_offer");
+            let offers = rx_offers.borrow().clone();
+            sse::send_offers(&events, &offers)?;
+            offers_consumer.observed();
 
-        let cfds = rx_cfds.borrow().clone();
-        if let Some(cfds) = cfds {
-            yield cfds.to_sse_event()
-        }
+            let cfds = rx_cfds.borrow().clone();
+            if let Some(cfds) = cfds {
+                events.send("cfds", Delivery::Guaranteed, cfds.to_sse_event())?;
+            }
+            cfds_consumer.observed();
 
-        loop{
-            select! {
-                Ok(()) = rx_wallet.changed() => {
-                    let wallet_info = rx_wallet.borrow().clone();
-                    yield wallet_info.to_sse_event();
-                },
-                Ok(()) = rx_maker_status.changed() => {
-                    let maker_status = rx_maker_status.borrow().clone();
-                    yield maker_status.to_sse_event();
-                },
-                Ok(()) = rx_maker_identity.changed() => {
-                    let maker_identity = rx_maker_identity.borrow().clone();
-                    yield maker_identity.to_sse_event();
-                },
-                Ok(()) = rx_offers.changed() => {
-                    let offers = rx_offers.borrow().clone();
-                    yield Event::json(&offers.btcusd_long).event("btcusd_long_offer");
-                    yield Event::json(&offers.btcusd_short).event("btcusd_short_offer");
-                    yield Event::json(&offers.ethusd_long).event("ethusd_long_offer");
-                    yield Event::json(&offers.ethusd_short).event("ethusd_short_offer");
-                }
-                Ok(()) = rx_cfds.changed() => {
-                    let cfds = rx_cfds.borrow().clone();
-                    if let Some(cfds) = cfds {
-                        yield cfds.to_sse_event()
+            let alerts = rx_alerts.borrow().clone();
+            events.send(
+                "liquidation_alerts",
+                Delivery::Guaranteed,
+                Event::json(&alerts).event("liquidation_alerts"),
+            )?;
+            alerts_consumer.observed();
+
+            loop {
+                select! {
+                    Ok(()) = rx_wallet.changed() => {
+                        events.send(
+                            "wallet",
+                            Delivery::Guaranteed,
+                            wallet_event(&rx_wallet, &rx_quote, &rx_fiat_rate),
+                        )?;
+                    },
+                    Ok(()) = rx_fiat_rate.changed() => {
+                        events.send(
+                            "wallet",
+                            Delivery::Guaranteed,
+                            wallet_event(&rx_wallet, &rx_quote, &rx_fiat_rate),
+                        )?;
+                    },
+                    Ok(()) = rx_maker_status.changed() => {
+                        events.send(
+                            "maker_status",
+                            Delivery::Guaranteed,
+                            maker_status_event(&rx_maker_status, &rx_maker_latency),
+                        )?;
+                    },
+                    Ok(()) = rx_maker_latency.changed() => {
+                        events.send(
+                            "maker_status",
+                            Delivery::Guaranteed,
+                            maker_status_event(&rx_maker_status, &rx_maker_latency),
+                        )?;
+                    },
+                    Ok(()) = rx_maker_identity.changed() => {
+                        let maker_identity = rx_maker_identity.borrow().clone();
+                        events.send(
+                            "maker_identity",
+                            Delivery::Guaranteed,
+                            maker_identity.to_sse_event(),
+                        )?;
+                    },
+                    Ok(()) = rx_maker_identity_pin.changed() => {
+                        let maker_identity_pin = *rx_maker_identity_pin.borrow();
+                        events.send(
+                            "maker_identity_pin",
+                            Delivery::Guaranteed,
+                            Event::json(&maker_identity_pin).event("maker_identity_pin"),
+                        )?;
+                    },
+                    Ok(()) = rx_offers.changed() => {
+                        let offers = rx_offers.borrow().clone();
+                        sse::send_offers(&events, &offers)?;
+                        offers_consumer.observed();
+                    }
+                    Ok(()) = rx_cfds.changed() => {
+                        let cfds = rx_cfds.borrow().clone();
+                        if let Some(cfds) = cfds {
+                            events.send("cfds", Delivery::Guaranteed, cfds.to_sse_event())?;
+                        }
+                        cfds_consumer.observed();
+                    }
+                    Ok(()) = rx_alerts.changed() => {
+                        let alerts = rx_alerts.borrow().clone();
+                        events.send(
+                            "liquidation_alerts",
+                            Delivery::Guaranteed,
+                            Event::json(&alerts).event("liquidation_alerts"),
+                        )?;
+                        alerts_consumer.observed();
+                    }
+                    _ = heartbeat.tick() => {
+                        // Stay connected to the maker to receive its offers while the UI is open
+                        let _ = maker_connection.send(maker_connection::KeepAlive).await;
+
+                        events.send(
+                            "heartbeat",
+                            Delivery::DropOldest,
+                            Event::json(&Heartbeat::new()).event("heartbeat"),
+                        )?;
                     }
-                }
-                _ = heartbeat.tick() => {
-                    yield Event::json(&Heartbeat::new()).event("heartbeat")
                 }
             }
         }
+        .await;
+    });
+
+    EventStream! {
+        // Stop producing events once the client is gone
+        let _producer = producer;
+
+        while let Some(event) = queue.recv().await {
+            yield event;
+        }
     }
 }
 
@@ -162,9 +303,22 @@ impl Heartbeat {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CfdOrderRequest {
+    /// The ID of the offer to take
     pub order_id: OrderId,
     pub quantity: Contracts,
     pub leverage: Leverage,
+    /// ID for the new order, generated by the caller
+    ///
+    /// Retrying a request with the same ID returns the order placed by the first request instead
+    /// of placing another one. Reusing the ID for a different offer, quantity or leverage is
+    /// rejected with a conflict.
+    #[serde(default)]
+    pub client_order_id: Option<OrderId>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PlacedOrder {
+    pub order_id: OrderId,
 }
 
 #[rocket::post("/cfd/order", data = "<cfd_order_request>")]
@@ -173,21 +327,33 @@ pub async fn post_order_request(
     cfd_order_request: Json<CfdOrderRequest>,
     taker: &State<Taker>,
     _user: User,
-) -> Result<(), HttpApiProblem> {
-    taker
+) -> Result<Json<PlacedOrder>, HttpApiProblem> {
+    let order_id = taker
         .place_order(
             cfd_order_request.order_id,
             cfd_order_request.quantity,
             cfd_order_request.leverage,
+            cfd_order_request.client_order_id,
         )
         .await
         .map_err(|e| {
-            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            // On an unavailable offer the UI should refresh its offers and let the user try again
+            let status = match (
+                e.downcast_ref::<offer::OfferUnavailable>(),
+                e.downcast_ref::<taker_cfd::ConflictingOrder>(),
+                e.downcast_ref::<taker_cfd::InvalidOrder>(),
+            ) {
+                (Some(_), _, _) | (_, Some(_), _) => StatusCode::CONFLICT,
+                (None, None, Some(_)) => StatusCode::BAD_REQUEST,
+                (None, None, None) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+
+            HttpApiProblem::new(status)
                 .title("Order request failed")
                 .detail(format!("{e:#}"))
         })?;
 
-    Ok(())
+    Ok(Json(PlacedOrder { order_id }))
 }
 
 #[rocket::post("/cfd/<order_id>/<action>")]
@@ -224,9 +390,163 @@ pub async fn post_cfd_action(
     Ok(())
 }
 
+#[rocket::get("/positions")]
+#[instrument(name = "GET /positions", skip_all, err)]
+pub async fn get_aggregated_positions(
+    rx: &State<FeedReceivers>,
+    _user: User,
+) -> Result<Json<Vec<projection::AggregatedPosition>>, HttpApiProblem> {
+    let cfds = rx.cfds.borrow().clone();
+
+    match cfds {
+        Some(cfds) => Ok(Json(projection::aggregate_positions(&cfds))),
+        None => Err(HttpApiProblem::new(StatusCode::SERVICE_UNAVAILABLE)
+            .title("CFDs not yet available")
+            .detail("CFDs are still being loaded from the database. Please retry later.")),
+    }
+}
+
+/// Convert a quantity entered for an offer into contracts, sats and US dollars.
+///
+/// The quantity is either given in `contracts` or in `sats`. The response contains the reason
+/// why the quantity cannot be entered for the offer, if any, so that the UI can validate input
+/// without duplicating the conversion logic.
+#[rocket::get("/offers/<offer_id>/quantity?<contracts>&<sats>")]
+#[instrument(name = "GET /offers/<offer_id>/quantity", skip(rx, _user), err)]
+pub async fn get_offer_quantity(
+    offer_id: Uuid,
+    contracts: Option<String>,
+    sats: Option<u64>,
+    rx: &State<FeedReceivers>,
+    _user: User,
+) -> Result<Json<projection::QuantityDetails>, HttpApiProblem> {
+    let input = match (contracts, sats) {
+        (Some(contracts), None) => {
+            let contracts = contracts.parse::<Contracts>().map_err(|e| {
+                HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                    .title("Invalid quantity")
+                    .detail(format!("{e:#}"))
+            })?;
+
+            projection::QuantityInput::Contracts(contracts)
+        }
+        (None, Some(sats)) => projection::QuantityInput::Sats(Amount::from_sat(sats)),
+        _ => {
+            return Err(HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Invalid quantity")
+                .detail("Pass the quantity either in contracts or in sats"))
+        }
+    };
+
+    let offers = rx.offers.borrow().clone();
+    let offer = offers.get(OrderId::from(offer_id)).ok_or_else(|| {
+        HttpApiProblem::new(StatusCode::NOT_FOUND)
+            .title("Offer not found")
+            .detail(format!("Offer {offer_id} is not available anymore"))
+    })?;
+
+    let details = offer.quantity_details(input, &rx.quote.borrow());
+
+    Ok(Json(details))
+}
+
+/// Size a position on an offer according to risk parameters.
+///
+/// Given the `balance` in sats and the `risk_percent` of it which may be lost when the position
+/// is closed at `stop_price`, the response contains the quantity and leverage to open the
+/// position with, as well as its margin and liquidation price. The position opens at
+/// `entry_price`, or at the price of the offer if not given.
+#[rocket::get(
+    "/offers/<offer_id>/position-size?<balance>&<risk_percent>&<entry_price>&<stop_price>"
+)]
+#[instrument(name = "GET /offers/<offer_id>/position-size", skip(rx, _user), err)]
+pub async fn get_offer_position_size(
+    offer_id: Uuid,
+    balance: u64,
+    risk_percent: String,
+    entry_price: Option<String>,
+    stop_price: String,
+    rx: &State<FeedReceivers>,
+    _user: User,
+) -> Result<Json<projection::PositionSize>, HttpApiProblem> {
+    let bad_request = |title: &'static str| {
+        move |e: anyhow::Error| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title(title)
+                .detail(format!("{e:#}"))
+        }
+    };
+
+    let risk_percent = risk_percent
+        .parse::<Decimal>()
+        .map_err(anyhow::Error::from)
+        .map_err(bad_request("Invalid risk percentage"))?;
+    let entry_price = entry_price
+        .map(|price| parse_price(&price))
+        .transpose()
+        .map_err(bad_request("Invalid entry price"))?;
+    let stop_price = parse_price(&stop_price).map_err(bad_request("Invalid stop price"))?;
+
+    let offers = rx.offers.borrow().clone();
+    let offer = offers.get(OrderId::from(offer_id)).ok_or_else(|| {
+        HttpApiProblem::new(StatusCode::NOT_FOUND)
+            .title("Offer not found")
+            .detail(format!("Offer {offer_id} is not available anymore"))
+    })?;
+
+    let size = offer
+        .position_size(projection::PositionSizeInput {
+            balance: Amount::from_sat(balance),
+            risk_percent,
+            entry_price,
+            stop_price,
+        })
+        .map_err(bad_request("Position cannot be sized"))?;
+
+    Ok(Json(size))
+}
+
+fn parse_price(price: &str) -> anyhow::Result<Price> {
+    Price::new(price.parse::<Decimal>()?)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Health {
+    electrum: electrum::ElectrumStatus,
+    ping_interval_secs: u64,
+    connection_timeout_secs: u64,
+}
+
 #[rocket::get("/alive")]
-#[instrument(name = "GET /alive")]
-pub fn get_health_check() {}
+#[instrument(name = "GET /alive", skip_all)]
+pub fn get_health_check(
+    active_electrum: &State<electrum::ActiveServer>,
+    connection: &State<ConnectionSettings>,
+) -> Json<Health> {
+    Json(Health {
+        electrum: active_electrum.status(),
+        ping_interval_secs: connection.ping_interval.as_secs(),
+        connection_timeout_secs: connection.connection_timeout.as_secs(),
+    })
+}
+
+/// Report the stage of every subsystem.
+///
+/// Responds with `503 Service Unavailable` until all subsystems are ready.
+#[rocket::get("/readiness")]
+#[instrument(name = "GET /readiness", skip_all)]
+pub fn get_readiness(
+    readiness: &State<watch::Receiver<readiness::Readiness>>,
+) -> (Status, Json<readiness::Readiness>) {
+    let readiness = readiness.borrow().clone();
+    let status = if readiness.is_ready() {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    (status, Json(readiness))
+}
 
 #[derive(Debug, Clone, Copy, Deserialize)]
 pub struct MarginRequest {
@@ -273,6 +593,12 @@ pub struct WithdrawRequest {
     #[serde(with = "bdk::bitcoin::util::amount::serde::as_btc")]
     amount: Amount,
     fee: f32,
+    /// Only spend these UTXOs. If empty, UTXOs are selected automatically.
+    #[serde(default)]
+    utxos: Vec<OutPoint>,
+    /// Never spend these UTXOs.
+    #[serde(default)]
+    exclude: Vec<OutPoint>,
 }
 
 #[rocket::post("/withdraw", data = "<withdraw_request>")]
@@ -280,7 +606,7 @@ pub struct WithdrawRequest {
 pub async fn post_withdraw_request(
     withdraw_request: Json<WithdrawRequest>,
     taker: &State<Taker>,
-    network: &State<Network>,
+    block_explorer: &State<BlockExplorer>,
     _user: User,
 ) -> Result<String, HttpApiProblem> {
     let amount =
@@ -291,6 +617,8 @@ pub async fn post_withdraw_request(
             amount,
             withdraw_request.address.clone(),
             bdk::FeeRate::from_sat_per_vb(withdraw_request.fee),
+            withdraw_request.utxos.clone(),
+            withdraw_request.exclude.clone(),
         )
         .await
         .map_err(|e| {
@@ -299,7 +627,7 @@ pub async fn post_withdraw_request(
                 .detail(format!("{e:#}"))
         })?;
 
-    Ok(projection::to_mempool_url(txid, *network.inner()))
+    Ok(block_explorer.tx_url(txid))
 }
 
 // TODO: Use non-cookie auth for /metrics endpoint as Prometheus does not
@@ -330,6 +658,710 @@ pub async fn put_sync_wallet(taker: &State<Taker>, _user: User) -> Result<(), Ht
     Ok(())
 }
 
+/// Rebuild the projection from the event store, e.g. to recover from a bug in it.
+///
+/// The rebuild runs in the background. The CFD feed keeps serving the current state until it is
+/// complete.
+#[rocket::post("/projection/rebuild")]
+#[instrument(name = "POST /projection/rebuild", skip_all, err)]
+pub async fn post_rebuild_projection(
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker.rebuild_projection().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::CONFLICT)
+            .title("Could not rebuild projection")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
+#[rocket::get("/utxos")]
+#[instrument(name = "GET /utxos", skip_all, err)]
+pub async fn get_utxos(
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<Json<Vec<wallet::Utxo>>, HttpApiProblem> {
+    let utxos = taker.list_utxos().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not list UTXOs")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(utxos))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UtxosRequest {
+    outpoints: Vec<OutPoint>,
+}
+
+#[rocket::put("/utxos/freeze", data = "<request>")]
+#[instrument(name = "PUT /utxos/freeze", skip(taker, _user), err)]
+pub async fn put_freeze_utxos(
+    request: Json<UtxosRequest>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .freeze_utxos(request.into_inner().outpoints)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not freeze UTXOs")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::put("/utxos/unfreeze", data = "<request>")]
+#[instrument(name = "PUT /utxos/unfreeze", skip(taker, _user), err)]
+pub async fn put_unfreeze_utxos(
+    request: Json<UtxosRequest>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .unfreeze_utxos(request.into_inner().outpoints)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not unfreeze UTXOs")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::get("/cfds/archived")]
+#[instrument(name = "GET /cfds/archived", skip_all, err)]
+pub async fn get_archived_cfds(
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<Json<Vec<projection::Cfd>>, HttpApiProblem> {
+    let cfds = taker.archived_cfds().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not load archived CFDs")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(cfds))
+}
+
+/// Statistics of the market of a contract symbol, e.g. `BTCUSD`, over the last `window_hours`.
+#[rocket::get("/analytics/<symbol>?<window_hours>")]
+#[instrument(name = "GET /analytics/<symbol>", skip(taker, _user), err)]
+pub async fn get_market_statistics(
+    symbol: &str,
+    window_hours: Option<u32>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<Json<analytics::Statistics>, HttpApiProblem> {
+    let contract_symbol = analytics::parse_contract_symbol(symbol).ok_or_else(|| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid contract symbol")
+            .detail(format!("Unknown contract symbol {symbol}"))
+    })?;
+    let window = window_hours
+        .map(|hours| time::Duration::hours(hours.into()))
+        .unwrap_or(analytics::DEFAULT_WINDOW);
+
+    let statistics = taker
+        .market_statistics(contract_symbol, window)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not compute market statistics")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(Json(statistics))
+}
+
+/// Export all closed CFDs, including the archived ones, as CSV (the
+/// default) or JSON.
+#[rocket::get("/cfds/export?<format>")]
+#[instrument(name = "GET /cfds/export", skip(taker, fiat_rate_provider, _user), err)]
+pub async fn get_trade_history(
+    format: Option<&str>,
+    taker: &State<Taker>,
+    fiat_rate_provider: &State<Option<fiat_rates::Provider>>,
+    _user: User,
+) -> Result<(ContentType, String), HttpApiProblem> {
+    let format = format
+        .unwrap_or("csv")
+        .parse::<trade_history::Format>()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Invalid export format")
+                .detail(format!("{e:#}"))
+        })?;
+
+    let export = taker
+        .trade_history(fiat_rate_provider.inner().as_ref())
+        .await
+        .and_then(|trades| trade_history::render(&trades, format))
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not export trade history")
+                .detail(format!("{e:#}"))
+        })?;
+
+    let content_type = match format {
+        trade_history::Format::Csv => ContentType::CSV,
+        trade_history::Format::Json => ContentType::JSON,
+    };
+
+    Ok((content_type, export))
+}
+
+/// The lineage of a CFD: the offer it was created from, its events and the
+/// transactions published for it.
+#[rocket::get("/trace/<order_id>")]
+#[instrument(
+    name = "GET /trace/<order_id>",
+    skip(taker, block_explorer, _user),
+    err
+)]
+pub async fn get_trace(
+    order_id: Uuid,
+    taker: &State<Taker>,
+    block_explorer: &State<BlockExplorer>,
+    _user: User,
+) -> Result<Json<trace::Trace>, HttpApiProblem> {
+    let order_id = OrderId::from(order_id);
+
+    let trace = taker
+        .trace(order_id, block_explorer.inner())
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not load trace")
+                .detail(format!("{e:#}"))
+        })?
+        .ok_or_else(|| {
+            HttpApiProblem::new(StatusCode::NOT_FOUND)
+                .title("CFD not found")
+                .detail(format!("There is no CFD for order id {order_id}"))
+        })?;
+
+    Ok(Json(trace))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogDirectives {
+    /// Directives in the format of `RUST_LOG`, e.g. `xtra_libp2p=trace`
+    directives: String,
+}
+
+fn require_log_filter(log_filter: &Option<LogFilter>) -> Result<&LogFilter, HttpApiProblem> {
+    log_filter.as_ref().ok_or_else(|| {
+        HttpApiProblem::new(StatusCode::CONFLICT)
+            .title("Logging is turned off")
+            .detail("The log filter cannot be adjusted if the log level is `off`")
+    })
+}
+
+#[rocket::get("/log-filter")]
+#[instrument(name = "GET /log-filter", skip_all, err)]
+pub async fn get_log_filter(
+    log_filter_state: &State<Option<LogFilter>>,
+    _user: User,
+) -> Result<Json<LogDirectives>, HttpApiProblem> {
+    let directives = require_log_filter(log_filter_state)?
+        .current()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not read log filter")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(Json(LogDirectives { directives }))
+}
+
+/// Replace the log directives the daemon was started with. An empty
+/// string goes back to the directives it was started with.
+#[rocket::put("/log-filter", data = "<request>")]
+#[instrument(name = "PUT /log-filter", skip(log_filter_state, _user), err)]
+pub async fn put_log_filter(
+    request: Json<LogDirectives>,
+    log_filter_state: &State<Option<LogFilter>>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let log_filter = require_log_filter(log_filter_state)?;
+    let directives = request.into_inner().directives;
+
+    let result = if directives.trim().is_empty() {
+        log_filter.reset()
+    } else {
+        log_filter.reload(&directives)
+    };
+
+    result.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Could not reload log filter")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
+#[rocket::put("/cfd/<order_id>/rollover-policy", data = "<policy>")]
+#[instrument(name = "PUT /cfd/<order_id>/rollover-policy", skip(taker, _user), err)]
+pub async fn put_rollover_policy(
+    order_id: Uuid,
+    policy: Json<RolloverPolicy>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .set_rollover_policy(OrderId::from(order_id), policy.into_inner())
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not update rollover policy")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::get("/preferences")]
+#[instrument(name = "GET /preferences", skip(taker, _user), err)]
+pub async fn get_preferences(
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<Json<preferences::Preferences>, HttpApiProblem> {
+    let preferences = taker.preferences().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not load preferences")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(preferences))
+}
+
+#[rocket::put("/preferences", data = "<preferences>")]
+#[instrument(name = "PUT /preferences", skip(taker, _user), err)]
+pub async fn put_preferences(
+    preferences: Json<preferences::Preferences>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .set_preferences(preferences.into_inner())
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not update preferences")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+/// Project the fees of a CFD over the next `rollovers` rollovers.
+///
+/// Each `funding_rate` is applied to one rollover, the last one to all remaining rollovers. Without
+/// a schedule, the funding rate the maker currently offers is assumed to stay the same.
+#[rocket::get("/cfd/<order_id>/rollover-simulation?<rollovers>&<funding_rate>")]
+#[instrument(
+    name = "GET /cfd/<order_id>/rollover-simulation",
+    skip(rx, taker, _user),
+    err
+)]
+pub async fn get_rollover_simulation(
+    order_id: Uuid,
+    rollovers: Option<u32>,
+    funding_rate: Vec<String>,
+    rx: &State<FeedReceivers>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<Json<projection::RolloverSimulation>, HttpApiProblem> {
+    let order_id = OrderId::from(order_id);
+
+    let rollovers = rollovers.unwrap_or(DEFAULT_SIMULATED_ROLLOVERS);
+    if rollovers > MAX_SIMULATED_ROLLOVERS {
+        return Err(HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Too many rollovers")
+            .detail(format!(
+                "At most {MAX_SIMULATED_ROLLOVERS} rollovers can be simulated"
+            )));
+    }
+
+    let mut schedule = funding_rate
+        .iter()
+        .map(|rate| rate.parse::<FundingRate>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Invalid funding rate")
+                .detail(format!("{e:#}"))
+        })?;
+
+    let cfd = rx
+        .cfds
+        .borrow()
+        .iter()
+        .flatten()
+        .find(|cfd| cfd.order_id == order_id)
+        .cloned()
+        .ok_or_else(|| {
+            HttpApiProblem::new(StatusCode::NOT_FOUND)
+                .title("CFD not found")
+                .detail(format!("There is no open CFD with order id {order_id}"))
+        })?;
+
+    if schedule.is_empty() {
+        let current = taker
+            .current_funding_rate(cfd.contract_symbol, cfd.position.counter_position())
+            .await
+            .map_err(|e| {
+                HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                    .title("Could not get funding rate")
+                    .detail(format!("{e:#}"))
+            })?
+            .ok_or_else(|| {
+                HttpApiProblem::new(StatusCode::SERVICE_UNAVAILABLE)
+                    .title("Funding rate not available")
+                    .detail("The maker currently does not offer a funding rate for this CFD")
+            })?;
+
+        schedule.push(current);
+    }
+
+    let simulation = cfd.simulate_rollovers(&schedule, rollovers).map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not simulate rollovers")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(simulation))
+}
+
+/// The payouts of a CFD in the format of the DLC specifications.
+#[derive(Debug, Clone, Serialize)]
+pub struct DlcSpecsContract {
+    /// The olivia event on whose outcome the payouts depend
+    event_id: BitMexPriceEventId,
+    #[serde(with = "bdk::bitcoin::util::amount::serde::as_sat")]
+    total_collateral: Amount,
+    refund_timelock: u32,
+    /// Hex-encoded `contract_descriptor_v1` TLV, from the maker's point of view
+    contract_descriptor: String,
+}
+
+/// Export the payouts of a CFD as a numeric outcome contract descriptor of the DLC specifications,
+/// e.g. to audit them with other DLC tooling.
+#[rocket::get("/cfd/<order_id>/dlcspecs")]
+#[instrument(name = "GET /cfd/<order_id>/dlcspecs", skip(rx, _user), err)]
+pub async fn get_dlcspecs_contract(
+    order_id: Uuid,
+    rx: &State<FeedReceivers>,
+    _user: User,
+) -> Result<Json<DlcSpecsContract>, HttpApiProblem> {
+    let order_id = OrderId::from(order_id);
+
+    let dlc = rx
+        .cfds
+        .borrow()
+        .iter()
+        .flatten()
+        .find(|cfd| cfd.order_id == order_id)
+        .and_then(|cfd| cfd.dlc().cloned())
+        .ok_or_else(|| {
+            HttpApiProblem::new(StatusCode::NOT_FOUND)
+                .title("DLC not found")
+                .detail(format!(
+                    "There is no open CFD with a DLC for order id {order_id}"
+                ))
+        })?;
+
+    let contract = dlc
+        .cets
+        .get(&dlc.settlement_event_id)
+        .context("No CETs for settlement event")
+        .and_then(|cets| dlcspecs::NumericContract::from_cets(cets))
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not export contract")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(Json(DlcSpecsContract {
+        event_id: dlc.settlement_event_id,
+        total_collateral: dlc.maker_lock_amount + dlc.taker_lock_amount,
+        refund_timelock: dlc.refund_timelock,
+        contract_descriptor: hex::encode(contract.to_tlv()),
+    }))
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PriceTriggerRequest {
+    trigger_price: Price,
+}
+
+#[rocket::put("/cfd/<order_id>/stop-loss", data = "<stop_loss>")]
+#[instrument(name = "PUT /cfd/<order_id>/stop-loss", skip(taker, _user), err)]
+pub async fn put_stop_loss(
+    order_id: Uuid,
+    stop_loss: Json<PriceTriggerRequest>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .set_price_trigger(
+            OrderId::from(order_id),
+            PriceTrigger::StopLoss,
+            stop_loss.trigger_price,
+        )
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not set stop-loss")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::delete("/cfd/<order_id>/stop-loss")]
+#[instrument(name = "DELETE /cfd/<order_id>/stop-loss", skip(taker, _user), err)]
+pub async fn delete_stop_loss(
+    order_id: Uuid,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .remove_price_trigger(OrderId::from(order_id), PriceTrigger::StopLoss)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not remove stop-loss")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::put("/cfd/<order_id>/take-profit", data = "<take_profit>")]
+#[instrument(name = "PUT /cfd/<order_id>/take-profit", skip(taker, _user), err)]
+pub async fn put_take_profit(
+    order_id: Uuid,
+    take_profit: Json<PriceTriggerRequest>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .set_price_trigger(
+            OrderId::from(order_id),
+            PriceTrigger::TakeProfit,
+            take_profit.trigger_price,
+        )
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not set take-profit")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::delete("/cfd/<order_id>/take-profit")]
+#[instrument(name = "DELETE /cfd/<order_id>/take-profit", skip(taker, _user), err)]
+pub async fn delete_take_profit(
+    order_id: Uuid,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .remove_price_trigger(OrderId::from(order_id), PriceTrigger::TakeProfit)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not remove take-profit")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ScheduledSettlementResponse {
+    settle_at: Timestamp,
+}
+
+/// Settle the CFD at a point in time, e.g. `{"at": 1666000000}`, or before the next rollover with
+/// `"next_funding_window"`.
+#[rocket::put("/cfd/<order_id>/scheduled-settlement", data = "<schedule>")]
+#[instrument(
+    name = "PUT /cfd/<order_id>/scheduled-settlement",
+    skip(taker, _user),
+    err
+)]
+pub async fn put_scheduled_settlement(
+    order_id: Uuid,
+    schedule: Json<scheduled_settlement::Schedule>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<Json<ScheduledSettlementResponse>, HttpApiProblem> {
+    let settle_at = taker
+        .schedule_settlement(OrderId::from(order_id), schedule.into_inner())
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not schedule settlement")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(Json(ScheduledSettlementResponse { settle_at }))
+}
+
+#[rocket::delete("/cfd/<order_id>/scheduled-settlement")]
+#[instrument(
+    name = "DELETE /cfd/<order_id>/scheduled-settlement",
+    skip(taker, _user),
+    err
+)]
+pub async fn delete_scheduled_settlement(
+    order_id: Uuid,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .cancel_scheduled_settlement(OrderId::from(order_id))
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Could not cancel scheduled settlement")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::put("/offer-filter", data = "<filter>")]
+#[instrument(name = "PUT /offer-filter", skip_all)]
+pub async fn put_offer_filter(filter: Json<OfferFilter>, taker: &State<Taker>, _user: User) {
+    taker.set_offer_filter(filter.into_inner());
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MakerAddressRequest {
+    /// The multiaddr of the maker, including its peer id
+    address: String,
+}
+
+#[rocket::put("/maker-address", data = "<request>")]
+#[instrument(name = "PUT /maker-address", skip(taker, _user), err)]
+pub async fn put_maker_address(
+    request: Json<MakerAddressRequest>,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    let address = request
+        .into_inner()
+        .address
+        .parse::<Multiaddr>()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Invalid maker address")
+                .detail(format!("{e:#}"))
+        })?;
+
+    taker.update_maker_address(address).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Could not update maker address")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
+/// Trust the identity of the maker after it changed from the one pinned
+/// on first use.
+#[rocket::post("/maker-identity/trust")]
+#[instrument(name = "POST /maker-identity/trust", skip_all, err)]
+pub async fn post_trust_maker_identity(
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker.trust_maker_identity().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not trust identity of maker")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
+#[rocket::get("/pending-requests")]
+#[instrument(name = "GET /pending-requests", skip_all, err)]
+pub async fn get_pending_requests(
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<Json<Vec<PendingRequest>>, HttpApiProblem> {
+    let requests = taker.pending_requests().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not list pending requests")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(requests))
+}
+
+#[rocket::delete("/pending-requests/<order_id>")]
+#[instrument(name = "DELETE /pending-requests/<order_id>", skip(taker, _user), err)]
+pub async fn delete_pending_request(
+    order_id: Uuid,
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker
+        .cancel_pending_request(OrderId::from(order_id))
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Could not cancel pending request")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::get("/risk")]
+#[instrument(name = "GET /risk", skip_all, err)]
+pub async fn get_risk_status(
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<Json<risk_limits::RiskStatus>, HttpApiProblem> {
+    let status = taker.risk_status().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not get risk status")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(status))
+}
+
+#[rocket::post("/risk/override")]
+#[instrument(name = "POST /risk/override", skip_all, err)]
+pub async fn post_override_loss_limit(
+    taker: &State<Taker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    taker.override_loss_limit().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not override loss limit")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct HealthCheck {
     daemon_version: String,
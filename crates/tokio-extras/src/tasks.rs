@@ -6,10 +6,12 @@ use tracing::Instrument;
 
 #[cfg(feature = "xtra")]
 pub use actor_scoped::*;
+pub use cancellable::*;
 pub use task_map::*;
 
 #[cfg(feature = "xtra")]
 mod actor_scoped;
+mod cancellable;
 mod task_map;
 
 /// Struct controlling the lifetime of the async tasks, such as
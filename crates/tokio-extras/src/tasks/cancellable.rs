@@ -0,0 +1,279 @@
+use crate::future_ext::FutureExt;
+use futures::future::RemoteHandle;
+use futures::Future;
+use futures::FutureExt as _;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+use std::hash;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tracing::Instrument;
+
+const PENDING: u8 = 0;
+const COMMITTED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// Struct controlling the lifetime of tasks which can be cancelled
+/// until they reach a point of no return.
+///
+/// Each task is handed a [`CommitToken`]. Before doing anything that
+/// must not be interrupted, the task has to [`CommitToken::commit`].
+/// From then on [`CancellableTasks::cancel`] refuses to stop it.
+///
+/// Every task is stored alongside a value describing it, which can be
+/// queried as long as the task is still running.
+pub struct CancellableTasks<K, V>(HashMap<K, Entry<V>>);
+
+struct Entry<V> {
+    value: V,
+    state: Arc<AtomicU8>,
+    handle: RemoteHandle<()>,
+}
+
+impl<K, V> CancellableTasks<K, V>
+where
+    K: Eq + hash::Hash,
+{
+    /// Spawn the task on the runtime and remember its handle.
+    ///
+    /// The task will be stopped if this instance of
+    /// [`CancellableTasks`] goes out of scope. If the task fails, the
+    /// `err_handler` will be invoked.
+    ///
+    /// Fails without spawning the task if a task with the same `key`
+    /// is still running, which is left untouched.
+    pub fn add_fallible<F, E, EF>(
+        &mut self,
+        key: K,
+        value: V,
+        task: impl FnOnce(CommitToken) -> F,
+        err_handler: impl FnOnce(E) -> EF + Send + 'static,
+    ) -> Result<(), AlreadyRunning>
+    where
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + Send + 'static,
+        EF: Future<Output = ()> + Send + 'static,
+    {
+        self.remove_finished();
+
+        if self.0.contains_key(&key) {
+            return Err(AlreadyRunning);
+        }
+
+        let state = Arc::new(AtomicU8::new(PENDING));
+        let task = task(CommitToken(state.clone()));
+
+        let fut = async move {
+            match task.await {
+                Ok(()) => {}
+                Err(err) => {
+                    let span = tracing::error_span!("fallible task handle_error", %err);
+                    err_handler(err).instrument(span).await
+                }
+            }
+        };
+
+        let handle = fut.spawn_with_handle();
+        self.0.insert(
+            key,
+            Entry {
+                value,
+                state,
+                handle,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// All tasks which are still running and can be cancelled.
+    pub fn cancellable(&mut self) -> impl Iterator<Item = (&K, &V)> {
+        self.remove_finished();
+
+        self.0
+            .iter()
+            .filter(|(_, entry)| entry.state.load(Ordering::SeqCst) == PENDING)
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Cancel a task which has not committed yet.
+    ///
+    /// Returns the value describing the task if it was cancelled and
+    /// `None` if there is no such task or it can no longer be
+    /// cancelled.
+    pub fn cancel(&mut self, key: &K) -> Option<V> {
+        self.remove_finished();
+
+        let entry = self.0.get(key)?;
+        entry
+            .state
+            .compare_exchange(PENDING, CANCELLED, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()?;
+
+        // Dropping the `RemoteHandle` stops the task
+        self.0.remove(key).map(|entry| entry.value)
+    }
+
+    fn remove_finished(&mut self) {
+        self.0
+            .retain(|_, entry| (&mut entry.handle).now_or_never().is_none());
+    }
+}
+
+impl<K, V> Default for CancellableTasks<K, V> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+/// Handed to a task spawned via [`CancellableTasks`] to mark its
+/// point of no return.
+pub struct CommitToken(Arc<AtomicU8>);
+
+impl CommitToken {
+    /// Prevent the task from being cancelled from now on.
+    ///
+    /// Fails if the task has already been cancelled.
+    pub fn commit(&self) -> Result<(), Cancelled> {
+        match self
+            .0
+            .compare_exchange(PENDING, COMMITTED, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) | Err(COMMITTED) => Ok(()),
+            Err(_) => Err(Cancelled),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Task was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AlreadyRunning;
+
+impl fmt::Display for AlreadyRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Task is already running")
+    }
+}
+
+impl std::error::Error for AlreadyRunning {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn pending_task_can_be_cancelled() {
+        let mut tasks = CancellableTasks::default();
+
+        tasks
+            .add_fallible(
+                1,
+                "task",
+                |_| futures::future::pending::<Result<(), Cancelled>>(),
+                |_| async {},
+            )
+            .unwrap();
+
+        assert_eq!(tasks.cancellable().count(), 1);
+        assert_eq!(tasks.cancel(&1), Some("task"));
+        assert_eq!(tasks.cancellable().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn committed_task_cannot_be_cancelled() {
+        let mut tasks = CancellableTasks::default();
+        let (tx, rx) = futures::channel::oneshot::channel();
+
+        tasks
+            .add_fallible(
+                1,
+                "task",
+                |token| async move {
+                    token.commit()?;
+                    tx.send(()).unwrap();
+                    futures::future::pending::<()>().await;
+
+                    Ok::<_, Cancelled>(())
+                },
+                |_| async {},
+            )
+            .unwrap();
+        rx.await.unwrap();
+
+        assert_eq!(tasks.cancellable().count(), 0);
+        assert_eq!(tasks.cancel(&1), None);
+    }
+
+    #[tokio::test]
+    async fn finished_task_is_removed() {
+        let mut tasks = CancellableTasks::default();
+
+        tasks
+            .add_fallible(
+                1,
+                "task",
+                |_| async { Ok::<_, Cancelled>(()) },
+                |_| async {},
+            )
+            .unwrap();
+        crate::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(tasks.cancellable().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn adding_task_with_key_of_running_task_keeps_running_task() {
+        let mut tasks = CancellableTasks::default();
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+
+        tasks
+            .add_fallible(
+                1,
+                "first",
+                |_| async move {
+                    rx.await.unwrap();
+
+                    Ok::<_, Cancelled>(())
+                },
+                |_| async {},
+            )
+            .unwrap();
+        let result = tasks.add_fallible(
+            1,
+            "second",
+            |_| async { Ok::<_, Cancelled>(()) },
+            |_| async {},
+        );
+
+        assert!(result.is_err());
+        assert_eq!(
+            tasks.cancellable().collect::<Vec<_>>(),
+            vec![(&1, &"first")]
+        );
+
+        tx.send(()).unwrap();
+        crate::time::sleep(Duration::from_millis(100)).await;
+
+        tasks
+            .add_fallible(
+                1,
+                "third",
+                |_| futures::future::pending::<Result<(), Cancelled>>(),
+                |_| async {},
+            )
+            .unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+//! A watchtower which is run independently of the maker.
+//!
+//! The maker also acts as a watchtower, but it is the party whose revoked
+//! commit transactions a taker needs to punish. Takers point
+//! `--watchtower` at this binary instead, e.g. run by themselves on an
+//! always-on machine or by a third party.
+
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use daemon::electrum;
+use daemon::libp2p_utils;
+use daemon::seed::RandomSeed;
+use daemon::seed::Seed;
+use daemon::watchtower;
+use daemon::ENDPOINT_CONNECTION_TIMEOUT;
+use daemon::RESTART_INTERVAL;
+use shared_bin::logger;
+use shared_bin::logger::LevelFilter;
+use shared_bin::logger::LogFormat;
+use shared_bin::logger::LOCAL_COLLECTOR_ENDPOINT;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio_extras::Tasks;
+use xtra::Context as XtraContext;
+use xtra_libp2p::endpoint;
+use xtra_libp2p::listener;
+use xtra_libp2p::Endpoint;
+use xtras::supervisor::always_restart_after;
+use xtras::supervisor::Supervisor;
+
+#[derive(Parser)]
+struct Opts {
+    /// The port to listen on for takers uploading justice kits.
+    #[clap(long, default_value = "10000")]
+    p2p_port: u16,
+
+    /// The Electrum server to watch for revoked commit transactions and
+    /// to publish punish transactions with.
+    #[clap(long)]
+    electrum: String,
+
+    /// Where to permanently store data, defaults to the current working directory.
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Configure the log level, e.g.: one of Error, Warn, Info, Debug, Trace
+    #[clap(short, long, default_value = "Info")]
+    log_level: LevelFilter,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opts = Opts::parse();
+
+    let data_dir = opts
+        .data_dir
+        .unwrap_or_else(|| std::env::current_dir().expect("unable to get cwd"));
+
+    if !data_dir.exists() {
+        tokio::fs::create_dir_all(&data_dir).await?;
+    }
+
+    let _logging = logger::init(
+        opts.log_level,
+        LogFormat::Text,
+        false,
+        false,
+        false,
+        false,
+        "watchtower",
+        LOCAL_COLLECTOR_ENDPOINT,
+        false,
+        data_dir.to_str().expect("missing data dir"),
+    )
+    .context("initialize logger")?;
+    tracing::info!("Running version: {}", daemon::version());
+
+    let seed = RandomSeed::initialize(&data_dir.join("watchtower_seed")).await?;
+    let identities = seed.derive_identities();
+
+    let db = sqlite_db::connect(data_dir.join("watchtower.sqlite"), false).await?;
+    let active_electrum = electrum::ActiveServer::fixed(opts.electrum);
+
+    let listen_multiaddr = libp2p_utils::create_listen_tcp_multiaddr(
+        &IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        opts.p2p_port,
+    )?;
+
+    let mut tasks = Tasks::default();
+    let (endpoint_addr, endpoint_context) = XtraContext::new(None);
+
+    let (tower_supervisor, tower_actor) = Supervisor::new({
+        let db = db.clone();
+        move || watchtower::tower::Actor::new(db.clone(), active_electrum.clone())
+    });
+    tasks.add(tower_supervisor.run_log_summary());
+
+    let (listener_supervisor, listener_actor) = Supervisor::<_, listener::Error>::with_policy(
+        {
+            let endpoint_addr = endpoint_addr.clone();
+            let listen_multiaddr = listen_multiaddr.clone();
+            move || listener::Actor::new(endpoint_addr.clone(), listen_multiaddr.clone())
+        },
+        always_restart_after(RESTART_INTERVAL),
+    );
+    tasks.add(listener_supervisor.run_log_summary());
+
+    let endpoint = Endpoint::new(
+        Box::new(libp2p_utils::tcp_or_websocket_transport),
+        identities.libp2p.clone(),
+        ENDPOINT_CONNECTION_TIMEOUT,
+        [(watchtower::PROTOCOL, tower_actor.into())],
+        endpoint::Subscribers::new(vec![], vec![], vec![], vec![listener_actor.into()]),
+        Arc::new(HashSet::default()),
+    );
+    tasks.add(endpoint_context.run(endpoint));
+
+    tracing::info!(
+        "Listening on {listen_multiaddr}, takers can upload with `--watchtower /<address>/tcp/{}/p2p/{}`",
+        opts.p2p_port,
+        identities.peer_id()
+    );
+
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! A minimal client for the JSON-RPC interface of bitcoind.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::json;
+use serde_json::Value;
+
+pub struct Rpc {
+    client: reqwest::blocking::Client,
+    url: String,
+    user: String,
+    password: String,
+}
+
+impl Rpc {
+    pub fn new(port: u16, user: &str, password: &str) -> Self {
+        Self {
+            client: reqwest::blocking::Client::new(),
+            url: format!("http://127.0.0.1:{port}"),
+            user: user.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    /// The client for the calls to the wallet `name`.
+    pub fn wallet(&self, name: &str) -> Self {
+        Self {
+            client: self.client.clone(),
+            url: format!("{}/wallet/{name}", self.url),
+            user: self.user.clone(),
+            password: self.password.clone(),
+        }
+    }
+
+    pub fn call(&self, method: &str, params: Value) -> Result<Value> {
+        // bitcoind responds with an error status and the error in the
+        // body, so the status is not checked
+        let response = self
+            .client
+            .post(&self.url)
+            .basic_auth(&self.user, Some(&self.password))
+            .json(&json!({
+                "jsonrpc": "1.0",
+                "id": "xtask",
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .with_context(|| format!("Failed to call `{method}`"))?
+            .json::<Value>()
+            .with_context(|| format!("Invalid response to `{method}`"))?;
+
+        if !response["error"].is_null() {
+            bail!("`{method}` failed: {}", response["error"]);
+        }
+
+        Ok(response["result"].clone())
+    }
+
+    pub fn block_count(&self) -> Result<u64> {
+        self.call("getblockcount", json!([]))?
+            .as_u64()
+            .context("Block count is not a number")
+    }
+
+    /// Mine `blocks` blocks, paying the block rewards to `address`.
+    pub fn mine(&self, blocks: u64, address: &str) -> Result<()> {
+        self.call("generatetoaddress", json!([blocks, address]))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,400 @@
+//! A local development environment on regtest.
+//!
+//! Starts bitcoind, electrs, the dev oracle, a maker and a taker, and
+//! mines a block at an interval so that CFDs progress on their own. On
+//! a new chain the wallets of the maker and the taker are funded, and
+//! as their keys are deterministic, they stay funded across restarts.
+//!
+//! bitcoind and electrs (0.9 or later) have to be installed. All data
+//! and the logs of every process are kept in `target/dev-env`. Pressing
+//! Ctrl+C stops all processes.
+//!
+//! To exercise the force-close paths, stop the maker or the taker and
+//! mine the blocks needed for the timelocks to expire, e.g.
+//!
+//! ```text
+//! bitcoin-cli -regtest -rpcuser=dev -rpcpassword=dev -generate 144
+//! ```
+//!
+//! and change the price the dev oracle attests to, to hit the
+//! liquidation price of a CFD.
+
+use crate::bitcoind::Rpc;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::hashes::Hash;
+use bdk::bitcoin::util::bip32::ExtendedPrivKey;
+use bdk::bitcoin::Address;
+use bdk::bitcoin::Network;
+use bdk::database::MemoryDatabase;
+use bdk::template::Bip84;
+use bdk::wallet::AddressIndex;
+use bdk::KeychainKind;
+use clap::Parser;
+use serde_json::json;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::net::TcpStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+const RPC_USER: &str = "dev";
+const RPC_PASSWORD: &str = "dev";
+const BITCOIND_RPC_PORT: u16 = 18443;
+const BITCOIND_P2P_PORT: u16 = 18444;
+const ELECTRUM_ADDRESS: &str = "127.0.0.1:60401";
+const ORACLE_ADDRESS: &str = "127.0.0.1:8100";
+const MAKER_HTTP_ADDRESS: &str = "127.0.0.1:8001";
+const MAKER_P2P_PORT: u16 = 10000;
+const TAKER_HTTP_ADDRESS: &str = "127.0.0.1:8000";
+
+/// The password of the maker's and the taker's HTTP API
+const PASSWORD: &str = "dev";
+
+/// The wallet of bitcoind receiving the block rewards
+const MINING_WALLET: &str = "mining";
+
+/// The identity derived from `testnet/maker_seed`, which the maker is
+/// started with
+const MAKER_ID: &str = "10d4ba2ac3f7a22da4009d813ff1bc3f404dfe2cc93a32bedf1512aa9951c95e";
+const MAKER_PEER_ID: &str = "12D3KooWDjzHna3pNi1Bt1DoRfrpsBREykJKXDRDxXvhJNAdDZEk";
+
+/// How many UTXOs each wallet is funded with, so that several CFDs can
+/// be opened without waiting for change outputs to confirm
+const FUNDING_UTXOS: u32 = 5;
+
+/// How long to wait for a process to become ready
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Parser)]
+pub struct Opts {
+    /// Where to keep the data of all processes, defaults to `target/dev-env`.
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Delete the data of previous runs, starting over with a new chain.
+    #[clap(long)]
+    reset: bool,
+
+    /// How often to mine a block, in seconds.
+    #[clap(long, default_value = "30")]
+    block_interval_secs: u64,
+
+    /// How many bitcoin each of the wallets of the maker and the taker is funded with.
+    #[clap(long, default_value = "10")]
+    funding_btc: u32,
+
+    /// The bitcoind binary to run.
+    #[clap(long, default_value = "bitcoind")]
+    bitcoind: PathBuf,
+
+    /// The electrs binary to run.
+    #[clap(long, default_value = "electrs")]
+    electrs: PathBuf,
+}
+
+pub fn run(opts: Opts) -> Result<()> {
+    let repo_root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let data_dir = opts
+        .data_dir
+        .clone()
+        .unwrap_or_else(|| repo_root.join("target/dev-env"));
+
+    if opts.reset && data_dir.exists() {
+        tracing::info!(data_dir = %data_dir.display(), "Deleting data of previous runs");
+        fs::remove_dir_all(&data_dir)?;
+    }
+
+    let mut processes = Processes::new(data_dir.join("logs"))?;
+
+    let bitcoind_dir = data_dir.join("bitcoind");
+    fs::create_dir_all(&bitcoind_dir)?;
+    processes.spawn(
+        "bitcoind",
+        Command::new(&opts.bitcoind)
+            .arg("-regtest")
+            .arg(format!("-datadir={}", bitcoind_dir.display()))
+            .arg(format!("-rpcuser={RPC_USER}"))
+            .arg(format!("-rpcpassword={RPC_PASSWORD}"))
+            .arg(format!("-rpcport={BITCOIND_RPC_PORT}"))
+            .arg(format!("-port={BITCOIND_P2P_PORT}"))
+            .args(["-server", "-fallbackfee=0.0001", "-printtoconsole"]),
+    )?;
+
+    let rpc = Rpc::new(BITCOIND_RPC_PORT, RPC_USER, RPC_PASSWORD);
+    let height = wait_for("bitcoind", || rpc.block_count().ok())?;
+    let wallet = load_mining_wallet(&rpc)?;
+    let mining_address = wallet
+        .call("getnewaddress", json!([]))?
+        .as_str()
+        .context("Address is not a string")?
+        .to_owned();
+
+    let maker_xprv = wallet_xprv("maker")?;
+    let taker_xprv = wallet_xprv("taker")?;
+
+    if height == 0 {
+        // The block rewards can only be spent after 100 confirmations
+        wallet.mine(101, &mining_address)?;
+
+        for (name, xprv) in [("maker", maker_xprv), ("taker", taker_xprv)] {
+            tracing::info!(%name, funding_btc = opts.funding_btc, "Funding wallet");
+            fund(&wallet, xprv, opts.funding_btc)?;
+        }
+
+        wallet.mine(1, &mining_address)?;
+    }
+
+    processes.spawn(
+        "electrs",
+        Command::new(&opts.electrs)
+            .args(["--network", "regtest"])
+            .arg("--daemon-dir")
+            .arg(&bitcoind_dir)
+            .arg("--db-dir")
+            .arg(data_dir.join("electrs"))
+            .args(["--electrum-rpc-addr", ELECTRUM_ADDRESS])
+            .arg("--daemon-rpc-addr")
+            .arg(format!("127.0.0.1:{BITCOIND_RPC_PORT}"))
+            .arg("--daemon-p2p-addr")
+            .arg(format!("127.0.0.1:{BITCOIND_P2P_PORT}"))
+            .arg("--auth")
+            .arg(format!("{RPC_USER}:{RPC_PASSWORD}")),
+    )?;
+    wait_for("electrs", || TcpStream::connect(ELECTRUM_ADDRESS).ok())?;
+
+    let bin_dir = build_binaries(&repo_root)?;
+    let electrum = format!("tcp://{ELECTRUM_ADDRESS}");
+
+    processes.spawn(
+        "dev-oracle",
+        Command::new(bin_dir.join("dev-oracle")).args(["--http-address", ORACLE_ADDRESS]),
+    )?;
+    let oracle_url = format!("http://{ORACLE_ADDRESS}");
+    let oracle_pk = wait_for("dev-oracle", || {
+        let info = reqwest::blocking::get(&oracle_url)
+            .ok()?
+            .json::<Value>()
+            .ok()?;
+
+        info["public_key"].as_str().map(str::to_owned)
+    })?;
+
+    let maker_dir = data_dir.join("maker");
+    let maker_seed = maker_dir.join("regtest/maker_seed");
+    if !maker_seed.exists() {
+        fs::create_dir_all(maker_dir.join("regtest"))?;
+        fs::copy(repo_root.join("testnet/maker_seed"), &maker_seed)
+            .context("Failed to copy the maker seed")?;
+    }
+
+    processes.spawn(
+        "maker",
+        Command::new(bin_dir.join("maker"))
+            .args(["--password", PASSWORD, "--headless"])
+            .args(["--http-address", MAKER_HTTP_ADDRESS])
+            .args(["--p2p-port", &MAKER_P2P_PORT.to_string()])
+            .arg("--data-dir")
+            .arg(&maker_dir)
+            .args(["--wallet-xprv", &maker_xprv.to_string()])
+            .args(["--oracle-url", &oracle_url, "--oracle-pk", &oracle_pk])
+            .args(["regtest", "--electrum", &electrum]),
+    )?;
+
+    processes.spawn(
+        "taker",
+        Command::new(bin_dir.join("taker"))
+            .args(["--password", PASSWORD, "--headless"])
+            .args(["--http-address", TAKER_HTTP_ADDRESS])
+            .args(["--maker", &format!("127.0.0.1:{MAKER_P2P_PORT}")])
+            .args(["--maker-id", MAKER_ID, "--maker-peer-id", MAKER_PEER_ID])
+            .arg("--data-dir")
+            .arg(data_dir.join("taker"))
+            .args(["--wallet-xprv", &taker_xprv.to_string()])
+            .args(["--oracle-url", &oracle_url, "--oracle-pk", &oracle_pk])
+            .args(["regtest", "--electrum", &electrum]),
+    )?;
+
+    tracing::info!(
+        "Maker at http://{MAKER_HTTP_ADDRESS}, taker at http://{TAKER_HTTP_ADDRESS}, \
+         both with password `{PASSWORD}`"
+    );
+    tracing::info!(
+        logs = %processes.log_dir.display(),
+        "Mining a block every {}s, press Ctrl+C to stop",
+        opts.block_interval_secs
+    );
+
+    loop {
+        thread::sleep(Duration::from_secs(opts.block_interval_secs));
+
+        processes.ensure_running()?;
+        wallet.mine(1, &mining_address)?;
+    }
+}
+
+/// Load the wallet receiving the block rewards, creating it on a new
+/// chain.
+fn load_mining_wallet(rpc: &Rpc) -> Result<Rpc> {
+    if rpc.call("loadwallet", json!([MINING_WALLET])).is_err() {
+        rpc.call("createwallet", json!([MINING_WALLET]))?;
+    }
+
+    Ok(rpc.wallet(MINING_WALLET))
+}
+
+/// The key of the wallet of `name`.
+///
+/// The key is derived from the name, so that the wallets are the same
+/// across restarts and only have to be funded once.
+fn wallet_xprv(name: &str) -> Result<ExtendedPrivKey> {
+    let seed = sha256::Hash::hash(format!("xtask-dev/{name}").as_bytes());
+
+    Ok(ExtendedPrivKey::new_master(Network::Regtest, &seed[..])?)
+}
+
+/// Send `amount_btc` to the wallet of `xprv`, spread over
+/// [`FUNDING_UTXOS`] addresses.
+fn fund(wallet: &Rpc, xprv: ExtendedPrivKey, amount_btc: u32) -> Result<()> {
+    let amount_per_utxo = f64::from(amount_btc) / f64::from(FUNDING_UTXOS);
+
+    for address in wallet_addresses(xprv, FUNDING_UTXOS)? {
+        wallet.call(
+            "sendtoaddress",
+            json!([address.to_string(), amount_per_utxo]),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The first `count` receiving addresses of the wallet of `xprv`, as
+/// derived by the daemons.
+fn wallet_addresses(xprv: ExtendedPrivKey, count: u32) -> Result<Vec<Address>> {
+    let wallet = bdk::Wallet::new(
+        Bip84(xprv, KeychainKind::External),
+        Some(Bip84(xprv, KeychainKind::Internal)),
+        Network::Regtest,
+        MemoryDatabase::default(),
+    )?;
+
+    (0..count)
+        .map(|index| Ok(wallet.get_address(AddressIndex::Peek(index))?.address))
+        .collect()
+}
+
+/// Build the binaries of the dev oracle, the maker and the taker,
+/// returning the directory they are in.
+fn build_binaries(repo_root: &Path) -> Result<PathBuf> {
+    tracing::info!("Building binaries");
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned());
+    let status = Command::new(cargo)
+        .args([
+            "build",
+            "--bin",
+            "dev-oracle",
+            "--bin",
+            "maker",
+            "--bin",
+            "taker",
+        ])
+        .current_dir(repo_root)
+        .status()?;
+    ensure!(status.success(), "Failed to build binaries");
+
+    let target_dir = env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| repo_root.join("target"));
+
+    Ok(target_dir.join("debug"))
+}
+
+/// Call `is_ready` until it returns a value.
+fn wait_for<T>(name: &str, mut is_ready: impl FnMut() -> Option<T>) -> Result<T> {
+    let started_at = Instant::now();
+
+    loop {
+        if let Some(value) = is_ready() {
+            return Ok(value);
+        }
+
+        ensure!(
+            started_at.elapsed() < STARTUP_TIMEOUT,
+            "{name} did not become ready within {}s",
+            STARTUP_TIMEOUT.as_secs()
+        );
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+/// The processes of the environment, which are killed once it is
+/// dropped.
+struct Processes {
+    log_dir: PathBuf,
+    children: Vec<(&'static str, Child)>,
+}
+
+impl Processes {
+    fn new(log_dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&log_dir)?;
+
+        Ok(Self {
+            log_dir,
+            children: Vec::new(),
+        })
+    }
+
+    /// Spawn `command`, writing its output to `<name>.log`.
+    fn spawn(&mut self, name: &'static str, command: &mut Command) -> Result<()> {
+        let log = File::create(self.log_file(name))?;
+        let child = command
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()
+            .with_context(|| format!("Failed to start {name}"))?;
+
+        tracing::info!(%name, pid = child.id(), "Started");
+        self.children.push((name, child));
+
+        Ok(())
+    }
+
+    /// Fail if any of the processes exited.
+    fn ensure_running(&mut self) -> Result<()> {
+        for (name, child) in self.children.iter_mut() {
+            if let Some(status) = child.try_wait()? {
+                let log_file = self.log_dir.join(format!("{name}.log"));
+                bail!("{name} exited with {status}, see {}", log_file.display());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn log_file(&self, name: &str) -> PathBuf {
+        self.log_dir.join(format!("{name}.log"))
+    }
+}
+
+impl Drop for Processes {
+    fn drop(&mut self) {
+        // Stop the daemons before the services they depend on
+        for (name, child) in self.children.iter_mut().rev() {
+            if let Err(e) = child.kill() {
+                tracing::warn!(%name, "Failed to stop process: {e:#}");
+            }
+            let _ = child.wait();
+        }
+    }
+}
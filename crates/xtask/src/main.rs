@@ -0,0 +1,24 @@
+//! Tasks for developing on this repository, run with `cargo xtask <task>`.
+
+mod bitcoind;
+mod dev;
+
+use anyhow::Result;
+use clap::Parser;
+use tracing_subscriber::filter::LevelFilter;
+
+#[derive(Parser)]
+enum Task {
+    /// Run bitcoind, electrs, the dev oracle, a maker and a taker on regtest.
+    Dev(dev::Opts),
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::INFO)
+        .init();
+
+    match Task::parse() {
+        Task::Dev(opts) => dev::run(opts),
+    }
+}
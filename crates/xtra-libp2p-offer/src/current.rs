@@ -1,5 +1,52 @@
+use asynchronous_codec::FramedRead;
+use asynchronous_codec::JsonCodec;
+use model::OfferId;
+use serde::Deserialize;
+use serde::Serialize;
+use xtra_libp2p::recorder::ensure_replay_finished;
+use xtra_libp2p::recorder::ReplayedSubstream;
+
 pub mod maker;
 mod protocol;
 pub mod taker;
 
-pub const PROTOCOL: &str = "/itchysats/offer/2.0.0";
+pub const PROTOCOL: &str = "/itchysats/offer/2.1.0";
+
+/// The version of the protocol which predates fee subsidies.
+///
+/// Takers which only speak this version are sent the fees they are
+/// actually charged instead.
+pub const PROTOCOL_WITHOUT_FEE_SUBSIDY: &str = "/itchysats/offer/2.0.0";
+
+/// Why an offer can no longer be taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+pub enum OfferUnavailable {
+    #[error("Offer {offer_id} was superseded by offer {superseded_by}")]
+    Superseded {
+        offer_id: OfferId,
+        superseded_by: OfferId,
+    },
+    #[error("Offer {0} has expired")]
+    Expired(OfferId),
+    #[error("Offer {0} not found in current offers")]
+    NotFound(OfferId),
+}
+
+/// Run the maker's handling of a recorded substream on the frames the
+/// taker sent.
+///
+/// The maker opens a substream to push its offers, the taker never
+/// sends anything.
+pub async fn replay_dialer(stream: ReplayedSubstream) -> anyhow::Result<()> {
+    let mut framed = FramedRead::new(stream, JsonCodec::<(), serde_json::Value>::new());
+    ensure_replay_finished(&mut framed).await
+}
+
+/// Run the taker's handling of a recorded substream on the frames the
+/// maker sent.
+pub async fn replay_listener(stream: ReplayedSubstream) -> anyhow::Result<()> {
+    match protocol::recv(stream).await {
+        Ok(_) | Err(protocol::ReceiveError::Terminated) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
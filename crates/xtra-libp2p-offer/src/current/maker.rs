@@ -1,11 +1,18 @@
 use crate::current::protocol;
+use crate::current::OfferUnavailable;
 use crate::current::PROTOCOL;
+use crate::current::PROTOCOL_WITHOUT_FEE_SUBSIDY;
 use async_trait::async_trait;
 use model::ContractSymbol;
+use model::OfferId;
+use model::OfferPair;
+use model::OfferTier;
 use model::Position;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::time::Duration;
+use time::OffsetDateTime;
 use tokio_extras::spawn_fallible;
 use tracing::Instrument;
 use xtra_libp2p::endpoint;
@@ -15,10 +22,16 @@ use xtra_libp2p::GetConnectionStats;
 use xtra_libp2p::OpenSubstream;
 use xtra_productivity::xtra_productivity;
 
+/// How many superseded offers to remember, so that orders referencing
+/// them can be rejected with a meaningful error.
+const MAX_SUPERSEDED_OFFERS: usize = 100;
+
 pub struct Actor {
     endpoint: xtra::Address<Endpoint>,
     connected_peers: HashSet<PeerId>,
-    current_offers: Offers,
+    /// The tiers of the takers which are not shown the public offers
+    tiers: HashMap<PeerId, OfferTier>,
+    current_offers: TieredOffers,
 }
 
 impl Actor {
@@ -26,10 +39,25 @@ impl Actor {
         Self {
             endpoint,
             connected_peers: HashSet::default(),
-            current_offers: Offers::default(),
+            tiers: HashMap::default(),
+            current_offers: TieredOffers::default(),
         }
     }
 
+    #[must_use]
+    pub fn with_tiers(self, tiers: HashMap<PeerId, OfferTier>) -> Self {
+        let tiers = tiers
+            .into_iter()
+            .filter(|(_, tier)| *tier != OfferTier::Public)
+            .collect();
+
+        Self { tiers, ..self }
+    }
+
+    fn tier(&self, peer_id: PeerId) -> OfferTier {
+        self.tiers.get(&peer_id).copied().unwrap_or_default()
+    }
+
     #[tracing::instrument(name = "Broadcast offers to taker", skip(self, offers, ctx))]
     async fn send_offers(
         &self,
@@ -40,12 +68,20 @@ impl Actor {
         let endpoint = self.endpoint.clone();
 
         let task = async move {
-            let stream = endpoint
-                .send(OpenSubstream::single_protocol(peer_id, PROTOCOL))
+            let (negotiated, stream) = endpoint
+                .send(OpenSubstream::multiple_protocols(
+                    peer_id,
+                    vec![PROTOCOL, PROTOCOL_WITHOUT_FEE_SUBSIDY],
+                ))
                 .await??
                 .await?;
 
-            protocol::send(stream, offers.into()).await?;
+            let offers = match negotiated {
+                PROTOCOL_WITHOUT_FEE_SUBSIDY => protocol::Offers::with_subsidised_fees(offers),
+                _ => offers.into(),
+            };
+
+            protocol::send(stream, offers).await?;
 
             anyhow::Ok(())
         };
@@ -74,18 +110,55 @@ impl Actor {
 #[xtra_productivity]
 impl Actor {
     async fn handle(&mut self, msg: NewOffers, ctx: &mut xtra::Context<Self>) {
-        self.current_offers.update(msg.0.clone());
+        let NewOffers { offers, tier } = msg;
+        self.current_offers.update(tier, offers);
+
+        // Takers of all tiers fall back to the public offers
+        let affected_peers = self
+            .connected_peers
+            .iter()
+            .copied()
+            .filter(|peer_id| tier == OfferTier::Public || self.tier(*peer_id) == tier)
+            .collect::<Vec<_>>();
 
+        // Takers treat every message as the complete set of offers, so
+        // that they learn about withdrawn offers as well
         let quiet = quiet_spans::sometimes_quiet_children();
-        for peer_id in self.connected_peers.iter().copied() {
-            self.send_offers(peer_id, msg.0.clone(), ctx)
+        for peer_id in affected_peers {
+            let offers = self.current_offers.for_tier(self.tier(peer_id));
+
+            self.send_offers(peer_id, offers, ctx)
                 .instrument(quiet.clone())
                 .await
         }
     }
 
     async fn handle(&mut self, _: GetLatestOffers) -> Vec<model::Offer> {
-        self.current_offers.to_vec()
+        self.current_offers.for_tier(OfferTier::Public)
+    }
+
+    async fn handle(&mut self, msg: GetOffer) -> Result<model::Offer, OfferUnavailable> {
+        let GetOffer { offer_id, peer_id } = msg;
+
+        self.current_offers
+            .get(self.tier(peer_id), offer_id, OffsetDateTime::now_utc())
+    }
+
+    async fn handle(&mut self, msg: SetTier, ctx: &mut xtra::Context<Self>) {
+        let SetTier { peer_id, tier } = msg;
+
+        if tier == OfferTier::Public {
+            self.tiers.remove(&peer_id);
+        } else {
+            self.tiers.insert(peer_id, tier);
+        }
+
+        tracing::info!(%peer_id, %tier, "Changed offer tier of taker");
+
+        if self.connected_peers.contains(&peer_id) {
+            self.send_offers(peer_id, self.current_offers.for_tier(tier), ctx)
+                .await;
+        }
     }
 }
 
@@ -98,8 +171,9 @@ impl Actor {
     ) {
         tracing::trace!("Adding newly established connection: {:?}", msg.peer_id);
         self.connected_peers.insert(msg.peer_id);
-        self.send_offers(msg.peer_id, self.current_offers.to_vec(), ctx)
-            .await;
+
+        let offers = self.current_offers.for_tier(self.tier(msg.peer_id));
+        self.send_offers(msg.peer_id, offers, ctx).await;
     }
 
     async fn handle_connection_dropped(&mut self, msg: endpoint::ConnectionDropped) {
@@ -108,37 +182,159 @@ impl Actor {
     }
 }
 
-/// Instruct the `offer::maker::Actor` to broadcast to all
-/// connected peers an update to the current offers.
-pub struct NewOffers(Vec<model::Offer>);
+/// Instruct the `offer::maker::Actor` to replace the offers of a
+/// contract symbol in a tier and broadcast the current offers to the
+/// connected peers which see that tier.
+///
+/// A position without an offer in the pair withdraws the current offer
+/// for that position.
+pub struct NewOffers {
+    offers: OfferPair,
+    tier: OfferTier,
+}
 
 impl NewOffers {
-    pub fn new(offers: Vec<model::Offer>) -> Self {
-        Self(offers)
+    pub fn new(offers: OfferPair) -> Self {
+        Self::for_tier(offers, OfferTier::Public)
+    }
+
+    pub fn for_tier(offers: OfferPair, tier: OfferTier) -> Self {
+        Self { offers, tier }
     }
 }
 
+/// Get the current public offers.
 #[derive(Clone, Copy)]
 pub struct GetLatestOffers;
 
+/// Look up one of the offers shown to `peer_id`, e.g. to place an order.
+#[derive(Clone, Copy)]
+pub struct GetOffer {
+    pub offer_id: OfferId,
+    pub peer_id: PeerId,
+}
+
+/// Show the offers of `tier` to the taker with `peer_id` from now on.
+#[derive(Clone, Copy)]
+pub struct SetTier {
+    pub peer_id: PeerId,
+    pub tier: OfferTier,
+}
+
+/// The current offers of every tier.
+///
+/// Takers of a tier are shown the public offer for every contract symbol
+/// and position for which their tier has no offer.
+#[derive(Clone, Default)]
+struct TieredOffers(HashMap<OfferTier, Offers>);
+
+impl TieredOffers {
+    fn update(&mut self, tier: OfferTier, pair: OfferPair) {
+        self.0.entry(tier).or_default().update(pair);
+    }
+
+    fn for_tier(&self, tier: OfferTier) -> Vec<model::Offer> {
+        let mut offers = match self.0.get(&tier) {
+            Some(offers) => offers.current.clone(),
+            None => HashMap::default(),
+        };
+
+        if let Some(public) = self.0.get(&OfferTier::Public) {
+            for (key, offer) in public.current.iter() {
+                offers.entry(*key).or_insert_with(|| offer.clone());
+            }
+        }
+
+        offers.into_values().collect()
+    }
+
+    fn get(
+        &self,
+        tier: OfferTier,
+        id: OfferId,
+        now: OffsetDateTime,
+    ) -> Result<model::Offer, OfferUnavailable> {
+        let public = || match self.0.get(&OfferTier::Public) {
+            Some(public) => public.get(id, now),
+            None => Err(OfferUnavailable::NotFound(id)),
+        };
+
+        let tiered = match self.0.get(&tier) {
+            Some(tiered) if tier != OfferTier::Public => tiered,
+            _ => return public(),
+        };
+
+        match tiered.get(id, now) {
+            Err(OfferUnavailable::NotFound(_)) => {
+                let offer = public()?;
+
+                // The public offer is hidden by the offer of the tier
+                let key = (offer.contract_symbol, offer.position_maker);
+                if tiered.current.contains_key(&key) {
+                    return Err(OfferUnavailable::NotFound(id));
+                }
+
+                Ok(offer)
+            }
+            result => result,
+        }
+    }
+}
+
 #[derive(Clone, Default)]
-struct Offers(HashMap<(ContractSymbol, Position), model::Offer>);
+struct Offers {
+    current: HashMap<(ContractSymbol, Position), model::Offer>,
+    /// The most recently replaced offers, oldest first
+    superseded: VecDeque<(OfferId, (ContractSymbol, Position))>,
+}
 
 impl Offers {
-    fn update(&mut self, offers: Vec<model::Offer>) {
-        for offer in offers.into_iter() {
-            let key = (offer.contract_symbol, offer.position_maker);
+    fn update(&mut self, pair: OfferPair) {
+        for position in [Position::Long, Position::Short] {
+            let key = (pair.contract_symbol, position);
 
-            if let Some(offer) = self.0.remove(&key) {
+            if let Some(offer) = self.current.remove(&key) {
                 tracing::debug!(offer_id = %offer.id, "Replaced offer");
+
+                self.superseded.push_back((offer.id, key));
+                if self.superseded.len() > MAX_SUPERSEDED_OFFERS {
+                    self.superseded.pop_front();
+                }
             };
 
-            self.0.insert(key, offer);
+            if let Some(offer) = pair.get(position) {
+                self.current.insert(key, offer.clone());
+            }
+        }
+    }
+
+    fn get(&self, id: OfferId, now: OffsetDateTime) -> Result<model::Offer, OfferUnavailable> {
+        if let Some(offer) = self.current.values().find(|offer| offer.id == id) {
+            if offer.is_expired(now) {
+                return Err(OfferUnavailable::Expired(id));
+            }
+
+            return Ok(offer.clone());
+        }
+
+        let superseded_by = self
+            .superseded
+            .iter()
+            .find(|(offer_id, _)| *offer_id == id)
+            .and_then(|(_, key)| self.current.get(key));
+
+        match superseded_by {
+            Some(offer) => Err(OfferUnavailable::Superseded {
+                offer_id: id,
+                superseded_by: offer.id,
+            }),
+            None => Err(OfferUnavailable::NotFound(id)),
         }
     }
 
+    #[cfg(test)]
     fn to_vec(&self) -> Vec<model::Offer> {
-        self.0.iter().map(|(_, offer)| offer).cloned().collect()
+        self.current.values().cloned().collect()
     }
 }
 
@@ -168,3 +364,124 @@ impl xtra::Actor for Actor {
 
     async fn stopped(self) -> Self::Stop {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::dummy_offers;
+
+    #[test]
+    fn replaced_offer_is_reported_as_superseded() {
+        let mut offers = Offers::default();
+
+        let old = dummy_offers();
+        offers.update(old.clone());
+
+        let new = dummy_offers();
+        offers.update(new.clone());
+
+        let now = OffsetDateTime::now_utc();
+        let old = old.long.unwrap();
+        let new = new.long.unwrap();
+
+        assert_eq!(offers.get(new.id, now), Ok(new.clone()));
+        assert_eq!(
+            offers.get(old.id, now),
+            Err(OfferUnavailable::Superseded {
+                offer_id: old.id,
+                superseded_by: new.id,
+            })
+        );
+
+        let unknown = OfferId::default();
+        assert_eq!(
+            offers.get(unknown, now),
+            Err(OfferUnavailable::NotFound(unknown))
+        );
+    }
+
+    #[test]
+    fn offer_missing_from_pair_is_withdrawn() {
+        let mut offers = Offers::default();
+
+        let old = dummy_offers();
+        offers.update(old.clone());
+
+        let new = OfferPair {
+            long: None,
+            ..dummy_offers()
+        };
+        offers.update(new.clone());
+
+        let now = OffsetDateTime::now_utc();
+        let withdrawn = old.long.unwrap();
+
+        assert_eq!(offers.to_vec(), new.into_offers());
+        assert_eq!(
+            offers.get(withdrawn.id, now),
+            Err(OfferUnavailable::NotFound(withdrawn.id))
+        );
+    }
+
+    #[test]
+    fn tier_offer_hides_public_offer_for_same_position() {
+        let mut offers = TieredOffers::default();
+
+        let public = dummy_offers();
+        offers.update(OfferTier::Public, public.clone());
+
+        let partner = OfferPair {
+            short: None,
+            ..dummy_offers()
+        };
+        offers.update(OfferTier::Partner, partner.clone());
+
+        let now = OffsetDateTime::now_utc();
+        let public_long = public.long.unwrap();
+        let public_short = public.short.unwrap();
+        let partner_long = partner.long.unwrap();
+
+        let partner_offers = offers.for_tier(OfferTier::Partner);
+        assert_eq!(partner_offers.len(), 2);
+        assert!(partner_offers.contains(&partner_long));
+        assert!(partner_offers.contains(&public_short));
+
+        assert_eq!(
+            offers.get(OfferTier::Partner, partner_long.id, now),
+            Ok(partner_long.clone())
+        );
+        assert_eq!(
+            offers.get(OfferTier::Partner, public_short.id, now),
+            Ok(public_short)
+        );
+        assert_eq!(
+            offers.get(OfferTier::Partner, public_long.id, now),
+            Err(OfferUnavailable::NotFound(public_long.id))
+        );
+    }
+
+    #[test]
+    fn tier_offers_are_not_shown_to_other_tiers() {
+        let mut offers = TieredOffers::default();
+
+        let public = dummy_offers();
+        offers.update(OfferTier::Public, public.clone());
+
+        let internal = dummy_offers();
+        offers.update(OfferTier::Internal, internal.clone());
+
+        let now = OffsetDateTime::now_utc();
+        let internal_long = internal.long.unwrap();
+
+        assert_eq!(offers.for_tier(OfferTier::Public).len(), 2);
+        assert_eq!(offers.for_tier(OfferTier::Partner).len(), 2);
+        assert!(!offers.for_tier(OfferTier::Partner).contains(&internal_long));
+
+        for tier in [OfferTier::Public, OfferTier::Partner] {
+            assert_eq!(
+                offers.get(tier, internal_long.id, now),
+                Err(OfferUnavailable::NotFound(internal_long.id))
+            );
+        }
+    }
+}
@@ -2,6 +2,7 @@ use asynchronous_codec::FramedRead;
 use asynchronous_codec::FramedWrite;
 use asynchronous_codec::JsonCodec;
 use asynchronous_codec::JsonCodecError;
+use bdk::bitcoin::Amount;
 use futures::AsyncReadExt;
 use futures::AsyncWriteExt;
 use futures::SinkExt;
@@ -9,6 +10,7 @@ use futures::StreamExt;
 use model::olivia::BitMexPriceEventId;
 use model::ContractSymbol;
 use model::Contracts;
+use model::FeeSubsidy;
 use model::FundingRate;
 use model::Leverage;
 use model::LotSize;
@@ -60,12 +62,25 @@ pub(crate) struct Offer {
     max_quantity: Contracts,
     leverage_choices: Vec<Leverage>,
     creation_timestamp_maker: Timestamp,
+    /// Not sent by makers which predate offer expiry.
+    #[serde(default)]
+    valid_until: Option<Timestamp>,
     settlement_interval: Duration,
+    /// Not sent by makers which predate configurable refund timelocks.
+    #[serde(default)]
+    refund_timelock: Option<u32>,
     oracle_event_id: BitMexPriceEventId,
     tx_fee_rate: TxFeeRate,
     funding_rate: FundingRate,
     opening_fee: OpeningFee,
     lot_size: LotSize,
+    /// Not sent on [`crate::PROTOCOL_WITHOUT_FEE_SUBSIDY`], whose
+    /// offers already carry the subsidised fees.
+    #[serde(default)]
+    fee_subsidy: Option<FeeSubsidy>,
+    /// Not sent by makers which predate the minimum taker balance.
+    #[serde(default, with = "::bdk::bitcoin::util::amount::serde::as_sat::opt")]
+    min_taker_balance: Option<Amount>,
 }
 
 impl From<model::Offer> for Offer {
@@ -79,12 +94,16 @@ impl From<model::Offer> for Offer {
             max_quantity: offer.max_quantity,
             leverage_choices: offer.leverage_choices,
             creation_timestamp_maker: offer.creation_timestamp_maker,
+            valid_until: Some(offer.valid_until),
             settlement_interval: offer.settlement_interval,
+            refund_timelock: Some(offer.refund_timelock),
             oracle_event_id: offer.oracle_event_id,
             tx_fee_rate: offer.tx_fee_rate,
             funding_rate: offer.funding_rate,
             opening_fee: offer.opening_fee,
             lot_size: offer.lot_size,
+            fee_subsidy: offer.fee_subsidy,
+            min_taker_balance: offer.min_taker_balance,
         }
     }
 }
@@ -100,12 +119,20 @@ impl From<Offer> for model::Offer {
             max_quantity: offer.max_quantity,
             leverage_choices: offer.leverage_choices,
             creation_timestamp_maker: offer.creation_timestamp_maker,
+            valid_until: offer.valid_until.unwrap_or_else(|| {
+                model::Offer::default_valid_until(offer.creation_timestamp_maker)
+            }),
             settlement_interval: offer.settlement_interval,
+            refund_timelock: offer.refund_timelock.unwrap_or_else(|| {
+                model::Offer::default_refund_timelock(offer.settlement_interval)
+            }),
             oracle_event_id: offer.oracle_event_id,
             tx_fee_rate: offer.tx_fee_rate,
             funding_rate: offer.funding_rate,
             opening_fee: offer.opening_fee,
             lot_size: offer.lot_size,
+            fee_subsidy: offer.fee_subsidy,
+            min_taker_balance: offer.min_taker_balance,
         }
     }
 }
@@ -116,6 +143,39 @@ impl From<Vec<model::Offer>> for Offers {
     }
 }
 
+impl Offers {
+    /// The offers as sent to takers on
+    /// [`crate::PROTOCOL_WITHOUT_FEE_SUBSIDY`].
+    ///
+    /// These takers do not know about fee subsidies, so we hand them
+    /// the fees they are actually charged. They also ignore the refund
+    /// timelock and the minimum taker balance, hence they are not sent
+    /// offers which deviate from the defaults.
+    pub(crate) fn with_subsidised_fees(offers: Vec<model::Offer>) -> Self {
+        let offers = offers
+            .into_iter()
+            .filter(|offer| {
+                offer.refund_timelock
+                    == model::Offer::default_refund_timelock(offer.settlement_interval)
+            })
+            .filter(|offer| offer.min_taker_balance.is_none())
+            .map(|offer| {
+                let funding_rate = offer.charged_initial_funding_rate();
+                let opening_fee = offer.charged_opening_fee();
+
+                Offer {
+                    funding_rate,
+                    opening_fee,
+                    fee_subsidy: None,
+                    ..Offer::from(offer)
+                }
+            })
+            .collect();
+
+        Offers(offers)
+    }
+}
+
 impl From<Offers> for Vec<model::Offer> {
     fn from(offers: Offers) -> Self {
         offers.0.into_iter().map(model::Offer::from).collect()
@@ -167,7 +227,7 @@ mod tests {
     async fn sent_offers_match_received_offers() {
         let (stream, sink) = pipe();
 
-        let maker_offers = dummy_offers();
+        let maker_offers = dummy_offers().into_offers();
 
         let (send_res, recv_res) =
             tokio::join!(send(sink, Offers::from(maker_offers.clone())), recv(stream));
@@ -175,4 +235,50 @@ mod tests {
         assert!(send_res.is_ok());
         assert_eq!(maker_offers, Vec::<model::Offer>::from(recv_res.unwrap()))
     }
+
+    #[test]
+    fn takers_without_fee_subsidy_are_sent_charged_fees() {
+        let offers = dummy_offers()
+            .into_offers()
+            .into_iter()
+            .map(|offer| model::Offer {
+                opening_fee: OpeningFee::new(Amount::from_sat(1_000)),
+                fee_subsidy: Some(FeeSubsidy {
+                    opening_fee: true,
+                    initial_funding_fee: true,
+                }),
+                ..offer
+            })
+            .collect::<Vec<_>>();
+
+        let sent = Vec::<model::Offer>::from(Offers::with_subsidised_fees(offers.clone()));
+
+        for (offer, sent) in offers.iter().zip(sent) {
+            assert_eq!(sent.fee_subsidy, None);
+            assert_eq!(sent.opening_fee, offer.charged_opening_fee());
+            assert_eq!(sent.funding_rate, offer.charged_initial_funding_rate());
+        }
+    }
+
+    #[test]
+    fn takers_without_fee_subsidy_are_not_sent_offers_they_cannot_take() {
+        let offers = dummy_offers();
+        let custom_refund_timelock = model::Offer {
+            refund_timelock: 300,
+            ..offers.long.clone().unwrap()
+        };
+        let min_taker_balance = model::Offer {
+            min_taker_balance: Some(Amount::from_sat(100_000)),
+            ..offers.short.clone().unwrap()
+        };
+        let default_offer = offers.long.unwrap();
+
+        let sent = Vec::<model::Offer>::from(Offers::with_subsidised_fees(vec![
+            custom_refund_timelock,
+            min_taker_balance,
+            default_offer.clone(),
+        ]));
+
+        assert_eq!(sent, vec![default_offer]);
+    }
 }
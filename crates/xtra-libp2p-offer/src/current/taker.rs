@@ -1,5 +1,12 @@
 use crate::current::protocol;
 use async_trait::async_trait;
+use model::ContractSymbol;
+use model::Leverage;
+use model::OfferPair;
+use serde::Deserialize;
+use std::collections::HashSet;
+use time::OffsetDateTime;
+use tokio::sync::watch;
 use tracing::Instrument;
 use xtra::prelude::MessageChannel;
 use xtra_libp2p::NewInboundSubstream;
@@ -7,11 +14,20 @@ use xtra_productivity::xtra_productivity;
 
 pub struct Actor {
     maker_offers: MessageChannel<LatestOffers, ()>,
+    filter: watch::Receiver<OfferFilter>,
 }
 
 impl Actor {
-    pub fn new(maker_offers: MessageChannel<LatestOffers, ()>) -> Self {
-        Self { maker_offers }
+    /// Create an actor which forwards the maker's offers matching
+    /// the latest value of `filter` to `maker_offers`.
+    pub fn new(
+        maker_offers: MessageChannel<LatestOffers, ()>,
+        filter: watch::Receiver<OfferFilter>,
+    ) -> Self {
+        Self {
+            maker_offers,
+            filter,
+        }
     }
 }
 
@@ -20,6 +36,7 @@ impl Actor {
     async fn handle(&mut self, msg: NewInboundSubstream, ctx: &mut xtra::Context<Self>) {
         let NewInboundSubstream { peer_id, stream } = msg;
         let maker_offers = self.maker_offers.clone();
+        let filter = self.filter.borrow().clone();
 
         let this = ctx.address().expect("self to be alive");
 
@@ -28,9 +45,13 @@ impl Actor {
 
             tracing::debug!(?offers, "Received offers");
 
+            let offers = drop_expired(offers.into(), OffsetDateTime::now_utc());
+            let offers = filter.apply(offers);
+            let offers = OfferPair::from_offers(offers);
+
             let span = tracing::debug_span!("Received new offers from maker", %peer_id);
             maker_offers
-                .send(LatestOffers(offers.into()))
+                .send(LatestOffers(offers))
                 .instrument(span)
                 .await?;
 
@@ -45,9 +66,82 @@ impl Actor {
     }
 }
 
+/// Drop the offers which can no longer be taken.
+fn drop_expired(offers: Vec<model::Offer>, now: OffsetDateTime) -> Vec<model::Offer> {
+    offers
+        .into_iter()
+        .filter(|offer| {
+            let expired = offer.is_expired(now);
+            if expired {
+                tracing::debug!(offer_id = %offer.id, "Dropping expired offer");
+            }
+
+            !expired
+        })
+        .collect()
+}
+
 /// Message used to inform other actors about the maker's latest
 /// offers.
-pub struct LatestOffers(pub Vec<model::Offer>);
+///
+/// Contains one pair per contract symbol. The maker always sends all
+/// of its current offers, so a position without an offer means that
+/// the maker withdrew the offer for it, or that it was filtered out.
+pub struct LatestOffers(pub Vec<OfferPair>);
+
+/// The offers the embedding application is interested in.
+///
+/// The default filter lets all offers through.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct OfferFilter {
+    /// Only forward offers for these contract symbols. If empty,
+    /// offers for all contract symbols are forwarded.
+    #[serde(default)]
+    pub contract_symbols: HashSet<ContractSymbol>,
+    #[serde(default)]
+    pub min_leverage: Option<Leverage>,
+    #[serde(default)]
+    pub max_leverage: Option<Leverage>,
+}
+
+impl OfferFilter {
+    /// Retain the offers matching the filter.
+    ///
+    /// The leverage choices of each offer are narrowed down to the
+    /// configured leverage range. Offers without any leverage choice
+    /// in that range are dropped.
+    pub fn apply(&self, offers: Vec<model::Offer>) -> Vec<model::Offer> {
+        offers
+            .into_iter()
+            .filter(|offer| {
+                self.contract_symbols.is_empty()
+                    || self.contract_symbols.contains(&offer.contract_symbol)
+            })
+            .filter_map(|mut offer| {
+                offer
+                    .leverage_choices
+                    .retain(|leverage| self.allows_leverage(*leverage));
+
+                if offer.leverage_choices.is_empty() {
+                    return None;
+                }
+
+                Some(offer)
+            })
+            .collect()
+    }
+
+    fn allows_leverage(&self, leverage: Leverage) -> bool {
+        let above_min = self
+            .min_leverage
+            .map_or(true, |min| leverage.get() >= min.get());
+        let below_max = self
+            .max_leverage
+            .map_or(true, |max| leverage.get() <= max.get());
+
+        above_min && below_max
+    }
+}
 
 #[async_trait]
 impl xtra::Actor for Actor {
@@ -65,6 +65,11 @@ pub(crate) struct Offer {
 
 impl From<model::Offer> for Offer {
     fn from(offer: model::Offer) -> Self {
+        // Takers on the deprecated protocol do not know about fee subsidies, so we hand them the
+        // fees they are actually charged
+        let funding_rate = offer.charged_initial_funding_rate();
+        let opening_fee = offer.charged_opening_fee();
+
         Self {
             id: offer.id,
             contract_symbol: offer.contract_symbol,
@@ -79,8 +84,8 @@ impl From<model::Offer> for Offer {
             origin: Origin::Ours, // the maker always creates it
             oracle_event_id: offer.oracle_event_id,
             tx_fee_rate: offer.tx_fee_rate,
-            funding_rate: offer.funding_rate,
-            opening_fee: offer.opening_fee,
+            funding_rate,
+            opening_fee,
         }
     }
 }
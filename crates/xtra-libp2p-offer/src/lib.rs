@@ -7,6 +7,7 @@ pub use current::*;
 mod tests {
     use super::*;
     use crate::taker::LatestOffers;
+    use crate::taker::OfferFilter;
     use async_trait::async_trait;
     use futures::Future;
     use model::olivia::BitMexPriceEventId;
@@ -15,6 +16,7 @@ mod tests {
     use model::FundingRate;
     use model::Leverage;
     use model::LotSize;
+    use model::OfferPair;
     use model::Position;
     use model::Price;
     use model::Timestamp;
@@ -93,7 +95,7 @@ mod tests {
         })
         .await;
 
-        assert_eq!(new_offers, received_offers)
+        assert_eq!(new_offers.into_offers(), received_offers)
     }
 
     #[tokio::test]
@@ -114,17 +116,19 @@ mod tests {
 
         let offer_btc_usd_long = dummy_offer(ContractSymbol::BtcUsd, Position::Long);
         maker_offer_addr
-            .send(crate::maker::NewOffers::new(vec![
-                offer_btc_usd_long.clone()
-            ]))
+            .send(crate::maker::NewOffers::new(OfferPair {
+                long: Some(offer_btc_usd_long.clone()),
+                ..OfferPair::empty(ContractSymbol::BtcUsd)
+            }))
             .await
             .unwrap();
 
         let offer_eth_usd_short = dummy_offer(ContractSymbol::EthUsd, Position::Short);
         maker_offer_addr
-            .send(crate::maker::NewOffers::new(vec![
-                offer_eth_usd_short.clone()
-            ]))
+            .send(crate::maker::NewOffers::new(OfferPair {
+                short: Some(offer_eth_usd_short.clone()),
+                ..OfferPair::empty(ContractSymbol::EthUsd)
+            }))
             .await
             .unwrap();
 
@@ -151,6 +155,37 @@ mod tests {
         assert!(received_offers.contains(&offer_eth_usd_short));
     }
 
+    #[test]
+    fn offer_filter_retains_matching_symbols_and_leverages() {
+        let mut btc_usd = dummy_offer(ContractSymbol::BtcUsd, Position::Long);
+        btc_usd.leverage_choices = vec![Leverage::ONE, Leverage::TWO, Leverage::new(5).unwrap()];
+        let eth_usd = dummy_offer(ContractSymbol::EthUsd, Position::Long);
+
+        let filter = OfferFilter {
+            contract_symbols: HashSet::from([ContractSymbol::BtcUsd]),
+            min_leverage: Some(Leverage::TWO),
+            max_leverage: Some(Leverage::new(3).unwrap()),
+        };
+
+        let offers = filter.apply(vec![btc_usd, eth_usd]);
+
+        assert_eq!(offers.len(), 1);
+        assert_eq!(offers[0].contract_symbol, ContractSymbol::BtcUsd);
+        assert_eq!(offers[0].leverage_choices, vec![Leverage::TWO]);
+    }
+
+    #[test]
+    fn offer_filter_drops_offers_without_leverage_in_range() {
+        let offer = dummy_offer(ContractSymbol::BtcUsd, Position::Long);
+
+        let filter = OfferFilter {
+            min_leverage: Some(Leverage::new(3).unwrap()),
+            ..OfferFilter::default()
+        };
+
+        assert!(filter.apply(vec![offer]).is_empty());
+    }
+
     fn create_endpoint_with_offer_maker(
     ) -> (PeerId, Address<crate::maker::Actor>, Address<Endpoint>) {
         let (endpoint_addr, endpoint_context) = Context::new(None);
@@ -183,9 +218,11 @@ mod tests {
     fn create_endpoint_with_offer_taker() -> (Address<OffersReceiver>, Address<Endpoint>) {
         let offers_receiver_addr = OffersReceiver::new().create(None).spawn_global();
 
-        let offer_taker_addr = crate::taker::Actor::new(offers_receiver_addr.clone().into())
-            .create(None)
-            .spawn_global();
+        let (_, filter) = tokio::sync::watch::channel(OfferFilter::default());
+        let offer_taker_addr =
+            crate::taker::Actor::new(offers_receiver_addr.clone().into(), filter)
+                .create(None)
+                .spawn_global();
 
         let endpoint_addr = Endpoint::new(
             Box::new(MemoryTransport::default),
@@ -221,7 +258,7 @@ mod tests {
     #[xtra_productivity]
     impl OffersReceiver {
         async fn handle(&mut self, msg: LatestOffers) {
-            self.offers = msg.0;
+            self.offers = msg.0.into_iter().flat_map(OfferPair::into_offers).collect();
         }
     }
 
@@ -250,14 +287,17 @@ mod tests {
         }
     }
 
-    pub fn dummy_offers() -> Vec<model::Offer> {
-        vec![
-            dummy_offer(ContractSymbol::BtcUsd, Position::Long),
-            dummy_offer(ContractSymbol::BtcUsd, Position::Short),
-        ]
+    pub fn dummy_offers() -> OfferPair {
+        OfferPair {
+            contract_symbol: ContractSymbol::BtcUsd,
+            long: Some(dummy_offer(ContractSymbol::BtcUsd, Position::Long)),
+            short: Some(dummy_offer(ContractSymbol::BtcUsd, Position::Short)),
+        }
     }
 
     fn dummy_offer(contract_symbol: ContractSymbol, position_maker: Position) -> model::Offer {
+        let creation_timestamp_maker = Timestamp::now();
+
         model::Offer {
             id: Default::default(),
             contract_symbol,
@@ -266,8 +306,10 @@ mod tests {
             min_quantity: Contracts::new(100),
             max_quantity: Contracts::new(1000),
             leverage_choices: vec![Leverage::TWO],
-            creation_timestamp_maker: Timestamp::now(),
+            creation_timestamp_maker,
+            valid_until: model::Offer::default_valid_until(creation_timestamp_maker),
             settlement_interval: time::Duration::hours(24),
+            refund_timelock: 216,
             oracle_event_id: BitMexPriceEventId::with_20_digits(
                 datetime!(2021-10-04 22:00:00).assume_utc(),
                 contract_symbol,
@@ -276,6 +318,8 @@ mod tests {
             funding_rate: FundingRate::new(Decimal::ONE).unwrap(),
             opening_fee: Default::default(),
             lot_size: LotSize::new(100),
+            fee_subsidy: None,
+            min_taker_balance: None,
         }
     }
 }
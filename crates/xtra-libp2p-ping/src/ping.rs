@@ -9,6 +9,7 @@ use std::time::Duration;
 use tokio_extras::spawn_fallible;
 use tracing::Instrument;
 use xtra::prelude::async_trait;
+use xtra::prelude::MessageChannel;
 use xtra::Address;
 use xtra::Context;
 use xtra_libp2p::endpoint;
@@ -35,11 +36,15 @@ use xtras::SendInterval;
 /// incoming pings even without a `ping_interval` set. This is useful if an application wants to
 /// allow other peers in the network to measure their latency but is not interested in measuring
 /// latencies itself or keeping connections alive otherwise.
+///
+/// Every measured latency is forwarded to the subscribers added with
+/// [`Actor::with_latency_subscriber`].
 pub struct Actor {
     endpoint: Address<Endpoint>,
     ping_interval: Duration,
     connected_peers: HashSet<PeerId>,
     latencies: HashMap<PeerId, Duration>,
+    latency_subscribers: Vec<MessageChannel<LatencyMeasured, ()>>,
 }
 
 impl Actor {
@@ -49,8 +54,18 @@ impl Actor {
             ping_interval,
             connected_peers: HashSet::default(),
             latencies: HashMap::default(),
+            latency_subscribers: Vec::new(),
         }
     }
+
+    #[must_use]
+    pub fn with_latency_subscriber(
+        mut self,
+        subscriber: MessageChannel<LatencyMeasured, ()>,
+    ) -> Self {
+        self.latency_subscribers.push(subscriber);
+        self
+    }
 }
 
 #[async_trait]
@@ -95,6 +110,13 @@ struct RecordLatency {
     latency: Duration,
 }
 
+/// The round trip of a ping to `peer_id` took `latency`.
+#[derive(Clone, Copy, Debug)]
+pub struct LatencyMeasured {
+    pub peer_id: PeerId,
+    pub latency: Duration,
+}
+
 /// Private message to get the latency of a peer.
 ///
 /// Primarily used for testing. May be exposed publicly at some point.
@@ -151,6 +173,12 @@ impl Actor {
 
         let latency_seconds = latency_milliseconds.checked_div(1000).unwrap_or_default();
         PEER_LATENCY_HISTOGRAM.observe(latency_seconds as f64);
+
+        for subscriber in &self.latency_subscribers {
+            subscriber
+                .send_async_next(LatencyMeasured { peer_id, latency })
+                .await;
+        }
     }
 
     async fn handle(&mut self, GetLatency(peer): GetLatency) -> Option<Duration> {
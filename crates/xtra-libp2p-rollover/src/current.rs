@@ -1,5 +1,77 @@
+use anyhow::Result;
+use asynchronous_codec::Framed;
+use asynchronous_codec::JsonCodec;
+use asynchronous_codec::JsonCodecError;
+use futures::Stream;
+use xtra_libp2p::recorder::ensure_replay_finished;
+use xtra_libp2p::recorder::next_replayed;
+use xtra_libp2p::recorder::ReplayedSubstream;
+
 pub mod maker;
 pub mod protocol;
 pub mod taker;
 
 pub const PROTOCOL: &str = "/itchysats/rollover/3.0.0";
+
+/// Run the taker's handling of a recorded substream on the frames the
+/// maker sent.
+pub async fn replay_dialer(stream: ReplayedSubstream) -> Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        JsonCodec::<protocol::DialerMessage, protocol::ListenerMessage>::new(),
+    );
+
+    let decision = match next_replayed(&mut framed).await? {
+        Some(msg) => msg.into_decision()?,
+        None => return Ok(()),
+    };
+
+    if let protocol::Decision::Confirm(_) = decision {
+        replay_rollover_msgs(&mut framed, protocol::ListenerMessage::into_rollover_msg).await?;
+    }
+
+    ensure_replay_finished(&mut framed).await
+}
+
+/// Run the maker's handling of a recorded substream on the frames the
+/// taker sent.
+pub async fn replay_listener(stream: ReplayedSubstream) -> Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        JsonCodec::<protocol::ListenerMessage, protocol::DialerMessage>::new(),
+    );
+
+    match next_replayed(&mut framed).await? {
+        Some(msg) => msg.into_propose()?,
+        None => return Ok(()),
+    };
+
+    replay_rollover_msgs(&mut framed, protocol::DialerMessage::into_rollover_msg).await?;
+
+    ensure_replay_finished(&mut framed).await
+}
+
+/// Receive the messages of the rollover in the order both parties
+/// expect them.
+async fn replay_rollover_msgs<S, M>(
+    stream: &mut S,
+    into_rollover_msg: fn(M) -> Result<protocol::RolloverMsg>,
+) -> Result<()>
+where
+    S: Stream<Item = Result<M, JsonCodecError>> + Unpin,
+{
+    let steps: [fn(protocol::RolloverMsg) -> Result<()>; 3] = [
+        |msg| msg.try_into_msg0().map(|_| ()),
+        |msg| msg.try_into_msg1().map(|_| ()),
+        |msg| msg.try_into_msg2().map(|_| ()),
+    ];
+
+    for step in steps {
+        match next_replayed(stream).await? {
+            Some(msg) => step(into_rollover_msg(msg)?)?,
+            None => return Ok(()),
+        }
+    }
+
+    Ok(())
+}
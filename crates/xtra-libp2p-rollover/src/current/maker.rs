@@ -7,12 +7,15 @@ use bdk_ext::keypair;
 use futures::SinkExt;
 use futures::StreamExt;
 use libp2p_core::PeerId;
-use maia_core::secp256k1_zkp::XOnlyPublicKey;
+use model::olivia::OracleKeys;
 use model::Dlc;
 use model::ExecuteOnCfd;
 use model::Position;
 use model::Role;
 use tokio_extras::FutureExt;
+use tracing::field;
+use tracing::Instrument;
+use tracing::Span;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_libp2p::Substream;
 use xtra_productivity::xtra_productivity;
@@ -23,7 +26,7 @@ use xtra_productivity::xtra_productivity;
 /// There is only one instance of this actor for all connections, meaning we must always spawn a
 /// task whenever we interact with a substream to not block the execution of other connections.
 pub struct Actor<E, O, R> {
-    oracle_pk: XOnlyPublicKey,
+    oracle_keys: OracleKeys,
     oracle: O,
     n_payouts: usize,
     executor: E,
@@ -34,13 +37,13 @@ pub struct Actor<E, O, R> {
 impl<E, O, R> Actor<E, O, R> {
     pub fn new(
         executor: E,
-        oracle_pk: XOnlyPublicKey,
+        oracle_keys: OracleKeys,
         oracle: O,
         rates: R,
         n_payouts: usize,
     ) -> Self {
         Self {
-            oracle_pk,
+            oracle_keys,
             oracle,
             n_payouts,
             executor,
@@ -84,6 +87,7 @@ where
     async fn handle(&mut self, msg: NewInboundSubstream, ctx: &mut xtra::Context<Self>) {
         let NewInboundSubstream { peer_id, stream } = msg;
         let address = ctx.address().expect("we are alive");
+        let span = tracing::info_span!("Rollover", order_id = field::Empty, %peer_id);
 
         tokio_extras::spawn_fallible(
             &address.clone(),
@@ -98,6 +102,8 @@ where
                     .context("Failed to decode Propose")?
                     .into_propose()?;
 
+                Span::current().record("order_id", &field::display(propose.order_id));
+
                 address
                     .send(ProposeReceived {
                         propose,
@@ -107,9 +113,13 @@ where
                     .await?;
 
                 anyhow::Ok(())
-            },
-            move |e| async move {
-                tracing::warn!(%peer_id, "Failed to handle incoming rollover protocol: {e:#}")
+            }
+            .instrument(span.clone()),
+            move |e| {
+                async move {
+                    tracing::warn!(%peer_id, "Failed to handle incoming rollover protocol: {e:#}")
+                }
+                .instrument(span)
             },
         );
     }
@@ -124,7 +134,7 @@ where
 
         let (base_dlc_params, contract_symbol) = match self
             .executor
-            .execute(order_id, |cfd| {
+            .execute_with_retry(order_id, |cfd| {
                 cfd.verify_counterparty_peer_id(&peer_id.into())?;
 
                 let (event, base_dlc_params) =
@@ -147,6 +157,8 @@ where
             }
         };
 
+        let span = tracing::info_span!("Rollover", %order_id, %peer_id);
+
         let this = ctx.address().expect("we are alive");
         if !self.is_accepting_rollovers {
             emit_rejected(order_id, &self.executor).await;
@@ -159,9 +171,16 @@ where
                             order_id,
                         })))
                         .await
-                },
-                move |e| async move {
-                    tracing::warn!(%order_id, "Failed to send reject rollover to the taker: {e:#}")
+                }
+                .instrument(span.clone()),
+                move |e| {
+                    async move {
+                        tracing::warn!(
+                            %order_id,
+                            "Failed to send reject rollover to the taker: {e:#}"
+                        )
+                    }
+                    .instrument(span)
                 },
             );
 
@@ -176,7 +195,7 @@ where
             let executor = self.executor.clone();
             let oracle = self.oracle.clone();
             let rates = self.rates.clone();
-            let oracle_pk = self.oracle_pk;
+            let oracle_keys = self.oracle_keys;
             let n_payouts = self.n_payouts;
             async move {
                 let Rates {
@@ -214,6 +233,12 @@ where
                     .add_funding_fee(rollover_params.current_fee)
                     .settle();
 
+                let oracle_pk = oracle_keys.for_event(
+                    *oracle_event_ids
+                        .last()
+                        .context("No oracle event to roll over to")?,
+                );
+
                 framed
                     .send(ListenerMessage::Decision(Decision::Confirm(Confirm {
                         order_id,
@@ -221,6 +246,7 @@ where
                         tx_fee_rate,
                         funding_rate,
                         complete_fee: complete_fee.into(),
+                        oracle_pk: Some(oracle_pk),
                     })))
                     .await
                     .context("Failed to send rollover confirmation message")?;
@@ -386,6 +412,9 @@ where
             }
         };
 
+        let task = task.instrument(span.clone());
+        let err_handler = move |e| err_handler(e).instrument(span);
+
         tokio_extras::spawn_fallible(&this, task, err_handler);
     }
 }
@@ -123,6 +123,11 @@ pub struct Confirm {
     pub tx_fee_rate: TxFeeRate,
     pub funding_rate: FundingRate,
     pub complete_fee: CompleteFee,
+    /// The oracle key the maker uses for the event to roll over to.
+    ///
+    /// Not sent by makers which predate oracle key rotation.
+    #[serde(default)]
+    pub oracle_pk: Option<XOnlyPublicKey>,
 }
 
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -1,5 +1,7 @@
 use crate::current;
 use crate::current::protocol::*;
+use anyhow::anyhow;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -7,16 +9,19 @@ use bdk::bitcoin::Txid;
 use bdk_ext::keypair;
 use futures::SinkExt;
 use futures::StreamExt;
-use maia_core::secp256k1_zkp::XOnlyPublicKey;
 use model::libp2p::PeerId;
 use model::olivia::BitMexPriceEventId;
+use model::olivia::OracleKeys;
 use model::Dlc;
 use model::ExecuteOnCfd;
 use model::OrderId;
 use model::Role;
 use model::Timestamp;
 use std::time::Duration;
+use tokio_extras::CancellableTasks;
+use tokio_extras::CommitToken;
 use tokio_extras::FutureExt;
+use tracing::Instrument;
 use xtra::Address;
 use xtra_libp2p::Endpoint;
 use xtra_libp2p::OpenSubstream;
@@ -32,10 +37,11 @@ const DECISION_TIMEOUT: Duration = Duration::from_secs(30);
 /// One actor to rule all the rollovers
 pub struct Actor<E, O> {
     endpoint: Address<Endpoint>,
-    oracle_pk: XOnlyPublicKey,
+    oracle_keys: OracleKeys,
     oracle: O,
     n_payouts: usize,
     executor: E,
+    pending_rollovers: CancellableTasks<OrderId, Timestamp>,
 }
 
 #[async_trait]
@@ -57,11 +63,23 @@ pub struct ProposeRollover {
     pub from_settlement_event_id: BitMexPriceEventId,
 }
 
+/// List the rollover proposals which are still awaiting the maker's
+/// decision, together with the time they were sent.
+#[derive(Copy, Clone)]
+pub struct GetPendingRollovers;
+
+/// Cancel a rollover proposal which is still awaiting the maker's
+/// decision.
+#[derive(Copy, Clone)]
+pub struct CancelRollover {
+    pub order_id: OrderId,
+}
+
 impl<E, O> Actor<E, O> {
     pub fn new(
         endpoint: Address<Endpoint>,
         executor: E,
-        oracle_pk: XOnlyPublicKey,
+        oracle_keys: OracleKeys,
         get_announcement: O,
         n_payouts: usize,
     ) -> Self {
@@ -69,8 +87,9 @@ impl<E, O> Actor<E, O> {
             endpoint,
             executor,
             oracle: get_announcement,
-            oracle_pk,
+            oracle_keys,
             n_payouts,
+            pending_rollovers: CancellableTasks::default(),
         }
     }
 }
@@ -99,7 +118,7 @@ where
     E: ExecuteOnCfd + Clone + Send + Sync + 'static,
     O: GetAnnouncements + Clone + Send + Sync + 'static,
 {
-    pub async fn handle(&mut self, msg: ProposeRollover, ctx: &mut xtra::Context<Self>) {
+    pub async fn handle(&mut self, msg: ProposeRollover) {
         let ProposeRollover {
             order_id,
             maker_peer_id,
@@ -119,21 +138,25 @@ where
             }
         };
 
-        tokio_extras::spawn_fallible(
-            &ctx.address().expect("self to be alive"),
+        let span = tracing::info_span!("Rollover", %order_id, peer_id = %maker_peer_id);
+
+        let result = self.pending_rollovers.add_fallible(
+            order_id,
+            Timestamp::now(),
             {
                 let executor = self.executor.clone();
                 let oracle = self.oracle.clone();
-                let oracle_pk = self.oracle_pk;
+                let oracle_keys = self.oracle_keys;
                 let n_payouts = self.n_payouts;
-                async move {
+                let span = span.clone();
+                let task = move |token: CommitToken| async move {
                     let mut framed = asynchronous_codec::Framed::new(
                         substream,
                         asynchronous_codec::JsonCodec::<DialerMessage, ListenerMessage>::new(),
                     );
 
                     let contract_symbol = executor
-                        .execute(order_id, |cfd| {
+                        .execute_with_retry(order_id, |cfd| {
                             let event = cfd.start_rollover_taker()?;
                             let contract_symbol = cfd.contract_symbol();
 
@@ -150,7 +173,7 @@ where
                         .await
                         .context("Failed to send Msg0")?;
 
-                    match framed
+                    let decision = framed
                         .next()
                         .timeout(DECISION_TIMEOUT, || {
                             tracing::debug_span!("receive decision")
@@ -164,15 +187,35 @@ where
                         })?
                         .context("End of stream while receiving rollover decision from maker")?
                         .context("Failed to decode rollover decision from maker")?
-                        .into_decision()?
-                    {
+                        .into_decision()?;
+
+                    // Once the maker has decided, the rollover can no longer be cancelled
+                    token.commit().context("Rollover was cancelled")?;
+
+                    match decision {
                         Decision::Confirm(Confirm {
                             order_id,
                             oracle_event_ids,
                             tx_fee_rate,
                             funding_rate,
                             complete_fee,
+                            oracle_pk: maker_oracle_pk,
                         }) => {
+                            let oracle_pk = oracle_keys.for_event(
+                                *oracle_event_ids
+                                    .last()
+                                    .context("No oracle event to roll over to")?,
+                            );
+
+                            // Makers which predate oracle key rotation do not tell us which key
+                            // they use
+                            if let Some(maker_oracle_pk) = maker_oracle_pk {
+                                ensure!(
+                                    maker_oracle_pk == oracle_pk,
+                                    "Maker uses oracle key {maker_oracle_pk} instead of {oracle_pk}"
+                                );
+                            }
+
                             let (rollover_params, dlc, position) = executor
                                 .execute(order_id, |cfd| {
                                     cfd.handle_rollover_accepted_taker(
@@ -355,14 +398,50 @@ where
                         }
                     }
                     Ok(())
-                }
+                };
+
+                move |token| task(token).instrument(span)
             },
             {
                 let executor = self.executor.clone();
-                move |e| async move {
-                    emit_failed(order_id, e, &executor).await;
+                move |e| {
+                    async move {
+                        emit_failed(order_id, e, &executor).await;
+                    }
+                    .instrument(span)
                 }
             },
         );
+
+        // Failing the CFD here would fail the rollover which is already in progress
+        if let Err(e) = result {
+            tracing::warn!(%order_id, "Not starting rollover: {e:#}");
+        }
+    }
+
+    pub async fn handle(&mut self, _: GetPendingRollovers) -> Vec<(OrderId, Timestamp)> {
+        self.pending_rollovers
+            .cancellable()
+            .map(|(order_id, since)| (*order_id, *since))
+            .collect()
+    }
+
+    pub async fn handle(&mut self, msg: CancelRollover) -> Result<()> {
+        let CancelRollover { order_id } = msg;
+
+        self.pending_rollovers
+            .cancel(&order_id)
+            .with_context(|| format!("No rollover for {order_id} awaiting the maker's decision"))?;
+
+        tracing::info!(%order_id, "Cancelled rollover");
+
+        emit_failed(
+            order_id,
+            anyhow!("Rollover cancelled by user"),
+            &self.executor,
+        )
+        .await;
+
+        Ok(())
     }
 }
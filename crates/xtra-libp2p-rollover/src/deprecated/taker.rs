@@ -7,9 +7,9 @@ use bdk::bitcoin::Txid;
 use bdk_ext::keypair;
 use futures::SinkExt;
 use futures::StreamExt;
-use maia_core::secp256k1_zkp::XOnlyPublicKey;
 use model::libp2p::PeerId;
 use model::olivia::BitMexPriceEventId;
+use model::olivia::OracleKeys;
 use model::Dlc;
 use model::ExecuteOnCfd;
 use model::OrderId;
@@ -17,6 +17,7 @@ use model::Role;
 use model::Timestamp;
 use std::time::Duration;
 use tokio_extras::FutureExt;
+use tracing::Instrument;
 use xtra::Address;
 use xtra_libp2p::Endpoint;
 use xtra_libp2p::OpenSubstream;
@@ -32,7 +33,7 @@ const DECISION_TIMEOUT: Duration = Duration::from_secs(30);
 /// One actor to rule all the rollovers
 pub struct Actor<E, O> {
     endpoint: Address<Endpoint>,
-    oracle_pk: XOnlyPublicKey,
+    oracle_keys: OracleKeys,
     oracle: O,
     n_payouts: usize,
     executor: E,
@@ -61,7 +62,7 @@ impl<E, O> Actor<E, O> {
     pub fn new(
         endpoint: Address<Endpoint>,
         executor: E,
-        oracle_pk: XOnlyPublicKey,
+        oracle_keys: OracleKeys,
         get_announcement: O,
         n_payouts: usize,
     ) -> Self {
@@ -69,7 +70,7 @@ impl<E, O> Actor<E, O> {
             endpoint,
             executor,
             oracle: get_announcement,
-            oracle_pk,
+            oracle_keys,
             n_payouts,
         }
     }
@@ -119,12 +120,14 @@ where
             }
         };
 
+        let span = tracing::info_span!("Rollover", %order_id, peer_id = %maker_peer_id);
+
         tokio_extras::spawn_fallible(
             &ctx.address().expect("self to be alive"),
             {
                 let executor = self.executor.clone();
                 let oracle = self.oracle.clone();
-                let oracle_pk = self.oracle_pk;
+                let oracle_keys = self.oracle_keys;
                 let n_payouts = self.n_payouts;
                 async move {
                     let mut framed = asynchronous_codec::Framed::new(
@@ -133,7 +136,7 @@ where
                     );
 
                     let contract_symbol = executor
-                        .execute(order_id, |cfd| {
+                        .execute_with_retry(order_id, |cfd| {
                             let event = cfd.start_rollover_taker()?;
                             let contract_symbol = cfd.contract_symbol();
 
@@ -173,6 +176,12 @@ where
                             funding_rate,
                             complete_fee,
                         }) => {
+                            let oracle_pk = oracle_keys.for_event(
+                                *oracle_event_ids
+                                    .last()
+                                    .context("No oracle event to roll over to")?,
+                            );
+
                             let (rollover_params, dlc, position) = executor
                                 .execute(order_id, |cfd| {
                                     cfd.handle_rollover_accepted_taker(
@@ -356,11 +365,15 @@ where
                     }
                     Ok(())
                 }
+                .instrument(span.clone())
             },
             {
                 let executor = self.executor.clone();
-                move |e| async move {
-                    emit_failed(order_id, e, &executor).await;
+                move |e| {
+                    async move {
+                        emit_failed(order_id, e, &executor).await;
+                    }
+                    .instrument(span)
                 }
             },
         );
@@ -1,4 +1,10 @@
 use crate::multiaddress_ext::MultiaddrExt as _;
+use crate::rate_limit::RateLimiter;
+use crate::rate_limit::RateLimits;
+use crate::recorder::Recorder;
+use crate::restricted_protocols::RestrictedProtocols;
+use crate::substream::Leak;
+use crate::substream::SubstreamState;
 use crate::upgrade;
 use crate::Connection;
 use crate::Substream;
@@ -25,8 +31,14 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::marker::PhantomData;
 use std::pin::Pin;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::sync::Weak;
 use std::time::Duration;
+use std::time::Instant;
 use thiserror::Error;
 use tokio_extras::Tasks;
 use tracing::instrument;
@@ -37,6 +49,10 @@ use xtra::Context;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncNext;
 use xtras::SendAsyncSafe;
+use xtras::SendInterval;
+
+/// How often the [`Endpoint`] looks for leaked substreams.
+const SUBSTREAM_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
 
 /// An actor for managing multiplexed connections over a given transport thus representing an
 /// _endpoint_.
@@ -44,7 +60,15 @@ use xtras::SendAsyncSafe;
 /// The actor does not impose any policy on connection and/or protocol management.
 /// New connections can be established by sending a [`Connect`] messages. Existing connections can
 /// be disconnected by sending [`Disconnect`]. Listening for incoming connections is done by sending
-/// a [`ListenOn`] message. To list the current state, send the [`GetConnectionStats`] message.
+/// a [`ListenOn`] message and stopped for good by sending [`StopListening`]. To list the current
+/// state, send the [`GetConnectionStats`] message.
+/// For per-peer traffic and substream counters, send [`GetDetailedConnectionStats`].
+/// Peers can be prevented from connecting at runtime by sending [`BlockPeer`] and allowed again by
+/// sending [`UnblockPeer`].
+/// New inbound protocols can be canaried by only negotiating them with specific peers, see
+/// [`AllowProtocol`].
+/// Misbehaving peers can be kept from opening an unbounded number of inbound substreams by
+/// configuring [`RateLimits`], see [`Endpoint::with_rate_limits`].
 ///
 /// The combination of the above should make it possible to implement a fairly large number of
 /// policies. For example, to maintain a connection to an another endpoint, you can regularly check
@@ -55,16 +79,28 @@ use xtras::SendAsyncSafe;
 /// connection. Any incoming substream will - assuming the protocol is supported by the endpoint -
 /// trigger a [`NewInboundSubstream`] message to the actor provided in the constructor.
 /// Opening a new substream can be achieved by sending the [`OpenSubstream`] message.
+///
+/// Substreams which leaked, e.g. because they were never dropped after their protocol finished,
+/// are reset periodically. This includes the substreams of dropped connections, which would
+/// otherwise keep the remains of the connection alive.
 pub struct Endpoint {
     transport_fn: Box<dyn Fn() -> Boxed<Connection> + Send + 'static>,
-    controls: HashMap<PeerId, (yamux::Control, Tasks)>,
+    controls: HashMap<PeerId, EstablishedConnection>,
     inbound_substream_channels: HashMap<&'static str, MessageChannel<NewInboundSubstream, ()>>,
     listen_addresses: HashSet<Multiaddr>,
+    /// The tasks accepting incoming connections, per listen address
+    listeners: HashMap<Multiaddr, Tasks>,
+    stopped_listening: bool,
     inflight_connections: HashSet<PeerId>,
-    blocked_peers: Arc<HashSet<PeerId>>,
+    blocked_peers: Arc<RwLock<HashSet<PeerId>>>,
     connection_timeout: Duration,
     subscribers: Subscribers,
     peer_listen_protocols: HashMap<PeerId, HashSet<String>>,
+    recorder: Option<Recorder>,
+    restricted_protocols: RestrictedProtocols,
+    rate_limiter: Option<RateLimiter>,
+    /// The counters of dropped connections whose substreams were still open
+    dropped_connections: Vec<(PeerId, Weak<PeerCounters>)>,
 }
 
 /// Open a substream to the provided peer.
@@ -132,6 +168,47 @@ pub struct Connect(pub Multiaddr);
 #[derive(Clone, Copy, Debug)]
 pub struct Disconnect(pub PeerId);
 
+/// Block the given peer.
+///
+/// An existing connection to the peer is dropped and further connection attempts from and to the
+/// peer are refused until the peer is unblocked via [`UnblockPeer`].
+#[derive(Clone, Copy, Debug)]
+pub struct BlockPeer(pub PeerId);
+
+/// Allow a peer previously blocked via [`BlockPeer`] to connect again.
+#[derive(Clone, Copy, Debug)]
+pub struct UnblockPeer(pub PeerId);
+
+/// Retrieve the set of currently blocked peers from the [`Endpoint`].
+#[derive(Clone, Copy, Debug)]
+pub struct GetBlockedPeers;
+
+/// Negotiate the inbound `protocol` with `peer_id`.
+///
+/// If the protocol was negotiated with all peers so far, it will only be negotiated with the
+/// allowed peers from now on. Other peers are treated as if we did not support the protocol.
+/// Fails if the protocol is not one of the endpoint's inbound protocols.
+#[derive(Clone, Debug)]
+pub struct AllowProtocol {
+    pub protocol: String,
+    pub peer_id: PeerId,
+}
+
+/// Stop negotiating the restricted inbound `protocol` with `peer_id`.
+#[derive(Clone, Debug)]
+pub struct DisallowProtocol {
+    pub protocol: String,
+    pub peer_id: PeerId,
+}
+
+/// Negotiate the inbound protocol with all peers again.
+#[derive(Clone, Debug)]
+pub struct LiftProtocolRestriction(pub String);
+
+/// Retrieve the restricted inbound protocols and the peers allowed to negotiate them.
+#[derive(Clone, Copy, Debug)]
+pub struct GetRestrictedProtocols;
+
 /// Listen on the provided [`Multiaddr`].
 ///
 /// For this to work, the [`Endpoint`] needs to be constructed with a compatible transport.
@@ -139,6 +216,14 @@ pub struct Disconnect(pub PeerId);
 /// transport.
 pub struct ListenOn(pub Multiaddr);
 
+/// Stop accepting incoming connections on all listen addresses, keeping the established
+/// connections.
+///
+/// Meant for shutting down gracefully: any later [`ListenOn`] is ignored, and subscribers are not
+/// notified about the removed listen addresses so that they do not try to listen again.
+#[derive(Clone, Copy, Debug)]
+pub struct StopListening;
+
 /// Retrieve [`ConnectionStats`] from the [`Endpoint`].
 #[derive(Clone, Copy, Debug)]
 pub struct GetConnectionStats;
@@ -149,6 +234,123 @@ pub struct ConnectionStats {
     pub listen_addresses: HashSet<Multiaddr>,
 }
 
+/// Retrieve [`DetailedConnectionStats`] from the [`Endpoint`].
+#[derive(Clone, Copy, Debug)]
+pub struct GetDetailedConnectionStats;
+
+#[derive(Debug, Default)]
+pub struct DetailedConnectionStats {
+    pub peers: HashMap<PeerId, PeerStats>,
+    pub listen_addresses: HashSet<Multiaddr>,
+}
+
+/// Statistics about the connection to a single peer.
+///
+/// All counters start at zero when the connection is established.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerStats {
+    /// Bytes written to all substreams with the peer.
+    pub bytes_sent: u64,
+    /// Bytes read from all substreams with the peer.
+    pub bytes_received: u64,
+    /// Number of successfully negotiated substreams, inbound and outbound, per protocol.
+    pub substreams: HashMap<&'static str, u64>,
+    /// Number of substreams which are currently open, per protocol.
+    pub open_substreams: HashMap<&'static str, u64>,
+    /// Number of substreams for which protocol negotiation failed or timed out.
+    pub negotiation_failures: u64,
+    /// How long the connection has been established for.
+    pub connection_age: Duration,
+}
+
+/// Counters for a connection which are shared with its substreams.
+#[derive(Debug, Default)]
+pub(crate) struct PeerCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    substreams: Mutex<HashMap<&'static str, u64>>,
+    open_substreams: Mutex<HashMap<u64, Arc<SubstreamState>>>,
+    next_substream_id: AtomicU64,
+    negotiation_failures: AtomicU64,
+}
+
+impl PeerCounters {
+    pub(crate) fn record_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn record_substream(&self, protocol: &'static str) {
+        *self
+            .substreams
+            .lock()
+            .expect("lock not to be poisoned")
+            .entry(protocol)
+            .or_default() += 1;
+    }
+
+    fn record_negotiation_failure(&self) {
+        self.negotiation_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn register_substream(&self, state: Arc<SubstreamState>) -> u64 {
+        let id = self.next_substream_id.fetch_add(1, Ordering::Relaxed);
+        self.open_substreams
+            .lock()
+            .expect("lock not to be poisoned")
+            .insert(id, state);
+
+        id
+    }
+
+    pub(crate) fn deregister_substream(&self, id: u64) {
+        self.open_substreams
+            .lock()
+            .expect("lock not to be poisoned")
+            .remove(&id);
+    }
+
+    fn open_substreams(&self) -> Vec<Arc<SubstreamState>> {
+        self.open_substreams
+            .lock()
+            .expect("lock not to be poisoned")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    fn snapshot(&self, established_at: Instant) -> PeerStats {
+        let mut open_substreams = HashMap::new();
+        for state in self.open_substreams() {
+            *open_substreams.entry(state.protocol).or_default() += 1;
+        }
+
+        PeerStats {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            substreams: self
+                .substreams
+                .lock()
+                .expect("lock not to be poisoned")
+                .clone(),
+            open_substreams,
+            negotiation_failures: self.negotiation_failures.load(Ordering::Relaxed),
+            connection_age: established_at.elapsed(),
+        }
+    }
+}
+
+struct EstablishedConnection {
+    control: yamux::Control,
+    counters: Arc<PeerCounters>,
+    established_at: Instant,
+    _tasks: Tasks,
+}
+
 /// Notifies an actor of a new, inbound substream from the given peer.
 #[derive(Debug)]
 pub struct NewInboundSubstream {
@@ -178,6 +380,10 @@ pub enum Error {
     AlreadyTryingToConnected(PeerId),
     #[error("Peer does not listen for given protocol(s)")]
     ProtocolNotSupportedByPeer,
+    #[error("Peer {0} is blocked")]
+    PeerBlocked(PeerId),
+    #[error("Protocol {0} is not supported")]
+    UnsupportedProtocol(String),
 }
 
 /// Subscribers that get notified on connection changes
@@ -236,6 +442,7 @@ impl Endpoint {
         T::Dial: Send + 'static,
         T::ListenerUpgrade: Send + 'static,
     {
+        let restricted_protocols = RestrictedProtocols::default();
         let transport_fn = Box::new({
             let transport = Box::new(transport);
             let identity = identity;
@@ -243,12 +450,14 @@ impl Endpoint {
                 .iter()
                 .map(|(proto, _)| *proto)
                 .collect();
+            let restricted_protocols = restricted_protocols.clone();
 
             move || {
                 upgrade::transport(
                     (transport)(),
                     &identity,
                     handlers.clone(),
+                    restricted_protocols.clone(),
                     connection_timeout,
                 )
             }
@@ -259,14 +468,64 @@ impl Endpoint {
             inbound_substream_channels: verify_unique_handlers(inbound_substream_handlers),
             controls: HashMap::default(),
             listen_addresses: HashSet::default(),
+            listeners: HashMap::default(),
+            stopped_listening: false,
             inflight_connections: HashSet::default(),
-            blocked_peers,
+            blocked_peers: Arc::new(RwLock::new((*blocked_peers).clone())),
             connection_timeout,
             subscribers,
             peer_listen_protocols: HashMap::default(),
+            recorder: None,
+            restricted_protocols,
+            rate_limiter: None,
+            dropped_connections: Vec::new(),
         }
     }
 
+    /// Only negotiate the given inbound protocols with the peers allowed to use them, and the peers
+    /// allowed via [`AllowProtocol`] later on.
+    ///
+    /// Fails if any of the protocols is not one of the endpoint's inbound protocols.
+    pub fn with_restricted_protocols<P>(
+        self,
+        protocols: impl IntoIterator<Item = (P, HashSet<PeerId>)>,
+    ) -> Result<Self, Error>
+    where
+        P: AsRef<str>,
+    {
+        for (protocol, allowed_peers) in protocols {
+            let protocol = self.inbound_protocol(protocol.as_ref())?;
+            self.restricted_protocols.restrict(protocol);
+
+            for peer_id in allowed_peers {
+                self.restricted_protocols.allow(protocol, peer_id);
+            }
+        }
+
+        Ok(self)
+    }
+
+    fn inbound_protocol(&self, protocol: &str) -> Result<&'static str, Error> {
+        self.inbound_substream_channels
+            .get_key_value(protocol)
+            .map(|(protocol, _)| *protocol)
+            .ok_or_else(|| Error::UnsupportedProtocol(protocol.to_owned()))
+    }
+
+    /// Record the frames of all substreams with the given [`Recorder`].
+    #[must_use]
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Reject inbound substreams which exceed the given [`RateLimits`].
+    #[must_use]
+    pub fn with_rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(limits));
+        self
+    }
+
     fn does_peer_listen_for(&self, peer_id: PeerId, protocols: &[&str]) -> Result<(), Error> {
         let listen_protocols = match self.peer_listen_protocols.get(&peer_id) {
             Some(listen_protocols) => listen_protocols,
@@ -290,14 +549,31 @@ impl Endpoint {
         Ok(())
     }
 
+    fn is_blocked(&self, peer_id: &PeerId) -> bool {
+        self.blocked_peers
+            .read()
+            .expect("lock not to be poisoned")
+            .contains(peer_id)
+    }
+
     async fn drop_connection(&mut self, this: &Address<Self>, peer_id: &PeerId) {
         self.peer_listen_protocols.remove(peer_id);
 
-        let (mut control, tasks) = match self.controls.remove(peer_id) {
+        let EstablishedConnection {
+            mut control,
+            counters,
+            _tasks: tasks,
+            ..
+        } = match self.controls.remove(peer_id) {
             None => return,
-            Some(control) => control,
+            Some(connection) => connection,
         };
 
+        if !counters.open_substreams().is_empty() {
+            self.dropped_connections
+                .push((*peer_id, Arc::downgrade(&counters)));
+        }
+
         // TODO: Evaluate whether dropping and closing has to be in a particular order.
         tokio_extras::spawn(this, async move {
             let _ = control.close().await;
@@ -306,9 +582,11 @@ impl Endpoint {
         self.notify_connection_dropped(*peer_id).await;
     }
 
-    #[instrument(skip(control, connection_timeout))]
+    #[instrument(skip(control, counters, recorder, connection_timeout))]
     async fn open_substream(
         mut control: yamux::Control,
+        counters: Arc<PeerCounters>,
+        recorder: Option<Recorder>,
         peer_id: PeerId,
         protocols: Vec<&'static str>,
         connection_timeout: Duration,
@@ -318,24 +596,44 @@ impl Endpoint {
             .instrument(tracing::debug_span!("open yamux stream"))
             .await?;
 
-        let (protocol, stream) = tokio_extras::time::timeout(
+        let negotiation = tokio_extras::time::timeout(
             connection_timeout,
             multistream_select::dialer_select_proto(stream, protocols, Version::V1),
             || tracing::debug_span!("dialer_select_proto", version = ?Version::V1),
         )
         .await
-        .map_err(|_timeout| Error::NegotiationTimeoutReached)?
-        .map_err(Error::NegotiationFailed)?;
+        .map_err(|_timeout| Error::NegotiationTimeoutReached)
+        .and_then(|result| result.map_err(Error::NegotiationFailed));
+
+        let (protocol, stream) = match negotiation {
+            Ok(negotiated) => negotiated,
+            Err(e) => {
+                counters.record_negotiation_failure();
+                return Err(e);
+            }
+        };
+        counters.record_substream(protocol);
+
+        let recording = recorder
+            .and_then(|recorder| recorder.start(peer_id, protocol, libp2p_core::Endpoint::Dialer));
 
         Ok((
             protocol,
-            Substream::new(stream, protocol, libp2p_core::Endpoint::Dialer),
+            Substream::new(
+                stream,
+                protocol,
+                libp2p_core::Endpoint::Dialer,
+                counters,
+                recording,
+            ),
         ))
     }
 }
 
 #[xtra_productivity]
 impl Endpoint {
+    async fn handle(&mut self, _: xtras::Probe) {}
+
     async fn handle(&mut self, msg: NewConnection, ctx: &mut Context<Self>) {
         self.inflight_connections.remove(&msg.peer_id);
         let this = ctx.address().expect("we are alive");
@@ -347,10 +645,20 @@ impl Endpoint {
             worker,
         } = msg;
 
+        if self.is_blocked(&peer_id) {
+            tracing::debug!(%peer_id, "Dropping connection to blocked peer");
+            return;
+        }
+
+        let counters = Arc::new(PeerCounters::default());
+
         let mut tasks = Tasks::default();
         tasks.add(worker);
         tasks.add_fallible(
             {
+                let counters = counters.clone();
+                let recorder = self.recorder.clone();
+                let rate_limiter = self.rate_limiter.clone();
                 let inbound_substream_channels = self
                     .inbound_substream_channels
                     .iter()
@@ -363,10 +671,12 @@ impl Endpoint {
                             Ok(Some(Ok((stream, protocol)))) => (stream, protocol),
                             Ok(Some(Err(upgrade::Error::NegotiationTimeoutReached))) => {
                                 tracing::debug!("Hit timeout while negotiating substream");
+                                counters.record_negotiation_failure();
                                 continue;
                             }
                             Ok(Some(Err(upgrade::Error::NegotiationFailed(e)))) => {
                                 tracing::debug!("Failed to negotiate substream: {}", e);
+                                counters.record_negotiation_failure();
                                 continue;
                             }
                             Ok(None) => bail!("Substream listener closed"),
@@ -377,8 +687,36 @@ impl Endpoint {
                             .get(&protocol)
                             .expect("Cannot negotiate a protocol that we don't support");
 
-                        let stream =
-                            Substream::new(stream, protocol, libp2p_core::Endpoint::Listener);
+                        let permit = match &rate_limiter {
+                            Some(rate_limiter) => {
+                                match rate_limiter.acquire(peer_id, protocol, Instant::now()) {
+                                    Ok(permit) => Some(permit),
+                                    Err(rejection) => {
+                                        tracing::debug!(
+                                            %protocol,
+                                            "Rejecting inbound substream: {rejection}"
+                                        );
+                                        continue;
+                                    }
+                                }
+                            }
+                            None => None,
+                        };
+
+                        counters.record_substream(protocol);
+                        let recording = recorder.as_ref().and_then(|recorder| {
+                            recorder.start(peer_id, protocol, libp2p_core::Endpoint::Listener)
+                        });
+                        let mut stream = Substream::new(
+                            stream,
+                            protocol,
+                            libp2p_core::Endpoint::Listener,
+                            counters.clone(),
+                            recording,
+                        );
+                        if let Some(permit) = permit {
+                            stream = stream.with_permit(permit);
+                        }
 
                         let substream = NewInboundSubstream { peer_id, stream };
                         let span =
@@ -393,7 +731,14 @@ impl Endpoint {
             },
         );
 
-        if self.controls.insert(peer_id, (control, tasks)).is_some() {
+        let connection = EstablishedConnection {
+            control,
+            counters,
+            established_at: Instant::now(),
+            _tasks: tasks,
+        };
+
+        if self.controls.insert(peer_id, connection).is_some() {
             tracing::warn!(%peer_id, "Missed drop event, replacing old connection")
         }
 
@@ -431,6 +776,22 @@ impl Endpoint {
         }
     }
 
+    async fn handle(&mut self, _: GetDetailedConnectionStats) -> DetailedConnectionStats {
+        DetailedConnectionStats {
+            peers: self
+                .controls
+                .iter()
+                .map(|(peer_id, connection)| {
+                    (
+                        *peer_id,
+                        connection.counters.snapshot(connection.established_at),
+                    )
+                })
+                .collect(),
+            listen_addresses: self.listen_addresses.clone(),
+        }
+    }
+
     async fn handle(&mut self, msg: Connect, ctx: &mut Context<Self>) -> Result<(), Error> {
         let this = ctx.address().expect("we are alive");
 
@@ -444,6 +805,10 @@ impl Endpoint {
             return Err(Error::AlreadyTryingToConnected(peer_id));
         }
 
+        if self.is_blocked(&peer_id) {
+            return Err(Error::PeerBlocked(peer_id));
+        }
+
         let mut transport = (self.transport_fn)();
 
         self.inflight_connections.insert(peer_id);
@@ -490,14 +855,95 @@ impl Endpoint {
             .await;
     }
 
+    async fn handle(&mut self, msg: BlockPeer, ctx: &mut Context<Self>) {
+        let peer_id = msg.0;
+
+        self.blocked_peers
+            .write()
+            .expect("lock not to be poisoned")
+            .insert(peer_id);
+        tracing::info!(%peer_id, "Blocked peer");
+
+        self.drop_connection(&ctx.address().expect("self to be alive"), &peer_id)
+            .await;
+    }
+
+    async fn handle(&mut self, msg: UnblockPeer) {
+        let peer_id = msg.0;
+
+        if self
+            .blocked_peers
+            .write()
+            .expect("lock not to be poisoned")
+            .remove(&peer_id)
+        {
+            tracing::info!(%peer_id, "Unblocked peer");
+        }
+    }
+
+    async fn handle(&mut self, msg: AllowProtocol) -> Result<(), Error> {
+        let protocol = self.inbound_protocol(&msg.protocol)?;
+        self.restricted_protocols.allow(protocol, msg.peer_id);
+
+        tracing::info!(
+            peer_id = %msg.peer_id,
+            %protocol,
+            "Allowed peer to use restricted protocol"
+        );
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, msg: DisallowProtocol) -> Result<(), Error> {
+        let protocol = self.inbound_protocol(&msg.protocol)?;
+        self.restricted_protocols.disallow(protocol, msg.peer_id);
+
+        tracing::info!(
+            peer_id = %msg.peer_id,
+            %protocol,
+            "Disallowed peer to use restricted protocol"
+        );
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, msg: LiftProtocolRestriction) -> Result<(), Error> {
+        let protocol = self.inbound_protocol(&msg.0)?;
+        self.restricted_protocols.lift(protocol);
+
+        tracing::info!(%protocol, "Lifted protocol restriction");
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, _: GetRestrictedProtocols) -> HashMap<String, HashSet<PeerId>> {
+        self.restricted_protocols
+            .snapshot()
+            .into_iter()
+            .map(|(protocol, allowed_peers)| (protocol.to_owned(), allowed_peers))
+            .collect()
+    }
+
+    async fn handle(&mut self, _: GetBlockedPeers) -> HashSet<PeerId> {
+        self.blocked_peers
+            .read()
+            .expect("lock not to be poisoned")
+            .clone()
+    }
+
     async fn handle(&mut self, msg: ListenOn, ctx: &mut Context<Self>) {
         let this = ctx.address().expect("we are alive");
         let listen_address = msg.0.clone();
 
+        if self.stopped_listening {
+            tracing::debug!(%listen_address, "Not listening after listening was stopped");
+            return;
+        }
+
         let mut transport = (self.transport_fn)();
 
-        tokio_extras::spawn_fallible::<_, _, _, (), _, _, _>(
-            &this.clone(),
+        let mut listener = Tasks::default();
+        listener.add_fallible(
             {
                 let blocked_peers = self.blocked_peers.clone();
                 let this = this.clone();
@@ -537,7 +983,11 @@ impl Endpoint {
                                                 }
                                             })?;
 
-                                        if blocked_peers.contains(&peer_id) {
+                                        if blocked_peers
+                                            .read()
+                                            .expect("lock not to be poisoned")
+                                            .contains(&peer_id)
+                                        {
                                             tracing::trace!(
                                                 target: "blocked_peers",
                                                 peer_id = %peer_id, // Weird but required
@@ -569,15 +1019,29 @@ impl Endpoint {
                     }
                 }
             },
-            |error| async move {
-                let _ = this
-                    .send(ListenerFailed {
-                        address: listen_address,
-                        error,
-                    })
-                    .await;
+            {
+                let listen_address = listen_address.clone();
+                |error| async move {
+                    let _ = this
+                        .send(ListenerFailed {
+                            address: listen_address,
+                            error,
+                        })
+                        .await;
+                }
             },
         );
+        self.listeners.insert(listen_address, listener);
+    }
+
+    async fn handle(&mut self, _: StopListening) {
+        self.stopped_listening = true;
+
+        // Dropping the tasks closes the listeners
+        self.listeners.clear();
+        for address in self.listen_addresses.drain() {
+            tracing::info!(%address, "Stopped listening");
+        }
     }
 
     #[must_use]
@@ -595,7 +1059,7 @@ impl Endpoint {
             "Type-system enforces that we only try to negotiate one protocol"
         );
 
-        let (control, _) = self
+        let connection = self
             .controls
             .get(&peer_id)
             .ok_or(Error::NoConnection(peer_id))?;
@@ -605,11 +1069,19 @@ impl Endpoint {
         let this = ctx.address().expect("self to be alive");
         let fut = {
             let connection_timeout = self.connection_timeout;
-            let control = control.clone();
+            let control = connection.control.clone();
+            let counters = connection.counters.clone();
+            let recorder = self.recorder.clone();
             async move {
-                let res =
-                    Self::open_substream(control, peer_id, protocols.clone(), connection_timeout)
-                        .await;
+                let res = Self::open_substream(
+                    control,
+                    counters,
+                    recorder,
+                    peer_id,
+                    protocols.clone(),
+                    connection_timeout,
+                )
+                .await;
 
                 if let Err(Error::BadConnection(e)) = &res {
                     tracing::debug!(
@@ -644,14 +1116,23 @@ impl Endpoint {
         let peer = msg.peer_id;
         let protocols = msg.protocols;
 
-        let (control, _) = self.controls.get(&peer).ok_or(Error::NoConnection(peer))?;
+        let connection = self.controls.get(&peer).ok_or(Error::NoConnection(peer))?;
 
         let fut = {
             let connection_timeout = self.connection_timeout;
-            let control = control.clone();
+            let control = connection.control.clone();
+            let counters = connection.counters.clone();
+            let recorder = self.recorder.clone();
             async move {
-                let (protocol, stream) =
-                    Self::open_substream(control, peer, protocols, connection_timeout).await?;
+                let (protocol, stream) = Self::open_substream(
+                    control,
+                    counters,
+                    recorder,
+                    peer,
+                    protocols,
+                    connection_timeout,
+                )
+                .await?;
 
                 Ok((protocol, stream))
             }
@@ -661,6 +1142,10 @@ impl Endpoint {
     }
 
     async fn handle(&mut self, msg: NewListenAddress) {
+        if self.stopped_listening {
+            return;
+        }
+
         // FIXME: This address could be a "catch-all" like "0.0.0.0" which actually results in
         // listening on multiple interfaces.
         self.listen_addresses.insert(msg.listen_address.clone());
@@ -671,6 +1156,37 @@ impl Endpoint {
         self.peer_listen_protocols
             .insert(msg.peer_id, msg.listen_protocols);
     }
+
+    async fn handle(&mut self, _: CleanupSubstreams) {
+        let now = Instant::now();
+
+        for (peer_id, connection) in self.controls.iter() {
+            for state in connection.counters.open_substreams() {
+                if let Some(leak) = state.leak(now) {
+                    state.reset(*peer_id, leak);
+                }
+            }
+        }
+
+        // Whoever still holds a substream of a dropped connection keeps the
+        // remains of the connection alive
+        self.dropped_connections.retain(|(peer_id, counters)| {
+            let counters = match counters.upgrade() {
+                Some(counters) => counters,
+                None => return false,
+            };
+
+            for state in counters.open_substreams() {
+                state.reset(*peer_id, Leak::ConnectionDropped);
+            }
+
+            true
+        });
+
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.prune(now);
+        }
+    }
 }
 
 impl Endpoint {
@@ -740,9 +1256,26 @@ fn verify_unique_handlers<const N: usize>(
 impl xtra::Actor for Endpoint {
     type Stop = ();
 
+    async fn started(&mut self, ctx: &mut Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        tokio_extras::spawn(
+            &this.clone(),
+            this.send_interval(
+                SUBSTREAM_CLEANUP_INTERVAL,
+                || CleanupSubstreams,
+                xtras::IncludeSpan::Never,
+            ),
+        );
+    }
+
     async fn stopped(self) -> Self::Stop {}
 }
 
+/// Message sent to ourselves at an interval to reset leaked substreams and to prune the state of
+/// the rate limiter.
+#[derive(Clone, Copy)]
+struct CleanupSubstreams;
+
 #[derive(Debug)]
 struct ListenerFailed {
     address: Multiaddr,
@@ -1,14 +1,26 @@
+pub use crate::endpoint::AllowProtocol;
+pub use crate::endpoint::BlockPeer;
 pub use crate::endpoint::Connect;
 pub use crate::endpoint::ConnectionStats;
+pub use crate::endpoint::DetailedConnectionStats;
+pub use crate::endpoint::DisallowProtocol;
 pub use crate::endpoint::Disconnect;
 pub use crate::endpoint::Endpoint;
 pub use crate::endpoint::Error;
+pub use crate::endpoint::GetBlockedPeers;
 pub use crate::endpoint::GetConnectionStats;
+pub use crate::endpoint::GetDetailedConnectionStats;
+pub use crate::endpoint::GetRestrictedProtocols;
+pub use crate::endpoint::LiftProtocolRestriction;
 pub use crate::endpoint::ListenOn;
 pub use crate::endpoint::Multiple;
 pub use crate::endpoint::NewInboundSubstream;
 pub use crate::endpoint::OpenSubstream;
+pub use crate::endpoint::PeerStats;
 pub use crate::endpoint::Single;
+pub use crate::endpoint::StopListening;
+pub use crate::endpoint::UnblockPeer;
+pub use crate::rate_limit::RateLimits;
 pub use crate::substream::Substream;
 pub use libp2p_core as libp2p;
 pub use multistream_select::NegotiationError;
@@ -22,6 +34,10 @@ pub mod dialer;
 pub mod endpoint;
 pub mod listener;
 pub mod multiaddress_ext;
+mod rate_limit;
+pub mod recorder;
+mod restricted_protocols;
+pub mod socks5;
 mod substream;
 mod upgrade;
 mod verify_peer_id;
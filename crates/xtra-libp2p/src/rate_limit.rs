@@ -0,0 +1,278 @@
+use conquer_once::Lazy;
+use libp2p_core::PeerId;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The window over which the rate of inbound substreams is measured.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Limits on the inbound substreams peers may open.
+///
+/// A substream which exceeds any of the limits is dropped right after protocol negotiation,
+/// before it is handed to the protocol's handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimits {
+    /// How many inbound substreams a single peer may open per minute.
+    pub per_peer_substreams_per_minute: usize,
+    /// How many inbound substreams all peers together may open per minute.
+    pub global_substreams_per_minute: usize,
+    /// How many inbound substreams a single peer may have open at the same time.
+    pub per_peer_concurrent_substreams: usize,
+    /// How many inbound substreams all peers together may have open at the same time.
+    pub global_concurrent_substreams: usize,
+}
+
+/// Why an inbound substream was rejected.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rejection {
+    #[error("Peer opened too many substreams in the last minute")]
+    PeerRate,
+    #[error("Too many substreams were opened in the last minute")]
+    GlobalRate,
+    #[error("Peer has too many open substreams")]
+    PeerConcurrency,
+    #[error("Too many substreams are open")]
+    GlobalConcurrency,
+}
+
+impl Rejection {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Rejection::PeerRate => "peer_rate",
+            Rejection::GlobalRate => "global_rate",
+            Rejection::PeerConcurrency => "peer_concurrency",
+            Rejection::GlobalConcurrency => "global_concurrency",
+        }
+    }
+}
+
+/// Enforces [`RateLimits`] on the inbound substreams of all connections of an endpoint.
+#[derive(Clone, Debug)]
+pub(crate) struct RateLimiter {
+    limits: RateLimits,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    global: Usage,
+    peers: HashMap<PeerId, Usage>,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    /// When the substreams of the last [`RATE_WINDOW`] were opened, oldest first
+    opened: VecDeque<Instant>,
+    concurrent: usize,
+}
+
+impl Usage {
+    fn prune(&mut self, now: Instant) {
+        while let Some(opened_at) = self.opened.front() {
+            if now.saturating_duration_since(*opened_at) < RATE_WINDOW {
+                break;
+            }
+
+            self.opened.pop_front();
+        }
+    }
+
+    fn is_unused(&self) -> bool {
+        self.opened.is_empty() && self.concurrent == 0
+    }
+
+    fn acquire(&mut self, now: Instant) {
+        self.opened.push_back(now);
+        self.concurrent += 1;
+    }
+
+    fn release(&mut self) {
+        self.concurrent = self.concurrent.saturating_sub(1);
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            state: Arc::default(),
+        }
+    }
+
+    /// Account for a new inbound substream of `protocol` opened by `peer_id`.
+    ///
+    /// The substream counts towards the concurrency limits until the returned [`Permit`] is
+    /// dropped.
+    pub(crate) fn acquire(
+        &self,
+        peer_id: PeerId,
+        protocol: &'static str,
+        now: Instant,
+    ) -> Result<Permit, Rejection> {
+        let mut state = self.state.lock().expect("lock not to be poisoned");
+        let State { global, peers } = &mut *state;
+
+        global.prune(now);
+        let peer = peers.entry(peer_id).or_default();
+        peer.prune(now);
+
+        let rejection = if peer.concurrent >= self.limits.per_peer_concurrent_substreams {
+            Some(Rejection::PeerConcurrency)
+        } else if peer.opened.len() >= self.limits.per_peer_substreams_per_minute {
+            Some(Rejection::PeerRate)
+        } else if global.concurrent >= self.limits.global_concurrent_substreams {
+            Some(Rejection::GlobalConcurrency)
+        } else if global.opened.len() >= self.limits.global_substreams_per_minute {
+            Some(Rejection::GlobalRate)
+        } else {
+            None
+        };
+
+        if let Some(rejection) = rejection {
+            if peer.is_unused() {
+                peers.remove(&peer_id);
+            }
+
+            SUBSTREAMS_REJECTED_COUNTER
+                .with(&HashMap::from([
+                    (PROTOCOL_LABEL, protocol),
+                    (REASON_LABEL, rejection.as_str()),
+                ]))
+                .inc();
+
+            return Err(rejection);
+        }
+
+        peer.acquire(now);
+        global.acquire(now);
+
+        Ok(Permit {
+            peer_id,
+            state: self.state.clone(),
+        })
+    }
+
+    /// Forget about peers which did not open any substreams recently.
+    pub(crate) fn prune(&self, now: Instant) {
+        let mut state = self.state.lock().expect("lock not to be poisoned");
+
+        state.global.prune(now);
+        state.peers.retain(|_, usage| {
+            usage.prune(now);
+            !usage.is_unused()
+        });
+    }
+}
+
+/// An inbound substream accounted for by the [`RateLimiter`].
+#[derive(Debug)]
+pub(crate) struct Permit {
+    peer_id: PeerId,
+    state: Arc<Mutex<State>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().expect("lock not to be poisoned");
+
+        state.global.release();
+        if let Some(usage) = state.peers.get_mut(&self.peer_id) {
+            usage.release();
+        }
+    }
+}
+
+const PROTOCOL_LABEL: &str = "protocol";
+
+/// Which limit an inbound substream exceeded.
+const REASON_LABEL: &str = "reason";
+
+static SUBSTREAMS_REJECTED_COUNTER: Lazy<prometheus::IntCounterVec> = Lazy::new(|| {
+    prometheus::register_int_counter_vec!(
+        "substreams_rejected_total",
+        "The total number of inbound substreams which were rejected because they exceeded a rate limit, segregated by protocol and reason.",
+        &[PROTOCOL_LABEL, REASON_LABEL]
+    )
+    .unwrap()
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROTOCOL: &str = "/test/1.0.0";
+
+    fn limits() -> RateLimits {
+        RateLimits {
+            per_peer_substreams_per_minute: 2,
+            global_substreams_per_minute: 3,
+            per_peer_concurrent_substreams: 1,
+            global_concurrent_substreams: 2,
+        }
+    }
+
+    #[test]
+    fn peer_cannot_exceed_concurrent_substreams_until_one_is_dropped() {
+        let limiter = RateLimiter::new(limits());
+        let peer_id = PeerId::random();
+        let now = Instant::now();
+
+        let permit = limiter.acquire(peer_id, PROTOCOL, now).unwrap();
+        assert_eq!(
+            limiter.acquire(peer_id, PROTOCOL, now).unwrap_err(),
+            Rejection::PeerConcurrency
+        );
+
+        drop(permit);
+        assert!(limiter.acquire(peer_id, PROTOCOL, now).is_ok());
+    }
+
+    #[test]
+    fn peer_cannot_exceed_substreams_per_minute_until_window_passed() {
+        let limiter = RateLimiter::new(limits());
+        let peer_id = PeerId::random();
+        let now = Instant::now();
+
+        limiter.acquire(peer_id, PROTOCOL, now).unwrap();
+        limiter.acquire(peer_id, PROTOCOL, now).unwrap();
+        assert_eq!(
+            limiter.acquire(peer_id, PROTOCOL, now).unwrap_err(),
+            Rejection::PeerRate
+        );
+
+        assert!(limiter
+            .acquire(peer_id, PROTOCOL, now + RATE_WINDOW)
+            .is_ok());
+    }
+
+    #[test]
+    fn global_limits_apply_across_peers() {
+        let limiter = RateLimiter::new(limits());
+        let now = Instant::now();
+
+        let first = limiter.acquire(PeerId::random(), PROTOCOL, now).unwrap();
+        let second = limiter.acquire(PeerId::random(), PROTOCOL, now).unwrap();
+        assert_eq!(
+            limiter
+                .acquire(PeerId::random(), PROTOCOL, now)
+                .unwrap_err(),
+            Rejection::GlobalConcurrency
+        );
+
+        drop(second);
+        let third = limiter.acquire(PeerId::random(), PROTOCOL, now).unwrap();
+        drop(third);
+        assert_eq!(
+            limiter
+                .acquire(PeerId::random(), PROTOCOL, now)
+                .unwrap_err(),
+            Rejection::GlobalRate
+        );
+
+        drop(first);
+    }
+}
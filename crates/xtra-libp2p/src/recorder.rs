@@ -0,0 +1,593 @@
+//! Recording of the frames exchanged over substreams.
+//!
+//! Interop bugs between makers and takers running different versions
+//! are hard to reproduce. If the [`Endpoint`](crate::Endpoint) is
+//! configured with a [`Recorder`], every substream writes the frames
+//! it sends and receives to a file in the recorder's directory. Our
+//! protocols exchange JSON values, so each complete JSON value read
+//! from or written to a substream becomes a [`Frame`]. Bytes which are
+//! not JSON are recorded as hex.
+//!
+//! Fields which might contain secrets are redacted before they are
+//! written to disk, see [`redact`].
+//!
+//! A recording consists of a [`Header`] line followed by one line per
+//! [`Frame`] and can be read back with [`load`]. The frames the peer
+//! sent can then be fed to our protocol handlers through a
+//! [`ReplayedSubstream`].
+
+use anyhow::Context;
+use anyhow::Result;
+use futures::AsyncRead;
+use futures::AsyncWrite;
+use futures::Stream;
+use futures::StreamExt;
+use libp2p_core::Endpoint;
+use libp2p_core::PeerId;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Instant;
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+
+/// Value replacing redacted fields.
+pub const REDACTED: &str = "<redacted>";
+
+/// Creates a recording for each substream of the endpoint.
+///
+/// Substreams must not block on disk I/O while they are polled, so
+/// they only pass the bytes they read and write to a background thread
+/// which extracts the frames and writes them to the recording's file.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+    n_recordings: Arc<AtomicU64>,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Recorder {
+    /// Record substreams into files in `dir`.
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create recording directory {}", dir.display()))?;
+
+        let (commands, receiver) = mpsc::unbounded_channel();
+        std::thread::Builder::new()
+            .name("protocol-recorder".to_owned())
+            .spawn(move || write_recordings(receiver))
+            .context("Failed to spawn recording thread")?;
+
+        Ok(Self {
+            dir,
+            n_recordings: Arc::default(),
+            commands,
+        })
+    }
+
+    /// Start recording a new substream.
+    ///
+    /// Failing to record must never affect the protocol, so errors
+    /// are logged and no recording is made.
+    pub(crate) fn start(
+        &self,
+        peer_id: PeerId,
+        protocol: &'static str,
+        role: Endpoint,
+    ) -> Option<Recording> {
+        let header = Header {
+            protocol: protocol.to_owned(),
+            role: role.into(),
+            peer_id: peer_id.to_string(),
+            started_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_secs())
+                .unwrap_or_default(),
+        };
+
+        let id = self.n_recordings.fetch_add(1, Ordering::Relaxed);
+        let file_name = format!(
+            "{}-{id}-{}-{}.jsonl",
+            header.started_at,
+            protocol.trim_start_matches('/').replace('/', "_"),
+            header.role,
+        );
+        let path = self.dir.join(file_name);
+
+        if self
+            .commands
+            .send(Command::Start { id, path, header })
+            .is_err()
+        {
+            tracing::warn!("Failed to start recording: recording thread is gone");
+            return None;
+        }
+
+        Some(Recording {
+            id,
+            started_at: Instant::now(),
+            commands: self.commands.clone(),
+        })
+    }
+}
+
+/// The recording of a single substream.
+pub(crate) struct Recording {
+    id: u64,
+    started_at: Instant,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Recording {
+    pub(crate) fn record_inbound(&mut self, bytes: &[u8]) {
+        self.record(Direction::Inbound, bytes);
+    }
+
+    pub(crate) fn record_outbound(&mut self, bytes: &[u8]) {
+        self.record(Direction::Outbound, bytes);
+    }
+
+    fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+
+        // Failing to send means the recording thread is gone, which
+        // has already been logged
+        let _ = self.commands.send(Command::Record {
+            id: self.id,
+            elapsed_ms: self.started_at.elapsed().as_millis() as u64,
+            direction,
+            bytes: bytes.to_vec(),
+        });
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Finish { id: self.id });
+    }
+}
+
+/// What the recording thread should do next.
+#[derive(Debug)]
+enum Command {
+    Start {
+        id: u64,
+        path: PathBuf,
+        header: Header,
+    },
+    Record {
+        id: u64,
+        elapsed_ms: u64,
+        direction: Direction,
+        bytes: Vec<u8>,
+    },
+    Finish {
+        id: u64,
+    },
+}
+
+/// Write recordings until all senders of `commands` are dropped.
+///
+/// Runs on a dedicated thread, so it can block on disk I/O.
+fn write_recordings(mut commands: mpsc::UnboundedReceiver<Command>) {
+    let mut files = HashMap::new();
+
+    while let Some(command) = commands.blocking_recv() {
+        match command {
+            Command::Start { id, path, header } => match RecordingFile::create(&path, &header) {
+                Ok(file) => {
+                    files.insert(id, file);
+                }
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), "Failed to start recording: {e:#}");
+                }
+            },
+            Command::Record {
+                id,
+                elapsed_ms,
+                direction,
+                bytes,
+            } => {
+                if let Some(file) = files.get_mut(&id) {
+                    file.record(elapsed_ms, direction, &bytes);
+                }
+            }
+            Command::Finish { id } => {
+                files.remove(&id);
+            }
+        }
+    }
+}
+
+/// The file a single substream is recorded into.
+struct RecordingFile {
+    writer: BufWriter<File>,
+    inbound: Vec<u8>,
+    outbound: Vec<u8>,
+}
+
+impl RecordingFile {
+    fn create(path: &Path, header: &Header) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, header)?;
+        writer.write_all(b"\n")?;
+
+        Ok(Self {
+            writer,
+            inbound: Vec::new(),
+            outbound: Vec::new(),
+        })
+    }
+
+    fn record(&mut self, elapsed_ms: u64, direction: Direction, bytes: &[u8]) {
+        let buffer = match direction {
+            Direction::Inbound => &mut self.inbound,
+            Direction::Outbound => &mut self.outbound,
+        };
+        buffer.extend_from_slice(bytes);
+
+        for payload in extract_payloads(buffer) {
+            let frame = Frame {
+                elapsed_ms,
+                direction,
+                payload,
+            };
+
+            if let Err(e) = self.write_frame(&frame) {
+                tracing::warn!("Failed to record frame: {e:#}");
+            }
+        }
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, frame)?;
+        self.writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+}
+
+impl Drop for RecordingFile {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+/// Take all complete JSON values from the start of `buffer`.
+///
+/// Incomplete values are left in the buffer until more bytes arrive.
+/// If the buffer does not contain JSON, its content is returned as a
+/// raw payload.
+fn extract_payloads(buffer: &mut Vec<u8>) -> Vec<Payload> {
+    let mut payloads = Vec::new();
+
+    let mut values =
+        serde_json::Deserializer::from_slice(&buffer[..]).into_iter::<serde_json::Value>();
+    let consumed = loop {
+        match values.next() {
+            Some(Ok(mut value)) => {
+                redact(&mut value);
+                payloads.push(Payload::Json(value));
+            }
+            Some(Err(e)) if e.is_eof() => break values.byte_offset(),
+            Some(Err(_)) => {
+                payloads.push(Payload::Raw(hex::encode(&buffer[values.byte_offset()..])));
+                break buffer.len();
+            }
+            None => break values.byte_offset(),
+        }
+    };
+
+    buffer.drain(..consumed);
+
+    payloads
+}
+
+/// Replace all fields of `value` which might contain secrets with
+/// [`REDACTED`].
+///
+/// Secret keys are named `*_sk` or contain `secret` in our protocols.
+pub fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                let name = name.to_lowercase();
+
+                if name.ends_with("_sk") || name == "sk" || name.contains("secret") {
+                    *field = serde_json::Value::String(REDACTED.to_owned());
+                } else {
+                    redact(field);
+                }
+            }
+        }
+        serde_json::Value::Array(elements) => elements.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Read a recording written by a [`Recorder`].
+pub fn load(path: &Path) -> Result<(Header, Vec<Frame>)> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut lines = BufReader::new(file).lines();
+
+    let header = lines.next().context("Recording is empty")??;
+    let header = serde_json::from_str(&header).context("Failed to parse recording header")?;
+
+    let frames = lines
+        .enumerate()
+        .map(|(i, line)| {
+            let frame = serde_json::from_str(&line?)
+                .with_context(|| format!("Failed to parse frame {i}"))?;
+
+            Ok(frame)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((header, frames))
+}
+
+/// A substream on which the peer sends the frames of a recording.
+///
+/// Used to run a protocol's handlers against a recorded session in
+/// isolation. What the handlers write is discarded.
+pub struct ReplayedSubstream {
+    inbound: futures::io::Cursor<Vec<u8>>,
+}
+
+impl ReplayedSubstream {
+    /// Replay the frames sent by the peer, in order.
+    pub fn new<'a>(frames: impl IntoIterator<Item = &'a serde_json::Value>) -> Self {
+        let bytes = frames
+            .into_iter()
+            .flat_map(|frame| frame.to_string().into_bytes())
+            .collect();
+
+        Self {
+            inbound: futures::io::Cursor::new(bytes),
+        }
+    }
+}
+
+impl AsyncRead for ReplayedSubstream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inbound).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ReplayedSubstream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Receive the next message the peer sent on a [`ReplayedSubstream`].
+///
+/// Recordings end wherever the session ended, e.g. because a proposal
+/// was rejected or because the next frame was redacted. Running out of
+/// messages is thus not an error.
+pub async fn next_replayed<S, T, E>(stream: &mut S) -> Result<Option<T>>
+where
+    S: Stream<Item = Result<T, E>> + Unpin,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    stream
+        .next()
+        .await
+        .transpose()
+        .context("Failed to decode message")
+}
+
+/// Fail if the peer sent anything after the handler finished.
+pub async fn ensure_replay_finished<S, T, E>(stream: &mut S) -> Result<()>
+where
+    S: Stream<Item = Result<T, E>> + Unpin,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    if stream.next().await.is_some() {
+        anyhow::bail!("Peer sent a message after the protocol finished");
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub protocol: String,
+    pub role: Role,
+    pub peer_id: String,
+    /// Unix timestamp of the start of the recording
+    pub started_at: u64,
+}
+
+/// Whether we opened the substream or the peer did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Dialer,
+    Listener,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Dialer => write!(f, "dialer"),
+            Role::Listener => write!(f, "listener"),
+        }
+    }
+}
+
+impl From<Endpoint> for Role {
+    fn from(endpoint: Endpoint) -> Self {
+        match endpoint {
+            Endpoint::Dialer => Role::Dialer,
+            Endpoint::Listener => Role::Listener,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Frame {
+    /// Milliseconds since the substream was opened
+    pub elapsed_ms: u64,
+    pub direction: Direction,
+    pub payload: Payload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Payload {
+    Json(serde_json::Value),
+    /// Hex-encoded bytes which are not JSON
+    Raw(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_complete_json_values_only() {
+        let mut buffer = br#"{"a":1}{"b":"#.to_vec();
+
+        let payloads = extract_payloads(&mut buffer);
+
+        assert_eq!(payloads, vec![Payload::Json(serde_json::json!({ "a": 1 }))]);
+        assert_eq!(buffer, br#"{"b":"#.to_vec());
+
+        buffer.extend_from_slice(b"2}");
+        let payloads = extract_payloads(&mut buffer);
+
+        assert_eq!(payloads, vec![Payload::Json(serde_json::json!({ "b": 2 }))]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn non_json_bytes_are_recorded_as_hex() {
+        let mut buffer = vec![0xde, 0xad, 0xbe, 0xef];
+
+        let payloads = extract_payloads(&mut buffer);
+
+        assert_eq!(payloads, vec![Payload::Raw("deadbeef".to_owned())]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn recording_thread_writes_frames_of_finished_recordings() {
+        let dir = std::env::temp_dir().join(format!("recorder-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("recording.jsonl");
+        let header = Header {
+            protocol: "/foo/1.0.0".to_owned(),
+            role: Role::Dialer,
+            peer_id: "12D3KooWP3BN6bq9jPy8cP7Grj1QyUBfr7U6BeQFgMwfTTu12wuY".to_owned(),
+            started_at: 0,
+        };
+
+        let (commands, receiver) = mpsc::unbounded_channel();
+        for command in [
+            Command::Start {
+                id: 0,
+                path: path.clone(),
+                header: header.clone(),
+            },
+            Command::Record {
+                id: 0,
+                elapsed_ms: 1,
+                direction: Direction::Outbound,
+                bytes: br#"{"a":"#.to_vec(),
+            },
+            Command::Record {
+                id: 0,
+                elapsed_ms: 2,
+                direction: Direction::Outbound,
+                bytes: b"1}".to_vec(),
+            },
+            Command::Finish { id: 0 },
+        ] {
+            commands.send(command).unwrap();
+        }
+        drop(commands);
+
+        write_recordings(receiver);
+        let (loaded_header, frames) = load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded_header, header);
+        assert_eq!(
+            frames,
+            vec![Frame {
+                elapsed_ms: 2,
+                direction: Direction::Outbound,
+                payload: Payload::Json(serde_json::json!({ "a": 1 })),
+            }]
+        );
+    }
+
+    #[test]
+    fn redacts_secret_keys_in_nested_values() {
+        let mut value = serde_json::json!({
+            "Msg2": {
+                "revocation_sk": "deadbeef",
+                "revocation_pk": "cafebabe",
+                "inner": [{ "publish_sk": "deadbeef" }]
+            }
+        });
+
+        redact(&mut value);
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "Msg2": {
+                    "revocation_sk": REDACTED,
+                    "revocation_pk": "cafebabe",
+                    "inner": [{ "publish_sk": REDACTED }]
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use libp2p_core::PeerId;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+/// Inbound protocols which are only negotiated with specific peers.
+///
+/// Allows rolling out a new protocol (version) to a few peers before
+/// offering it to everyone. Peers which are not allowed to use a
+/// restricted protocol are treated as if we did not support it, i.e.
+/// they fall back to the next protocol they are willing to speak.
+#[derive(Clone, Default, Debug)]
+pub(crate) struct RestrictedProtocols(Arc<RwLock<HashMap<&'static str, HashSet<PeerId>>>>);
+
+impl RestrictedProtocols {
+    /// Allow `peer_id` to negotiate `protocol`, restricting the
+    /// protocol to the allowed peers if it was not restricted before.
+    pub(crate) fn allow(&self, protocol: &'static str, peer_id: PeerId) {
+        self.0
+            .write()
+            .expect("lock not to be poisoned")
+            .entry(protocol)
+            .or_default()
+            .insert(peer_id);
+    }
+
+    /// Stop negotiating `protocol` with `peer_id`.
+    ///
+    /// The protocol stays restricted, even if no peer is allowed to
+    /// negotiate it anymore.
+    pub(crate) fn disallow(&self, protocol: &'static str, peer_id: PeerId) {
+        if let Some(allowed_peers) = self
+            .0
+            .write()
+            .expect("lock not to be poisoned")
+            .get_mut(protocol)
+        {
+            allowed_peers.remove(&peer_id);
+        }
+    }
+
+    /// Restrict `protocol` without allowing any peer to negotiate it.
+    pub(crate) fn restrict(&self, protocol: &'static str) {
+        self.0
+            .write()
+            .expect("lock not to be poisoned")
+            .entry(protocol)
+            .or_default();
+    }
+
+    /// Negotiate `protocol` with all peers.
+    pub(crate) fn lift(&self, protocol: &'static str) {
+        self.0
+            .write()
+            .expect("lock not to be poisoned")
+            .remove(protocol);
+    }
+
+    /// The subset of `protocols` which may be negotiated with `peer_id`.
+    pub(crate) fn filter(&self, peer_id: &PeerId, protocols: &[&'static str]) -> Vec<&'static str> {
+        let restricted = self.0.read().expect("lock not to be poisoned");
+
+        protocols
+            .iter()
+            .copied()
+            .filter(|protocol| match restricted.get(protocol) {
+                Some(allowed_peers) => allowed_peers.contains(peer_id),
+                None => true,
+            })
+            .collect()
+    }
+
+    pub(crate) fn snapshot(&self) -> HashMap<&'static str, HashSet<PeerId>> {
+        self.0.read().expect("lock not to be poisoned").clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STABLE: &str = "/stable/1.0.0";
+    const EXPERIMENTAL: &str = "/experimental/1.0.0";
+
+    #[test]
+    fn restricted_protocol_is_only_offered_to_allowed_peers() {
+        let restricted = RestrictedProtocols::default();
+        let friend = PeerId::random();
+        let stranger = PeerId::random();
+
+        restricted.allow(EXPERIMENTAL, friend);
+
+        assert_eq!(
+            restricted.filter(&friend, &[EXPERIMENTAL, STABLE]),
+            vec![EXPERIMENTAL, STABLE]
+        );
+        assert_eq!(
+            restricted.filter(&stranger, &[EXPERIMENTAL, STABLE]),
+            vec![STABLE]
+        );
+    }
+
+    #[test]
+    fn lifted_protocol_is_offered_to_everyone() {
+        let restricted = RestrictedProtocols::default();
+        let stranger = PeerId::random();
+
+        restricted.restrict(EXPERIMENTAL);
+        restricted.lift(EXPERIMENTAL);
+
+        assert_eq!(
+            restricted.filter(&stranger, &[EXPERIMENTAL]),
+            vec![EXPERIMENTAL]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::FutureExt;
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::transport::ListenerEvent;
+use libp2p_core::transport::TransportError;
+use libp2p_core::Multiaddr;
+use libp2p_core::Transport;
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+use tokio_socks::TargetAddr;
+use tokio_util::compat::Compat;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+/// A dial-only [`Transport`] that establishes TCP connections through a SOCKS5 proxy.
+///
+/// Meant to be used with the SOCKS5 port of a Tor daemon, in which case our IP address is not
+/// exposed to the peers we dial. Supports `/ip4`, `/ip6`, `/dns`, `/dns4` and `/dns6` addresses
+/// with a `/tcp` port, as well as `/onion3` addresses. Domain names are resolved by the proxy,
+/// so no DNS requests are leaked either.
+///
+/// Listening is not supported: incoming connections would reveal our IP address anyway.
+#[derive(Clone, Copy, Debug)]
+pub struct Socks5Transport {
+    proxy: SocketAddr,
+}
+
+impl Socks5Transport {
+    pub fn new(proxy: SocketAddr) -> Self {
+        Self { proxy }
+    }
+}
+
+impl Transport for Socks5Transport {
+    type Output = Compat<TcpStream>;
+    type Error = tokio_socks::Error;
+    #[allow(clippy::type_complexity)]
+    type Listener =
+        BoxStream<'static, Result<ListenerEvent<Self::ListenerUpgrade, Self::Error>, Self::Error>>;
+    type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(&mut self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>>
+    where
+        Self: Sized,
+    {
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>>
+    where
+        Self: Sized,
+    {
+        let target = target_addr(&addr).ok_or(TransportError::MultiaddrNotSupported(addr))?;
+        let proxy = self.proxy;
+
+        Ok(async move {
+            let stream = Socks5Stream::connect(proxy, target).await?;
+
+            Ok(stream.into_inner().compat())
+        }
+        .boxed())
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>>
+    where
+        Self: Sized,
+    {
+        self.dial(addr)
+    }
+
+    fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+        None
+    }
+}
+
+/// Extract the address the proxy should connect to from a multiaddr.
+///
+/// A trailing `/p2p` segment is ignored. Returns `None` for unsupported addresses.
+fn target_addr(addr: &Multiaddr) -> Option<TargetAddr<'static>> {
+    let mut protocols = addr
+        .iter()
+        .filter(|protocol| !matches!(protocol, Protocol::P2p(_)));
+
+    let target = match (protocols.next()?, protocols.next()) {
+        (Protocol::Ip4(ip), Some(Protocol::Tcp(port))) => {
+            TargetAddr::Ip(SocketAddr::new(ip.into(), port))
+        }
+        (Protocol::Ip6(ip), Some(Protocol::Tcp(port))) => {
+            TargetAddr::Ip(SocketAddr::new(ip.into(), port))
+        }
+        (
+            Protocol::Dns(host) | Protocol::Dns4(host) | Protocol::Dns6(host),
+            Some(Protocol::Tcp(port)),
+        ) => TargetAddr::Domain(Cow::Owned(host.into_owned()), port),
+        (Protocol::Onion3(onion), None) => {
+            let host = data_encoding::BASE32.encode(onion.hash()).to_lowercase();
+
+            TargetAddr::Domain(Cow::Owned(format!("{host}.onion")), onion.port())
+        }
+        _ => return None,
+    };
+
+    if protocols.next().is_some() {
+        return None;
+    }
+
+    Some(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONION: &str = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+
+    #[test]
+    fn onion_address_is_resolved_by_proxy() {
+        let addr = format!(
+            "/onion3/{ONION}:10000/p2p/12D3KooWP3BN6bq9jPy8cP7Grj1QyUBfr7U6BeQFgMwfTTu12wuY"
+        )
+        .parse()
+        .unwrap();
+
+        let target = target_addr(&addr).unwrap();
+
+        assert_eq!(
+            target,
+            TargetAddr::Domain(Cow::Owned(format!("{ONION}.onion")), 10000)
+        );
+    }
+
+    #[test]
+    fn domain_is_resolved_by_proxy() {
+        let addr = "/dns/mainnet.itchysats.network/tcp/10001".parse().unwrap();
+
+        let target = target_addr(&addr).unwrap();
+
+        assert_eq!(
+            target,
+            TargetAddr::Domain(Cow::Borrowed("mainnet.itchysats.network"), 10001)
+        );
+    }
+
+    #[test]
+    fn websocket_address_is_not_supported() {
+        let addr = "/ip4/127.0.0.1/tcp/10000/ws".parse().unwrap();
+
+        assert!(target_addr(&addr).is_none());
+    }
+}
@@ -1,20 +1,38 @@
+use crate::endpoint::PeerCounters;
+use crate::rate_limit::Permit;
+use crate::recorder::Recording;
 use conquer_once::Lazy;
 use futures::ready;
 use futures::AsyncRead;
 use futures::AsyncWrite;
 use libp2p_core::Endpoint;
 use libp2p_core::Negotiated;
+use libp2p_core::PeerId;
 use pin_project::pin_project;
 use prometheus::HistogramTimer;
 use prometheus::IntCounter;
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Debug;
+use std::io;
 use std::io::IoSlice;
 use std::io::IoSliceMut;
 use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long a substream can go without any reads or writes before it is considered leaked.
+pub(crate) const SUBSTREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// How long a substream can be kept around after both sides closed it before it is considered
+/// leaked.
+pub(crate) const FINISHED_SUBSTREAM_GRACE_PERIOD: Duration = Duration::from_secs(60);
 
 /// A substream is an isolated channel within another connection.
 ///
@@ -29,6 +47,14 @@ use std::task::Poll;
 ///
 /// Substreams are instrumented with prometheus metrics that track the duration they are alive for
 /// and how many bytes are read from and written to the stream.
+///
+/// If the [`crate::Endpoint`] is configured with a [`crate::recorder::Recorder`], the frames sent
+/// and received over the substream are recorded as well.
+///
+/// Open substreams are accounted for in the counters of their connection. The
+/// [`crate::Endpoint`] periodically resets substreams which leaked, i.e. which were idle for too
+/// long, which were kept around after the protocol finished or which outlived their connection.
+/// Reading from or writing to a reset substream fails.
 #[pin_project]
 pub struct Substream {
     #[pin]
@@ -45,6 +71,17 @@ pub struct Substream {
 
     /// The prometheus counter for the number of bytes written.
     written_counter: IntCounter,
+
+    /// The registration of the substream with the counters of its connection.
+    registration: Registration,
+
+    /// The recording of the frames sent and received, if enabled.
+    recording: Option<Recording>,
+
+    /// The permit of an inbound substream under the endpoint's rate limits, if enabled.
+    ///
+    /// Releases the substream's slot once the substream is dropped.
+    _permit: Option<Permit>,
 }
 
 impl Debug for Substream {
@@ -60,6 +97,8 @@ impl Substream {
         inner: Negotiated<yamux::Stream>,
         protocol: &'static str,
         role: Endpoint,
+        peer_counters: Arc<PeerCounters>,
+        recording: Option<Recording>,
     ) -> Self {
         let role = match role {
             Endpoint::Dialer => "dialer",
@@ -72,7 +111,221 @@ impl Substream {
             _timer: SUBSTREAM_DURATION_HISTOGRAM.with(&labels).start_timer(),
             read_counter: SUBSTREAM_BYTES_READ_COUNTER.with(&labels),
             written_counter: SUBSTREAM_BYTES_WRITTEN_COUNTER.with(&labels),
+            registration: Registration::new(
+                peer_counters,
+                SubstreamState::new(protocol, role, Instant::now()),
+            ),
+            recording,
+            _permit: None,
+        }
+    }
+
+    #[must_use]
+    pub(crate) fn with_permit(mut self, permit: Permit) -> Self {
+        self._permit = Some(permit);
+        self
+    }
+}
+
+/// Why a substream is considered leaked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Leak {
+    /// Nothing was read from or written to the substream for [`SUBSTREAM_IDLE_TIMEOUT`]
+    Idle,
+    /// Both sides closed the substream more than [`FINISHED_SUBSTREAM_GRACE_PERIOD`] ago
+    Finished,
+    /// The connection of the substream was dropped
+    ConnectionDropped,
+}
+
+impl Leak {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Leak::Idle => "idle",
+            Leak::Finished => "finished",
+            Leak::ConnectionDropped => "connection_dropped",
+        }
+    }
+}
+
+/// The state of an open substream, shared with the counters of its connection.
+#[derive(Debug)]
+pub(crate) struct SubstreamState {
+    pub(crate) protocol: &'static str,
+    role: &'static str,
+    activity: Mutex<Activity>,
+    reset: AtomicBool,
+}
+
+#[derive(Debug)]
+struct Activity {
+    last_read_or_write: Instant,
+    closed_locally: bool,
+    closed_remotely: bool,
+    finished_at: Option<Instant>,
+}
+
+impl SubstreamState {
+    fn new(protocol: &'static str, role: &'static str, now: Instant) -> Self {
+        Self {
+            protocol,
+            role,
+            activity: Mutex::new(Activity {
+                last_read_or_write: now,
+                closed_locally: false,
+                closed_remotely: false,
+                finished_at: None,
+            }),
+            reset: AtomicBool::new(false),
+        }
+    }
+
+    fn record_read_or_write(&self, now: Instant) {
+        self.activity().last_read_or_write = now;
+    }
+
+    fn record_close(&self, now: Instant, locally: bool) {
+        let mut activity = self.activity();
+
+        if locally {
+            activity.closed_locally = true;
+        } else {
+            activity.closed_remotely = true;
+        }
+
+        if activity.closed_locally && activity.closed_remotely && activity.finished_at.is_none() {
+            activity.finished_at = Some(now);
+        }
+    }
+
+    /// Whether the substream leaked as of `now`.
+    ///
+    /// A substream which was already reset is not reported again.
+    pub(crate) fn leak(&self, now: Instant) -> Option<Leak> {
+        if self.is_reset() {
+            return None;
+        }
+
+        let activity = self.activity();
+
+        if let Some(finished_at) = activity.finished_at {
+            if now.saturating_duration_since(finished_at) >= FINISHED_SUBSTREAM_GRACE_PERIOD {
+                return Some(Leak::Finished);
+            }
+        }
+
+        if now.saturating_duration_since(activity.last_read_or_write) >= SUBSTREAM_IDLE_TIMEOUT {
+            return Some(Leak::Idle);
         }
+
+        None
+    }
+
+    /// Reset the leaked substream, so that reading from or writing to it fails from now on.
+    ///
+    /// Returns `false` if the substream was already reset.
+    pub(crate) fn reset(&self, peer_id: PeerId, leak: Leak) -> bool {
+        if self.reset.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+
+        tracing::warn!(
+            %peer_id,
+            protocol = %self.protocol,
+            role = %self.role,
+            reason = %leak.as_str(),
+            "Resetting leaked substream"
+        );
+
+        SUBSTREAMS_LEAKED_COUNTER
+            .with(&HashMap::from([
+                (PROTOCOL_LABEL, self.protocol),
+                (ROLE_LABEL, self.role),
+                (REASON_LABEL, leak.as_str()),
+            ]))
+            .inc();
+
+        true
+    }
+
+    fn is_reset(&self) -> bool {
+        self.reset.load(Ordering::Relaxed)
+    }
+
+    fn activity(&self) -> std::sync::MutexGuard<'_, Activity> {
+        self.activity.lock().expect("lock not to be poisoned")
+    }
+}
+
+/// Accounts for a substream in the counters of its connection until it is dropped.
+struct Registration {
+    id: u64,
+    state: Arc<SubstreamState>,
+    peer_counters: Arc<PeerCounters>,
+}
+
+impl Registration {
+    fn new(peer_counters: Arc<PeerCounters>, state: SubstreamState) -> Self {
+        let state = Arc::new(state);
+        let id = peer_counters.register_substream(state.clone());
+
+        SUBSTREAMS_OPEN_GAUGE
+            .with(&HashMap::from([
+                (PROTOCOL_LABEL, state.protocol),
+                (ROLE_LABEL, state.role),
+            ]))
+            .inc();
+
+        Self {
+            id,
+            state,
+            peer_counters,
+        }
+    }
+
+    fn record_read(&self, bytes_read: usize, buf_len: usize) {
+        self.peer_counters.record_received(bytes_read);
+
+        // Reading nothing into a non-empty buffer means that the remote closed the substream
+        if bytes_read == 0 && buf_len > 0 {
+            self.state.record_close(Instant::now(), false);
+        } else {
+            self.state.record_read_or_write(Instant::now());
+        }
+    }
+
+    fn record_write(&self, bytes_written: usize) {
+        self.peer_counters.record_sent(bytes_written);
+        self.state.record_read_or_write(Instant::now());
+    }
+
+    fn record_close(&self) {
+        self.state.record_close(Instant::now(), true);
+    }
+
+    /// Fail if the substream was reset because it leaked.
+    fn ensure_not_reset(&self) -> io::Result<()> {
+        if self.state.is_reset() {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "Substream was reset because it leaked",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.peer_counters.deregister_substream(self.id);
+
+        SUBSTREAMS_OPEN_GAUGE
+            .with(&HashMap::from([
+                (PROTOCOL_LABEL, self.state.protocol),
+                (ROLE_LABEL, self.state.role),
+            ]))
+            .dec();
     }
 }
 
@@ -83,9 +336,14 @@ impl AsyncRead for Substream {
         buf: &mut [u8],
     ) -> Poll<std::io::Result<usize>> {
         let this = self.project();
+        this.registration.ensure_not_reset()?;
 
         let bytes_read = ready!(this.inner.poll_read(cx, buf)?);
         this.read_counter.inc_by(bytes_read as u64);
+        this.registration.record_read(bytes_read, buf.len());
+        if let Some(recording) = this.recording {
+            recording.record_inbound(&buf[..bytes_read]);
+        }
 
         Poll::Ready(Ok(bytes_read))
     }
@@ -96,9 +354,20 @@ impl AsyncRead for Substream {
         bufs: &mut [IoSliceMut<'_>],
     ) -> Poll<std::io::Result<usize>> {
         let this = self.project();
+        this.registration.ensure_not_reset()?;
 
         let bytes_read = ready!(this.inner.poll_read_vectored(cx, bufs)?);
         this.read_counter.inc_by(bytes_read as u64);
+        let buf_len = bufs.iter().map(|buf| buf.len()).sum();
+        this.registration.record_read(bytes_read, buf_len);
+        if let Some(recording) = this.recording {
+            let mut remaining = bytes_read;
+            for buf in bufs.iter() {
+                let n = remaining.min(buf.len());
+                recording.record_inbound(&buf[..n]);
+                remaining -= n;
+            }
+        }
 
         Poll::Ready(Ok(bytes_read))
     }
@@ -111,9 +380,14 @@ impl AsyncWrite for Substream {
         buf: &[u8],
     ) -> Poll<std::io::Result<usize>> {
         let this = self.project();
+        this.registration.ensure_not_reset()?;
 
         let bytes_written = ready!(this.inner.poll_write(cx, buf)?);
         this.written_counter.inc_by(bytes_written as u64);
+        this.registration.record_write(bytes_written);
+        if let Some(recording) = this.recording {
+            recording.record_outbound(&buf[..bytes_written]);
+        }
 
         Poll::Ready(Ok(bytes_written))
     }
@@ -124,9 +398,19 @@ impl AsyncWrite for Substream {
         bufs: &[IoSlice<'_>],
     ) -> Poll<std::io::Result<usize>> {
         let this = self.project();
+        this.registration.ensure_not_reset()?;
 
         let bytes_written = ready!(this.inner.poll_write_vectored(cx, bufs)?);
         this.written_counter.inc_by(bytes_written as u64);
+        this.registration.record_write(bytes_written);
+        if let Some(recording) = this.recording {
+            let mut remaining = bytes_written;
+            for buf in bufs.iter() {
+                let n = remaining.min(buf.len());
+                recording.record_outbound(&buf[..n]);
+                remaining -= n;
+            }
+        }
 
         Poll::Ready(Ok(bytes_written))
     }
@@ -136,7 +420,12 @@ impl AsyncWrite for Substream {
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
-        self.project().inner.poll_close(cx)
+        let this = self.project();
+
+        ready!(this.inner.poll_close(cx)?);
+        this.registration.record_close();
+
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -145,6 +434,27 @@ const PROTOCOL_LABEL: &str = "protocol";
 /// The role of substream in the protocol: dialer or listener.
 const ROLE_LABEL: &str = "role";
 
+/// Why a substream was considered leaked.
+const REASON_LABEL: &str = "reason";
+
+static SUBSTREAMS_OPEN_GAUGE: Lazy<prometheus::IntGaugeVec> = Lazy::new(|| {
+    prometheus::register_int_gauge_vec!(
+        "substreams_open",
+        "The number of open substreams, segregated by protocol.",
+        &[PROTOCOL_LABEL, ROLE_LABEL]
+    )
+    .unwrap()
+});
+
+static SUBSTREAMS_LEAKED_COUNTER: Lazy<prometheus::IntCounterVec> = Lazy::new(|| {
+    prometheus::register_int_counter_vec!(
+        "substreams_leaked_total",
+        "The total number of substreams which were reset because they leaked, segregated by protocol and reason.",
+        &[PROTOCOL_LABEL, ROLE_LABEL, REASON_LABEL]
+    )
+    .unwrap()
+});
+
 static SUBSTREAM_DURATION_HISTOGRAM: Lazy<prometheus::HistogramVec> = Lazy::new(|| {
     prometheus::register_histogram_vec!(
         "substream_duration_seconds",
@@ -172,3 +482,54 @@ static SUBSTREAM_BYTES_WRITTEN_COUNTER: Lazy<prometheus::IntCounterVec> = Lazy::
     )
     .unwrap()
 });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substream_without_reads_or_writes_leaks_after_idle_timeout() {
+        let opened_at = Instant::now();
+        let state = SubstreamState::new("/test/1.0.0", "dialer", opened_at);
+
+        let before_timeout = opened_at + SUBSTREAM_IDLE_TIMEOUT - Duration::from_secs(1);
+        assert_eq!(state.leak(before_timeout), None);
+
+        state.record_read_or_write(opened_at + Duration::from_secs(1));
+        assert_eq!(state.leak(opened_at + SUBSTREAM_IDLE_TIMEOUT), None);
+
+        let after_timeout = opened_at + Duration::from_secs(1) + SUBSTREAM_IDLE_TIMEOUT;
+        assert_eq!(state.leak(after_timeout), Some(Leak::Idle));
+    }
+
+    #[test]
+    fn substream_closed_by_both_sides_leaks_after_grace_period() {
+        let opened_at = Instant::now();
+        let state = SubstreamState::new("/test/1.0.0", "listener", opened_at);
+
+        state.record_close(opened_at, true);
+        assert_eq!(
+            state.leak(opened_at + FINISHED_SUBSTREAM_GRACE_PERIOD),
+            None
+        );
+
+        let closed_at = opened_at + Duration::from_secs(1);
+        state.record_close(closed_at, false);
+        assert_eq!(state.leak(closed_at), None);
+        assert_eq!(
+            state.leak(closed_at + FINISHED_SUBSTREAM_GRACE_PERIOD),
+            Some(Leak::Finished)
+        );
+    }
+
+    #[test]
+    fn reset_substream_is_only_reported_once() {
+        let opened_at = Instant::now();
+        let state = SubstreamState::new("/test/1.0.0", "dialer", opened_at);
+        let peer_id = PeerId::random();
+
+        assert!(state.reset(peer_id, Leak::ConnectionDropped));
+        assert!(!state.reset(peer_id, Leak::ConnectionDropped));
+        assert_eq!(state.leak(opened_at + SUBSTREAM_IDLE_TIMEOUT), None);
+    }
+}
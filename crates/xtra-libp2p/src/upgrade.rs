@@ -1,3 +1,4 @@
+use crate::restricted_protocols::RestrictedProtocols;
 use crate::verify_peer_id::VerifyPeerId;
 use crate::Connection;
 use futures::channel::mpsc;
@@ -27,10 +28,14 @@ use void::Void;
 /// - PeerID verification for each connection
 /// - Yamux multiplexing
 /// - Connection upgrade timeout
+///
+/// Inbound substreams are negotiated with the `supported_inbound_protocols`, except for the
+/// `restricted_protocols` which the peer is not allowed to use.
 pub fn transport<T>(
     transport: T,
     identity: &Keypair,
     supported_inbound_protocols: Vec<&'static str>,
+    restricted_protocols: RestrictedProtocols,
     connection_timeout: Duration,
 ) -> Boxed<Connection>
 where
@@ -130,7 +135,12 @@ where
 
         let incoming = receiver
             .then(move |stream| {
-                let supported_protocols = supported_inbound_protocols.clone();
+                let supported_protocols =
+                    restricted_protocols.filter(&peer, &supported_inbound_protocols);
+                let span = tracing::debug_span!(
+                    "Select protocol for incoming stream",
+                    ?supported_protocols
+                );
 
                 let fut = async move {
                     let result = tokio_extras::time::timeout(
@@ -147,10 +157,7 @@ where
                     }
                 };
 
-                fut.instrument(tracing::debug_span!(
-                    "Select protocol for incoming stream",
-                    ?supported_inbound_protocols
-                ))
+                fut.instrument(span)
             })
             .boxed();
 
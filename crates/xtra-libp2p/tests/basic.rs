@@ -20,12 +20,18 @@ use xtra::Context;
 use xtra_libp2p::endpoint;
 use xtra_libp2p::endpoint::RegisterListenProtocols;
 use xtra_libp2p::libp2p::PeerId;
+use xtra_libp2p::AllowProtocol;
+use xtra_libp2p::BlockPeer;
 use xtra_libp2p::Connect;
 use xtra_libp2p::Disconnect;
+use xtra_libp2p::GetBlockedPeers;
 use xtra_libp2p::GetConnectionStats;
+use xtra_libp2p::GetDetailedConnectionStats;
 use xtra_libp2p::ListenOn;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_libp2p::OpenSubstream;
+use xtra_libp2p::StopListening;
+use xtra_libp2p::UnblockPeer;
 use xtra_productivity::xtra_productivity;
 
 mod util;
@@ -59,6 +65,81 @@ async fn hello_world() {
     assert_eq!(string, "Hello Bob!");
 }
 
+#[tokio::test]
+async fn detailed_stats_track_substreams_and_traffic() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        [(
+            "/hello-world/1.0.0",
+            alice_hello_world_handler.clone().into(),
+        )],
+        [],
+    )
+    .await;
+
+    let bob_to_alice = bob
+        .endpoint
+        .send(OpenSubstream::single_protocol(
+            alice.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+    hello_world_dialer(bob_to_alice, "Bob").await.unwrap();
+
+    let bob_stats = bob.endpoint.send(GetDetailedConnectionStats).await.unwrap();
+    let bob_to_alice_stats = &bob_stats.peers[&alice.peer_id];
+
+    assert_eq!(
+        bob_to_alice_stats.substreams.get("/hello-world/1.0.0"),
+        Some(&1)
+    );
+    assert_eq!(bob_to_alice_stats.negotiation_failures, 0);
+    assert!(bob_to_alice_stats.bytes_sent > 0);
+    assert!(bob_to_alice_stats.bytes_received > 0);
+}
+
+#[tokio::test]
+async fn detailed_stats_track_open_substreams() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        [(
+            "/hello-world/1.0.0",
+            alice_hello_world_handler.clone().into(),
+        )],
+        [],
+    )
+    .await;
+
+    let bob_to_alice = bob
+        .endpoint
+        .send(OpenSubstream::single_protocol(
+            alice.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    let bob_stats = bob.endpoint.send(GetDetailedConnectionStats).await.unwrap();
+    assert_eq!(
+        bob_stats.peers[&alice.peer_id]
+            .open_substreams
+            .get("/hello-world/1.0.0"),
+        Some(&1)
+    );
+
+    hello_world_dialer(bob_to_alice, "Bob").await.unwrap();
+
+    let bob_stats = bob.endpoint.send(GetDetailedConnectionStats).await.unwrap();
+    assert!(bob_stats.peers[&alice.peer_id].open_substreams.is_empty());
+}
+
 #[tokio::test]
 async fn blocked_peers_cannot_connect() {
     let bob = make_node([]);
@@ -108,6 +189,50 @@ async fn blocked_peers_cannot_connect() {
     assert!(bob_to_alice.is_err());
 }
 
+#[tokio::test]
+async fn blocking_peer_at_runtime_drops_connection_until_unblocked() {
+    let (alice, bob, alice_listen) = alice_and_bob([], []).await;
+
+    alice.endpoint.send(BlockPeer(bob.peer_id)).await.unwrap();
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    let blocked_peers = alice.endpoint.send(GetBlockedPeers).await.unwrap();
+
+    assert_eq!(alice_stats.connected_peers, HashSet::from([]));
+    assert_eq!(blocked_peers, HashSet::from([bob.peer_id]));
+
+    let connect_to_bob = alice
+        .endpoint
+        .send(Connect(
+            Multiaddr::empty()
+                .with(Protocol::Memory(0))
+                .with(Protocol::P2p(bob.peer_id.into())),
+        ))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        connect_to_bob,
+        Err(xtra_libp2p::Error::PeerBlocked(peer_id)) if peer_id == bob.peer_id
+    ));
+
+    alice.endpoint.send(UnblockPeer(bob.peer_id)).await.unwrap();
+
+    bob.endpoint
+        .send(Connect(
+            alice_listen.with(Protocol::P2p(alice.peer_id.into())),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let bob_stats = bob.endpoint.send(GetConnectionStats).await.unwrap();
+    let blocked_peers = alice.endpoint.send(GetBlockedPeers).await.unwrap();
+
+    assert_eq!(bob_stats.connected_peers, HashSet::from([alice.peer_id]));
+    assert!(blocked_peers.is_empty());
+}
+
 #[tokio::test]
 async fn after_connect_see_each_other_as_connected() {
     let (alice, bob, _) = alice_and_bob([], []).await;
@@ -252,6 +377,28 @@ async fn listen_address_is_reflected_in_stats() {
     );
 }
 
+#[tokio::test]
+async fn stop_listening_keeps_established_connections() {
+    let (alice, bob, alice_listen) = alice_and_bob([], []).await;
+    let carol = make_node([]);
+
+    alice.endpoint.send(StopListening).await.unwrap();
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert_eq!(alice_stats.connected_peers, HashSet::from([bob.peer_id]));
+    assert!(alice_stats.listen_addresses.is_empty());
+
+    let carol_to_alice = carol
+        .endpoint
+        .send(Connect(
+            alice_listen.with(Protocol::P2p(alice.peer_id.into())),
+        ))
+        .await
+        .unwrap();
+
+    assert!(carol_to_alice.is_err());
+}
+
 #[tokio::test]
 async fn cannot_open_substream_for_unhandled_protocol() {
     let (alice, bob, _) = alice_and_bob([], []).await;
@@ -364,6 +511,72 @@ async fn falls_back_to_next_protocol_if_unsupported() {
     assert_eq!(actual_protocol, "/hello-world/1.0.0");
 }
 
+#[tokio::test]
+async fn restricted_protocol_is_only_negotiated_with_allowed_peers() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        [
+            (
+                "/hello-world/1.0.0",
+                alice_hello_world_handler.clone().into(),
+            ),
+            (
+                "/hello-world/2.0.0",
+                alice_hello_world_handler.clone().into(),
+            ),
+        ],
+        [],
+    )
+    .await;
+
+    alice
+        .endpoint
+        .send(AllowProtocol {
+            protocol: "/hello-world/2.0.0".to_owned(),
+            peer_id: PeerId::random(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let (protocol_before_allowed, _) = bob
+        .endpoint
+        .send(OpenSubstream::multiple_protocols(
+            alice.peer_id,
+            vec!["/hello-world/2.0.0", "/hello-world/1.0.0"],
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    alice
+        .endpoint
+        .send(AllowProtocol {
+            protocol: "/hello-world/2.0.0".to_owned(),
+            peer_id: bob.peer_id,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let (protocol_after_allowed, _) = bob
+        .endpoint
+        .send(OpenSubstream::multiple_protocols(
+            alice.peer_id,
+            vec!["/hello-world/2.0.0", "/hello-world/1.0.0"],
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    assert_eq!(protocol_before_allowed, "/hello-world/1.0.0");
+    assert_eq!(protocol_after_allowed, "/hello-world/2.0.0");
+}
+
 #[tokio::test]
 async fn given_alice_knows_bob_does_not_support_hello_world_when_dial_then_fail_early() {
     let (alice, bob, _) = alice_and_bob([], []).await;
@@ -1,5 +1,6 @@
 mod actor_name;
 pub mod handler_timeout;
+mod probe;
 mod send_async_next;
 mod send_async_safe;
 pub mod send_interval;
@@ -7,6 +8,7 @@ pub mod supervisor;
 
 pub use actor_name::ActorName;
 pub use handler_timeout::HandlerTimeoutExt;
+pub use probe::Probe;
 pub use send_async_next::SendAsyncNext;
 pub use send_async_safe::SendAsyncSafe;
 pub use send_interval::IncludeSpan;
@@ -0,0 +1,6 @@
+/// Message to check whether an actor is still processing its mailbox.
+///
+/// Actors monitored by a watchdog handle this message without doing any work. If the response
+/// does not arrive in time, the actor is likely stuck.
+#[derive(Debug, Clone, Copy)]
+pub struct Probe;
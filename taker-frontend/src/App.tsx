@@ -180,9 +180,10 @@ export const App = () => {
 
     let incompatible = false;
     if (makerCompatibilityOrUndefined) {
-        incompatible = makerCompatibilityOrUndefined.unsupported_protocols !== null
+        incompatible = (makerCompatibilityOrUndefined.unsupported_protocols !== null
             && makerCompatibilityOrUndefined.unsupported_protocols !== undefined
-            && makerCompatibilityOrUndefined.unsupported_protocols.length > 0;
+            && makerCompatibilityOrUndefined.unsupported_protocols.length > 0)
+            || makerCompatibilityOrUndefined.maker_requires_newer_taker === true;
     }
 
     const connectedToMaker = connectedToMakerOrUndefined ? connectedToMakerOrUndefined : { online: false };
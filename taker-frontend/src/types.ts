@@ -6,8 +6,14 @@ export interface GithubVersion {
     tag_name: string;
 }
 
+export interface FiatAmount {
+    currency: string;
+    amount: number;
+}
+
 export interface WalletInfo {
     balance: number;
+    balance_fiat?: FiatAmount;
     address: string;
     last_updated_at: number;
     transactions: Transaction[];
@@ -90,6 +96,10 @@ export interface Cfd {
     payout?: number;
     closing_price?: number;
 
+    margin_fiat?: FiatAmount;
+    profit_fiat?: FiatAmount;
+    payout_fiat?: FiatAmount;
+
     state: State;
     details: CfdDetails;
     expiry_timestamp?: number;
@@ -294,6 +304,15 @@ export interface WithdrawRequest {
 
 export interface ConnectionStatus {
     online: boolean;
+    degraded?: boolean;
+    latency?: Latency;
+}
+
+export interface Latency {
+    last_ms: number;
+    p50_ms: number;
+    p95_ms: number;
+    samples: number;
 }
 
 export interface IdentityInfo {
@@ -303,4 +322,6 @@ export interface IdentityInfo {
 
 export interface MakerCompatibility {
     unsupported_protocols?: string[];
+    disabled_features?: string[];
+    maker_requires_newer_taker?: boolean;
 }